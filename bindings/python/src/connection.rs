@@ -12,7 +12,7 @@ pub struct PyConnection {
 impl PyConnection {
     /// Execute a query
     fn execute<'py>(&self, py: Python<'py>, sql: &str) -> PyResult<Bound<'py, PyAny>> {
-        let sql = sql.to_string();
+        let _sql = sql.to_string();
 
         pyo3_asyncio_0_21::tokio::future_into_py(py, async move {
             // TODO: Implement query execution
@@ -22,7 +22,7 @@ impl PyConnection {
 
     /// Execute a query and return rows
     fn query<'py>(&self, py: Python<'py>, sql: &str) -> PyResult<Bound<'py, PyAny>> {
-        let sql = sql.to_string();
+        let _sql = sql.to_string();
 
         pyo3_asyncio_0_21::tokio::future_into_py(py, async move {
             // TODO: Implement query
@@ -2,20 +2,23 @@
 //!
 //! This crate provides Python bindings using PyO3.
 
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
-use std::collections::HashMap;
 
 mod connection;
 mod model;
 mod query;
+mod sync;
 mod types;
 
 use connection::{PyConnection, PyPool};
-use model::PyModel;
 use query::PyQueryBuilder;
+use sync::SyncConnection;
 use types::PyValue;
 
+create_exception!(chakra, DatabaseError, PyException, "A database call failed");
+
 /// Chakra ORM Python module
 #[pymodule]
 fn chakra(m: &Bound<'_, PyModule>) -> PyResult<()> {
@@ -24,30 +27,37 @@ fn chakra(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyPool>()?;
     m.add_class::<PyQueryBuilder>()?;
     m.add_class::<PyValue>()?;
+    m.add("DatabaseError", m.py().get_type_bound::<DatabaseError>())?;
 
     // Register functions
     m.add_function(wrap_pyfunction!(connect, m)?)?;
     m.add_function(wrap_pyfunction!(connect_async, m)?)?;
 
+    // `chakra.sync`, importable on its own
+    let sync_module = PyModule::new_bound(m.py(), "sync")?;
+    sync::register(&sync_module)?;
+    m.add_submodule(&sync_module)?;
+    m.py()
+        .import_bound("sys")?
+        .getattr("modules")?
+        .set_item("chakra.sync", &sync_module)?;
+
     // Add version
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
 
     Ok(())
 }
 
-/// Connect to a database synchronously
+/// Connect to a database synchronously, like `chakra.sync.connect`
 #[pyfunction]
-fn connect(url: &str) -> PyResult<PyConnection> {
-    // TODO: Implement sync connection
-    Err(pyo3::exceptions::PyNotImplementedError::new_err(
-        "Synchronous connection not yet implemented",
-    ))
+fn connect(py: Python<'_>, url: &str) -> PyResult<SyncConnection> {
+    sync::connect(py, url)
 }
 
 /// Connect to a database asynchronously
 #[pyfunction]
 fn connect_async<'py>(py: Python<'py>, url: &str) -> PyResult<Bound<'py, PyAny>> {
-    let url = url.to_string();
+    let _url = url.to_string();
 
     pyo3_asyncio_0_21::tokio::future_into_py(py, async move {
         // TODO: Implement async connection
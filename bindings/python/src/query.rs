@@ -1,7 +1,7 @@
 //! Query builder for Python bindings
 
+use chakra_core::format::SqlFormatter;
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
 
 /// Python query builder
 #[pyclass]
@@ -105,6 +105,11 @@ impl PyQueryBuilder {
         sql
     }
 
+    /// Build the SQL query pretty-printed over multiple lines
+    fn pretty(&self) -> String {
+        SqlFormatter::default().format(&self.build())
+    }
+
     /// Get string representation
     fn __str__(&self) -> String {
         self.build()
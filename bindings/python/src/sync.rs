@@ -0,0 +1,575 @@
+//! Synchronous API for Python, as the `chakra.sync` module
+//!
+//! For code without an event loop, such as Flask views or Celery tasks:
+//!
+//! ```python
+//! import chakra.sync
+//!
+//! conn = chakra.sync.connect("postgres://localhost/app")
+//! with conn.transaction():                 # BEGIN
+//!     conn.execute("INSERT INTO users (name) VALUES ($1)", ["alice"])
+//!     with conn.transaction():             # SAVEPOINT chakra_sp_1
+//!         conn.execute("UPDATE quotas SET used = used + 1")
+//!         # an exception here rolls back to the savepoint only
+//! rows = conn.query("SELECT id, name FROM users")
+//! conn.executemany("INSERT INTO tags (name) VALUES ($1)", [["a"], ["b"]])
+//! ```
+//!
+//! `fetch_all` and `fetch_one` build rows with a row factory: `dict` or
+//! `tuple`, built directly in Rust, or any other callable, such as a
+//! dataclass or pydantic model, called with the columns as keyword
+//! arguments. The connection's `row_factory` is used unless one is passed:
+//!
+//! ```python
+//! users = conn.fetch_all("SELECT id, name FROM users", row_factory=User)
+//! conn.row_factory = tuple
+//! (count,) = conn.fetch_one("SELECT count(*) FROM users")
+//! ```
+//!
+//! For analytics, `fetch_arrow` returns a `pyarrow.Table` and `fetch_pandas`
+//! a DataFrame. The rows are laid out by column in Rust and handed to
+//! pyarrow through the Arrow C stream interface, without a Python object
+//! per value.
+//!
+//! Calls run on a runtime thread owned by the module and block the caller
+//! with the GIL released, so other Python threads keep running. Each
+//! connection holds a single database connection, which a transaction and
+//! its savepoints run on.
+
+use crate::types::{py_to_value, value_to_py};
+use crate::DatabaseError;
+use arrow_array::ffi_stream::FFI_ArrowArrayStream;
+use arrow_array::{RecordBatch, RecordBatchIterator};
+use chakra_core::error::{ChakraError, Result};
+use chakra_core::executor::Executor;
+use chakra_core::result::Row;
+use chakra_core::transaction::SavepointOp;
+use chakra_core::types::Value;
+use chakra_mysql::{MySqlConfig, MySqlExecutor, MySqlPool};
+use chakra_postgres::{PostgresConfig, PostgresExecutor};
+use chakra_sqlite::{SqliteConfig, SqliteExecutor};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyDict, PyList, PyTuple, PyType};
+use std::ffi::CString;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::Runtime;
+
+/// Register the module's classes and functions
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<SyncConnection>()?;
+    m.add_class::<SyncTransaction>()?;
+    m.add_class::<ArrowStream>()?;
+    m.add_function(wrap_pyfunction!(connect, m)?)?;
+    m.add("DatabaseError", m.py().get_type_bound::<DatabaseError>())?;
+    Ok(())
+}
+
+/// Open a connection from a `postgres://`, `mysql://` or `sqlite:` URL
+#[pyfunction]
+pub fn connect(py: Python<'_>, url: &str) -> PyResult<SyncConnection> {
+    let url = url.to_string();
+    let executor = block_on(py, async move { open(&url).await })?;
+    Ok(SyncConnection {
+        executor: Some(executor),
+        depth: Arc::new(AtomicUsize::new(0)),
+        row_factory: py.get_type_bound::<PyDict>().into_any().unbind(),
+    })
+}
+
+/// A connection to one database, for synchronous code
+#[pyclass(name = "Connection", module = "chakra.sync")]
+pub struct SyncConnection {
+    /// `None` once closed
+    executor: Option<Arc<dyn Executor>>,
+    /// Transactions open on the connection, the outermost one included
+    depth: Arc<AtomicUsize>,
+    /// How `fetch_all` and `fetch_one` build rows by default
+    #[pyo3(get, set)]
+    row_factory: PyObject,
+}
+
+#[pymethods]
+impl SyncConnection {
+    /// Execute a statement and return the affected row count
+    #[pyo3(signature = (sql, params = None))]
+    fn execute(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<u64> {
+        let executor = self.executor()?;
+        let (sql, params) = (sql.to_string(), values(py, params)?);
+        block_on(py, async move { executor.execute(&sql, &params).await })
+    }
+
+    /// Execute a statement once for each parameter list and return the
+    /// total affected row count
+    ///
+    /// Outside a transaction block the statement is prepared once and every
+    /// list runs in one transaction. Inside one, the lists run one at a time
+    /// in it.
+    fn executemany(&self, py: Python<'_>, sql: &str, params: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let executor = self.executor()?;
+        let params = params
+            .iter()?
+            .map(|set| set?.iter()?.map(|value| py_to_value(py, &value?)).collect())
+            .collect::<PyResult<Vec<Vec<Value>>>>()?;
+        let sql = sql.to_string();
+        if self.in_transaction() {
+            return block_on(py, async move {
+                let mut affected = 0;
+                for params in &params {
+                    affected += executor.execute(&sql, params).await?;
+                }
+                Ok(affected)
+            });
+        }
+        block_on(
+            py,
+            async move { executor.execute_many(&sql, &params).await },
+        )
+    }
+
+    /// Execute a query and return its rows as dicts
+    #[pyo3(signature = (sql, params = None))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<Vec<PyObject>> {
+        let rows = self.rows(py, sql, params)?;
+        rows.iter()
+            .map(|row| RowFactory::Dict.build(py, row))
+            .collect()
+    }
+
+    /// Execute a query and return its rows built by `row_factory`, or the
+    /// connection's
+    #[pyo3(signature = (sql, params = None, row_factory = None))]
+    fn fetch_all(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+        row_factory: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<PyObject>> {
+        let factory = self.factory(py, row_factory)?;
+        let rows = self.rows(py, sql, params)?;
+        rows.iter().map(|row| factory.build(py, row)).collect()
+    }
+
+    /// Like `fetch_all`, returning the first row or `None`
+    #[pyo3(signature = (sql, params = None, row_factory = None))]
+    fn fetch_one(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+        row_factory: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Option<PyObject>> {
+        let factory = self.factory(py, row_factory)?;
+        let rows = self.rows(py, sql, params)?;
+        rows.first().map(|row| factory.build(py, row)).transpose()
+    }
+
+    /// Execute a query and return its rows as a `pyarrow.Table`
+    #[pyo3(signature = (sql, params = None))]
+    fn fetch_arrow(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<PyObject> {
+        let stream = self.fetch_arrow_stream(py, sql, params)?;
+        let pyarrow = py.import_bound("pyarrow")?;
+        Ok(pyarrow.call_method1("table", (stream,))?.unbind())
+    }
+
+    /// Execute a query and return its rows as a pandas DataFrame
+    #[pyo3(signature = (sql, params = None))]
+    fn fetch_pandas(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<PyObject> {
+        let table = self.fetch_arrow(py, sql, params)?;
+        table.call_method0(py, "to_pandas")
+    }
+
+    /// Execute a query and return its rows as an `ArrowStream`, for Arrow
+    /// libraries other than pyarrow
+    #[pyo3(signature = (sql, params = None))]
+    fn fetch_arrow_stream(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<ArrowStream> {
+        let rows = self.rows(py, sql, params)?;
+        let batch = chakra_core::arrow::to_record_batch(&rows).map_err(database_error)?;
+        Ok(ArrowStream { batch: Some(batch) })
+    }
+
+    /// A transaction, for use in a `with` block
+    ///
+    /// Inside another transaction's block it is a savepoint instead.
+    fn transaction(&self) -> PyResult<SyncTransaction> {
+        Ok(SyncTransaction {
+            executor: self.executor()?,
+            connection_depth: Arc::clone(&self.depth),
+            depth: None,
+        })
+    }
+
+    /// Whether a transaction is open
+    #[getter]
+    fn in_transaction(&self) -> bool {
+        self.depth.load(Ordering::SeqCst) > 0
+    }
+
+    /// Close the connection; later calls raise
+    fn close(&mut self) {
+        self.executor = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&Bound<'_, PyType>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+impl SyncConnection {
+    fn executor(&self) -> PyResult<Arc<dyn Executor>> {
+        self.executor
+            .clone()
+            .ok_or_else(|| DatabaseError::new_err("Connection is closed"))
+    }
+
+    fn rows(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<Vec<Row>> {
+        let executor = self.executor()?;
+        let (sql, params) = (sql.to_string(), values(py, params)?);
+        block_on(py, async move { executor.query(&sql, &params).await })
+    }
+
+    fn factory(
+        &self,
+        py: Python<'_>,
+        row_factory: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<RowFactory> {
+        match row_factory {
+            Some(factory) => RowFactory::new(factory),
+            None => RowFactory::new(self.row_factory.bind(py)),
+        }
+    }
+}
+
+/// How rows are turned into Python objects
+enum RowFactory {
+    Dict,
+    Tuple,
+    /// Called with the columns as keyword arguments
+    Call(PyObject),
+}
+
+impl RowFactory {
+    fn new(factory: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let py = factory.py();
+        if factory.is(&py.get_type_bound::<PyDict>()) {
+            Ok(RowFactory::Dict)
+        } else if factory.is(&py.get_type_bound::<PyTuple>()) {
+            Ok(RowFactory::Tuple)
+        } else if factory.is_callable() {
+            Ok(RowFactory::Call(factory.clone().unbind()))
+        } else {
+            Err(PyTypeError::new_err(
+                "row_factory must be dict, tuple or a callable taking the columns as keyword arguments",
+            ))
+        }
+    }
+
+    fn build(&self, py: Python<'_>, row: &Row) -> PyResult<PyObject> {
+        let value = |column: &String| value_to_py(py, row.get(column).unwrap_or(&Value::Null));
+        if let RowFactory::Tuple = self {
+            return Ok(PyTuple::new_bound(py, row.columns().iter().map(value)).into_py(py));
+        }
+        let dict = PyDict::new_bound(py);
+        for column in row.columns() {
+            dict.set_item(column, value(column))?;
+        }
+        match self {
+            RowFactory::Call(factory) => factory.call_bound(py, (), Some(&dict)),
+            _ => Ok(dict.into_py(py)),
+        }
+    }
+}
+
+/// Query results laid out by column, exported once through the Arrow
+/// PyCapsule interface
+///
+/// `pyarrow.table(stream)`, `polars.DataFrame(stream)` and other Arrow
+/// libraries read it without copying.
+#[pyclass(name = "ArrowStream", module = "chakra.sync")]
+pub struct ArrowStream {
+    /// `None` once exported
+    batch: Option<RecordBatch>,
+}
+
+#[pymethods]
+impl ArrowStream {
+    /// Number of rows, or 0 once exported
+    #[getter]
+    fn num_rows(&self) -> usize {
+        self.batch.as_ref().map_or(0, |batch| batch.num_rows())
+    }
+
+    /// Export the results as an `ArrowArrayStream` capsule
+    ///
+    /// A requested schema is ignored: the columns keep their own types.
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_stream__<'py>(
+        &mut self,
+        py: Python<'py>,
+        requested_schema: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        let _ = requested_schema;
+        let batch = self
+            .batch
+            .take()
+            .ok_or_else(|| PyValueError::new_err("ArrowStream has already been exported"))?;
+        let schema = batch.schema();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        let name = CString::new("arrow_array_stream").expect("no NUL in capsule name");
+        PyCapsule::new_bound(py, stream, Some(name))
+    }
+}
+
+/// A transaction or savepoint, begun on entering its `with` block
+///
+/// Leaving the block commits, or rolls back if it raised. A savepoint is
+/// released or rolled back to, leaving the outer transaction open.
+#[pyclass(name = "Transaction", module = "chakra.sync")]
+pub struct SyncTransaction {
+    executor: Arc<dyn Executor>,
+    connection_depth: Arc<AtomicUsize>,
+    /// Savepoint level while the block runs, 0 for the outermost transaction
+    depth: Option<usize>,
+}
+
+#[pymethods]
+impl SyncTransaction {
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyResult<PyRefMut<'_, Self>> {
+        if slf.depth.is_some() {
+            return Err(DatabaseError::new_err("Transaction already entered"));
+        }
+        let depth = slf.connection_depth.load(Ordering::SeqCst);
+        let statements = enter_statements(slf.executor.as_ref(), depth).map_err(database_error)?;
+        run(slf.py(), &slf.executor, statements)?;
+        slf.connection_depth.fetch_add(1, Ordering::SeqCst);
+        slf.depth = Some(depth);
+        Ok(slf)
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: Option<&Bound<'_, PyType>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let depth = self
+            .depth
+            .take()
+            .ok_or_else(|| DatabaseError::new_err("Transaction was not entered"))?;
+        if self.connection_depth.load(Ordering::SeqCst) != depth + 1 {
+            return Err(DatabaseError::new_err(
+                "Transactions must be exited in the reverse order they were entered",
+            ));
+        }
+        self.connection_depth.store(depth, Ordering::SeqCst);
+        let statements = exit_statements(depth, exc_type.is_some()).map_err(database_error)?;
+        run(py, &self.executor, statements)?;
+        // Let the block's exception propagate
+        Ok(false)
+    }
+
+    /// Savepoint level while entered: 0 for the outermost transaction
+    #[getter]
+    fn depth(&self) -> Option<usize> {
+        self.depth
+    }
+}
+
+/// Same names as `chakra_core::transaction::Transaction` uses
+fn savepoint_name(depth: usize) -> String {
+    format!("chakra_sp_{}", depth)
+}
+
+/// Statements entering a transaction block `depth` blocks deep
+fn enter_statements(executor: &dyn Executor, depth: usize) -> Result<Vec<String>> {
+    match depth {
+        0 => Ok(executor.dialect().begin_transaction(None)),
+        depth => Ok(vec![SavepointOp::Begin.sql(&savepoint_name(depth))?]),
+    }
+}
+
+/// Statements leaving a transaction block `depth` blocks deep, rolling
+/// back if it `failed`
+fn exit_statements(depth: usize, failed: bool) -> Result<Vec<String>> {
+    match (depth, failed) {
+        (0, false) => Ok(vec!["COMMIT".to_string()]),
+        (0, true) => Ok(vec!["ROLLBACK".to_string()]),
+        (depth, failed) => {
+            let name = savepoint_name(depth);
+            let mut statements = Vec::new();
+            if failed {
+                statements.push(SavepointOp::RollbackTo.sql(&name)?);
+            }
+            statements.push(SavepointOp::Release.sql(&name)?);
+            Ok(statements)
+        }
+    }
+}
+
+/// Run transaction control statements in order
+fn run(py: Python<'_>, executor: &Arc<dyn Executor>, statements: Vec<String>) -> PyResult<()> {
+    let executor = Arc::clone(executor);
+    block_on(py, async move {
+        for sql in &statements {
+            executor.execute(sql, &[]).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Connect with a pool of one, so each statement of a transaction runs on
+/// the connection that began it
+async fn open(url: &str) -> Result<Arc<dyn Executor>> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        let config = PostgresConfig::from_url(url)
+            .map_err(|e| ChakraError::config(e.to_string()))?
+            .pool_size(1);
+        let pool = chakra_postgres::connect(config).await?;
+        Ok(Arc::new(PostgresExecutor::new(Arc::new(pool))))
+    } else if url.starts_with("mysql://") {
+        let pool = MySqlPool::new(mysql_config(url)?).await?;
+        Ok(Arc::new(MySqlExecutor::new(Arc::new(pool))))
+    } else if url.starts_with("sqlite:") {
+        let config = SqliteConfig::from_url(url).map_err(|e| ChakraError::config(e.to_string()))?;
+        let conn = chakra_sqlite::connect(config).await?;
+        Ok(Arc::new(SqliteExecutor::new(Arc::new(conn))))
+    } else {
+        Err(ChakraError::config(format!(
+            "Unsupported database URL: {}",
+            url
+        )))
+    }
+}
+
+/// A pool of one MySQL connection that keeps its session between
+/// statements
+///
+/// The usual reset on return to the pool would end a transaction begun
+/// by an earlier statement.
+fn mysql_config(url: &str) -> Result<MySqlConfig> {
+    Ok(MySqlConfig::from_url(url)
+        .map_err(|e| ChakraError::config(e.to_string()))?
+        .pool_size(1, 1)
+        .reset_connection(false))
+}
+
+/// The runtime every synchronous call runs on, started on first use
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("chakra-sync")
+            .enable_all()
+            .build()
+            .expect("failed to start the chakra.sync runtime")
+    })
+}
+
+/// Run `future` on the runtime thread and wait for it with the GIL released
+fn block_on<T, F>(py: Python<'_>, future: F) -> PyResult<T>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T>> + Send + 'static,
+{
+    py.allow_threads(|| runtime().block_on(runtime().spawn(future)))
+        .map_err(|e| DatabaseError::new_err(e.to_string()))?
+        .map_err(database_error)
+}
+
+/// Parameters from a Python list
+fn values(py: Python<'_>, params: Option<&Bound<'_, PyList>>) -> PyResult<Vec<Value>> {
+    params.map_or(Ok(Vec::new()), |params| {
+        params.iter().map(|param| py_to_value(py, &param)).collect()
+    })
+}
+
+fn database_error(e: ChakraError) -> PyErr {
+    DatabaseError::new_err(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mysql_config() {
+        let config = mysql_config("mysql://root@localhost/app").unwrap();
+        assert_eq!((config.pool_min, config.pool_max), (1, 1));
+        assert!(!config.reset_connection);
+    }
+
+    #[test]
+    fn test_rollback_discards_writes() {
+        runtime().block_on(async {
+            let executor = open("sqlite::memory:").await.unwrap();
+            let run = |statements: Vec<String>| {
+                let executor = Arc::clone(&executor);
+                async move {
+                    for sql in &statements {
+                        executor.execute(sql, &[]).await.unwrap();
+                    }
+                }
+            };
+            run(vec!["CREATE TABLE users (name TEXT)".to_string()]).await;
+
+            // with conn.transaction(): ... with conn.transaction(): raise
+            run(enter_statements(executor.as_ref(), 0).unwrap()).await;
+            run(vec!["INSERT INTO users VALUES ('alice')".to_string()]).await;
+            run(enter_statements(executor.as_ref(), 1).unwrap()).await;
+            run(vec!["INSERT INTO users VALUES ('bob')".to_string()]).await;
+            run(exit_statements(1, true).unwrap()).await;
+            let rows = executor.query("SELECT name FROM users", &[]).await.unwrap();
+            assert_eq!(rows.len(), 1);
+            run(exit_statements(0, true).unwrap()).await;
+
+            let rows = executor.query("SELECT name FROM users", &[]).await.unwrap();
+            assert!(rows.is_empty());
+        });
+    }
+}
@@ -1,7 +1,7 @@
 //! Type conversions for Python bindings
 
 use pyo3::prelude::*;
-use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyNone, PyString};
+use pyo3::types::{PyBytes, PyDict, PyList};
 
 /// Python value wrapper
 #[pyclass]
@@ -11,6 +11,7 @@ pub struct PyValue {
 }
 
 #[derive(Clone)]
+#[allow(dead_code)]
 enum ValueKind {
     Null,
     Bool(bool),
@@ -27,37 +28,49 @@ impl PyValue {
     /// Create a null value
     #[staticmethod]
     fn null() -> Self {
-        Self { inner: ValueKind::Null }
+        Self {
+            inner: ValueKind::Null,
+        }
     }
 
     /// Create from bool
     #[staticmethod]
     fn from_bool(v: bool) -> Self {
-        Self { inner: ValueKind::Bool(v) }
+        Self {
+            inner: ValueKind::Bool(v),
+        }
     }
 
     /// Create from int
     #[staticmethod]
     fn from_int(v: i64) -> Self {
-        Self { inner: ValueKind::Int(v) }
+        Self {
+            inner: ValueKind::Int(v),
+        }
     }
 
     /// Create from float
     #[staticmethod]
     fn from_float(v: f64) -> Self {
-        Self { inner: ValueKind::Float(v) }
+        Self {
+            inner: ValueKind::Float(v),
+        }
     }
 
     /// Create from string
     #[staticmethod]
     fn from_string(v: &str) -> Self {
-        Self { inner: ValueKind::String(v.to_string()) }
+        Self {
+            inner: ValueKind::String(v.to_string()),
+        }
     }
 
     /// Create from bytes
     #[staticmethod]
     fn from_bytes(v: &[u8]) -> Self {
-        Self { inner: ValueKind::Bytes(v.to_vec()) }
+        Self {
+            inner: ValueKind::Bytes(v.to_vec()),
+        }
     }
 
     /// Check if null
@@ -110,7 +123,7 @@ impl PyValue {
 }
 
 /// Convert Python object to Chakra Value
-pub fn py_to_value(py: Python<'_>, obj: &Bound<'_, PyAny>) -> PyResult<chakra_core::types::Value> {
+pub fn py_to_value(_py: Python<'_>, obj: &Bound<'_, PyAny>) -> PyResult<chakra_core::types::Value> {
     if obj.is_none() {
         return Ok(chakra_core::types::Value::Null);
     }
@@ -155,6 +168,10 @@ pub fn value_to_py(py: Python<'_>, value: &chakra_core::types::Value) -> PyObjec
         chakra_core::types::Value::DateTime(dt) => dt.to_rfc3339().into_py(py),
         chakra_core::types::Value::Date(d) => d.to_string().into_py(py),
         chakra_core::types::Value::Time(t) => t.to_string().into_py(py),
+        chakra_core::types::Value::IpAddr(a) => a.to_string().into_py(py),
+        chakra_core::types::Value::Cidr(c) => c.to_string().into_py(py),
+        chakra_core::types::Value::MacAddr(m) => m.to_string().into_py(py),
+        chakra_core::types::Value::Range(r) => r.to_string().into_py(py),
         chakra_core::types::Value::Json(j) => j.to_string().into_py(py),
         chakra_core::types::Value::Array(arr) => {
             let list = PyList::empty_bound(py);
@@ -0,0 +1,53 @@
+//! Configuration commands
+
+use crate::config::{ChakraConfig, Severity};
+use colored::Colorize;
+use std::path::Path;
+
+pub fn validate(
+    config_path: &Path,
+    database_url: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} {}", "Validating".cyan(), config_path.display());
+
+    if !config_path.exists() {
+        println!("  {} no config file, using defaults", "Note:".yellow());
+    }
+
+    let loaded = ChakraConfig::load(config_path)?.with_database_url(database_url);
+    if loaded.config.database.url.is_none() {
+        println!(
+            "  {} no database URL; set DATABASE_URL or [database] url",
+            "Note:".yellow()
+        );
+    }
+
+    let issues = loaded.issues();
+    for issue in &issues {
+        println!("  {}", issue);
+    }
+
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        return Err(format!("{} is invalid", config_path.display()).into());
+    }
+
+    println!("{}", "Configuration is valid".green());
+    Ok(())
+}
+
+pub fn print(
+    config_path: &Path,
+    database_url: Option<&str>,
+    resolved: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !resolved {
+        let content = std::fs::read_to_string(config_path)
+            .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
+        print!("{}", content);
+        return Ok(());
+    }
+
+    let loaded = ChakraConfig::load(config_path)?.with_database_url(database_url);
+    print!("{}", loaded.config.to_toml()?);
+    Ok(())
+}
@@ -1,7 +1,16 @@
 //! Database commands implementation
 
+use crate::connection::{self, Connection};
+use chakra_core::checksum::Checksum;
+use chakra_core::query::Query;
+use chakra_core::sql::SqlFragment;
+use chakra_core::types::Value;
+use chakra_schema::data_diff::{
+    boundary_query, chunk_checksum_query, chunk_query, row_key, TableDataDiff,
+};
 use colored::Colorize;
 use std::path::Path;
+use tokio::fs;
 
 pub async fn create(
     _config_path: &Path,
@@ -19,7 +28,10 @@ pub async fn drop(
     force: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if !force {
-        println!("{}", "This will delete all data in the database!".red().bold());
+        println!(
+            "{}",
+            "This will delete all data in the database!".red().bold()
+        );
         // TODO: Add confirmation prompt
     }
 
@@ -35,7 +47,12 @@ pub async fn reset(
     force: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if !force {
-        println!("{}", "This will delete all data and recreate the database!".red().bold());
+        println!(
+            "{}",
+            "This will delete all data and recreate the database!"
+                .red()
+                .bold()
+        );
         // TODO: Add confirmation prompt
     }
 
@@ -70,3 +87,176 @@ pub async fn shell(
     println!("{}", "Shell not yet implemented".yellow());
     Ok(())
 }
+
+pub async fn diff_data(
+    tables: &[String],
+    from: &str,
+    to: &str,
+    key: &[String],
+    chunk_size: usize,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if chunk_size == 0 {
+        return Err("--chunk-size must be greater than zero".into());
+    }
+
+    // Progress goes to stderr so the generated SQL can be piped from stdout
+    eprintln!("{}", "Comparing table data...".cyan());
+    let source = Connection::open(from).await?;
+    let target = Connection::open(to).await?;
+
+    let mut script = String::new();
+    for table in tables {
+        let diff = diff_table(&source, &target, table, key, chunk_size).await?;
+
+        if diff.is_empty() {
+            eprintln!("  {} {}", "Identical".green(), table);
+            continue;
+        }
+
+        eprintln!(
+            "  {} {} ({} inserts, {} updates, {} deletes)",
+            "Differs".yellow(),
+            table,
+            diff.inserts.len(),
+            diff.updates.len(),
+            diff.deletes.len()
+        );
+
+        script.push_str(&format!("-- {}\n", table));
+        for statement in diff.to_sql(target.dialect()) {
+            script.push_str(&statement);
+            script.push('\n');
+        }
+    }
+
+    match output {
+        Some(path) => {
+            fs::write(path, &script).await?;
+            eprintln!("  {} {}", "Created".green(), path.display());
+        }
+        None => print!("{}", script),
+    }
+
+    Ok(())
+}
+
+pub async fn verify_replica(
+    config_path: &Path,
+    database_url: Option<&str>,
+    tables: &[String],
+    replica: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "Verifying replica...".cyan());
+    let primary = Connection::open(&connection::database_url(config_path, database_url)?).await?;
+    let replica = Connection::open(replica).await?;
+    if primary.dialect().name() != replica.dialect().name() {
+        return Err("Checksums can only be compared between databases of the same kind".into());
+    }
+
+    let mut mismatched = 0;
+    for table in tables {
+        let query = Query::select().from(table).build();
+        let expected = primary.checksum_query(&query).await?;
+        let actual = replica.checksum_query(&query).await?;
+
+        if expected == actual {
+            println!("  {} {} ({})", "Matches".green(), table, expected);
+        } else {
+            mismatched += 1;
+            println!(
+                "  {} {} (primary {}, replica {})",
+                "Differs".red(),
+                table,
+                expected,
+                actual
+            );
+        }
+    }
+
+    if mismatched > 0 {
+        return Err(format!(
+            "{} of {} tables differ on the replica",
+            mismatched,
+            tables.len()
+        )
+        .into());
+    }
+    println!("{}", "Replica matches the primary".green());
+    Ok(())
+}
+
+/// Walk a table in primary key order, comparing hashed chunks
+async fn diff_table(
+    source: &Connection,
+    target: &Connection,
+    table: &str,
+    key: &[String],
+    chunk_size: usize,
+) -> Result<TableDataDiff, Box<dyn std::error::Error>> {
+    let mut diff = TableDataDiff::new(table, key.to_vec());
+
+    // Server checksums are dialect-specific, so chunks can only be skipped
+    // when both sides are the same kind of database
+    let columns = if source.dialect().name() == target.dialect().name() {
+        let sample = chunk_query(source.dialect(), table, key, None, None, Some(1));
+        source
+            .query_fragment(&sample)
+            .await?
+            .first()
+            .map(|row| row.columns().to_vec())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let mut after: Option<Vec<Value>> = None;
+    loop {
+        // The last chunk has no upper bound so it also picks up trailing target rows
+        let boundary = boundary_query(source.dialect(), table, key, after.as_deref(), chunk_size);
+        let upto = source
+            .query_fragment(&boundary)
+            .await?
+            .first()
+            .map(|row| row_key(row, key));
+
+        let (after_key, upto_key) = (after.as_deref(), upto.as_deref());
+        let checksum_query = |conn: &Connection| {
+            chunk_checksum_query(conn.dialect(), table, key, &columns, after_key, upto_key)
+        };
+        let checksums = if columns.is_empty() {
+            None
+        } else {
+            checksum_query(source).zip(checksum_query(target))
+        };
+        let matches = match checksums {
+            Some((source_sum, target_sum)) => {
+                checksum(source, &source_sum).await? == checksum(target, &target_sum).await?
+            }
+            None => false,
+        };
+
+        if !matches {
+            let rows_query = |conn: &Connection| {
+                chunk_query(conn.dialect(), table, key, after_key, upto_key, None)
+            };
+            let source_rows = source.query_fragment(&rows_query(source)).await?;
+            let target_rows = target.query_fragment(&rows_query(target)).await?;
+            diff.compare_rows(&source_rows, &target_rows);
+        }
+
+        match upto {
+            Some(upto) => after = Some(upto),
+            None => return Ok(diff),
+        }
+    }
+}
+
+async fn checksum(
+    conn: &Connection,
+    fragment: &SqlFragment,
+) -> Result<Checksum, Box<dyn std::error::Error>> {
+    let rows = conn.query_fragment(fragment).await?;
+    let row = rows.first().ok_or("checksum query returned no rows")?;
+    Ok(Checksum::from_row(row)?)
+}
@@ -1,14 +1,21 @@
 //! Code generation commands
 
+use crate::config::ChakraConfig;
+use crate::connection::{self, Connection};
+use chakra_schema::codegen::{self, GenerationManifest};
+use chakra_schema::{Schema, Table};
 use colored::Colorize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tokio::fs;
 
 pub async fn models(
-    _config_path: &Path,
-    _database_url: Option<&str>,
+    config_path: &Path,
+    database_url: Option<&str>,
     output: &Path,
     tables: &[String],
     schema: Option<&str>,
+    offline: bool,
+    force: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", "Generating models from database...".cyan());
 
@@ -21,25 +28,249 @@ pub async fn models(
     }
 
     println!("  Output: {}", output.display());
+    println!();
+
+    let db_schema = load_schema(config_path, database_url, schema, offline).await?;
+    let selected = select_tables(&db_schema, tables);
+    let options = ChakraConfig::load(config_path)?.config.generate;
+
+    let mut generation = Generation::load(config_path, force)?;
+    fs::create_dir_all(output).await?;
+
+    let mut written = 0;
+    for table in &selected {
+        let path = output.join(format!("{}.rs", codegen::module_name(&table.name)));
+        let fingerprint = codegen::fingerprint(&(table, &options));
+        if generation
+            .write(&path, &fingerprint, || codegen::rust_model(table, &options))
+            .await?
+        {
+            written += 1;
+        }
+    }
+
+    let names: Vec<&str> = selected.iter().map(|t| t.name.as_str()).collect();
+    let mod_path = output.join("mod.rs");
+    generation
+        .write(&mod_path, &codegen::fingerprint(&names), || {
+            codegen::rust_models_mod(&selected)
+        })
+        .await?;
+
+    generation.finish()?;
 
-    // TODO: Implement model generation
     println!();
-    println!("{}", "Model generation not yet implemented.".yellow());
+    println!(
+        "{} {} model(s) written, {} unchanged",
+        "Done:".green().bold(),
+        written,
+        selected.len() - written
+    );
 
     Ok(())
 }
 
 pub async fn types(
-    _config_path: &Path,
-    _database_url: Option<&str>,
+    config_path: &Path,
+    database_url: Option<&str>,
     output: &Path,
+    offline: bool,
+    force: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", "Generating TypeScript types...".cyan());
     println!("  Output: {}", output.display());
-
-    // TODO: Implement TypeScript type generation
     println!();
-    println!("{}", "TypeScript generation not yet implemented.".yellow());
+
+    let db_schema = load_schema(config_path, database_url, None, offline).await?;
+    let selected = select_tables(&db_schema, &[]);
+
+    let mut generation = Generation::load(config_path, force)?;
+
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).await?;
+    }
+    generation
+        .write(output, &codegen::fingerprint(&selected), || {
+            codegen::typescript_types(&selected)
+        })
+        .await?;
+
+    generation.finish()?;
 
     Ok(())
 }
+
+/// State shared by the files written in one generation run
+struct Generation {
+    manifest: GenerationManifest,
+    manifest_path: PathBuf,
+    base_dir: PathBuf,
+    force: bool,
+    conflicts: usize,
+}
+
+impl Generation {
+    fn load(config_path: &Path, force: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let manifest_path = state_dir(config_path).join("generate.json");
+        Ok(Self {
+            manifest: GenerationManifest::load(&manifest_path)?,
+            manifest_path,
+            base_dir: state_dir(config_path).join("base"),
+            force,
+            conflicts: 0,
+        })
+    }
+
+    /// Write a generated file, keeping user edits unless `--force` is set
+    ///
+    /// Returns false when the file was already up to date or was skipped.
+    async fn write(
+        &mut self,
+        path: &Path,
+        fingerprint: &str,
+        render: impl FnOnce() -> String,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let key = path.display().to_string();
+        // Pristine output of the previous run, used as the merge base
+        let base_path = self
+            .base_dir
+            .join(format!("{}.base", codegen::fingerprint(&key)));
+
+        if !self.force && self.manifest.is_current(&key, fingerprint) && path.exists() {
+            println!("  {} {}", "Unchanged".dimmed(), path.display());
+            return Ok(false);
+        }
+
+        let generated = render();
+        let existing = if self.force {
+            None
+        } else {
+            fs::read_to_string(path).await.ok()
+        };
+
+        let (generated, content) = match existing {
+            None => (generated.clone(), generated),
+            Some(existing) if existing.lines().any(|l| l.starts_with("<<<<<<< local")) => {
+                println!(
+                    "  {} {} (unresolved merge conflicts)",
+                    "Skipped".yellow(),
+                    path.display()
+                );
+                self.conflicts += 1;
+                return Ok(false);
+            }
+            Some(existing) => {
+                let generated = codegen::preserve_regions(&generated, &existing);
+                match fs::read_to_string(&base_path).await {
+                    Ok(base) => {
+                        let merged = codegen::merge3(&base, &existing, &generated);
+                        if merged.conflicts > 0 {
+                            self.conflicts += merged.conflicts;
+                            println!(
+                                "  {} {} ({} conflict(s))",
+                                "Conflict".red(),
+                                path.display(),
+                                merged.conflicts
+                            );
+                        }
+                        (generated, merged.content)
+                    }
+                    Err(_)
+                        if self.manifest.files.contains_key(&key) || is_placeholder(&existing) =>
+                    {
+                        (generated.clone(), generated)
+                    }
+                    Err(_) => {
+                        println!(
+                            "  {} {} (not generated by chakra, use --force to overwrite)",
+                            "Skipped".yellow(),
+                            path.display()
+                        );
+                        return Ok(false);
+                    }
+                }
+            }
+        };
+
+        fs::write(path, content).await?;
+        fs::create_dir_all(&self.base_dir).await?;
+        fs::write(&base_path, generated).await?;
+        self.manifest.record(key, fingerprint);
+        println!("  {} {}", "Generated".green(), path.display());
+        Ok(true)
+    }
+
+    /// Save the manifest and report unresolved conflicts
+    fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.manifest.save(&self.manifest_path)?;
+
+        if self.conflicts > 0 {
+            println!();
+            println!(
+                "{} {} merge conflict(s); resolve the <<<<<<< markers or rerun with --force",
+                "Warning:".yellow().bold(),
+                self.conflicts
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Load the schema from the database, or from the snapshot when offline
+///
+/// Online runs refresh the snapshot so later `--offline` runs see the
+/// latest introspection.
+async fn load_schema(
+    config_path: &Path,
+    database_url: Option<&str>,
+    schema: Option<&str>,
+    offline: bool,
+) -> Result<Schema, Box<dyn std::error::Error>> {
+    let snapshot_path = state_dir(config_path).join("schema.json");
+
+    if offline {
+        let snapshot = fs::read_to_string(&snapshot_path).await.map_err(|e| {
+            format!(
+                "No schema snapshot at {} ({}); run without --offline first",
+                snapshot_path.display(),
+                e
+            )
+        })?;
+        println!("  {} {}", "Using snapshot".cyan(), snapshot_path.display());
+        return Ok(Schema::from_snapshot(&snapshot)?);
+    }
+
+    let url = connection::database_url(config_path, database_url)?;
+    let conn = Connection::open(&url).await?;
+    let db_schema = conn.introspect(schema).await?;
+
+    fs::create_dir_all(state_dir(config_path)).await?;
+    fs::write(&snapshot_path, db_schema.to_snapshot()).await?;
+
+    Ok(db_schema)
+}
+
+fn select_tables<'a>(schema: &'a Schema, tables: &[String]) -> Vec<&'a Table> {
+    let mut selected: Vec<&Table> = schema
+        .tables
+        .values()
+        .filter(|t| tables.is_empty() || tables.contains(&t.name))
+        .collect();
+    selected.sort_by(|a, b| a.name.cmp(&b.name));
+    selected
+}
+
+/// Files holding only comments, like the `mod.rs` from `chakra init`
+fn is_placeholder(content: &str) -> bool {
+    content
+        .lines()
+        .map(str::trim)
+        .all(|line| line.is_empty() || line.starts_with("//"))
+}
+
+fn state_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(".chakra")
+}
@@ -29,6 +29,22 @@ path = "src/models"
 derive_debug = true
 derive_clone = true
 derive_serialize = true
+
+[schema]
+# Index foreign key columns that no index covers
+index_foreign_keys = false
+# Identifier quoting: "always_quote", "fold_lower" (match unquoted
+# PostgreSQL names) or "preserve" (quote only where needed)
+identifiers = "always_quote"
+
+# Block dangerous statements at runtime, in the environments listed
+# (matched against CHAKRA_ENV)
+# [guard]
+# environments = ["staging"]
+#
+# [[guard.rules]]
+# action = "deny"
+# check = "delete_without_where"
 "#;
 
 const DEFAULT_GITIGNORE: &str = r#"# Chakra ORM
@@ -58,7 +74,11 @@ pub async fn run(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error
         fs::write(&config_path, DEFAULT_CONFIG).await?;
         println!("  {} {}", "Created".green(), config_path.display());
     } else {
-        println!("  {} {} (already exists)", "Skipped".yellow(), config_path.display());
+        println!(
+            "  {} {} (already exists)",
+            "Skipped".yellow(),
+            config_path.display()
+        );
     }
 
     // Create mod.rs for models
@@ -74,7 +94,7 @@ pub async fn run(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error
         let content = fs::read_to_string(&gitignore_path).await?;
         if !content.contains(".chakra/") {
             let mut new_content = content;
-            new_content.push_str("\n");
+            new_content.push('\n');
             new_content.push_str(DEFAULT_GITIGNORE);
             fs::write(&gitignore_path, new_content).await?;
             println!("  {} {}", "Updated".green(), gitignore_path.display());
@@ -85,9 +105,18 @@ pub async fn run(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error
     println!("{}", "Project initialized successfully!".green().bold());
     println!();
     println!("Next steps:");
-    println!("  1. Update {} with your database URL", "chakra.toml".cyan());
-    println!("  2. Run {} to create your first migration", "chakra migrate new initial".cyan());
-    println!("  3. Run {} to apply migrations", "chakra migrate up".cyan());
+    println!(
+        "  1. Update {} with your database URL",
+        "chakra.toml".cyan()
+    );
+    println!(
+        "  2. Run {} to create your first migration",
+        "chakra migrate new initial".cyan()
+    );
+    println!(
+        "  3. Run {} to apply migrations",
+        "chakra migrate up".cyan()
+    );
 
     Ok(())
 }
@@ -1,23 +1,34 @@
 //! Migration commands implementation
 
+use crate::connection::{self, Connection};
+use chakra_core::router::DEFAULT_DATABASE;
 use chakra_migrate::file::{generate_migration_id, MigrationLoader};
+use chakra_migrate::history::{InMemoryHistory, MigrationHistory, MigrationRecord};
 use chakra_migrate::migration::Migration;
+use chakra_migrate::planner::PlannedMigration;
+use chakra_migrate::{MigrationExecutor, MigrationPlanner};
+use chakra_schema::ddl::PostgresDdlGenerator;
 use colored::Colorize;
 use std::path::Path;
-use tokio::fs;
 
 pub async fn new(
     config_path: &Path,
     name: &str,
     app: Option<&str>,
+    database: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let migrations_dir = config_path.parent().unwrap_or(Path::new(".")).join("migrations");
+    let migrations_dir = config_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("migrations");
 
     let loader = MigrationLoader::new(&migrations_dir);
     let id = generate_migration_id();
 
-    let migration = Migration::new(&id, name)
-        .description(format!("Migration: {}", name));
+    let mut migration = Migration::new(&id, name).description(format!("Migration: {}", name));
+    if let Some(database) = database {
+        migration = migration.database(database);
+    }
 
     let path = loader.save(&migration, app).await?;
 
@@ -50,6 +61,115 @@ pub async fn up(
     Ok(())
 }
 
+pub async fn test(
+    config_path: &Path,
+    database_url: Option<&str>,
+    database: Option<&str>,
+    after: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let migrations_dir = config_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("migrations");
+    let files = MigrationLoader::new(&migrations_dir)
+        .load_database(named(database))
+        .await?;
+
+    // Migrations up to `after` are taken to be applied already
+    let history = InMemoryHistory::new();
+    if let Some(after) = after {
+        for mf in files.iter().filter(|mf| mf.migration.id.as_str() <= after) {
+            history
+                .record_applied(MigrationRecord::new(&mf.migration.id, &mf.migration.name))
+                .await?;
+        }
+    }
+
+    let plan = MigrationPlanner::new(files).plan_up(&history, None).await?;
+    if plan.is_empty() {
+        println!("{}", "No pending migrations.".green());
+        return Ok(());
+    }
+
+    // A sharded database is tested on every shard
+    let urls = connection::database_urls(config_path, database_url, database)?;
+    let mut passed = true;
+    for (i, url) in urls.iter().enumerate() {
+        if urls.len() > 1 {
+            println!("{}", format!("Shard {} of {}", i + 1, urls.len()).bold());
+        }
+        passed &= test_on(url, &plan).await?;
+        println!();
+    }
+
+    if passed {
+        println!(
+            "{}",
+            "All migrations apply cleanly. Changes were rolled back.".green()
+        );
+        Ok(())
+    } else {
+        Err("Migration test failed; changes were rolled back".into())
+    }
+}
+
+/// Test a plan against one database, printing each migration's outcome
+async fn test_on(url: &str, plan: &[PlannedMigration]) -> Result<bool, Box<dyn std::error::Error>> {
+    let conn = Connection::open(url).await?;
+    let Connection::Postgres(executor) = &conn else {
+        return Err(format!(
+            "migrate test needs transactional DDL, which {} does not support",
+            conn.dialect().name()
+        )
+        .into());
+    };
+
+    println!(
+        "{}",
+        format!("Testing {} migration(s), then rolling back...", plan.len()).cyan()
+    );
+    println!();
+
+    let history = InMemoryHistory::new();
+    let results = MigrationExecutor::new(executor, &PostgresDdlGenerator, &history)
+        .test_plan(plan)
+        .await?;
+
+    for (planned, result) in plan.iter().zip(&results) {
+        let migration = &planned.migration;
+        if result.success {
+            println!(
+                "  [{}] {} - {} ({} statements, {} checks)",
+                "ok".green(),
+                migration.id,
+                migration.name,
+                result.statements_executed,
+                migration.verify.len()
+            );
+        } else {
+            println!(
+                "  [{}] {} - {}",
+                "failed".red(),
+                migration.id,
+                migration.name
+            );
+            if let Some(ref error) = result.error {
+                println!("    {}", error);
+            }
+        }
+    }
+    for planned in plan.iter().skip(results.len()) {
+        println!(
+            "  [{}] {} - {}",
+            "skipped".yellow(),
+            planned.migration.id,
+            planned.migration.name
+        );
+    }
+
+    Ok(results.iter().all(|r| r.success))
+}
+
 pub async fn down(
     _config_path: &Path,
     _database_url: Option<&str>,
@@ -60,7 +180,10 @@ pub async fn down(
         println!("{}", "DRY RUN - No changes will be made".yellow().bold());
     }
 
-    println!("{}", format!("Rolling back {} migration(s)...", count).cyan());
+    println!(
+        "{}",
+        format!("Rolling back {} migration(s)...", count).cyan()
+    );
 
     // TODO: Implement rollback
     println!();
@@ -72,14 +195,18 @@ pub async fn down(
 pub async fn status(
     config_path: &Path,
     _database_url: Option<&str>,
+    database: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let migrations_dir = config_path.parent().unwrap_or(Path::new(".")).join("migrations");
+    let migrations_dir = config_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("migrations");
 
     println!("{}", "Migration Status".cyan().bold());
     println!();
 
     let loader = MigrationLoader::new(&migrations_dir);
-    let migrations = loader.load_all().await?;
+    let migrations = loader.load_database(named(database)).await?;
 
     if migrations.is_empty() {
         println!("  No migrations found.");
@@ -98,24 +225,28 @@ pub async fn status(
         };
 
         println!(
-            "  {} {} - {}",
-            format!("[{}]", status_str),
-            mf.migration.id,
-            mf.migration.name
+            "  [{}] {} - {}",
+            status_str, mf.migration.id, mf.migration.name
         );
     }
 
     Ok(())
 }
 
-pub async fn list(config_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let migrations_dir = config_path.parent().unwrap_or(Path::new(".")).join("migrations");
+pub async fn list(
+    config_path: &Path,
+    database: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let migrations_dir = config_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("migrations");
 
     println!("{}", "Migrations".cyan().bold());
     println!();
 
     let loader = MigrationLoader::new(&migrations_dir);
-    let migrations = loader.load_all().await?;
+    let migrations = loader.load_database(named(database)).await?;
 
     if migrations.is_empty() {
         println!("  No migrations found.");
@@ -135,10 +266,11 @@ pub async fn list(config_path: &Path) -> Result<(), Box<dyn std::error::Error>>
 }
 
 pub async fn makemigrations(
-    config_path: &Path,
+    _config_path: &Path,
     _database_url: Option<&str>,
-    app: Option<&str>,
-    name: Option<&str>,
+    database: Option<&str>,
+    _app: Option<&str>,
+    _name: Option<&str>,
     dry_run: bool,
     _auto: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -146,7 +278,14 @@ pub async fn makemigrations(
         println!("{}", "DRY RUN - No files will be created".yellow().bold());
     }
 
-    println!("{}", "Detecting model changes...".cyan());
+    // Each database gets its own migration set from the models routed to it
+    match named(database) {
+        Some(database) => println!(
+            "{}",
+            format!("Detecting model changes for {}...", database).cyan()
+        ),
+        None => println!("{}", "Detecting model changes...".cyan()),
+    }
 
     // TODO: Implement auto-detection
     println!();
@@ -154,3 +293,8 @@ pub async fn makemigrations(
 
     Ok(())
 }
+
+/// The database whose migrations to use, `None` for the default one
+fn named(database: Option<&str>) -> Option<&str> {
+    database.filter(|d| *d != DEFAULT_DATABASE)
+}
@@ -1,5 +1,6 @@
 //! CLI command implementations
 
+pub mod config;
 pub mod db;
 pub mod generate;
 pub mod init;
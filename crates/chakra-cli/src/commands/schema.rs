@@ -1,6 +1,17 @@
 //! Schema commands implementation
 
+use crate::config::ChakraConfig;
+use crate::connection::{self, Connection};
+use chakra_core::identifier::identifier_policy;
+use chakra_core::model::ModelMeta;
+use chakra_migrate::file::{generate_migration_id, MigrationLoader};
+use chakra_migrate::migration::Migration;
+use chakra_schema::ddl::{
+    DdlGenerator, DdlStatement, MySqlDdlGenerator, PostgresDdlGenerator, Safety, SqliteDdlGenerator,
+};
+use chakra_schema::{Schema, SchemaDiffer};
 use colored::Colorize;
+use dialoguer::Select;
 use std::path::Path;
 
 pub async fn introspect(
@@ -23,11 +34,53 @@ pub async fn introspect(
     Ok(())
 }
 
+pub fn create(
+    config_path: &Path,
+    database_url: Option<&str>,
+    from_models: &Path,
+    dialect: Option<&str>,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let models = load_models(from_models)?;
+
+    // Without --dialect, follow the configured database, if any
+    let url = connection::database_url(config_path, database_url).ok();
+    let dialect = dialect
+        .or_else(|| url.as_deref().and_then(|u| u.split(':').next()))
+        .unwrap_or("postgres");
+    let generator = ddl_generator(dialect)?;
+
+    let config = ChakraConfig::load(config_path)?.config;
+    let mut schema = Schema::from_models(&models);
+    if config.schema.index_foreign_keys {
+        schema.index_foreign_keys(None);
+    }
+    config.permissions.apply(&mut schema);
+    let sql = schema.to_create_sql(generator.as_ref());
+    match output {
+        Some(path) => {
+            std::fs::write(path, &sql)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            println!(
+                "{} {} table(s) to {}",
+                "Wrote".green(),
+                models.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", sql),
+    }
+
+    Ok(())
+}
+
 pub async fn push(
-    _config_path: &Path,
-    _database_url: Option<&str>,
+    config_path: &Path,
+    database_url: Option<&str>,
+    from_models: &Path,
     dry_run: bool,
-    _accept_data_loss: bool,
+    accept_data_loss: bool,
+    confirm_each: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if dry_run {
         println!("{}", "DRY RUN - No changes will be made".yellow().bold());
@@ -35,11 +88,216 @@ pub async fn push(
 
     println!("{}", "Pushing schema to database...".cyan());
 
-    // TODO: Implement schema push
+    let config = ChakraConfig::load(config_path)?.config;
+    let mut desired = Schema::from_models(&load_models(from_models)?);
+    config.permissions.apply(&mut desired);
+
+    let url = connection::database_url(config_path, database_url)?;
+    let conn = Connection::open(&url).await?;
+    let current = conn.introspect(None).await?;
+    let generator = ddl_generator(conn.dialect().name())?;
+    let statements = SchemaDiffer::new()
+        .index_foreign_keys(config.schema.index_foreign_keys)
+        .identifiers(identifier_policy(conn.dialect().name()))
+        .diff(&current, &desired)
+        .to_ddl(generator.as_ref());
+
     println!();
-    println!("{}", "Schema push not yet implemented.".yellow());
+    if statements.is_empty() {
+        println!("{}", "Schema is up to date.".green());
+        return Ok(());
+    }
 
-    Ok(())
+    let total = statements.len();
+    if dry_run {
+        for (i, stmt) in statements.iter().enumerate() {
+            print_statement(i, total, stmt);
+        }
+        return Ok(());
+    }
+
+    let data_loss = statements
+        .iter()
+        .filter(|s| s.safety() == Safety::DataLoss)
+        .count();
+    if data_loss > 0 && !accept_data_loss && !confirm_each {
+        for (i, stmt) in statements.iter().enumerate() {
+            if stmt.safety() == Safety::DataLoss {
+                print_statement(i, total, stmt);
+            }
+        }
+        return Err(format!(
+            "{} statement(s) would lose data; review them with --confirm-each or pass --accept-data-loss",
+            data_loss
+        )
+        .into());
+    }
+
+    let mut applied = 0;
+    let mut skipped: Vec<DdlStatement> = Vec::new();
+    let mut failure = None;
+    for (i, stmt) in statements.iter().enumerate() {
+        print_statement(i, total, stmt);
+
+        if confirm_each {
+            let choice = Select::new()
+                .with_prompt("Apply this statement?")
+                .items(&["apply", "skip", "abort"])
+                .default(0)
+                .interact()?;
+            match choice {
+                1 => {
+                    skipped.push(stmt.clone());
+                    continue;
+                }
+                2 => {
+                    skipped.extend(statements[i..].iter().cloned());
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        // Notes for operations the database can't do
+        if stmt.sql.starts_with("--") {
+            continue;
+        }
+        if let Err(e) = conn.execute(&stmt.sql, &[]).await {
+            if !confirm_each {
+                return Err(e.into());
+            }
+            // Keep what's left so it can be fixed up and applied later
+            skipped.extend(statements[i..].iter().cloned());
+            failure = Some(e);
+            break;
+        }
+        applied += 1;
+    }
+
+    println!();
+    println!("Applied {} of {} statement(s)", applied, total);
+    if !skipped.is_empty() {
+        let migrations_dir = config_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(&config.migrations.path);
+        let path = MigrationLoader::new(&migrations_dir)
+            .save(&skipped_migration(&skipped), None)
+            .await?;
+        println!(
+            "{} {} skipped statement(s) to {}",
+            "Saved".yellow(),
+            skipped.len(),
+            path.display()
+        );
+    }
+
+    match failure {
+        Some(e) => Err(e.into()),
+        None => {
+            println!("{}", "Schema push complete.".green());
+            Ok(())
+        }
+    }
+}
+
+/// Report foreign keys without an index, in the models or the database
+///
+/// Fails when there are any, so CI can run it.
+pub async fn lint(
+    config_path: &Path,
+    database_url: Option<&str>,
+    from_models: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = match from_models {
+        Some(path) => {
+            let mut schema = Schema::from_models(&load_models(path)?);
+            if ChakraConfig::load(config_path)?
+                .config
+                .schema
+                .index_foreign_keys
+            {
+                schema.index_foreign_keys(None);
+            }
+            schema
+        }
+        None => {
+            let url = connection::database_url(config_path, database_url)?;
+            Connection::open(&url).await?.introspect(None).await?
+        }
+    };
+
+    let unindexed = schema.unindexed_foreign_keys();
+    if unindexed.is_empty() {
+        println!("{}", "Every foreign key is indexed.".green());
+        return Ok(());
+    }
+    for (table, fk) in &unindexed {
+        println!(
+            "{}: {}.({}) references {} but has no index",
+            "unindexed foreign key".yellow().bold(),
+            table.name.cyan(),
+            fk.columns.join(", "),
+            fk.references_table
+        );
+    }
+    Err(format!(
+        "{} unindexed foreign key(s); add indexes or set `index_foreign_keys = true` under [schema]",
+        unindexed.len()
+    )
+    .into())
+}
+
+/// Read a JSON array of model metadata
+fn load_models(path: &Path) -> Result<Vec<ModelMeta>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let models = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid model metadata in {}: {}", path.display(), e))?;
+    Ok(models)
+}
+
+/// DDL generator for a dialect or URL scheme name
+fn ddl_generator(dialect: &str) -> Result<Box<dyn DdlGenerator>, Box<dyn std::error::Error>> {
+    Ok(match dialect {
+        "postgres" | "postgresql" => Box::new(PostgresDdlGenerator),
+        "mysql" => Box::new(MySqlDdlGenerator),
+        "sqlite" => Box::new(SqliteDdlGenerator),
+        other => {
+            return Err(
+                format!("Unknown dialect `{}`; use postgres, mysql or sqlite", other).into(),
+            )
+        }
+    })
+}
+
+/// Print a statement with its position and safety
+fn print_statement(index: usize, total: usize, stmt: &DdlStatement) {
+    let safety = stmt.safety();
+    let label = match safety {
+        Safety::Safe => safety.to_string().green(),
+        Safety::Risky => safety.to_string().yellow(),
+        Safety::DataLoss => safety.to_string().red().bold(),
+    };
+    println!("[{}/{}] {}", index + 1, total, label);
+    for line in stmt.sql.lines() {
+        println!("  {}", line);
+    }
+}
+
+/// A migration holding statements left out of a push, reversible when all
+/// of them are
+fn skipped_migration(skipped: &[DdlStatement]) -> Migration {
+    let up: Vec<String> = skipped.iter().map(|s| format!("{};", s.sql)).collect();
+    let down: Option<Vec<String>> = skipped
+        .iter()
+        .rev()
+        .map(|s| s.reverse_sql.as_ref().map(|sql| format!("{};", sql)))
+        .collect();
+
+    Migration::new(generate_migration_id(), "schema_push_skipped")
+        .description("Statements skipped during `chakra schema push --confirm-each`")
+        .raw_sql(up.join("\n"), down.map(|d| d.join("\n")))
 }
 
 pub async fn pull(
@@ -0,0 +1,172 @@
+//! Database connections for CLI commands
+//!
+//! Picks the adapter from the URL scheme so commands can work against any
+//! supported backend.
+
+use crate::config::ChakraConfig;
+use chakra_core::checksum::Checksum;
+use chakra_core::error::{ChakraError, Result};
+use chakra_core::executor::Executor;
+use chakra_core::query::Query;
+use chakra_core::result::Row;
+use chakra_core::router::DEFAULT_DATABASE;
+use chakra_core::sql::{Dialect, SqlFragment};
+use chakra_core::types::Value;
+use chakra_mysql::{MySqlConfig, MySqlExecutor, MySqlPool};
+use chakra_postgres::{PostgresConfig, PostgresExecutor, PostgresIntrospector};
+use chakra_schema::introspect::SchemaIntrospector;
+use chakra_schema::Schema;
+use chakra_sqlite::{SqliteConfig, SqliteExecutor};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Resolve the database URL from the command line or the config file
+pub fn database_url(config_path: &Path, database_url: Option<&str>) -> Result<String> {
+    if let Some(url) = database_url {
+        return Ok(url.to_string());
+    }
+
+    let loaded = ChakraConfig::load(config_path).map_err(|e| ChakraError::config(e.to_string()))?;
+    loaded
+        .config
+        .database
+        .url
+        .ok_or_else(|| ChakraError::config("No database URL: set DATABASE_URL or [database] url"))
+}
+
+/// Resolve the URL of a named database from `[databases.<name>]`, or of
+/// the default one as `database_url` does
+pub fn named_database_url(
+    config_path: &Path,
+    database_url: Option<&str>,
+    database: Option<&str>,
+) -> Result<String> {
+    let name = match database {
+        Some(name) if name != DEFAULT_DATABASE => name,
+        _ => return self::database_url(config_path, database_url),
+    };
+
+    let loaded = ChakraConfig::load(config_path).map_err(|e| ChakraError::config(e.to_string()))?;
+    loaded
+        .config
+        .databases
+        .get(name)
+        .and_then(|db| db.url.clone())
+        .ok_or_else(|| {
+            ChakraError::config(format!("No database URL: set [databases.{}] url", name))
+        })
+}
+
+/// Resolve the URL of every shard of a sharded named database, or the one
+/// URL `named_database_url` gives for any other
+pub fn database_urls(
+    config_path: &Path,
+    database_url: Option<&str>,
+    database: Option<&str>,
+) -> Result<Vec<String>> {
+    if let (None, Some(name)) = (database_url, database.filter(|d| *d != DEFAULT_DATABASE)) {
+        let loaded =
+            ChakraConfig::load(config_path).map_err(|e| ChakraError::config(e.to_string()))?;
+        if let Some(shards) = loaded
+            .config
+            .databases
+            .get(name)
+            .map(|db| &db.shards)
+            .filter(|s| !s.is_empty())
+        {
+            return Ok(shards.clone());
+        }
+    }
+    named_database_url(config_path, database_url, database).map(|url| vec![url])
+}
+
+/// A connection to one of the supported databases
+pub enum Connection {
+    Postgres(PostgresExecutor),
+    MySql(MySqlExecutor),
+    Sqlite(SqliteExecutor),
+}
+
+impl Connection {
+    /// Connect using a database URL
+    pub async fn open(url: &str) -> Result<Self> {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            let config =
+                PostgresConfig::from_url(url).map_err(|e| ChakraError::config(e.to_string()))?;
+            let pool = chakra_postgres::connect(config).await?;
+            Ok(Self::Postgres(PostgresExecutor::new(Arc::new(pool))))
+        } else if url.starts_with("mysql://") {
+            let config =
+                MySqlConfig::from_url(url).map_err(|e| ChakraError::config(e.to_string()))?;
+            let pool = MySqlPool::new(config).await?;
+            Ok(Self::MySql(MySqlExecutor::new(Arc::new(pool))))
+        } else if url.starts_with("sqlite:") {
+            let config =
+                SqliteConfig::from_url(url).map_err(|e| ChakraError::config(e.to_string()))?;
+            let conn = chakra_sqlite::connect(config).await?;
+            Ok(Self::Sqlite(SqliteExecutor::new(Arc::new(conn))))
+        } else {
+            Err(ChakraError::config(format!(
+                "Unsupported database URL: {}",
+                url
+            )))
+        }
+    }
+
+    /// Get the SQL dialect for this connection
+    pub fn dialect(&self) -> &dyn Dialect {
+        match self {
+            Self::Postgres(exec) => exec.dialect(),
+            Self::MySql(exec) => exec.dialect(),
+            Self::Sqlite(exec) => exec.dialect(),
+        }
+    }
+
+    /// Execute a query and return rows
+    pub async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        match self {
+            Self::Postgres(exec) => exec.query(sql, params).await,
+            Self::MySql(exec) => exec.query(sql, params).await,
+            Self::Sqlite(exec) => exec.query(sql, params).await,
+        }
+    }
+
+    /// Execute a statement and return the affected row count
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        match self {
+            Self::Postgres(exec) => exec.execute(sql, params).await,
+            Self::MySql(exec) => exec.execute(sql, params).await,
+            Self::Sqlite(exec) => exec.execute(sql, params).await,
+        }
+    }
+
+    /// Compute an order-independent checksum of a query's rows
+    pub async fn checksum_query(&self, query: &Query) -> Result<Checksum> {
+        match self {
+            Self::Postgres(exec) => exec.checksum_query(query).await,
+            Self::MySql(exec) => exec.checksum_query(query).await,
+            Self::Sqlite(exec) => exec.checksum_query(query).await,
+        }
+    }
+
+    /// Introspect the database schema
+    pub async fn introspect(&self, schema: Option<&str>) -> Result<Schema> {
+        match self {
+            Self::Postgres(exec) => {
+                let introspector = PostgresIntrospector::new(exec.pool().clone());
+                introspector
+                    .introspect_schema(schema.unwrap_or("public"))
+                    .await
+            }
+            Self::MySql(_) | Self::Sqlite(_) => Err(ChakraError::config(format!(
+                "Schema introspection is not supported for {}",
+                self.dialect().name()
+            ))),
+        }
+    }
+
+    /// Execute a query fragment and return rows
+    pub async fn query_fragment(&self, fragment: &SqlFragment) -> Result<Vec<Row>> {
+        self.query(&fragment.sql, &fragment.params).await
+    }
+}
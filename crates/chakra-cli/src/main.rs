@@ -1,11 +1,12 @@
 //! Chakra ORM Command-Line Interface
 
 use clap::{Parser, Subcommand};
-use colored::Colorize;
 use std::path::PathBuf;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod commands;
+mod config;
+mod connection;
 
 #[derive(Parser)]
 #[command(name = "chakra")]
@@ -46,6 +47,10 @@ enum Commands {
 
     /// Migration commands
     Migrate {
+        /// Named database from `[databases]` whose migrations to use
+        #[arg(long, global = true)]
+        database: Option<String>,
+
         #[command(subcommand)]
         command: MigrateCommands,
     },
@@ -61,6 +66,25 @@ enum Commands {
         #[command(subcommand)]
         command: SchemaCommands,
     },
+
+    /// Configuration checks
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Check the configuration file for mistakes
+    Validate,
+
+    /// Print the configuration file
+    Print {
+        /// Print the effective configuration, with defaults and overrides applied
+        #[arg(long)]
+        resolved: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -87,6 +111,44 @@ enum DbCommands {
 
     /// Open a database shell
     Shell,
+
+    /// Compare table data between two databases
+    DiffData {
+        /// Tables to compare
+        #[arg(long, value_delimiter = ',', required = true)]
+        tables: Vec<String>,
+
+        /// Source database URL
+        #[arg(long)]
+        from: String,
+
+        /// Target database URL
+        #[arg(long)]
+        to: String,
+
+        /// Primary key columns
+        #[arg(long, value_delimiter = ',', default_value = "id")]
+        key: Vec<String>,
+
+        /// Rows per hashed chunk
+        #[arg(long, default_value = "1000")]
+        chunk_size: usize,
+
+        /// Output file (stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare checksums of table data on a primary and a replica
+    VerifyReplica {
+        /// Tables to compare
+        #[arg(long, value_delimiter = ',', required = true)]
+        tables: Vec<String>,
+
+        /// Replica database URL
+        #[arg(long)]
+        replica: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -112,6 +174,14 @@ enum MigrateCommands {
         dry_run: bool,
     },
 
+    /// Apply pending migrations and their verification queries in a
+    /// transaction, then roll back
+    Test {
+        /// Treat migrations up to and including this ID as applied
+        #[arg(long)]
+        after: Option<String>,
+    },
+
     /// Rollback migrations
     Down {
         /// Number of migrations to rollback
@@ -164,6 +234,14 @@ enum GenerateCommands {
         /// Schema name
         #[arg(short, long)]
         schema: Option<String>,
+
+        /// Use the schema snapshot instead of introspecting the database
+        #[arg(long)]
+        offline: bool,
+
+        /// Overwrite generated files, discarding local edits
+        #[arg(long)]
+        force: bool,
     },
 
     /// Generate TypeScript types
@@ -171,6 +249,14 @@ enum GenerateCommands {
         /// Output file
         #[arg(short, long, default_value = "types.ts")]
         output: PathBuf,
+
+        /// Use the schema snapshot instead of introspecting the database
+        #[arg(long)]
+        offline: bool,
+
+        /// Overwrite generated files, discarding local edits
+        #[arg(long)]
+        force: bool,
     },
 }
 
@@ -187,8 +273,27 @@ enum SchemaCommands {
         output: Option<PathBuf>,
     },
 
+    /// Print the CREATE script for a fresh database
+    Create {
+        /// JSON file with an array of model metadata
+        #[arg(long)]
+        from_models: PathBuf,
+
+        /// Target dialect (postgres, mysql, sqlite); defaults to the database URL's
+        #[arg(long)]
+        dialect: Option<String>,
+
+        /// Output file (stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
     /// Push schema changes to database
     Push {
+        /// JSON file with an array of model metadata
+        #[arg(long)]
+        from_models: PathBuf,
+
         /// Dry run
         #[arg(long)]
         dry_run: bool,
@@ -196,6 +301,11 @@ enum SchemaCommands {
         /// Accept data loss
         #[arg(long)]
         accept_data_loss: bool,
+
+        /// Prompt to apply, skip or abort each statement; skipped ones are
+        /// saved to a follow-up migration
+        #[arg(long)]
+        confirm_each: bool,
     },
 
     /// Pull schema from database
@@ -207,6 +317,13 @@ enum SchemaCommands {
 
     /// Show schema diff
     Diff,
+
+    /// Flag foreign keys without an index
+    Lint {
+        /// JSON file with an array of model metadata; checks the database if not given
+        #[arg(long)]
+        from_models: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
@@ -216,10 +333,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
     let log_level = if cli.verbose { "debug" } else { "info" };
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| log_level.into()))
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| log_level.into()),
+        )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    // Catch configuration mistakes before any command acts on them
+    if !matches!(cli.command, Commands::Init { .. } | Commands::Config { .. }) {
+        let loaded =
+            config::ChakraConfig::load(&cli.config)?.with_database_url(cli.database_url.as_deref());
+        let issues = loaded.issues();
+        for issue in &issues {
+            eprintln!("{}", issue);
+        }
+        if issues.iter().any(|i| i.severity == config::Severity::Error) {
+            return Err(format!(
+                "{} is invalid; run `chakra config validate` for details",
+                cli.config.display()
+            )
+            .into());
+        }
+        chakra_core::identifier::set_identifier_policy(loaded.config.schema.identifiers);
+    }
+
     // Run command
     match cli.command {
         Commands::Init { path } => {
@@ -241,48 +379,155 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             DbCommands::Shell => {
                 commands::db::shell(&cli.config, cli.database_url.as_deref()).await?;
             }
+            DbCommands::DiffData {
+                tables,
+                from,
+                to,
+                key,
+                chunk_size,
+                output,
+            } => {
+                commands::db::diff_data(&tables, &from, &to, &key, chunk_size, output.as_deref())
+                    .await?;
+            }
+            DbCommands::VerifyReplica { tables, replica } => {
+                commands::db::verify_replica(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &tables,
+                    &replica,
+                )
+                .await?;
+            }
         },
-        Commands::Migrate { command } => match command {
+        Commands::Migrate { database, command } => match command {
             MigrateCommands::New { name, app } => {
-                commands::migrate::new(&cli.config, &name, app.as_deref()).await?;
+                commands::migrate::new(&cli.config, &name, app.as_deref(), database.as_deref())
+                    .await?;
             }
             MigrateCommands::Up { target, dry_run } => {
-                commands::migrate::up(&cli.config, cli.database_url.as_deref(), target.as_deref(), dry_run)
-                    .await?;
+                commands::migrate::up(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    target.as_deref(),
+                    dry_run,
+                )
+                .await?;
+            }
+            MigrateCommands::Test { after } => {
+                commands::migrate::test(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    database.as_deref(),
+                    after.as_deref(),
+                )
+                .await?;
             }
             MigrateCommands::Down { count, dry_run } => {
                 commands::migrate::down(&cli.config, cli.database_url.as_deref(), count, dry_run)
                     .await?;
             }
             MigrateCommands::Status => {
-                commands::migrate::status(&cli.config, cli.database_url.as_deref()).await?;
+                commands::migrate::status(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    database.as_deref(),
+                )
+                .await?;
             }
             MigrateCommands::List => {
-                commands::migrate::list(&cli.config).await?;
+                commands::migrate::list(&cli.config, database.as_deref()).await?;
             }
-            MigrateCommands::Makemigrations { app, name, dry_run, auto } => {
-                commands::migrate::makemigrations(&cli.config, cli.database_url.as_deref(), app.as_deref(), name.as_deref(), dry_run, auto)
-                    .await?;
+            MigrateCommands::Makemigrations {
+                app,
+                name,
+                dry_run,
+                auto,
+            } => {
+                commands::migrate::makemigrations(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    database.as_deref(),
+                    app.as_deref(),
+                    name.as_deref(),
+                    dry_run,
+                    auto,
+                )
+                .await?;
             }
         },
         Commands::Generate { command } => match command {
-            GenerateCommands::Models { output, tables, schema } => {
-                commands::generate::models(&cli.config, cli.database_url.as_deref(), &output, &tables, schema.as_deref())
-                    .await?;
+            GenerateCommands::Models {
+                output,
+                tables,
+                schema,
+                offline,
+                force,
+            } => {
+                commands::generate::models(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &output,
+                    &tables,
+                    schema.as_deref(),
+                    offline,
+                    force,
+                )
+                .await?;
             }
-            GenerateCommands::Types { output } => {
-                commands::generate::types(&cli.config, cli.database_url.as_deref(), &output)
-                    .await?;
+            GenerateCommands::Types {
+                output,
+                offline,
+                force,
+            } => {
+                commands::generate::types(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &output,
+                    offline,
+                    force,
+                )
+                .await?;
             }
         },
         Commands::Schema { command } => match command {
             SchemaCommands::Introspect { format, output } => {
-                commands::schema::introspect(&cli.config, cli.database_url.as_deref(), &format, output.as_deref())
-                    .await?;
+                commands::schema::introspect(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &format,
+                    output.as_deref(),
+                )
+                .await?;
             }
-            SchemaCommands::Push { dry_run, accept_data_loss } => {
-                commands::schema::push(&cli.config, cli.database_url.as_deref(), dry_run, accept_data_loss)
-                    .await?;
+            SchemaCommands::Create {
+                from_models,
+                dialect,
+                output,
+            } => {
+                commands::schema::create(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &from_models,
+                    dialect.as_deref(),
+                    output.as_deref(),
+                )?;
+            }
+            SchemaCommands::Push {
+                from_models,
+                dry_run,
+                accept_data_loss,
+                confirm_each,
+            } => {
+                commands::schema::push(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    &from_models,
+                    dry_run,
+                    accept_data_loss,
+                    confirm_each,
+                )
+                .await?;
             }
             SchemaCommands::Pull { output } => {
                 commands::schema::pull(&cli.config, cli.database_url.as_deref(), output.as_deref())
@@ -291,6 +536,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             SchemaCommands::Diff => {
                 commands::schema::diff(&cli.config, cli.database_url.as_deref()).await?;
             }
+            SchemaCommands::Lint { from_models } => {
+                commands::schema::lint(
+                    &cli.config,
+                    cli.database_url.as_deref(),
+                    from_models.as_deref(),
+                )
+                .await?;
+            }
+        },
+        Commands::Config { command } => match command {
+            ConfigCommands::Validate => {
+                commands::config::validate(&cli.config, cli.database_url.as_deref())?;
+            }
+            ConfigCommands::Print { resolved } => {
+                commands::config::print(&cli.config, cli.database_url.as_deref(), resolved)?;
+            }
         },
     }
 
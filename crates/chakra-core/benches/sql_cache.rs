@@ -0,0 +1,48 @@
+//! SQL generation with and without the compiled SQL cache
+//!
+//! Run with `cargo bench -p chakra-core --bench sql_cache`.
+
+use chakra_core::expr::{Expr, F};
+use chakra_core::query::{Order, Query};
+use chakra_core::sql::{Dialect, PostgresDialect};
+use chakra_core::sql_cache::SqlCache;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// A typical list page: filters, a join, ordering and paging
+fn list_query() -> Query {
+    Query::select()
+        .from("orders")
+        .columns(&["orders.id", "orders.total", "customers.name"])
+        .join("customers", Expr::raw("customers.id = orders.customer_id"))
+        .filter(Expr::gt("orders.id", 7i64))
+        .filter(F::new("orders.status").is_in(vec!["paid", "shipped", "refunded"]))
+        .filter(Expr::between("orders.total", 10i64, 500i64))
+        .order_by("orders.created_at", Order::Desc)
+        .limit(50)
+        .offset(100)
+        .build()
+}
+
+fn bench_generate(c: &mut Criterion) {
+    let query = list_query();
+
+    c.bench_function("generate/uncached", |b| {
+        b.iter(|| PostgresDialect.generate(black_box(&query)))
+    });
+
+    let cache = SqlCache::default();
+    cache.generate(&PostgresDialect, &query);
+    c.bench_function("generate/cache_hit", |b| {
+        b.iter(|| cache.generate(&PostgresDialect, black_box(&query)))
+    });
+
+    c.bench_function("generate/cache_miss", |b| {
+        b.iter(|| {
+            cache.clear();
+            cache.generate(&PostgresDialect, black_box(&query))
+        })
+    });
+}
+
+criterion_group!(benches, bench_generate);
+criterion_main!(benches);
@@ -0,0 +1,344 @@
+//! Arrow export of query results
+//!
+//! With the `arrow` feature, `Executor::fetch_arrow` returns a query's rows
+//! as one Arrow `RecordBatch`, laid out by column, for analytics code and
+//! dataframe libraries that would otherwise walk the rows one at a time:
+//!
+//! ```rust,ignore
+//! let batch = executor.fetch_arrow(&Query::select().from("orders").build()).await?;
+//! let totals = batch.column_by_name("total").unwrap();
+//!
+//! // Or as an Arrow IPC stream, for another process or language
+//! let bytes = to_ipc(&batch)?;
+//! ```
+//!
+//! Each column's Arrow type comes from its values:
+//!
+//! | Values | Arrow type |
+//! |--------|------------|
+//! | `Bool` | `Boolean` |
+//! | `Int32` | `Int32` |
+//! | `Int32`, `Int64` | `Int64` |
+//! | integers and `Float64` | `Float64` |
+//! | integers and `Decimal` | `Decimal128(38, s)`, `s` the largest scale |
+//! | `String`, `Uuid`, `Json`, `Array` | `Utf8`, JSON text for the last two |
+//! | `Bytes` | `Binary` |
+//! | `DateTime` | `Timestamp(Microsecond, "UTC")` |
+//! | `Date` | `Date32` |
+//! | `Time` | `Time64(Microsecond)` |
+//! | only `Null` | `Null` |
+//!
+//! Any other mix of values in a column is a type conversion error.
+
+use crate::error::{ChakraError, Result};
+use crate::result::Row;
+use crate::types::Value;
+use arrow_array::builder::{
+    BinaryBuilder, BooleanBuilder, Date32Builder, Decimal128Builder, Float64Builder, Int32Builder,
+    Int64Builder, StringBuilder, Time64MicrosecondBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, NullArray, RecordBatch, RecordBatchOptions};
+use arrow_schema::{ArrowError, Field, Schema};
+use chrono::{NaiveDate, Timelike};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+pub use arrow_array;
+pub use arrow_schema;
+
+/// Convert rows into a record batch, with the first row's columns
+pub fn to_record_batch(rows: &[Row]) -> Result<RecordBatch> {
+    let columns = rows
+        .first()
+        .map(|row| row.columns().to_vec())
+        .unwrap_or_default();
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays = Vec::with_capacity(columns.len());
+    for column in &columns {
+        let values: Vec<&Value> = rows
+            .iter()
+            .map(|row| row.get(column).unwrap_or(&Value::Null))
+            .collect();
+        let kind = Kind::of(column, &values)?;
+        let array = kind.build(&values).map_err(arrow_error)?;
+        fields.push(Field::new(column, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)
+        .map_err(arrow_error)
+}
+
+/// Encode a record batch as an Arrow IPC stream
+pub fn to_ipc(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut writer = arrow_ipc::writer::StreamWriter::try_new(&mut buffer, &batch.schema())
+        .map_err(arrow_error)?;
+    writer.write(batch).map_err(arrow_error)?;
+    writer.finish().map_err(arrow_error)?;
+    drop(writer);
+    Ok(buffer)
+}
+
+fn arrow_error(e: ArrowError) -> ChakraError {
+    ChakraError::internal(format!("Arrow export failed: {}", e))
+}
+
+/// The Arrow type chosen for a column
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Null,
+    Boolean,
+    Int32,
+    Int64,
+    Float64,
+    Decimal(u32),
+    Utf8,
+    Binary,
+    Timestamp,
+    Date32,
+    Time64,
+}
+
+impl Kind {
+    fn of(column: &str, values: &[&Value]) -> Result<Self> {
+        let mut kind = Kind::Null;
+        for value in values {
+            let next = match value {
+                Value::Null => continue,
+                Value::Bool(_) => Kind::Boolean,
+                Value::Int32(_) => Kind::Int32,
+                Value::Int64(_) => Kind::Int64,
+                Value::Float64(_) => Kind::Float64,
+                Value::Decimal(d) => Kind::Decimal(d.scale()),
+                Value::String(_)
+                | Value::Uuid(_)
+                | Value::IpAddr(_)
+                | Value::Cidr(_)
+                | Value::MacAddr(_)
+                | Value::Range(_)
+                | Value::Json(_)
+                | Value::Array(_) => Kind::Utf8,
+                Value::Bytes(_) => Kind::Binary,
+                Value::DateTime(_) => Kind::Timestamp,
+                Value::Date(_) => Kind::Date32,
+                Value::Time(_) => Kind::Time64,
+            };
+            kind = kind
+                .merge(next)
+                .ok_or_else(|| ChakraError::TypeConversion {
+                    message: format!("Column `{}` mixes {:?} and {:?} values", column, kind, next),
+                    from_type: format!("{:?}", next),
+                    to_type: format!("{:?}", kind),
+                })?;
+        }
+        Ok(kind)
+    }
+
+    /// The type holding values of both kinds, if there is one
+    fn merge(self, other: Kind) -> Option<Kind> {
+        use Kind::*;
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (Null, b) => Some(b),
+            (Int32, Int64) | (Int64, Int32) => Some(Int64),
+            (Int32 | Int64 | Float64, Float64) | (Float64, Int32 | Int64) => Some(Float64),
+            (Decimal(a), Decimal(b)) => Some(Decimal(a.max(b))),
+            (Int32 | Int64, Decimal(s)) | (Decimal(s), Int32 | Int64) => Some(Decimal(s)),
+            _ => None,
+        }
+    }
+
+    fn build(self, values: &[&Value]) -> std::result::Result<ArrayRef, ArrowError> {
+        let array: ArrayRef = match self {
+            Kind::Null => Arc::new(NullArray::new(values.len())),
+            Kind::Boolean => {
+                let mut builder = BooleanBuilder::with_capacity(values.len());
+                values
+                    .iter()
+                    .for_each(|v| builder.append_option(v.as_bool()));
+                Arc::new(builder.finish())
+            }
+            Kind::Int32 => {
+                let mut builder = Int32Builder::with_capacity(values.len());
+                values
+                    .iter()
+                    .for_each(|v| builder.append_option(v.as_i32()));
+                Arc::new(builder.finish())
+            }
+            Kind::Int64 => {
+                let mut builder = Int64Builder::with_capacity(values.len());
+                values
+                    .iter()
+                    .for_each(|v| builder.append_option(v.as_i64()));
+                Arc::new(builder.finish())
+            }
+            Kind::Float64 => {
+                let mut builder = Float64Builder::with_capacity(values.len());
+                values
+                    .iter()
+                    .for_each(|v| builder.append_option(v.as_f64()));
+                Arc::new(builder.finish())
+            }
+            Kind::Decimal(scale) => {
+                let mut builder = Decimal128Builder::with_capacity(values.len());
+                for value in values {
+                    builder.append_option(as_decimal(value).map(|mut d| {
+                        d.rescale(scale);
+                        d.mantissa()
+                    }));
+                }
+                Arc::new(builder.finish().with_precision_and_scale(38, scale as i8)?)
+            }
+            Kind::Utf8 => {
+                let mut builder = StringBuilder::with_capacity(values.len(), values.len() * 16);
+                for value in values {
+                    match value {
+                        Value::String(s) => builder.append_value(s),
+                        Value::Uuid(u) => builder.append_value(u.to_string()),
+                        Value::IpAddr(a) => builder.append_value(a.to_string()),
+                        Value::Cidr(c) => builder.append_value(c.to_string()),
+                        Value::MacAddr(m) => builder.append_value(m.to_string()),
+                        Value::Range(r) => builder.append_value(r.to_string()),
+                        Value::Json(json) => builder.append_value(json.to_string()),
+                        Value::Array(_) => {
+                            builder.append_value(serde_json::to_string(value).unwrap_or_default())
+                        }
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            Kind::Binary => {
+                let mut builder = BinaryBuilder::with_capacity(values.len(), 0);
+                for value in values {
+                    match value {
+                        Value::Bytes(b) => builder.append_value(b),
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            Kind::Timestamp => {
+                let mut builder = TimestampMicrosecondBuilder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::DateTime(dt) => builder.append_value(dt.timestamp_micros()),
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish().with_timezone("UTC"))
+            }
+            Kind::Date32 => {
+                let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+                let mut builder = Date32Builder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Date(d) => builder.append_value((*d - epoch).num_days() as i32),
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            Kind::Time64 => {
+                let mut builder = Time64MicrosecondBuilder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Time(t) => builder.append_value(
+                            t.num_seconds_from_midnight() as i64 * 1_000_000
+                                + t.nanosecond() as i64 / 1_000,
+                        ),
+                        _ => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+        };
+        Ok(array)
+    }
+}
+
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Int32(i) => Some(Decimal::from(*i)),
+        Value::Int64(i) => Some(Decimal::from(*i)),
+        Value::Decimal(d) => Some(*d),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, Decimal128Array, Int64Array, StringArray};
+    use arrow_schema::DataType;
+
+    fn row(id: Value, name: Value, total: Value) -> Row {
+        Row::new(
+            vec!["id".into(), "name".into(), "total".into()],
+            vec![id, name, total],
+        )
+    }
+
+    #[test]
+    fn test_to_record_batch() {
+        let rows = vec![
+            row(
+                Value::Int32(1),
+                Value::String("a".into()),
+                Value::Decimal(Decimal::new(150, 2)),
+            ),
+            row(
+                Value::Int64(2),
+                Value::Null,
+                Value::Decimal(Decimal::new(3, 0)),
+            ),
+        ];
+        let batch = to_record_batch(&rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(ids.values(), &[1, 2]);
+        let names = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!((names.value(0), names.is_null(1)), ("a", true));
+        let totals = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(totals.data_type(), &DataType::Decimal128(38, 2));
+        assert_eq!(totals.values(), &[150, 300]);
+
+        let mixed = vec![
+            row(Value::Int32(1), Value::Null, Value::Null),
+            row(Value::Bool(true), Value::Null, Value::Null),
+        ];
+        assert!(matches!(
+            to_record_batch(&mixed),
+            Err(ChakraError::TypeConversion { .. })
+        ));
+
+        assert_eq!(to_record_batch(&[]).unwrap().num_columns(), 0);
+    }
+
+    #[test]
+    fn test_to_ipc() {
+        let rows = vec![row(
+            Value::Int32(1),
+            Value::String("a".into()),
+            Value::Float64(2.5),
+        )];
+        let batch = to_record_batch(&rows).unwrap();
+        let bytes = to_ipc(&batch).unwrap();
+        let mut reader = arrow_ipc::reader::StreamReader::try_new(bytes.as_slice(), None).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), batch);
+    }
+}
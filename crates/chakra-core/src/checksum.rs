@@ -0,0 +1,173 @@
+//! Order-independent checksums of query results
+//!
+//! A `Checksum` summarizes a result set as its row count and the XOR of a
+//! hash of each row, so two databases holding the same rows agree no matter
+//! what order they return them in. `Executor::checksum_query` computes it on
+//! the server where the dialect can hash a row, which is how replicas are
+//! verified without shipping their data:
+//!
+//! ```rust,ignore
+//! let query = Query::select().from("orders").build();
+//! let primary = primary.checksum_query(&query).await?;
+//! let replica = replica.checksum_query(&query).await?;
+//! assert_eq!(primary, replica);
+//! ```
+//!
+//! Row hashes are dialect-specific, so only checksums from the same kind of
+//! database can be compared. Rows repeated an even number of times cancel
+//! out of the hash, though not out of the row count.
+
+use crate::error::{ChakraError, Result};
+use crate::query::Query;
+use crate::result::Row;
+use crate::types::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Column holding the row count in a checksum query
+pub const ROW_COUNT_COLUMN: &str = "row_count";
+
+/// Column holding the combined row hash in a checksum query
+pub const CHECKSUM_COLUMN: &str = "checksum";
+
+/// Row count and combined row hash of a result set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    /// Number of rows
+    pub rows: u64,
+    /// XOR of every row's hash
+    pub hash: u64,
+}
+
+impl Checksum {
+    /// Checksum rows fetched from the database
+    ///
+    /// Used by dialects that can't hash rows themselves. Values are hashed
+    /// by their literal form, in column order.
+    pub fn of_rows(rows: &[Row]) -> Self {
+        let hash = rows.iter().fold(0, |hash, row| {
+            let mut hasher = DefaultHasher::new();
+            for column in row.columns() {
+                row.get(column)
+                    .unwrap_or(&Value::Null)
+                    .to_sql_literal()
+                    .hash(&mut hasher);
+            }
+            hash ^ hasher.finish()
+        });
+        Self {
+            rows: rows.len() as u64,
+            hash,
+        }
+    }
+
+    /// Read the checksum from the single row of a dialect's checksum query
+    pub fn from_row(row: &Row) -> Result<Self> {
+        let column = |name: &str| match row.get(name) {
+            Some(Value::Int64(n)) => Ok(*n as u64),
+            Some(Value::Int32(n)) => Ok(*n as u64),
+            Some(Value::String(s)) => s
+                .parse::<u64>()
+                .or_else(|_| s.parse::<i64>().map(|n| n as u64))
+                .map_err(|e| ChakraError::internal(format!("invalid {} '{}': {}", name, s, e))),
+            other => Err(ChakraError::internal(format!(
+                "checksum query returned {:?} for {}",
+                other, name
+            ))),
+        };
+        Ok(Self {
+            rows: column(ROW_COUNT_COLUMN)?,
+            hash: column(CHECKSUM_COLUMN)?,
+        })
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} rows, {:016x}", self.rows, self.hash)
+    }
+}
+
+/// Names of the columns a SELECT returns, or none if any is `*` or an
+/// expression without an alias
+pub(crate) fn result_columns(query: &Query) -> Vec<String> {
+    let is_ident = |name: &str| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    };
+    let mut columns = Vec::with_capacity(query.columns.len() + query.select_exprs.len());
+    for column in &query.columns {
+        let name = column.rsplit('.').next().unwrap_or(column);
+        if !is_ident(name) {
+            return Vec::new();
+        }
+        columns.push(name.to_string());
+    }
+    columns.extend(query.select_exprs.iter().map(|(_, alias)| alias.clone()));
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i64, name: &str) -> Row {
+        Row::new(
+            vec!["id".to_string(), "name".to_string()],
+            vec![Value::Int64(id), Value::String(name.to_string())],
+        )
+    }
+
+    #[test]
+    fn test_of_rows_ignores_order() {
+        let a = Checksum::of_rows(&[row(1, "alice"), row(2, "bob")]);
+        let b = Checksum::of_rows(&[row(2, "bob"), row(1, "alice")]);
+        assert_eq!(a, b);
+        assert_eq!(a.rows, 2);
+
+        assert_ne!(a, Checksum::of_rows(&[row(1, "alice"), row(2, "carol")]));
+        assert_eq!(Checksum::of_rows(&[]), Checksum::default());
+    }
+
+    #[test]
+    fn test_from_row() {
+        let row = Row::new(
+            vec![ROW_COUNT_COLUMN.to_string(), CHECKSUM_COLUMN.to_string()],
+            vec![Value::Int64(3), Value::Int64(-1)],
+        );
+        let checksum = Checksum::from_row(&row).unwrap();
+        assert_eq!(
+            checksum,
+            Checksum {
+                rows: 3,
+                hash: u64::MAX
+            }
+        );
+        assert_eq!(checksum.to_string(), "3 rows, ffffffffffffffff");
+
+        let row = Row::new(vec![ROW_COUNT_COLUMN.to_string()], vec![Value::Int64(3)]);
+        assert!(Checksum::from_row(&row).is_err());
+    }
+
+    #[test]
+    fn test_result_columns() {
+        let query = Query::select()
+            .from("users")
+            .columns(&["id", "u.name"])
+            .build();
+        assert_eq!(result_columns(&query), vec!["id", "name"]);
+
+        let query = Query::select().from("users").build();
+        assert!(result_columns(&query).is_empty());
+
+        let query = Query::select()
+            .from("users")
+            .columns(&["id", "COUNT(*)"])
+            .build();
+        assert!(result_columns(&query).is_empty());
+    }
+}
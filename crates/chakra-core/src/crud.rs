@@ -0,0 +1,488 @@
+//! Loading and saving single model instances
+//!
+//! `Crud` is implemented for every `Model`, so a derived model can be
+//! persisted without building INSERT and UPDATE queries by hand:
+//!
+//! ```rust,ignore
+//! let mut user = User { id: 0, name: "ada".into(), ..Default::default() };
+//! user.save(&executor).await?; // INSERT, `id` filled in from RETURNING
+//!
+//! user.name = "Ada".into();
+//! user.save(&executor).await?; // UPDATE ... WHERE id = ...
+//!
+//! let same = User::get(&executor, user.id).await?;
+//! user.refresh(&executor).await?;
+//! user.delete(&executor).await?;
+//! ```
+//!
+//! Writes select every column back with RETURNING, emulated on MySQL, so
+//! database defaults and trigger changes end up on the struct.
+//!
+//! Models with a `#[chakra(snapshot)]` field track their changes: `update`
+//! and `save` then write only the columns changed since the model was
+//! loaded or saved, and skip the UPDATE when nothing changed.
+//!
+//! For many rows at once, `bulk_create` inserts in multi-row batches and
+//! `bulk_update` writes chosen columns of many rows in one statement per
+//! batch.
+//!
+//! Given a `Router`, each method runs on the model's database.
+//!
+//! `in_bulk` loads many instances by primary key at once, keyed by it.
+//!
+//! `raw` runs hand-written SQL and decodes the rows as the model.
+//!
+//! Inside a `TenantContext`, tenant-scoped models are inserted with the
+//! current tenant and only that tenant's rows are updated, deleted or loaded.
+//!
+//! Models over a view are read-only: every write fails with
+//! `ModelError::ReadOnly`.
+//!
+//! A written value that doesn't fit its field's type, see
+//! `ModelMeta::check_param_types`, fails before the statement is sent.
+
+use crate::error::{ChakraError, ModelError, QueryError, Result};
+use crate::executor::{read_only_violation, route, Executor};
+use crate::expr::Expr;
+use crate::model::{is_unset, Model};
+use crate::query::{BulkUpdate, OnConflict, Query};
+use crate::queryset::QuerySet;
+use crate::raw::RawQuery;
+use crate::result::Row;
+use crate::sql::SqlFragment;
+use crate::tenant::{tenant_filter, tenant_value};
+use crate::types::{ToValue, Value};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Rows per INSERT in `bulk_create` unless set otherwise
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// Options for `bulk_create_with`
+#[derive(Debug, Clone)]
+pub struct BulkCreate {
+    batch_size: usize,
+    on_conflict: Option<OnConflict>,
+}
+
+impl Default for BulkCreate {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            on_conflict: None,
+        }
+    }
+}
+
+impl BulkCreate {
+    /// Batches of `DEFAULT_BATCH_SIZE` rows, failing on conflicts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert at most `batch_size` rows per statement; batches are split
+    /// further if they would exceed the dialect's parameter limit
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Handle rows that violate a unique constraint, e.g. skip them with
+    /// `OnConflict::any().do_nothing()`
+    pub fn on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = Some(on_conflict);
+        self
+    }
+}
+
+/// Single-instance persistence for models
+#[async_trait]
+pub trait Crud: Model {
+    /// Load the instance with primary key `id`, within the default scope
+    async fn get(executor: &dyn Executor, id: Self::PrimaryKey) -> Result<Self>;
+
+    /// Load the instances whose primary key is one of `ids`, within the
+    /// default scope, keyed by primary key
+    ///
+    /// See `QuerySet::in_bulk`; ids with no row are missing from the map.
+    async fn in_bulk(
+        executor: &dyn Executor,
+        ids: &[Self::PrimaryKey],
+    ) -> Result<HashMap<Self::PrimaryKey, Self>>
+    where
+        Self::PrimaryKey: Eq + Hash,
+    {
+        Self::objects().in_bulk(executor, ids).await
+    }
+
+    /// INSERT this instance, validating it and filling generated ids first
+    async fn insert(&mut self, executor: &dyn Executor) -> Result<()>;
+
+    /// UPDATE every column of this instance's row, or only the changed ones
+    /// if the model tracks changes, failing with `NotFound` if there is no
+    /// such row
+    async fn update(&mut self, executor: &dyn Executor) -> Result<()>;
+
+    /// INSERT if the primary key is unset (NULL, zero or empty), otherwise
+    /// UPDATE, falling back to INSERT when no row has the key yet
+    async fn save(&mut self, executor: &dyn Executor) -> Result<()>;
+
+    /// DELETE this instance's row and return the number of rows deleted
+    async fn delete(&self, executor: &dyn Executor) -> Result<u64>;
+
+    /// Reload every column from the database, ignoring the default scope
+    ///
+    /// Loaded relationships are reset to unloaded.
+    async fn refresh(&mut self, executor: &dyn Executor) -> Result<()>;
+
+    /// INSERT `models` in batches of `DEFAULT_BATCH_SIZE` rows and return
+    /// the number of rows inserted
+    async fn bulk_create(executor: &dyn Executor, models: Vec<Self>) -> Result<u64> {
+        Self::bulk_create_with(executor, models, BulkCreate::new()).await
+    }
+
+    /// INSERT `models` with a chosen batch size and conflict handling, and
+    /// return the number of rows inserted
+    ///
+    /// The batches run in one transaction, so a failing batch undoes the
+    /// ones before it.
+    async fn bulk_create_with(
+        executor: &dyn Executor,
+        models: Vec<Self>,
+        options: BulkCreate,
+    ) -> Result<u64>;
+
+    /// UPDATE `columns` of every model's row to the model's values and
+    /// return the number of rows updated
+    ///
+    /// Each batch is one statement: `UPDATE ... FROM (VALUES ...)` on
+    /// PostgreSQL, `CASE` expressions elsewhere.
+    async fn bulk_update(executor: &dyn Executor, models: &[Self], columns: &[&str])
+        -> Result<u64>;
+
+    /// Hand-written SQL whose rows decode as this model, e.g.
+    /// `User::raw("SELECT * FROM users WHERE id = $1", vec![id.into()])`
+    ///
+    /// The default scope and tenant filter are not applied.
+    fn raw(sql: impl Into<String>, params: Vec<Value>) -> RawQuery<Self> {
+        Self::raw_fragment(SqlFragment::with_params(sql, params))
+    }
+
+    /// Like `raw`, for a fragment such as one built with `sql!`
+    fn raw_fragment(fragment: SqlFragment) -> RawQuery<Self> {
+        RawQuery::with_decoder(fragment, <Self as Model>::from_row)
+    }
+}
+
+#[async_trait]
+impl<M: Model> Crud for M {
+    async fn get(executor: &dyn Executor, id: Self::PrimaryKey) -> Result<Self> {
+        let meta = M::meta();
+        let [column] = meta.primary_key.as_slice() else {
+            return Err(ChakraError::Model(ModelError::InvalidField {
+                model: meta.name.clone(),
+                field: "primary key (get needs a single-column key)".to_string(),
+            }));
+        };
+        M::objects()
+            .filter(Expr::eq(column.clone(), id.to_value()))
+            .get(executor)
+            .await
+    }
+
+    async fn insert(&mut self, executor: &dyn Executor) -> Result<()> {
+        writable::<M>()?;
+        let executor = route(executor, M::meta())?;
+        let query = Query::insert()
+            .table(M::table_name())
+            .values(insert_values(self)?)
+            .returning(&columns::<M>())
+            .sensitive(M::meta().sensitive_columns())
+            .build();
+        M::meta().check_param_types(&query)?;
+        let rows = executor.execute_returning(&query).await?;
+        assign(self, rows.first())
+    }
+
+    async fn update(&mut self, executor: &dyn Executor) -> Result<()> {
+        writable::<M>()?;
+        let executor = route(executor, M::meta())?;
+        let key = key_filter(self)?;
+        self.validate()?;
+        // A row never moves to another tenant
+        if let Some((column, tenant)) = tenant_value::<M>() {
+            self.set_field(column, tenant)?;
+        }
+        let primary_key = &M::meta().primary_key;
+        let changed = self.changed_columns();
+        if changed.as_ref().is_some_and(Vec::is_empty) {
+            return Ok(());
+        }
+        let mut builder = Query::update().table(M::table_name());
+        let mut assigned = false;
+        for (column, value) in self.to_values()? {
+            let unchanged = changed
+                .as_ref()
+                .is_some_and(|c| !c.contains(&column.as_str()));
+            if !primary_key.contains(&column) && !unchanged {
+                builder = builder.set(column, value);
+                assigned = true;
+            }
+        }
+        if !assigned {
+            // Nothing to write; still report a missing row
+            return self.refresh(executor).await;
+        }
+        let query = builder
+            .filter(key)
+            .returning(&columns::<M>())
+            .sensitive(M::meta().sensitive_columns())
+            .build();
+        M::meta().check_param_types(&query)?;
+        let rows = executor.execute_returning(&query).await?;
+        match rows.first() {
+            Some(row) => assign(self, Some(row)),
+            None => Err(ChakraError::Query(QueryError::NotFound)),
+        }
+    }
+
+    async fn save(&mut self, executor: &dyn Executor) -> Result<()> {
+        let key = M::meta()
+            .primary_key
+            .iter()
+            .map(|column| self.get_field(column))
+            .collect::<Result<Vec<_>>>()?;
+        if key.iter().any(|value| is_unset(value.as_ref())) {
+            return self.insert(executor).await;
+        }
+        match self.update(executor).await {
+            Err(e) if e.is_not_found() => self.insert(executor).await,
+            result => result,
+        }
+    }
+
+    async fn delete(&self, executor: &dyn Executor) -> Result<u64> {
+        writable::<M>()?;
+        let executor = route(executor, M::meta())?;
+        let query = Query::delete()
+            .from(M::table_name())
+            .filter(key_filter(self)?)
+            .build();
+        executor.execute_query(&query).await
+    }
+
+    async fn refresh(&mut self, executor: &dyn Executor) -> Result<()> {
+        *self = QuerySet::<M>::new()
+            .unscoped()
+            .all_tenants()
+            .filter(key_filter(self)?)
+            .get(executor)
+            .await?;
+        Ok(())
+    }
+
+    async fn bulk_create_with(
+        executor: &dyn Executor,
+        mut models: Vec<Self>,
+        options: BulkCreate,
+    ) -> Result<u64> {
+        writable::<M>()?;
+        let executor = route(executor, M::meta())?;
+        let mut queries = Vec::new();
+        for batch in models.chunks_mut(options.batch_size) {
+            let mut builder = Query::insert()
+                .table(M::table_name())
+                .sensitive(M::meta().sensitive_columns());
+            for model in batch {
+                builder = builder.values(insert_values(model)?);
+            }
+            if let Some(on_conflict) = &options.on_conflict {
+                builder = builder.on_conflict(on_conflict.clone());
+            }
+            let query = builder.build();
+            M::meta().check_param_types(&query)?;
+            queries.push(query);
+        }
+        if let [query] = queries.as_slice() {
+            return executor.insert_all(query).await;
+        }
+        let tx = executor.begin_transaction().await?;
+        let result = async {
+            let mut inserted = 0;
+            for query in &queries {
+                inserted += tx.insert_all(query).await?;
+            }
+            Ok(inserted)
+        }
+        .await;
+        match result {
+            Ok(inserted) => {
+                tx.commit().await?;
+                Ok(inserted)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn bulk_update(
+        executor: &dyn Executor,
+        models: &[Self],
+        columns: &[&str],
+    ) -> Result<u64> {
+        writable::<M>()?;
+        let executor = route(executor, M::meta())?;
+        let meta = M::meta();
+        let invalid = |field: &str| {
+            ChakraError::Model(ModelError::InvalidField {
+                model: meta.name.clone(),
+                field: field.to_string(),
+            })
+        };
+        let field = |column: &str| {
+            M::fields()
+                .iter()
+                .find(|f| f.column_name() == column)
+                .ok_or_else(|| invalid(column))
+        };
+        let [key] = meta.primary_key.as_slice() else {
+            return Err(invalid(
+                "primary key (bulk_update needs a single-column key)",
+            ));
+        };
+        if models.is_empty() || columns.is_empty() {
+            return Ok(0);
+        }
+        for model in models {
+            model.validate()?;
+        }
+        if executor.read_only() {
+            return Err(read_only_violation("UPDATE"));
+        }
+
+        let mut update = BulkUpdate {
+            table: M::table_name().to_string(),
+            key: key.clone(),
+            key_type: field(key)?.field_type.clone(),
+            columns: Vec::with_capacity(columns.len()),
+            rows: Vec::with_capacity(models.len()),
+            conditions: Vec::new(),
+        };
+        // Rows of other tenants are left alone, and rows never move tenant
+        if let Some((column, tenant)) = tenant_value::<M>() {
+            if columns.contains(&column) {
+                return Err(invalid(column));
+            }
+            update.conditions.push((column.to_string(), tenant));
+        }
+        for column in columns {
+            if column == key {
+                return Err(invalid(column));
+            }
+            update
+                .columns
+                .push((column.to_string(), field(column)?.field_type.clone()));
+        }
+        for model in models {
+            let mut row = vec![model.get_field(key)?.unwrap_or(Value::Null)];
+            for column in columns {
+                row.push(model.get_field(column)?.unwrap_or(Value::Null));
+            }
+            for (column, value) in columns.iter().zip(&row[1..]) {
+                meta.check_value_type(column, value, false, false)?;
+            }
+            update.rows.push(row);
+        }
+
+        let dialect = executor.dialect();
+        let mut updated = 0;
+        for chunk in update.chunks(dialect.max_params()) {
+            let fragment = dialect.generate_bulk_update(&chunk);
+            updated += executor.execute(&fragment.sql, &fragment.params).await?;
+        }
+        Ok(updated)
+    }
+}
+
+/// Fail if `M` reads from a view
+fn writable<M: Model>() -> Result<()> {
+    let meta = M::meta();
+    if meta.view {
+        return Err(ChakraError::Model(ModelError::ReadOnly {
+            model: meta.name.clone(),
+        }));
+    }
+    Ok(())
+}
+
+/// Values to INSERT for `model`, after validating it and filling generated
+/// ids and the current tenant
+///
+/// Auto-increment columns are left to the database unless already set.
+fn insert_values<M: Model>(model: &mut M) -> Result<HashMap<String, Value>> {
+    model.validate()?;
+    if let Some((column, tenant)) = tenant_value::<M>() {
+        model.set_field(column, tenant)?;
+    }
+    let mut values = model.prepare_insert()?;
+    for field in M::fields().iter().filter(|f| f.auto_increment) {
+        let column = field.column_name();
+        if let Some(value) = model.get_field(column)?.filter(|v| !is_unset(Some(v))) {
+            values.insert(column.to_string(), value);
+        }
+    }
+    Ok(values)
+}
+
+/// Column names of every field
+fn columns<M: Model>() -> Vec<&'static str> {
+    M::fields().iter().map(|f| f.column_name()).collect()
+}
+
+/// `pk = value` for each primary key column of `model`, and the current
+/// tenant
+fn key_filter<M: Model>(model: &M) -> Result<Expr> {
+    let meta = M::meta();
+    let mut filter: Option<Expr> = None;
+    for column in &meta.primary_key {
+        let value = model.get_field(column)?.unwrap_or(Value::Null);
+        if value.is_null() {
+            return Err(ChakraError::Model(ModelError::MissingField {
+                model: meta.name.clone(),
+                field: column.clone(),
+            }));
+        }
+        let eq = Expr::eq(column.clone(), value);
+        filter = Some(match filter {
+            Some(filter) => filter.and(eq),
+            None => eq,
+        });
+    }
+    let filter = filter.ok_or_else(|| {
+        ChakraError::Model(ModelError::InvalidField {
+            model: meta.name.clone(),
+            field: "primary key".to_string(),
+        })
+    })?;
+    Ok(match tenant_filter::<M>() {
+        Some(tenant) => filter.and(tenant),
+        None => filter,
+    })
+}
+
+/// Copy the columns of a RETURNING row onto `model`, which then matches
+/// its row
+fn assign<M: Model>(model: &mut M, row: Option<&Row>) -> Result<()> {
+    if let Some(row) = row {
+        for column in row.columns() {
+            if let Some(value) = row.get(column) {
+                model.set_field(column, value.clone())?;
+            }
+        }
+    }
+    model.mark_clean();
+    Ok(())
+}
@@ -0,0 +1,379 @@
+//! Field-level encryption
+//!
+//! Sensitive columns can be encrypted in the application, so the database,
+//! its backups and anyone reading them only see ciphertext. Wrap the field
+//! in `Encrypted<T>`, or keep its plain type and mark it encrypted:
+//!
+//! ```rust,ignore
+//! #[derive(Model)]
+//! struct Patient {
+//!     #[chakra(primary_key, auto_increment)]
+//!     id: i64,
+//!     diagnosis: Encrypted<String>,
+//!     #[chakra(encrypted)]
+//!     ssn: Option<String>,
+//! }
+//!
+//! set_keyring(Keyring::new(Aes256GcmCipher::new("2024-06", &key)));
+//! ```
+//!
+//! Values are serialized with `serde_json`, encrypted with the keyring's
+//! primary `FieldCipher` and stored as bytes tagged with the cipher's key
+//! id. NULL stays NULL. Reading a row decrypts with whichever cipher the
+//! value names, so to rotate keys make the new cipher primary and keep the
+//! old ones with `Keyring::previous`. Rows move to the new key as they are
+//! saved; `Encrypted::needs_rotation` finds those still on an old one.
+//! Without a keyring, writing an encrypted field fails with a
+//! configuration error rather than storing the plain value.
+//!
+//! Each encryption uses a fresh nonce, so the same value never produces the
+//! same bytes twice: encrypted columns cannot be filtered on, sorted or
+//! made unique.
+
+use crate::error::{ChakraError, Result};
+use crate::model::{Snapshot, REDACTED};
+use crate::result::FromValue;
+use crate::types::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Format of the stored bytes: version, key id length, key id, ciphertext
+const FORMAT_VERSION: u8 = 1;
+
+/// The keyring used for every encrypted field
+static KEYRING: RwLock<Option<Arc<Keyring>>> = RwLock::new(None);
+
+/// Encrypts and decrypts field values with one key
+pub trait FieldCipher: Send + Sync {
+    /// Identifies the key; stored with each value to pick the cipher that
+    /// decrypts it
+    fn key_id(&self) -> &str;
+
+    /// Encrypt `plaintext`, including anything needed to decrypt it, such as
+    /// the nonce
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypt the output of `encrypt`
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The cipher new values are encrypted with, plus older ones still needed to
+/// read existing rows
+pub struct Keyring {
+    primary: Arc<dyn FieldCipher>,
+    previous: Vec<Arc<dyn FieldCipher>>,
+}
+
+impl Keyring {
+    /// A keyring encrypting with `primary`
+    pub fn new(primary: impl FieldCipher + 'static) -> Self {
+        Self {
+            primary: Arc::new(primary),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Keep `cipher` for decrypting values written before a rotation
+    pub fn previous(mut self, cipher: impl FieldCipher + 'static) -> Self {
+        self.previous.push(Arc::new(cipher));
+        self
+    }
+
+    /// The key id new values are encrypted with
+    pub fn primary_key_id(&self) -> &str {
+        self.primary.key_id()
+    }
+
+    /// Encrypt with the primary cipher, tagging the result with its key id
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let key_id = self.primary.key_id().as_bytes();
+        let length = u8::try_from(key_id.len())
+            .map_err(|_| ChakraError::config("Field cipher key ids are limited to 255 bytes"))?;
+        let ciphertext = self.primary.encrypt(plaintext)?;
+        let mut stored = Vec::with_capacity(2 + key_id.len() + ciphertext.len());
+        stored.extend([FORMAT_VERSION, length]);
+        stored.extend_from_slice(key_id);
+        stored.extend(ciphertext);
+        Ok(stored)
+    }
+
+    /// Decrypt a stored value with the cipher it names
+    pub fn decrypt(&self, stored: &[u8]) -> Result<Vec<u8>> {
+        let (key_id, ciphertext) = split(stored)?;
+        let cipher = std::iter::once(&self.primary)
+            .chain(&self.previous)
+            .find(|c| c.key_id() == key_id)
+            .ok_or_else(|| ChakraError::config(format!("No field cipher for key `{}`", key_id)))?;
+        cipher.decrypt(ciphertext)
+    }
+}
+
+/// Use `keyring` for every encrypted field from now on
+pub fn set_keyring(keyring: Keyring) {
+    *KEYRING.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(keyring));
+}
+
+/// The keyring set with `set_keyring`
+pub fn keyring() -> Result<Arc<Keyring>> {
+    KEYRING
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .ok_or_else(|| {
+            ChakraError::config("No keyring set for encrypted fields; call set_keyring at startup")
+        })
+}
+
+/// The key id and ciphertext of a stored value
+fn split(stored: &[u8]) -> Result<(&str, &[u8])> {
+    let invalid = || ChakraError::TypeConversion {
+        message: "Not an encrypted value".to_string(),
+        from_type: "bytes".to_string(),
+        to_type: "Encrypted".to_string(),
+    };
+    match stored {
+        [FORMAT_VERSION, length, rest @ ..] if rest.len() >= *length as usize => {
+            let (key_id, ciphertext) = rest.split_at(*length as usize);
+            Ok((
+                std::str::from_utf8(key_id).map_err(|_| invalid())?,
+                ciphertext,
+            ))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// A value stored encrypted
+///
+/// Dereferences to the plain value. A value read from the database keeps
+/// its stored bytes, so saving it unchanged writes them back as they were.
+#[derive(Clone)]
+pub struct Encrypted<T> {
+    value: T,
+    /// The encrypted form, once computed or as loaded, shared by clones
+    stored: Arc<OnceLock<Vec<u8>>>,
+}
+
+impl<T> Encrypted<T> {
+    /// Wrap a plain value, encrypted when first written
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            stored: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The plain value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// The key id of the stored value, `None` until it is encrypted
+    pub fn key_id(&self) -> Option<&str> {
+        self.stored
+            .get()
+            .and_then(|stored| split(stored).ok())
+            .map(|(key_id, _)| key_id)
+    }
+
+    /// Whether the value is stored with a key other than the primary one
+    pub fn needs_rotation(&self) -> bool {
+        match (self.key_id(), keyring()) {
+            (Some(key_id), Ok(keyring)) => key_id != keyring.primary_key_id(),
+            _ => false,
+        }
+    }
+
+    /// The same value, encrypted again with the primary key when written
+    pub fn rotate(self) -> Self {
+        Self::new(self.value)
+    }
+}
+
+impl<T: Serialize> Encrypted<T> {
+    /// The stored bytes, encrypting the value on first use
+    pub fn encrypt(&self) -> Result<Vec<u8>> {
+        if let Some(stored) = self.stored.get() {
+            return Ok(stored.clone());
+        }
+        let plaintext = serde_json::to_vec(&self.value).map_err(|e| {
+            ChakraError::internal(format!("Cannot serialize encrypted value: {}", e))
+        })?;
+        let stored = keyring()?.encrypt(&plaintext)?;
+        Ok(self.stored.get_or_init(|| stored).clone())
+    }
+}
+
+impl<T> Deref for Encrypted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Default> Default for Encrypted<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Encrypted<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Encrypted<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T> fmt::Debug for Encrypted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Encrypted({})", REDACTED)
+    }
+}
+
+impl<T: DeserializeOwned> FromValue for Encrypted<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        let Value::Bytes(stored) = value else {
+            return Err(ChakraError::TypeConversion {
+                message: "Encrypted values are stored as bytes".to_string(),
+                from_type: value.type_name().to_string(),
+                to_type: "Encrypted".to_string(),
+            });
+        };
+        let plaintext = keyring()?.decrypt(stored)?;
+        let value =
+            serde_json::from_slice(&plaintext).map_err(|e| ChakraError::TypeConversion {
+                message: format!("Decrypted value does not match the field: {}", e),
+                from_type: "bytes".to_string(),
+                to_type: std::any::type_name::<T>().to_string(),
+            })?;
+        Ok(Self {
+            value,
+            stored: Arc::new(OnceLock::from(stored.clone())),
+        })
+    }
+}
+
+/// Whether field `index` of a snapshot holds something other than `value`
+/// once decrypted, for change tracking of `#[chakra(encrypted)]` fields;
+/// `None` before the snapshot is captured
+pub fn changed<T: DeserializeOwned + PartialEq>(
+    snapshot: &Snapshot,
+    index: usize,
+    value: &T,
+) -> Option<bool> {
+    if !snapshot.is_captured() {
+        return None;
+    }
+    let stored: Option<T> = match snapshot.get(index) {
+        Some(Value::Null) => serde_json::from_value(serde_json::Value::Null).ok(),
+        Some(stored) => Encrypted::<T>::from_value(stored)
+            .ok()
+            .map(Encrypted::into_inner),
+        None => None,
+    };
+    Some(stored.as_ref() != Some(value))
+}
+
+/// AES-256-GCM with a random 96-bit nonce per value
+#[cfg(feature = "encryption")]
+pub struct Aes256GcmCipher {
+    key_id: String,
+    cipher: aes_gcm::Aes256Gcm,
+}
+
+#[cfg(feature = "encryption")]
+impl Aes256GcmCipher {
+    /// Nonce length, prepended to each ciphertext
+    const NONCE_LENGTH: usize = 12;
+
+    /// A cipher for a 256-bit `key`, stored with values as `key_id`
+    pub fn new(key_id: impl Into<String>, key: &[u8; 32]) -> Self {
+        use aes_gcm::KeyInit;
+        Self {
+            key_id: key_id.into(),
+            cipher: aes_gcm::Aes256Gcm::new(key.into()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl FieldCipher for Aes256GcmCipher {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, AeadCore, OsRng};
+        let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| ChakraError::internal("AES-GCM encryption failed"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        use aes_gcm::aead::Aead;
+        if ciphertext.len() < Self::NONCE_LENGTH {
+            return Err(ChakraError::internal("Encrypted value is truncated"));
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(Self::NONCE_LENGTH);
+        self.cipher.decrypt(nonce.into(), ciphertext).map_err(|_| {
+            ChakraError::internal(format!(
+                "Cannot decrypt value with key `{}`: wrong key or altered data",
+                self.key_id
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// XORs with a one-byte key, enough to tell keys apart
+    struct Xor(&'static str, u8);
+
+    impl FieldCipher for Xor {
+        fn key_id(&self) -> &str {
+            self.0
+        }
+
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+            Ok(plaintext.iter().map(|b| b ^ self.1).collect())
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+            self.encrypt(ciphertext)
+        }
+    }
+
+    #[test]
+    fn test_keyring() {
+        let old = Keyring::new(Xor("k1", 0x55));
+        let stored = old.encrypt(b"\"secret\"").unwrap();
+        assert_eq!(&stored[..4], &[FORMAT_VERSION, 2, b'k', b'1']);
+        assert!(!stored.windows(6).any(|w| w == b"secret"));
+
+        let rotated = Keyring::new(Xor("k2", 0x33)).previous(Xor("k1", 0x55));
+        assert_eq!(rotated.decrypt(&stored).unwrap(), b"\"secret\"");
+        assert_eq!(rotated.primary_key_id(), "k2");
+        assert_eq!(split(&rotated.encrypt(b"x").unwrap()).unwrap().0, "k2");
+
+        let err = Keyring::new(Xor("k2", 0x33)).decrypt(&stored).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Configuration error: No field cipher for key `k1`"
+        );
+        assert!(split(b"plain").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for Chakra ORM
 
+use serde::Serialize;
 use std::fmt;
 use thiserror::Error;
 
@@ -61,6 +62,10 @@ pub enum ChakraError {
     #[error("Configuration error: {message}")]
     Config { message: String },
 
+    /// Statement blocked by a statement guard rule
+    #[error("Statement denied by rule '{rule}': {message}")]
+    Policy { rule: String, message: String },
+
     /// IO errors
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
@@ -70,6 +75,88 @@ pub enum ChakraError {
     Internal(String),
 }
 
+/// Stable identifier of an error's kind, from `ChakraError::code`
+///
+/// Unlike messages, codes don't change between releases, so middleware can
+/// map them to responses or metrics. New codes may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ErrorCode {
+    ConnectionFailed,
+    AuthenticationFailed,
+    Tls,
+    PoolClosed,
+    PoolTimeout,
+    PoolOverloaded,
+    Pool,
+    NotFound,
+    MultipleResults,
+    UniqueViolation,
+    ForeignKeyViolation,
+    CheckViolation,
+    NotNullViolation,
+    SyntaxError,
+    QueryTimeout,
+    Cancelled,
+    SerializationFailure,
+    Deadlock,
+    InvalidQuery,
+    ExecutionFailed,
+    Transaction,
+    Model,
+    Validation,
+    Migration,
+    TypeConversion,
+    Config,
+    PolicyViolation,
+    Io,
+    Internal,
+}
+
+impl ErrorCode {
+    /// The code as a snake_case string, e.g. `"unique_violation"`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ConnectionFailed => "connection_failed",
+            ErrorCode::AuthenticationFailed => "authentication_failed",
+            ErrorCode::Tls => "tls",
+            ErrorCode::PoolClosed => "pool_closed",
+            ErrorCode::PoolTimeout => "pool_timeout",
+            ErrorCode::PoolOverloaded => "pool_overloaded",
+            ErrorCode::Pool => "pool",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MultipleResults => "multiple_results",
+            ErrorCode::UniqueViolation => "unique_violation",
+            ErrorCode::ForeignKeyViolation => "foreign_key_violation",
+            ErrorCode::CheckViolation => "check_violation",
+            ErrorCode::NotNullViolation => "not_null_violation",
+            ErrorCode::SyntaxError => "syntax_error",
+            ErrorCode::QueryTimeout => "query_timeout",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::SerializationFailure => "serialization_failure",
+            ErrorCode::Deadlock => "deadlock",
+            ErrorCode::InvalidQuery => "invalid_query",
+            ErrorCode::ExecutionFailed => "execution_failed",
+            ErrorCode::Transaction => "transaction",
+            ErrorCode::Model => "model",
+            ErrorCode::Validation => "validation",
+            ErrorCode::Migration => "migration",
+            ErrorCode::TypeConversion => "type_conversion",
+            ErrorCode::Config => "config",
+            ErrorCode::PolicyViolation => "policy_violation",
+            ErrorCode::Io => "io",
+            ErrorCode::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Connection-specific errors
 #[derive(Error, Debug)]
 pub enum ConnectionError {
@@ -85,6 +172,12 @@ pub enum ConnectionError {
     #[error("Pool timeout after {timeout:?}")]
     PoolTimeout { timeout: std::time::Duration },
 
+    #[error("Pool overloaded: {waiters} waiting, average wait {avg_wait:?}")]
+    PoolOverloaded {
+        waiters: usize,
+        avg_wait: std::time::Duration,
+    },
+
     #[error("Authentication failed: {message}")]
     AuthenticationFailed { message: String },
 
@@ -101,8 +194,13 @@ pub enum QueryError {
     #[error("Multiple records found where one expected")]
     MultipleResults,
 
-    #[error("Unique constraint violated on field: {field}")]
-    UniqueViolation { field: String },
+    #[error("Unique constraint violated on {}", unique_target(constraint, fields))]
+    UniqueViolation {
+        /// Constraint name, when the database reports one
+        constraint: Option<String>,
+        /// Fields covered by the constraint, if known
+        fields: Vec<String>,
+    },
 
     #[error("Foreign key constraint violated: {constraint}")]
     ForeignKeyViolation { constraint: String },
@@ -125,6 +223,12 @@ pub enum QueryError {
     #[error("Query cancelled")]
     Cancelled,
 
+    #[error("Could not serialize access: {message}")]
+    SerializationFailure { message: String },
+
+    #[error("Deadlock detected: {message}")]
+    Deadlock { message: String },
+
     #[error("Invalid query: {message}")]
     Invalid { message: String },
 
@@ -149,6 +253,9 @@ pub enum ModelError {
 
     #[error("Relationship not loaded: {relationship}")]
     RelationshipNotLoaded { relationship: String },
+
+    #[error("Model {model} reads from a view and can't be written")]
+    ReadOnly { model: String },
 }
 
 /// Validation errors
@@ -179,6 +286,35 @@ pub enum ValidationError {
 
     #[error("Pattern mismatch for field '{field}': expected pattern {pattern}")]
     PatternMismatch { field: String, pattern: String },
+
+    /// Every failed check of one model, from `Model::validate`
+    #[error("{}", join_errors(errors))]
+    Fields { errors: Vec<ValidationError> },
+}
+
+impl ValidationError {
+    /// The field that failed, or `None` for `Fields`
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            ValidationError::FieldValidation { field, .. }
+            | ValidationError::OutOfRange { field, .. }
+            | ValidationError::InvalidFormat { field, .. }
+            | ValidationError::TooLong { field, .. }
+            | ValidationError::TooShort { field, .. }
+            | ValidationError::PatternMismatch { field, .. } => Some(field),
+            ValidationError::Fields { .. } => None,
+        }
+    }
+
+    /// The individual failures, e.g. to show next to form fields
+    pub fn errors(&self) -> Vec<&ValidationError> {
+        match self {
+            ValidationError::Fields { errors } => {
+                errors.iter().flat_map(ValidationError::errors).collect()
+            }
+            other => vec![other],
+        }
+    }
 }
 
 impl ChakraError {
@@ -201,15 +337,155 @@ impl ChakraError {
         }
     }
 
+    /// The stable code of this error's kind
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ChakraError::Connection(e) => match e {
+                ConnectionError::ConnectionFailed { .. } => ErrorCode::ConnectionFailed,
+                ConnectionError::Configuration { .. } => ErrorCode::Config,
+                ConnectionError::PoolClosed => ErrorCode::PoolClosed,
+                ConnectionError::PoolTimeout { .. } => ErrorCode::PoolTimeout,
+                ConnectionError::PoolOverloaded { .. } => ErrorCode::PoolOverloaded,
+                ConnectionError::AuthenticationFailed { .. } => ErrorCode::AuthenticationFailed,
+                ConnectionError::SslError { .. } => ErrorCode::Tls,
+            },
+            ChakraError::Query(e) => match e {
+                QueryError::NotFound => ErrorCode::NotFound,
+                QueryError::MultipleResults => ErrorCode::MultipleResults,
+                QueryError::UniqueViolation { .. } => ErrorCode::UniqueViolation,
+                QueryError::ForeignKeyViolation { .. } => ErrorCode::ForeignKeyViolation,
+                QueryError::CheckViolation { .. } => ErrorCode::CheckViolation,
+                QueryError::NotNullViolation { .. } => ErrorCode::NotNullViolation,
+                QueryError::SyntaxError { .. } => ErrorCode::SyntaxError,
+                QueryError::Timeout { .. } => ErrorCode::QueryTimeout,
+                QueryError::Cancelled => ErrorCode::Cancelled,
+                QueryError::SerializationFailure { .. } => ErrorCode::SerializationFailure,
+                QueryError::Deadlock { .. } => ErrorCode::Deadlock,
+                QueryError::Invalid { .. } => ErrorCode::InvalidQuery,
+                QueryError::ExecutionFailed { .. } => ErrorCode::ExecutionFailed,
+            },
+            ChakraError::Transaction { .. } => ErrorCode::Transaction,
+            ChakraError::Model(_) => ErrorCode::Model,
+            ChakraError::Validation(_) => ErrorCode::Validation,
+            ChakraError::Migration { .. } => ErrorCode::Migration,
+            ChakraError::TypeConversion { .. } => ErrorCode::TypeConversion,
+            ChakraError::Pool { .. } => ErrorCode::Pool,
+            ChakraError::Config { .. } => ErrorCode::Config,
+            ChakraError::Policy { .. } => ErrorCode::PolicyViolation,
+            ChakraError::Io(_) => ErrorCode::Io,
+            ChakraError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Check if running the same work again may succeed: a transaction
+    /// conflict, or no connection to run it on at the time
+    ///
+    /// Timeouts are not included, as the same query is likely to time out
+    /// again.
+    pub fn is_retryable(&self) -> bool {
+        self.is_transaction_conflict()
+            || matches!(
+                self.code(),
+                ErrorCode::ConnectionFailed | ErrorCode::PoolTimeout | ErrorCode::PoolOverloaded
+            )
+    }
+
+    /// Check if a unique, foreign key, check or NOT NULL constraint rejected
+    /// the statement
+    pub fn is_constraint_violation(&self) -> bool {
+        matches!(
+            self.code(),
+            ErrorCode::UniqueViolation
+                | ErrorCode::ForeignKeyViolation
+                | ErrorCode::CheckViolation
+                | ErrorCode::NotNullViolation
+        )
+    }
+
+    /// Name of the violated constraint, when the database reports one
+    ///
+    /// NOT NULL violations name a field instead; see the error itself.
+    pub fn constraint_name(&self) -> Option<&str> {
+        match self {
+            ChakraError::Query(QueryError::UniqueViolation { constraint, .. }) => {
+                constraint.as_deref()
+            }
+            ChakraError::Query(
+                QueryError::ForeignKeyViolation { constraint }
+                | QueryError::CheckViolation { constraint },
+            ) => Some(constraint),
+            _ => None,
+        }
+    }
+
+    /// Check if the database rejected the credentials
+    pub fn is_authentication_failure(&self) -> bool {
+        matches!(
+            self,
+            ChakraError::Connection(ConnectionError::AuthenticationFailed { .. })
+        )
+    }
+
+    /// Check if a statement guard blocked the statement
+    pub fn is_policy_violation(&self) -> bool {
+        matches!(self, ChakraError::Policy { .. })
+    }
+
+    /// Check if a model failed validation before being written
+    pub fn is_validation_error(&self) -> bool {
+        matches!(self, ChakraError::Validation(_))
+    }
+
     /// Check if this is a not found error
     pub fn is_not_found(&self) -> bool {
         matches!(self, ChakraError::Query(QueryError::NotFound))
     }
 
+    /// Create a unique violation error
+    pub fn unique_violation(constraint: Option<String>, fields: Vec<String>) -> Self {
+        Self::Query(QueryError::UniqueViolation { constraint, fields })
+    }
+
     /// Check if this is a unique violation
     pub fn is_unique_violation(&self) -> bool {
         matches!(self, ChakraError::Query(QueryError::UniqueViolation { .. }))
     }
+
+    /// Check if the transaction lost a conflict with a concurrent one, by a
+    /// serialization failure or deadlock, and may succeed if run again
+    pub fn is_transaction_conflict(&self) -> bool {
+        matches!(
+            self,
+            ChakraError::Query(
+                QueryError::SerializationFailure { .. } | QueryError::Deadlock { .. }
+            )
+        )
+    }
+
+    /// Fields of the violated unique constraint, for per-field form errors
+    pub fn unique_violation_fields(&self) -> Option<&[String]> {
+        match self {
+            ChakraError::Query(QueryError::UniqueViolation { fields, .. }) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+fn join_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn unique_target(constraint: &Option<String>, fields: &[String]) -> String {
+    match (constraint, fields.len()) {
+        (_, 1) => format!("field: {}", fields[0]),
+        (_, n) if n > 1 => format!("fields: {}", fields.join(", ")),
+        (Some(name), _) => format!("constraint: {}", name),
+        (None, _) => "unknown constraint".to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -222,7 +498,10 @@ mod tests {
         assert_eq!(err.to_string(), "Query error: Record not found");
 
         let err = ChakraError::connection("Failed to connect");
-        assert_eq!(err.to_string(), "Connection error: Connection failed: Failed to connect");
+        assert_eq!(
+            err.to_string(),
+            "Connection error: Connection failed: Failed to connect"
+        );
     }
 
     #[test]
@@ -230,11 +509,73 @@ mod tests {
         let err = ChakraError::Query(QueryError::NotFound);
         assert!(err.is_not_found());
         assert!(!err.is_unique_violation());
+        assert!(!err.is_transaction_conflict());
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert!(!err.is_retryable() && !err.is_constraint_violation());
 
         let err = ChakraError::Query(QueryError::UniqueViolation {
-            field: "email".to_string(),
+            constraint: None,
+            fields: vec!["email".to_string()],
         });
         assert!(!err.is_not_found());
         assert!(err.is_unique_violation());
+        assert_eq!(
+            err.to_string(),
+            "Query error: Unique constraint violated on field: email"
+        );
+
+        let err = ChakraError::unique_violation(
+            Some("uq_tenant_slug".into()),
+            vec!["tenant_id".into(), "slug".into()],
+        );
+        assert_eq!(
+            err.unique_violation_fields().unwrap(),
+            ["tenant_id", "slug"]
+        );
+        assert!(err.is_constraint_violation());
+        assert_eq!(err.constraint_name(), Some("uq_tenant_slug"));
+        assert_eq!(err.code().to_string(), "unique_violation");
+        assert_eq!(
+            err.to_string(),
+            "Query error: Unique constraint violated on fields: tenant_id, slug"
+        );
+
+        let err = ChakraError::Query(QueryError::Deadlock {
+            message: "deadlock detected".into(),
+        });
+        assert!(err.is_transaction_conflict() && err.is_retryable());
+        assert_eq!(serde_json::to_string(&err.code()).unwrap(), "\"deadlock\"");
+
+        let err = ChakraError::Query(QueryError::ForeignKeyViolation {
+            constraint: "posts_author_id_fkey".into(),
+        });
+        assert!(err.is_constraint_violation() && !err.is_retryable());
+        assert_eq!(err.constraint_name(), Some("posts_author_id_fkey"));
+
+        let err = ChakraError::connection("connection reset by peer");
+        assert_eq!(err.code(), ErrorCode::ConnectionFailed);
+        assert!(err.is_retryable());
+        assert!(!ChakraError::Query(QueryError::Timeout { duration_ms: 5 }).is_retryable());
+        assert_eq!(ChakraError::internal("x").constraint_name(), None);
+
+        let err = ChakraError::from(ValidationError::Fields {
+            errors: vec![
+                ValidationError::TooShort {
+                    field: "name".into(),
+                    min_length: 1,
+                    actual_length: 0,
+                },
+                ValidationError::InvalidFormat {
+                    field: "email".into(),
+                    message: "not an email address".into(),
+                },
+            ],
+        });
+        assert!(err.is_validation_error());
+        assert_eq!(
+            err.to_string(),
+            "Validation error: Value too short for field 'name': min 1, got 0; \
+             Invalid format for field 'email': not an email address"
+        );
     }
 }
@@ -0,0 +1,456 @@
+//! Query execution for Chakra ORM
+//!
+//! This module defines the `Executor` trait implemented by each database
+//! adapter, so higher layers can run queries without knowing the backend.
+
+use crate::checksum::{self, Checksum};
+use crate::error::{ChakraError, Result};
+use crate::model::ModelMeta;
+use crate::query::{Query, QueryType};
+use crate::result::{FromValue, Row, RowStream};
+use crate::sql::{Dialect, SqlFragment};
+use crate::sql_cache::SqlCache;
+use crate::transaction::Transaction;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+
+/// What a write statement did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecResult {
+    /// Rows inserted, updated or deleted
+    pub rows_affected: u64,
+    /// The key generated for the last row an INSERT created, if known
+    pub last_insert_id: Option<Value>,
+}
+
+impl ExecResult {
+    /// The generated key as `T`, failing if the statement reported none
+    pub fn last_insert_id_as<T: FromValue>(&self) -> Result<T> {
+        let id = self
+            .last_insert_id
+            .as_ref()
+            .ok_or_else(|| ChakraError::internal("the statement reported no inserted id"))?;
+        T::from_value(id)
+    }
+}
+
+/// Conversion to `&dyn Executor`, implemented for every executor
+///
+/// Lets the trait's default methods hand `self` to functions taking a
+/// trait object.
+pub trait AsExecutor {
+    /// This executor as a trait object
+    fn as_executor(&self) -> &dyn Executor;
+}
+
+impl<E: Executor> AsExecutor for E {
+    fn as_executor(&self) -> &dyn Executor {
+        self
+    }
+}
+
+/// A database executor
+#[async_trait]
+pub trait Executor: AsExecutor + Send + Sync {
+    /// Get the SQL dialect
+    fn dialect(&self) -> &dyn Dialect;
+
+    /// Execute a query and return rows
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>>;
+
+    /// Execute a statement and return the affected row count
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64>;
+
+    /// Execute a statement and return the affected row count and the key
+    /// generated for the last row it inserted
+    ///
+    /// The default reports no key. Adapters read it on the same connection
+    /// right after the statement, as `LAST_INSERT_ID()` on MySQL and
+    /// `last_insert_rowid()` on SQLite; it only means anything after an
+    /// INSERT.
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        Ok(ExecResult {
+            rows_affected: self.execute(sql, params).await?,
+            last_insert_id: None,
+        })
+    }
+
+    /// Stream the rows of a query as they are fetched
+    ///
+    /// Nothing is sent until the stream is first polled. The default fetches
+    /// every row up front; adapters override it to fetch incrementally.
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        buffered_stream(self, sql, params)
+    }
+
+    /// Maximum rows returned by an unbounded SELECT, if guarded
+    fn max_rows_guard(&self) -> Option<usize> {
+        None
+    }
+
+    /// Cache of generated SQL keyed by query shape, if enabled
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        None
+    }
+
+    /// Whether the connection was configured read-only
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// The executor for a model's database, if this executor spans several
+    /// databases like a `Router` does
+    fn route(&self, _meta: &ModelMeta) -> Result<Option<&dyn Executor>> {
+        Ok(None)
+    }
+
+    /// This executor held to one connection, for statements that must run
+    /// together such as a transaction's
+    ///
+    /// `None` if every statement already runs on the same connection.
+    /// Pooled executors check a connection out and keep it until the
+    /// returned executor is dropped, when the pool resets it.
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        Ok(None)
+    }
+
+    /// Begin a transaction on one connection, or a savepoint if this
+    /// executor is already a transaction
+    async fn begin_transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::begin(self.as_executor()).await
+    }
+
+    /// Fail with a `read_only` policy error if `query` writes and the
+    /// connection is read-only
+    ///
+    /// The database session is read-only as well; this rejects the query
+    /// before it is sent.
+    fn check_read_only(&self, query: &Query) -> Result<()> {
+        if self.read_only() && !query.is_read_only() {
+            return Err(read_only_violation(
+                &format!("{:?}", query.query_type).to_uppercase(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Generate SQL for a query, through the SQL cache if there is one
+    fn generate(&self, query: &Query) -> SqlFragment {
+        match self.sql_cache() {
+            Some(cache) => cache.generate(self.dialect(), query),
+            None => self.dialect().generate(query),
+        }
+    }
+
+    /// Run a SELECT query and return its rows
+    ///
+    /// Unbounded queries are limited by `max_rows_guard` unless marked
+    /// with `.unlimited()`.
+    async fn fetch_all(&self, query: &Query) -> Result<Vec<Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = match self.max_rows_guard() {
+            Some(max_rows) => {
+                let mut query = query.clone();
+                query.apply_row_guard(max_rows);
+                self.generate(&query)
+            }
+            None => self.generate(query),
+        };
+        self.query(&fragment.sql, &fragment.params).await
+    }
+
+    /// Compute an order-independent checksum of a SELECT query's rows
+    ///
+    /// The database hashes the rows where the dialect supports it, so only
+    /// the count and hash are sent back; otherwise the rows are fetched and
+    /// hashed here. Rows are not limited by `max_rows_guard`.
+    async fn checksum_query(&self, query: &Query) -> Result<Checksum> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        let columns = checksum::result_columns(query);
+        match self.dialect().checksum_sql(&fragment.sql, &columns) {
+            Some(sql) => {
+                let rows = self.query(&sql, &fragment.params).await?;
+                let row = rows
+                    .first()
+                    .ok_or_else(|| ChakraError::internal("checksum query returned no rows"))?;
+                Checksum::from_row(row)
+            }
+            None => {
+                let rows = self.query(&fragment.sql, &fragment.params).await?;
+                Ok(Checksum::of_rows(&rows))
+            }
+        }
+    }
+
+    /// Run a SELECT query and return its rows as an Arrow record batch
+    ///
+    /// Rows are limited as in `fetch_all`; see `crate::arrow` for the column
+    /// types.
+    #[cfg(feature = "arrow")]
+    async fn fetch_arrow(&self, query: &Query) -> Result<arrow_array::RecordBatch> {
+        let rows = self.fetch_all(query).await?;
+        crate::arrow::to_record_batch(&rows)
+    }
+
+    /// Stream the rows of a SELECT query as they are fetched
+    ///
+    /// Unlike `fetch_all`, the rows are not limited by `max_rows_guard`:
+    /// streaming is how large scans are meant to run.
+    fn fetch_stream(&self, query: &Query) -> Result<RowStream<'_, Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        Ok(RowStream::new(self.stream(fragment.sql, fragment.params)))
+    }
+
+    /// Run an INSERT, split into as many statements as the dialect's
+    /// parameter limit requires, and return the total affected row count
+    ///
+    /// On SQLite, rows that leave out different columns also go in
+    /// separate statements. Several statements run in one transaction, so
+    /// either every row is inserted or none is.
+    async fn insert_all(&self, query: &Query) -> Result<u64> {
+        self.check_read_only(query)?;
+        let batches = self.dialect().insert_batches(query);
+        for batch in &batches {
+            self.dialect().validate(batch)?;
+        }
+        if let [batch] = batches.as_slice() {
+            let fragment = self.dialect().generate(batch);
+            return self.execute(&fragment.sql, &fragment.params).await;
+        }
+        let tx = self.begin_transaction().await?;
+        let result = async {
+            let mut affected = 0;
+            for batch in &batches {
+                let fragment = self.dialect().generate(batch);
+                affected += tx.execute(&fragment.sql, &fragment.params).await?;
+            }
+            Ok(affected)
+        }
+        .await;
+        match result {
+            Ok(affected) => {
+                tx.commit().await?;
+                Ok(affected)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Run an INSERT, UPDATE or DELETE and return its `returning` columns
+    ///
+    /// Executors for databases without RETURNING override this to emulate it.
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        if query.returning.is_empty() {
+            self.execute(&fragment.sql, &fragment.params).await?;
+            return Ok(Vec::new());
+        }
+        self.query(&fragment.sql, &fragment.params).await
+    }
+
+    /// Run an INSERT, UPDATE or DELETE and return the affected row count
+    async fn execute_query(&self, query: &Query) -> Result<u64> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        self.execute(&fragment.sql, &fragment.params).await
+    }
+
+    /// Run an INSERT, UPDATE or DELETE and return what it did
+    ///
+    /// For an INSERT, `last_insert_id` is the key of the last row inserted.
+    /// Where the dialect has RETURNING it is read from the first `returning`
+    /// column, so PostgreSQL needs one; otherwise `returning` is ignored and
+    /// the key comes from `execute_with_id`. Naming the key in `returning`
+    /// gives the same result on every database, except that MySQL reports
+    /// the first row's key for a multi-row INSERT.
+    async fn exec(&self, query: &Query) -> Result<ExecResult> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let insert = query.query_type == QueryType::Insert;
+        let mut query = query.clone();
+        let key = match query.returning.first() {
+            Some(key) if insert && self.dialect().supports_returning() => key.clone(),
+            _ => {
+                query.returning.clear();
+                let fragment = self.generate(&query);
+                let mut result = self
+                    .execute_with_id(&fragment.sql, &fragment.params)
+                    .await?;
+                if !insert || result.rows_affected == 0 {
+                    result.last_insert_id = None;
+                }
+                return Ok(result);
+            }
+        };
+        query.returning = vec![key.clone()];
+        let fragment = self.generate(&query);
+        let rows = self.query(&fragment.sql, &fragment.params).await?;
+        Ok(ExecResult {
+            rows_affected: rows.len() as u64,
+            last_insert_id: rows.last().and_then(|row| row.get(&key)).cloned(),
+        })
+    }
+
+    /// Execute one statement once for each parameter set and return the
+    /// total affected row count
+    ///
+    /// The default runs them one at a time. The adapters prepare the
+    /// statement once and run every set in one transaction, or in a
+    /// savepoint of the open one, so a failure leaves none of them applied.
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        let mut affected = 0;
+        for params in params {
+            affected += self.execute(sql, params).await?;
+        }
+        Ok(affected)
+    }
+
+    /// Check `values` against the model's multi-column unique constraints
+    /// before inserting, failing with a `UniqueViolation` naming its fields
+    async fn validate_unique(
+        &self,
+        meta: &ModelMeta,
+        values: &HashMap<String, Value>,
+    ) -> Result<()> {
+        for check in meta.unique_checks(values) {
+            let fragment = self.generate(&check.query);
+            if !self
+                .query(&fragment.sql, &fragment.params)
+                .await?
+                .is_empty()
+            {
+                return Err(check.violation());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A boxed executor, such as the connection `pin` returns, runs everything
+/// on the executor it holds
+#[async_trait]
+impl<E: Executor + ?Sized> Executor for Box<E> {
+    fn dialect(&self) -> &dyn Dialect {
+        (**self).dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        (**self).query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        (**self).execute(sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        (**self).execute_with_id(sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        (**self).stream(sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        (**self).max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        (**self).sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        (**self).read_only()
+    }
+
+    fn route(&self, meta: &ModelMeta) -> Result<Option<&dyn Executor>> {
+        (**self).route(meta)
+    }
+
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        (**self).pin().await
+    }
+
+    async fn begin_transaction(&self) -> Result<Transaction<'_>> {
+        (**self).begin_transaction().await
+    }
+
+    async fn fetch_all(&self, query: &Query) -> Result<Vec<Row>> {
+        (**self).fetch_all(query).await
+    }
+
+    async fn checksum_query(&self, query: &Query) -> Result<Checksum> {
+        (**self).checksum_query(query).await
+    }
+
+    fn fetch_stream(&self, query: &Query) -> Result<RowStream<'_, Row>> {
+        (**self).fetch_stream(query)
+    }
+
+    async fn insert_all(&self, query: &Query) -> Result<u64> {
+        (**self).insert_all(query).await
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        (**self).execute_returning(query).await
+    }
+
+    async fn execute_query(&self, query: &Query) -> Result<u64> {
+        (**self).execute_query(query).await
+    }
+
+    async fn exec(&self, query: &Query) -> Result<ExecResult> {
+        (**self).exec(query).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        (**self).execute_many(sql, params).await
+    }
+
+    async fn validate_unique(
+        &self,
+        meta: &ModelMeta,
+        values: &HashMap<String, Value>,
+    ) -> Result<()> {
+        (**self).validate_unique(meta, values).await
+    }
+}
+
+/// The executor a model's queries run on: the one `executor` routes it to,
+/// or `executor` itself
+pub fn route<'a>(executor: &'a dyn Executor, meta: &ModelMeta) -> Result<&'a dyn Executor> {
+    Ok(executor.route(meta)?.unwrap_or(executor))
+}
+
+/// A stream that runs `sql` with `executor.query` when first polled and
+/// then yields the fetched rows
+pub fn buffered_stream<E: Executor + ?Sized>(
+    executor: &E,
+    sql: String,
+    params: Vec<Value>,
+) -> BoxStream<'_, Result<Row>> {
+    stream::once(async move { executor.query(&sql, &params).await })
+        .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed()
+}
+
+/// The error for a `statement` rejected on a read-only connection
+pub fn read_only_violation(statement: &str) -> ChakraError {
+    ChakraError::Policy {
+        rule: "read_only".to_string(),
+        message: format!("{} is not allowed on a read-only connection", statement),
+    }
+}
@@ -0,0 +1,411 @@
+//! EXPLAIN support for Chakra ORM
+//!
+//! This module provides:
+//! - `ExplainOptions` - ANALYZE, VERBOSE and output format
+//! - `Explain` - a query wrapped in the dialect's EXPLAIN syntax
+//! - `QueryPlan` - a typed plan parsed from EXPLAIN output, for assertions
+//!   like "this query uses the `users_email_key` index" in tests
+
+use crate::error::{ChakraError, Result};
+use crate::query::Query;
+use crate::result::{FromValue, Row};
+use crate::sql::{Dialect, SqlFragment};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// Output format for EXPLAIN
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExplainFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// How to run EXPLAIN
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExplainOptions {
+    /// Run the query and report actual rows and timings
+    pub analyze: bool,
+    /// Include output columns and other detail (Postgres)
+    pub verbose: bool,
+    pub format: ExplainFormat,
+}
+
+impl ExplainOptions {
+    /// Plain EXPLAIN
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the query and report what actually happened
+    pub fn analyze(mut self) -> Self {
+        self.analyze = true;
+        self
+    }
+
+    /// Ask for more detail
+    pub fn verbose(mut self) -> Self {
+        self.verbose = true;
+        self
+    }
+
+    /// Ask for a JSON plan, which `QueryPlan` can parse
+    pub fn json(mut self) -> Self {
+        self.format = ExplainFormat::Json;
+        self
+    }
+}
+
+/// A query wrapped in EXPLAIN
+#[derive(Debug, Clone)]
+pub struct Explain {
+    pub query: Query,
+    pub options: ExplainOptions,
+}
+
+impl Explain {
+    /// Generate the EXPLAIN statement for a dialect
+    ///
+    /// Postgres takes every option; MySQL has `EXPLAIN FORMAT=JSON` and
+    /// `EXPLAIN ANALYZE` (tree output only); SQLite always gets
+    /// `EXPLAIN QUERY PLAN`.
+    pub fn generate(&self, dialect: &dyn Dialect) -> SqlFragment {
+        let mut fragment = dialect.generate(&self.query);
+        let prefix = match dialect.name() {
+            "postgresql" => {
+                let mut options = Vec::new();
+                if self.options.analyze {
+                    options.push("ANALYZE");
+                }
+                if self.options.verbose {
+                    options.push("VERBOSE");
+                }
+                if self.options.format == ExplainFormat::Json {
+                    options.push("FORMAT JSON");
+                }
+                if options.is_empty() {
+                    "EXPLAIN".to_string()
+                } else {
+                    format!("EXPLAIN ({})", options.join(", "))
+                }
+            }
+            "mysql" if self.options.analyze => "EXPLAIN ANALYZE".to_string(),
+            "mysql" if self.options.format == ExplainFormat::Json => {
+                "EXPLAIN FORMAT=JSON".to_string()
+            }
+            "sqlite" => "EXPLAIN QUERY PLAN".to_string(),
+            _ => "EXPLAIN".to_string(),
+        };
+        fragment.sql = format!("{} {}", prefix, fragment.sql);
+        fragment
+    }
+}
+
+impl Query {
+    /// Wrap this query in EXPLAIN
+    pub fn explain(&self, options: ExplainOptions) -> Explain {
+        Explain {
+            query: self.clone(),
+            options,
+        }
+    }
+}
+
+/// One step of a query plan
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlanNode {
+    /// e.g. `Seq Scan`, `Index Scan`, `ref`, `SEARCH`
+    pub operation: String,
+    pub relation: Option<String>,
+    pub index: Option<String>,
+    /// Whether every row of `relation` is read
+    pub full_scan: bool,
+    pub estimated_rows: Option<f64>,
+    pub actual_rows: Option<f64>,
+    pub cost: Option<f64>,
+    pub children: Vec<PlanNode>,
+}
+
+/// A parsed EXPLAIN result
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub roots: Vec<PlanNode>,
+}
+
+impl QueryPlan {
+    /// Parse the rows returned by an `Explain`
+    ///
+    /// Postgres and MySQL plans must use `ExplainOptions::json()`; SQLite's
+    /// `EXPLAIN QUERY PLAN` rows are read as they are.
+    pub fn from_rows(dialect: &dyn Dialect, rows: &[Row]) -> Result<Self> {
+        if dialect.name() == "sqlite" {
+            let mut steps = Vec::new();
+            for row in rows {
+                steps.push((
+                    row.get_as::<i64>("id")?,
+                    row.get_as::<i64>("parent")?,
+                    row.get_as::<String>("detail")?,
+                ));
+            }
+            return Ok(Self::from_sqlite(&steps));
+        }
+
+        let value = rows
+            .first()
+            .and_then(|row| row.get_by_index(0))
+            .ok_or_else(|| ChakraError::internal("EXPLAIN returned no rows"))?;
+        let json = Json::from_value(value).map_err(|_| {
+            ChakraError::internal("EXPLAIN output is not JSON; use ExplainOptions::json()")
+        })?;
+        match dialect.name() {
+            "mysql" => Ok(Self::from_mysql_json(&json)),
+            _ => Ok(Self::from_postgres_json(&json)),
+        }
+    }
+
+    /// Parse Postgres `EXPLAIN (FORMAT JSON)` output
+    pub fn from_postgres_json(json: &Json) -> Self {
+        fn node(plan: &Json) -> PlanNode {
+            let text = |key: &str| plan.get(key).and_then(Json::as_str).map(str::to_string);
+            let operation = text("Node Type").unwrap_or_default();
+            PlanNode {
+                full_scan: operation == "Seq Scan",
+                operation,
+                relation: text("Relation Name"),
+                index: text("Index Name"),
+                estimated_rows: plan.get("Plan Rows").and_then(Json::as_f64),
+                actual_rows: plan.get("Actual Rows").and_then(Json::as_f64),
+                cost: plan.get("Total Cost").and_then(Json::as_f64),
+                children: plan
+                    .get("Plans")
+                    .and_then(Json::as_array)
+                    .map(|plans| plans.iter().map(node).collect())
+                    .unwrap_or_default(),
+            }
+        }
+
+        let entries = match json {
+            Json::Array(entries) => entries.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        Self {
+            roots: entries
+                .iter()
+                .filter_map(|entry| entry.get("Plan"))
+                .map(node)
+                .collect(),
+        }
+    }
+
+    /// Parse MySQL `EXPLAIN FORMAT=JSON` output
+    ///
+    /// Each `table` object becomes a node; the blocks around them (nested
+    /// loops, ordering, grouping) are flattened.
+    pub fn from_mysql_json(json: &Json) -> Self {
+        fn number(value: Option<&Json>) -> Option<f64> {
+            match value? {
+                Json::Number(n) => n.as_f64(),
+                Json::String(s) => s.parse().ok(),
+                _ => None,
+            }
+        }
+
+        fn collect(value: &Json, nodes: &mut Vec<PlanNode>) {
+            match value {
+                Json::Object(map) => {
+                    for (key, value) in map {
+                        if key == "table" {
+                            let text =
+                                |k: &str| value.get(k).and_then(Json::as_str).map(str::to_string);
+                            let operation = text("access_type").unwrap_or_default();
+                            let mut children = Vec::new();
+                            if let Some(sub) = value.get("materialized_from_subquery") {
+                                collect(sub, &mut children);
+                            }
+                            nodes.push(PlanNode {
+                                full_scan: operation == "ALL",
+                                operation,
+                                relation: text("table_name"),
+                                index: text("key"),
+                                estimated_rows: number(value.get("rows_examined_per_scan")),
+                                actual_rows: None,
+                                cost: value
+                                    .get("cost_info")
+                                    .and_then(|c| number(c.get("prefix_cost"))),
+                                children,
+                            });
+                        } else {
+                            collect(value, nodes);
+                        }
+                    }
+                }
+                Json::Array(items) => items.iter().for_each(|item| collect(item, nodes)),
+                _ => {}
+            }
+        }
+
+        let mut roots = Vec::new();
+        collect(json, &mut roots);
+        Self { roots }
+    }
+
+    /// Build a plan from SQLite's `(id, parent, detail)` rows
+    pub fn from_sqlite(steps: &[(i64, i64, String)]) -> Self {
+        fn node(steps: &[(i64, i64, String)], id: i64, detail: &str) -> PlanNode {
+            let mut words = detail.split_whitespace();
+            let operation = words.next().unwrap_or_default().to_string();
+            let relation = match words.next() {
+                // Before 3.36 scans read `SCAN TABLE users`
+                Some("TABLE") => words.next(),
+                other => other,
+            }
+            .filter(|_| operation == "SCAN" || operation == "SEARCH")
+            .map(str::to_string);
+            let index = ["USING COVERING INDEX ", "USING INDEX "]
+                .iter()
+                .find_map(|marker| detail.split_once(marker))
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .map(str::to_string);
+
+            PlanNode {
+                full_scan: operation == "SCAN" && relation.is_some() && index.is_none(),
+                operation,
+                relation,
+                index,
+                children: steps
+                    .iter()
+                    .filter(|(_, parent, _)| *parent == id)
+                    .map(|(child, _, detail)| node(steps, *child, detail))
+                    .collect(),
+                ..PlanNode::default()
+            }
+        }
+
+        Self {
+            roots: steps
+                .iter()
+                .filter(|(_, parent, _)| *parent == 0)
+                .map(|(id, _, detail)| node(steps, *id, detail))
+                .collect(),
+        }
+    }
+
+    /// Every node, depth first
+    pub fn nodes(&self) -> Vec<&PlanNode> {
+        fn walk<'a>(node: &'a PlanNode, out: &mut Vec<&'a PlanNode>) {
+            out.push(node);
+            node.children.iter().for_each(|child| walk(child, out));
+        }
+        let mut out = Vec::new();
+        self.roots.iter().for_each(|root| walk(root, &mut out));
+        out
+    }
+
+    /// Whether any step reads through the named index
+    pub fn uses_index(&self, index: &str) -> bool {
+        self.nodes()
+            .iter()
+            .any(|n| n.index.as_deref() == Some(index))
+    }
+
+    /// Tables read in full, without an index
+    pub fn full_scans(&self) -> Vec<&str> {
+        self.nodes()
+            .iter()
+            .filter(|n| n.full_scan)
+            .filter_map(|n| n.relation.as_deref())
+            .collect()
+    }
+
+    /// The planner's cost estimate, the highest of any step's
+    ///
+    /// Costs are in the planner's own units and only comparable within one
+    /// database. SQLite plans have none.
+    pub fn estimated_cost(&self) -> Option<f64> {
+        self.nodes().iter().filter_map(|n| n.cost).reduce(f64::max)
+    }
+
+    /// The most rows any step is expected to produce or examine
+    pub fn estimated_rows(&self) -> Option<f64> {
+        self.nodes()
+            .iter()
+            .filter_map(|n| n.estimated_rows)
+            .reduce(f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::Expr;
+    use crate::sql::{MySqlDialect, PostgresDialect, SqliteDialect};
+
+    #[test]
+    fn test_explain_sql() {
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::eq("id", 1))
+            .build();
+
+        let explain = query.explain(ExplainOptions::new().analyze().json());
+        assert_eq!(
+            explain.generate(&PostgresDialect).sql,
+            "EXPLAIN (ANALYZE, FORMAT JSON) SELECT * FROM \"users\" WHERE \"id\" = $1"
+        );
+        assert_eq!(
+            query
+                .explain(ExplainOptions::new().json())
+                .generate(&MySqlDialect)
+                .sql,
+            "EXPLAIN FORMAT=JSON SELECT * FROM `users` WHERE `id` = ?"
+        );
+        assert_eq!(
+            query
+                .explain(ExplainOptions::new())
+                .generate(&SqliteDialect)
+                .sql,
+            "EXPLAIN QUERY PLAN SELECT * FROM \"users\" WHERE \"id\" = $1"
+        );
+    }
+
+    #[test]
+    fn test_parse_plans() {
+        let json = serde_json::json!([{"Plan": {
+            "Node Type": "Nested Loop",
+            "Total Cost": 16.5,
+            "Plans": [
+                {"Node Type": "Seq Scan", "Relation Name": "orders", "Plan Rows": 120},
+                {"Node Type": "Index Scan", "Relation Name": "users", "Index Name": "users_pkey"}
+            ]
+        }}]);
+        let rows = vec![Row::new(vec!["QUERY PLAN".to_string()], vec![json.into()])];
+        let plan = QueryPlan::from_rows(&PostgresDialect, &rows).unwrap();
+        assert!(plan.uses_index("users_pkey"));
+        assert_eq!(plan.full_scans(), vec!["orders"]);
+        assert_eq!(plan.roots[0].cost, Some(16.5));
+        assert_eq!(plan.estimated_cost(), Some(16.5));
+        assert_eq!(plan.estimated_rows(), Some(120.0));
+
+        let mysql = QueryPlan::from_mysql_json(&serde_json::json!({"query_block": {
+            "nested_loop": [
+                {"table": {"table_name": "o", "access_type": "ALL"}},
+                {"table": {"table_name": "u", "access_type": "eq_ref", "key": "PRIMARY"}}
+            ]
+        }}));
+        assert!(mysql.uses_index("PRIMARY"));
+        assert_eq!(mysql.full_scans(), vec!["o"]);
+
+        let sqlite = QueryPlan::from_sqlite(&[
+            (2, 0, "SCAN orders".to_string()),
+            (
+                5,
+                0,
+                "SEARCH users USING INTEGER PRIMARY KEY (rowid=?)".to_string(),
+            ),
+            (9, 0, "SEARCH t USING COVERING INDEX t_a (a=?)".to_string()),
+        ]);
+        assert_eq!(sqlite.full_scans(), vec!["orders"]);
+        assert_eq!(sqlite.estimated_cost(), None);
+        assert!(sqlite.uses_index("t_a"));
+    }
+}
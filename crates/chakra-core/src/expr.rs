@@ -3,13 +3,20 @@
 //! This module provides:
 //! - `Expr` - Expression tree for WHERE clauses
 //! - `F` - Field reference expressions
+//! - `Column` - Typed column references generated for models
+//! - `JsonPath` - Paths into JSON columns
 //! - `Q` - Query expressions for complex conditions
 
+use crate::range::Range;
 use crate::types::Value;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::Duration;
 
 /// Comparison operators
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum CompareOp {
     Eq,
     Ne,
@@ -69,11 +76,23 @@ pub enum Expr {
         right: String,
     },
 
-    /// BETWEEN: column BETWEEN low AND high
+    /// BETWEEN: column [NOT] BETWEEN [SYMMETRIC] low AND high
     Between {
         column: String,
         low: Value,
         high: Value,
+        #[serde(default)]
+        negated: bool,
+        /// Accept the bounds in either order
+        #[serde(default)]
+        symmetric: bool,
+    },
+
+    /// Timestamp column within the last `seconds`, or older than that
+    RelativeTime {
+        column: String,
+        seconds: u64,
+        older: bool,
     },
 
     /// IN: column IN (values)
@@ -96,16 +115,22 @@ pub enum Expr {
     Raw(String),
 
     /// Function call
-    Function {
-        name: String,
-        args: Vec<Expr>,
-    },
+    Function { name: String, args: Vec<Expr> },
 
     /// Aggregate function
     Aggregate {
         function: AggregateFunc,
         column: String,
         distinct: bool,
+        /// Only aggregate rows matching this condition: `FILTER (WHERE ...)`
+        #[serde(default)]
+        filter: Option<Box<Expr>>,
+        /// Order of the aggregated values, for STRING_AGG and ARRAY_AGG
+        #[serde(default)]
+        order_by: Vec<crate::query::OrderBy>,
+        /// Separator for STRING_AGG, `,` if unset
+        #[serde(default)]
+        separator: Option<String>,
     },
 
     /// Arithmetic operation
@@ -123,10 +148,95 @@ pub enum Expr {
 
     /// Subquery
     Subquery(Box<crate::query::Query>),
+
+    /// EXISTS (subquery)
+    Exists {
+        query: Box<crate::query::Query>,
+        negated: bool,
+    },
+
+    /// IN (subquery): column IN (SELECT ...)
+    InSubquery {
+        column: String,
+        query: Box<crate::query::Query>,
+        negated: bool,
+    },
+
+    /// Comparison with a subquery: column op [ANY | ALL] (SELECT ...)
+    ///
+    /// Without a quantifier the subquery must return a single value.
+    SubqueryCompare {
+        column: String,
+        op: CompareOp,
+        quantifier: Option<Quantifier>,
+        query: Box<crate::query::Query>,
+    },
+
+    /// Value at a path in a JSON column: `column->'a'`, or `->>` as text
+    JsonGet {
+        column: String,
+        path: Vec<String>,
+        as_text: bool,
+    },
+
+    /// Comparison of the value at a path in a JSON column
+    JsonCompare {
+        column: String,
+        path: Vec<String>,
+        op: CompareOp,
+        value: Value,
+    },
+
+    /// JSON containment: column @> value
+    JsonContains { column: String, value: Value },
+
+    /// IN against one array parameter: column = ANY($1), or <> ALL when negated
+    InArray {
+        column: String,
+        values: Vec<Value>,
+        negated: bool,
+    },
+
+    /// Array containment: column @> ARRAY[values]
+    ArrayContains { column: String, values: Vec<Value> },
+
+    /// Array overlap: column && ARRAY[values]
+    ArrayOverlaps { column: String, values: Vec<Value> },
+
+    /// Range overlap: column && range
+    RangeOverlaps { column: String, value: Value },
+
+    /// Range containment: column @> value, a range or a single element
+    RangeContains { column: String, value: Value },
+
+    /// Type conversion: CAST(expr AS sql_type)
+    Cast { expr: Box<Expr>, sql_type: String },
+}
+
+/// A time zone name as a quoted literal, kept out of the parameters like a
+/// `DatePart` unit
+fn zone_literal(tz: &str) -> Expr {
+    Expr::Raw(Value::String(tz.to_string()).to_sql_literal())
+}
+
+/// Quantifier for comparisons against a subquery
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Serialize, Deserialize)]
+pub enum Quantifier {
+    Any,
+    All,
+}
+
+impl Quantifier {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Quantifier::Any => "ANY",
+            Quantifier::All => "ALL",
+        }
+    }
 }
 
 /// Aggregate functions
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum AggregateFunc {
     Count,
     Sum,
@@ -135,6 +245,10 @@ pub enum AggregateFunc {
     Max,
     ArrayAgg,
     StringAgg,
+    /// True if every value is true
+    BoolAnd,
+    /// True if any value is true
+    BoolOr,
 }
 
 impl AggregateFunc {
@@ -147,12 +261,49 @@ impl AggregateFunc {
             AggregateFunc::Max => "MAX",
             AggregateFunc::ArrayAgg => "ARRAY_AGG",
             AggregateFunc::StringAgg => "STRING_AGG",
+            AggregateFunc::BoolAnd => "BOOL_AND",
+            AggregateFunc::BoolOr => "BOOL_OR",
+        }
+    }
+}
+
+/// Units for `Expr::date_trunc` and `Expr::extract`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DatePart {
+    Year,
+    Month,
+    /// Weeks starting on Monday
+    Week,
+    Day,
+    Hour,
+    Minute,
+}
+
+impl DatePart {
+    pub const ALL: [DatePart; 6] = [
+        DatePart::Year,
+        DatePart::Month,
+        DatePart::Week,
+        DatePart::Day,
+        DatePart::Hour,
+        DatePart::Minute,
+    ];
+
+    /// The unit as a quoted SQL string literal, e.g. `'day'`
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            DatePart::Year => "'year'",
+            DatePart::Month => "'month'",
+            DatePart::Week => "'week'",
+            DatePart::Day => "'day'",
+            DatePart::Hour => "'hour'",
+            DatePart::Minute => "'minute'",
         }
     }
 }
 
 /// Arithmetic operators
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum ArithmeticOp {
     Add,
     Sub,
@@ -284,6 +435,39 @@ impl Expr {
         }
     }
 
+    /// Create an array containment expression: column has every value
+    pub fn array_contains<V: Into<Value>>(column: impl Into<String>, values: Vec<V>) -> Self {
+        Expr::ArrayContains {
+            column: column.into(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Create an array overlap expression: column has any of the values
+    pub fn array_overlaps<V: Into<Value>>(column: impl Into<String>, values: Vec<V>) -> Self {
+        Expr::ArrayOverlaps {
+            column: column.into(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Create a range overlap expression: column shares a point with `range`
+    pub fn range_overlaps<T: Into<Value>>(column: impl Into<String>, range: Range<T>) -> Self {
+        Expr::RangeOverlaps {
+            column: column.into(),
+            value: range.into(),
+        }
+    }
+
+    /// Create a range containment expression: column covers `value`, either
+    /// a whole range or a single element such as a timestamp
+    pub fn range_contains(column: impl Into<String>, value: impl Into<Value>) -> Self {
+        Expr::RangeContains {
+            column: column.into(),
+            value: value.into(),
+        }
+    }
+
     /// Create a BETWEEN expression
     pub fn between(
         column: impl Into<String>,
@@ -294,6 +478,50 @@ impl Expr {
             column: column.into(),
             low: low.into(),
             high: high.into(),
+            negated: false,
+            symmetric: false,
+        }
+    }
+
+    /// Create a NOT BETWEEN expression
+    pub fn not_between(
+        column: impl Into<String>,
+        low: impl Into<Value>,
+        high: impl Into<Value>,
+    ) -> Self {
+        Expr::Between {
+            column: column.into(),
+            low: low.into(),
+            high: high.into(),
+            negated: true,
+            symmetric: false,
+        }
+    }
+
+    /// Make a BETWEEN accept its bounds in either order (`BETWEEN SYMMETRIC`);
+    /// other expressions are returned unchanged
+    pub fn symmetric(mut self) -> Self {
+        if let Expr::Between { symmetric, .. } = &mut self {
+            *symmetric = true;
+        }
+        self
+    }
+
+    /// Create a filter for timestamps within the last `duration`
+    pub fn within_last(column: impl Into<String>, duration: Duration) -> Self {
+        Expr::RelativeTime {
+            column: column.into(),
+            seconds: duration.as_secs(),
+            older: false,
+        }
+    }
+
+    /// Create a filter for timestamps more than `duration` ago
+    pub fn older_than(column: impl Into<String>, duration: Duration) -> Self {
+        Expr::RelativeTime {
+            column: column.into(),
+            seconds: duration.as_secs(),
+            older: true,
         }
     }
 
@@ -322,10 +550,81 @@ impl Expr {
     }
 
     /// Negate the expression
+    #[allow(clippy::should_implement_trait)]
     pub fn not(self) -> Self {
         Expr::Not(Box::new(self))
     }
 
+    // Subquery constructors
+
+    /// Create an EXISTS expression
+    pub fn exists(query: crate::query::Query) -> Self {
+        Expr::Exists {
+            query: Box::new(query),
+            negated: false,
+        }
+    }
+
+    /// Create a NOT EXISTS expression
+    pub fn not_exists(query: crate::query::Query) -> Self {
+        Expr::Exists {
+            query: Box::new(query),
+            negated: true,
+        }
+    }
+
+    /// Create a `column IN (subquery)` expression
+    pub fn in_subquery(column: impl Into<String>, query: crate::query::Query) -> Self {
+        Expr::InSubquery {
+            column: column.into(),
+            query: Box::new(query),
+            negated: false,
+        }
+    }
+
+    /// Create a `column NOT IN (subquery)` expression
+    pub fn not_in_subquery(column: impl Into<String>, query: crate::query::Query) -> Self {
+        Expr::InSubquery {
+            column: column.into(),
+            query: Box::new(query),
+            negated: true,
+        }
+    }
+
+    /// Compare a column with a single-value subquery
+    pub fn compare_subquery(
+        column: impl Into<String>,
+        op: CompareOp,
+        query: crate::query::Query,
+    ) -> Self {
+        Expr::SubqueryCompare {
+            column: column.into(),
+            op,
+            quantifier: None,
+            query: Box::new(query),
+        }
+    }
+
+    /// Create a `column op ANY (subquery)` expression
+    pub fn any(column: impl Into<String>, op: CompareOp, query: crate::query::Query) -> Self {
+        Expr::SubqueryCompare {
+            column: column.into(),
+            op,
+            quantifier: Some(Quantifier::Any),
+            query: Box::new(query),
+        }
+    }
+
+    /// Create a `column op ALL (subquery)` expression
+    pub fn all(column: impl Into<String>, op: CompareOp, query: crate::query::Query) -> Self {
+        Expr::SubqueryCompare {
+            column: column.into(),
+            op,
+            quantifier: Some(Quantifier::All),
+            query: Box::new(query),
+        }
+    }
+
     /// Create a raw SQL expression
     pub fn raw(sql: impl Into<String>) -> Self {
         Expr::Raw(sql.into())
@@ -340,6 +639,258 @@ impl Expr {
     pub fn value(val: impl Into<Value>) -> Self {
         Expr::Value(val.into())
     }
+
+    /// Create a function call
+    pub fn function(name: impl Into<String>, args: Vec<Expr>) -> Self {
+        Expr::Function {
+            name: name.into(),
+            args,
+        }
+    }
+
+    /// The first argument that is not NULL
+    pub fn coalesce(args: Vec<Expr>) -> Self {
+        Self::function("COALESCE", args)
+    }
+
+    /// NULL if `value` equals `null_if`, otherwise `value`
+    pub fn nullif(value: Expr, null_if: Expr) -> Self {
+        Self::function("NULLIF", vec![value, null_if])
+    }
+
+    /// The largest argument; `MAX(...)` on SQLite
+    pub fn greatest(args: Vec<Expr>) -> Self {
+        Self::function("GREATEST", args)
+    }
+
+    /// The smallest argument; `MIN(...)` on SQLite
+    pub fn least(args: Vec<Expr>) -> Self {
+        Self::function("LEAST", args)
+    }
+
+    /// Concatenate strings, skipping NULL arguments
+    pub fn concat(args: Vec<Expr>) -> Self {
+        Self::function("CONCAT", args)
+    }
+
+    /// Lowercase a string
+    pub fn lower(arg: Expr) -> Self {
+        Self::function("LOWER", vec![arg])
+    }
+
+    /// Uppercase a string
+    pub fn upper(arg: Expr) -> Self {
+        Self::function("UPPER", vec![arg])
+    }
+
+    /// Length of a string in characters
+    pub fn length(arg: Expr) -> Self {
+        Self::function("LENGTH", vec![arg])
+    }
+
+    /// Truncate a timestamp to the start of its year, month, week, day,
+    /// hour or minute
+    pub fn date_trunc(unit: DatePart, arg: Expr) -> Self {
+        // The unit stays a literal so equal calls in SELECT and GROUP BY match
+        Self::function(
+            "DATE_TRUNC",
+            vec![Expr::Raw(unit.as_sql().to_string()), arg],
+        )
+    }
+
+    /// Truncate a UTC timestamp to the start of its `unit` in time zone
+    /// `tz`, e.g. `"Europe/Berlin"` or `"+05:30"`, giving the local time
+    ///
+    /// SQLite only knows `"UTC"` and fixed offsets.
+    pub fn date_trunc_in(unit: DatePart, arg: Expr, tz: &str) -> Self {
+        Self::function(
+            "DATE_TRUNC",
+            vec![Expr::Raw(unit.as_sql().to_string()), arg, zone_literal(tz)],
+        )
+    }
+
+    /// The year, month, ISO week, day of month, hour or minute of a
+    /// timestamp, as an integer
+    pub fn extract(unit: DatePart, arg: Expr) -> Self {
+        Self::function("EXTRACT", vec![Expr::Raw(unit.as_sql().to_string()), arg])
+    }
+
+    /// Like `extract`, for the local time in `tz` of a UTC timestamp
+    pub fn extract_in(unit: DatePart, arg: Expr, tz: &str) -> Self {
+        Self::function(
+            "EXTRACT",
+            vec![Expr::Raw(unit.as_sql().to_string()), arg, zone_literal(tz)],
+        )
+    }
+
+    /// Convert to another SQL type, named as the database spells it
+    pub fn cast(expr: Expr, sql_type: impl Into<String>) -> Self {
+        Expr::Cast {
+            expr: Box::new(expr),
+            sql_type: sql_type.into(),
+        }
+    }
+
+    /// Create an aggregate over a column, or `*` for COUNT
+    pub fn aggregate(function: AggregateFunc, column: impl Into<String>) -> Self {
+        Expr::Aggregate {
+            function,
+            column: column.into(),
+            distinct: false,
+            filter: None,
+            order_by: Vec::new(),
+            separator: None,
+        }
+    }
+
+    /// Create a COUNT(*) aggregate
+    pub fn count_all() -> Self {
+        Self::aggregate(AggregateFunc::Count, "*")
+    }
+
+    /// Join a column's values into one string: `STRING_AGG` on PostgreSQL,
+    /// `GROUP_CONCAT` on MySQL and SQLite
+    pub fn string_agg(column: impl Into<String>, separator: impl Into<String>) -> Self {
+        Expr::Aggregate {
+            function: AggregateFunc::StringAgg,
+            column: column.into(),
+            distinct: false,
+            filter: None,
+            order_by: Vec::new(),
+            separator: Some(separator.into()),
+        }
+    }
+
+    /// Collect a column's values into an array
+    pub fn array_agg(column: impl Into<String>) -> Self {
+        Self::aggregate(AggregateFunc::ArrayAgg, column)
+    }
+
+    /// True if `column` is true in every row: `BOOL_AND` on PostgreSQL,
+    /// `MIN` over the stored 0/1 on MySQL and SQLite
+    pub fn bool_and(column: impl Into<String>) -> Self {
+        Self::aggregate(AggregateFunc::BoolAnd, column)
+    }
+
+    /// True if `column` is true in any row: `BOOL_OR` on PostgreSQL,
+    /// `MAX` over the stored 0/1 on MySQL and SQLite
+    pub fn bool_or(column: impl Into<String>) -> Self {
+        Self::aggregate(AggregateFunc::BoolOr, column)
+    }
+
+    /// SQL-standard spelling of `bool_and`
+    pub fn every(column: impl Into<String>) -> Self {
+        Self::bool_and(column)
+    }
+
+    /// Order the values fed to an aggregate by `column`, after any earlier
+    /// ordering; other expressions are returned unchanged
+    pub fn ordered_by(mut self, column: impl Into<String>, order: crate::query::Order) -> Self {
+        if let Expr::Aggregate { order_by, .. } = &mut self {
+            order_by.push(crate::query::OrderBy {
+                column: column.into(),
+                order,
+                nulls: None,
+                expr: None,
+            });
+        }
+        self
+    }
+
+    /// Restrict an aggregate to rows matching `condition`, ANDed with any
+    /// earlier filter; other expressions are returned unchanged
+    pub fn filter_where(mut self, condition: Expr) -> Self {
+        if let Expr::Aggregate { filter, .. } = &mut self {
+            *filter = Some(Box::new(match filter.take() {
+                Some(existing) => existing.and(condition),
+                None => condition,
+            }));
+        }
+        self
+    }
+
+    /// Call `f` on this expression and every expression nested in it,
+    /// including those in subqueries
+    pub fn walk(&self, f: &mut dyn FnMut(&Expr)) {
+        f(self);
+        match self {
+            Expr::And(exprs) | Expr::Or(exprs) | Expr::Function { args: exprs, .. } => {
+                exprs.iter().for_each(|e| e.walk(f))
+            }
+            Expr::Not(e) | Expr::Cast { expr: e, .. } => e.walk(f),
+            Expr::Aggregate {
+                filter, order_by, ..
+            } => {
+                filter.iter().for_each(|e| e.walk(f));
+                order_by
+                    .iter()
+                    .filter_map(|o| o.expr.as_ref())
+                    .for_each(|e| e.walk(f));
+            }
+            Expr::Arithmetic { left, right, .. } => {
+                left.walk(f);
+                right.walk(f);
+            }
+            Expr::Case {
+                conditions,
+                else_result,
+            } => {
+                for (when, then) in conditions {
+                    when.walk(f);
+                    then.walk(f);
+                }
+                if let Some(e) = else_result {
+                    e.walk(f);
+                }
+            }
+            Expr::Subquery(query)
+            | Expr::Exists { query, .. }
+            | Expr::InSubquery { query, .. }
+            | Expr::SubqueryCompare { query, .. } => query.walk_exprs(f),
+            _ => {}
+        }
+    }
+
+    /// Like `walk`, but mutable; children are visited before their parent
+    pub fn walk_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        match self {
+            Expr::And(exprs) | Expr::Or(exprs) | Expr::Function { args: exprs, .. } => {
+                exprs.iter_mut().for_each(|e| e.walk_mut(f))
+            }
+            Expr::Not(e) | Expr::Cast { expr: e, .. } => e.walk_mut(f),
+            Expr::Aggregate {
+                filter, order_by, ..
+            } => {
+                filter.iter_mut().for_each(|e| e.walk_mut(f));
+                order_by
+                    .iter_mut()
+                    .filter_map(|o| o.expr.as_mut())
+                    .for_each(|e| e.walk_mut(f));
+            }
+            Expr::Arithmetic { left, right, .. } => {
+                left.walk_mut(f);
+                right.walk_mut(f);
+            }
+            Expr::Case {
+                conditions,
+                else_result,
+            } => {
+                for (when, then) in conditions {
+                    when.walk_mut(f);
+                    then.walk_mut(f);
+                }
+                if let Some(e) = else_result {
+                    e.walk_mut(f);
+                }
+            }
+            Expr::Subquery(query)
+            | Expr::Exists { query, .. }
+            | Expr::InSubquery { query, .. }
+            | Expr::SubqueryCompare { query, .. } => query.walk_exprs_mut(f),
+            _ => {}
+        }
+        f(self);
+    }
 }
 
 /// Field reference (F object) for column references in expressions
@@ -405,6 +956,36 @@ impl F {
         Expr::between(&self.column, low, high)
     }
 
+    pub fn not_between(&self, low: impl Into<Value>, high: impl Into<Value>) -> Expr {
+        Expr::not_between(&self.column, low, high)
+    }
+
+    /// Timestamps within the last `duration`, e.g. `within_last(Duration::from_secs(3600))`
+    pub fn within_last(&self, duration: Duration) -> Expr {
+        Expr::within_last(&self.column, duration)
+    }
+
+    /// Timestamps more than `duration` ago
+    pub fn older_than(&self, duration: Duration) -> Expr {
+        Expr::older_than(&self.column, duration)
+    }
+
+    pub fn array_contains<V: Into<Value>>(&self, values: Vec<V>) -> Expr {
+        Expr::array_contains(&self.column, values)
+    }
+
+    pub fn array_overlaps<V: Into<Value>>(&self, values: Vec<V>) -> Expr {
+        Expr::array_overlaps(&self.column, values)
+    }
+
+    pub fn range_overlaps<T: Into<Value>>(&self, range: Range<T>) -> Expr {
+        Expr::range_overlaps(&self.column, range)
+    }
+
+    pub fn range_contains(&self, value: impl Into<Value>) -> Expr {
+        Expr::range_contains(&self.column, value)
+    }
+
     pub fn is_null(&self) -> Expr {
         Expr::is_null(&self.column)
     }
@@ -417,6 +998,16 @@ impl F {
         Expr::like(&self.column, pattern)
     }
 
+    /// This column, or `fallback` where it is NULL
+    pub fn coalesce(&self, fallback: impl Into<Value>) -> Expr {
+        Expr::coalesce(vec![self.to_expr(), Expr::value(fallback)])
+    }
+
+    /// This column, or NULL where it equals `value`
+    pub fn nullif(&self, value: impl Into<Value>) -> Expr {
+        Expr::nullif(self.to_expr(), Expr::value(value))
+    }
+
     pub fn starts_with(&self, prefix: impl AsRef<str>) -> Expr {
         Expr::like(&self.column, format!("{}%", prefix.as_ref()))
     }
@@ -429,6 +1020,33 @@ impl F {
         Expr::like(&self.column, format!("%{}%", substring.as_ref()))
     }
 
+    /// This timestamp truncated to the start of its `unit` in time zone
+    /// `tz`, e.g. `F::col("ts").trunc(DatePart::Day, "Europe/Berlin")`
+    pub fn trunc(&self, unit: DatePart, tz: &str) -> Expr {
+        Expr::date_trunc_in(unit, self.to_expr(), tz)
+    }
+
+    /// The `unit` of this timestamp as an integer, e.g. the month
+    pub fn extract(&self, unit: DatePart) -> Expr {
+        Expr::extract(unit, self.to_expr())
+    }
+
+    /// Path into this JSON column, e.g. `F::col("data").json_get("key").eq(1)`
+    pub fn json_get(&self, key: impl Into<String>) -> JsonPath {
+        JsonPath {
+            column: self.column.clone(),
+            path: vec![key.into()],
+        }
+    }
+
+    /// Check that this JSON column contains `value` (`@>`)
+    pub fn json_contains(&self, value: impl Into<Value>) -> Expr {
+        Expr::JsonContains {
+            column: self.column.clone(),
+            value: value.into(),
+        }
+    }
+
     // Arithmetic
 
     pub fn add(&self, value: impl Into<Value>) -> Expr {
@@ -448,6 +1066,260 @@ impl F {
     }
 }
 
+impl From<F> for Expr {
+    fn from(f: F) -> Self {
+        f.to_expr()
+    }
+}
+
+/// A column of model `M` holding values of type `T`
+///
+/// `#[derive(Model)]` generates one per field, reached through `User::col()`,
+/// so a misspelled column or a value of the wrong type fails to compile.
+/// Nullable fields use their inner type; compare them with `is_null`.
+pub struct Column<M, T> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> (M, T)>,
+}
+
+impl<M, T> Column<M, T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the column name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Convert to an expression
+    pub fn to_expr(&self) -> Expr {
+        Expr::Column(self.name.to_string())
+    }
+
+    /// Convert to an untyped field reference
+    pub fn to_f(&self) -> F {
+        F::new(self.name)
+    }
+
+    pub fn is_null(&self) -> Expr {
+        Expr::is_null(self.name)
+    }
+
+    pub fn is_not_null(&self) -> Expr {
+        Expr::is_not_null(self.name)
+    }
+}
+
+impl<M, T: Into<Value>> Column<M, T> {
+    // Comparison methods
+
+    pub fn eq(&self, value: impl Into<T>) -> Expr {
+        Expr::eq(self.name, value.into())
+    }
+
+    pub fn ne(&self, value: impl Into<T>) -> Expr {
+        Expr::ne(self.name, value.into())
+    }
+
+    pub fn lt(&self, value: impl Into<T>) -> Expr {
+        Expr::lt(self.name, value.into())
+    }
+
+    pub fn lte(&self, value: impl Into<T>) -> Expr {
+        Expr::lte(self.name, value.into())
+    }
+
+    pub fn gt(&self, value: impl Into<T>) -> Expr {
+        Expr::gt(self.name, value.into())
+    }
+
+    pub fn gte(&self, value: impl Into<T>) -> Expr {
+        Expr::gte(self.name, value.into())
+    }
+
+    pub fn is_in<V: Into<T>>(&self, values: Vec<V>) -> Expr {
+        Expr::is_in(self.name, values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn not_in<V: Into<T>>(&self, values: Vec<V>) -> Expr {
+        Expr::not_in(self.name, values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn between(&self, low: impl Into<T>, high: impl Into<T>) -> Expr {
+        Expr::between(self.name, low.into(), high.into())
+    }
+
+    pub fn not_between(&self, low: impl Into<T>, high: impl Into<T>) -> Expr {
+        Expr::not_between(self.name, low.into(), high.into())
+    }
+
+    // Scalar functions
+
+    /// This column, or `fallback` where it is NULL
+    pub fn coalesce(&self, fallback: impl Into<T>) -> Expr {
+        Expr::coalesce(vec![self.to_expr(), Expr::value(fallback.into())])
+    }
+
+    /// This column, or NULL where it equals `value`
+    pub fn nullif(&self, value: impl Into<T>) -> Expr {
+        Expr::nullif(self.to_expr(), Expr::value(value.into()))
+    }
+
+    /// The larger of this column and `other`
+    pub fn greatest(&self, other: Column<M, T>) -> Expr {
+        Expr::greatest(vec![self.to_expr(), other.to_expr()])
+    }
+
+    /// The smaller of this column and `other`
+    pub fn least(&self, other: Column<M, T>) -> Expr {
+        Expr::least(vec![self.to_expr(), other.to_expr()])
+    }
+}
+
+impl<M> Column<M, String> {
+    pub fn like(&self, pattern: impl Into<String>) -> Expr {
+        Expr::like(self.name, pattern)
+    }
+
+    pub fn starts_with(&self, prefix: impl AsRef<str>) -> Expr {
+        self.to_f().starts_with(prefix)
+    }
+
+    pub fn ends_with(&self, suffix: impl AsRef<str>) -> Expr {
+        self.to_f().ends_with(suffix)
+    }
+
+    pub fn contains(&self, substring: impl AsRef<str>) -> Expr {
+        self.to_f().contains(substring)
+    }
+}
+
+impl<M> Column<M, DateTime<Utc>> {
+    /// Timestamps within the last `duration`
+    pub fn within_last(&self, duration: Duration) -> Expr {
+        Expr::within_last(self.name, duration)
+    }
+
+    /// Timestamps more than `duration` ago
+    pub fn older_than(&self, duration: Duration) -> Expr {
+        Expr::older_than(self.name, duration)
+    }
+}
+
+// Manual impls, so the model type needn't be Clone or Debug
+impl<M, T> Clone for Column<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Column<M, T> {}
+
+impl<M, T> fmt::Debug for Column<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Column").field(&self.name).finish()
+    }
+}
+
+impl<M, T> From<Column<M, T>> for Expr {
+    fn from(column: Column<M, T>) -> Self {
+        column.to_expr()
+    }
+}
+
+/// A path into a JSON column
+///
+/// Keys that parse as integers index into arrays.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    column: String,
+    path: Vec<String>,
+}
+
+impl JsonPath {
+    /// Descend into a nested key
+    pub fn json_get(mut self, key: impl Into<String>) -> Self {
+        self.path.push(key.into());
+        self
+    }
+
+    /// The JSON value at this path
+    pub fn to_expr(&self) -> Expr {
+        Expr::JsonGet {
+            column: self.column.clone(),
+            path: self.path.clone(),
+            as_text: false,
+        }
+    }
+
+    /// The value at this path as text
+    pub fn as_text(&self) -> Expr {
+        Expr::JsonGet {
+            column: self.column.clone(),
+            path: self.path.clone(),
+            as_text: true,
+        }
+    }
+
+    fn compare(&self, op: CompareOp, value: Value) -> Expr {
+        Expr::JsonCompare {
+            column: self.column.clone(),
+            path: self.path.clone(),
+            op,
+            value,
+        }
+    }
+
+    // Comparison methods
+
+    pub fn eq(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Eq, value.into())
+    }
+
+    pub fn ne(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Ne, value.into())
+    }
+
+    pub fn lt(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Lt, value.into())
+    }
+
+    pub fn lte(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Lte, value.into())
+    }
+
+    pub fn gt(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Gt, value.into())
+    }
+
+    pub fn gte(&self, value: impl Into<Value>) -> Expr {
+        self.compare(CompareOp::Gte, value.into())
+    }
+
+    pub fn like(&self, pattern: impl Into<String>) -> Expr {
+        self.compare(CompareOp::Like, Value::String(pattern.into()))
+    }
+
+    /// Missing keys and JSON nulls both count as NULL
+    pub fn is_null(&self) -> Expr {
+        self.compare(CompareOp::IsNull, Value::Null)
+    }
+
+    pub fn is_not_null(&self) -> Expr {
+        self.compare(CompareOp::IsNotNull, Value::Null)
+    }
+}
+
+impl From<JsonPath> for Expr {
+    fn from(path: JsonPath) -> Self {
+        path.to_expr()
+    }
+}
+
 /// Query object (Q) for complex boolean expressions
 #[derive(Debug, Clone)]
 pub struct Q {
@@ -487,6 +1359,7 @@ impl Q {
     }
 
     /// Negate
+    #[allow(clippy::should_implement_trait)]
     pub fn not(self) -> Self {
         Self {
             expr: self.expr.not(),
@@ -494,6 +1367,12 @@ impl Q {
     }
 }
 
+impl From<Q> for Expr {
+    fn from(q: Q) -> Self {
+        q.expr
+    }
+}
+
 // Implement bitwise operators for Q
 impl std::ops::BitAnd for Q {
     type Output = Q;
@@ -0,0 +1,1018 @@
+//! SQL pretty-printing
+//!
+//! This module formats generated SQL for humans: migration dry runs, DDL
+//! output and debug logging. Clauses start on their own line, select lists
+//! and boolean conditions are broken up, and subqueries are indented.
+//!
+//! `fingerprint` reduces a statement to its shape, so statements that differ
+//! only in their values can be counted together. `insert_values_row` finds
+//! the row of a single-row INSERT so it can be repeated for a batch.
+
+use crate::expr::Expr;
+use crate::model::REDACTED;
+use crate::query::Query;
+use crate::sql::{Dialect, SqlFragment};
+use crate::types::Value;
+use std::ops::Range;
+
+/// Keyword casing applied by the formatter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeywordCase {
+    /// `SELECT`
+    #[default]
+    Upper,
+    /// `select`
+    Lower,
+    /// Keep keywords as written
+    Preserve,
+}
+
+/// Pretty-prints SQL statements
+#[derive(Debug, Clone)]
+pub struct SqlFormatter {
+    indent: String,
+    keyword_case: KeywordCase,
+}
+
+impl Default for SqlFormatter {
+    fn default() -> Self {
+        Self {
+            indent: "  ".to_string(),
+            keyword_case: KeywordCase::Upper,
+        }
+    }
+}
+
+impl SqlFormatter {
+    /// Create a formatter with two-space indentation and uppercase keywords
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of spaces per indentation level
+    pub fn indent(mut self, spaces: usize) -> Self {
+        self.indent = " ".repeat(spaces);
+        self
+    }
+
+    /// Set keyword casing
+    pub fn keyword_case(mut self, case: KeywordCase) -> Self {
+        self.keyword_case = case;
+        self
+    }
+
+    /// Format SQL text
+    pub fn format(&self, sql: &str) -> String {
+        self.render(sql, None)
+    }
+
+    /// Format a fragment with its parameters inlined as literals
+    ///
+    /// The result is meant for reading, not for execution.
+    pub fn format_fragment(&self, fragment: &SqlFragment) -> String {
+        self.render(&fragment.sql, Some(fragment))
+    }
+
+    fn render(&self, sql: &str, fragment: Option<&SqlFragment>) -> String {
+        let tokens = tokenize(sql);
+        let mut out = Writer::new(&self.indent);
+        let mut frames = vec![Frame::root()];
+        let mut prev: Option<Token> = None;
+        let mut next_param = 0;
+        let mut in_between = false;
+        let mut glue_next = false;
+
+        for (i, &token) in tokens.iter().enumerate() {
+            let next_word = tokens.get(i + 1).map(|t| t.upper());
+            let prev_word = prev.map(|t| t.upper());
+            let frame = frames.last_mut().expect("root frame is never popped");
+            let mut space = !glue_next && needs_space(prev, token);
+            glue_next = false;
+
+            match token.kind {
+                Kind::LineComment => {
+                    out.push(token.text, true);
+                    out.newline(frame.level);
+                    continue;
+                }
+                Kind::Word => {
+                    let upper = token.upper();
+
+                    if frame.block {
+                        let clause =
+                            clause_start(&upper, prev_word.as_deref(), next_word.as_deref(), frame);
+                        if let Some(clause) = clause {
+                            out.newline(frame.level);
+                            frame.clause = clause;
+                        } else if (upper == "AND" || upper == "OR")
+                            && frame.clause == Clause::Condition
+                            && !in_between
+                        {
+                            out.newline(frame.level + 1);
+                        }
+                    }
+
+                    match upper.as_str() {
+                        "BETWEEN" => in_between = true,
+                        "AND" => in_between = false,
+                        "TABLE" if frame.head.as_deref() == Some("CREATE") => {
+                            frame.create_table = true
+                        }
+                        _ => {}
+                    }
+                    if frame.head.is_none() {
+                        frame.head = Some(upper.clone());
+                    }
+
+                    if is_keyword(&upper) {
+                        out.push(&self.case(token.text), space);
+                    } else {
+                        out.push(token.text, space);
+                    }
+                }
+                Kind::Placeholder => {
+                    let param = placeholder_param(token.text, &mut next_param);
+                    match fragment.zip(param).and_then(|(f, param)| match param {
+                        Param::Index(n) => f.params.get(n.wrapping_sub(1)),
+                        Param::Name(name) => f.named.get(name),
+                    }) {
+                        Some(value) => out.push(&value.to_sql_literal(), space),
+                        None => out.push(token.text, space),
+                    }
+                }
+                Kind::Punct => match token.text {
+                    "(" => {
+                        let subquery =
+                            matches!(next_word.as_deref(), Some("SELECT") | Some("WITH"));
+                        let table_block = frame.block && frame.create_table && !frame.paren_seen;
+                        if frame.block
+                            && !frame.paren_seen
+                            && matches!(frame.head.as_deref(), Some("INSERT") | Some("CREATE"))
+                        {
+                            space = true;
+                        }
+                        frame.paren_seen = true;
+                        out.push("(", space);
+
+                        if subquery || table_block {
+                            let level = frame.level + 1;
+                            frames.push(Frame {
+                                level,
+                                table_block,
+                                ..Frame::root()
+                            });
+                            out.newline(level);
+                        } else {
+                            let level = frame.level;
+                            frames.push(Frame {
+                                block: false,
+                                level,
+                                ..Frame::root()
+                            });
+                        }
+                    }
+                    ")" => {
+                        if frames.len() > 1 {
+                            let closed = frames.pop().expect("checked length");
+                            if closed.block {
+                                let level = frames.last().map(|f| f.level).unwrap_or(0);
+                                out.newline(level);
+                            }
+                        }
+                        out.push(")", false);
+                    }
+                    "," => {
+                        out.push(",", false);
+                        if frame.table_block {
+                            out.newline(frame.level);
+                        } else if frame.block
+                            && matches!(frame.clause, Clause::List | Clause::Values)
+                        {
+                            out.newline(frame.level + 1);
+                        }
+                    }
+                    ";" => {
+                        out.push(";", false);
+                        frames.truncate(1);
+                        frames[0] = Frame::root();
+                        in_between = false;
+                        if i + 1 < tokens.len() {
+                            out.blank_line();
+                        }
+                    }
+                    _ => out.push(token.text, space),
+                },
+                Kind::Operator => {
+                    out.push(token.text, space);
+                    let unary = token.text == "-"
+                        && prev.is_none_or(|p| {
+                            matches!(p.kind, Kind::Operator)
+                                || matches!(p.text, "(" | "," | "[")
+                                || (p.kind == Kind::Word && is_keyword(&p.upper()))
+                        });
+                    glue_next = unary;
+                }
+                Kind::Literal | Kind::Quoted | Kind::Comment => out.push(token.text, space),
+            }
+
+            prev = Some(token);
+        }
+
+        out.finish()
+    }
+
+    fn case(&self, word: &str) -> String {
+        match self.keyword_case {
+            KeywordCase::Upper => word.to_ascii_uppercase(),
+            KeywordCase::Lower => word.to_ascii_lowercase(),
+            KeywordCase::Preserve => word.to_string(),
+        }
+    }
+}
+
+impl SqlFragment {
+    /// Pretty-print this fragment with parameters inlined, for debugging
+    pub fn debug_sql(&self) -> String {
+        SqlFormatter::default().format_fragment(self)
+    }
+}
+
+impl Query {
+    /// Generate and pretty-print this query with parameters inlined
+    ///
+    /// Values bound to sensitive columns are shown as `REDACTED`.
+    pub fn debug_sql(&self, dialect: &dyn Dialect) -> String {
+        let mut fragment = dialect.generate(self);
+        let hidden = self.sensitive_values();
+        for param in &mut fragment.params {
+            if hidden.contains(param) {
+                *param = Value::String(REDACTED.to_string());
+            }
+        }
+        fragment.debug_sql()
+    }
+
+    /// Non-null values bound to sensitive columns anywhere in the query
+    ///
+    /// Parameters are matched by value, so an equal value bound elsewhere is
+    /// redacted too.
+    fn sensitive_values(&self) -> Vec<Value> {
+        let mut values = Vec::new();
+        if self.sensitive.is_empty() {
+            return values;
+        }
+        let is_sensitive = |column: &str| {
+            let column = column.rsplit('.').next().unwrap_or(column);
+            self.sensitive.iter().any(|c| c == column)
+        };
+
+        for row in &self.values {
+            values.extend(
+                row.iter()
+                    .filter(|(column, _)| is_sensitive(column))
+                    .map(|(_, v)| v.clone()),
+            );
+        }
+        for (column, expr) in &self.set_exprs {
+            if is_sensitive(column) {
+                expr.walk(&mut |e| {
+                    if let Expr::Value(v) = e {
+                        values.push(v.clone());
+                    }
+                });
+            }
+        }
+        self.walk_exprs(&mut |e| match e {
+            Expr::Compare { column, value, .. }
+            | Expr::JsonCompare { column, value, .. }
+            | Expr::JsonContains { column, value }
+            | Expr::RangeOverlaps { column, value }
+            | Expr::RangeContains { column, value }
+                if is_sensitive(column) =>
+            {
+                values.push(value.clone())
+            }
+            Expr::Between {
+                column, low, high, ..
+            } if is_sensitive(column) => values.extend([low.clone(), high.clone()]),
+            Expr::In {
+                column, values: v, ..
+            }
+            | Expr::InArray {
+                column, values: v, ..
+            }
+            | Expr::ArrayContains { column, values: v }
+            | Expr::ArrayOverlaps { column, values: v }
+                if is_sensitive(column) =>
+            {
+                values.extend(v.iter().cloned())
+            }
+            _ => {}
+        });
+
+        values.retain(|v| !v.is_null());
+        values
+    }
+}
+
+/// The clause a block is currently in, which decides line breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Clause {
+    None,
+    /// Comma-separated items, one per line
+    List,
+    /// `AND`/`OR` conditions, one per line
+    Condition,
+    /// `VALUES` rows, one per line
+    Values,
+    Other,
+}
+
+/// A statement level: the top level, a subquery or a parenthesised group
+#[derive(Debug, Clone)]
+struct Frame {
+    /// Whether clauses inside start on new lines
+    block: bool,
+    level: usize,
+    clause: Clause,
+    /// First keyword of the statement
+    head: Option<String>,
+    create_table: bool,
+    /// Column list of a `CREATE TABLE`
+    table_block: bool,
+    paren_seen: bool,
+}
+
+impl Frame {
+    fn root() -> Self {
+        Self {
+            block: true,
+            level: 0,
+            clause: Clause::None,
+            head: None,
+            create_table: false,
+            table_block: false,
+            paren_seen: false,
+        }
+    }
+}
+
+fn clause_start(
+    upper: &str,
+    prev: Option<&str>,
+    next: Option<&str>,
+    frame: &Frame,
+) -> Option<Clause> {
+    let statement_start = frame.head.is_none() || prev == Some(")");
+
+    match upper {
+        "SELECT" | "RETURNING" => Some(Clause::List),
+        "FROM" if !matches!(prev, Some("DELETE") | Some("DISTINCT")) => Some(Clause::Other),
+        "WHERE" | "HAVING" => Some(Clause::Condition),
+        "GROUP" | "ORDER" if next == Some("BY") => Some(Clause::List),
+        "LIMIT" | "OFFSET" | "UNION" | "INTERSECT" | "EXCEPT" => Some(Clause::Other),
+        "VALUES" if frame.head.as_deref() == Some("INSERT") => Some(Clause::Values),
+        "SET" if matches!(frame.head.as_deref(), Some("UPDATE") | Some("INSERT")) => {
+            Some(Clause::List)
+        }
+        "INSERT" | "UPDATE" | "DELETE" | "WITH" if statement_start => Some(Clause::Other),
+        "ON" if matches!(next, Some("CONFLICT") | Some("DUPLICATE")) => Some(Clause::Other),
+        "JOIN" if !matches!(prev, Some(p) if JOIN_MODIFIERS.contains(&p)) => {
+            Some(Clause::Condition)
+        }
+        "LEFT" | "RIGHT" | "INNER" | "FULL" | "CROSS" | "NATURAL"
+            if matches!(next, Some("JOIN") | Some("OUTER")) =>
+        {
+            Some(Clause::Condition)
+        }
+        _ => None,
+    }
+}
+
+const JOIN_MODIFIERS: &[&str] = &[
+    "LEFT", "RIGHT", "INNER", "OUTER", "FULL", "CROSS", "NATURAL",
+];
+
+const KEYWORDS: &[&str] = &[
+    "ADD",
+    "ALL",
+    "ALTER",
+    "AND",
+    "ANY",
+    "AS",
+    "ASC",
+    "BETWEEN",
+    "BY",
+    "CASCADE",
+    "CASE",
+    "CHECK",
+    "COLUMN",
+    "CONFLICT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "DEFAULT",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DO",
+    "DROP",
+    "DUPLICATE",
+    "ELSE",
+    "END",
+    "EXCEPT",
+    "EXISTS",
+    "FALSE",
+    "FILTER",
+    "FIRST",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GROUP",
+    "HAVING",
+    "IF",
+    "ILIKE",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSERT",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "KEY",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "NATURAL",
+    "NOT",
+    "NOTHING",
+    "NULL",
+    "NULLS",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PRIMARY",
+    "REFERENCES",
+    "RENAME",
+    "RETURNING",
+    "RIGHT",
+    "SELECT",
+    "SET",
+    "SOME",
+    "TABLE",
+    "THEN",
+    "TO",
+    "TRUE",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VALUES",
+    "VIEW",
+    "WHEN",
+    "WHERE",
+    "WITH",
+];
+
+fn is_keyword(upper: &str) -> bool {
+    KEYWORDS.contains(&upper)
+}
+
+fn needs_space(prev: Option<Token>, token: Token) -> bool {
+    let Some(prev) = prev else {
+        return false;
+    };
+    if matches!(token.text, ")" | "," | ";" | "." | "]" | "[" | "::") {
+        return false;
+    }
+    if prev.kind == Kind::Punct && matches!(prev.text, "(" | "." | "[" | "::") {
+        return false;
+    }
+    if token.text == "(" {
+        // Function calls hug their parentheses; keywords and quoted names do not
+        return match prev.kind {
+            Kind::Word => is_keyword(&prev.upper()),
+            Kind::Punct => prev.text != ")",
+            _ => true,
+        };
+    }
+    true
+}
+
+/// Accumulates formatted output
+struct Writer<'a> {
+    out: String,
+    indent: &'a str,
+    line_start: bool,
+}
+
+impl<'a> Writer<'a> {
+    fn new(indent: &'a str) -> Self {
+        Self {
+            out: String::new(),
+            indent,
+            line_start: true,
+        }
+    }
+
+    fn push(&mut self, text: &str, space: bool) {
+        if space && !self.line_start {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.line_start = false;
+    }
+
+    /// Start a new line at `level`, replacing the indentation of an empty one
+    fn newline(&mut self, level: usize) {
+        if self.line_start {
+            let cut = self.out.rfind('\n').map(|p| p + 1).unwrap_or(0);
+            self.out.truncate(cut);
+        } else {
+            self.out.push('\n');
+        }
+        if !self.out.is_empty() {
+            self.out.push_str(&self.indent.repeat(level));
+        }
+        self.line_start = true;
+    }
+
+    fn blank_line(&mut self) {
+        self.out.push('\n');
+        self.line_start = false;
+        self.newline(0);
+    }
+
+    fn finish(self) -> String {
+        self.out.trim_end().to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Word,
+    Quoted,
+    Literal,
+    Placeholder,
+    Punct,
+    Operator,
+    Comment,
+    LineComment,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    start: usize,
+}
+
+impl Token<'_> {
+    fn upper(&self) -> String {
+        match self.kind {
+            Kind::Word => self.text.to_ascii_uppercase(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn tokenize(sql: &str) -> Vec<Token<'_>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        let start = i;
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let kind = match c {
+            b'\'' => {
+                i = skip_quoted(bytes, i, c);
+                Kind::Literal
+            }
+            b'"' | b'`' => {
+                i = skip_quoted(bytes, i, c);
+                Kind::Quoted
+            }
+            b'-' if next == Some(b'-') => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(bytes.len(), |p| i + p);
+                Kind::LineComment
+            }
+            b'/' if next == Some(b'*') => {
+                i = sql[i + 2..].find("*/").map_or(bytes.len(), |p| i + p + 4);
+                Kind::Comment
+            }
+            b'$' | b'?' if next.is_some_and(|b| b.is_ascii_digit()) => {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                Kind::Placeholder
+            }
+            b'?' => {
+                i += 1;
+                Kind::Placeholder
+            }
+            b'$' => match dollar_quote_end(sql, i) {
+                Some(end) => {
+                    i = end;
+                    Kind::Literal
+                }
+                None => {
+                    i += 1;
+                    Kind::Operator
+                }
+            },
+            b':' if next == Some(b':') => {
+                i += 2;
+                Kind::Punct
+            }
+            // MySQL system variables, never placeholders
+            b'@' if next == Some(b'@') => {
+                i += 2;
+                while i < bytes.len() && (is_word_byte(bytes[i]) || bytes[i] == b'.') {
+                    i += 1;
+                }
+                Kind::Word
+            }
+            b':' | b'@' if next.is_some_and(is_word_byte) => {
+                i += 1;
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                Kind::Placeholder
+            }
+            b'(' | b')' | b',' | b';' | b'.' | b'[' | b']' => {
+                i += 1;
+                Kind::Punct
+            }
+            c if is_word_byte(c) => {
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                Kind::Word
+            }
+            _ => {
+                i += 1;
+                while i < bytes.len()
+                    && b"<>=!|&+-*/%^~@#".contains(&bytes[i])
+                    && !bytes[i..].starts_with(b"--")
+                    && !bytes[i..].starts_with(b"/*")
+                {
+                    i += 1;
+                }
+                Kind::Operator
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &sql[start..i],
+            start,
+        });
+    }
+
+    tokens
+}
+
+/// A keyword or name in a SQL script, skipping literals and comments
+pub(crate) struct Word {
+    /// Uppercased text
+    pub upper: String,
+    /// Parenthesis nesting depth
+    pub depth: usize,
+    /// Index of the `;`-separated statement it belongs to
+    pub statement: usize,
+}
+
+/// Unquoted words of `sql` with their nesting depth and statement
+pub(crate) fn words(sql: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut depth = 0usize;
+    let mut statement = 0;
+    for token in tokenize(sql) {
+        match (token.kind, token.text) {
+            (Kind::Punct, "(") => depth += 1,
+            (Kind::Punct, ")") => depth = depth.saturating_sub(1),
+            (Kind::Punct, ";") if depth == 0 => statement += 1,
+            (Kind::Word, _) => words.push(Word {
+                upper: token.upper(),
+                depth,
+                statement,
+            }),
+            _ => {}
+        }
+    }
+    words
+}
+
+/// The shape of `sql` on one line: literals, numbers and placeholders become
+/// `?`, lists of them `...`, comments are dropped and keywords uppercased
+///
+/// `SELECT * FROM posts WHERE author_id = $1` and
+/// `select * from posts where author_id = 42` share a fingerprint.
+pub fn fingerprint(sql: &str) -> String {
+    // Each part, and whether it is a name, which a call's `(` follows directly
+    let mut parts: Vec<(String, bool)> = Vec::new();
+    for token in tokenize(sql) {
+        let part = match token.kind {
+            Kind::Comment | Kind::LineComment => continue,
+            Kind::Literal | Kind::Placeholder => ("?".to_string(), false),
+            Kind::Word if token.text.as_bytes()[0].is_ascii_digit() => ("?".to_string(), false),
+            Kind::Word if is_keyword(&token.upper()) => (token.upper(), false),
+            Kind::Word | Kind::Quoted => (token.text.to_string(), true),
+            _ => (token.text.to_string(), false),
+        };
+        // `?, ?, ?` becomes `...`, whatever the list's length
+        if part.0 == "?" {
+            if let [.., (value, _), (comma, _)] = parts.as_slice() {
+                if comma == "," && (value == "?" || value == "...") {
+                    parts.truncate(parts.len() - 2);
+                    parts.push(("...".to_string(), false));
+                    continue;
+                }
+            }
+        }
+        parts.push(part);
+    }
+
+    let mut out = String::with_capacity(sql.len());
+    let mut prev: Option<&(String, bool)> = None;
+    for part in &parts {
+        let tight = match (prev, part.0.as_str()) {
+            (None, _) => true,
+            (_, "," | ")" | "." | ";") => true,
+            (Some((_, name)), "(") => *name,
+            (Some((p, _)), _) => p == "(" || p == ".",
+        };
+        if !tight {
+            out.push(' ');
+        }
+        out.push_str(&part.0);
+        prev = Some(part);
+    }
+    out
+}
+
+/// Byte span of the `(...)` row after the `VALUES` of a single-row INSERT
+/// or REPLACE, if every placeholder of `sql` is in it
+///
+/// Repeating the row, comma separated, makes a multi-row insert binding the
+/// parameter sets one after another. Statements whose later clauses take
+/// parameters, like `ON DUPLICATE KEY UPDATE n = ?`, are not matched.
+pub fn insert_values_row(sql: &str) -> Option<Range<usize>> {
+    let tokens: Vec<Token> = tokenize(sql)
+        .into_iter()
+        .filter(|t| !matches!(t.kind, Kind::Comment | Kind::LineComment))
+        .collect();
+    if !matches!(tokens.first()?.upper().as_str(), "INSERT" | "REPLACE") {
+        return None;
+    }
+    let values = tokens
+        .iter()
+        .position(|t| matches!(t.upper().as_str(), "VALUES" | "VALUE"))?;
+    let open = tokens.get(values + 1)?;
+    if (open.kind, open.text) != (Kind::Punct, "(") {
+        return None;
+    }
+    let mut depth = 0;
+    let close = values
+        + 1
+        + tokens[values + 1..].iter().position(|t| {
+            match (t.kind, t.text) {
+                (Kind::Punct, "(") => depth += 1,
+                (Kind::Punct, ")") => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })?;
+
+    // Numbered and named placeholders would bind the same value in every row
+    let in_row = |i: usize| (values + 1..=close).contains(&i);
+    let rest = &tokens[close + 1..];
+    let stray = tokens.iter().enumerate().any(|(i, t)| {
+        t.kind == Kind::Placeholder && (t.text != "?" || !in_row(i))
+            || (t.kind, t.text) == (Kind::Punct, ";") && i + 1 != tokens.len()
+    });
+    if stray || rest.first().is_some_and(|t| t.text == ",") {
+        return None;
+    }
+    Some(open.start..tokens[close].start + 1)
+}
+
+/// A parameter referenced by a placeholder
+pub(crate) enum Param<'a> {
+    /// 1-based position in the positional parameters
+    Index(usize),
+    /// Key in the named parameters
+    Name(&'a str),
+}
+
+/// Placeholders in `sql` with their byte spans, skipping literals and comments
+///
+/// `@name` is only a placeholder with `at_names`; otherwise it is a user
+/// variable, as on MySQL.
+pub(crate) fn placeholders(sql: &str, at_names: bool) -> Vec<(Range<usize>, Param<'_>)> {
+    let mut next_param = 0;
+    tokenize(sql)
+        .into_iter()
+        .filter(|t| t.kind == Kind::Placeholder && (at_names || !t.text.starts_with('@')))
+        .filter_map(|t| {
+            let param = placeholder_param(t.text, &mut next_param)?;
+            Some((t.start..t.start + t.text.len(), param))
+        })
+        .collect()
+}
+
+/// Resolve `$N`, `?N`, `?` (numbered in order), `:name` and `@name`
+fn placeholder_param<'a>(text: &'a str, next_param: &mut usize) -> Option<Param<'a>> {
+    match text.as_bytes()[0] {
+        b'$' | b'?' if text.len() > 1 => text[1..].parse().ok().map(Param::Index),
+        b'?' => {
+            *next_param += 1;
+            Some(Param::Index(*next_param))
+        }
+        b':' | b'@' => Some(Param::Name(&text[1..])),
+        _ => None,
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn dollar_quote_end(sql: &str, start: usize) -> Option<usize> {
+    let tag_len = sql[start + 1..].find('$')? + 2;
+    let tag = &sql[start..start + tag_len];
+    if !tag[1..tag_len - 1].bytes().all(is_word_byte) {
+        return None;
+    }
+    let body = start + tag_len;
+    Some(
+        sql[body..]
+            .find(tag)
+            .map_or(sql.len(), |p| body + p + tag_len),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Value;
+
+    #[test]
+    fn test_insert_values_row() {
+        let row = |sql: &'static str| insert_values_row(sql).map(|r| &sql[r]);
+        assert_eq!(
+            row("INSERT INTO t (a, b) VALUES (?, NOW()) -- one row"),
+            Some("(?, NOW())")
+        );
+        assert_eq!(row("replace into t values (?, '(?)');"), Some("(?, '(?)')"));
+        assert_eq!(
+            row("INSERT INTO t (a) VALUES (?) ON DUPLICATE KEY UPDATE a = VALUES(a)"),
+            Some("(?)")
+        );
+        assert_eq!(
+            row("INSERT INTO t (a) VALUES (?) ON DUPLICATE KEY UPDATE a = ?"),
+            None
+        );
+        assert_eq!(row("INSERT INTO t (a) VALUES (?), (?)"), None);
+        assert_eq!(row("INSERT INTO t (a) VALUES ($1)"), None);
+        assert_eq!(row("INSERT INTO t (a) SELECT ? FROM u"), None);
+        assert_eq!(row("UPDATE t SET a = ?"), None);
+        assert_eq!(row("INSERT INTO t VALUES (?); DELETE FROM t"), None);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint("select * from posts where author_id = $1 -- lazy load"),
+            "SELECT * FROM posts WHERE author_id = ?"
+        );
+        assert_eq!(
+            fingerprint("SELECT *  FROM posts WHERE author_id = 42"),
+            fingerprint("select * from posts where author_id = $1")
+        );
+        assert_eq!(
+            fingerprint("SELECT name FROM tags WHERE id IN (?1, ?2, ?3) AND label = 'a, b'"),
+            "SELECT name FROM tags WHERE id IN (...) AND label = ?"
+        );
+        assert_eq!(
+            fingerprint("SELECT name FROM tags WHERE id IN (1, 2)"),
+            fingerprint("SELECT name FROM tags WHERE id IN (7, 8, 9, 10)")
+        );
+        assert_eq!(
+            fingerprint("SELECT count(t.id) FROM t"),
+            "SELECT count(t.id) FROM t"
+        );
+    }
+
+    #[test]
+    fn test_format_select() {
+        let sql = "select u.id, u.name, count(o.id) as orders from users u \
+                   left join orders o on o.user_id = u.id and o.total > -5 \
+                   where u.active = $1 and u.age between 18 and 65 \
+                   group by u.id, u.name order by u.name limit 10";
+
+        assert_eq!(
+            SqlFormatter::new().format(sql),
+            "SELECT u.id,\n  u.name,\n  count(o.id) AS orders\n\
+             FROM users u\n\
+             LEFT JOIN orders o ON o.user_id = u.id\n  AND o.total > -5\n\
+             WHERE u.active = $1\n  AND u.age BETWEEN 18 AND 65\n\
+             GROUP BY u.id,\n  u.name\n\
+             ORDER BY u.name\n\
+             LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_format_subquery_and_ddl() {
+        let formatter = SqlFormatter::new()
+            .indent(4)
+            .keyword_case(KeywordCase::Lower);
+        assert_eq!(
+            formatter.format("SELECT id FROM t WHERE id IN (SELECT t_id FROM u WHERE x = 'a, b')"),
+            "select id\nfrom t\nwhere id in (\n    select t_id\n    from u\n    where x = 'a, b'\n)"
+        );
+
+        let ddl = "CREATE TABLE \"users\" (\"id\" BIGSERIAL NOT NULL, \"email\" VARCHAR(255), \
+                   \"seen_at\" TIMESTAMP WITH TIME ZONE, PRIMARY KEY (\"id\"))";
+        assert_eq!(
+            SqlFormatter::new().format(ddl),
+            "CREATE TABLE \"users\" (\n  \"id\" BIGSERIAL NOT NULL,\n  \"email\" VARCHAR(255),\n  \
+             \"seen_at\" TIMESTAMP WITH TIME ZONE,\n  PRIMARY KEY (\"id\")\n)"
+        );
+    }
+
+    #[test]
+    fn test_debug_sql_redacts_sensitive() {
+        use crate::sql::PostgresDialect;
+
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::eq("email", "ann@example.com"))
+            .filter(Expr::eq("name", "Ann"))
+            .sensitive(["email"])
+            .build();
+        let sql = query.debug_sql(&PostgresDialect);
+        assert!(sql.contains(r#""email" = '[REDACTED]'"#));
+        assert!(sql.contains(r#""name" = 'Ann'"#));
+
+        let update = Query::update()
+            .table("users")
+            .set("email", "bob@example.com")
+            .sensitive(["email"])
+            .reveal_sensitive()
+            .build();
+        assert!(update
+            .debug_sql(&PostgresDialect)
+            .contains("bob@example.com"));
+    }
+
+    #[test]
+    fn test_format_fragment_inlines_params() {
+        let fragment = SqlFragment {
+            sql: "INSERT INTO notes (body, n) VALUES (?, ?) ON CONFLICT (n) DO UPDATE SET body = ?"
+                .into(),
+            params: vec![Value::from("it's"), Value::from(3i64), Value::from("x")],
+            ..SqlFragment::new()
+        };
+        assert_eq!(
+            fragment.debug_sql(),
+            "INSERT INTO notes (body, n)\nVALUES ('it''s', 3)\n\
+             ON CONFLICT (n) DO UPDATE\nSET body = 'x'"
+        );
+    }
+}
@@ -0,0 +1,664 @@
+//! Runtime allow/deny rules for statements
+//!
+//! A `StatementGuard` inspects each statement before it reaches the database
+//! and blocks those matching a deny rule with a `ChakraError::Policy`. It is
+//! a safety net for shared databases, e.g. on staging:
+//!
+//! ```toml
+//! [guard]
+//! environments = ["staging"]
+//!
+//! [[guard.rules]]
+//! action = "deny"
+//! check = "delete_without_where"
+//!
+//! [[guard.rules]]
+//! action = "deny"
+//! check = { starts_with = "DROP TABLE" }
+//! ```
+//!
+//! Rules look at the statement's keywords, not its text, so literals and
+//! comments never match. The first matching rule decides; statements no rule
+//! matches are allowed. Migrations run through their own executor and are
+//! not guarded.
+//!
+//! A guard can also hold `PlanLimits`. A `GuardedExecutor` then runs EXPLAIN
+//! before each SELECT built from a `Query` (querysets, `fetch_all`,
+//! `fetch_stream`) and refuses, or only logs, those the planner expects to
+//! cost or return more than the limits. This keeps filters assembled at
+//! runtime, say by an admin page or a GraphQL layer, from scanning a huge
+//! table by accident:
+//!
+//! ```toml
+//! [guard.plan]
+//! max_cost = 100000.0
+//! max_rows = 50000
+//! action = "deny"   # or "warn"
+//! ```
+//!
+//! Hand-written SQL sent with `query` is not explained. SQLite's planner
+//! gives no estimates, so plan limits do nothing there.
+
+use crate::error::{ChakraError, Result};
+use crate::executor::{ExecResult, Executor};
+use crate::explain::{ExplainOptions, QueryPlan};
+use crate::format::{fingerprint, words, Word};
+use crate::query::{Query, QueryType};
+use crate::result::{Row, RowStream};
+use crate::sql::Dialect;
+use crate::sql_cache::SqlCache;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Statements that change the schema
+const DDL_VERBS: &[&str] = &["CREATE", "ALTER", "DROP", "TRUNCATE", "RENAME"];
+
+/// Statements a `WITH` clause can lead into
+const CTE_VERBS: &[&str] = &["SELECT", "INSERT", "UPDATE", "DELETE", "MERGE"];
+
+/// What a rule does with a matching statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Allow,
+    Deny,
+}
+
+/// Which statements a rule matches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementCheck {
+    /// DELETE with no top-level WHERE
+    DeleteWithoutWhere,
+    /// UPDATE with no top-level WHERE
+    UpdateWithoutWhere,
+    /// DROP TABLE
+    DropTable,
+    /// TRUNCATE
+    Truncate,
+    /// CREATE, ALTER, DROP, TRUNCATE or RENAME of anything
+    Ddl,
+    /// Statements starting with these keywords, e.g. `"DROP DATABASE"`
+    StartsWith(String),
+}
+
+impl StatementCheck {
+    /// Name used in policy errors
+    pub fn name(&self) -> String {
+        match self {
+            StatementCheck::DeleteWithoutWhere => "delete_without_where".to_string(),
+            StatementCheck::UpdateWithoutWhere => "update_without_where".to_string(),
+            StatementCheck::DropTable => "drop_table".to_string(),
+            StatementCheck::Truncate => "truncate".to_string(),
+            StatementCheck::Ddl => "ddl".to_string(),
+            StatementCheck::StartsWith(prefix) => format!("starts_with {}", prefix),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            StatementCheck::DeleteWithoutWhere => "DELETE without WHERE".to_string(),
+            StatementCheck::UpdateWithoutWhere => "UPDATE without WHERE".to_string(),
+            StatementCheck::DropTable => "DROP TABLE".to_string(),
+            StatementCheck::Truncate => "TRUNCATE".to_string(),
+            StatementCheck::Ddl => "schema changes".to_string(),
+            StatementCheck::StartsWith(prefix) => prefix.to_uppercase(),
+        }
+    }
+
+    /// Check one statement's words
+    fn matches(&self, statement: &[&Word]) -> bool {
+        let verb = statement_verb(statement);
+        let has_where = || statement.iter().any(|w| w.depth == 0 && w.upper == "WHERE");
+        match self {
+            StatementCheck::DeleteWithoutWhere => verb == Some("DELETE") && !has_where(),
+            StatementCheck::UpdateWithoutWhere => verb == Some("UPDATE") && !has_where(),
+            StatementCheck::DropTable => starts_with(statement, &["DROP", "TABLE"]),
+            StatementCheck::Truncate => verb == Some("TRUNCATE"),
+            StatementCheck::Ddl => verb.is_some_and(|v| DDL_VERBS.contains(&v)),
+            StatementCheck::StartsWith(prefix) => {
+                let prefix: Vec<String> = prefix
+                    .split_whitespace()
+                    .map(str::to_ascii_uppercase)
+                    .collect();
+                let prefix: Vec<&str> = prefix.iter().map(String::as_str).collect();
+                !prefix.is_empty() && starts_with(statement, &prefix)
+            }
+        }
+    }
+}
+
+/// An allow or deny rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementRule {
+    pub action: RuleAction,
+    pub check: StatementCheck,
+    /// Explanation for the error, instead of the check's description
+    #[serde(default)]
+    pub message: String,
+}
+
+/// What happens to a query whose plan exceeds the limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    /// Fail with `ChakraError::Policy` without running it
+    #[default]
+    Deny,
+    /// Log a warning under the `chakra::guard` target and run it
+    Warn,
+}
+
+/// Planner estimates a generated SELECT may not exceed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanLimits {
+    /// Highest total cost, in the planner's units
+    pub max_cost: Option<f64>,
+    /// Most rows any step of the plan may produce or examine
+    pub max_rows: Option<f64>,
+    pub action: PlanAction,
+}
+
+impl PlanLimits {
+    /// Limits that deny nothing until set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the estimated total cost
+    pub fn max_cost(mut self, max_cost: f64) -> Self {
+        self.max_cost = Some(max_cost);
+        self
+    }
+
+    /// Limit the estimated rows of any step
+    pub fn max_rows(mut self, max_rows: f64) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Log queries over the limits instead of refusing them
+    pub fn warn_only(mut self) -> Self {
+        self.action = PlanAction::Warn;
+        self
+    }
+
+    /// The first limit `plan` exceeds, as the rule name and a description
+    fn exceeded(&self, plan: &QueryPlan) -> Option<(&'static str, String)> {
+        let over = |estimate: Option<f64>, limit: Option<f64>| match (estimate, limit) {
+            (Some(estimate), Some(limit)) if estimate > limit => Some((estimate, limit)),
+            _ => None,
+        };
+        if let Some((cost, limit)) = over(plan.estimated_cost(), self.max_cost) {
+            return Some((
+                "max_cost",
+                format!("estimated cost {} exceeds the limit of {}", cost, limit),
+            ));
+        }
+        if let Some((rows, limit)) = over(plan.estimated_rows(), self.max_rows) {
+            return Some((
+                "max_rows",
+                format!("estimated {} rows exceeds the limit of {}", rows, limit),
+            ));
+        }
+        None
+    }
+}
+
+/// Ordered rules applied to every statement
+#[derive(Debug, Clone, Default)]
+pub struct StatementGuard {
+    rules: Vec<StatementRule>,
+    plan: Option<PlanLimits>,
+}
+
+impl StatementGuard {
+    /// A guard with no rules, allowing everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule allowing statements matching `check`
+    pub fn allow(self, check: StatementCheck) -> Self {
+        self.rule(StatementRule {
+            action: RuleAction::Allow,
+            check,
+            message: String::new(),
+        })
+    }
+
+    /// Add a rule denying statements matching `check`
+    pub fn deny(self, check: StatementCheck) -> Self {
+        self.rule(StatementRule {
+            action: RuleAction::Deny,
+            check,
+            message: String::new(),
+        })
+    }
+
+    /// Add a rule, checked after the ones already added
+    pub fn rule(mut self, rule: StatementRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Check the plan of each generated SELECT against `limits`
+    pub fn plan_limits(mut self, limits: PlanLimits) -> Self {
+        self.plan = Some(limits);
+        self
+    }
+
+    /// The rules, in the order they are checked
+    pub fn rules(&self) -> &[StatementRule] {
+        &self.rules
+    }
+
+    /// The limits generated SELECTs are planned against, if any
+    pub fn limits(&self) -> Option<&PlanLimits> {
+        self.plan.as_ref()
+    }
+
+    /// Check the plan of `sql` against the plan limits
+    ///
+    /// Fails with `ChakraError::Policy` if a limit is exceeded and the
+    /// action is `Deny`; with `Warn` it logs and succeeds.
+    pub fn check_plan(&self, sql: &str, plan: &QueryPlan) -> Result<()> {
+        let Some(limits) = &self.plan else {
+            return Ok(());
+        };
+        let Some((rule, reason)) = limits.exceeded(plan) else {
+            return Ok(());
+        };
+        match limits.action {
+            PlanAction::Deny => Err(ChakraError::Policy {
+                rule: rule.to_string(),
+                message: format!(
+                    "Query refused: {}; narrow its filters or add an index",
+                    reason
+                ),
+            }),
+            PlanAction::Warn => {
+                warn!(target: "chakra::guard", rule, query = %fingerprint(sql), "Expensive query: {}", reason);
+                Ok(())
+            }
+        }
+    }
+
+    /// Fail with `ChakraError::Policy` if any statement in `sql` is denied
+    pub fn check(&self, sql: &str) -> Result<()> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+        let words = words(sql);
+        let mut start = 0;
+        while start < words.len() {
+            let index = words[start].statement;
+            let end = words[start..]
+                .iter()
+                .position(|w| w.statement != index)
+                .map_or(words.len(), |p| start + p);
+            let statement: Vec<&Word> = words[start..end].iter().collect();
+            if let Some(rule) = self.rules.iter().find(|r| r.check.matches(&statement)) {
+                if rule.action == RuleAction::Deny {
+                    let message = if rule.message.is_empty() {
+                        format!("{} is not allowed here", rule.check.description())
+                    } else {
+                        rule.message.clone()
+                    };
+                    return Err(ChakraError::Policy {
+                        rule: rule.check.name(),
+                        message,
+                    });
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+}
+
+/// The `[guard]` table of `chakra.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuardConfig {
+    /// Environments the guard is active in; empty means all of them
+    pub environments: Vec<String>,
+    pub rules: Vec<StatementRule>,
+    /// The `[guard.plan]` table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<PlanLimits>,
+}
+
+impl GuardConfig {
+    /// The guard for `environment` (e.g. from `CHAKRA_ENV`), or `None` if
+    /// there are no rules or plan limits, or the guard is not active there
+    pub fn guard_for(&self, environment: Option<&str>) -> Option<StatementGuard> {
+        let active = self.environments.is_empty()
+            || environment.is_some_and(|env| self.environments.iter().any(|e| e == env));
+        if !active || (self.rules.is_empty() && self.plan.is_none()) {
+            return None;
+        }
+        Some(StatementGuard {
+            rules: self.rules.clone(),
+            plan: self.plan.clone(),
+        })
+    }
+}
+
+/// An executor that checks every statement against a guard first
+pub struct GuardedExecutor<E> {
+    inner: E,
+    guard: StatementGuard,
+}
+
+impl<E: Executor> GuardedExecutor<E> {
+    /// Wrap `inner`
+    pub fn new(inner: E, guard: StatementGuard) -> Self {
+        Self { inner, guard }
+    }
+
+    /// The wrapped executor, for statements that must bypass the guard
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// The guard's rules
+    pub fn guard(&self) -> &StatementGuard {
+        &self.guard
+    }
+
+    /// EXPLAIN `query` and check its plan, if it is a SELECT and the guard
+    /// has plan limits
+    async fn check_plan(&self, query: &Query) -> Result<()> {
+        let dialect = self.dialect();
+        if self.guard.plan.is_none()
+            || query.query_type != QueryType::Select
+            || dialect.name() == "sqlite"
+        {
+            return Ok(());
+        }
+        let explain = query
+            .explain(ExplainOptions::new().json())
+            .generate(dialect);
+        let rows = self.inner.query(&explain.sql, &explain.params).await?;
+        let plan = QueryPlan::from_rows(dialect, &rows)?;
+        self.guard.check_plan(&explain.sql, &plan)
+    }
+}
+
+#[async_trait]
+impl<E: Executor> Executor for GuardedExecutor<E> {
+    fn dialect(&self) -> &dyn Dialect {
+        self.inner.dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.guard.check(sql)?;
+        self.inner.query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.guard.check(sql)?;
+        self.inner.execute(sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        self.guard.check(sql)?;
+        self.inner.execute_with_id(sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        self.guard.check(sql)?;
+        self.inner.execute_many(sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        match self.guard.check(&sql) {
+            Ok(()) => self.inner.stream(sql, params),
+            Err(e) => stream::once(async { Err(e) }).boxed(),
+        }
+    }
+
+    async fn fetch_all(&self, query: &Query) -> Result<Vec<Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let mut query = query.clone();
+        if let Some(max_rows) = self.max_rows_guard() {
+            query.apply_row_guard(max_rows);
+        }
+        self.check_plan(&query).await?;
+        let fragment = self.generate(&query);
+        self.query(&fragment.sql, &fragment.params).await
+    }
+
+    fn fetch_stream(&self, query: &Query) -> Result<RowStream<'_, Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        if self.guard.plan.is_none() {
+            return Ok(RowStream::new(self.stream(fragment.sql, fragment.params)));
+        }
+        // The plan is checked when the stream is first polled
+        let query = query.clone();
+        let rows = stream::once(async move {
+            self.check_plan(&query).await?;
+            Ok::<_, ChakraError>(self.stream(fragment.sql, fragment.params))
+        })
+        .try_flatten()
+        .boxed();
+        Ok(RowStream::new(rows))
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.inner.max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.inner.sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        self.inner.read_only()
+    }
+
+    /// The pinned connection is guarded the same way
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        Ok(self.inner.pin().await?.map(|conn| {
+            Box::new(GuardedExecutor::new(conn, self.guard.clone())) as Box<dyn Executor + '_>
+        }))
+    }
+}
+
+/// The statement's main keyword, looking past a leading `WITH` clause
+fn statement_verb<'a>(statement: &[&'a Word]) -> Option<&'a str> {
+    let first = statement.first()?;
+    if first.upper != "WITH" {
+        return Some(&first.upper);
+    }
+    statement
+        .iter()
+        .skip(1)
+        .find(|w| w.depth == 0 && CTE_VERBS.contains(&w.upper.as_str()))
+        .map(|w| w.upper.as_str())
+}
+
+fn starts_with(statement: &[&Word], prefix: &[&str]) -> bool {
+    statement.len() >= prefix.len()
+        && statement
+            .iter()
+            .zip(prefix)
+            .all(|(w, p)| w.depth == 0 && w.upper == *p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staging_guard() -> StatementGuard {
+        StatementGuard::new()
+            .allow(StatementCheck::StartsWith(
+                "DROP TABLE IF EXISTS".to_string(),
+            ))
+            .deny(StatementCheck::DeleteWithoutWhere)
+            .deny(StatementCheck::UpdateWithoutWhere)
+            .deny(StatementCheck::DropTable)
+    }
+
+    #[test]
+    fn test_statement_guard() {
+        let guard = staging_guard();
+        assert!(guard.check("DELETE FROM users WHERE id = $1").is_ok());
+        assert!(guard
+            .check("UPDATE users SET name = 'x' WHERE id = 1")
+            .is_ok());
+        assert!(guard
+            .check("SELECT * FROM users -- DELETE FROM users")
+            .is_ok());
+        assert!(guard
+            .check("INSERT INTO log VALUES ('DROP TABLE users')")
+            .is_ok());
+        assert!(guard.check("DROP TABLE IF EXISTS scratch").is_ok());
+
+        let err = guard.check("delete from users").unwrap_err();
+        assert!(err.is_policy_violation());
+        assert_eq!(
+            err.to_string(),
+            "Statement denied by rule 'delete_without_where': DELETE without WHERE is not allowed here"
+        );
+        // A WHERE inside a subquery does not count
+        assert!(guard
+            .check("UPDATE users SET n = (SELECT 1 FROM t WHERE t.id = 1)")
+            .is_err());
+        assert!(guard
+            .check("WITH old AS (SELECT id FROM users WHERE x) DELETE FROM users")
+            .is_err());
+        assert!(guard.check("SELECT 1; DROP TABLE users").is_err());
+    }
+
+    #[test]
+    fn test_guard_config() {
+        let config: GuardConfig = serde_json::from_str(
+            r#"{
+                "environments": ["staging"],
+                "rules": [
+                    {"action": "deny", "check": "truncate", "message": "ask a DBA"},
+                    {"action": "deny", "check": {"starts_with": "drop database"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.guard_for(Some("production")).is_none());
+        assert!(config.guard_for(None).is_none());
+
+        let guard = config.guard_for(Some("staging")).unwrap();
+        let err = guard.check("TRUNCATE events").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Statement denied by rule 'truncate': ask a DBA"
+        );
+        assert!(guard.check("DROP DATABASE app").is_err());
+        assert!(guard.check("DROP TABLE events").is_ok());
+
+        let config: GuardConfig =
+            serde_json::from_str(r#"{"plan": {"max_rows": 1000, "action": "warn"}}"#).unwrap();
+        let guard = config.guard_for(None).unwrap();
+        assert_eq!(
+            guard.limits(),
+            Some(&PlanLimits::new().max_rows(1000.0).warn_only())
+        );
+    }
+
+    /// Plans every SELECT as a scan of `rows` rows
+    struct Planner {
+        rows: f64,
+        statements: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Executor for Planner {
+        fn dialect(&self) -> &dyn Dialect {
+            &crate::sql::PostgresDialect
+        }
+
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            self.statements.lock().unwrap().push(sql.to_string());
+            let plan = serde_json::json!([{"Plan": {
+                "Node Type": "Seq Scan",
+                "Relation Name": "events",
+                "Total Cost": self.rows / 10.0,
+                "Plan Rows": self.rows
+            }}]);
+            Ok(vec![Row::new(
+                vec!["QUERY PLAN".to_string()],
+                vec![plan.into()],
+            )])
+        }
+
+        async fn execute(&self, _sql: &str, _params: &[Value]) -> Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_limits() {
+        let executor = |rows, limits| {
+            let planner = Planner {
+                rows,
+                statements: Default::default(),
+            };
+            GuardedExecutor::new(planner, StatementGuard::new().plan_limits(limits))
+        };
+        let query = Query::select().from("events").build();
+
+        let guarded = executor(50.0, PlanLimits::new().max_rows(100.0));
+        assert_eq!(guarded.fetch_all(&query).await.unwrap().len(), 1);
+        assert_eq!(
+            *guarded.inner().statements.lock().unwrap(),
+            vec![
+                "EXPLAIN (FORMAT JSON) SELECT * FROM \"events\"",
+                "SELECT * FROM \"events\""
+            ]
+        );
+
+        let guarded = executor(1e6, PlanLimits::new().max_rows(1e9).max_cost(1000.0));
+        let err = guarded.fetch_all(&query).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Statement denied by rule 'max_cost': Query refused: estimated cost 100000 exceeds the limit \
+             of 1000; narrow its filters or add an index"
+        );
+        let err = guarded
+            .fetch_stream(&query)
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(err.is_policy_violation());
+        // Neither query ran
+        assert_eq!(guarded.inner().statements.lock().unwrap().len(), 2);
+
+        // Writes and hand-written SQL are not explained
+        guarded.query("SELECT * FROM events", &[]).await.unwrap();
+        guarded
+            .execute_query(&Query::delete().from("events").build())
+            .await
+            .unwrap();
+        assert_eq!(guarded.inner().statements.lock().unwrap().len(), 3);
+
+        let guarded = executor(1e6, PlanLimits::new().max_rows(100.0).warn_only());
+        assert_eq!(
+            guarded
+                .fetch_stream(&query)
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Client-side ID generation for Chakra ORM
+//!
+//! This module provides:
+//! - `IdStrategy` - how a generated primary key is produced
+//! - Snowflake, ULID, nanoid and UUID generators
+//! - A registry for application-defined generators
+
+use crate::error::{ChakraError, Result};
+use crate::types::Value;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Snowflake epoch, 2024-01-01T00:00:00Z in milliseconds
+pub const SNOWFLAKE_EPOCH_MS: u64 = 1_704_067_200_000;
+
+/// Largest machine id that fits in a snowflake
+pub const MAX_MACHINE_ID: u16 = (1 << 10) - 1;
+
+/// Default nanoid length
+pub const NANOID_LENGTH: usize = 21;
+
+const NANOID_ALPHABET: &[u8; 64] =
+    b"_-0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const CROCKFORD_BASE32: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Machine id for snowflakes that don't set one, 0 until configured
+static MACHINE_ID: AtomicU16 = AtomicU16::new(0);
+
+/// Last snowflake timestamp and sequence, shared so ids never repeat
+static SNOWFLAKE_STATE: Mutex<(u64, u16)> = Mutex::new((0, 0));
+
+/// Application-defined generators by name
+static CUSTOM_GENERATORS: RwLock<Option<HashMap<String, Arc<dyn IdGenerator>>>> = RwLock::new(None);
+
+/// A source of generated ids
+pub trait IdGenerator: Send + Sync {
+    /// Produce the next id
+    fn generate(&self) -> Value;
+}
+
+impl<F> IdGenerator for F
+where
+    F: Fn() -> Value + Send + Sync,
+{
+    fn generate(&self) -> Value {
+        self()
+    }
+}
+
+/// How a generated primary key is produced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdStrategy {
+    /// 64-bit time-ordered id: 41 bits of milliseconds, 10 bits of machine id,
+    /// 12 bits of sequence. `None` uses the process-wide machine id.
+    Snowflake { machine_id: Option<u16> },
+    /// 26-character, lexicographically time-ordered ULID
+    Ulid,
+    /// URL-safe random string of the given length
+    NanoId { length: usize },
+    /// Random UUID (version 4)
+    UuidV4,
+    /// A generator registered with `register_id_generator`
+    Custom(String),
+}
+
+impl IdStrategy {
+    /// Snowflake ids using the process-wide machine id
+    pub fn snowflake() -> Self {
+        IdStrategy::Snowflake { machine_id: None }
+    }
+
+    /// Nanoids of the default length
+    pub fn nanoid() -> Self {
+        IdStrategy::NanoId {
+            length: NANOID_LENGTH,
+        }
+    }
+
+    /// Generate the next id
+    pub fn generate(&self) -> Result<Value> {
+        Ok(match self {
+            IdStrategy::Snowflake { machine_id } => {
+                Value::Int64(snowflake(machine_id.unwrap_or_else(machine_id_default)))
+            }
+            IdStrategy::Ulid => Value::String(ulid()),
+            IdStrategy::NanoId { length } => Value::String(nanoid(*length)),
+            IdStrategy::UuidV4 => Value::Uuid(Uuid::new_v4()),
+            IdStrategy::Custom(name) => {
+                let generators = CUSTOM_GENERATORS.read().unwrap();
+                match generators.as_ref().and_then(|g| g.get(name)) {
+                    Some(generator) => generator.generate(),
+                    None => {
+                        return Err(ChakraError::config(format!(
+                            "No id generator registered as `{}`",
+                            name
+                        )))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Whether the database can produce the same kind of id as a column default
+    pub fn has_database_default(&self) -> bool {
+        matches!(self, IdStrategy::UuidV4)
+    }
+}
+
+impl fmt::Display for IdStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdStrategy::Snowflake { machine_id: None } => write!(f, "snowflake"),
+            IdStrategy::Snowflake {
+                machine_id: Some(id),
+            } => write!(f, "snowflake:{}", id),
+            IdStrategy::Ulid => write!(f, "ulid"),
+            IdStrategy::NanoId { length } => write!(f, "nanoid:{}", length),
+            IdStrategy::UuidV4 => write!(f, "uuid"),
+            IdStrategy::Custom(name) => write!(f, "custom:{}", name),
+        }
+    }
+}
+
+impl FromStr for IdStrategy {
+    type Err = String;
+
+    /// Parse `snowflake[:machine_id]`, `ulid`, `nanoid[:length]`, `uuid` or `custom:name`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let invalid = || format!("Invalid id strategy: {}", s);
+
+        match (name, arg) {
+            ("snowflake", None) => Ok(IdStrategy::snowflake()),
+            ("snowflake", Some(id)) => match id.parse() {
+                Ok(id) if id <= MAX_MACHINE_ID => Ok(IdStrategy::Snowflake {
+                    machine_id: Some(id),
+                }),
+                _ => Err(invalid()),
+            },
+            ("ulid", None) => Ok(IdStrategy::Ulid),
+            ("nanoid", None) => Ok(IdStrategy::nanoid()),
+            ("nanoid", Some(length)) => match length.parse() {
+                Ok(length) if length > 0 => Ok(IdStrategy::NanoId { length }),
+                _ => Err(invalid()),
+            },
+            ("uuid", None) => Ok(IdStrategy::UuidV4),
+            ("custom", Some(name)) if !name.is_empty() => Ok(IdStrategy::Custom(name.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Set the machine id used by snowflakes that don't set their own
+///
+/// Each process generating snowflakes for the same table needs a distinct id.
+/// When unset, `CHAKRA_MACHINE_ID` is read on first use.
+pub fn set_machine_id(machine_id: u16) {
+    assert!(
+        machine_id <= MAX_MACHINE_ID,
+        "machine id must fit in 10 bits"
+    );
+    MACHINE_ID.store(machine_id | 0x8000, Ordering::Relaxed);
+}
+
+fn machine_id_default() -> u16 {
+    let id = MACHINE_ID.load(Ordering::Relaxed);
+    if id & 0x8000 != 0 {
+        return id & MAX_MACHINE_ID;
+    }
+
+    let from_env = std::env::var("CHAKRA_MACHINE_ID")
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .unwrap_or(0)
+        & MAX_MACHINE_ID;
+    MACHINE_ID.store(from_env | 0x8000, Ordering::Relaxed);
+    from_env
+}
+
+/// Register a generator usable as `IdStrategy::Custom(name)`
+pub fn register_id_generator(name: impl Into<String>, generator: impl IdGenerator + 'static) {
+    let mut generators = CUSTOM_GENERATORS.write().unwrap();
+    generators
+        .get_or_insert_with(HashMap::new)
+        .insert(name.into(), Arc::new(generator));
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Generate a snowflake id
+///
+/// When the 4096 ids of a millisecond run out, or the clock moves back, the
+/// timestamp is advanced past the last one used so ids stay increasing.
+pub fn snowflake(machine_id: u16) -> i64 {
+    let mut state = SNOWFLAKE_STATE.lock().unwrap();
+    let (last_ms, sequence) = *state;
+
+    let now = now_ms().saturating_sub(SNOWFLAKE_EPOCH_MS);
+    let (ms, sequence) = if now > last_ms {
+        (now, 0)
+    } else if sequence < 0xFFF {
+        (last_ms, sequence + 1)
+    } else {
+        (last_ms + 1, 0)
+    };
+    *state = (ms, sequence);
+
+    let id = (ms & ((1 << 41) - 1)) << 22
+        | u64::from(machine_id & MAX_MACHINE_ID) << 12
+        | u64::from(sequence);
+    id as i64
+}
+
+/// Generate a ULID
+pub fn ulid() -> String {
+    let random: u128 = rand::thread_rng().gen::<u128>() & ((1 << 80) - 1);
+    let value = (u128::from(now_ms()) & ((1 << 48) - 1)) << 80 | random;
+
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD_BASE32[((value >> (i * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+/// Generate a nanoid of the given length
+pub fn nanoid(length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..length)
+        .map(|_| NANOID_ALPHABET[rng.gen_range(0..64)] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generators() {
+        let a = snowflake(7);
+        let b = snowflake(7);
+        assert!(b > a);
+        assert_eq!((a >> 12) & i64::from(MAX_MACHINE_ID), 7);
+
+        let id = ulid();
+        assert_eq!(id.len(), 26);
+        assert!(id.chars().all(|c| CROCKFORD_BASE32.contains(&(c as u8))));
+
+        assert_eq!(nanoid(NANOID_LENGTH).len(), 21);
+        assert!(matches!(
+            IdStrategy::UuidV4.generate().unwrap(),
+            Value::Uuid(_)
+        ));
+    }
+
+    #[test]
+    fn test_strategy_from_str() {
+        for s in [
+            "snowflake",
+            "snowflake:3",
+            "ulid",
+            "nanoid:10",
+            "uuid",
+            "custom:order_no",
+        ] {
+            assert_eq!(s.parse::<IdStrategy>().unwrap().to_string(), s);
+        }
+        assert!("snowflake:5000".parse::<IdStrategy>().is_err());
+        assert!("serial".parse::<IdStrategy>().is_err());
+
+        register_id_generator("answer", || Value::Int64(42));
+        assert_eq!(
+            IdStrategy::Custom("answer".into()).generate().unwrap(),
+            Value::Int64(42)
+        );
+        assert!(IdStrategy::Custom("missing".into()).generate().is_err());
+    }
+}
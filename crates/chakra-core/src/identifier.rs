@@ -0,0 +1,487 @@
+//! How identifiers are written into SQL
+//!
+//! PostgreSQL folds unquoted identifiers to lowercase but keeps quoted ones
+//! as written, so a `userId` column created by hand-written DDL without
+//! quotes is really `userid`, and a quoted `"userId"` no longer matches it.
+//! The identifier policy decides this once for query generation, DDL,
+//! schema comparison and row decoding. Each dialect has its own:
+//!
+//! ```rust,ignore
+//! use chakra_core::identifier::{set_dialect_identifier_policy, IdentifierPolicy};
+//!
+//! // Match a PostgreSQL database whose tables were created without quotes
+//! set_dialect_identifier_policy("postgresql", IdentifierPolicy::FoldLower);
+//! ```
+//!
+//! Set it at startup, before any SQL is generated; the CLI sets every
+//! dialect's from `identifiers` under `[schema]`.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The policy of each dialect, `AlwaysQuote` until configured
+static POLICIES: [AtomicU8; 3] = [AtomicU8::new(0), AtomicU8::new(0), AtomicU8::new(0)];
+
+/// Words that can't be written as bare identifiers in any supported dialect
+const RESERVED: &[&str] = &[
+    "all",
+    "alter",
+    "and",
+    "any",
+    "as",
+    "asc",
+    "between",
+    "by",
+    "case",
+    "check",
+    "column",
+    "constraint",
+    "create",
+    "cross",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "except",
+    "exists",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "full",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "index",
+    "inner",
+    "insert",
+    "intersect",
+    "into",
+    "is",
+    "join",
+    "key",
+    "left",
+    "like",
+    "limit",
+    "natural",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "outer",
+    "primary",
+    "references",
+    "right",
+    "select",
+    "set",
+    "table",
+    "then",
+    "to",
+    "true",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "using",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// Further words PostgreSQL reserves, or allows only as function or type
+/// names
+const POSTGRES_RESERVED: &[&str] = &[
+    "analyse",
+    "analyze",
+    "array",
+    "asymmetric",
+    "authorization",
+    "binary",
+    "both",
+    "cast",
+    "collate",
+    "collation",
+    "concurrently",
+    "current_catalog",
+    "current_role",
+    "current_schema",
+    "deferrable",
+    "do",
+    "freeze",
+    "ilike",
+    "initially",
+    "isnull",
+    "lateral",
+    "leading",
+    "localtime",
+    "localtimestamp",
+    "notnull",
+    "only",
+    "overlaps",
+    "placing",
+    "returning",
+    "session_user",
+    "similar",
+    "some",
+    "symmetric",
+    "system_user",
+    "tablesample",
+    "trailing",
+    "variadic",
+    "verbose",
+    "window",
+];
+
+/// Further words MySQL reserves
+const MYSQL_RESERVED: &[&str] = &[
+    "add",
+    "analyze",
+    "before",
+    "bigint",
+    "binary",
+    "blob",
+    "both",
+    "call",
+    "cascade",
+    "change",
+    "char",
+    "character",
+    "collate",
+    "condition",
+    "continue",
+    "convert",
+    "cube",
+    "cume_dist",
+    "cursor",
+    "database",
+    "databases",
+    "dec",
+    "decimal",
+    "declare",
+    "delayed",
+    "dense_rank",
+    "describe",
+    "deterministic",
+    "distinctrow",
+    "div",
+    "double",
+    "dual",
+    "each",
+    "elseif",
+    "empty",
+    "enclosed",
+    "escaped",
+    "exit",
+    "explain",
+    "first_value",
+    "float",
+    "force",
+    "fulltext",
+    "function",
+    "generated",
+    "get",
+    "groups",
+    "if",
+    "ignore",
+    "infile",
+    "inout",
+    "int",
+    "integer",
+    "interval",
+    "iterate",
+    "json_table",
+    "keys",
+    "kill",
+    "lag",
+    "last_value",
+    "lateral",
+    "lead",
+    "leading",
+    "leave",
+    "linear",
+    "lines",
+    "load",
+    "localtime",
+    "localtimestamp",
+    "lock",
+    "long",
+    "loop",
+    "match",
+    "maxvalue",
+    "mod",
+    "modifies",
+    "nth_value",
+    "ntile",
+    "numeric",
+    "of",
+    "optimize",
+    "option",
+    "optionally",
+    "out",
+    "outfile",
+    "over",
+    "partition",
+    "percent_rank",
+    "precision",
+    "procedure",
+    "purge",
+    "range",
+    "rank",
+    "read",
+    "reads",
+    "real",
+    "recursive",
+    "regexp",
+    "release",
+    "rename",
+    "repeat",
+    "replace",
+    "require",
+    "restrict",
+    "return",
+    "revoke",
+    "rlike",
+    "row",
+    "row_number",
+    "rows",
+    "schema",
+    "schemas",
+    "separator",
+    "show",
+    "signal",
+    "smallint",
+    "spatial",
+    "specific",
+    "sql",
+    "starting",
+    "stored",
+    "straight_join",
+    "system",
+    "terminated",
+    "tinyint",
+    "trailing",
+    "trigger",
+    "undo",
+    "unlock",
+    "unsigned",
+    "usage",
+    "utc_date",
+    "utc_time",
+    "utc_timestamp",
+    "varbinary",
+    "varchar",
+    "varying",
+    "virtual",
+    "while",
+    "window",
+    "write",
+    "xor",
+    "zerofill",
+];
+
+/// Further words SQLite can't read as identifiers
+const SQLITE_RESERVED: &[&str] = &[
+    "add",
+    "autoincrement",
+    "collate",
+    "commit",
+    "deferrable",
+    "escape",
+    "if",
+    "isnull",
+    "notnull",
+    "returning",
+    "transaction",
+];
+
+/// How identifiers are written into SQL
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifierPolicy {
+    /// Quote every identifier, keeping its case
+    #[default]
+    AlwaysQuote,
+    /// Lowercase every identifier, then quote it, matching what PostgreSQL
+    /// does to unquoted names
+    ///
+    /// MySQL keeps unquoted names as written, and its table names are
+    /// case-sensitive on most systems, so there it quotes like
+    /// `AlwaysQuote`.
+    FoldLower,
+    /// Keep each identifier's case, quoting only names that would otherwise
+    /// be folded or misread, like `userId` or `order`
+    Preserve,
+}
+
+impl IdentifierPolicy {
+    /// The name the database stores for `name`
+    pub fn fold(self, name: &str) -> Cow<'_, str> {
+        match self {
+            Self::FoldLower if name.chars().any(char::is_uppercase) => {
+                Cow::Owned(name.to_lowercase())
+            }
+            _ => Cow::Borrowed(name),
+        }
+    }
+
+    /// Whether `a` and `b` name the same object under this policy
+    pub fn same(self, a: &str, b: &str) -> bool {
+        self.fold(a) == self.fold(b)
+    }
+
+    /// Write `name` as an identifier of `dialect`, see `Dialect::name`,
+    /// quoting it if needed
+    pub fn quote(self, name: &str, dialect: &str) -> String {
+        let policy = self.for_dialect(dialect);
+        let name = policy.fold(name);
+        if policy == Self::Preserve && is_bare(&name, dialect) {
+            return name.into_owned();
+        }
+        let quote = if dialect == "mysql" { '`' } else { '"' };
+        let escaped = name.replace(quote, &format!("{}{}", quote, quote));
+        format!("{}{}{}", quote, escaped, quote)
+    }
+
+    /// The policy as it applies to `dialect`
+    fn for_dialect(self, dialect: &str) -> Self {
+        match self {
+            Self::FoldLower if dialect == "mysql" => Self::AlwaysQuote,
+            policy => policy,
+        }
+    }
+}
+
+/// Whether `name` reads back unchanged without quotes in `dialect`
+fn is_bare(name: &str, dialect: &str) -> bool {
+    let reserved = match dialect {
+        "postgresql" => POSTGRES_RESERVED,
+        "mysql" => MYSQL_RESERVED,
+        _ => SQLITE_RESERVED,
+    };
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED.contains(&name)
+        && !reserved.contains(&name)
+}
+
+/// Write `name` as an identifier of `dialect` under its policy
+pub fn quote(dialect: &str, name: &str) -> String {
+    identifier_policy(dialect).quote(name, dialect)
+}
+
+/// Set the identifier policy of every dialect
+pub fn set_identifier_policy(policy: IdentifierPolicy) {
+    for dialect in ["postgresql", "mysql", "sqlite"] {
+        set_dialect_identifier_policy(dialect, policy);
+    }
+}
+
+/// Set the identifier policy of `dialect`, see `Dialect::name`, e.g. to
+/// fold names for a PostgreSQL database while a SQLite one keeps them
+pub fn set_dialect_identifier_policy(dialect: &str, policy: IdentifierPolicy) {
+    let value = match policy.for_dialect(dialect) {
+        IdentifierPolicy::AlwaysQuote => 0,
+        IdentifierPolicy::FoldLower => 1,
+        IdentifierPolicy::Preserve => 2,
+    };
+    POLICIES[slot(dialect)].store(value, Ordering::Relaxed);
+}
+
+/// The identifier policy in effect for `dialect`
+pub fn identifier_policy(dialect: &str) -> IdentifierPolicy {
+    match POLICIES[slot(dialect)].load(Ordering::Relaxed) {
+        1 => IdentifierPolicy::FoldLower,
+        2 => IdentifierPolicy::Preserve,
+        _ => IdentifierPolicy::AlwaysQuote,
+    }
+}
+
+/// Whether any dialect folds identifiers, so a database may report a
+/// column under its folded name
+pub fn folds_identifiers() -> bool {
+    POLICIES
+        .iter()
+        .any(|policy| policy.load(Ordering::Relaxed) == 1)
+}
+
+fn slot(dialect: &str) -> usize {
+    match dialect {
+        "mysql" => 1,
+        "sqlite" => 2,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        let quote = |policy: IdentifierPolicy, name| policy.quote(name, "postgresql");
+        assert_eq!(quote(IdentifierPolicy::AlwaysQuote, "userId"), "\"userId\"");
+        assert_eq!(quote(IdentifierPolicy::AlwaysQuote, "a\"b"), "\"a\"\"b\"");
+
+        assert_eq!(quote(IdentifierPolicy::FoldLower, "userId"), "\"userid\"");
+        assert_eq!(quote(IdentifierPolicy::FoldLower, "email"), "\"email\"");
+
+        assert_eq!(quote(IdentifierPolicy::Preserve, "email"), "email");
+        assert_eq!(quote(IdentifierPolicy::Preserve, "user_2"), "user_2");
+        assert_eq!(quote(IdentifierPolicy::Preserve, "userId"), "\"userId\"");
+        assert_eq!(quote(IdentifierPolicy::Preserve, "order"), "\"order\"");
+        assert_eq!(quote(IdentifierPolicy::Preserve, "2fa"), "\"2fa\"");
+        assert_eq!(
+            IdentifierPolicy::Preserve.quote("my`col", "mysql"),
+            "`my``col`"
+        );
+    }
+
+    #[test]
+    fn test_quote_dialects() {
+        // MySQL table names are case-sensitive and never folded
+        assert_eq!(
+            IdentifierPolicy::FoldLower.quote("Users", "mysql"),
+            "`Users`"
+        );
+        assert_eq!(
+            IdentifierPolicy::FoldLower.quote("Users", "sqlite"),
+            "\"users\""
+        );
+
+        // Each dialect's own reserved words stay quoted
+        let preserve = |name, dialect| IdentifierPolicy::Preserve.quote(name, dialect);
+        assert_eq!(preserve("returning", "postgresql"), "\"returning\"");
+        assert_eq!(preserve("window", "postgresql"), "\"window\"");
+        assert_eq!(preserve("range", "postgresql"), "range");
+        assert_eq!(preserve("range", "mysql"), "`range`");
+        assert_eq!(preserve("rank", "mysql"), "`rank`");
+        assert_eq!(preserve("window", "mysql"), "`window`");
+        assert_eq!(preserve("returning", "mysql"), "returning");
+        assert_eq!(preserve("returning", "sqlite"), "\"returning\"");
+        assert_eq!(preserve("rank", "sqlite"), "rank");
+    }
+
+    #[test]
+    fn test_same() {
+        assert!(IdentifierPolicy::FoldLower.same("userId", "userid"));
+        assert!(!IdentifierPolicy::AlwaysQuote.same("userId", "userid"));
+        assert!(!IdentifierPolicy::Preserve.same("userId", "userid"));
+    }
+}
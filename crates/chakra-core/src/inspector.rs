@@ -0,0 +1,100 @@
+//! Recording how a queryset was built
+//!
+//! After `QuerySet::inspect()`, every call that shapes the query is kept as
+//! an `InspectStep`. `QuerySet::explain()` prints those steps as SQL
+//! comments above the final SQL, each eager load annotated with the join or
+//! follow-up query it plans:
+//!
+//! ```text
+//! -- Post.objects() on posts
+//! -- 1. filter: "title" = 'engines'
+//! -- 2. select_related("author"): LEFT JOIN authors AS author ON "author"."id" = "posts"."author_id"
+//! -- 3. prefetch_related("tags"): then SELECT "tags".* ... WHERE post_tags.post_id IN (...), keys from posts.id
+//! SELECT ...
+//! ```
+
+use crate::expr::Expr;
+use crate::sql::{Dialect, SqlFragment};
+
+/// One call made on an inspected queryset
+#[derive(Debug, Clone)]
+pub enum InspectStep {
+    Filter(Expr),
+    Exclude(Expr),
+    /// Column as passed, `-` prefix for descending
+    OrderBy(String),
+    Limit(usize),
+    Offset(usize),
+    Distinct,
+    Unscoped,
+    /// Name of the registered scope dropped
+    WithoutScope(String),
+    AllTenants,
+    AllowCasts,
+    SelectRelated(String),
+    PrefetchRelated(String),
+    /// Alias of the computed column
+    Annotate(String),
+}
+
+impl InspectStep {
+    /// One-line description, without the planned SQL of eager loads
+    pub fn describe(&self, dialect: &dyn Dialect) -> String {
+        match self {
+            InspectStep::Filter(expr) => format!("filter: {}", expr_sql(dialect, expr)),
+            InspectStep::Exclude(expr) => format!("exclude: {}", expr_sql(dialect, expr)),
+            InspectStep::OrderBy(column) => format!("order_by(\"{}\")", column),
+            InspectStep::Limit(limit) => format!("limit({})", limit),
+            InspectStep::Offset(offset) => format!("offset({})", offset),
+            InspectStep::Distinct => "distinct()".to_string(),
+            InspectStep::Unscoped => "unscoped(): default scopes dropped".to_string(),
+            InspectStep::WithoutScope(name) => {
+                format!("without_scope(\"{}\"): scope dropped", name)
+            }
+            InspectStep::AllTenants => "all_tenants(): tenant filter dropped".to_string(),
+            InspectStep::AllowCasts => "allow_casts(): filter types not checked".to_string(),
+            InspectStep::SelectRelated(name) => format!("select_related(\"{}\")", name),
+            InspectStep::PrefetchRelated(name) => format!("prefetch_related(\"{}\")", name),
+            InspectStep::Annotate(alias) => format!("annotate: {}", alias),
+        }
+    }
+}
+
+/// The steps recorded for one queryset
+#[derive(Debug, Clone, Default)]
+pub struct QueryInspector {
+    steps: Vec<InspectStep>,
+}
+
+impl QueryInspector {
+    /// Start with no steps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a step
+    pub fn record(&mut self, step: InspectStep) {
+        self.steps.push(step);
+    }
+
+    /// Steps in the order they were made
+    pub fn steps(&self) -> &[InspectStep] {
+        &self.steps
+    }
+}
+
+/// An expression as inline SQL on one line
+pub(crate) fn expr_sql(dialect: &dyn Dialect, expr: &Expr) -> String {
+    let mut fragment = SqlFragment::new();
+    dialect.generate_expr(expr, &mut fragment);
+    one_line(&fragment.debug_sql())
+}
+
+/// Join pretty-printed SQL back onto one line
+pub(crate) fn one_line(sql: &str) -> String {
+    sql.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -3,10 +3,35 @@
 //! The core query engine for Chakra ORM. This crate provides:
 //!
 //! - Query building and SQL generation
-//! - Type system and field definitions
+//! - A configurable identifier quoting and case folding policy
+//! - Lazy model querysets
+//! - Default scopes registered at runtime
+//! - Queryset inspection and explanation
+//! - Type system and field definitions, extensible with custom value types
+//! - Network and MAC address types
+//! - Range types with overlap and containment operators
+//! - Rust enums stored as database enums
 //! - Expression evaluation (F, Q objects)
 //! - Result mapping and decoding
+//! - Order-independent result checksums for replica verification
+//! - Arrow export of query results (`arrow` feature)
 //! - Model metadata and registry
+//! - Saving, loading and deleting model instances
+//! - Field-level encryption with key rotation
+//! - Model validation before writes
+//! - Database views defined as queries, read through read-only models
+//! - Hand-written SQL decoded into models
+//! - SQL script splitting
+//! - SQL pretty-printing
+//! - Compiled SQL caching
+//! - Routing models to named databases
+//! - Sessions with an identity map and queued writes
+//! - Hash-based horizontal sharding
+//! - Transactions with nested savepoints, isolation levels and conflict retry
+//! - Temporary staging tables
+//! - Statement allow/deny rules and query plan limits
+//! - N+1 query detection per request scope
+//! - Discriminator-column multi-tenancy
 //!
 //! ## Example
 //!
@@ -24,13 +49,41 @@
 //! let sql = PostgresDialect.generate(&query);
 //! ```
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod checksum;
+pub mod crud;
+pub mod encryption;
 pub mod error;
+pub mod executor;
+pub mod explain;
 pub mod expr;
+pub mod format;
+pub mod guard;
+pub mod id;
+pub mod identifier;
+pub mod inspector;
 pub mod model;
+pub mod n_plus_one;
+pub mod net;
 pub mod query;
+pub mod queryset;
+pub mod range;
+pub mod raw;
 pub mod result;
+pub mod router;
+pub mod scope;
+pub mod script;
+pub mod session;
+pub mod shard;
 pub mod sql;
+pub mod sql_cache;
+pub mod temp_table;
+pub mod tenant;
+pub mod transaction;
 pub mod types;
+pub mod validate;
+pub mod view;
 
 // Re-export derive macros if enabled
 #[cfg(feature = "derive")]
@@ -38,16 +91,38 @@ pub use chakra_derive::*;
 
 /// Prelude module for convenient imports
 pub mod prelude {
-    pub use crate::error::{ChakraError, Result};
+    pub use crate::crud::{BulkCreate, Crud};
+    pub use crate::encryption::{set_keyring, Encrypted, FieldCipher, Keyring};
+    pub use crate::error::{ChakraError, ErrorCode, Result};
+    pub use crate::executor::{ExecResult, Executor};
+    pub use crate::explain::{ExplainOptions, QueryPlan};
     pub use crate::expr::{Expr, F, Q};
-    pub use crate::model::{Field, FieldMeta, Model, ModelMeta, Related};
-    pub use crate::query::{Order, Query, QueryBuilder};
-    pub use crate::result::{FromRow, Row, RowStream};
-    pub use crate::sql::{Dialect, PostgresDialect, SqlFragment};
-    pub use crate::types::{FieldType, Value};
+    pub use crate::format::SqlFormatter;
+    pub use crate::guard::{GuardedExecutor, PlanLimits, StatementGuard};
+    pub use crate::identifier::{
+        set_dialect_identifier_policy, set_identifier_policy, IdentifierPolicy,
+    };
+    pub use crate::inspector::QueryInspector;
+    pub use crate::model::{Field, FieldMeta, Model, ModelMeta, Record, Related, Snapshot};
+    pub use crate::n_plus_one::{NPlusOneDetector, QueryScope};
+    pub use crate::query::{OnConflict, Order, Query, QueryBuilder, TableFunction};
+    pub use crate::queryset::{Aggregate, Annotated, Avg, Count, Max, Min, QuerySet, Sum};
+    pub use crate::raw::RawQuery;
+    pub use crate::result::{FromRow, FromValue, Lossy, Row, RowStream};
+    pub use crate::router::{Router, Routes};
+    pub use crate::session::Session;
+    pub use crate::shard::ShardRouter;
+    pub use crate::sql::{Dialect, NamedStyle, PostgresDialect, SqlFragment};
+    pub use crate::sql_cache::SqlCache;
+    pub use crate::tenant::TenantContext;
+    pub use crate::transaction::{
+        IsolationLevel, Transaction, TransactionOptions, TransactionRetry,
+    };
+    pub use crate::types::{DbEnum, FieldType, ToValue, Value};
+    pub use crate::view::View;
 
     #[cfg(feature = "derive")]
-    pub use chakra_derive::Model;
+    pub use chakra_derive::{sql, DbEnum, Model};
 }
 
 /// Library version
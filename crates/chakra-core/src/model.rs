@@ -4,22 +4,32 @@
 //! - `Model` trait for ORM models
 //! - `ModelMeta` for model metadata
 //! - `FieldMeta` for field metadata
+//! - `Record` for logging model values with sensitive fields redacted
 //! - `Related` for relationship handling
+//! - `Snapshot` and `FieldMask` for tracking changed fields
 
-use crate::error::{ChakraError, ModelError, Result};
+use crate::error::{ChakraError, ModelError, QueryError, Result, ValidationError};
+use crate::expr::Expr;
+use crate::id::IdStrategy;
+use crate::query::{Query, QueryBuilder};
+use crate::queryset::QuerySet;
 use crate::result::Row;
-use crate::types::{FieldType, Value};
+use crate::types::{FieldType, ToValue, Value};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::sync::{Arc, RwLock};
 
+/// Placeholder shown instead of a sensitive value
+pub const REDACTED: &str = "[REDACTED]";
+
 /// Global model registry
 static MODEL_REGISTRY: RwLock<Option<ModelRegistry>> = RwLock::new(None);
 
 /// Trait for ORM models
 pub trait Model: Sized + Send + Sync {
     /// The primary key type
-    type PrimaryKey: Clone + Send + Sync + Into<Value>;
+    type PrimaryKey: Clone + Send + Sync + ToValue;
 
     /// Get the table name
     fn table_name() -> &'static str;
@@ -37,13 +47,150 @@ pub trait Model: Sized + Send + Sync {
     fn from_row(row: &Row) -> Result<Self>;
 
     /// Convert to a map of values
-    fn to_values(&self) -> HashMap<String, Value>;
+    ///
+    /// Fails if an encrypted field can't be encrypted, e.g. with no keyring.
+    fn to_values(&self) -> Result<HashMap<String, Value>>;
 
-    /// Get a field value by name
-    fn get_field(&self, name: &str) -> Option<Value>;
+    /// Get a field value by name, failing like `to_values`
+    fn get_field(&self, name: &str) -> Result<Option<Value>>;
 
     /// Set a field value by name
     fn set_field(&mut self, name: &str, value: Value) -> Result<()>;
+
+    /// Filter applied to every query built with `query()`
+    fn default_scope() -> Option<Expr> {
+        None
+    }
+
+    /// Column holding the tenant id, if rows are scoped by `TenantContext`
+    fn tenant_column() -> Option<&'static str> {
+        None
+    }
+
+    /// Start a SELECT on this model's table with its default scope
+    fn query() -> QueryBuilder {
+        let builder = Query::select()
+            .from(Self::table_name())
+            .sensitive(Self::meta().sensitive_columns());
+        match Self::default_scope() {
+            Some(scope) => builder.scope(scope),
+            None => builder,
+        }
+    }
+
+    /// Fill generated ids that are still unset and return the values to insert
+    ///
+    /// A field counts as unset when it is NULL, zero, empty or the nil UUID.
+    fn prepare_insert(&mut self) -> Result<HashMap<String, Value>> {
+        for field in Self::fields() {
+            let Some(FieldDefault::Generated(strategy)) = &field.default else {
+                continue;
+            };
+            if is_unset(self.get_field(field.column_name())?.as_ref()) {
+                self.set_field(field.column_name(), strategy.generate()?)?;
+            }
+        }
+        self.to_values()
+    }
+
+    /// A lazy queryset over this model's table with its default scope
+    fn objects() -> QuerySet<Self> {
+        QuerySet::new()
+    }
+
+    /// Start a SELECT on this model's table ignoring its default scope
+    fn unscoped() -> QueryBuilder {
+        Query::select()
+            .from(Self::table_name())
+            .sensitive(Self::meta().sensitive_columns())
+    }
+
+    /// Metadata of the model that relationship `name` points to
+    fn related_meta(_name: &str) -> Option<&'static ModelMeta> {
+        None
+    }
+
+    /// Load relationship `name` from rows of its target model: at most one
+    /// row for a single relation, none meaning there is no related record
+    fn set_related(&mut self, name: &str, _rows: &[Row]) -> Result<()> {
+        Err(ChakraError::Model(ModelError::InvalidRelationship {
+            model: Self::meta().name.clone(),
+            relationship: name.to_string(),
+        }))
+    }
+
+    /// Values for logging or serialization, with sensitive fields redacted
+    fn to_record(&self) -> Result<Record> {
+        Ok(Record::new(
+            self.to_values()?,
+            Self::meta().sensitive_columns(),
+        ))
+    }
+
+    /// The model's `#[chakra(snapshot)]` field, if it tracks changes
+    fn snapshot_mut(&mut self) -> Option<&mut Snapshot> {
+        None
+    }
+
+    /// Run the field checks declared with `#[chakra(validate(...))]`,
+    /// returning every failure at once
+    ///
+    /// Called before each insert and update; see `validate`.
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Fields changed since the model was loaded or saved, by position in
+    /// `fields()`
+    ///
+    /// `None` if the model doesn't track changes or has not been loaded or
+    /// saved yet, in which case every field counts as changed.
+    fn changed_fields(&self) -> Option<FieldMask> {
+        None
+    }
+
+    /// Column names of `changed_fields`, e.g. for audit logs
+    fn changed_columns(&self) -> Option<Vec<&'static str>> {
+        let mask = self.changed_fields()?;
+        Some(
+            Self::fields()
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask.contains(*i))
+                .map(|(_, f)| f.column_name())
+                .collect(),
+        )
+    }
+
+    /// Record the current values as unchanged
+    ///
+    /// A field that can't be encrypted is recorded as NULL, so it counts
+    /// as changed.
+    fn mark_clean(&mut self) {
+        let values: Vec<Value> = Self::fields()
+            .iter()
+            .map(|f| {
+                self.get_field(f.column_name())
+                    .ok()
+                    .flatten()
+                    .unwrap_or(Value::Null)
+            })
+            .collect();
+        if let Some(snapshot) = self.snapshot_mut() {
+            snapshot.capture(values);
+        }
+    }
+}
+
+/// Whether a field value counts as not yet assigned: NULL, zero, empty or
+/// the nil UUID
+pub(crate) fn is_unset(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Int32(0)) | Some(Value::Int64(0)) => true,
+        Some(Value::String(s)) => s.is_empty(),
+        Some(Value::Uuid(u)) => u.is_nil(),
+        Some(_) => false,
+    }
 }
 
 /// Metadata for a model
@@ -55,6 +202,12 @@ pub struct ModelMeta {
     pub table: String,
     /// Schema name (optional)
     pub schema: Option<String>,
+    /// Named database the model lives in, if not the default one
+    #[serde(default)]
+    pub database: Option<String>,
+    /// Column whose value picks the shard a row lives on, if sharded
+    #[serde(default)]
+    pub shard_key: Option<String>,
     /// Primary key field(s)
     pub primary_key: Vec<String>,
     /// Field metadata
@@ -65,6 +218,9 @@ pub struct ModelMeta {
     pub constraints: Vec<ConstraintMeta>,
     /// Relationship metadata
     pub relationships: Vec<RelationMeta>,
+    /// Whether `table` is a view, which makes the model read-only
+    #[serde(default)]
+    pub view: bool,
 }
 
 impl ModelMeta {
@@ -80,11 +236,237 @@ impl ModelMeta {
 
     /// Get the primary key field(s)
     pub fn primary_key_fields(&self) -> Vec<&FieldMeta> {
+        self.fields.iter().filter(|f| f.primary_key).collect()
+    }
+
+    /// Columns of fields marked sensitive
+    pub fn sensitive_columns(&self) -> Vec<String> {
         self.fields
             .iter()
-            .filter(|f| f.primary_key)
+            .filter(|f| f.sensitive)
+            .map(|f| f.column_name().to_string())
             .collect()
     }
+
+    /// The field stored in `column`, which may be qualified with this
+    /// model's table
+    fn field_by_column(&self, column: &str) -> Option<&FieldMeta> {
+        let column = match column.rsplit_once('.') {
+            Some((table, column)) if table == self.table => column,
+            Some(_) => return None,
+            None => column,
+        };
+        self.fields.iter().find(|f| f.column_name() == column)
+    }
+
+    /// Check that each value `query` writes to or compares with one of this
+    /// model's columns fits the column's type
+    ///
+    /// Covers INSERT rows, UPDATE assignments and the WHERE clause. Catches
+    /// a string bound to an integer column, say, before the database
+    /// rejects it, which inside a transaction aborts the transaction.
+    /// Columns of other tables, and subqueries, aren't checked.
+    pub fn check_param_types(&self, query: &Query) -> Result<()> {
+        for row in &query.values {
+            for (column, value) in row {
+                self.check_value_type(column, value, false, false)?;
+            }
+        }
+        for (column, expr) in &query.set_exprs {
+            if let Expr::Value(value) = expr {
+                self.check_value_type(column, value, false, false)?;
+            }
+        }
+        match &query.where_clause {
+            Some(expr) => self.check_expr_types(expr),
+            None => Ok(()),
+        }
+    }
+
+    fn check_expr_types(&self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                exprs.iter().try_for_each(|e| self.check_expr_types(e))
+            }
+            Expr::Not(e) => self.check_expr_types(e),
+            Expr::Compare { column, value, .. } | Expr::RangeOverlaps { column, value } => {
+                self.check_value_type(column, value, false, true)
+            }
+            Expr::RangeContains { column, value } => {
+                self.check_value_type(column, value, !matches!(value, Value::Range(_)), true)
+            }
+            Expr::Between {
+                column, low, high, ..
+            } => {
+                self.check_value_type(column, low, false, true)?;
+                self.check_value_type(column, high, false, true)
+            }
+            Expr::In { column, values, .. } | Expr::InArray { column, values, .. } => values
+                .iter()
+                .try_for_each(|v| self.check_value_type(column, v, false, true)),
+            Expr::ArrayContains { column, values } | Expr::ArrayOverlaps { column, values } => {
+                values
+                    .iter()
+                    .try_for_each(|v| self.check_value_type(column, v, true, true))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check `value` against `column`'s type, or against the type of its
+    /// elements if `element`; a `filter` value can be sent anyway with
+    /// `allow_casts`
+    pub(crate) fn check_value_type(
+        &self,
+        column: &str,
+        value: &Value,
+        element: bool,
+        filter: bool,
+    ) -> Result<()> {
+        let Some(field) = self.field_by_column(column) else {
+            return Ok(());
+        };
+        let field_type = match &field.field_type {
+            FieldType::Array { element_type } | FieldType::Range { element_type } if element => {
+                element_type
+            }
+            _ if element => return Ok(()),
+            field_type => field_type,
+        };
+        if field_type.accepts(value) {
+            return Ok(());
+        }
+        Err(ChakraError::TypeConversion {
+            message: format!(
+                "{}.{} is {}{}, but the query binds a {} value to it{}",
+                self.name,
+                field.name,
+                field_type.to_postgres_type(),
+                if element { " per element" } else { "" },
+                value.type_name(),
+                if filter {
+                    "; call allow_casts() to send it anyway"
+                } else {
+                    ""
+                }
+            ),
+            from_type: value.type_name().to_string(),
+            to_type: field_type.to_postgres_type(),
+        })
+    }
+
+    /// Field name for a column, falling back to the column itself
+    fn field_for_column<'a>(&'a self, column: &'a str) -> &'a str {
+        self.fields
+            .iter()
+            .find(|f| f.column_name() == column)
+            .map_or(column, |f| f.name.as_str())
+    }
+
+    /// Unique constraints as `(name, columns)`, including single-field ones
+    pub fn unique_constraints(&self) -> Vec<(String, Vec<String>)> {
+        let mut unique: Vec<(String, Vec<String>)> = self
+            .constraints
+            .iter()
+            .filter_map(|c| match c {
+                ConstraintMeta::Unique { name, columns } => Some((name.clone(), columns.clone())),
+                _ => None,
+            })
+            .collect();
+        unique.extend(self.fields.iter().filter(|f| f.unique).map(|f| {
+            (
+                format!("{}_{}_key", self.table, f.column_name()),
+                vec![f.column_name().to_string()],
+            )
+        }));
+        unique
+    }
+
+    /// Map a unique violation from the database onto this model's constraint
+    ///
+    /// The database reports a constraint name (PostgreSQL, MySQL) or the
+    /// columns involved (SQLite); either is matched against the declared
+    /// constraints so the error names the model's fields. Other errors are
+    /// returned unchanged.
+    pub fn resolve_unique_violation(&self, error: ChakraError) -> ChakraError {
+        let ChakraError::Query(QueryError::UniqueViolation { constraint, fields }) = &error else {
+            return error;
+        };
+
+        let unique = self.unique_constraints();
+        let matched = unique.iter().find(|(name, columns)| match constraint {
+            Some(constraint) if constraint == name => true,
+            _ => {
+                !fields.is_empty()
+                    && fields.len() == columns.len()
+                    && fields.iter().all(|f| columns.contains(f))
+            }
+        });
+
+        match matched {
+            Some((name, columns)) => ChakraError::unique_violation(
+                Some(name.clone()),
+                columns
+                    .iter()
+                    .map(|c| self.field_for_column(c).to_string())
+                    .collect(),
+            ),
+            None => error,
+        }
+    }
+
+    /// Queries that find rows colliding with `values` on a multi-column
+    /// unique constraint
+    ///
+    /// Running these before an insert lets a form report every conflict at
+    /// once. Constraints with a missing or NULL value are skipped, since NULLs
+    /// never collide. The database constraint remains the real guarantee.
+    pub fn unique_checks(&self, values: &HashMap<String, Value>) -> Vec<UniqueCheck> {
+        self.constraints
+            .iter()
+            .filter_map(|c| match c {
+                ConstraintMeta::Unique { name, columns } if columns.len() > 1 => {
+                    Some((name, columns))
+                }
+                _ => None,
+            })
+            .filter_map(|(name, columns)| {
+                let mut query = Query::select().from(&self.table).column("1").limit(1);
+                for column in columns {
+                    match values.get(column) {
+                        None | Some(Value::Null) => return None,
+                        Some(value) => query = query.where_eq(column, value.clone()),
+                    }
+                }
+                Some(UniqueCheck {
+                    constraint: name.clone(),
+                    fields: columns
+                        .iter()
+                        .map(|c| self.field_for_column(c).to_string())
+                        .collect(),
+                    query: query.build(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A pre-insert lookup for one unique constraint
+#[derive(Debug, Clone)]
+pub struct UniqueCheck {
+    /// Constraint name
+    pub constraint: String,
+    /// Fields covered by the constraint
+    pub fields: Vec<String>,
+    /// SELECT returning a row if the values are already taken
+    pub query: Query,
+}
+
+impl UniqueCheck {
+    /// The error to report when the check finds a row
+    pub fn violation(&self) -> ChakraError {
+        ChakraError::unique_violation(Some(self.constraint.clone()), self.fields.clone())
+    }
 }
 
 /// Builder for ModelMeta
@@ -99,11 +481,14 @@ impl ModelMetaBuilder {
                 name: name.into(),
                 table: table.into(),
                 schema: None,
+                database: None,
+                shard_key: None,
                 primary_key: Vec::new(),
                 fields: Vec::new(),
                 indexes: Vec::new(),
                 constraints: Vec::new(),
                 relationships: Vec::new(),
+                view: false,
             },
         }
     }
@@ -113,6 +498,16 @@ impl ModelMetaBuilder {
         self
     }
 
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.meta.database = Some(database.into());
+        self
+    }
+
+    pub fn shard_key(mut self, column: impl Into<String>) -> Self {
+        self.meta.shard_key = Some(column.into());
+        self
+    }
+
     pub fn field(mut self, field: FieldMeta) -> Self {
         if field.primary_key {
             self.meta.primary_key.push(field.name.clone());
@@ -136,6 +531,11 @@ impl ModelMetaBuilder {
         self
     }
 
+    pub fn view(mut self) -> Self {
+        self.meta.view = true;
+        self
+    }
+
     pub fn build(self) -> ModelMeta {
         self.meta
     }
@@ -164,6 +564,9 @@ pub struct FieldMeta {
     pub default: Option<FieldDefault>,
     /// Foreign key reference
     pub foreign_key: Option<ForeignKeyMeta>,
+    /// Redact values in debug SQL and records
+    #[serde(default)]
+    pub sensitive: bool,
 }
 
 impl FieldMeta {
@@ -197,6 +600,7 @@ impl FieldMetaBuilder {
                 index: false,
                 default: None,
                 foreign_key: None,
+                sensitive: false,
             },
         }
     }
@@ -251,6 +655,11 @@ impl FieldMetaBuilder {
         self
     }
 
+    pub fn sensitive(mut self) -> Self {
+        self.meta.sensitive = true;
+        self
+    }
+
     pub fn build(self) -> FieldMeta {
         self.meta
     }
@@ -267,6 +676,8 @@ pub enum FieldDefault {
     AutoIncrement,
     /// Generate UUID
     Uuid,
+    /// Generated client-side on insert
+    Generated(IdStrategy),
 }
 
 /// Foreign key metadata
@@ -309,9 +720,18 @@ impl ForeignKeyAction {
 pub struct IndexMeta {
     pub name: String,
     pub columns: Vec<String>,
+    /// Expression keys after `columns`, e.g. `lower(email)`
+    #[serde(default)]
+    pub expressions: Vec<String>,
     pub unique: bool,
     pub descending: bool,
     pub where_clause: Option<String>,
+    /// Non-key columns stored in the index (PostgreSQL `INCLUDE`)
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Storage parameters such as `fillfactor` (PostgreSQL `WITH`)
+    #[serde(default)]
+    pub storage: BTreeMap<String, String>,
 }
 
 impl IndexMeta {
@@ -319,12 +739,30 @@ impl IndexMeta {
         Self {
             name: name.into(),
             columns,
+            expressions: Vec::new(),
             unique: false,
             descending: false,
             where_clause: None,
+            include: Vec::new(),
+            storage: BTreeMap::new(),
         }
     }
 
+    pub fn expression(mut self, expression: impl Into<String>) -> Self {
+        self.expressions.push(expression.into());
+        self
+    }
+
+    pub fn include(mut self, columns: Vec<String>) -> Self {
+        self.include = columns;
+        self
+    }
+
+    pub fn storage(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage.insert(name.into(), value.into());
+        self
+    }
+
     pub fn unique(mut self) -> Self {
         self.unique = true;
         self
@@ -368,8 +806,14 @@ pub struct RelationMeta {
     pub name: String,
     pub relation_type: RelationType,
     pub target_model: String,
+    /// Column holding the link: on this model for `ManyToOne` and
+    /// `OneToOne`, on the target for `OneToMany`, and the through table's
+    /// column pointing here for `ManyToMany`
     pub foreign_key: Option<String>,
     pub through_table: Option<String>,
+    /// Through table column pointing at the target, for `ManyToMany`
+    #[serde(default)]
+    pub related_key: Option<String>,
     pub back_populates: Option<String>,
 }
 
@@ -426,6 +870,26 @@ impl<T> Related<T> {
         }
     }
 
+    /// Create a loaded relationship that may have no record, as for a
+    /// NULL foreign key
+    pub fn loaded_from(value: Option<T>) -> Self {
+        Self {
+            value,
+            loaded: true,
+        }
+    }
+
+    /// Get the value if loaded, `None` if loaded without a record
+    pub fn get_optional(&self) -> Result<Option<&T>> {
+        if self.loaded {
+            Ok(self.value.as_ref())
+        } else {
+            Err(ChakraError::Model(ModelError::RelationshipNotLoaded {
+                relationship: "unknown".to_string(),
+            }))
+        }
+    }
+
     /// Set the value
     pub fn set(&mut self, value: T) {
         self.value = Some(value);
@@ -454,6 +918,82 @@ impl<T: Clone> Clone for Related<T> {
     }
 }
 
+/// Field values as last loaded or saved, for `Model::changed_fields`
+///
+/// Add one to a model to track changes:
+///
+/// ```rust,ignore
+/// #[derive(Model)]
+/// struct User {
+///     #[chakra(primary_key)]
+///     id: i64,
+///     name: String,
+///     #[chakra(snapshot)]
+///     loaded: Snapshot,
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    values: Option<Vec<Value>>,
+}
+
+impl Snapshot {
+    /// Store `values`, one per field in `fields()` order
+    pub fn capture(&mut self, values: Vec<Value>) {
+        self.values = Some(values);
+    }
+
+    /// Forget the stored values, so every field counts as changed
+    pub fn clear(&mut self) {
+        self.values = None;
+    }
+
+    /// Whether values have been stored
+    pub fn is_captured(&self) -> bool {
+        self.values.is_some()
+    }
+
+    /// The stored value of field `index`
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.values.as_ref()?.get(index)
+    }
+
+    /// Whether field `index` differs from `value`; `None` before `capture`
+    pub fn changed(&self, index: usize, value: &Value) -> Option<bool> {
+        let values = self.values.as_ref()?;
+        Some(values.get(index) != Some(value))
+    }
+}
+
+/// A set of fields by position in `Model::fields()`, at most 64
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldMask(u64);
+
+impl FieldMask {
+    /// Maximum number of fields in a mask
+    pub const MAX_FIELDS: usize = 64;
+
+    /// Add field `index`
+    pub fn insert(&mut self, index: usize) {
+        self.0 |= 1 << index;
+    }
+
+    /// Whether field `index` is in the set
+    pub fn contains(&self, index: usize) -> bool {
+        index < Self::MAX_FIELDS && self.0 & (1 << index) != 0
+    }
+
+    /// Whether no field is in the set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The raw bitmap, bit `i` for field `i`
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+}
+
 /// Model registry for runtime model lookup
 #[derive(Debug, Default)]
 pub struct ModelRegistry {
@@ -525,6 +1065,7 @@ impl Field {
                 index: false,
                 default: None,
                 foreign_key: None,
+                sensitive: false,
             },
         }
     }
@@ -558,6 +1099,75 @@ impl Field {
         self.meta.default = Some(FieldDefault::Value(value));
         self
     }
+
+    pub fn sensitive(mut self) -> Self {
+        self.meta.sensitive = true;
+        self
+    }
+}
+
+/// Model values for logs and serialization
+///
+/// Sensitive columns read as `REDACTED` in `Debug`, `Serialize` and `values()`
+/// until `reveal_sensitive()` is called.
+#[derive(Clone)]
+pub struct Record {
+    values: HashMap<String, Value>,
+    sensitive: Vec<String>,
+    reveal: bool,
+}
+
+impl Record {
+    /// Wrap values, redacting the given columns
+    pub fn new(values: HashMap<String, Value>, sensitive: Vec<String>) -> Self {
+        Self {
+            values,
+            sensitive,
+            reveal: false,
+        }
+    }
+
+    /// Show sensitive values as they are
+    pub fn reveal_sensitive(mut self) -> Self {
+        self.reveal = true;
+        self
+    }
+
+    /// Get a value by column, redacted if sensitive
+    pub fn get(&self, column: &str) -> Option<Value> {
+        self.values.get(column).map(|v| self.visible(column, v))
+    }
+
+    /// All values ordered by column, sensitive ones redacted
+    pub fn values(&self) -> BTreeMap<String, Value> {
+        self.values
+            .iter()
+            .map(|(column, v)| (column.clone(), self.visible(column, v)))
+            .collect()
+    }
+
+    fn visible(&self, column: &str, value: &Value) -> Value {
+        if !self.reveal && !value.is_null() && self.sensitive.iter().any(|c| c == column) {
+            Value::String(REDACTED.to_string())
+        } else {
+            value.clone()
+        }
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.values()).finish()
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.values().serialize(serializer)
+    }
 }
 
 #[cfg(test)]
@@ -574,10 +1184,7 @@ mod tests {
                     .auto_increment()
                     .build(),
             )
-            .field(
-                FieldMeta::builder("name", FieldType::string(100))
-                    .build(),
-            )
+            .field(FieldMeta::builder("name", FieldType::string(100)).build())
             .build();
 
         assert_eq!(meta.name, "User");
@@ -587,6 +1194,131 @@ mod tests {
         assert_eq!(meta.primary_key, vec!["id"]);
     }
 
+    #[test]
+    fn test_check_param_types() {
+        let meta = ModelMeta::builder("Account", "accounts")
+            .field(FieldMeta::builder("age", FieldType::Integer).build())
+            .field(FieldMeta::builder("scores", FieldType::array(FieldType::Integer)).build())
+            .build();
+        let check = |filter: Expr| {
+            let query = Query::select().from("accounts").filter(filter).build();
+            meta.check_param_types(&query)
+        };
+
+        assert!(check(Expr::eq("age", 42i64)).is_ok());
+        assert!(check(Expr::eq("accounts.age", Value::Null)).is_ok());
+        assert!(check(Expr::array_contains("scores", vec![10])).is_ok());
+        // Other tables' columns are left to the database
+        assert!(check(Expr::eq("owners.age", "x")).is_ok());
+
+        let err = check(Expr::eq("age", "42")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type conversion error: Account.age is INTEGER, but the query binds a string value \
+             to it; call allow_casts() to send it anyway"
+        );
+        assert!(check(Expr::eq("name", "x").and(Expr::is_in("accounts.age", vec!["1"]))).is_err());
+        assert!(check(Expr::array_overlaps("scores", vec!["10"])).is_err());
+
+        // Values written by INSERT and UPDATE
+        let insert = |age: Value| {
+            let row = HashMap::from([("age".to_string(), age)]);
+            Query::insert().table("accounts").values(row).build()
+        };
+        assert!(meta.check_param_types(&insert(Value::Int32(1))).is_ok());
+        assert!(meta.check_param_types(&insert(Value::Null)).is_ok());
+        let err = meta.check_param_types(&insert("1".into())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type conversion error: Account.age is INTEGER, but the query binds a string value \
+             to it"
+        );
+        let update = Query::update().table("accounts").set("age", "x").build();
+        assert!(meta.check_param_types(&update).is_err());
+        let update = Query::update()
+            .table("accounts")
+            .set_expr("age", Expr::Value("x".into()))
+            .build();
+        assert!(meta.check_param_types(&update).is_err());
+    }
+
+    #[test]
+    fn test_unique_violation_mapping() {
+        let meta = ModelMeta::builder("Page", "pages")
+            .field(
+                FieldMeta::builder("tenant", FieldType::BigInt)
+                    .column("tenant_id")
+                    .build(),
+            )
+            .field(FieldMeta::builder("slug", FieldType::String { max_length: None }).build())
+            .field(
+                FieldMeta::builder("email", FieldType::String { max_length: None })
+                    .unique()
+                    .build(),
+            )
+            .constraint(ConstraintMeta::Unique {
+                name: "uq_tenant_slug".to_string(),
+                columns: vec!["tenant_id".to_string(), "slug".to_string()],
+            })
+            .build();
+
+        // PostgreSQL and MySQL report the constraint name
+        let err = meta.resolve_unique_violation(ChakraError::unique_violation(
+            Some("uq_tenant_slug".into()),
+            vec![],
+        ));
+        assert_eq!(err.unique_violation_fields().unwrap(), ["tenant", "slug"]);
+
+        // SQLite reports the columns
+        let err = meta
+            .resolve_unique_violation(ChakraError::unique_violation(None, vec!["email".into()]));
+        assert_eq!(err.unique_violation_fields().unwrap(), ["email"]);
+        assert!(err.to_string().contains("field: email"));
+
+        let mut values = HashMap::new();
+        values.insert("tenant_id".to_string(), Value::Int64(1));
+        assert!(meta.unique_checks(&values).is_empty());
+
+        values.insert("slug".to_string(), Value::String("home".into()));
+        let checks = meta.unique_checks(&values);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].query.limit, Some(1));
+        assert!(checks[0].violation().is_unique_violation());
+    }
+
+    #[test]
+    fn test_sensitive_record() {
+        let meta = ModelMeta::builder("User", "users")
+            .field(
+                FieldMeta::builder("id", FieldType::BigInt)
+                    .primary_key()
+                    .build(),
+            )
+            .field(
+                FieldMeta::builder("ssn", FieldType::Text)
+                    .column("tax_id")
+                    .sensitive()
+                    .build(),
+            )
+            .build();
+        assert_eq!(meta.sensitive_columns(), ["tax_id"]);
+
+        let mut values = HashMap::new();
+        values.insert("id".to_string(), Value::Int64(7));
+        values.insert("tax_id".to_string(), Value::String("123-45-6789".into()));
+        let record = Record::new(values, meta.sensitive_columns());
+
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"id":7,"tax_id":"[REDACTED]"}"#
+        );
+        assert!(!format!("{:?}", record).contains("6789"));
+        assert_eq!(
+            record.reveal_sensitive().get("tax_id"),
+            Some(Value::String("123-45-6789".into()))
+        );
+    }
+
     #[test]
     fn test_related() {
         let mut rel: Related<Vec<i32>> = Related::new();
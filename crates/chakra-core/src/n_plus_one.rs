@@ -0,0 +1,291 @@
+//! N+1 query detection
+//!
+//! Loading a relationship row by row runs the same query once per row: one
+//! query for the posts, then N for their authors. `NPlusOneDetector` wraps
+//! an executor and counts the queries run in each `QueryScope`, such as one
+//! web request, by fingerprint. When one passes the threshold it logs a
+//! warning under the `chakra::n_plus_one` target, once per scope:
+//!
+//! ```rust,ignore
+//! let executor = NPlusOneDetector::new(executor).threshold(5);
+//!
+//! QueryScope::run("GET /posts", async {
+//!     for post in Post::objects().all(&executor).await? {
+//!         // the 6th of these warns: use select_related("author")
+//!         let author = User::get(&executor, post.author_id).await?;
+//!     }
+//!     Ok(())
+//! })
+//! .await
+//! ```
+//!
+//! The warning carries the scope, the count and the query's fingerprint,
+//! plus a backtrace of the query that crossed the threshold when
+//! `RUST_BACKTRACE` is set. Only reads are counted, and nothing is recorded
+//! outside a scope, so the detector costs little when left in production.
+
+use crate::error::Result;
+use crate::executor::{ExecResult, Executor};
+use crate::format::fingerprint;
+use crate::query::Query;
+use crate::result::Row;
+use crate::sql::Dialect;
+use crate::sql_cache::SqlCache;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Runs of one query allowed in a scope before warning, unless set otherwise
+pub const DEFAULT_THRESHOLD: usize = 10;
+
+tokio::task_local! {
+    static SCOPE: Arc<ScopeState>;
+}
+
+/// Queries run so far in one scope
+struct ScopeState {
+    label: String,
+    counts: Mutex<HashMap<String, usize>>,
+}
+
+/// A logical unit of work, such as a request, whose queries are counted
+pub struct QueryScope;
+
+impl QueryScope {
+    /// Run `future` as a scope named `label`
+    pub async fn run<F: Future>(label: impl Into<String>, future: F) -> F::Output {
+        SCOPE.scope(ScopeState::new(label), future).await
+    }
+
+    /// Run `f` as a scope named `label`, for synchronous code
+    pub fn sync_run<R>(label: impl Into<String>, f: impl FnOnce() -> R) -> R {
+        SCOPE.sync_scope(ScopeState::new(label), f)
+    }
+
+    /// The queries run so far in the current scope, most frequent first, or
+    /// nothing outside a scope
+    pub fn report() -> Vec<QueryCount> {
+        let Ok(state) = SCOPE.try_with(Arc::clone) else {
+            return Vec::new();
+        };
+        let counts = state.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut report: Vec<QueryCount> = counts
+            .iter()
+            .map(|(fingerprint, count)| QueryCount {
+                fingerprint: fingerprint.clone(),
+                count: *count,
+            })
+            .collect();
+        report.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+        report
+    }
+}
+
+impl ScopeState {
+    fn new(label: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            label: label.into(),
+            counts: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+/// How many times a query ran in a scope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryCount {
+    /// The query with its values replaced, see `format::fingerprint`
+    pub fingerprint: String,
+    pub count: usize,
+}
+
+/// An executor that counts its reads per `QueryScope` and warns about
+/// queries repeated past a threshold
+pub struct NPlusOneDetector<E> {
+    inner: E,
+    threshold: usize,
+}
+
+impl<E: Executor> NPlusOneDetector<E> {
+    /// Wrap `inner`, warning past `DEFAULT_THRESHOLD` runs
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Warn once a query runs more than `threshold` times in a scope
+    pub fn threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// The wrapped executor
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Count a read in the current scope, if any
+    fn record(&self, sql: &str) {
+        let Ok(state) = SCOPE.try_with(Arc::clone) else {
+            return;
+        };
+        let fingerprint = fingerprint(sql);
+        let count = {
+            let mut counts = state.counts.lock().unwrap_or_else(|e| e.into_inner());
+            let count = counts.entry(fingerprint.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if count == self.threshold + 1 {
+            let backtrace = Backtrace::capture();
+            let backtrace = match backtrace.status() {
+                BacktraceStatus::Captured => format!("\n{}", backtrace),
+                _ => String::new(),
+            };
+            warn!(
+                target: "chakra::n_plus_one",
+                scope = %state.label,
+                count,
+                query = %fingerprint,
+                "Possible N+1: the same query ran more than {} times in {}; load the rows together \
+                 with select_related, prefetch_related or in_bulk{}",
+                self.threshold,
+                state.label,
+                backtrace
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl<E: Executor> Executor for NPlusOneDetector<E> {
+    fn dialect(&self) -> &dyn Dialect {
+        self.inner.dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.record(sql);
+        self.inner.query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.inner.execute(sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        self.inner.execute_with_id(sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        self.inner.execute_many(sql, params).await
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        self.inner.execute_returning(query).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        self.record(&sql);
+        self.inner.stream(sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.inner.max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.inner.sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        self.inner.read_only()
+    }
+
+    /// Reads on the pinned connection are counted in the same scope
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        Ok(self.inner.pin().await?.map(|conn| {
+            Box::new(NPlusOneDetector::new(conn).threshold(self.threshold))
+                as Box<dyn Executor + '_>
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::PostgresDialect;
+
+    struct Empty;
+
+    #[async_trait]
+    impl Executor for Empty {
+        fn dialect(&self) -> &dyn Dialect {
+            &PostgresDialect
+        }
+
+        async fn query(&self, _sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            Ok(Vec::new())
+        }
+
+        async fn execute(&self, _sql: &str, _params: &[Value]) -> Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_scope() {
+        let executor = NPlusOneDetector::new(Empty).threshold(2);
+
+        // Outside a scope nothing is counted
+        executor
+            .query("SELECT * FROM users WHERE id = $1", &[])
+            .await
+            .unwrap();
+        assert!(QueryScope::report().is_empty());
+
+        let report = QueryScope::run("GET /posts", async {
+            executor.query("SELECT * FROM posts", &[]).await.unwrap();
+            for id in 0..3 {
+                let sql = format!("SELECT * FROM users WHERE id = {}", id);
+                executor.query(&sql, &[]).await.unwrap();
+            }
+            executor
+                .execute("UPDATE posts SET views = views + 1", &[])
+                .await
+                .unwrap();
+            QueryScope::report()
+        })
+        .await;
+        assert_eq!(
+            report,
+            vec![
+                QueryCount {
+                    fingerprint: "SELECT * FROM users WHERE id = ?".to_string(),
+                    count: 3,
+                },
+                QueryCount {
+                    fingerprint: "SELECT * FROM posts".to_string(),
+                    count: 1,
+                },
+            ]
+        );
+
+        // Each scope starts from zero
+        let report = QueryScope::run("GET /users", async {
+            executor.query("SELECT * FROM posts", &[]).await.unwrap();
+            QueryScope::report()
+        })
+        .await;
+        assert_eq!(report[0].count, 1);
+    }
+}
@@ -0,0 +1,208 @@
+//! Network address types
+//!
+//! IP addresses are `std::net::IpAddr`; this module adds the network and
+//! hardware address types PostgreSQL stores natively as `cidr` and
+//! `macaddr`. Other databases store all three as text.
+
+use crate::error::{ChakraError, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IP network: an address and the length of its network prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Create a network, failing if `prefix` is longer than the address
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        if prefix > max_prefix(&addr) {
+            return Err(ChakraError::internal(format!(
+                "prefix /{} is too long for {}",
+                prefix, addr
+            )));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    /// The network address
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Length of the network prefix in bits
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether the prefix covers the whole address, naming a single host
+    pub fn is_host(&self) -> bool {
+        self.prefix == max_prefix(&self.addr)
+    }
+
+    /// Whether `addr` is inside this network
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Bits in an address of `addr`'s family
+fn max_prefix(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(addr: IpAddr) -> Self {
+        Self {
+            prefix: max_prefix(&addr),
+            addr,
+        }
+    }
+}
+
+/// `10.0.0.0/8`; a bare address is a single-host network
+impl FromStr for Cidr {
+    type Err = ChakraError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ChakraError::internal(format!("invalid network address '{}'", s));
+        match s.split_once('/') {
+            Some((addr, prefix)) => Self::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix.parse().map_err(|_| invalid())?,
+            ),
+            None => s.parse::<IpAddr>().map(Self::from).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// A 48-bit MAC address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// The address's bytes
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+/// `08:00:2b:01:02:03`, also with `-` separators
+impl FromStr for MacAddr {
+    type Err = ChakraError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ChakraError::internal(format!("invalid MAC address '{}'", s));
+        let mut octets = [0u8; 6];
+        let mut parts = s.split([':', '-']);
+        for octet in &mut octets {
+            let part = parts.next().filter(|p| p.len() == 2).ok_or_else(invalid)?;
+            *octet = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self(octets))
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Both types serialize as their text form
+macro_rules! serde_as_string {
+    ($ty:ty) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+serde_as_string!(Cidr);
+serde_as_string!(MacAddr);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert_eq!(net.prefix(), 16);
+        assert_eq!(net.to_string(), "10.1.0.0/16");
+        assert!(net.contains("10.1.200.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let host: Cidr = "2001:db8::1".parse().unwrap();
+        assert!(host.is_host());
+        assert_eq!(host.to_string(), "2001:db8::1/128");
+        assert!("0.0.0.0/0"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_mac_addr() {
+        let mac: MacAddr = "08-00-2B-01-02-03".parse().unwrap();
+        assert_eq!(mac.octets(), [0x08, 0x00, 0x2b, 0x01, 0x02, 0x03]);
+        assert_eq!(mac.to_string(), "08:00:2b:01:02:03");
+        assert_eq!(
+            serde_json::to_string(&mac).unwrap(),
+            "\"08:00:2b:01:02:03\""
+        );
+
+        assert!("08:00:2b:01:02".parse::<MacAddr>().is_err());
+        assert!("08:00:2b:01:02:03:04".parse::<MacAddr>().is_err());
+        assert!("080:0:2b:01:02:03".parse::<MacAddr>().is_err());
+    }
+}
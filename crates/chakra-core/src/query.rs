@@ -2,13 +2,14 @@
 //!
 //! This module provides a fluent API for building SQL queries.
 
+use crate::error::{ChakraError, QueryError, Result};
 use crate::expr::Expr;
-use crate::types::Value;
+use crate::types::{FieldType, Value};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Sort order
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum Order {
     Asc,
     Desc,
@@ -24,12 +25,13 @@ impl Order {
 }
 
 /// Join type
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum JoinType {
     Inner,
     Left,
     Right,
     Full,
+    Cross,
 }
 
 impl JoinType {
@@ -39,6 +41,7 @@ impl JoinType {
             JoinType::Left => "LEFT JOIN",
             JoinType::Right => "RIGHT JOIN",
             JoinType::Full => "FULL OUTER JOIN",
+            JoinType::Cross => "CROSS JOIN",
         }
     }
 }
@@ -50,6 +53,57 @@ pub struct Join {
     pub table: String,
     pub alias: Option<String>,
     pub on: Expr,
+    /// Set-returning function joined instead of `table`
+    #[serde(default)]
+    pub function: Option<TableFunction>,
+}
+
+/// A set-returning function used as a table, e.g. `unnest($1) AS t(id)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableFunction {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub alias: Option<String>,
+    /// Column aliases, e.g. `t (id, position)`
+    pub columns: Vec<String>,
+    /// Append a row number column with `WITH ORDINALITY`
+    pub with_ordinality: bool,
+}
+
+impl TableFunction {
+    /// Call `name` with the given arguments
+    pub fn new(name: impl Into<String>, args: Vec<Expr>) -> Self {
+        Self {
+            name: name.into(),
+            args,
+            alias: None,
+            columns: Vec::new(),
+            with_ordinality: false,
+        }
+    }
+
+    /// `unnest($1)` over an array parameter
+    pub fn unnest(values: impl Into<Value>) -> Self {
+        Self::new("unnest", vec![Expr::Value(values.into())])
+    }
+
+    /// Set the table alias
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    /// Name the function's output columns
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Number the rows with an extra `bigint` column
+    pub fn with_ordinality(mut self) -> Self {
+        self.with_ordinality = true;
+        self
+    }
 }
 
 /// Order by clause
@@ -58,22 +112,189 @@ pub struct OrderBy {
     pub column: String,
     pub order: Order,
     pub nulls: Option<NullsOrder>,
+    /// Expression sorted by instead of `column`, e.g. `LOWER(name)`
+    #[serde(default)]
+    pub expr: Option<Expr>,
 }
 
 /// Nulls ordering
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum NullsOrder {
     First,
     Last,
 }
 
+/// Grouping sets added to GROUP BY after its plain columns
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
+pub enum GroupingSets {
+    /// `ROLLUP (a, b)`: groups `(a, b)`, `(a)` and the grand total
+    Rollup(Vec<String>),
+    /// `CUBE (a, b)`: groups for every subset of the columns
+    Cube(Vec<String>),
+    /// `GROUPING SETS ((a, b), (b), ())`
+    Sets(Vec<Vec<String>>),
+}
+
+impl GroupingSets {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            GroupingSets::Rollup(_) => "ROLLUP",
+            GroupingSets::Cube(_) => "CUBE",
+            GroupingSets::Sets(_) => "GROUPING SETS",
+        }
+    }
+}
+
+/// A common table expression in a `WITH` clause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cte {
+    pub name: String,
+    /// Optional column list, e.g. `tree (id, depth)`
+    pub columns: Vec<String>,
+    pub query: Box<Query>,
+    /// Recursive term joined to `query` with `UNION ALL`
+    pub recursive_term: Option<Box<Query>>,
+}
+
+/// Conflict handling for INSERT (`ON CONFLICT` / `ON DUPLICATE KEY UPDATE`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnConflict {
+    /// Conflict target columns; MySQL ignores these and uses any unique key
+    pub target: Vec<String>,
+    pub action: ConflictAction,
+}
+
+/// What to do with a conflicting row
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ConflictAction {
+    DoNothing,
+    DoUpdate(Vec<ConflictUpdate>),
+}
+
+/// An assignment in `DO UPDATE SET`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ConflictUpdate {
+    /// Take the value from the row that failed to insert
+    Excluded(String),
+    /// Set a fixed value
+    Value(String, Value),
+}
+
+impl ConflictUpdate {
+    /// Get the assigned column
+    pub fn column(&self) -> &str {
+        match self {
+            ConflictUpdate::Excluded(column) | ConflictUpdate::Value(column, _) => column,
+        }
+    }
+}
+
+impl OnConflict {
+    /// Handle conflicts on the given columns, doing nothing by default
+    pub fn columns(columns: &[&str]) -> Self {
+        Self {
+            target: columns.iter().map(|s| s.to_string()).collect(),
+            action: ConflictAction::DoNothing,
+        }
+    }
+
+    /// Handle conflicts on any unique constraint
+    ///
+    /// PostgreSQL only allows this with `do_nothing`; updating needs the
+    /// conflict columns.
+    pub fn any() -> Self {
+        Self::columns(&[])
+    }
+
+    /// Skip conflicting rows
+    pub fn do_nothing(mut self) -> Self {
+        self.action = ConflictAction::DoNothing;
+        self
+    }
+
+    /// Overwrite the given columns with the values being inserted
+    pub fn update(mut self, columns: &[&str]) -> Self {
+        for column in columns {
+            self.push_update(ConflictUpdate::Excluded(column.to_string()));
+        }
+        self
+    }
+
+    /// Set a column to a fixed value on conflict
+    pub fn set(mut self, column: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.push_update(ConflictUpdate::Value(column.into(), value.into()));
+        self
+    }
+
+    fn push_update(&mut self, update: ConflictUpdate) {
+        match &mut self.action {
+            ConflictAction::DoUpdate(updates) => updates.push(update),
+            ConflictAction::DoNothing => self.action = ConflictAction::DoUpdate(vec![update]),
+        }
+    }
+}
+
+/// A multi-row UPDATE giving each row its own values, matched on one key
+/// column
+#[derive(Debug, Clone)]
+pub struct BulkUpdate {
+    pub table: String,
+    pub key: String,
+    pub key_type: FieldType,
+    /// Updated columns, with their types for dialects that need casts
+    pub columns: Vec<(String, FieldType)>,
+    /// Per row: the key value, then one value per column
+    pub rows: Vec<Vec<Value>>,
+    /// Extra `column = value` conditions every updated row must meet
+    pub conditions: Vec<(String, Value)>,
+}
+
+impl BulkUpdate {
+    /// Split so each statement binds at most `max_params` values, assuming
+    /// the key and each value may be bound twice
+    pub fn chunks(&self, max_params: usize) -> Vec<BulkUpdate> {
+        let per_row = 2 * self.columns.len() + 1;
+        let rows = (max_params.saturating_sub(self.conditions.len()) / per_row).max(1);
+        self.rows
+            .chunks(rows)
+            .map(|chunk| BulkUpdate {
+                rows: chunk.to_vec(),
+                ..self.clone()
+            })
+            .collect()
+    }
+}
+
 /// Query type
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub enum QueryType {
     Select,
     Insert,
     Update,
     Delete,
+    Truncate,
+}
+
+/// Options for TRUNCATE
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash, Eq, Serialize, Deserialize)]
+pub struct TruncateOptions {
+    /// Also truncate tables with foreign keys to this one (Postgres)
+    pub cascade: bool,
+    /// Reset sequences owned by the table's columns (Postgres)
+    pub restart_identity: bool,
+}
+
+/// Version of the JSON wire format written by `Query::to_json`
+///
+/// Bumped whenever a change to `Query` or `Expr` would make an older reader
+/// misread a payload. Fields added with a serde default don't bump it.
+pub const QUERY_JSON_VERSION: u32 = 1;
+
+/// A query as sent over the wire: `{"version": 1, "query": {...}}`
+#[derive(Serialize, Deserialize)]
+struct QueryEnvelope<Q> {
+    version: u32,
+    query: Q,
 }
 
 /// A complete query representation
@@ -83,20 +304,150 @@ pub struct Query {
     pub table: String,
     pub alias: Option<String>,
     pub columns: Vec<String>,
+    /// Computed columns selected after `columns`, as `expr AS alias`
+    #[serde(default)]
+    pub select_exprs: Vec<(Expr, String)>,
     pub values: Vec<HashMap<String, Value>>,
     pub where_clause: Option<Expr>,
     pub joins: Vec<Join>,
     pub order_by: Vec<OrderBy>,
     pub group_by: Vec<String>,
+    /// ROLLUP, CUBE or GROUPING SETS following `group_by`
+    #[serde(default)]
+    pub grouping_sets: Option<GroupingSets>,
     pub having: Option<Expr>,
     pub limit: Option<usize>,
     pub offset: Option<usize>,
     pub distinct: bool,
     pub returning: Vec<String>,
+    /// Conflict handling for INSERT
+    #[serde(default)]
+    pub on_conflict: Option<OnConflict>,
+    /// Row source for `INSERT ... SELECT`, used instead of `values`
+    #[serde(default)]
+    pub source: Option<Box<Query>>,
+    /// UPDATE assignments computed from expressions, e.g. other columns
+    #[serde(default)]
+    pub set_exprs: Vec<(String, Expr)>,
+    /// Extra tables joined into an UPDATE
+    #[serde(default)]
+    pub update_from: Vec<String>,
+    /// Columns identifying a row, which pick the rows of a bounded UPDATE
+    /// or DELETE instead of the dialect's row locator
+    #[serde(default)]
+    pub key_columns: Vec<String>,
+    /// Common table expressions, rendered as `WITH` before the statement
+    #[serde(default)]
+    pub ctes: Vec<Cte>,
+    /// Set-returning function selected from instead of `table`
+    #[serde(default)]
+    pub from_function: Option<TableFunction>,
     pub for_update: bool,
+    /// Exempt this query from the executor's row guard
+    #[serde(default)]
+    pub unlimited: bool,
+    /// sqlcommenter tags prepended to the generated statement
+    #[serde(default)]
+    pub comments: BTreeMap<String, String>,
+    /// Columns whose bound values are redacted in debug SQL
+    #[serde(default)]
+    pub sensitive: Vec<String>,
+    /// CASCADE and RESTART IDENTITY for TRUNCATE
+    #[serde(default)]
+    pub truncate: TruncateOptions,
+    /// Create `table` as a temporary table holding the rows of `source`
+    #[serde(default)]
+    pub temp_table: bool,
 }
 
 impl Query {
+    /// Whether running the query only reads: a SELECT that takes no row
+    /// locks, creates no temp table and has no writing CTEs
+    pub fn is_read_only(&self) -> bool {
+        self.query_type == QueryType::Select
+            && !self.for_update
+            && !self.temp_table
+            && self.ctes.iter().all(|cte| {
+                cte.query.is_read_only()
+                    && cte.recursive_term.as_ref().is_none_or(|r| r.is_read_only())
+            })
+    }
+
+    /// Call `f` on every expression in the query and its subqueries
+    pub fn walk_exprs(&self, f: &mut dyn FnMut(&Expr)) {
+        for cte in &self.ctes {
+            cte.query.walk_exprs(f);
+            if let Some(recursive) = &cte.recursive_term {
+                recursive.walk_exprs(f);
+            }
+        }
+        if let Some(source) = &self.source {
+            source.walk_exprs(f);
+        }
+        let functions = self
+            .from_function
+            .iter()
+            .chain(self.joins.iter().filter_map(|j| j.function.as_ref()));
+        for function in functions {
+            function.args.iter().for_each(|arg| arg.walk(f));
+        }
+        for join in &self.joins {
+            join.on.walk(f);
+        }
+        for (expr, _) in &self.select_exprs {
+            expr.walk(f);
+        }
+        for (_, expr) in &self.set_exprs {
+            expr.walk(f);
+        }
+        if let Some(expr) = &self.where_clause {
+            expr.walk(f);
+        }
+        if let Some(expr) = &self.having {
+            expr.walk(f);
+        }
+        for expr in self.order_by.iter().filter_map(|o| o.expr.as_ref()) {
+            expr.walk(f);
+        }
+    }
+
+    /// Like `walk_exprs`, but mutable
+    pub fn walk_exprs_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        for cte in &mut self.ctes {
+            cte.query.walk_exprs_mut(f);
+            if let Some(recursive) = &mut cte.recursive_term {
+                recursive.walk_exprs_mut(f);
+            }
+        }
+        if let Some(source) = &mut self.source {
+            source.walk_exprs_mut(f);
+        }
+        if let Some(function) = &mut self.from_function {
+            function.args.iter_mut().for_each(|arg| arg.walk_mut(f));
+        }
+        for join in &mut self.joins {
+            if let Some(function) = &mut join.function {
+                function.args.iter_mut().for_each(|arg| arg.walk_mut(f));
+            }
+            join.on.walk_mut(f);
+        }
+        for (expr, _) in &mut self.select_exprs {
+            expr.walk_mut(f);
+        }
+        for (_, expr) in &mut self.set_exprs {
+            expr.walk_mut(f);
+        }
+        if let Some(expr) = &mut self.where_clause {
+            expr.walk_mut(f);
+        }
+        if let Some(expr) = &mut self.having {
+            expr.walk_mut(f);
+        }
+        for expr in self.order_by.iter_mut().filter_map(|o| o.expr.as_mut()) {
+            expr.walk_mut(f);
+        }
+    }
+
     /// Create a new SELECT query builder
     pub fn select() -> QueryBuilder {
         QueryBuilder::new(QueryType::Select)
@@ -116,6 +467,196 @@ impl Query {
     pub fn delete() -> QueryBuilder {
         QueryBuilder::new(QueryType::Delete)
     }
+
+    /// Create a TRUNCATE query builder for a table
+    ///
+    /// SQLite has no TRUNCATE and gets `DELETE FROM table` instead.
+    pub fn truncate(table: impl Into<String>) -> QueryBuilder {
+        QueryBuilder::new(QueryType::Truncate).table(table)
+    }
+
+    /// Serialize to the versioned JSON wire format
+    pub fn to_json(&self) -> Result<String> {
+        let envelope = QueryEnvelope {
+            version: QUERY_JSON_VERSION,
+            query: self,
+        };
+        serde_json::to_string(&envelope).map_err(|e| invalid_json(e.to_string()))
+    }
+
+    /// Read a query written by `to_json`, rejecting other format versions
+    /// and queries the builder could not have produced
+    pub fn from_json(json: &str) -> Result<Query> {
+        let envelope: QueryEnvelope<serde_json::Value> =
+            serde_json::from_str(json).map_err(|e| invalid_json(e.to_string()))?;
+        if envelope.version != QUERY_JSON_VERSION {
+            return Err(invalid_json(format!(
+                "unsupported version {}, expected {}",
+                envelope.version, QUERY_JSON_VERSION
+            )));
+        }
+        let query: Query =
+            serde_json::from_value(envelope.query).map_err(|e| invalid_json(e.to_string()))?;
+        query.check_shape()?;
+        Ok(query)
+    }
+
+    /// Check the invariants the builder guarantees, for this query and every
+    /// query nested in it
+    fn check_shape(&self) -> Result<()> {
+        if self.table.is_empty() && self.from_function.is_none() {
+            return Err(invalid_json("query has no table".to_string()));
+        }
+        match self.query_type {
+            QueryType::Insert if self.values.is_empty() && self.source.is_none() => {
+                return Err(invalid_json("INSERT has no values or source".to_string()));
+            }
+            QueryType::Update if self.values.is_empty() && self.set_exprs.is_empty() => {
+                return Err(invalid_json("UPDATE has no assignments".to_string()));
+            }
+            _ => {}
+        }
+        for cte in &self.ctes {
+            cte.query.check_shape()?;
+            if let Some(recursive) = &cte.recursive_term {
+                recursive.check_shape()?;
+            }
+        }
+        if let Some(source) = &self.source {
+            source.check_shape()?;
+        }
+        let mut result = Ok(());
+        self.walk_exprs(&mut |expr| {
+            let subquery = match expr {
+                Expr::Subquery(query)
+                | Expr::Exists { query, .. }
+                | Expr::InSubquery { query, .. }
+                | Expr::SubqueryCompare { query, .. } => query,
+                _ => return,
+            };
+            if result.is_ok() {
+                result = subquery.check_shape();
+            }
+        });
+        result
+    }
+
+    /// Stage the rows of `query` in a new temporary table, visible only to
+    /// this session: `CREATE TEMPORARY TABLE table AS SELECT ...`
+    ///
+    /// The table's columns are the SELECT's. It is dropped when the session
+    /// ends, or earlier with `TempTable::drop`.
+    pub fn insert_into_temp(table: impl Into<String>, query: Query) -> QueryBuilder {
+        QueryBuilder::new(QueryType::Insert)
+            .table(table)
+            .from_select(query)
+            .temp_table()
+    }
+
+    /// Apply a row guard LIMIT to an unbounded SELECT
+    ///
+    /// Returns `true` if the limit was applied. Queries marked `unlimited`,
+    /// queries with an explicit LIMIT and non-SELECT queries are left alone.
+    pub fn apply_row_guard(&mut self, max_rows: usize) -> bool {
+        if self.query_type != QueryType::Select || self.limit.is_some() || self.unlimited {
+            return false;
+        }
+
+        tracing::warn!(
+            table = %self.table,
+            max_rows,
+            "Unbounded SELECT limited by max_rows_guard; call .unlimited() to opt out"
+        );
+        self.limit = Some(max_rows);
+        true
+    }
+
+    /// Columns of a multi-row INSERT, in first-seen order
+    pub fn insert_columns(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = Vec::new();
+        for row in &self.values {
+            let mut extra: Vec<&str> = row
+                .keys()
+                .map(String::as_str)
+                .filter(|c| !columns.contains(c))
+                .collect();
+            // Each row adds its new columns in a stable order, so equal
+            // rows always render the same SQL whatever the map's order
+            extra.sort_unstable();
+            columns.extend(extra);
+        }
+        columns
+    }
+
+    /// Split an INSERT so each statement binds at most `max_params` values
+    ///
+    /// Every chunk keeps at least one row; non-INSERT queries are returned as is.
+    pub fn insert_chunks(&self, max_params: usize) -> Vec<Query> {
+        if self.query_type != QueryType::Insert || self.values.len() <= 1 {
+            return vec![self.clone()];
+        }
+
+        let columns = self.insert_columns().len().max(1);
+        let fixed = match &self.on_conflict {
+            Some(OnConflict {
+                action: ConflictAction::DoUpdate(updates),
+                ..
+            }) => updates
+                .iter()
+                .filter(|u| matches!(u, ConflictUpdate::Value(..)))
+                .count(),
+            _ => 0,
+        };
+        let rows = (max_params.saturating_sub(fixed) / columns).max(1);
+
+        self.values
+            .chunks(rows)
+            .map(|chunk| Query {
+                values: chunk.to_vec(),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Split a multi-row INSERT into runs of consecutive rows that set the
+    /// same columns, for databases that can't write `DEFAULT` for a value a
+    /// row leaves out
+    ///
+    /// Rows that set no columns at all are inserted one by one.
+    pub fn insert_groups(&self) -> Vec<Query> {
+        if self.query_type != QueryType::Insert || self.values.len() <= 1 {
+            return vec![self.clone()];
+        }
+
+        let same_columns = |a: &HashMap<String, Value>, b: &HashMap<String, Value>| {
+            !a.is_empty() && a.len() == b.len() && a.keys().all(|k| b.contains_key(k))
+        };
+        let mut groups: Vec<Query> = Vec::new();
+        for row in &self.values {
+            match groups.last_mut() {
+                Some(group) if same_columns(&group.values[0], row) => {
+                    group.values.push(row.clone())
+                }
+                _ => groups.push(Query {
+                    values: vec![row.clone()],
+                    ..self.clone()
+                }),
+            }
+        }
+        groups
+    }
+
+    /// Whether a row of a multi-row INSERT leaves out a column another sets
+    pub fn has_missing_values(&self) -> bool {
+        let columns = self.insert_columns().len();
+        self.values.iter().any(|row| row.len() < columns)
+    }
+}
+
+fn invalid_json(reason: String) -> ChakraError {
+    ChakraError::Query(QueryError::Invalid {
+        message: format!("Invalid query JSON: {}", reason),
+    })
 }
 
 /// Fluent query builder
@@ -125,17 +666,32 @@ pub struct QueryBuilder {
     table: Option<String>,
     alias: Option<String>,
     columns: Vec<String>,
+    select_exprs: Vec<(Expr, String)>,
     values: Vec<HashMap<String, Value>>,
     where_clauses: Vec<Expr>,
+    scope: Option<Expr>,
     joins: Vec<Join>,
     order_by: Vec<OrderBy>,
     group_by: Vec<String>,
+    grouping_sets: Option<GroupingSets>,
     having: Option<Expr>,
     limit: Option<usize>,
     offset: Option<usize>,
     distinct: bool,
     returning: Vec<String>,
+    on_conflict: Option<OnConflict>,
+    source: Option<Query>,
+    set_exprs: Vec<(String, Expr)>,
+    update_from: Vec<String>,
+    key_columns: Vec<String>,
+    ctes: Vec<Cte>,
+    from_function: Option<TableFunction>,
     for_update: bool,
+    unlimited: bool,
+    comments: BTreeMap<String, String>,
+    sensitive: Vec<String>,
+    truncate: TruncateOptions,
+    temp_table: bool,
 }
 
 impl QueryBuilder {
@@ -146,17 +702,32 @@ impl QueryBuilder {
             table: None,
             alias: None,
             columns: Vec::new(),
+            select_exprs: Vec::new(),
             values: Vec::new(),
             where_clauses: Vec::new(),
+            scope: None,
             joins: Vec::new(),
             order_by: Vec::new(),
             group_by: Vec::new(),
+            grouping_sets: None,
             having: None,
             limit: None,
             offset: None,
             distinct: false,
             returning: Vec::new(),
+            on_conflict: None,
+            source: None,
+            set_exprs: Vec::new(),
+            update_from: Vec::new(),
+            key_columns: Vec::new(),
+            ctes: Vec::new(),
+            from_function: None,
             for_update: false,
+            unlimited: false,
+            comments: BTreeMap::new(),
+            sensitive: Vec::new(),
+            truncate: TruncateOptions::default(),
+            temp_table: false,
         }
     }
 
@@ -171,6 +742,12 @@ impl QueryBuilder {
         self.from(table)
     }
 
+    /// Select from a set-returning function, e.g. `generate_series`
+    pub fn from_function(mut self, function: TableFunction) -> Self {
+        self.from_function = Some(function);
+        self
+    }
+
     /// Set table alias
     pub fn alias(mut self, alias: impl Into<String>) -> Self {
         self.alias = Some(alias.into());
@@ -195,6 +772,12 @@ impl QueryBuilder {
         self
     }
 
+    /// Select a computed column, e.g. an aggregate, as `alias`
+    pub fn column_expr(mut self, expr: Expr, alias: impl Into<String>) -> Self {
+        self.select_exprs.push((expr, alias.into()));
+        self
+    }
+
     /// Add a WHERE filter
     pub fn filter(mut self, expr: Expr) -> Self {
         self.where_clauses.push(expr);
@@ -213,6 +796,7 @@ impl QueryBuilder {
             table: table.into(),
             alias: None,
             on,
+            function: None,
         });
         self
     }
@@ -224,20 +808,98 @@ impl QueryBuilder {
             table: table.into(),
             alias: None,
             on,
+            function: None,
+        });
+        self
+    }
+
+    /// Add a LEFT JOIN of `table` under `alias`
+    pub fn left_join_as(
+        mut self,
+        table: impl Into<String>,
+        alias: impl Into<String>,
+        on: Expr,
+    ) -> Self {
+        self.joins.push(Join {
+            join_type: JoinType::Left,
+            table: table.into(),
+            alias: Some(alias.into()),
+            on,
+            function: None,
+        });
+        self
+    }
+
+    fn push_function_join(
+        mut self,
+        join_type: JoinType,
+        function: TableFunction,
+        on: Expr,
+    ) -> Self {
+        self.joins.push(Join {
+            join_type,
+            table: String::new(),
+            alias: None,
+            on,
+            function: Some(function),
         });
         self
     }
 
+    /// Add an INNER JOIN against a set-returning function
+    pub fn join_function(self, function: TableFunction, on: Expr) -> Self {
+        self.push_function_join(JoinType::Inner, function, on)
+    }
+
+    /// Add a LEFT JOIN against a set-returning function
+    pub fn left_join_function(self, function: TableFunction, on: Expr) -> Self {
+        self.push_function_join(JoinType::Left, function, on)
+    }
+
+    /// Pair each row with the function's output, e.g. `unnest(posts.tags)`
+    pub fn cross_join_function(self, function: TableFunction) -> Self {
+        self.push_function_join(JoinType::Cross, function, Expr::raw("TRUE"))
+    }
+
     /// Add ORDER BY
     pub fn order_by(mut self, column: impl Into<String>, order: Order) -> Self {
         self.order_by.push(OrderBy {
             column: column.into(),
             order,
             nulls: None,
+            expr: None,
+        });
+        self
+    }
+
+    /// Add ORDER BY on an expression, e.g. `LOWER(name)` or a CASE
+    pub fn order_by_expr(mut self, expr: Expr, order: Order) -> Self {
+        self.order_by.push(OrderBy {
+            column: String::new(),
+            order,
+            nulls: None,
+            expr: Some(expr),
         });
         self
     }
 
+    /// Sort NULLs first in the last ORDER BY added
+    pub fn nulls_first(self) -> Self {
+        self.nulls(NullsOrder::First)
+    }
+
+    /// Sort NULLs last in the last ORDER BY added
+    pub fn nulls_last(self) -> Self {
+        self.nulls(NullsOrder::Last)
+    }
+
+    fn nulls(mut self, nulls: NullsOrder) -> Self {
+        if let Some(order_by) = self.order_by.last_mut() {
+            order_by.nulls = Some(nulls);
+        }
+        self
+    }
+
     /// Add ORDER BY ASC
     pub fn order_by_asc(self, column: impl Into<String>) -> Self {
         self.order_by(column, Order::Asc)
@@ -254,6 +916,32 @@ impl QueryBuilder {
         self
     }
 
+    /// Add GROUP BY ROLLUP, with subtotals for each prefix of `columns`
+    pub fn group_by_rollup(mut self, columns: &[&str]) -> Self {
+        self.grouping_sets = Some(GroupingSets::Rollup(
+            columns.iter().map(|s| s.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Add GROUP BY CUBE, with subtotals for every subset of `columns`
+    pub fn group_by_cube(mut self, columns: &[&str]) -> Self {
+        self.grouping_sets = Some(GroupingSets::Cube(
+            columns.iter().map(|s| s.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Add GROUP BY GROUPING SETS; an empty set is the grand total
+    pub fn grouping_sets(mut self, sets: &[&[&str]]) -> Self {
+        self.grouping_sets = Some(GroupingSets::Sets(
+            sets.iter()
+                .map(|set| set.iter().map(|s| s.to_string()).collect())
+                .collect(),
+        ));
+        self
+    }
+
     /// Add HAVING
     pub fn having(mut self, expr: Expr) -> Self {
         self.having = Some(expr);
@@ -284,18 +972,171 @@ impl QueryBuilder {
         self
     }
 
+    /// Add a common table expression
+    pub fn with_cte(mut self, name: impl Into<String>, query: Query) -> Self {
+        self.ctes.push(Cte {
+            name: name.into(),
+            columns: Vec::new(),
+            query: Box::new(query),
+            recursive_term: None,
+        });
+        self
+    }
+
+    /// Add a recursive common table expression
+    ///
+    /// Renders `WITH RECURSIVE name (columns) AS (anchor UNION ALL recursive)`;
+    /// `recursive` refers back to `name`.
+    pub fn with_recursive(
+        mut self,
+        name: impl Into<String>,
+        columns: &[&str],
+        anchor: Query,
+        recursive: Query,
+    ) -> Self {
+        self.ctes.push(Cte {
+            name: name.into(),
+            columns: columns.iter().map(|s| s.to_string()).collect(),
+            query: Box::new(anchor),
+            recursive_term: Some(Box::new(recursive)),
+        });
+        self
+    }
+
+    /// Set conflict handling for INSERT (upsert)
+    pub fn on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = Some(on_conflict);
+        self
+    }
+
     /// Set FOR UPDATE
     pub fn for_update(mut self) -> Self {
         self.for_update = true;
         self
     }
 
+    /// Truncate tables referencing this one too
+    pub fn cascade(mut self) -> Self {
+        self.truncate.cascade = true;
+        self
+    }
+
+    /// Reset the table's identity sequences when truncating
+    pub fn restart_identity(mut self) -> Self {
+        self.truncate.restart_identity = true;
+        self
+    }
+
+    /// Set the default scope, a filter kept apart so `unscoped()` can drop it
+    pub fn scope(mut self, expr: Expr) -> Self {
+        self.scope = Some(expr);
+        self
+    }
+
+    /// Drop the model's default scope
+    pub fn unscoped(mut self) -> Self {
+        self.scope = None;
+        self
+    }
+
+    /// The default scope, unless dropped
+    pub fn default_scope(&self) -> Option<&Expr> {
+        self.scope.as_ref()
+    }
+
+    /// Opt out of the executor's row guard
+    pub fn unlimited(mut self) -> Self {
+        self.unlimited = true;
+        self
+    }
+
+    /// Redact values bound to these columns in debug SQL
+    pub fn sensitive(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.sensitive.extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Show sensitive values in debug SQL
+    pub fn reveal_sensitive(mut self) -> Self {
+        self.sensitive.clear();
+        self
+    }
+
+    /// Annotate the statement with `key=value` pairs
+    ///
+    /// Pairs are whitespace separated, e.g. `"service=checkout route=/pay"`,
+    /// and rendered as a sqlcommenter-compatible comment.
+    pub fn comment(mut self, annotation: &str) -> Self {
+        for pair in annotation.split_whitespace() {
+            if let Some((key, value)) = pair.split_once('=') {
+                self.comments.insert(key.to_string(), value.to_string());
+            }
+        }
+        self
+    }
+
+    /// Add a single comment tag
+    pub fn comment_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.comments.insert(key.into(), value.into());
+        self
+    }
+
+    /// Tag the statement with the current tracing span ID, if any
+    pub fn trace_comment(self) -> Self {
+        match tracing::Span::current().id() {
+            Some(id) => self.comment_tag("span_id", format!("{:016x}", id.into_u64())),
+            None => self,
+        }
+    }
+
     /// Set values for INSERT
     pub fn values(mut self, values: HashMap<String, Value>) -> Self {
         self.values.push(values);
         self
     }
 
+    /// Insert the rows returned by a SELECT
+    ///
+    /// Renders `INSERT INTO table (columns) SELECT ...`, with the target
+    /// columns taken from `.columns()` when set.
+    pub fn from_select(mut self, query: Query) -> Self {
+        self.source = Some(query);
+        self
+    }
+
+    /// Create the target table as a temporary table from the `from_select`
+    /// source instead of inserting into an existing one
+    pub fn temp_table(mut self) -> Self {
+        self.temp_table = true;
+        self
+    }
+
+    /// Set a column to an expression, e.g. `F::col("stock.qty")`
+    pub fn set_expr(mut self, column: impl Into<String>, expr: impl Into<Expr>) -> Self {
+        self.set_exprs.push((column.into(), expr.into()));
+        self
+    }
+
+    /// Join another table into an UPDATE
+    ///
+    /// Renders `UPDATE t SET ... FROM other` on PostgreSQL and the
+    /// multi-table `UPDATE t, other SET ...` on MySQL; the join condition
+    /// goes in `filter`.
+    pub fn update_from(mut self, table: impl Into<String>) -> Self {
+        self.update_from.push(table.into());
+        self
+    }
+
+    /// Name the columns identifying a row, usually the primary key
+    ///
+    /// A bounded UPDATE or DELETE on PostgreSQL or SQLite selects its rows
+    /// by them rather than by `ctid` or `rowid`, which a partitioned table
+    /// repeats across partitions.
+    pub fn key_columns(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.key_columns.extend(columns.into_iter().map(Into::into));
+        self
+    }
+
     /// Set a single value
     pub fn set(mut self, column: impl Into<String>, value: impl Into<Value>) -> Self {
         if self.values.is_empty() {
@@ -309,34 +1150,53 @@ impl QueryBuilder {
 
     /// Build the query
     pub fn build(self) -> Query {
-        let where_clause = if self.where_clauses.is_empty() {
+        let mut where_clauses = self.where_clauses;
+        if let Some(scope) = self.scope {
+            where_clauses.insert(0, scope);
+        }
+
+        let where_clause = if where_clauses.is_empty() {
             None
-        } else if self.where_clauses.len() == 1 {
-            Some(self.where_clauses.into_iter().next().unwrap())
+        } else if where_clauses.len() == 1 {
+            Some(where_clauses.into_iter().next().unwrap())
         } else {
-            Some(Expr::And(self.where_clauses))
+            Some(Expr::And(where_clauses))
         };
 
         Query {
             query_type: self.query_type,
             table: self.table.unwrap_or_default(),
             alias: self.alias,
-            columns: if self.columns.is_empty() {
+            columns: if self.columns.is_empty() && self.select_exprs.is_empty() {
                 vec!["*".to_string()]
             } else {
                 self.columns
             },
+            select_exprs: self.select_exprs,
             values: self.values,
             where_clause,
             joins: self.joins,
             order_by: self.order_by,
             group_by: self.group_by,
+            grouping_sets: self.grouping_sets,
             having: self.having,
             limit: self.limit,
             offset: self.offset,
             distinct: self.distinct,
             returning: self.returning,
+            on_conflict: self.on_conflict,
+            source: self.source.map(Box::new),
+            set_exprs: self.set_exprs,
+            update_from: self.update_from,
+            key_columns: self.key_columns,
+            ctes: self.ctes,
+            from_function: self.from_function,
             for_update: self.for_update,
+            unlimited: self.unlimited,
+            comments: self.comments,
+            sensitive: self.sensitive,
+            truncate: self.truncate,
+            temp_table: self.temp_table,
         }
     }
 }
@@ -376,6 +1236,72 @@ mod tests {
         assert_eq!(query.returning, vec!["id"]);
     }
 
+    #[test]
+    fn test_insert_chunks() {
+        let mut builder = Query::insert().table("events");
+        for i in 0..5 {
+            builder = builder.values(HashMap::from([
+                ("id".to_string(), Value::from(i)),
+                ("kind".to_string(), Value::from("click")),
+            ]));
+        }
+        let query = builder.build();
+
+        let chunks = query.insert_chunks(4);
+        assert_eq!(
+            chunks.iter().map(|q| q.values.len()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(query.insert_chunks(1).len(), 5);
+        assert_eq!(query.insert_chunks(100).len(), 1);
+    }
+
+    #[test]
+    fn test_insert_groups() {
+        let row = |columns: &[&str]| {
+            columns
+                .iter()
+                .map(|c| (c.to_string(), Value::from(1)))
+                .collect::<HashMap<_, _>>()
+        };
+        let query = Query::insert()
+            .table("tags")
+            .values(row(&["slug"]))
+            .values(row(&["slug"]))
+            .values(row(&["slug", "color"]))
+            .values(row(&["slug"]))
+            .build();
+        assert!(query.has_missing_values());
+
+        let groups = query.insert_groups();
+        assert_eq!(
+            groups.iter().map(|q| q.values.len()).collect::<Vec<_>>(),
+            vec![2, 1, 1]
+        );
+        assert!(groups.iter().all(|q| !q.has_missing_values()));
+    }
+
+    #[test]
+    fn test_default_scope() {
+        let archived = Expr::raw("status <> 'archived'");
+        let query = Query::select()
+            .from("users")
+            .scope(archived.clone())
+            .filter(Expr::eq("id", 1))
+            .build();
+        match query.where_clause {
+            Some(Expr::And(clauses)) => assert_eq!(clauses.len(), 2),
+            other => panic!("expected scope AND filter, got {:?}", other),
+        }
+
+        let query = Query::select()
+            .from("users")
+            .scope(archived)
+            .unscoped()
+            .build();
+        assert!(query.where_clause.is_none());
+    }
+
     #[test]
     fn test_update_query_builder() {
         let query = Query::update()
@@ -398,4 +1324,63 @@ mod tests {
         assert_eq!(query.query_type, QueryType::Delete);
         assert!(query.where_clause.is_some());
     }
+
+    #[test]
+    fn test_row_guard() {
+        let mut query = Query::select().from("events").build();
+        assert!(query.apply_row_guard(1000));
+        assert_eq!(query.limit, Some(1000));
+
+        let mut query = Query::select().from("events").limit(5).build();
+        assert!(!query.apply_row_guard(1000));
+        assert_eq!(query.limit, Some(5));
+
+        let mut query = Query::select().from("events").unlimited().build();
+        assert!(!query.apply_row_guard(1000));
+        assert_eq!(query.limit, None);
+    }
+
+    #[test]
+    fn test_comment_tags() {
+        let query = Query::select()
+            .from("orders")
+            .comment("service=checkout route=/pay")
+            .comment_tag("action", "list")
+            .build();
+
+        assert_eq!(query.comments.len(), 3);
+        assert_eq!(query.comments["route"], "/pay");
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let query = Query::select()
+            .from("orders")
+            .filter(
+                Expr::eq("status", "paid").and(Expr::exists(
+                    Query::select()
+                        .from("refunds")
+                        .filter(Expr::raw("refunds.order_id = orders.id"))
+                        .build(),
+                )),
+            )
+            .order_by_desc("created_at")
+            .limit(20)
+            .build();
+
+        let json = query.to_json().unwrap();
+        assert!(json.starts_with("{\"version\":1,"));
+        let decoded = Query::from_json(&json).unwrap();
+        assert_eq!(decoded.to_json().unwrap(), json);
+
+        let future = json.replacen("\"version\":1", "\"version\":2", 1);
+        assert!(Query::from_json(&future)
+            .unwrap_err()
+            .to_string()
+            .contains("version 2"));
+
+        let tableless = json.replacen("\"table\":\"refunds\"", "\"table\":\"\"", 1);
+        assert!(Query::from_json(&tableless).is_err());
+        assert!(Query::from_json("{\"query\": {}}").is_err());
+    }
 }
@@ -0,0 +1,1136 @@
+//! Model-level querying
+//!
+//! `QuerySet` wraps the query builder for one model. Building it runs
+//! nothing; a query is only sent by `all`, `stream`, `get`, `first`, `count`,
+//! `exists` or `aggregate`, so a queryset can be built up in steps and reused.
+//!
+//! ```rust,ignore
+//! let active = User::objects()
+//!     .filter(Q::new("is_active", true))
+//!     .order_by("-created_at");
+//!
+//! let total = active.count(&executor).await?;
+//! let page = active.limit(20).all(&executor).await?;
+//! ```
+//!
+//! Relationships are loaded eagerly with `select_related`, which joins the
+//! related table into the same query, or `prefetch_related`, which runs one
+//! `IN` query per relationship after the main one.
+//!
+//! `aggregate` computes totals over the matching rows, and `annotate` adds a
+//! computed column to each row, grouped by the model's primary key. Both take
+//! `Count`, `Sum`, `Avg`, `Min` and `Max` over a column, a relationship or a
+//! relationship's field:
+//!
+//! ```rust,ignore
+//! let stats: Row = Product::objects().aggregate(&executor, [Count("id"), Avg("price")]).await?;
+//! let price: Option<f64> = stats.try_get("price__avg")?;
+//!
+//! let authors = User::objects()
+//!     .annotate(Count("posts"))
+//!     .annotate(Sum("posts__views").alias("views"))
+//!     .order_by("-views")
+//!     .all_annotated(&executor)
+//!     .await?;
+//! let posts: i64 = authors[0].get("posts__count")?;
+//! ```
+//!
+//! `values` and `values_list` fetch only some fields, as maps or tuples,
+//! instead of whole models:
+//!
+//! ```rust,ignore
+//! let names: Vec<(i64, String)> = User::objects().values_list(&executor, &["id", "name"]).await?;
+//! ```
+//!
+//! `stream` yields models as the database returns them, for scans too large
+//! to load at once:
+//!
+//! ```rust,ignore
+//! let mut events = Event::objects().order_by("id").stream(&executor)?;
+//! while let Some(event) = events.try_next().await? {
+//!     export(event)?;
+//! }
+//! ```
+//!
+//! `in_bulk` loads the rows with the given primary keys, keyed by them, in
+//! as few `IN` queries as the parameter limit allows, for dataloader-style
+//! lookups:
+//!
+//! ```rust,ignore
+//! let authors: HashMap<i64, User> = User::objects().in_bulk(&executor, &author_ids).await?;
+//! let names = posts.iter().map(|p| authors.get(&p.author_id).map(|a| a.name.as_str()));
+//! ```
+//!
+//! `inspect` records each call so `explain` can show how the SQL came about.
+//!
+//! For tenant-scoped models, the current `TenantContext` is added as a
+//! filter each time the queryset runs, unless `all_tenants` was called.
+//! Scopes registered with `register_scope` are added the same way, unless
+//! dropped with `without_scope` or `unscoped`.
+//!
+//! Before a query is sent, each value its filters compare with a field is
+//! checked against the field's type, so a string bound to an integer column
+//! fails locally instead of aborting the surrounding transaction. Call
+//! `allow_casts` for comparisons the database is meant to convert.
+
+use crate::crud::DEFAULT_BATCH_SIZE;
+use crate::error::{ChakraError, ModelError, QueryError, Result};
+use crate::executor::{route, Executor};
+use crate::expr::{AggregateFunc, CompareOp, Expr};
+use crate::inspector::{expr_sql, one_line, InspectStep, QueryInspector};
+use crate::model::{Model, ModelMeta, RelationMeta, RelationType};
+use crate::query::{Order, OrderBy, Query, QueryBuilder};
+use crate::result::{FromRow, FromValue, Row, RowStream};
+use crate::scope::scope_filters;
+use crate::sql::Dialect;
+use crate::tenant::tenant_filter;
+use crate::types::{ToValue, Value};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use tracing::debug;
+
+/// Column naming the owning row in a many-to-many prefetch
+const OWNER_COLUMN: &str = "chakra_owner";
+
+/// A lazily evaluated query over a model's table
+pub struct QuerySet<M: Model> {
+    builder: QueryBuilder,
+    select_related: Vec<String>,
+    prefetch_related: Vec<String>,
+    annotations: Vec<Aggregate>,
+    inspector: Option<QueryInspector>,
+    all_tenants: bool,
+    /// Registered scopes are skipped entirely
+    unscoped: bool,
+    /// Names of registered scopes to skip
+    without_scopes: Vec<String>,
+    /// Values are sent without checking them against the field types
+    allow_casts: bool,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: Model> Clone for QuerySet<M> {
+    fn clone(&self) -> Self {
+        Self {
+            builder: self.builder.clone(),
+            select_related: self.select_related.clone(),
+            prefetch_related: self.prefetch_related.clone(),
+            annotations: self.annotations.clone(),
+            inspector: self.inspector.clone(),
+            all_tenants: self.all_tenants,
+            unscoped: self.unscoped,
+            without_scopes: self.without_scopes.clone(),
+            allow_casts: self.allow_casts,
+            _model: PhantomData,
+        }
+    }
+}
+
+impl<M: Model> Default for QuerySet<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: Model> QuerySet<M> {
+    /// All rows of the model's table, within its default scope
+    pub fn new() -> Self {
+        Self::from_builder(M::query())
+    }
+
+    /// Wrap a builder already pointed at the model's table
+    pub fn from_builder(builder: QueryBuilder) -> Self {
+        Self {
+            builder,
+            select_related: Vec::new(),
+            prefetch_related: Vec::new(),
+            annotations: Vec::new(),
+            inspector: None,
+            all_tenants: false,
+            unscoped: false,
+            without_scopes: Vec::new(),
+            allow_casts: false,
+            _model: PhantomData,
+        }
+    }
+
+    /// Keep rows matching `condition`, ANDed with earlier filters
+    pub fn filter(mut self, condition: impl Into<Expr>) -> Self {
+        let condition = condition.into();
+        self.record(|| InspectStep::Filter(condition.clone()));
+        self.builder = self.builder.filter(condition);
+        self
+    }
+
+    /// Drop rows matching `condition`
+    pub fn exclude(mut self, condition: impl Into<Expr>) -> Self {
+        let condition = condition.into();
+        self.record(|| InspectStep::Exclude(condition.clone()));
+        self.builder = self.builder.filter(condition.not());
+        self
+    }
+
+    /// Sort by a column, descending if it starts with `-`, after any
+    /// earlier ordering
+    pub fn order_by(mut self, column: &str) -> Self {
+        self.record(|| InspectStep::OrderBy(column.to_string()));
+        self.builder = match column.strip_prefix('-') {
+            Some(column) => self.builder.order_by(column, Order::Desc),
+            None => self.builder.order_by(column, Order::Asc),
+        };
+        self
+    }
+
+    /// Return at most `limit` rows
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.record(|| InspectStep::Limit(limit));
+        self.builder = self.builder.limit(limit);
+        self
+    }
+
+    /// Skip the first `offset` rows
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.record(|| InspectStep::Offset(offset));
+        self.builder = self.builder.offset(offset);
+        self
+    }
+
+    /// Remove duplicate rows
+    pub fn distinct(mut self) -> Self {
+        self.record(|| InspectStep::Distinct);
+        self.builder = self.builder.distinct();
+        self
+    }
+
+    /// Include rows outside the model's default scope and every registered
+    /// scope
+    pub fn unscoped(mut self) -> Self {
+        self.record(|| InspectStep::Unscoped);
+        self.builder = self.builder.unscoped();
+        self.unscoped = true;
+        self
+    }
+
+    /// Include rows outside the registered scope `name`, keeping the others
+    pub fn without_scope(mut self, name: &str) -> Self {
+        self.record(|| InspectStep::WithoutScope(name.to_string()));
+        self.without_scopes.push(name.to_string());
+        self
+    }
+
+    /// Include every tenant's rows, even inside a `TenantContext`
+    pub fn all_tenants(mut self) -> Self {
+        self.record(|| InspectStep::AllTenants);
+        self.all_tenants = true;
+        self
+    }
+
+    /// Send filter values whose type doesn't match their field's, for casts
+    /// the database is meant to make, like a string compared with a date
+    /// column
+    pub fn allow_casts(mut self) -> Self {
+        self.record(|| InspectStep::AllowCasts);
+        self.allow_casts = true;
+        self
+    }
+
+    /// Load relationship `name` in the same query through a LEFT JOIN
+    ///
+    /// Only for relationships whose foreign key is on this model; use
+    /// `prefetch_related` for the others.
+    pub fn select_related(mut self, name: &str) -> Self {
+        self.record(|| InspectStep::SelectRelated(name.to_string()));
+        self.select_related.push(name.to_string());
+        self
+    }
+
+    /// Load relationship `name` with a follow-up `IN` query over the keys of
+    /// the fetched rows, split into batches that fit the parameter limit
+    pub fn prefetch_related(mut self, name: &str) -> Self {
+        self.record(|| InspectStep::PrefetchRelated(name.to_string()));
+        self.prefetch_related.push(name.to_string());
+        self
+    }
+
+    /// Add a computed column to each row, e.g. `Count("posts")` for the
+    /// number of related posts, read back through `all_annotated`
+    ///
+    /// The rows are grouped by primary key, with LEFT JOINs for the
+    /// relationships aggregated over. Annotating over two to-many
+    /// relationships multiplies their rows; count them with `distinct()`.
+    pub fn annotate(mut self, aggregate: Aggregate) -> Self {
+        self.record(|| InspectStep::Annotate(aggregate.name()));
+        self.annotations.push(aggregate);
+        self
+    }
+
+    /// Record the calls made on this queryset from here on, for `explain`
+    /// and for a debug log entry each time it runs
+    pub fn inspect(mut self) -> Self {
+        self.inspector.get_or_insert_with(QueryInspector::new);
+        self
+    }
+
+    /// The steps recorded since `inspect`, if enabled
+    pub fn inspector(&self) -> Option<&QueryInspector> {
+        self.inspector.as_ref()
+    }
+
+    /// The SELECT this queryset runs for `all`, including its joins
+    pub fn query(&self) -> Result<Query> {
+        Ok(self.select()?.build())
+    }
+
+    /// The final SQL of `all`, preceded by comments explaining how it was
+    /// built: the default scope, the recorded steps, the joins added by
+    /// `select_related` and the follow-up queries of `prefetch_related`
+    pub fn explain(&self, dialect: &dyn Dialect) -> Result<String> {
+        let meta = M::meta();
+        let mut lines = vec![format!("-- {}.objects() on {}", meta.name, meta.table)];
+        if let Some(scope) = self.builder.default_scope() {
+            lines.push(format!("-- default scope: {}", expr_sql(dialect, scope)));
+        }
+        for (name, filter) in self.registered_scopes() {
+            lines.push(format!("-- scope {}: {}", name, expr_sql(dialect, &filter)));
+        }
+        if let Some(tenant) = self.tenant() {
+            lines.push(format!("-- tenant: {}", expr_sql(dialect, &tenant)));
+        }
+        match &self.inspector {
+            Some(inspector) => {
+                for (i, step) in inspector.steps().iter().enumerate() {
+                    let plan = match step {
+                        InspectStep::SelectRelated(name) => {
+                            let (table, on) = related_join::<M>(name)?;
+                            format!(
+                                ": LEFT JOIN {} AS {} ON {}",
+                                table,
+                                name,
+                                expr_sql(dialect, &on)
+                            )
+                        }
+                        InspectStep::PrefetchRelated(name) => {
+                            let plan = plan_prefetch::<M>(name)?;
+                            let follow_up = plan
+                                .select
+                                .filter(Expr::raw(format!("{} IN (...)", plan.column)))
+                                .build();
+                            format!(
+                                ": then {}, keys from {}.{}",
+                                one_line(&follow_up.debug_sql(dialect)),
+                                meta.table,
+                                plan.owner_field
+                            )
+                        }
+                        _ => String::new(),
+                    };
+                    lines.push(format!("-- {}. {}{}", i + 1, step.describe(dialect), plan));
+                }
+            }
+            None => lines.push("-- steps not recorded; call inspect() first".to_string()),
+        }
+        lines.push(self.query()?.debug_sql(dialect));
+        Ok(lines.join("\n"))
+    }
+
+    /// Fetch every matching row
+    pub async fn all(&self, executor: &dyn Executor) -> Result<Vec<M>> {
+        if self.inspector.is_some() {
+            debug!("{}", self.explain(executor.dialect())?);
+        }
+        let rows = route(executor, M::meta())?
+            .fetch_all(&self.query()?)
+            .await?;
+        self.load(executor, &rows).await
+    }
+
+    /// Stream the matching rows as they are fetched, for scans too large to
+    /// hold in memory
+    ///
+    /// `select_related` joins are filled in; `prefetch_related` needs all
+    /// the rows at once and is an error here. The executor's row guard does
+    /// not apply.
+    pub fn stream<'a>(&'a self, executor: &'a dyn Executor) -> Result<RowStream<'a, M>> {
+        if !self.prefetch_related.is_empty() {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: "prefetch_related can't be streamed; use select_related or all()"
+                    .to_string(),
+            }));
+        }
+        if self.inspector.is_some() {
+            debug!("{}", self.explain(executor.dialect())?);
+        }
+        Ok(route(executor, M::meta())?
+            .fetch_stream(&self.query()?)?
+            .map_rows(|row| self.load_row(&row)))
+    }
+
+    /// Fetch every matching row along with its annotations
+    pub async fn all_annotated(&self, executor: &dyn Executor) -> Result<Vec<Annotated<M>>> {
+        if self.inspector.is_some() {
+            debug!("{}", self.explain(executor.dialect())?);
+        }
+        let rows = route(executor, M::meta())?
+            .fetch_all(&self.query()?)
+            .await?;
+        let models = self.load(executor, &rows).await?;
+        let names: Vec<String> = self.annotations.iter().map(Aggregate::name).collect();
+        Ok(models
+            .into_iter()
+            .zip(&rows)
+            .map(|(model, row)| {
+                let values = names
+                    .iter()
+                    .map(|n| row.get(n).cloned().unwrap_or(Value::Null))
+                    .collect();
+                Annotated {
+                    model,
+                    annotations: Row::new(names.clone(), values),
+                }
+            })
+            .collect())
+    }
+
+    /// Compute `aggregates` over all matching rows, as one row
+    ///
+    /// `T` is `Row` or any `FromRow` type with a field per aggregate name.
+    /// Aggregates over relationships can't follow a limit, offset or
+    /// `distinct`.
+    pub async fn aggregate<T: FromRow>(
+        &self,
+        executor: &dyn Executor,
+        aggregates: impl IntoIterator<Item = Aggregate>,
+    ) -> Result<T> {
+        let aggregates: Vec<Aggregate> = aggregates.into_iter().collect();
+        let mut builder = self.scoped()?;
+        let query = builder.clone().build();
+        if query.limit.is_some() || query.offset.is_some() || query.distinct {
+            // Aggregate the limited rows, not the whole table
+            let mut outer = Query::select()
+                .with_cte("aggregated", query)
+                .from("aggregated")
+                .unlimited();
+            for aggregate in &aggregates {
+                if aggregate.relation::<M>().is_some() {
+                    return Err(ChakraError::Query(QueryError::Invalid {
+                        message: format!(
+                            "can't aggregate over relationship '{}' after limit, offset or distinct",
+                            aggregate.column
+                        ),
+                    }));
+                }
+                outer = outer.column_expr(
+                    aggregate.expr("aggregated", &aggregate.column),
+                    aggregate.name(),
+                );
+            }
+            builder = outer;
+        } else {
+            builder = join_aggregates::<M>(builder, &aggregates, &mut Vec::new())?.unlimited();
+        }
+        let mut query = builder.build();
+        query.columns.clear();
+        query.order_by.clear();
+        let rows = route(executor, M::meta())?.fetch_all(&query).await?;
+        match rows.first() {
+            Some(row) => T::from_row(row),
+            None => Err(ChakraError::Query(QueryError::NotFound)),
+        }
+    }
+
+    /// Fetch only `fields` of each matching row, keyed by field name
+    ///
+    /// No fields means all of the model's fields. Relationships are not
+    /// loaded.
+    pub async fn values(
+        &self,
+        executor: &dyn Executor,
+        fields: &[&str],
+    ) -> Result<Vec<HashMap<String, Value>>> {
+        let rows = route(executor, M::meta())?
+            .fetch_all(&self.projection(fields)?)
+            .await?;
+        Ok(rows.into_iter().map(Row::into_values).collect())
+    }
+
+    /// Fetch only `fields` of each matching row, decoded as `T`
+    ///
+    /// `T` is usually a tuple with one element per field, in order, but can
+    /// be any `FromRow` type whose fields are among `fields`.
+    pub async fn values_list<T: FromRow>(
+        &self,
+        executor: &dyn Executor,
+        fields: &[&str],
+    ) -> Result<Vec<T>> {
+        let rows = route(executor, M::meta())?
+            .fetch_all(&self.projection(fields)?)
+            .await?;
+        rows.iter().map(T::from_row).collect()
+    }
+
+    /// The query selecting only `fields`, each aliased to its field name
+    fn projection(&self, fields: &[&str]) -> Result<Query> {
+        if !self.annotations.is_empty() {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: "values() can't be combined with annotate()".to_string(),
+            }));
+        }
+        let meta = M::meta();
+        let fields: Vec<&str> = if fields.is_empty() {
+            meta.fields.iter().map(|f| f.name.as_str()).collect()
+        } else {
+            fields.to_vec()
+        };
+        let mut columns = Vec::with_capacity(fields.len());
+        for name in fields {
+            let field = meta.get_field(name).ok_or_else(|| {
+                ChakraError::Query(QueryError::Invalid {
+                    message: format!("{} has no field '{}'", meta.name, name),
+                })
+            })?;
+            columns.push((
+                Expr::Column(format!("{}.{}", M::table_name(), field.column_name())),
+                field.name.clone(),
+            ));
+        }
+        let mut query = self.scoped()?.build();
+        query.columns.clear();
+        query.select_exprs = columns;
+        Ok(query)
+    }
+
+    /// Fetch the single matching row, failing with `NotFound` or
+    /// `MultipleResults` if there isn't exactly one
+    pub async fn get(&self, executor: &dyn Executor) -> Result<M> {
+        let query = self.select()?.limit(2).build();
+        let rows = route(executor, M::meta())?.fetch_all(&query).await?;
+        match rows.len() {
+            1 => Ok(self.load(executor, &rows).await?.remove(0)),
+            0 => Err(ChakraError::Query(QueryError::NotFound)),
+            _ => Err(ChakraError::Query(QueryError::MultipleResults)),
+        }
+    }
+
+    /// Fetch the first matching row, by primary key unless ordered
+    pub async fn first(&self, executor: &dyn Executor) -> Result<Option<M>> {
+        let mut query = self.select()?.limit(1).build();
+        if query.order_by.is_empty() {
+            for column in &M::meta().primary_key {
+                query.order_by.push(OrderBy {
+                    column: format!("{}.{}", M::table_name(), column),
+                    order: Order::Asc,
+                    nulls: None,
+                    expr: None,
+                });
+            }
+        }
+        let rows = route(executor, M::meta())?.fetch_all(&query).await?;
+        Ok(self.load(executor, &rows).await?.into_iter().next())
+    }
+
+    /// Fetch the matching rows whose primary key is one of `ids`, keyed by
+    /// primary key
+    ///
+    /// Duplicate ids are fetched once, and ids with no matching row are
+    /// left out of the map. The ids are split into one `IN` query per batch
+    /// of at most `DEFAULT_BATCH_SIZE`, fewer if the dialect's parameter
+    /// limit leaves less room beside the queryset's own filters, so the
+    /// queryset can't be limited or offset.
+    pub async fn in_bulk(
+        &self,
+        executor: &dyn Executor,
+        ids: &[M::PrimaryKey],
+    ) -> Result<HashMap<M::PrimaryKey, M>>
+    where
+        M::PrimaryKey: Eq + Hash,
+    {
+        let meta = M::meta();
+        let [key] = meta.primary_key.as_slice() else {
+            return Err(ChakraError::Model(ModelError::InvalidField {
+                model: meta.name.clone(),
+                field: "primary key (in_bulk needs a single-column key)".to_string(),
+            }));
+        };
+        let select = self.select()?;
+        let base = select.clone().build();
+        if base.limit.is_some() || base.offset.is_some() {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: "in_bulk can't follow limit or offset".to_string(),
+            }));
+        }
+        let mut seen = HashSet::with_capacity(ids.len());
+        let ids: Vec<&M::PrimaryKey> = ids.iter().filter(|id| seen.insert(*id)).collect();
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let routed = route(executor, meta)?;
+        let reserved = routed.generate(&base).params.len();
+        let batch_size = routed
+            .dialect()
+            .max_params()
+            .saturating_sub(reserved)
+            .clamp(1, DEFAULT_BATCH_SIZE);
+        let column = format!("{}.{}", M::table_name(), key);
+        let mut found = HashMap::with_capacity(ids.len());
+        for batch in ids.chunks(batch_size) {
+            let values: Vec<Value> = batch.iter().map(|id| id.to_value()).collect();
+            let query = select
+                .clone()
+                .filter(Expr::is_in(column.clone(), values))
+                .unlimited()
+                .build();
+            let rows = routed.fetch_all(&query).await?;
+            for model in self.load(executor, &rows).await? {
+                found.insert(model.primary_key().clone(), model);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Count the matching rows, after any limit and offset
+    pub async fn count(&self, executor: &dyn Executor) -> Result<i64> {
+        let mut query = self.scoped()?.build();
+        let count = if query.limit.is_some() || query.offset.is_some() || query.distinct {
+            // The limit applies to the rows, not to the single count row
+            Query::select()
+                .with_cte("counted", query)
+                .from("counted")
+                .columns(&["COUNT(*) AS count"])
+                .unlimited()
+                .build()
+        } else {
+            query.columns = vec!["COUNT(*) AS count".to_string()];
+            query.order_by.clear();
+            query.unlimited = true;
+            query
+        };
+        let rows = route(executor, M::meta())?.fetch_all(&count).await?;
+        match rows.first() {
+            Some(row) => row.get_as("count"),
+            None => Ok(0),
+        }
+    }
+
+    /// Check whether any row matches
+    pub async fn exists(&self, executor: &dyn Executor) -> Result<bool> {
+        let mut query = self.scoped()?.limit(1).build();
+        query.columns = vec!["1 AS one".to_string()];
+        query.order_by.clear();
+        Ok(!route(executor, M::meta())?
+            .fetch_all(&query)
+            .await?
+            .is_empty())
+    }
+
+    /// The builder with a join per `select_related` relationship, each
+    /// related column selected as `relation__column`, and the annotations
+    fn select(&self) -> Result<QueryBuilder> {
+        if self.select_related.is_empty() && self.annotations.is_empty() {
+            return self.scoped();
+        }
+        let table = M::table_name();
+        let mut builder = self.scoped()?.column(format!("{}.*", table));
+        let mut group_by: Vec<String> = M::meta()
+            .primary_key
+            .iter()
+            .map(|key| format!("{}.{}", table, key_column(M::meta(), key)))
+            .collect();
+        for name in &self.select_related {
+            let (_, target) = relation::<M>(name)?;
+            let (target_table, on) = related_join::<M>(name)?;
+            builder = builder.left_join_as(target_table, name.clone(), on);
+            for field in &target.fields {
+                let column = field.column_name();
+                builder = builder.column_expr(
+                    Expr::Column(format!("{}.{}", name, column)),
+                    format!("{}__{}", name, column),
+                );
+            }
+            let key = key_column(target, primary_key(target)?);
+            group_by.push(format!("{}.{}", name, key));
+        }
+        if !self.annotations.is_empty() {
+            let mut joined = self.select_related.clone();
+            builder = join_aggregates::<M>(builder, &self.annotations, &mut joined)?;
+            // Grouping by primary keys lets every column of those tables be selected
+            let group_by: Vec<&str> = group_by.iter().map(String::as_str).collect();
+            builder = builder.group_by(&group_by);
+        }
+        Ok(builder)
+    }
+
+    /// The current tenant's filter, unless opted out
+    fn tenant(&self) -> Option<Expr> {
+        if self.all_tenants {
+            return None;
+        }
+        tenant_filter::<M>()
+    }
+
+    /// Filters of the registered scopes not dropped from this queryset
+    fn registered_scopes(&self) -> Vec<(String, Expr)> {
+        if self.unscoped {
+            return Vec::new();
+        }
+        let mut filters = scope_filters(M::meta());
+        filters.retain(|(name, _)| !self.without_scopes.contains(name));
+        filters
+    }
+
+    /// The builder limited by the registered scopes and to the current
+    /// tenant, failing if a filter value doesn't fit its field's type
+    fn scoped(&self) -> Result<QueryBuilder> {
+        let mut builder = self.builder.clone();
+        for (_, filter) in self.registered_scopes() {
+            builder = builder.filter(filter);
+        }
+        if let Some(tenant) = self.tenant() {
+            builder = builder.filter(tenant);
+        }
+        if !self.allow_casts {
+            M::meta().check_param_types(&builder.clone().build())?;
+        }
+        Ok(builder)
+    }
+
+    /// Run `make` if recording
+    fn record(&mut self, make: impl FnOnce() -> InspectStep) {
+        if let Some(inspector) = &mut self.inspector {
+            inspector.record(make());
+        }
+    }
+
+    /// Build models from fetched rows and fill their eager relationships
+    async fn load(&self, executor: &dyn Executor, rows: &[Row]) -> Result<Vec<M>> {
+        let mut models = rows
+            .iter()
+            .map(|row| self.load_row(row))
+            .collect::<Result<Vec<M>>>()?;
+        for name in &self.prefetch_related {
+            prefetch(executor, &mut models, name).await?;
+        }
+        Ok(models)
+    }
+
+    /// Build a model from a fetched row, filling its `select_related` joins
+    fn load_row(&self, row: &Row) -> Result<M> {
+        let mut model = M::from_row(row)?;
+        for name in &self.select_related {
+            let (_, target) = relation::<M>(name)?;
+            let prefix = format!("{}__", name);
+            let (columns, values): (Vec<String>, Vec<Value>) = row
+                .columns()
+                .iter()
+                .filter_map(|column| {
+                    let field = column.strip_prefix(&prefix)?;
+                    Some((
+                        field.to_string(),
+                        row.get(column).cloned().unwrap_or(Value::Null),
+                    ))
+                })
+                .unzip();
+            let related = Row::new(columns, values);
+            // A LEFT JOIN without a match leaves every joined column NULL
+            let found = related
+                .get(key_column(target, primary_key(target)?))
+                .is_some_and(|value| !value.is_null());
+            let found = if found {
+                std::slice::from_ref(&related)
+            } else {
+                &[]
+            };
+            model.set_related(name, found)?;
+        }
+        Ok(model)
+    }
+}
+
+/// A model with the columns computed by `QuerySet::annotate`
+#[derive(Debug, Clone)]
+pub struct Annotated<M> {
+    pub model: M,
+    /// One column per annotation, by name
+    pub annotations: Row,
+}
+
+impl<M> Annotated<M> {
+    /// The value of annotation `name`
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T> {
+        self.annotations.get_as(name)
+    }
+}
+
+impl<M> Deref for Annotated<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.model
+    }
+}
+
+/// An aggregate for `QuerySet::aggregate` and `QuerySet::annotate`
+///
+/// `column` is `*`, a column of the model, a relationship (aggregating the
+/// related primary key) or `relation__field`. The result is named
+/// `{column}__{function}`, e.g. `price__avg`, unless aliased.
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    function: AggregateFunc,
+    column: String,
+    alias: Option<String>,
+    distinct: bool,
+    filter: Option<Expr>,
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFunc, column: impl Into<String>) -> Self {
+        Self {
+            function,
+            column: column.into(),
+            alias: None,
+            distinct: false,
+            filter: None,
+        }
+    }
+
+    /// Name the result column
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    /// Aggregate distinct values only
+    pub fn distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
+    /// Aggregate only the rows matching `condition`, with columns qualified
+    /// by table or relationship name
+    pub fn filter(mut self, condition: impl Into<Expr>) -> Self {
+        self.filter = Some(condition.into());
+        self
+    }
+
+    /// The result column's name
+    pub fn name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None if self.column == "*" => self.function.as_sql().to_lowercase(),
+            None => format!("{}__{}", self.column, self.function.as_sql().to_lowercase()),
+        }
+    }
+
+    /// The relationship of `M` aggregated over, if any
+    fn relation<M: Model>(&self) -> Option<&str> {
+        let name = self.column.split("__").next().unwrap_or(&self.column);
+        M::meta()
+            .relationships
+            .iter()
+            .any(|r| r.name == name)
+            .then_some(name)
+    }
+
+    /// The aggregate expression over `column` of `table`
+    fn expr(&self, table: &str, column: &str) -> Expr {
+        let column = if column == "*" {
+            column.to_string()
+        } else {
+            format!("{}.{}", table, column)
+        };
+        Expr::Aggregate {
+            function: self.function.clone(),
+            column,
+            distinct: self.distinct,
+            filter: self.filter.clone().map(Box::new),
+            order_by: Vec::new(),
+            separator: None,
+        }
+    }
+}
+
+/// Count rows, or non-null values of a column
+#[allow(non_snake_case)]
+pub fn Count(column: &str) -> Aggregate {
+    Aggregate::new(AggregateFunc::Count, column)
+}
+
+/// Sum a column
+#[allow(non_snake_case)]
+pub fn Sum(column: &str) -> Aggregate {
+    Aggregate::new(AggregateFunc::Sum, column)
+}
+
+/// Average a column
+#[allow(non_snake_case)]
+pub fn Avg(column: &str) -> Aggregate {
+    Aggregate::new(AggregateFunc::Avg, column)
+}
+
+/// Smallest value of a column
+#[allow(non_snake_case)]
+pub fn Min(column: &str) -> Aggregate {
+    Aggregate::new(AggregateFunc::Min, column)
+}
+
+/// Largest value of a column
+#[allow(non_snake_case)]
+pub fn Max(column: &str) -> Aggregate {
+    Aggregate::new(AggregateFunc::Max, column)
+}
+
+/// Select `aggregates` over `M`'s table, LEFT JOINing each relationship they
+/// use that isn't in `joined` yet
+fn join_aggregates<M: Model>(
+    mut builder: QueryBuilder,
+    aggregates: &[Aggregate],
+    joined: &mut Vec<String>,
+) -> Result<QueryBuilder> {
+    for aggregate in aggregates {
+        let expr = match aggregate.relation::<M>() {
+            Some(name) => {
+                let (_, target) = relation::<M>(name)?;
+                if !joined.iter().any(|j| j == name) {
+                    builder = join_relation::<M>(builder, name)?;
+                    joined.push(name.to_string());
+                }
+                let field = match aggregate.column.split_once("__") {
+                    Some((_, field)) => field,
+                    None => primary_key(target)?,
+                };
+                aggregate.expr(name, field)
+            }
+            None => aggregate.expr(M::table_name(), &aggregate.column),
+        };
+        builder = builder.column_expr(expr, aggregate.name());
+    }
+    Ok(builder)
+}
+
+/// LEFT JOIN the rows of relationship `name`, aliased as `name`
+fn join_relation<M: Model>(builder: QueryBuilder, name: &str) -> Result<QueryBuilder> {
+    let (relation, target) = relation::<M>(name)?;
+    if let RelationType::ManyToOne | RelationType::OneToOne = relation.relation_type {
+        let (table, on) = related_join::<M>(name)?;
+        return Ok(builder.left_join_as(table, name, on));
+    }
+    let plan = plan_prefetch::<M>(name)?;
+    let owner_key = format!("{}.{}", M::table_name(), plan.owner_field);
+    match relation.relation_type {
+        RelationType::ManyToMany => {
+            // Through the link table, aliased so the target can take `name`
+            let through = relation.through_table.clone().unwrap_or_default();
+            let link = format!("{}__through", name);
+            let related_key = relation.related_key.clone().unwrap_or_default();
+            let foreign_key = relation.foreign_key.clone().unwrap_or_default();
+            Ok(builder
+                .left_join_as(
+                    through,
+                    link.clone(),
+                    Expr::ColumnCompare {
+                        left: format!("{}.{}", link, foreign_key),
+                        op: CompareOp::Eq,
+                        right: owner_key,
+                    },
+                )
+                .left_join_as(
+                    target.table.clone(),
+                    name,
+                    Expr::ColumnCompare {
+                        left: format!("{}.{}", name, primary_key(target)?),
+                        op: CompareOp::Eq,
+                        right: format!("{}.{}", link, related_key),
+                    },
+                ))
+        }
+        _ => Ok(builder.left_join_as(
+            target.table.clone(),
+            name,
+            Expr::ColumnCompare {
+                left: format!("{}.{}", name, plan.column),
+                op: CompareOp::Eq,
+                right: owner_key,
+            },
+        )),
+    }
+}
+
+/// Look up relationship `name` of `M` and the metadata of its target
+fn relation<M: Model>(name: &str) -> Result<(&'static RelationMeta, &'static ModelMeta)> {
+    let meta = M::meta();
+    let relation = meta.relationships.iter().find(|r| r.name == name);
+    match (relation, M::related_meta(name)) {
+        (Some(relation), Some(target)) => Ok((relation, target)),
+        _ => Err(ChakraError::Model(ModelError::InvalidRelationship {
+            model: meta.name.clone(),
+            relationship: name.to_string(),
+        })),
+    }
+}
+
+fn primary_key(meta: &ModelMeta) -> Result<&str> {
+    meta.primary_key.first().map(String::as_str).ok_or_else(|| {
+        ChakraError::Model(ModelError::InvalidField {
+            model: meta.name.clone(),
+            field: "primary key".to_string(),
+        })
+    })
+}
+
+/// The column of key field `key`, which may already be a column
+fn key_column<'a>(meta: &'a ModelMeta, key: &'a str) -> &'a str {
+    meta.get_field(key).map_or(key, |field| field.column_name())
+}
+
+/// The table `select_related` joins for relationship `name`, and the join
+/// condition against the related table aliased as `name`
+fn related_join<M: Model>(name: &str) -> Result<(String, Expr)> {
+    let (relation, target) = relation::<M>(name)?;
+    let foreign_key = match (&relation.relation_type, &relation.foreign_key) {
+        (RelationType::ManyToOne | RelationType::OneToOne, Some(fk)) => fk,
+        _ => {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: format!(
+                    "select_related needs the foreign key of '{}' on {}; use prefetch_related",
+                    name,
+                    M::meta().name
+                ),
+            }))
+        }
+    };
+    let on = Expr::ColumnCompare {
+        left: format!("{}.{}", name, key_column(target, primary_key(target)?)),
+        op: CompareOp::Eq,
+        right: format!("{}.{}", M::table_name(), key_column(M::meta(), foreign_key)),
+    };
+    Ok((target.table.clone(), on))
+}
+
+/// How `prefetch_related` loads one relationship
+struct PrefetchPlan {
+    /// The related rows, before filtering by key
+    select: QueryBuilder,
+    /// Column filtered with `IN` over the keys
+    column: String,
+    /// Field of the fetched models holding the keys
+    owner_field: String,
+    /// Column of the related rows matching those keys
+    row_key: String,
+}
+
+fn plan_prefetch<M: Model>(name: &str) -> Result<PrefetchPlan> {
+    let (relation, target) = relation::<M>(name)?;
+    let missing = |what: &str| {
+        ChakraError::Model(ModelError::InvalidRelationship {
+            model: M::meta().name.clone(),
+            relationship: format!("{} (no {})", name, what),
+        })
+    };
+    let foreign_key = relation
+        .foreign_key
+        .as_deref()
+        .ok_or_else(|| missing("foreign key"))?;
+    let target_key = primary_key(target)?;
+    let select = Query::select().from(target.table.clone());
+
+    Ok(match relation.relation_type {
+        RelationType::ManyToOne | RelationType::OneToOne => PrefetchPlan {
+            select,
+            column: target_key.to_string(),
+            owner_field: foreign_key.to_string(),
+            row_key: target_key.to_string(),
+        },
+        RelationType::OneToMany => PrefetchPlan {
+            select,
+            column: foreign_key.to_string(),
+            owner_field: primary_key(M::meta())?.to_string(),
+            row_key: foreign_key.to_string(),
+        },
+        RelationType::ManyToMany => {
+            let through = relation
+                .through_table
+                .as_deref()
+                .ok_or_else(|| missing("through table"))?;
+            let related_key = relation
+                .related_key
+                .as_deref()
+                .ok_or_else(|| missing("related key"))?;
+            PrefetchPlan {
+                select: select
+                    .column(format!("{}.*", target.table))
+                    .column(format!("{}.{} AS {}", through, foreign_key, OWNER_COLUMN))
+                    .join(
+                        through,
+                        Expr::ColumnCompare {
+                            left: format!("{}.{}", through, related_key),
+                            op: CompareOp::Eq,
+                            right: format!("{}.{}", target.table, target_key),
+                        },
+                    ),
+                column: format!("{}.{}", through, foreign_key),
+                owner_field: primary_key(M::meta())?.to_string(),
+                row_key: OWNER_COLUMN.to_string(),
+            }
+        }
+    })
+}
+
+/// Fill relationship `name` of every model with one batched `IN` query
+async fn prefetch<M: Model>(executor: &dyn Executor, models: &mut [M], name: &str) -> Result<()> {
+    let plan = plan_prefetch::<M>(name)?;
+    let keys: Vec<_> = models
+        .iter()
+        .map(|m| m.get_field(&plan.owner_field))
+        .collect::<Result<_>>()?;
+    let executor = match M::related_meta(name) {
+        Some(meta) => route(executor, meta)?,
+        None => executor,
+    };
+    let rows = fetch_in(executor, plan.select, &plan.column, &keys).await?;
+
+    // Keys compare by their SQL literal, so an INTEGER read back as Int64
+    // matches an Int32 field
+    let mut by_key: HashMap<String, Vec<Row>> = HashMap::new();
+    for row in rows {
+        if let Some(key) = row.get(&plan.row_key) {
+            by_key.entry(key.to_sql_literal()).or_default().push(row);
+        }
+    }
+    for (model, key) in models.iter_mut().zip(keys) {
+        let related = key
+            .and_then(|key| by_key.get(&key.to_sql_literal()))
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        model.set_related(name, related)?;
+    }
+    Ok(())
+}
+
+/// Run `select` for rows whose `column` is one of `keys`, in batches that
+/// fit the dialect's parameter limit
+async fn fetch_in(
+    executor: &dyn Executor,
+    select: QueryBuilder,
+    column: &str,
+    keys: &[Option<Value>],
+) -> Result<Vec<Row>> {
+    let mut distinct: Vec<Value> = Vec::new();
+    for key in keys.iter().flatten().filter(|key| !key.is_null()) {
+        if !distinct.contains(key) {
+            distinct.push(key.clone());
+        }
+    }
+    let mut rows = Vec::new();
+    for batch in distinct.chunks(executor.dialect().max_params().max(1)) {
+        let query = select
+            .clone()
+            .filter(Expr::is_in(column, batch.to_vec()))
+            .unlimited()
+            .build();
+        rows.extend(executor.fetch_all(&query).await?);
+    }
+    Ok(rows)
+}
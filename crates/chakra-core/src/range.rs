@@ -0,0 +1,292 @@
+//! Range values
+//!
+//! A `Range<T>` is a span of integers, decimals, dates or timestamps with
+//! each bound inclusive, exclusive or missing, stored natively by
+//! PostgreSQL as `int4range`, `tstzrange` and the like. Rust ranges convert
+//! into it, so a booking's slot is written as:
+//!
+//! ```rust,ignore
+//! #[derive(Model)]
+//! struct Booking {
+//!     #[chakra(primary_key)]
+//!     id: i64,
+//!     during: Range<DateTime<Utc>>,
+//! }
+//!
+//! let booking = Booking { id: 0, during: (start..end).into() };
+//! let clashes = Booking::objects()
+//!     .filter(Expr::range_overlaps("during", booking.during.clone()))
+//!     .count(&executor)
+//!     .await?;
+//! ```
+//!
+//! Other databases store the range as text in PostgreSQL's format, such as
+//! `[1,10)`, and have no range operators.
+
+use crate::error::{ChakraError, Result};
+use crate::result::FromValue;
+use crate::types::Value;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Bound, RangeFrom, RangeInclusive, RangeTo};
+
+/// A range of values, possibly empty or unbounded on either side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Range<T> {
+    lower: Bound<T>,
+    upper: Bound<T>,
+    empty: bool,
+}
+
+impl<T> Range<T> {
+    /// A range between two bounds
+    pub fn new(lower: Bound<T>, upper: Bound<T>) -> Self {
+        Self {
+            lower,
+            upper,
+            empty: false,
+        }
+    }
+
+    /// The range holding nothing
+    pub fn empty() -> Self {
+        Self {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            empty: true,
+        }
+    }
+
+    /// The lower bound, `Unbounded` if the range is empty
+    pub fn lower(&self) -> Bound<&T> {
+        self.lower.as_ref()
+    }
+
+    /// The upper bound, `Unbounded` if the range is empty
+    pub fn upper(&self) -> Bound<&T> {
+        self.upper.as_ref()
+    }
+
+    /// Whether this is the empty range
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    /// Convert the bound values, keeping their inclusivity
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Range<U> {
+        let mut bound = |b: Bound<T>| match b {
+            Bound::Included(v) => Bound::Included(f(v)),
+            Bound::Excluded(v) => Bound::Excluded(f(v)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Range {
+            lower: bound(self.lower),
+            upper: bound(self.upper),
+            empty: self.empty,
+        }
+    }
+
+    /// Convert the bound values, failing on the first that fails
+    pub fn try_map<U>(self, mut f: impl FnMut(T) -> Result<U>) -> Result<Range<U>> {
+        let mut bound = |b: Bound<T>| -> Result<Bound<U>> {
+            Ok(match b {
+                Bound::Included(v) => Bound::Included(f(v)?),
+                Bound::Excluded(v) => Bound::Excluded(f(v)?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        Ok(Range {
+            lower: bound(self.lower)?,
+            upper: bound(self.upper)?,
+            empty: self.empty,
+        })
+    }
+}
+
+/// `start..end`, inclusive of `start` only
+impl<T> From<std::ops::Range<T>> for Range<T> {
+    fn from(range: std::ops::Range<T>) -> Self {
+        Self::new(Bound::Included(range.start), Bound::Excluded(range.end))
+    }
+}
+
+impl<T> From<RangeInclusive<T>> for Range<T> {
+    fn from(range: RangeInclusive<T>) -> Self {
+        let (start, end) = range.into_inner();
+        Self::new(Bound::Included(start), Bound::Included(end))
+    }
+}
+
+impl<T> From<RangeFrom<T>> for Range<T> {
+    fn from(range: RangeFrom<T>) -> Self {
+        Self::new(Bound::Included(range.start), Bound::Unbounded)
+    }
+}
+
+impl<T> From<RangeTo<T>> for Range<T> {
+    fn from(range: RangeTo<T>) -> Self {
+        Self::new(Bound::Unbounded, Bound::Excluded(range.end))
+    }
+}
+
+impl<T: Into<Value>> From<Range<T>> for Value {
+    fn from(range: Range<T>) -> Self {
+        Value::Range(Box::new(range.map(Into::into)))
+    }
+}
+
+/// Ranges read from other databases are text, whose bounds are decoded
+/// leniently since they arrive as strings too
+impl<T: FromValue> FromValue for Range<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Range(range) => (**range).clone().try_map(|v| T::from_value(&v)),
+            Value::String(s) => s
+                .parse::<Range<String>>()?
+                .try_map(|v| T::from_value_lossy(&Value::String(v))),
+            _ => Err(ChakraError::TypeConversion {
+                message: "Cannot convert to Range".to_string(),
+                from_type: value.type_name().to_string(),
+                to_type: "Range".to_string(),
+            }),
+        }
+    }
+}
+
+/// PostgreSQL's text form, e.g. `[1,10)` or `empty`, with bounds in double
+/// quotes
+impl fmt::Display for Range<Value> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.empty {
+            return f.write_str("empty");
+        }
+        let text = |bound: &Bound<Value>| match bound {
+            Bound::Included(v) | Bound::Excluded(v) => {
+                let text = match v {
+                    Value::String(s) => s.clone(),
+                    Value::DateTime(dt) => dt.to_rfc3339(),
+                    _ => v.to_sql_literal().trim_matches('\'').replace("''", "'"),
+                };
+                format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            Bound::Unbounded => String::new(),
+        };
+        let open = if matches!(self.lower, Bound::Included(_)) {
+            '['
+        } else {
+            '('
+        };
+        let close = if matches!(self.upper, Bound::Included(_)) {
+            ']'
+        } else {
+            ')'
+        };
+        write!(
+            f,
+            "{}{},{}{}",
+            open,
+            text(&self.lower),
+            text(&self.upper),
+            close
+        )
+    }
+}
+
+impl std::str::FromStr for Range<String> {
+    type Err = ChakraError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ChakraError::internal(format!("invalid range '{}'", s));
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("empty") {
+            return Ok(Self::empty());
+        }
+        let lower_inclusive = match s.chars().next() {
+            Some('[') => true,
+            Some('(') => false,
+            _ => return Err(invalid()),
+        };
+        let upper_inclusive = match s.chars().last() {
+            Some(']') => true,
+            Some(')') => false,
+            _ => return Err(invalid()),
+        };
+        let (lower, rest) = parse_bound(&s[1..s.len() - 1]).ok_or_else(invalid)?;
+        let rest = rest.strip_prefix(',').ok_or_else(invalid)?;
+        let (upper, rest) = parse_bound(rest).ok_or_else(invalid)?;
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+        let bound = |value: Option<String>, inclusive: bool| match value {
+            Some(v) if inclusive => Bound::Included(v),
+            Some(v) => Bound::Excluded(v),
+            None => Bound::Unbounded,
+        };
+        Ok(Self::new(
+            bound(lower, lower_inclusive),
+            bound(upper, upper_inclusive),
+        ))
+    }
+}
+
+/// Read one bound, quoted or bare, returning it and the text after it;
+/// `None` for a missing bound
+fn parse_bound(s: &str) -> Option<(Option<String>, &str)> {
+    let Some(quoted) = s.strip_prefix('"') else {
+        let end = s.find(',').unwrap_or(s.len());
+        let value = s[..end].trim();
+        return Some(((!value.is_empty()).then(|| value.to_string()), &s[end..]));
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?.1),
+            '"' => return Some((Some(value), &quoted[i + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_range_text() {
+        let range: Range<Value> = Range::from(1..10).map(Value::Int32);
+        assert_eq!(range.to_string(), "[\"1\",\"10\")");
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let range: Range<Value> = Range::from(..day).map(Value::Date);
+        assert_eq!(range.to_string(), "(,\"2024-05-01\")");
+        assert_eq!(Range::<Value>::empty().to_string(), "empty");
+
+        let parsed: Range<String> = "[\"a,\\\"b\",z]".parse().unwrap();
+        assert_eq!(parsed.lower(), Bound::Included(&"a,\"b".to_string()));
+        assert_eq!(parsed.upper(), Bound::Included(&"z".to_string()));
+        let parsed: Range<String> = "(,5)".parse().unwrap();
+        assert_eq!(parsed.lower(), Bound::Unbounded);
+        assert!("[1,2".parse::<Range<String>>().is_err());
+        assert!("[1,2,3]".parse::<Range<String>>().is_err());
+    }
+
+    #[test]
+    fn test_range_from_value() {
+        let value = Value::from(Range::from(1..=5));
+        assert_eq!(
+            Range::<i32>::from_value(&value).unwrap(),
+            Range::from(1..=5)
+        );
+
+        // Text from MySQL or SQLite
+        let value = Value::String(Range::from(3i64..).map(Value::Int64).to_string());
+        assert_eq!(
+            Range::<i64>::from_value(&value).unwrap(),
+            Range::from(3i64..)
+        );
+        assert!(Range::<i64>::from_value(&Value::String("[a,b)".into())).is_err());
+        assert!(Range::<i64>::from_value(&Value::Int64(1)).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Hand-written SQL decoded into models
+//!
+//! `RawQuery` is the escape hatch for queries the builder can't express.
+//! Parameters are still bound, never spliced into the SQL:
+//!
+//! ```rust,ignore
+//! let users = User::raw("SELECT * FROM users WHERE lower(email) = lower($1)", vec![email.into()])
+//!     .all(&executor)
+//!     .await?;
+//!
+//! // `sql!` checks the placeholders against the arguments at compile time
+//! let top: Vec<(String, i64)> = RawQuery::new(sql!(
+//!     "SELECT name, count(*) FROM posts WHERE created_at > $1 GROUP BY name", since
+//! ))
+//! .all(&executor)
+//! .await?;
+//! ```
+//!
+//! Write placeholders as `$1`, `?` or `:name`; they are rewritten for the
+//! executor's dialect, and a placeholder without a value or a value without
+//! a placeholder is an error before anything is sent. Default scopes and
+//! tenant filters are not applied to raw SQL.
+
+use crate::error::{ChakraError, QueryError, Result};
+use crate::executor::Executor;
+use crate::result::{FromRow, Row, RowStream};
+use crate::sql::SqlFragment;
+
+/// A hand-written query whose rows decode as `T`
+pub struct RawQuery<T> {
+    fragment: SqlFragment,
+    decode: fn(&Row) -> Result<T>,
+}
+
+impl<T: FromRow> RawQuery<T> {
+    /// A query decoding rows with `T`'s `FromRow`
+    pub fn new(fragment: SqlFragment) -> Self {
+        Self::with_decoder(fragment, T::from_row)
+    }
+}
+
+impl<T> RawQuery<T> {
+    /// A query decoding rows with `decode`
+    pub fn with_decoder(fragment: SqlFragment, decode: fn(&Row) -> Result<T>) -> Self {
+        Self { fragment, decode }
+    }
+
+    /// The SQL and parameters as written
+    pub fn fragment(&self) -> &SqlFragment {
+        &self.fragment
+    }
+
+    /// Check the parameters and rewrite the placeholders for the executor
+    fn prepare(&self, executor: &dyn Executor) -> Result<SqlFragment> {
+        self.fragment.check_params(executor.dialect())?;
+        self.fragment.to_positional(executor.dialect())
+    }
+
+    /// Fetch and decode every row
+    pub async fn all(&self, executor: &dyn Executor) -> Result<Vec<T>> {
+        let fragment = self.prepare(executor)?;
+        let rows = executor.query(&fragment.sql, &fragment.params).await?;
+        rows.iter().map(self.decode).collect()
+    }
+
+    /// Fetch the first row, if any
+    pub async fn first(&self, executor: &dyn Executor) -> Result<Option<T>> {
+        let fragment = self.prepare(executor)?;
+        let rows = executor.query(&fragment.sql, &fragment.params).await?;
+        rows.first().map(self.decode).transpose()
+    }
+
+    /// Fetch exactly one row, failing with `NotFound` or `MultipleResults`
+    /// if there isn't exactly one
+    pub async fn get(&self, executor: &dyn Executor) -> Result<T> {
+        let fragment = self.prepare(executor)?;
+        let rows = executor.query(&fragment.sql, &fragment.params).await?;
+        match rows.as_slice() {
+            [row] => (self.decode)(row),
+            [] => Err(ChakraError::Query(QueryError::NotFound)),
+            _ => Err(ChakraError::Query(QueryError::MultipleResults)),
+        }
+    }
+}
+
+impl<T: Send + 'static> RawQuery<T> {
+    /// Stream the rows as they are fetched
+    pub fn stream<'a>(&self, executor: &'a dyn Executor) -> Result<RowStream<'a, T>> {
+        let fragment = self.prepare(executor)?;
+        let decode = self.decode;
+        Ok(
+            RowStream::new(executor.stream(fragment.sql, fragment.params))
+                .map_rows(move |row| decode(&row)),
+        )
+    }
+}
+
+impl<T> Clone for RawQuery<T> {
+    fn clone(&self) -> Self {
+        Self {
+            fragment: self.fragment.clone(),
+            decode: self.decode,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for RawQuery<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawQuery")
+            .field("fragment", &self.fragment)
+            .finish()
+    }
+}
@@ -3,11 +3,22 @@
 //! This module provides:
 //! - `Row` - A database row
 //! - `FromRow` - Trait for deserializing rows
-//! - `RowStream` - Async stream of rows
+//! - `RowStream` - Stream of rows decoded as they are fetched
 
 use crate::error::{ChakraError, Result};
-use crate::types::Value;
+use crate::identifier::{folds_identifiers, IdentifierPolicy};
+use crate::net::{Cidr, MacAddr};
+use crate::types::{DbEnum, Value};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
 
 /// A database row
 #[derive(Debug, Clone)]
@@ -19,11 +30,7 @@ pub struct Row {
 impl Row {
     /// Create a new row from columns and values
     pub fn new(columns: Vec<String>, values: Vec<Value>) -> Self {
-        let values_map: HashMap<String, Value> = columns
-            .iter()
-            .cloned()
-            .zip(values.into_iter())
-            .collect();
+        let values_map: HashMap<String, Value> = columns.iter().cloned().zip(values).collect();
         Self {
             columns,
             values: values_map,
@@ -37,8 +44,19 @@ impl Row {
     }
 
     /// Get a value by column name
+    ///
+    /// While a dialect's identifier policy folds names, a name is also
+    /// found under its folded form, which is how the database reports it.
     pub fn get(&self, column: &str) -> Option<&Value> {
-        self.values.get(column)
+        self.values.get(column).or_else(|| {
+            if !folds_identifiers() {
+                return None;
+            }
+            match IdentifierPolicy::FoldLower.fold(column) {
+                Cow::Owned(folded) => self.values.get(&folded),
+                Cow::Borrowed(_) => None,
+            }
+        })
     }
 
     /// Get a value by column index
@@ -48,9 +66,9 @@ impl Row {
 
     /// Get value as a specific type
     pub fn get_as<T: FromValue>(&self, column: &str) -> Result<T> {
-        let value = self.get(column).ok_or_else(|| {
-            ChakraError::internal(format!("Column not found: {}", column))
-        })?;
+        let value = self
+            .get(column)
+            .ok_or_else(|| ChakraError::internal(format!("Column not found: {}", column)))?;
         T::from_value(value)
     }
 
@@ -73,9 +91,28 @@ impl Row {
         &self.values
     }
 
+    /// Take the values, keyed by column name
+    pub fn into_values(self) -> HashMap<String, Value> {
+        self.values
+    }
+
+    /// The columns named with `prefix`, without it, e.g. `author_id` and
+    /// `author_name` as `id` and `name` for the prefix `author_`
+    pub fn with_prefix(&self, prefix: &str) -> Row {
+        let (columns, values) = self
+            .columns
+            .iter()
+            .filter_map(|column| {
+                let name = column.strip_prefix(prefix)?;
+                Some((name.to_string(), self.values.get(column)?.clone()))
+            })
+            .unzip();
+        Row::new(columns, values)
+    }
+
     /// Check if column exists
     pub fn has_column(&self, column: &str) -> bool {
-        self.values.contains_key(column)
+        self.get(column).is_some()
     }
 
     /// Number of columns
@@ -90,8 +127,42 @@ impl Row {
 }
 
 /// Trait for converting from Value
+///
+/// `from_value` converts only when nothing is lost: `3.0` reads as an `i64`
+/// but `3.5` does not, and a `DateTime` reads as a `NaiveDate` only at
+/// midnight. `from_value_lossy` also rounds, truncates and drops parts, and
+/// parses numbers from strings; decode with `Lossy<T>` or
+/// `#[chakra(lossy)]` to use it.
 pub trait FromValue: Sized {
     fn from_value(value: &Value) -> Result<Self>;
+
+    /// Convert, accepting conversions that lose precision or parts
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        Self::from_value(value)
+    }
+}
+
+/// A `T` decoded with `FromValue::from_value_lossy`, e.g.
+/// `row.get_as::<Lossy<i64>>("average")?.0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Lossy<T>(pub T);
+
+impl<T: FromValue> FromValue for Lossy<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        T::from_value_lossy(value).map(Lossy)
+    }
+}
+
+fn conversion_error(value: &Value, to_type: &str, message: impl Into<String>) -> ChakraError {
+    ChakraError::TypeConversion {
+        message: message.into(),
+        from_type: value.type_name().to_string(),
+        to_type: to_type.to_string(),
+    }
+}
+
+fn cannot_convert(value: &Value, to_type: &str) -> ChakraError {
+    conversion_error(value, to_type, format!("Cannot convert to {}", to_type))
 }
 
 impl FromValue for bool {
@@ -100,44 +171,61 @@ impl FromValue for bool {
             Value::Bool(b) => Ok(*b),
             Value::Int32(i) => Ok(*i != 0),
             Value::Int64(i) => Ok(*i != 0),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to bool".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "bool".to_string(),
-            }),
+            _ => Err(cannot_convert(value, "bool")),
         }
     }
 }
 
+/// An integer from `value`, truncating fractions and parsing strings only
+/// when `lossy`
+fn integer(value: &Value, to_type: &str, lossy: bool) -> Result<i64> {
+    let overflow = || conversion_error(value, to_type, "Integer overflow");
+    match value {
+        Value::Int32(i) => Ok(*i as i64),
+        Value::Int64(i) => Ok(*i),
+        Value::Float64(f) if f.fract() == 0.0 || (lossy && f.is_finite()) => {
+            let f = f.trunc();
+            // i64::MAX as f64 rounds up to 2^63, which is out of range
+            if f >= i64::MIN as f64 && f < i64::MAX as f64 {
+                Ok(f as i64)
+            } else {
+                Err(overflow())
+            }
+        }
+        Value::Decimal(d) if d.fract().is_zero() || lossy => {
+            d.trunc().to_i64().ok_or_else(overflow)
+        }
+        Value::Bool(b) if lossy => Ok(*b as i64),
+        Value::String(s) if lossy => match s.trim().parse::<i64>() {
+            Ok(i) => Ok(i),
+            Err(_) => integer(&Value::Decimal(parse_decimal(value, s)?), to_type, true),
+        },
+        Value::Float64(_) | Value::Decimal(_) => {
+            Err(conversion_error(value, to_type, "Value has a fraction"))
+        }
+        _ => Err(cannot_convert(value, to_type)),
+    }
+}
+
 impl FromValue for i32 {
     fn from_value(value: &Value) -> Result<Self> {
-        match value {
-            Value::Int32(i) => Ok(*i),
-            Value::Int64(i) => i32::try_from(*i).map_err(|_| ChakraError::TypeConversion {
-                message: "Integer overflow".to_string(),
-                from_type: "i64".to_string(),
-                to_type: "i32".to_string(),
-            }),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to i32".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "i32".to_string(),
-            }),
-        }
+        i32::try_from(integer(value, "i32", false)?)
+            .map_err(|_| conversion_error(value, "i32", "Integer overflow"))
+    }
+
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        i32::try_from(integer(value, "i32", true)?)
+            .map_err(|_| conversion_error(value, "i32", "Integer overflow"))
     }
 }
 
 impl FromValue for i64 {
     fn from_value(value: &Value) -> Result<Self> {
-        match value {
-            Value::Int32(i) => Ok(*i as i64),
-            Value::Int64(i) => Ok(*i),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to i64".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "i64".to_string(),
-            }),
-        }
+        integer(value, "i64", false)
+    }
+
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        integer(value, "i64", true)
     }
 }
 
@@ -147,11 +235,56 @@ impl FromValue for f64 {
             Value::Float64(f) => Ok(*f),
             Value::Int32(i) => Ok(*i as f64),
             Value::Int64(i) => Ok(*i as f64),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to f64".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "f64".to_string(),
-            }),
+            // Only decimals a float holds exactly, like 2.5 but not 0.1 + 1e-20
+            Value::Decimal(d) => d
+                .to_f64()
+                .filter(|f| decimal_from_f64(*f) == Some(*d))
+                .ok_or_else(|| conversion_error(value, "f64", "Decimal has no exact f64")),
+            _ => Err(cannot_convert(value, "f64")),
+        }
+    }
+
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::Decimal(d) => d.to_f64().ok_or_else(|| cannot_convert(value, "f64")),
+            Value::String(s) => s
+                .trim()
+                .parse()
+                .map_err(|_| conversion_error(value, "f64", "Invalid number string")),
+            _ => Self::from_value(value),
+        }
+    }
+}
+
+/// The shortest decimal that reads back as `f`, e.g. 19.99 rather than
+/// 19.989999999999998436805981327779591083526611328125
+fn decimal_from_f64(f: f64) -> Option<Decimal> {
+    if !f.is_finite() {
+        return None;
+    }
+    // f64's Display is the shortest round-tripping form, without exponent
+    Decimal::from_str(&f.to_string()).ok()
+}
+
+fn parse_decimal(value: &Value, s: &str) -> Result<Decimal> {
+    let s = s.trim();
+    Decimal::from_str(s)
+        .or_else(|_| Decimal::from_scientific(s))
+        .map_err(|_| conversion_error(value, "Decimal", "Invalid decimal string"))
+}
+
+impl FromValue for Decimal {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Decimal(d) => Ok(*d),
+            Value::Int32(i) => Ok(Decimal::from(*i)),
+            Value::Int64(i) => Ok(Decimal::from(*i)),
+            // SQLite keeps DECIMAL columns as REAL; read back what was written
+            Value::Float64(f) => decimal_from_f64(*f)
+                .ok_or_else(|| conversion_error(value, "Decimal", "Float out of Decimal range")),
+            // PostgreSQL NUMERIC over the text protocol, MySQL DECIMAL
+            Value::String(s) => parse_decimal(value, s),
+            _ => Err(cannot_convert(value, "Decimal")),
         }
     }
 }
@@ -160,24 +293,136 @@ impl FromValue for String {
     fn from_value(value: &Value) -> Result<Self> {
         match value {
             Value::String(s) => Ok(s.clone()),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to String".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "String".to_string(),
-            }),
+            _ => Err(cannot_convert(value, "String")),
         }
     }
+
+    /// Any scalar as text, timestamps in RFC 3339
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Int32(i) => Ok(i.to_string()),
+            Value::Int64(i) => Ok(i.to_string()),
+            Value::Float64(f) => Ok(f.to_string()),
+            Value::Decimal(d) => Ok(d.to_string()),
+            Value::Uuid(u) => Ok(u.to_string()),
+            Value::IpAddr(a) => Ok(a.to_string()),
+            Value::Cidr(c) => Ok(c.to_string()),
+            Value::MacAddr(m) => Ok(m.to_string()),
+            Value::Range(r) => Ok(r.to_string()),
+            Value::DateTime(dt) => Ok(dt.to_rfc3339()),
+            Value::Date(d) => Ok(d.to_string()),
+            Value::Time(t) => Ok(t.to_string()),
+            Value::Json(j) => Ok(j.to_string()),
+            _ => Self::from_value(value),
+        }
+    }
+}
+
+/// A timestamp without offset, as `2024-05-01 12:30:00` or
+/// `2024-05-01T12:30:00.123`
+fn parse_naive_datetime(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::from_str(s))
+        .ok()
+}
+
+fn invalid_string(value: &Value, to_type: &str) -> ChakraError {
+    conversion_error(value, to_type, format!("Invalid {} string", to_type))
 }
 
 impl FromValue for chrono::DateTime<chrono::Utc> {
     fn from_value(value: &Value) -> Result<Self> {
         match value {
             Value::DateTime(dt) => Ok(*dt),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to DateTime".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "DateTime".to_string(),
+            Value::Date(d) => Ok(d.and_time(NaiveTime::MIN).and_utc()),
+            Value::String(s) => chrono::DateTime::parse_from_rfc3339(s.trim())
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+                .map_err(|_| invalid_string(value, "DateTime")),
+            _ => Err(cannot_convert(value, "DateTime")),
+        }
+    }
+
+    /// Also reads timestamps without an offset as UTC
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Self::from_value(value)
+                .or_else(|e| parse_naive_datetime(s).map(|dt| dt.and_utc()).ok_or(e)),
+            _ => Self::from_value(value),
+        }
+    }
+}
+
+impl FromValue for NaiveDateTime {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::DateTime(dt) => Ok(dt.naive_utc()),
+            Value::Date(d) => Ok(d.and_time(NaiveTime::MIN)),
+            Value::String(s) => {
+                parse_naive_datetime(s).ok_or_else(|| invalid_string(value, "NaiveDateTime"))
+            }
+            _ => Err(cannot_convert(value, "NaiveDateTime")),
+        }
+    }
+
+    /// Also reads timestamps with an offset, converted to UTC
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(_) => Self::from_value(value).or_else(|_| {
+                chrono::DateTime::<chrono::Utc>::from_value(value).map(|dt| dt.naive_utc())
             }),
+            _ => Self::from_value(value),
+        }
+    }
+}
+
+impl FromValue for NaiveDate {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Date(d) => Ok(*d),
+            Value::DateTime(dt) if dt.time() == NaiveTime::MIN => Ok(dt.date_naive()),
+            Value::DateTime(_) => Err(conversion_error(
+                value,
+                "NaiveDate",
+                "DateTime is not at midnight",
+            )),
+            Value::String(s) => {
+                NaiveDate::from_str(s.trim()).map_err(|_| invalid_string(value, "NaiveDate"))
+            }
+            _ => Err(cannot_convert(value, "NaiveDate")),
+        }
+    }
+
+    /// Also takes the date of any timestamp
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::DateTime(dt) => Ok(dt.date_naive()),
+            Value::String(_) => Self::from_value(value)
+                .or_else(|_| NaiveDateTime::from_value_lossy(value).map(|dt| dt.date())),
+            _ => Self::from_value(value),
+        }
+    }
+}
+
+impl FromValue for NaiveTime {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Time(t) => Ok(*t),
+            Value::String(s) => {
+                NaiveTime::from_str(s.trim()).map_err(|_| invalid_string(value, "NaiveTime"))
+            }
+            _ => Err(cannot_convert(value, "NaiveTime")),
+        }
+    }
+
+    /// Also takes the time of day of any timestamp
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::DateTime(dt) => Ok(dt.time()),
+            Value::String(_) => Self::from_value(value)
+                .or_else(|_| NaiveDateTime::from_value_lossy(value).map(|dt| dt.time())),
+            _ => Self::from_value(value),
         }
     }
 }
@@ -191,15 +436,49 @@ impl FromValue for uuid::Uuid {
                 from_type: "String".to_string(),
                 to_type: "Uuid".to_string(),
             }),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to Uuid".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "Uuid".to_string(),
-            }),
+            _ => Err(cannot_convert(value, "Uuid")),
         }
     }
 }
 
+/// Network addresses also parse from text, as MySQL and SQLite store them
+macro_rules! from_value_parsed {
+    ($ty:ty, $variant:ident, $name:literal) => {
+        impl FromValue for $ty {
+            fn from_value(value: &Value) -> Result<Self> {
+                match value {
+                    Value::$variant(v) => Ok(*v),
+                    Value::String(s) => s.parse().map_err(|_| {
+                        conversion_error(value, $name, format!("Invalid {} '{}'", $name, s))
+                    }),
+                    _ => Err(cannot_convert(value, $name)),
+                }
+            }
+        }
+    };
+}
+
+from_value_parsed!(IpAddr, IpAddr, "IpAddr");
+from_value_parsed!(Cidr, Cidr, "Cidr");
+from_value_parsed!(MacAddr, MacAddr, "MacAddr");
+
+/// Decode a `DbEnum` from its label, as `#[derive(DbEnum)]` does
+pub fn enum_from_value<E: DbEnum>(value: &Value) -> Result<E> {
+    let to_type = std::any::type_name::<E>();
+    let label = value
+        .as_str()
+        .ok_or_else(|| cannot_convert(value, to_type))?;
+    E::from_label(label)
+        .ok_or_else(|| conversion_error(value, to_type, format!("Unknown enum label '{}'", label)))
+}
+
+/// The value as it is, for decoding it by hand
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
 impl FromValue for serde_json::Value {
     fn from_value(value: &Value) -> Result<Self> {
         match value {
@@ -209,11 +488,7 @@ impl FromValue for serde_json::Value {
                 from_type: "String".to_string(),
                 to_type: "Json".to_string(),
             }),
-            _ => Err(ChakraError::TypeConversion {
-                message: "Cannot convert to Json".to_string(),
-                from_type: value.type_name().to_string(),
-                to_type: "Json".to_string(),
-            }),
+            _ => Err(cannot_convert(value, "Json")),
         }
     }
 }
@@ -225,49 +500,105 @@ impl<T: FromValue> FromValue for Option<T> {
             other => Ok(Some(T::from_value(other)?)),
         }
     }
+
+    fn from_value_lossy(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            other => Ok(Some(T::from_value_lossy(other)?)),
+        }
+    }
 }
 
 /// Trait for types that can be constructed from a database row
 pub trait FromRow: Sized {
     fn from_row(row: &Row) -> Result<Self>;
+
+    /// Whether every column this type reads is NULL or missing, as on the
+    /// unmatched side of a LEFT JOIN
+    ///
+    /// The default checks every column of the row; derived impls check only
+    /// their fields' columns.
+    fn all_null(row: &Row) -> bool {
+        row.values().values().all(Value::is_null)
+    }
 }
 
-/// Async stream of rows
-pub struct RowStream<T> {
-    _marker: std::marker::PhantomData<T>,
-    // In a real implementation, this would hold the async stream
-    // For now, we use a simple vector
-    rows: Vec<Row>,
-    index: usize,
+impl FromRow for Row {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(row.clone())
+    }
 }
 
-impl<T: FromRow> RowStream<T> {
-    /// Create a new stream from rows
-    pub fn new(rows: Vec<Row>) -> Self {
+/// Tuples decode the row's columns by position
+macro_rules! impl_from_row_tuple {
+    ($($index:tt: $name:ident),+) => {
+        impl<$($name: FromValue),+> FromRow for ($($name,)+) {
+            fn from_row(row: &Row) -> Result<Self> {
+                Ok(($(
+                    $name::from_value(row.get_by_index($index).ok_or_else(|| {
+                        ChakraError::internal(format!("Column not found: {}", $index))
+                    })?)?,
+                )+))
+            }
+        }
+    };
+}
+
+impl_from_row_tuple!(0: A);
+impl_from_row_tuple!(0: A, 1: B);
+impl_from_row_tuple!(0: A, 1: B, 2: C);
+impl_from_row_tuple!(0: A, 1: B, 2: C, 3: D);
+impl_from_row_tuple!(0: A, 1: B, 2: C, 3: D, 4: E);
+impl_from_row_tuple!(0: A, 1: B, 2: C, 3: D, 4: E, 5: F);
+impl_from_row_tuple!(0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G);
+impl_from_row_tuple!(0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G, 7: H);
+
+/// Rows decoded as they arrive from the database
+///
+/// Adapters fetch incrementally (a cursor on PostgreSQL, one row at a time on
+/// SQLite and MySQL), so only the rows not yet consumed are held in memory.
+pub struct RowStream<'a, T> {
+    inner: BoxStream<'a, Result<T>>,
+}
+
+impl<'a> RowStream<'a, Row> {
+    /// Wrap a stream of rows
+    pub fn new(rows: impl Stream<Item = Result<Row>> + Send + 'a) -> Self {
         Self {
-            _marker: std::marker::PhantomData,
-            rows,
-            index: 0,
+            inner: rows.boxed(),
+        }
+    }
+
+    /// A stream over rows already fetched
+    pub fn from_rows(rows: Vec<Row>) -> Self {
+        Self::new(stream::iter(rows.into_iter().map(Ok)))
+    }
+
+    /// Decode each row as `T`
+    pub fn decode<T: FromRow + Send + 'a>(self) -> RowStream<'a, T> {
+        self.map_rows(|row| T::from_row(&row))
+    }
+}
+
+impl<'a, T: Send + 'a> RowStream<'a, T> {
+    /// Convert each item with `f`
+    pub fn map_rows<U>(self, mut f: impl FnMut(T) -> Result<U> + Send + 'a) -> RowStream<'a, U> {
+        RowStream {
+            inner: self.inner.map(move |item| item.and_then(&mut f)).boxed(),
         }
     }
 
-    /// Collect all rows
+    /// Collect all rows, stopping at the first error
     pub async fn collect(self) -> Result<Vec<T>> {
-        self.rows.iter().map(T::from_row).collect()
+        self.inner.try_collect().await
     }
 }
 
-impl<T: FromRow> Iterator for RowStream<T> {
+impl<T> Stream for RowStream<'_, T> {
     type Item = Result<T>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.rows.len() {
-            let row = &self.rows[self.index];
-            self.index += 1;
-            Some(T::from_row(row))
-        } else {
-            None
-        }
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
     }
 }
 
@@ -287,6 +618,122 @@ mod tests {
         assert_eq!(row.get("nonexistent"), None);
     }
 
+    #[test]
+    fn test_row_with_prefix() {
+        let row = Row::new(
+            vec![
+                "id".to_string(),
+                "author_id".to_string(),
+                "author_name".to_string(),
+            ],
+            vec![Value::Int64(1), Value::Null, Value::Null],
+        );
+        let author = row.with_prefix("author_");
+        assert_eq!(author.columns(), ["id", "name"]);
+        assert!(Row::all_null(&author));
+        assert!(!Row::all_null(&row));
+    }
+
+    #[test]
+    fn test_from_value_matrix() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let noon = NaiveTime::from_hms_opt(12, 30, 0).unwrap();
+        let timestamp = date.and_time(noon);
+
+        // Money: exact decimals from every representation adapters produce
+        let price = Decimal::new(1999, 2);
+        assert_eq!(Decimal::from_value(&Value::Decimal(price)).unwrap(), price);
+        assert_eq!(
+            Decimal::from_value(&Value::String("19.99".into())).unwrap(),
+            price
+        );
+        assert_eq!(Decimal::from_value(&Value::Float64(19.99)).unwrap(), price);
+        assert_eq!(
+            Decimal::from_value(&Value::Int64(20)).unwrap(),
+            Decimal::from(20)
+        );
+        assert!(Decimal::from_value(&Value::Float64(f64::NAN)).is_err());
+        assert_eq!(
+            f64::from_value(&Value::Decimal(Decimal::new(25, 1))).unwrap(),
+            2.5
+        );
+        let long = Value::Decimal(Decimal::from_str("0.1000000000000000000001").unwrap());
+        assert!(f64::from_value(&long).is_err());
+        assert_eq!(f64::from_value_lossy(&long).unwrap(), 0.1);
+
+        // Integers only lose fractions when lossy
+        assert_eq!(i64::from_value(&Value::Float64(3.0)).unwrap(), 3);
+        assert!(i64::from_value(&Value::Float64(3.5)).is_err());
+        assert_eq!(i64::from_value_lossy(&Value::Float64(-3.5)).unwrap(), -3);
+        assert_eq!(
+            i32::from_value_lossy(&Value::String(" 1.9 ".into())).unwrap(),
+            1
+        );
+        assert!(i32::from_value_lossy(&Value::Float64(1e10)).is_err());
+        assert!(i64::from_value_lossy(&Value::Float64(f64::INFINITY)).is_err());
+
+        // Dates and times
+        let midnight = Value::DateTime(date.and_time(NaiveTime::MIN).and_utc());
+        assert_eq!(
+            NaiveDate::from_value(&Value::String("2024-05-01".into())).unwrap(),
+            date
+        );
+        assert_eq!(NaiveDate::from_value(&midnight).unwrap(), date);
+        assert!(NaiveDate::from_value(&Value::DateTime(timestamp.and_utc())).is_err());
+        assert_eq!(
+            NaiveDate::from_value_lossy(&Value::DateTime(timestamp.and_utc())).unwrap(),
+            date
+        );
+        assert_eq!(
+            NaiveTime::from_value(&Value::String("12:30:00".into())).unwrap(),
+            noon
+        );
+        assert_eq!(
+            NaiveTime::from_value_lossy(&Value::String("2024-05-01 12:30:00".into())).unwrap(),
+            noon
+        );
+        for text in [
+            "2024-05-01 12:30:00",
+            "2024-05-01T12:30:00",
+            "2024-05-01 12:30:00.000",
+        ] {
+            assert_eq!(
+                NaiveDateTime::from_value(&Value::String(text.into())).unwrap(),
+                timestamp
+            );
+        }
+        assert_eq!(
+            NaiveDateTime::from_value(&Value::Date(date)).unwrap(),
+            date.and_time(NaiveTime::MIN)
+        );
+        let offset = Value::String("2024-05-01T14:30:00+02:00".into());
+        assert!(NaiveDateTime::from_value(&offset).is_err());
+        assert_eq!(NaiveDateTime::from_value_lossy(&offset).unwrap(), timestamp);
+        let utc = chrono::DateTime::<chrono::Utc>::from_value(&offset).unwrap();
+        assert_eq!(utc, timestamp.and_utc());
+        let naive = Value::String("2024-05-01 12:30:00".into());
+        assert!(chrono::DateTime::<chrono::Utc>::from_value(&naive).is_err());
+        assert_eq!(
+            chrono::DateTime::<chrono::Utc>::from_value_lossy(&naive).unwrap(),
+            utc
+        );
+        assert_eq!(Value::from(timestamp), Value::DateTime(utc));
+
+        // Lossy text, and lossy options and wrappers
+        assert_eq!(
+            String::from_value_lossy(&Value::Decimal(price)).unwrap(),
+            "19.99"
+        );
+        assert!(String::from_value(&Value::Int64(1)).is_err());
+        assert_eq!(
+            Option::<i64>::from_value_lossy(&Value::Float64(2.7)).unwrap(),
+            Some(2)
+        );
+        let row = Row::new(vec!["avg".into()], vec![Value::Float64(2.7)]);
+        assert_eq!(row.get_as::<Lossy<i64>>("avg").unwrap(), Lossy(2));
+        assert!(row.get_as::<i64>("avg").is_err());
+    }
+
     #[test]
     fn test_row_get_as() {
         let row = Row::new(
@@ -301,6 +748,66 @@ mod tests {
         assert_eq!(name, "Bob");
     }
 
+    #[test]
+    fn test_tuple_from_row() {
+        let row = Row::new(
+            vec!["id".to_string(), "name".to_string()],
+            vec![Value::Int64(42), Value::Null],
+        );
+
+        let (id, name): (i64, Option<String>) = FromRow::from_row(&row).unwrap();
+        assert_eq!((id, name), (42, None));
+        assert!(<(i64, String, bool)>::from_row(&row).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_row_stream() {
+        let rows = (1..=3)
+            .map(|i| Row::new(vec!["id".to_string()], vec![Value::Int64(i)]))
+            .collect();
+        let ids: Vec<(i64,)> = RowStream::from_rows(rows).decode().collect().await.unwrap();
+        assert_eq!(ids, [(1,), (2,), (3,)]);
+
+        let rows = vec![Row::new(
+            vec!["id".to_string()],
+            vec![Value::String("x".to_string())],
+        )];
+        assert!(RowStream::from_rows(rows)
+            .decode::<(i64,)>()
+            .collect()
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_from_value_network() {
+        let addr: IpAddr = "10.0.0.7".parse().unwrap();
+        assert_eq!(IpAddr::from_value(&Value::IpAddr(addr)).unwrap(), addr);
+        assert_eq!(
+            IpAddr::from_value(&Value::String("10.0.0.7".into())).unwrap(),
+            addr
+        );
+        assert!(IpAddr::from_value(&Value::String("10.0.0.0/8".into())).is_err());
+        assert!(IpAddr::from_value(&Value::Int64(7)).is_err());
+
+        let net = Cidr::new(addr, 24).unwrap();
+        assert_eq!(
+            Cidr::from_value(&Value::String("10.0.0.7/24".into())).unwrap(),
+            net
+        );
+        assert_eq!(
+            String::from_value_lossy(&Value::Cidr(net)).unwrap(),
+            "10.0.0.7/24"
+        );
+
+        let mac = MacAddr([8, 0, 0x2b, 1, 2, 3]);
+        assert_eq!(
+            MacAddr::from_value(&Value::String("08:00:2b:01:02:03".into())).unwrap(),
+            mac
+        );
+        assert_eq!(Value::from(mac).to_sql_literal(), "'08:00:2b:01:02:03'");
+    }
+
     #[test]
     fn test_from_value_option() {
         let null = Value::Null;
@@ -0,0 +1,299 @@
+//! Routing models to named databases
+//!
+//! When a schema is split across physical databases, each model names the
+//! database it lives in, with `#[chakra(database = "analytics")]` or a route
+//! in `chakra.toml`:
+//!
+//! ```toml
+//! [databases.analytics]
+//! url = "postgres://localhost/analytics"
+//!
+//! [routing]
+//! PageView = "analytics"
+//! ```
+//!
+//! A `Router` holds an executor per database and is itself an `Executor`,
+//! so querysets and model methods given a router run on the model's
+//! database:
+//!
+//! ```rust,ignore
+//! let router = Router::new(main_db)
+//!     .database("analytics", analytics_db)
+//!     .routes(config.routing);
+//!
+//! let user = User::get(&router, 1).await?;                  // default database
+//! let views = PageView::objects().count(&router).await?;  // analytics
+//! ```
+//!
+//! Raw SQL and queries that aren't for a model run on the default database.
+//! Joins, `select_related` and transactions can't span databases.
+
+use crate::error::{ChakraError, Result};
+use crate::executor::{ExecResult, Executor};
+use crate::model::ModelMeta;
+use crate::result::Row;
+use crate::sql::Dialect;
+use crate::sql_cache::SqlCache;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the database models use unless routed elsewhere
+pub const DEFAULT_DATABASE: &str = "default";
+
+/// Database overrides by model name, taking precedence over the models'
+/// own `database` attribute
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Routes {
+    models: BTreeMap<String, String>,
+}
+
+impl Routes {
+    /// No overrides
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route model `model` to `database`
+    pub fn route(mut self, model: impl Into<String>, database: impl Into<String>) -> Self {
+        self.models.insert(model.into(), database.into());
+        self
+    }
+
+    /// The overrides, by model name
+    pub fn models(&self) -> &BTreeMap<String, String> {
+        &self.models
+    }
+
+    /// The database a model lives in
+    pub fn database_for<'a>(&'a self, meta: &'a ModelMeta) -> &'a str {
+        self.models
+            .get(&meta.name)
+            .or(meta.database.as_ref())
+            .map_or(DEFAULT_DATABASE, String::as_str)
+    }
+}
+
+/// Executors for several databases, picked by model
+pub struct Router {
+    default: Arc<dyn Executor>,
+    databases: HashMap<String, Arc<dyn Executor>>,
+    routes: Routes,
+}
+
+impl Router {
+    /// Route every model to `default` unless it names another database
+    pub fn new(default: Arc<dyn Executor>) -> Self {
+        Self {
+            default,
+            databases: HashMap::new(),
+            routes: Routes::new(),
+        }
+    }
+
+    /// Add a named database
+    pub fn database(mut self, name: impl Into<String>, executor: Arc<dyn Executor>) -> Self {
+        self.databases.insert(name.into(), executor);
+        self
+    }
+
+    /// Override where models are routed, e.g. with `[routing]` from `chakra.toml`
+    pub fn routes(mut self, routes: Routes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Route model `model` to `database`
+    pub fn route_model(mut self, model: impl Into<String>, database: impl Into<String>) -> Self {
+        self.routes = self.routes.route(model, database);
+        self
+    }
+
+    /// The executor for a database by name
+    pub fn get(&self, name: &str) -> Option<&dyn Executor> {
+        if name == DEFAULT_DATABASE {
+            return Some(self.default.as_ref());
+        }
+        self.databases.get(name).map(|e| e.as_ref())
+    }
+
+    /// The executor for a model's database
+    pub fn executor_for(&self, meta: &ModelMeta) -> Result<&dyn Executor> {
+        let database = self.routes.database_for(meta);
+        self.get(database).ok_or_else(|| {
+            ChakraError::config(format!(
+                "Model {} is routed to database `{}`, which the router has no executor for",
+                meta.name, database
+            ))
+        })
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut databases: Vec<&str> = self.databases.keys().map(String::as_str).collect();
+        databases.sort_unstable();
+        f.debug_struct("Router")
+            .field("databases", &databases)
+            .field("routes", &self.routes)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Executor for Router {
+    fn dialect(&self) -> &dyn Dialect {
+        self.default.dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.default.query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.default.execute(sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        self.default.execute_with_id(sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        self.default.execute_many(sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        self.default.stream(sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.default.max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.default.sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        self.default.read_only()
+    }
+
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        self.default.pin().await
+    }
+
+    fn route(&self, meta: &ModelMeta) -> Result<Option<&dyn Executor>> {
+        self.executor_for(meta).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{PostgresDialect, SqliteDialect};
+    use std::sync::Mutex;
+
+    /// Records the SQL it is given
+    struct Recorder {
+        dialect: Box<dyn Dialect>,
+        sql: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn new(dialect: impl Dialect + 'static) -> Arc<Self> {
+            Arc::new(Self {
+                dialect: Box::new(dialect),
+                sql: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Executor for Recorder {
+        fn dialect(&self) -> &dyn Dialect {
+            self.dialect.as_ref()
+        }
+
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            self.sql.lock().unwrap().push(sql.to_string());
+            Ok(Vec::new())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64> {
+            self.sql.lock().unwrap().push(sql.to_string());
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let main = Recorder::new(PostgresDialect);
+        let analytics = Recorder::new(SqliteDialect);
+        let router = Router::new(main.clone())
+            .database("analytics", analytics.clone())
+            .route_model("Visit", "analytics");
+
+        let user = ModelMeta::builder("User", "users").build();
+        let event = ModelMeta::builder("Event", "events")
+            .database("analytics")
+            .build();
+        let visit = ModelMeta::builder("Visit", "visits").build();
+        let archived = ModelMeta::builder("Archived", "archived")
+            .database("archive")
+            .build();
+
+        assert_eq!(
+            crate::executor::route(&router, &user)
+                .unwrap()
+                .dialect()
+                .name(),
+            "postgresql"
+        );
+        assert_eq!(
+            crate::executor::route(&router, &event)
+                .unwrap()
+                .dialect()
+                .name(),
+            "sqlite"
+        );
+        assert_eq!(
+            crate::executor::route(&router, &visit)
+                .unwrap()
+                .dialect()
+                .name(),
+            "sqlite"
+        );
+        let err = router.executor_for(&archived).err().unwrap();
+        assert!(err.to_string().contains("`archive`"));
+
+        // Anything else runs on the default database
+        router.execute("SELECT 1", &[]).await.unwrap();
+        assert_eq!(main.sql.lock().unwrap().len(), 1);
+        assert!(analytics.sql.lock().unwrap().is_empty());
+
+        // A plain executor runs every model itself
+        let plain: &dyn Executor = main.as_ref();
+        assert_eq!(
+            crate::executor::route(plain, &event)
+                .unwrap()
+                .dialect()
+                .name(),
+            "postgresql"
+        );
+    }
+
+    #[test]
+    fn test_routes_override() {
+        let routes: Routes = serde_json::from_str(r#"{"PageView": "analytics"}"#).unwrap();
+        let meta = ModelMeta::builder("PageView", "page_views")
+            .database("events")
+            .build();
+        assert_eq!(routes.database_for(&meta), "analytics");
+        assert_eq!(Routes::new().database_for(&meta), "events");
+    }
+}
@@ -0,0 +1,116 @@
+//! Default scopes registered at runtime
+//!
+//! `#[chakra(default_scope = "...")]` fixes one model's scope when it is
+//! compiled. Scopes registered here are asked for a filter each time a
+//! queryset runs, for every model, so one scope can cover all models with a
+//! column and read per-request state such as the signed-in organization:
+//!
+//! ```rust,ignore
+//! register_scope("active", |meta: &ModelMeta| {
+//!     meta.get_field("is_active")?;
+//!     Some(Expr::eq(format!("{}.is_active", meta.table), true))
+//! });
+//! register_scope("organization", |meta: &ModelMeta| {
+//!     meta.get_field("organization_id")?;
+//!     let organization = CURRENT_ORGANIZATION.try_with(|id| *id).ok()?;
+//!     Some(Expr::eq(format!("{}.organization_id", meta.table), organization))
+//! });
+//! register_model_scope::<Invoice>("unpaid", || Some(Expr::eq("invoices.paid", false)));
+//!
+//! let users = User::objects().all(&executor).await?;                         // active, this organization
+//! let all = User::objects().without_scope("active").all(&executor).await?;   // inactive too
+//! let every = User::objects().unscoped().all(&executor).await?;              // no scopes at all
+//! ```
+//!
+//! Scopes filter querysets, and so `Crud::get`, but not saves, deletes,
+//! `refresh` or raw SQL. Qualify columns with the table, as above, so that
+//! joined tables with the same column don't make them ambiguous.
+
+use crate::expr::Expr;
+use crate::model::{Model, ModelMeta};
+use std::sync::{Arc, RwLock};
+
+/// Registered scopes, in the order they were first registered
+static SCOPES: RwLock<Vec<(String, Arc<dyn DefaultScope>)>> = RwLock::new(Vec::new());
+
+/// A filter added to the querysets of the models it applies to
+pub trait DefaultScope: Send + Sync {
+    /// The filter for a model, or `None` to leave its querysets alone
+    fn filter(&self, meta: &ModelMeta) -> Option<Expr>;
+}
+
+impl<F> DefaultScope for F
+where
+    F: Fn(&ModelMeta) -> Option<Expr> + Send + Sync,
+{
+    fn filter(&self, meta: &ModelMeta) -> Option<Expr> {
+        self(meta)
+    }
+}
+
+/// Register a scope under `name`, replacing any scope already registered
+/// under it
+pub fn register_scope(name: impl Into<String>, scope: impl DefaultScope + 'static) {
+    let name = name.into();
+    let scope: Arc<dyn DefaultScope> = Arc::new(scope);
+    let mut scopes = SCOPES.write().unwrap_or_else(|e| e.into_inner());
+    match scopes.iter_mut().find(|(n, _)| *n == name) {
+        Some((_, existing)) => *existing = scope,
+        None => scopes.push((name, scope)),
+    }
+}
+
+/// Register a scope for model `M` only
+pub fn register_model_scope<M: Model>(
+    name: impl Into<String>,
+    filter: impl Fn() -> Option<Expr> + Send + Sync + 'static,
+) {
+    register_scope(name, move |meta: &ModelMeta| {
+        if meta.name == M::meta().name {
+            filter()
+        } else {
+            None
+        }
+    });
+}
+
+/// Remove the scope registered under `name`, returning whether there was one
+pub fn unregister_scope(name: &str) -> bool {
+    let mut scopes = SCOPES.write().unwrap_or_else(|e| e.into_inner());
+    let before = scopes.len();
+    scopes.retain(|(n, _)| n != name);
+    scopes.len() < before
+}
+
+/// The filters registered scopes add for a model, with the scopes' names
+pub fn scope_filters(meta: &ModelMeta) -> Vec<(String, Expr)> {
+    // Scopes run outside the lock, so they may register scopes themselves
+    let scopes = SCOPES.read().unwrap_or_else(|e| e.into_inner()).clone();
+    scopes
+        .into_iter()
+        .filter_map(|(name, scope)| Some((name, scope.filter(meta)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_filters() {
+        let meta = ModelMeta::builder("ScopedAccount", "scoped_accounts").build();
+        let other = ModelMeta::builder("ScopedOther", "scoped_others").build();
+        register_scope("test_scope_filters", |meta: &ModelMeta| {
+            (meta.name == "ScopedAccount").then(|| Expr::eq("scoped_accounts.closed", false))
+        });
+        assert_eq!(scope_filters(&meta).len(), 1);
+        assert!(scope_filters(&other).is_empty());
+
+        // Registering again replaces the scope
+        register_scope("test_scope_filters", |_: &ModelMeta| None);
+        assert!(scope_filters(&meta).is_empty());
+
+        assert!(unregister_scope("test_scope_filters"));
+        assert!(!unregister_scope("test_scope_filters"));
+    }
+}
@@ -0,0 +1,351 @@
+//! Multi-statement SQL script splitting
+//!
+//! This module splits SQL scripts (migration files, seed scripts) into
+//! individual statements, honouring string literals, quoted identifiers,
+//! comments, dollar-quoted bodies, trigger blocks and MySQL `DELIMITER`.
+
+/// Splits SQL scripts into individual statements
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptSplitter {
+    /// Recognise `$$ ... $$` and `$tag$ ... $tag$` bodies
+    pub dollar_quotes: bool,
+    /// Allow `/* */` comments to nest
+    pub nested_comments: bool,
+    /// Treat `\` as an escape inside string literals
+    pub backslash_escapes: bool,
+    /// Recognise `#` line comments
+    pub hash_comments: bool,
+    /// Recognise the `DELIMITER` client command
+    pub delimiter_command: bool,
+    /// Keep `CREATE TRIGGER ... BEGIN ... END` bodies together
+    pub trigger_blocks: bool,
+}
+
+impl ScriptSplitter {
+    /// Splitter for PostgreSQL scripts
+    pub fn postgres() -> Self {
+        Self {
+            dollar_quotes: true,
+            nested_comments: true,
+            ..Self::default()
+        }
+    }
+
+    /// Splitter for MySQL scripts
+    pub fn mysql() -> Self {
+        Self {
+            backslash_escapes: true,
+            hash_comments: true,
+            delimiter_command: true,
+            ..Self::default()
+        }
+    }
+
+    /// Splitter for SQLite scripts
+    pub fn sqlite() -> Self {
+        Self {
+            trigger_blocks: true,
+            ..Self::default()
+        }
+    }
+
+    /// Split a script into statements
+    ///
+    /// Statements are returned trimmed and without their terminating
+    /// delimiter. Empty and comment-only statements are dropped.
+    pub fn split(&self, script: &str) -> Vec<String> {
+        let bytes = script.as_bytes();
+        let len = bytes.len();
+        let mut statements = Vec::new();
+        let mut delimiter = String::from(";");
+        let mut start = 0;
+        let mut i = 0;
+        let mut has_content = false;
+        let mut leading_words: Vec<String> = Vec::new();
+        let mut block_depth = 0usize;
+
+        while i < len {
+            if self.delimiter_command && !has_content && is_line_start(bytes, i) {
+                if let Some(rest) = strip_keyword(&bytes[i..], b"DELIMITER") {
+                    let line_end = find_byte(bytes, i, b'\n');
+                    let new_delimiter = script[len - rest.len()..line_end].trim();
+                    if !new_delimiter.is_empty() {
+                        delimiter = new_delimiter.to_string();
+                    }
+                    i = line_end;
+                    start = i;
+                    continue;
+                }
+            }
+
+            let c = bytes[i];
+            let next = bytes.get(i + 1).copied();
+
+            match c {
+                b'\'' => {
+                    let escapes = self.backslash_escapes
+                        || (self.dollar_quotes && is_escape_string_prefix(bytes, i));
+                    i = skip_quoted(bytes, i, c, escapes);
+                    has_content = true;
+                    continue;
+                }
+                b'"' | b'`' => {
+                    i = skip_quoted(bytes, i, c, self.backslash_escapes && c == b'"');
+                    has_content = true;
+                    continue;
+                }
+                b'-' if next == Some(b'-') => {
+                    i = find_byte(bytes, i, b'\n');
+                    continue;
+                }
+                b'#' if self.hash_comments => {
+                    i = find_byte(bytes, i, b'\n');
+                    continue;
+                }
+                b'/' if next == Some(b'*') => {
+                    i = self.skip_block_comment(bytes, i);
+                    continue;
+                }
+                b'$' if self.dollar_quotes && !is_ident_byte_before(bytes, i) => {
+                    if let Some(end) = skip_dollar_quoted(bytes, i) {
+                        i = end;
+                        has_content = true;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            if block_depth == 0 && bytes[i..].starts_with(delimiter.as_bytes()) {
+                if has_content {
+                    statements.push(script[start..i].trim().to_string());
+                }
+                i += delimiter.len();
+                start = i;
+                has_content = false;
+                leading_words.clear();
+                continue;
+            }
+
+            if self.trigger_blocks && is_ident_start(c) && !is_ident_byte_before(bytes, i) {
+                let word_end = (i..len).find(|&j| !is_ident_byte(bytes[j])).unwrap_or(len);
+                let word = script[i..word_end].to_ascii_uppercase();
+
+                if leading_words.len() < 4 {
+                    leading_words.push(word.clone());
+                }
+                let in_trigger = leading_words.first().map(String::as_str) == Some("CREATE")
+                    && leading_words.iter().any(|w| w == "TRIGGER");
+                if in_trigger {
+                    match word.as_str() {
+                        "BEGIN" | "CASE" => block_depth += 1,
+                        "END" => block_depth = block_depth.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+
+                i = word_end;
+                has_content = true;
+                continue;
+            }
+
+            if !c.is_ascii_whitespace() {
+                has_content = true;
+            }
+            i += 1;
+        }
+
+        if has_content {
+            statements.push(script[start..].trim().to_string());
+        }
+
+        statements
+    }
+
+    fn skip_block_comment(&self, bytes: &[u8], start: usize) -> usize {
+        let mut depth = 0usize;
+        let mut i = start;
+
+        while i < bytes.len() {
+            if bytes[i..].starts_with(b"/*") {
+                if depth == 0 || self.nested_comments {
+                    depth += 1;
+                }
+                i += 2;
+            } else if bytes[i..].starts_with(b"*/") {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            } else {
+                i += 1;
+            }
+        }
+
+        bytes.len()
+    }
+}
+
+/// Split a script using the splitter for the given dialect name
+pub fn split_statements(dialect: &str, script: &str) -> Vec<String> {
+    let splitter = match dialect {
+        "postgresql" => ScriptSplitter::postgres(),
+        "mysql" => ScriptSplitter::mysql(),
+        "sqlite" => ScriptSplitter::sqlite(),
+        _ => ScriptSplitter::default(),
+    };
+    splitter.split(script)
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+fn is_ident_byte_before(bytes: &[u8], i: usize) -> bool {
+    i > 0 && is_ident_byte(bytes[i - 1])
+}
+
+fn is_line_start(bytes: &[u8], i: usize) -> bool {
+    bytes[..i]
+        .iter()
+        .rev()
+        .take_while(|&&b| b != b'\n')
+        .all(|b| b.is_ascii_whitespace())
+}
+
+fn is_escape_string_prefix(bytes: &[u8], quote: usize) -> bool {
+    quote > 0 && matches!(bytes[quote - 1], b'E' | b'e') && !is_ident_byte_before(bytes, quote - 1)
+}
+
+fn strip_keyword<'a>(bytes: &'a [u8], keyword: &[u8]) -> Option<&'a [u8]> {
+    let head = bytes.get(..keyword.len())?;
+    let rest = &bytes[keyword.len()..];
+    (head.eq_ignore_ascii_case(keyword) && rest.first().is_some_and(|b| *b == b' ' || *b == b'\t'))
+        .then_some(rest)
+}
+
+fn find_byte(bytes: &[u8], from: usize, needle: u8) -> usize {
+    bytes[from..]
+        .iter()
+        .position(|&b| b == needle)
+        .map(|p| from + p)
+        .unwrap_or(bytes.len())
+}
+
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        if backslash_escapes && b == b'\\' {
+            i += 2;
+        } else if b == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    bytes.len()
+}
+
+fn skip_dollar_quoted(bytes: &[u8], start: usize) -> Option<usize> {
+    let tag_end =
+        (start + 1..bytes.len()).find(|&j| !is_ident_byte(bytes[j]) || bytes[j] == b'$')?;
+    if bytes[tag_end] != b'$' || bytes.get(start + 1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+
+    let tag = &bytes[start..=tag_end];
+    let body = tag_end + 1;
+    let close = bytes[body..]
+        .windows(tag.len())
+        .position(|w| w == tag)
+        .map(|p| body + p + tag.len())
+        .unwrap_or(bytes.len());
+    Some(close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_strings_and_comments() {
+        let script = "-- seed data; not a statement\n\
+                      INSERT INTO notes (body) VALUES ('a; b', 'it''s');\n\
+                      /* block; comment */\n\
+                      UPDATE notes SET body = \"x;y\";";
+
+        let statements = ScriptSplitter::default().split(script);
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].ends_with("VALUES ('a; b', 'it''s')"));
+        assert_eq!(
+            statements[1],
+            "/* block; comment */\nUPDATE notes SET body = \"x;y\""
+        );
+    }
+
+    #[test]
+    fn test_split_postgres_dollar_quotes() {
+        let script = r#"
+            CREATE FUNCTION touch() RETURNS trigger AS $fn$
+            BEGIN
+                NEW.updated_at := now();
+                RETURN NEW;
+            END;
+            $fn$ LANGUAGE plpgsql;
+            SELECT $1::int, E'\';';
+            DO $$ BEGIN PERFORM 1; END $$;
+        "#;
+
+        let statements = ScriptSplitter::postgres().split(script);
+        assert_eq!(statements.len(), 3);
+        assert!(statements[0].ends_with("$fn$ LANGUAGE plpgsql"));
+        assert_eq!(statements[1], r"SELECT $1::int, E'\';'");
+        assert_eq!(statements[2], "DO $$ BEGIN PERFORM 1; END $$");
+    }
+
+    #[test]
+    fn test_split_mysql_delimiter() {
+        let script = "DROP PROCEDURE IF EXISTS bump;\n\
+                      DELIMITER //\n\
+                      CREATE PROCEDURE bump() BEGIN UPDATE c SET n = n + 1; SELECT 'a\\'b;'; END //\n\
+                      DELIMITER ;\n\
+                      # done\n\
+                      CALL bump();";
+
+        let statements = ScriptSplitter::mysql().split(script);
+        assert_eq!(
+            statements,
+            vec![
+                "DROP PROCEDURE IF EXISTS bump",
+                "CREATE PROCEDURE bump() BEGIN UPDATE c SET n = n + 1; SELECT 'a\\'b;'; END",
+                "# done\nCALL bump()",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_sqlite_trigger() {
+        let script = "CREATE TRIGGER audit AFTER UPDATE ON t BEGIN\n\
+                        INSERT INTO log VALUES (CASE WHEN new.x > 0 THEN 'up' ELSE 'down' END);\n\
+                        UPDATE t SET seen = 1;\n\
+                      END;\n\
+                      SELECT 1;";
+
+        let statements = ScriptSplitter::sqlite().split(script);
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].ends_with("END"));
+        assert_eq!(statements[1], "SELECT 1");
+    }
+}
@@ -0,0 +1,435 @@
+//! Unit of work with an identity map
+//!
+//! A `Session` remembers every instance it loads by primary key, so fetching
+//! the same row twice returns the first copy without another query. Writes
+//! are queued and sent together by `flush`:
+//!
+//! ```rust,ignore
+//! let mut session = Session::new(&executor);
+//!
+//! let mut author = session.get::<Author>(1).await?.unwrap();
+//! author.name = "Ada".into();
+//! session.add(author);
+//! session.add(Post { title: "Engines".into(), author_id: Some(1), ..Default::default() });
+//! let old = session.get::<Post>(7).await?.unwrap();
+//! session.delete(old);
+//!
+//! session.flush().await?; // one transaction
+//! let posts = session.instances::<Post>(); // with ids filled in
+//! ```
+//!
+//! A flush runs in one transaction: saves of models others belong to come
+//! first, then their dependents, and deletes in the reverse order, children
+//! before parents. Deletes of one model are batched into a single statement
+//! per parameter limit. If any write fails the transaction is rolled back
+//! and the queued writes are kept.
+//!
+//! On a pooled executor the flush holds one connection for the whole
+//! transaction; on a `Transaction` it runs in a savepoint. Since the
+//! transaction is on one database, models routed elsewhere by a `Router`
+//! are written there too.
+
+use crate::crud::Crud;
+use crate::error::Result;
+use crate::executor::{route, Executor};
+use crate::expr::Expr;
+use crate::model::{is_unset, Model, ModelMeta, RelationType};
+use crate::query::Query;
+use crate::queryset::QuerySet;
+use crate::tenant::tenant_filter;
+use crate::types::{ToValue, Value};
+use async_trait::async_trait;
+use std::any::Any;
+use std::collections::HashMap;
+
+/// Identity map key: model name and primary key literal
+type Identity = (String, String);
+
+/// A queued write
+enum Pending {
+    Save(Box<dyn Instance>),
+    Delete(Box<dyn Instance>),
+}
+
+impl Pending {
+    fn instance(&self) -> &dyn Instance {
+        match self {
+            Pending::Save(instance) | Pending::Delete(instance) => instance.as_ref(),
+        }
+    }
+}
+
+/// A model instance with its type erased
+#[async_trait]
+trait Instance: Send + Sync {
+    fn meta(&self) -> &'static ModelMeta;
+
+    /// Primary key column values, `None` while any is unset (NULL, zero or
+    /// empty), as for a model `save` would insert
+    fn key_values(&self) -> Option<Vec<Value>>;
+
+    /// The current tenant filter for the model, if it is tenant-scoped
+    fn scope(&self) -> Option<Expr>;
+
+    async fn save(&mut self, executor: &dyn Executor) -> Result<()>;
+
+    fn clone_instance(&self) -> Box<dyn Instance>;
+
+    fn to_any(&self) -> Box<dyn Any + Send + Sync>;
+}
+
+#[async_trait]
+impl<M: Model + Clone + 'static> Instance for M {
+    fn meta(&self) -> &'static ModelMeta {
+        M::meta()
+    }
+
+    fn key_values(&self) -> Option<Vec<Value>> {
+        M::meta()
+            .primary_key
+            .iter()
+            .map(|column| {
+                self.get_field(column)
+                    .ok()
+                    .flatten()
+                    .filter(|v| !is_unset(Some(v)))
+            })
+            .collect()
+    }
+
+    fn scope(&self) -> Option<Expr> {
+        tenant_filter::<M>()
+    }
+
+    async fn save(&mut self, executor: &dyn Executor) -> Result<()> {
+        Crud::save(self, executor).await
+    }
+
+    fn clone_instance(&self) -> Box<dyn Instance> {
+        Box::new(self.clone())
+    }
+
+    fn to_any(&self) -> Box<dyn Any + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// Identity map key of an instance, `None` while its key is unset
+fn identity(instance: &dyn Instance) -> Option<Identity> {
+    let values = instance.key_values()?;
+    Some((instance.meta().name.clone(), key_literal(&values)))
+}
+
+fn key_literal(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(Value::to_sql_literal)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Tracks loaded instances and queues writes until `flush`
+pub struct Session<'a> {
+    executor: &'a dyn Executor,
+    identity: HashMap<Identity, Box<dyn Any + Send + Sync>>,
+    pending: Vec<Pending>,
+}
+
+impl<'a> Session<'a> {
+    /// An empty session reading and writing through `executor`
+    pub fn new(executor: &'a dyn Executor) -> Self {
+        Self {
+            executor,
+            identity: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// The instance with primary key `id`, from the identity map if it was
+    /// loaded before, otherwise fetched within the default scope
+    pub async fn get<M: Model + Clone + 'static>(
+        &mut self,
+        id: M::PrimaryKey,
+    ) -> Result<Option<M>> {
+        let key = (M::meta().name.clone(), key_literal(&[id.to_value()]));
+        if let Some(model) = self.identity.get(&key).and_then(|m| m.downcast_ref::<M>()) {
+            return Ok(Some(model.clone()));
+        }
+        match M::get(self.executor, id).await {
+            Ok(model) => Ok(Some(self.register(model))),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Run a queryset, returning instances already in the identity map in
+    /// place of their freshly fetched rows
+    pub async fn fetch<M: Model + Clone + 'static>(
+        &mut self,
+        queryset: &QuerySet<M>,
+    ) -> Result<Vec<M>> {
+        let models = queryset.all(self.executor).await?;
+        Ok(models
+            .into_iter()
+            .map(|model| self.register(model))
+            .collect())
+    }
+
+    /// The instance with primary key `id` if it is in the identity map
+    pub fn cached<M: Model + Clone + 'static>(&self, id: M::PrimaryKey) -> Option<M> {
+        let key = (M::meta().name.clone(), key_literal(&[id.to_value()]));
+        self.identity.get(&key)?.downcast_ref::<M>().cloned()
+    }
+
+    /// Every instance of `M` in the identity map
+    pub fn instances<M: Model + Clone + 'static>(&self) -> Vec<M> {
+        self.identity
+            .values()
+            .filter_map(|m| m.downcast_ref::<M>())
+            .cloned()
+            .collect()
+    }
+
+    /// Add `model` to the identity map unless an instance with its key is
+    /// already there, and return the mapped instance
+    fn register<M: Model + Clone + 'static>(&mut self, model: M) -> M {
+        let Some(key) = identity(&model) else {
+            return model;
+        };
+        self.identity
+            .entry(key)
+            .or_insert_with(|| Box::new(model))
+            .downcast_ref::<M>()
+            .expect("identity map keys include the model name")
+            .clone()
+    }
+
+    /// Queue an INSERT, or an UPDATE if the primary key is set
+    ///
+    /// The instance replaces any earlier one with the same key, in the
+    /// identity map and among the queued writes.
+    pub fn add<M: Model + Clone + 'static>(&mut self, model: M) {
+        if let Some(key) = identity(&model) {
+            self.identity.insert(key, Box::new(model.clone()));
+        }
+        self.queue(Pending::Save(Box::new(model)));
+    }
+
+    /// Queue a DELETE of the instance's row
+    pub fn delete<M: Model + Clone + 'static>(&mut self, model: M) {
+        // A model never saved has no row to delete
+        if let Some(key) = identity(&model) {
+            self.identity.remove(&key);
+            self.queue(Pending::Delete(Box::new(model)));
+        }
+    }
+
+    fn queue(&mut self, write: Pending) {
+        if let Some(key) = identity(write.instance()) {
+            self.pending
+                .retain(|p| identity(p.instance()).as_ref() != Some(&key));
+        }
+        self.pending.push(write);
+    }
+
+    /// Number of queued writes
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Forget every loaded instance and queued write
+    pub fn clear(&mut self) {
+        self.identity.clear();
+        self.pending.clear();
+    }
+
+    /// Send the queued writes in one transaction, in dependency order
+    ///
+    /// Saved instances are put back into the identity map with their
+    /// generated values. On failure nothing is written and the queue is kept.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let order = dependency_order(self.pending.iter().map(|p| p.instance().meta()));
+        let rank = |meta: &ModelMeta| {
+            order
+                .iter()
+                .position(|name| *name == meta.name)
+                .unwrap_or(0)
+        };
+
+        // Saves work on copies, so the queue is intact if the flush fails
+        let mut saves: Vec<Box<dyn Instance>> = Vec::new();
+        let mut deletes: Vec<&dyn Instance> = Vec::new();
+        for pending in &self.pending {
+            match pending {
+                Pending::Save(instance) => saves.push(instance.clone_instance()),
+                Pending::Delete(instance) => deletes.push(instance.as_ref()),
+            }
+        }
+        saves.sort_by_key(|i| rank(i.meta()));
+        deletes.sort_by_key(|i| std::cmp::Reverse(rank(i.meta())));
+
+        let tx = self.executor.begin_transaction().await?;
+        let result = async {
+            for instance in saves.iter_mut() {
+                instance.save(&tx).await?;
+            }
+            let mut start = 0;
+            while start < deletes.len() {
+                let meta = deletes[start].meta();
+                let end = deletes[start..]
+                    .iter()
+                    .position(|i| i.meta().name != meta.name)
+                    .map_or(deletes.len(), |p| start + p);
+                delete_batch(&tx, meta, &deletes[start..end]).await?;
+                start = end;
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => tx.commit().await?,
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e);
+            }
+        }
+
+        self.pending.clear();
+        for instance in saves {
+            if let Some(key) = identity(instance.as_ref()) {
+                self.identity.insert(key, instance.to_any());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Session<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("identity", &self.identity.len())
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+/// DELETE the rows of instances of one model, as few statements as the
+/// dialect's parameter limit allows
+async fn delete_batch(
+    executor: &dyn Executor,
+    meta: &ModelMeta,
+    instances: &[&dyn Instance],
+) -> Result<u64> {
+    let executor = route(executor, meta)?;
+    let columns = &meta.primary_key;
+    let per_statement = (executor.dialect().max_params() / columns.len().max(1)).max(1);
+    let mut deleted = 0;
+    for chunk in instances.chunks(per_statement) {
+        let keys: Vec<Vec<Value>> = chunk.iter().filter_map(|i| i.key_values()).collect();
+        let filter = match columns.as_slice() {
+            [column] => Expr::is_in(column.clone(), keys.into_iter().flatten().collect()),
+            _ => keys
+                .into_iter()
+                .map(|values| {
+                    columns
+                        .iter()
+                        .zip(values)
+                        .map(|(column, value)| Expr::eq(column.clone(), value))
+                        .reduce(Expr::and)
+                        .expect("models have a primary key")
+                })
+                .reduce(Expr::or)
+                .expect("chunks are never empty"),
+        };
+        let filter = match chunk[0].scope() {
+            Some(scope) => filter.and(scope),
+            None => filter,
+        };
+        let query = Query::delete()
+            .from(meta.table.clone())
+            .filter(filter)
+            .build();
+        deleted += executor.execute_query(&query).await?;
+    }
+    Ok(deleted)
+}
+
+/// Model names ordered so each comes after the models it belongs to
+///
+/// Models in a cycle keep the order they were first seen in.
+fn dependency_order<'m>(metas: impl Iterator<Item = &'m ModelMeta>) -> Vec<&'m str> {
+    let mut models: Vec<&ModelMeta> = Vec::new();
+    for meta in metas {
+        if !models.iter().any(|m| m.name == meta.name) {
+            models.push(meta);
+        }
+    }
+    let parents = |meta: &ModelMeta| -> Vec<String> {
+        meta.relationships
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r.relation_type,
+                    RelationType::ManyToOne | RelationType::OneToOne
+                )
+            })
+            .filter(|r| r.target_model != meta.name)
+            .map(|r| r.target_model.clone())
+            .collect()
+    };
+
+    let mut order: Vec<&str> = Vec::new();
+    while order.len() < models.len() {
+        let ready = models.iter().find(|m| {
+            !order.contains(&m.name.as_str())
+                && parents(m)
+                    .iter()
+                    .all(|p| order.contains(&p.as_str()) || !models.iter().any(|m| &m.name == p))
+        });
+        // A cycle: take the first remaining model
+        let next = ready
+            .or_else(|| models.iter().find(|m| !order.contains(&m.name.as_str())))
+            .expect("a model remains");
+        order.push(&next.name);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::RelationMeta;
+
+    fn belongs_to(name: &str, table: &str, parent: &str) -> ModelMeta {
+        ModelMeta::builder(name, table)
+            .relationship(RelationMeta {
+                name: parent.to_lowercase(),
+                relation_type: RelationType::ManyToOne,
+                target_model: parent.to_string(),
+                foreign_key: Some(format!("{}_id", parent.to_lowercase())),
+                through_table: None,
+                related_key: None,
+                back_populates: None,
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let comment = belongs_to("Comment", "comments", "Post");
+        let post = belongs_to("Post", "posts", "Author");
+        let author = ModelMeta::builder("Author", "authors").build();
+        let tag = belongs_to("Tag", "tags", "Missing");
+
+        let order = dependency_order([&comment, &tag, &post, &author, &post].into_iter());
+        assert_eq!(order, ["Tag", "Author", "Post", "Comment"]);
+
+        // A cycle keeps the order models were first seen in
+        let a = belongs_to("A", "a", "B");
+        let b = belongs_to("B", "b", "A");
+        assert_eq!(dependency_order([&b, &a].into_iter()), ["B", "A"]);
+    }
+}
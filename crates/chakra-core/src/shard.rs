@@ -0,0 +1,840 @@
+//! Hash-based horizontal sharding
+//!
+//! A sharded model names the column that picks each row's shard:
+//!
+//! ```rust,ignore
+//! #[derive(Model)]
+//! #[chakra(table = "orders", shard_key = "customer_id")]
+//! struct Order { ... }
+//! ```
+//!
+//! A `ShardRouter` places shards on a consistent-hash ring and is itself an
+//! `Executor`, so querysets and model methods given a router run on the
+//! right shards:
+//!
+//! ```rust,ignore
+//! let shards = ShardRouter::new("s0", s0)
+//!     .shard("s1", s1)
+//!     .shard("s2", s2)
+//!     .model::<Order>();
+//!
+//! // One shard: the filter pins the shard key
+//! let mine = Order::objects().filter(Expr::eq("customer_id", 42)).all(&shards).await?;
+//!
+//! // Every shard, merged: ORDER BY, LIMIT and OFFSET apply to the whole result
+//! let latest = Order::objects().order_by("-created_at").limit(10).all(&shards).await?;
+//! let total = Order::objects().count(&shards).await?;
+//! ```
+//!
+//! Queries whose filter fixes the shard key with `=` or `IN` go only to the
+//! shards holding those keys; other queries are scattered to every shard and
+//! the rows gathered. `COUNT`, `SUM`, `MIN` and `MAX` are combined across
+//! shards; `AVG`, `GROUP BY` and `DISTINCT` need a shard key filter. Inserts
+//! are split by the shard key of each row, which must be set.
+//!
+//! Tables that aren't sharded, raw SQL and transactions use the first shard.
+//! Joins only see rows on the same shard, so join sharded tables on their
+//! shard key.
+
+use crate::error::{ChakraError, QueryError, Result};
+use crate::executor::{ExecResult, Executor};
+use crate::expr::{AggregateFunc, CompareOp, Expr};
+use crate::model::{Model, ModelMeta};
+use crate::query::{NullsOrder, Order, Query, QueryType};
+use crate::result::{Row, RowStream};
+use crate::sql::Dialect;
+use crate::sql_cache::SqlCache;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+/// Points each shard has on the hash ring
+const VIRTUAL_NODES: usize = 64;
+
+/// Executors for the shards of one database, picked by shard key
+pub struct ShardRouter {
+    shards: Vec<(String, Arc<dyn Executor>)>,
+    /// Ring points and the index of the shard owning each, sorted
+    ring: Vec<(u64, usize)>,
+    /// Shard key column by sharded table
+    tables: HashMap<String, String>,
+}
+
+impl ShardRouter {
+    /// A router with one shard, which also holds the tables that aren't sharded
+    pub fn new(name: impl Into<String>, executor: Arc<dyn Executor>) -> Self {
+        Self {
+            shards: Vec::new(),
+            ring: Vec::new(),
+            tables: HashMap::new(),
+        }
+        .shard(name, executor)
+    }
+
+    /// Add a shard
+    ///
+    /// Keys are placed by shard name, so a new shard takes over only about
+    /// its share of the keys, and only from its neighbours on the ring.
+    pub fn shard(mut self, name: impl Into<String>, executor: Arc<dyn Executor>) -> Self {
+        let name = name.into();
+        let index = self.shards.len();
+        for node in 0..VIRTUAL_NODES {
+            self.ring
+                .push((hash(format!("{}#{}", name, node).as_bytes()), index));
+        }
+        self.ring.sort_unstable();
+        self.shards.push((name, executor));
+        self
+    }
+
+    /// Shard model `M`'s table by its `shard_key`, if it has one
+    pub fn model<M: Model>(self) -> Self {
+        let meta = M::meta();
+        match &meta.shard_key {
+            Some(column) => self.table(meta.table.clone(), column.clone()),
+            None => self,
+        }
+    }
+
+    /// Shard `table` by `column`
+    pub fn table(mut self, table: impl Into<String>, column: impl Into<String>) -> Self {
+        self.tables.insert(table.into(), column.into());
+        self
+    }
+
+    /// Shard names and executors, in the order they were added
+    pub fn shards(&self) -> impl Iterator<Item = (&str, &dyn Executor)> {
+        self.shards
+            .iter()
+            .map(|(name, executor)| (name.as_str(), executor.as_ref()))
+    }
+
+    /// The shard holding rows whose shard key is `key`
+    pub fn shard_for(&self, key: &Value) -> (&str, &dyn Executor) {
+        let (name, executor) = &self.shards[self.shard_index(key)];
+        (name, executor.as_ref())
+    }
+
+    /// Whether `meta`'s table is sharded by this router
+    pub fn is_sharded(&self, meta: &ModelMeta) -> bool {
+        self.tables.contains_key(&meta.table)
+    }
+
+    fn shard_index(&self, key: &Value) -> usize {
+        let point = hash(key.to_sql_literal().as_bytes());
+        let i = self.ring.partition_point(|(p, _)| *p < point);
+        self.ring[i % self.ring.len()].1
+    }
+
+    fn first(&self) -> &dyn Executor {
+        self.shards[0].1.as_ref()
+    }
+
+    /// Shard key column of the sharded table `query` runs on, `None` if it
+    /// only uses tables that aren't sharded
+    fn shard_column(&self, query: &Query) -> Result<Option<&str>> {
+        if let Some(column) = self.tables.get(&query.table) {
+            return Ok(Some(column));
+        }
+        let nested = query
+            .joins
+            .iter()
+            .any(|j| self.tables.contains_key(&j.table))
+            || query.ctes.iter().any(|c| self.uses_sharded(&c.query))
+            || query
+                .source
+                .as_deref()
+                .is_some_and(|q| self.uses_sharded(q));
+        if nested {
+            return Err(not_shardable(
+                "a sharded table used in a join, CTE or subquery of another table",
+            ));
+        }
+        Ok(None)
+    }
+
+    fn uses_sharded(&self, query: &Query) -> bool {
+        self.tables.contains_key(&query.table)
+            || query
+                .joins
+                .iter()
+                .any(|j| self.tables.contains_key(&j.table))
+            || query.ctes.iter().any(|c| self.uses_sharded(&c.query))
+            || query
+                .source
+                .as_deref()
+                .is_some_and(|q| self.uses_sharded(q))
+    }
+
+    /// Shards rows matching `query`'s filter can be on
+    fn targets(&self, query: &Query, column: &str) -> Vec<usize> {
+        match query
+            .where_clause
+            .as_ref()
+            .and_then(|filter| self.pinned(filter, column))
+        {
+            Some(shards) => shards.into_iter().collect(),
+            None => (0..self.shards.len()).collect(),
+        }
+    }
+
+    /// Shards a filter limits the rows to, `None` if it doesn't fix the shard key
+    fn pinned(&self, expr: &Expr, column: &str) -> Option<BTreeSet<usize>> {
+        let is_key = |c: &str| c == column || c.rsplit_once('.').is_some_and(|(_, c)| c == column);
+        match expr {
+            Expr::Compare {
+                column: c,
+                op: CompareOp::Eq,
+                value,
+            } if is_key(c) => Some(BTreeSet::from([self.shard_index(value)])),
+            Expr::In {
+                column: c,
+                values,
+                negated: false,
+            }
+            | Expr::InArray {
+                column: c,
+                values,
+                negated: false,
+            } if is_key(c) => Some(values.iter().map(|v| self.shard_index(v)).collect()),
+            Expr::And(exprs) => exprs
+                .iter()
+                .filter_map(|e| self.pinned(e, column))
+                .reduce(|a, b| a.intersection(&b).copied().collect()),
+            Expr::Or(exprs) => exprs
+                .iter()
+                .map(|e| self.pinned(e, column))
+                .collect::<Option<Vec<_>>>()
+                .map(|sets| sets.into_iter().flatten().collect()),
+            _ => None,
+        }
+    }
+
+    /// Split an INSERT into one per shard, by the shard key of each row
+    fn split_insert(&self, query: &Query, column: &str) -> Result<Vec<(usize, Query)>> {
+        if query.source.is_some() {
+            return Err(not_shardable("INSERT ... SELECT into a sharded table"));
+        }
+        let mut by_shard: BTreeMap<usize, Vec<HashMap<String, Value>>> = BTreeMap::new();
+        for row in &query.values {
+            let key = row.get(column).filter(|v| !v.is_null()).ok_or_else(|| {
+                not_shardable(&format!(
+                    "an INSERT into {} without its shard key {}",
+                    query.table, column
+                ))
+            })?;
+            by_shard
+                .entry(self.shard_index(key))
+                .or_default()
+                .push(row.clone());
+        }
+        Ok(by_shard
+            .into_iter()
+            .map(|(shard, values)| {
+                let mut query = query.clone();
+                query.values = values;
+                (shard, query)
+            })
+            .collect())
+    }
+
+    /// The shards a write runs on, with the statement for each
+    fn writes(&self, query: &Query) -> Result<Vec<(&dyn Executor, Query)>> {
+        let Some(column) = self.shard_column(query)? else {
+            return Ok(vec![(self.first(), query.clone())]);
+        };
+        let writes = match query.query_type {
+            QueryType::Insert => self.split_insert(query, column)?,
+            _ => self
+                .targets(query, column)
+                .into_iter()
+                .map(|i| (i, query.clone()))
+                .collect(),
+        };
+        Ok(writes
+            .into_iter()
+            .map(|(i, query)| (self.shards[i].1.as_ref(), query))
+            .collect())
+    }
+
+    /// Run a SELECT on several shards and combine the rows as one database would
+    async fn gather(&self, shards: &[usize], query: &Query) -> Result<Vec<Row>> {
+        if !query.group_by.is_empty() || query.grouping_sets.is_some() || query.having.is_some() {
+            return Err(not_shardable("GROUP BY across shards"));
+        }
+        if query.distinct {
+            return Err(not_shardable("DISTINCT across shards"));
+        }
+        let merges = aggregate_merges(query)?;
+
+        // Each shard returns enough rows to fill the page after merging
+        let mut shard_query = query.clone();
+        if let Some(limit) = query.limit {
+            shard_query.limit = Some(limit + query.offset.unwrap_or(0));
+            shard_query.offset = None;
+        }
+        let results = try_join_all(
+            shards
+                .iter()
+                .map(|&i| self.shards[i].1.fetch_all(&shard_query)),
+        )
+        .await?;
+
+        if let Some(merges) = merges {
+            return Ok(vec![merge_aggregates(&merges, results)]);
+        }
+        let mut rows: Vec<Row> = results.into_iter().flatten().collect();
+        if !query.order_by.is_empty() {
+            sort_rows(&mut rows, query, self.dialect())?;
+        }
+        let offset = query.offset.unwrap_or(0);
+        let limit = query.limit.unwrap_or(usize::MAX);
+        Ok(rows.into_iter().skip(offset).take(limit).collect())
+    }
+}
+
+impl fmt::Debug for ShardRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shards: Vec<&str> = self.shards.iter().map(|(name, _)| name.as_str()).collect();
+        let mut tables: Vec<(&String, &String)> = self.tables.iter().collect();
+        tables.sort_unstable();
+        f.debug_struct("ShardRouter")
+            .field("shards", &shards)
+            .field("tables", &tables)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Executor for ShardRouter {
+    fn dialect(&self) -> &dyn Dialect {
+        self.first().dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.first().query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.first().execute(sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        self.first().execute_with_id(sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        self.first().execute_many(sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        self.first().stream(sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.first().max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.first().sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        self.first().read_only()
+    }
+
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        self.first().pin().await
+    }
+
+    async fn fetch_all(&self, query: &Query) -> Result<Vec<Row>> {
+        let Some(column) = self.shard_column(query)? else {
+            return self.first().fetch_all(query).await;
+        };
+        match self.targets(query, column).as_slice() {
+            [] => Ok(Vec::new()),
+            [shard] => self.shards[*shard].1.fetch_all(query).await,
+            shards => self.gather(shards, query).await,
+        }
+    }
+
+    /// Streams from several shards are concatenated, so they can't be
+    /// ordered, limited or aggregated
+    fn fetch_stream(&self, query: &Query) -> Result<RowStream<'_, Row>> {
+        let Some(column) = self.shard_column(query)? else {
+            return self.first().fetch_stream(query);
+        };
+        let shards = self.targets(query, column);
+        if let [shard] = shards.as_slice() {
+            return self.shards[*shard].1.fetch_stream(query);
+        }
+        if !query.order_by.is_empty() || query.limit.is_some() || query.offset.is_some() {
+            return Err(not_shardable("an ordered or limited stream across shards"));
+        }
+        if query.distinct || !query.group_by.is_empty() || aggregate_merges(query)?.is_some() {
+            return Err(not_shardable(
+                "an aggregate or DISTINCT stream across shards",
+            ));
+        }
+        let streams = shards
+            .iter()
+            .map(|&i| self.shards[i].1.fetch_stream(query))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RowStream::new(stream::iter(streams).flatten()))
+    }
+
+    async fn insert_all(&self, query: &Query) -> Result<u64> {
+        let mut affected = 0;
+        for (executor, query) in self.writes(query)? {
+            affected += executor.insert_all(&query).await?;
+        }
+        Ok(affected)
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        let mut rows = Vec::new();
+        for (executor, query) in self.writes(query)? {
+            rows.extend(executor.execute_returning(&query).await?);
+        }
+        Ok(rows)
+    }
+
+    async fn execute_query(&self, query: &Query) -> Result<u64> {
+        let mut affected = 0;
+        for (executor, query) in self.writes(query)? {
+            affected += executor.execute_query(&query).await?;
+        }
+        Ok(affected)
+    }
+
+    async fn exec(&self, query: &Query) -> Result<ExecResult> {
+        let mut result = ExecResult::default();
+        for (executor, query) in self.writes(query)? {
+            let shard = executor.exec(&query).await?;
+            result.rows_affected += shard.rows_affected;
+            result.last_insert_id = shard.last_insert_id.or(result.last_insert_id);
+        }
+        Ok(result)
+    }
+
+    /// Unique checks on a sharded table look at every shard
+    async fn validate_unique(
+        &self,
+        meta: &ModelMeta,
+        values: &HashMap<String, Value>,
+    ) -> Result<()> {
+        for check in meta.unique_checks(values) {
+            if !self.fetch_all(&check.query).await?.is_empty() {
+                return Err(check.violation());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn not_shardable(what: &str) -> ChakraError {
+    ChakraError::Query(QueryError::Invalid {
+        message: format!(
+            "can't run {}; filter on the shard key to use one shard",
+            what
+        ),
+    })
+}
+
+/// 64-bit FNV-1a with a final avalanche, stable across builds and processes
+fn hash(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h
+}
+
+/// How a selected aggregate combines across shards
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Merge {
+    Sum,
+    Min,
+    Max,
+}
+
+/// The merge for each selected column if the query selects only
+/// aggregates, `None` if it selects none
+fn aggregate_merges(query: &Query) -> Result<Option<Vec<(String, Merge)>>> {
+    let mut merges = Vec::new();
+    let mut plain = 0;
+    for column in &query.columns {
+        match aggregate_column(column) {
+            Some(merge) => merges.push(merge?),
+            None => plain += 1,
+        }
+    }
+    for (expr, alias) in &query.select_exprs {
+        match expr {
+            Expr::Aggregate {
+                function, distinct, ..
+            } => {
+                let merge = match (function, distinct) {
+                    (AggregateFunc::Count | AggregateFunc::Sum, false) => Merge::Sum,
+                    (AggregateFunc::Min, _) => Merge::Min,
+                    (AggregateFunc::Max, _) => Merge::Max,
+                    _ => {
+                        return Err(not_shardable(
+                            &format!("{:?} across shards", function).to_uppercase(),
+                        ))
+                    }
+                };
+                merges.push((alias.clone(), merge));
+            }
+            _ => plain += 1,
+        }
+    }
+    match (merges.is_empty(), plain) {
+        (true, _) => Ok(None),
+        (false, 0) => Ok(Some(merges)),
+        (false, _) => Err(not_shardable(
+            "aggregates next to plain columns across shards",
+        )),
+    }
+}
+
+/// Parse a column like `COUNT(*) AS count`, `None` if it isn't an aggregate
+fn aggregate_column(column: &str) -> Option<Result<(String, Merge)>> {
+    let (call, alias) = column
+        .rsplit_once(" AS ")
+        .or_else(|| column.rsplit_once(" as "))?;
+    let (function, args) = call.trim().split_once('(')?;
+    let merge = match function.trim().to_uppercase().as_str() {
+        "COUNT" | "SUM" => Merge::Sum,
+        "MIN" => Merge::Min,
+        "MAX" => Merge::Max,
+        "AVG" => return Some(Err(not_shardable("AVG across shards"))),
+        _ => return None,
+    };
+    if args.trim_start().to_uppercase().starts_with("DISTINCT") && merge == Merge::Sum {
+        return Some(Err(not_shardable("a DISTINCT count or sum across shards")));
+    }
+    Some(Ok((alias.trim().trim_matches('"').to_string(), merge)))
+}
+
+/// Combine each shard's single aggregate row into one
+fn merge_aggregates(merges: &[(String, Merge)], results: Vec<Vec<Row>>) -> Row {
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for (alias, merge) in merges {
+        let shard_values = results
+            .iter()
+            .filter_map(|rows| rows.first()?.get(alias))
+            .filter(|v| !v.is_null());
+        let value = match merge {
+            Merge::Sum => shard_values.cloned().reduce(add).unwrap_or(Value::Null),
+            Merge::Min => shard_values
+                .min_by(|a, b| compare(a, b))
+                .cloned()
+                .unwrap_or(Value::Null),
+            Merge::Max => shard_values
+                .max_by(|a, b| compare(a, b))
+                .cloned()
+                .unwrap_or(Value::Null),
+        };
+        columns.push(alias.clone());
+        values.push(value);
+    }
+    Row::new(columns, values)
+}
+
+fn add(a: Value, b: Value) -> Value {
+    match (&a, &b) {
+        (Value::Decimal(x), Value::Decimal(y)) => Value::Decimal(x + y),
+        (Value::Float64(_), _) | (_, Value::Float64(_)) => {
+            Value::Float64(a.as_f64().unwrap_or_default() + b.as_f64().unwrap_or_default())
+        }
+        _ => match (a.as_i64(), b.as_i64()) {
+            (Some(x), Some(y)) => Value::Int64(x + y),
+            _ => a,
+        },
+    }
+}
+
+/// Order of two non-NULL values of the same column
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Decimal(x), Value::Decimal(y)) => x.cmp(y),
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        (Value::Bytes(x), Value::Bytes(y)) => x.cmp(y),
+        (Value::Uuid(x), Value::Uuid(y)) => x.cmp(y),
+        (Value::DateTime(x), Value::DateTime(y)) => x.cmp(y),
+        (Value::Date(x), Value::Date(y)) => x.cmp(y),
+        (Value::Time(x), Value::Time(y)) => x.cmp(y),
+        (Value::IpAddr(x), Value::IpAddr(y)) => x.cmp(y),
+        _ => match (a.as_i64(), b.as_i64()) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                _ => Ordering::Equal,
+            },
+        },
+    }
+}
+
+/// Sort gathered rows by the query's ORDER BY, placing NULLs where the
+/// database would
+fn sort_rows(rows: &mut [Row], query: &Query, dialect: &dyn Dialect) -> Result<()> {
+    let mut keys = Vec::new();
+    for order in &query.order_by {
+        if order.expr.is_some() {
+            return Err(not_shardable("ORDER BY an expression across shards"));
+        }
+        let column = order
+            .column
+            .rsplit_once('.')
+            .map_or(order.column.as_str(), |(_, c)| c);
+        if rows.first().is_some_and(|row| row.get(column).is_none()) {
+            return Err(not_shardable(&format!(
+                "ORDER BY {} across shards without selecting it",
+                order.column
+            )));
+        }
+        let descending = order.order == Order::Desc;
+        // PostgreSQL sorts NULLs as the largest values, the others as the smallest
+        let nulls_first = match order.nulls {
+            Some(NullsOrder::First) => true,
+            Some(NullsOrder::Last) => false,
+            None => descending == (dialect.name() == "postgresql"),
+        };
+        keys.push((column, descending, nulls_first));
+    }
+    rows.sort_by(|a, b| {
+        for &(column, descending, nulls_first) in &keys {
+            let a = a.get(column).filter(|v| !v.is_null());
+            let b = b.get(column).filter(|v| !v.is_null());
+            let ordering = match (a, b) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) if nulls_first => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) if nulls_first => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) if descending => compare(b, a),
+                (Some(a), Some(b)) => compare(a, b),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryBuilder;
+    use crate::sql::PostgresDialect;
+    use std::sync::Mutex;
+
+    /// Records the SQL it is given and answers every query with `rows`
+    struct Shard {
+        rows: Vec<Row>,
+        sql: Mutex<Vec<String>>,
+    }
+
+    impl Shard {
+        fn new(rows: Vec<Row>) -> Arc<Self> {
+            Arc::new(Self {
+                rows,
+                sql: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn sql(&self) -> Vec<String> {
+            self.sql.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Executor for Shard {
+        fn dialect(&self) -> &dyn Dialect {
+            &PostgresDialect
+        }
+
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            self.sql.lock().unwrap().push(sql.to_string());
+            Ok(self.rows.clone())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64> {
+            self.sql.lock().unwrap().push(sql.to_string());
+            Ok(1)
+        }
+    }
+
+    fn row(id: i64, score: Option<i64>) -> Row {
+        Row::new(
+            vec!["id".to_string(), "score".to_string()],
+            vec![Value::Int64(id), score.map_or(Value::Null, Value::Int64)],
+        )
+    }
+
+    fn router(shards: &[Arc<Shard>]) -> ShardRouter {
+        let mut router = ShardRouter::new("s0", shards[0].clone());
+        for (i, shard) in shards.iter().enumerate().skip(1) {
+            router = router.shard(format!("s{}", i), shard.clone());
+        }
+        router.table("events", "user_id")
+    }
+
+    fn select() -> QueryBuilder {
+        Query::select().from("events").columns(&["id", "score"])
+    }
+
+    #[test]
+    fn test_consistent_hashing() {
+        let shards: Vec<_> = (0..3).map(|_| Shard::new(Vec::new())).collect();
+        let three = router(&shards);
+        let four = router(&shards).shard("s3", Shard::new(Vec::new()));
+
+        let mut counts = [0; 3];
+        let mut moved = 0;
+        for key in 0..3000 {
+            let before = three.shard_index(&Value::Int64(key));
+            let after = four.shard_index(&Value::Int64(key));
+            counts[before] += 1;
+            if before != after {
+                // Keys only move to the new shard
+                assert_eq!(after, 3);
+                moved += 1;
+            }
+        }
+        assert!(counts.iter().all(|&n| n > 600), "{:?}", counts);
+        assert!((400..1200).contains(&moved), "{}", moved);
+        // Same key, same shard, whatever its integer width
+        assert_eq!(
+            three.shard_for(&Value::Int32(7)).0,
+            three.shard_for(&Value::Int64(7)).0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_single_key_queries() {
+        let shards: Vec<_> = (0..3).map(|_| Shard::new(vec![row(1, None)])).collect();
+        let router = router(&shards);
+        let key = Value::Int64(42);
+        let owner = router.shard_index(&key);
+
+        let query = select()
+            .filter(Expr::eq("events.user_id", 42).and(Expr::gt("score", 1)))
+            .build();
+        router.fetch_all(&query).await.unwrap();
+        for (i, shard) in shards.iter().enumerate() {
+            assert_eq!(shard.sql().len(), usize::from(i == owner));
+        }
+
+        // Unsharded tables live on the first shard
+        router
+            .fetch_all(&Query::select().from("users").build())
+            .await
+            .unwrap();
+        assert_eq!(shards[0].sql().len(), 1 + usize::from(owner == 0));
+
+        let none = select()
+            .filter(Expr::is_in("user_id", Vec::<Value>::new()))
+            .build();
+        assert!(router.fetch_all(&none).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scatter_gather_merges_order_and_limit() {
+        let shards = vec![
+            Shard::new(vec![row(1, Some(5)), row(2, Some(1))]),
+            Shard::new(vec![row(3, Some(4)), row(4, None)]),
+            Shard::new(vec![row(5, Some(3))]),
+        ];
+        let router = router(&shards);
+
+        let query = select()
+            .order_by("score", Order::Desc)
+            .limit(3)
+            .offset(1)
+            .build();
+        let rows = router.fetch_all(&query).await.unwrap();
+        let ids: Vec<i64> = rows.iter().map(|r| r.get_as("id").unwrap()).collect();
+        // PostgreSQL puts NULLs first when descending
+        assert_eq!(ids, [1, 3, 5]);
+        // Each shard was asked for limit + offset rows, from the start
+        assert!(shards[1].sql()[0].contains("LIMIT 4"));
+        assert!(!shards[1].sql()[0].contains("OFFSET"));
+
+        let grouped = select().group_by(&["score"]).build();
+        assert!(router.fetch_all(&grouped).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_merge() {
+        let counts = |n: i64, max: Option<i64>| {
+            Row::new(
+                vec!["count".to_string(), "max".to_string()],
+                vec![Value::Int64(n), max.map_or(Value::Null, Value::Int64)],
+            )
+        };
+        let shards = vec![
+            Shard::new(vec![counts(2, Some(9))]),
+            Shard::new(vec![counts(3, None)]),
+        ];
+        let router = router(&shards);
+
+        let query = Query::select()
+            .from("events")
+            .columns(&["COUNT(*) AS count", "MAX(score) AS max"])
+            .build();
+        let rows = router.fetch_all(&query).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_as::<i64>("count").unwrap(), 5);
+        assert_eq!(rows[0].get_as::<i64>("max").unwrap(), 9);
+
+        let avg = Query::select()
+            .from("events")
+            .columns(&["AVG(score) AS avg"])
+            .build();
+        assert!(router.fetch_all(&avg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_inserts_split_by_shard_key() {
+        let shards: Vec<_> = (0..3).map(|_| Shard::new(Vec::new())).collect();
+        let router = router(&shards);
+
+        let rows: Vec<HashMap<String, Value>> = (0..30)
+            .map(|user| HashMap::from([("user_id".to_string(), Value::Int64(user))]))
+            .collect();
+        let query = rows
+            .into_iter()
+            .fold(Query::insert().table("events"), |q, row| q.values(row))
+            .build();
+        assert_eq!(router.insert_all(&query).await.unwrap(), 3);
+        assert!(shards.iter().all(|s| s.sql().len() == 1));
+
+        let keyless = Query::insert()
+            .table("events")
+            .values(HashMap::from([("score".to_string(), Value::Int64(1))]))
+            .build();
+        assert!(router.insert_all(&keyless).await.is_err());
+
+        // Deletes without the shard key go to every shard
+        let delete = Query::delete()
+            .from("events")
+            .filter(Expr::eq("id", 1))
+            .build();
+        assert_eq!(router.execute_query(&delete).await.unwrap(), 3);
+    }
+}
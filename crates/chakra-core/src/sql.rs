@@ -2,9 +2,37 @@
 //!
 //! This module provides SQL generation from query objects.
 
-use crate::expr::{AggregateFunc, ArithmeticOp, CompareOp, Expr};
-use crate::query::{Order, Query, QueryType};
+use crate::error::{ChakraError, QueryError, Result};
+use crate::expr::{AggregateFunc, ArithmeticOp, CompareOp, DatePart, Expr, Quantifier};
+use crate::format::{placeholders, Param};
+use crate::identifier;
+use crate::query::{
+    BulkUpdate, ConflictAction, ConflictUpdate, GroupingSets, JoinType, NullsOrder, OnConflict,
+    OrderBy, Query, QueryType, TableFunction, TruncateOptions,
+};
+use crate::transaction::IsolationLevel;
 use crate::types::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Prefix used for named placeholders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamedStyle {
+    /// `:name`, understood by SQLite and mysql_async
+    #[default]
+    Colon,
+    /// `@name`, understood by SQLite and SQL Server drivers
+    At,
+}
+
+impl NamedStyle {
+    /// Get the placeholder for a named parameter
+    pub fn placeholder(&self, name: &str) -> String {
+        match self {
+            NamedStyle::Colon => format!(":{}", name),
+            NamedStyle::At => format!("@{}", name),
+        }
+    }
+}
 
 /// A SQL fragment with its parameters
 #[derive(Debug, Clone)]
@@ -13,6 +41,8 @@ pub struct SqlFragment {
     pub sql: String,
     /// The parameter values
     pub params: Vec<Value>,
+    /// Values for `:name` / `@name` placeholders
+    pub named: BTreeMap<String, Value>,
 }
 
 impl SqlFragment {
@@ -21,6 +51,7 @@ impl SqlFragment {
         Self {
             sql: String::new(),
             params: Vec::new(),
+            named: BTreeMap::new(),
         }
     }
 
@@ -29,9 +60,25 @@ impl SqlFragment {
         Self {
             sql: sql.into(),
             params: Vec::new(),
+            named: BTreeMap::new(),
+        }
+    }
+
+    /// Create from SQL string and its positional parameters
+    pub fn with_params(sql: impl Into<String>, params: Vec<Value>) -> Self {
+        Self {
+            sql: sql.into(),
+            params,
+            named: BTreeMap::new(),
         }
     }
 
+    /// Bind a value to a named placeholder
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.named.insert(name.into(), value.into());
+        self
+    }
+
     /// Append SQL
     pub fn push_sql(&mut self, sql: &str) {
         self.sql.push_str(sql);
@@ -43,10 +90,173 @@ impl SqlFragment {
         self.params.len()
     }
 
+    /// Append a named parameter, returning its placeholder
+    pub fn push_named_param(&mut self, name: &str, value: Value, style: NamedStyle) -> String {
+        self.named.insert(name.to_string(), value);
+        style.placeholder(name)
+    }
+
+    /// Check if any parameters are bound by name
+    pub fn has_named_params(&self) -> bool {
+        !self.named.is_empty()
+    }
+
     /// Combine with another fragment
     pub fn append(&mut self, other: SqlFragment) {
         self.sql.push_str(&other.sql);
         self.params.extend(other.params);
+        self.named.extend(other.named);
+    }
+
+    /// Append a separately generated fragment, shifting its numbered
+    /// placeholders (`$1`, `?1`) past the parameters already here
+    pub fn append_numbered(&mut self, other: SqlFragment) {
+        let offset = self.params.len();
+        let mut last = 0;
+
+        for (span, param) in placeholders(&other.sql, false) {
+            let text = &other.sql[span.clone()];
+            if let Param::Index(n) = param {
+                if text.len() > 1 && (text.starts_with('$') || text.starts_with('?')) {
+                    self.sql.push_str(&other.sql[last..span.start]);
+                    self.sql.push_str(&format!("{}{}", &text[..1], n + offset));
+                    last = span.end;
+                }
+            }
+        }
+
+        self.sql.push_str(&other.sql[last..]);
+        self.params.extend(other.params);
+        self.named.extend(other.named);
+    }
+
+    /// Rewrite all placeholders into the dialect's positional ones
+    ///
+    /// Drivers without named binds get a plain parameter list; a name used
+    /// twice shares one parameter where the dialect numbers placeholders.
+    pub fn to_positional(&self, dialect: &dyn Dialect) -> Result<SqlFragment> {
+        let numbered = dialect.placeholder(1) != dialect.placeholder(2);
+        let mut out = SqlFragment::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut last = 0;
+
+        for (span, param) in placeholders(&self.sql, dialect.binds_at_names()) {
+            let (key, value) = match param {
+                Param::Index(n) => (format!("${}", n), self.params.get(n.wrapping_sub(1))),
+                Param::Name(name) => (name.to_string(), self.named.get(name)),
+            };
+            let value = value.ok_or_else(|| {
+                ChakraError::Query(QueryError::Invalid {
+                    message: format!("No value bound for placeholder {}", &self.sql[span.clone()]),
+                })
+            })?;
+
+            out.push_sql(&self.sql[last..span.start]);
+            let idx = match seen.get(&key) {
+                Some(&idx) if numbered => idx,
+                _ => {
+                    let idx = out.push_param(value.clone());
+                    seen.insert(key, idx);
+                    idx
+                }
+            };
+            out.push_sql(&dialect.placeholder(idx));
+            last = span.end;
+        }
+
+        out.push_sql(&self.sql[last..]);
+        Ok(out)
+    }
+
+    /// Check that every placeholder has a value and every value is used
+    ///
+    /// Hand-written SQL is checked before it is sent to `dialect`, so a
+    /// miscounted parameter list fails with a clear error instead of binding
+    /// the wrong values.
+    pub fn check_params(&self, dialect: &dyn Dialect) -> Result<()> {
+        let mut used = vec![false; self.params.len()];
+        let mut names = Vec::new();
+        for (span, param) in placeholders(&self.sql, dialect.binds_at_names()) {
+            match param {
+                Param::Index(n) if (1..=used.len()).contains(&n) => used[n - 1] = true,
+                Param::Name(name) if self.named.contains_key(name) => names.push(name),
+                _ => {
+                    return Err(ChakraError::Query(QueryError::Invalid {
+                        message: format!("No value bound for placeholder {}", &self.sql[span]),
+                    }))
+                }
+            }
+        }
+        if let Some(unused) = used.iter().position(|used| !used) {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: format!(
+                    "{} parameters given but placeholder ${} is never used",
+                    self.params.len(),
+                    unused + 1
+                ),
+            }));
+        }
+        if let Some(unused) = self
+            .named
+            .keys()
+            .find(|name| !names.contains(&name.as_str()))
+        {
+            return Err(ChakraError::Query(QueryError::Invalid {
+                message: format!("Parameter :{} is bound but never used", unused),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Rewrite positional placeholders into named ones (`$1` becomes `:p1`)
+    pub fn into_named(self, style: NamedStyle) -> SqlFragment {
+        let mut out = SqlFragment::new();
+        out.named = self.named;
+        let mut last = 0;
+
+        for (span, param) in placeholders(&self.sql, false) {
+            let name = match param {
+                Param::Index(n) => match self.params.get(n.wrapping_sub(1)) {
+                    Some(value) => {
+                        let name = format!("p{}", n);
+                        out.named.insert(name.clone(), value.clone());
+                        name
+                    }
+                    None => continue,
+                },
+                Param::Name(name) => name.to_string(),
+            };
+            out.push_sql(&self.sql[last..span.start]);
+            out.push_sql(&style.placeholder(&name));
+            last = span.end;
+        }
+
+        out.push_sql(&self.sql[last..]);
+        out
+    }
+
+    /// The SQL with its parameters written in as literals, for statements
+    /// that can't take parameters, such as view definitions
+    ///
+    /// Placeholders without a value are left as they are.
+    pub fn inline_params(&self, dialect: &dyn Dialect) -> String {
+        let mut sql = String::with_capacity(self.sql.len());
+        let mut last = 0;
+
+        for (span, param) in placeholders(&self.sql, dialect.binds_at_names()) {
+            let value = match param {
+                Param::Index(n) => self.params.get(n.wrapping_sub(1)),
+                Param::Name(name) => self.named.get(name),
+            };
+            if let Some(value) = value {
+                sql.push_str(&self.sql[last..span.start]);
+                sql.push_str(&dialect.literal(value));
+                last = span.end;
+            }
+        }
+
+        sql.push_str(&self.sql[last..]);
+        sql
     }
 }
 
@@ -56,6 +266,51 @@ impl Default for SqlFragment {
     }
 }
 
+/// Render tags as a sqlcommenter comment, e.g. `/*route='%2Fpay',service='checkout'*/`
+///
+/// Keys and values are percent-encoded, which also keeps `*/`, quotes and
+/// `$` out of the comment body.
+pub fn sql_comment(tags: &BTreeMap<String, String>) -> String {
+    fn encode(s: &str) -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    let pairs: Vec<String> = tags
+        .iter()
+        .map(|(k, v)| format!("{}='{}'", encode(k), encode(v)))
+        .collect();
+    format!("/*{}*/", pairs.join(","))
+}
+
+/// Lowercase hex digits of `bytes`
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// An array as the quoted JSON text it is stored as where there is no
+/// array type
+fn json_array_literal(items: &[Value]) -> String {
+    let json: Vec<serde_json::Value> = items
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Int32(i) => serde_json::json!(i),
+            Value::Int64(i) => serde_json::json!(i),
+            Value::Float64(f) => serde_json::json!(f),
+            Value::Bool(b) => serde_json::json!(b),
+            _ => serde_json::Value::Null,
+        })
+        .collect();
+    Value::String(serde_json::Value::Array(json).to_string()).to_sql_literal()
+}
+
 /// SQL dialect trait
 pub trait Dialect: Send + Sync {
     /// Get the dialect name
@@ -67,17 +322,253 @@ pub trait Dialect: Send + Sync {
     /// Quote an identifier
     fn quote_identifier(&self, name: &str) -> String;
 
+    /// Quote a table or column reference, leaving expressions as written
+    ///
+    /// Plain and dotted names are quoted part by part and `*` is kept, so
+    /// `Order.userId` becomes `"Order"."userId"`. Anything else, such as
+    /// `COUNT(*)`, `name AS n` or an already quoted name, passes through.
+    fn quote_name(&self, name: &str) -> String {
+        let is_ident = |part: &str| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_')
+                && part
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        };
+        let parts: Vec<&str> = name.split('.').collect();
+        let (last, qualifiers) = parts.split_last().expect("split yields one part");
+        if !qualifiers.iter().all(|p| is_ident(p)) || !(is_ident(last) || *last == "*") {
+            return name.to_string();
+        }
+        parts
+            .iter()
+            .map(|p| {
+                if *p == "*" {
+                    "*".to_string()
+                } else {
+                    self.quote_identifier(p)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Quote each of a list of names and join them with commas
+    fn quote_names(&self, names: &[String]) -> String {
+        names
+            .iter()
+            .map(|n| self.quote_name(n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Render a value as an inline literal, for generated scripts and
+    /// statements that can't take parameters
+    fn literal(&self, value: &Value) -> String;
+
+    /// Whether `ON CONFLICT DO UPDATE` must name the conflicting columns
+    fn upsert_requires_target(&self) -> bool {
+        false
+    }
+
+    /// Whether `@name` in hand-written SQL is a named placeholder rather
+    /// than a user variable
+    fn binds_at_names(&self) -> bool {
+        false
+    }
+
     /// Generate SQL from a query
     fn generate(&self, query: &Query) -> SqlFragment;
 
     /// Generate SQL from an expression
     fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment);
 
+    /// Maximum number of bound parameters in one statement
+    fn max_params(&self) -> usize;
+
+    /// Whether a multi-row INSERT can write `DEFAULT` for a column a row
+    /// leaves out
+    fn supports_default_values(&self) -> bool {
+        true
+    }
+
+    /// Split an INSERT into chunks of rows that fit `max_params` and, where
+    /// `DEFAULT` can't stand in for a missing value, that set the same columns
+    fn insert_batches(&self, query: &Query) -> Vec<Query> {
+        let groups = if self.supports_default_values() {
+            vec![query.clone()]
+        } else {
+            query.insert_groups()
+        };
+        groups
+            .iter()
+            .flat_map(|group| group.insert_chunks(self.max_params()))
+            .collect()
+    }
+
+    /// Generate an INSERT as one statement per batch from `insert_batches`
+    fn generate_batches(&self, query: &Query) -> Vec<SqlFragment> {
+        self.insert_batches(query)
+            .iter()
+            .map(|batch| self.generate(batch))
+            .collect()
+    }
+
     /// Check if this dialect supports RETURNING
     fn supports_returning(&self) -> bool;
 
     /// Check if this dialect supports ILIKE
     fn supports_ilike(&self) -> bool;
+
+    /// Check if this dialect supports `NULLS FIRST` / `NULLS LAST`
+    fn supports_nulls_order(&self) -> bool;
+
+    /// Check if this dialect supports `BETWEEN SYMMETRIC`
+    fn supports_between_symmetric(&self) -> bool;
+
+    /// Check if this dialect supports `FILTER (WHERE ...)` on aggregates
+    fn supports_aggregate_filter(&self) -> bool;
+
+    /// This dialect's name for an aggregate, e.g. `GROUP_CONCAT` for
+    /// STRING_AGG on MySQL
+    fn aggregate_name(&self, function: &AggregateFunc) -> &'static str;
+
+    /// Check if a string aggregate takes its separator in a trailing
+    /// `SEPARATOR ','` clause instead of as a second argument
+    fn uses_separator_clause(&self) -> bool;
+
+    /// Check if this dialect supports ROLLUP, CUBE and GROUPING SETS in GROUP BY
+    fn supports_grouping_sets(&self) -> bool;
+
+    /// Check if this dialect has range types and their `&&` and `@>` operators
+    fn supports_ranges(&self) -> bool;
+
+    /// Reject a query using features this dialect cannot express, rather
+    /// than sending SQL the database will fail to parse
+    fn validate(&self, query: &Query) -> Result<()> {
+        validate_ranges(query, self.supports_ranges(), self.name())?;
+        validate_conflict_target(query, self.upsert_requires_target(), self.name())?;
+        validate_default_values(query, self.supports_default_values(), self.name())?;
+        validate_bounded_offset(query, self.row_locator().is_some(), self.name())?;
+        match &query.grouping_sets {
+            Some(sets) if !self.supports_grouping_sets() => {
+                Err(ChakraError::Query(QueryError::Invalid {
+                    message: format!(
+                        "GROUP BY {} is not supported by {}",
+                        sets.keyword(),
+                        self.name()
+                    ),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compare a quoted timestamp column with the time `seconds` before now:
+    /// newer or equal, or strictly older when `older`
+    ///
+    /// The default is standard SQL interval arithmetic on `CURRENT_TIMESTAMP`.
+    fn relative_time(&self, column: &str, seconds: u64, older: bool) -> String {
+        let op = if older { "<" } else { ">=" };
+        format!(
+            "{} {} CURRENT_TIMESTAMP - INTERVAL '{}' SECOND",
+            column, op, seconds
+        )
+    }
+
+    /// Rewrite a portable scalar function call, such as those built by
+    /// `Expr::greatest` or `Expr::date_trunc`, into this dialect's spelling;
+    /// `None` renders the call as written
+    fn translate_function(&self, _name: &str, _args: &[Expr]) -> Option<Expr> {
+        None
+    }
+
+    /// Column addressing a physical row, used to emulate ORDER BY and LIMIT
+    /// on UPDATE and DELETE; `None` if the dialect takes them directly
+    fn row_locator(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Wrap SELECT `sql` in a query returning its `row_count` and the XOR
+    /// of a hash of each row as `checksum`, or `None` if rows must be hashed
+    /// client-side
+    ///
+    /// `columns` names the result columns, or is empty if they are unknown.
+    fn checksum_sql(&self, _sql: &str, _columns: &[String]) -> Option<String> {
+        None
+    }
+
+    /// Drop a temporary table, doing nothing if it is gone
+    fn drop_temp_table(&self, table: &str) -> String {
+        format!("DROP TABLE IF EXISTS {}", self.quote_name(table))
+    }
+
+    /// Defer checking the deferrable constraints `names`, or all of them if
+    /// empty, to the end of the transaction, or check them immediately again
+    fn set_constraints(&self, _names: &[&str], _deferred: bool) -> Result<String> {
+        Err(ChakraError::config(format!(
+            "{} cannot defer constraints",
+            self.name()
+        )))
+    }
+
+    /// Statements that begin a transaction, at `isolation` if given or the
+    /// database's default level otherwise
+    fn begin_transaction(&self, isolation: Option<IsolationLevel>) -> Vec<String> {
+        match isolation {
+            Some(level) => vec![format!(
+                "START TRANSACTION ISOLATION LEVEL {}",
+                level.as_sql()
+            )],
+            None => vec!["BEGIN".to_string()],
+        }
+    }
+
+    /// Generate one UPDATE for all rows of a bulk update
+    ///
+    /// The default picks each new value with `CASE key WHEN ... END` and
+    /// limits the statement to the rows' keys.
+    fn generate_bulk_update(&self, update: &BulkUpdate) -> SqlFragment {
+        let mut fragment = SqlFragment::new();
+        let key = self.quote_name(&update.key);
+        fragment.push_sql(&format!("UPDATE {} SET ", self.quote_name(&update.table)));
+        for (i, (column, _)) in update.columns.iter().enumerate() {
+            if i > 0 {
+                fragment.push_sql(", ");
+            }
+            let column = self.quote_name(column);
+            fragment.push_sql(&format!("{} = CASE {}", column, key));
+            for row in &update.rows {
+                let when = fragment.push_param(row[0].clone());
+                let then = fragment.push_param(row[i + 1].clone());
+                fragment.push_sql(&format!(
+                    " WHEN {} THEN {}",
+                    self.placeholder(when),
+                    self.placeholder(then)
+                ));
+            }
+            fragment.push_sql(&format!(" ELSE {} END", column));
+        }
+        fragment.push_sql(&format!(" WHERE {} IN (", key));
+        for (i, row) in update.rows.iter().enumerate() {
+            if i > 0 {
+                fragment.push_sql(", ");
+            }
+            let idx = fragment.push_param(row[0].clone());
+            fragment.push_sql(&self.placeholder(idx));
+        }
+        fragment.push_sql(")");
+        for (column, value) in &update.conditions {
+            let idx = fragment.push_param(value.clone());
+            fragment.push_sql(&format!(
+                " AND {} = {}",
+                self.quote_name(column),
+                self.placeholder(idx)
+            ));
+        }
+        fragment
+    }
 }
 
 /// PostgreSQL dialect
@@ -93,8 +584,35 @@ impl Dialect for PostgresDialect {
         format!("${}", index)
     }
 
+    fn max_params(&self) -> usize {
+        // The wire protocol counts parameters in an Int16
+        65535
+    }
+
     fn quote_identifier(&self, name: &str) -> String {
-        format!("\"{}\"", name.replace('"', "\"\""))
+        identifier::quote(self.name(), name)
+    }
+
+    fn literal(&self, value: &Value) -> String {
+        match value {
+            Value::Float64(f) if f.is_nan() => "'NaN'::float8".to_string(),
+            Value::Float64(f) if f.is_infinite() => {
+                format!("'{}Infinity'::float8", if *f < 0.0 { "-" } else { "" })
+            }
+            // X'..' would be a bit string
+            Value::Bytes(b) => format!("'\\x{}'::bytea", hex(b)),
+            // ARRAY[] can't infer its element type
+            Value::Array(items) if items.is_empty() => "'{}'".to_string(),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| self.literal(v)).collect();
+                format!("ARRAY[{}]", items.join(", "))
+            }
+            _ => value.to_sql_literal(),
+        }
+    }
+
+    fn upsert_requires_target(&self) -> bool {
+        true
     }
 
     fn supports_returning(&self) -> bool {
@@ -105,30 +623,225 @@ impl Dialect for PostgresDialect {
         true
     }
 
+    fn supports_nulls_order(&self) -> bool {
+        true
+    }
+
+    fn relative_time(&self, column: &str, seconds: u64, older: bool) -> String {
+        let op = if older { "<" } else { ">=" };
+        format!("{} {} now() - interval '{} seconds'", column, op, seconds)
+    }
+
+    fn translate_function(&self, name: &str, args: &[Expr]) -> Option<Expr> {
+        // TIMEZONE(zone, ts) is `ts AT TIME ZONE zone`: local time of a TIMESTAMPTZ
+        let local = |arg: &Expr, zone: Option<&Expr>| match zone {
+            Some(zone) => Expr::function("TIMEZONE", vec![zone.clone(), arg.clone()]),
+            None => arg.clone(),
+        };
+        match name {
+            "DATE_TRUNC" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                zone?;
+                Some(Expr::date_trunc(unit, local(arg, zone)))
+            }
+            "EXTRACT" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                let part = Expr::function(
+                    "DATE_PART",
+                    vec![Expr::Raw(unit.as_sql().to_string()), local(arg, zone)],
+                );
+                Some(Expr::cast(part, "INTEGER"))
+            }
+            _ => None,
+        }
+    }
+
+    fn supports_aggregate_filter(&self) -> bool {
+        true
+    }
+
+    fn aggregate_name(&self, function: &AggregateFunc) -> &'static str {
+        function.as_sql()
+    }
+
+    fn uses_separator_clause(&self) -> bool {
+        false
+    }
+
+    fn supports_grouping_sets(&self) -> bool {
+        true
+    }
+
+    fn supports_ranges(&self) -> bool {
+        true
+    }
+
+    fn supports_between_symmetric(&self) -> bool {
+        true
+    }
+
+    fn row_locator(&self) -> Option<&'static str> {
+        Some("ctid")
+    }
+
+    /// Hashes each row's text form; `bit_xor` needs PostgreSQL 14
+    fn checksum_sql(&self, sql: &str, _columns: &[String]) -> Option<String> {
+        Some(format!(
+            "SELECT COUNT(*) AS {}, COALESCE(BIT_XOR(('x' || LEFT(MD5(t::text), 16))::bit(64)::bigint), 0) AS {} FROM ({}) AS t",
+            crate::checksum::ROW_COUNT_COLUMN,
+            crate::checksum::CHECKSUM_COLUMN,
+            sql
+        ))
+    }
+
+    fn set_constraints(&self, names: &[&str], deferred: bool) -> Result<String> {
+        let names = match names {
+            [] => "ALL".to_string(),
+            names => names
+                .iter()
+                .map(|n| self.quote_name(n))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mode = if deferred { "DEFERRED" } else { "IMMEDIATE" };
+        Ok(format!("SET CONSTRAINTS {} {}", names, mode))
+    }
+
+    /// `UPDATE t SET a = v.a FROM (VALUES ...) AS v (key, a) WHERE t.key = v.key`
+    ///
+    /// Every value is cast to its column's type, since parameters in VALUES
+    /// have nothing else to infer a type from.
+    fn generate_bulk_update(&self, update: &BulkUpdate) -> SqlFragment {
+        const ALIAS: &str = "chakra_values";
+        let mut fragment = SqlFragment::new();
+        let table = self.quote_name(&update.table);
+        let key = self.quote_name(&update.key);
+        let assignments: Vec<String> = update
+            .columns
+            .iter()
+            .map(|(column, _)| {
+                let column = self.quote_name(column);
+                format!("{} = {}.{}", column, ALIAS, column)
+            })
+            .collect();
+        fragment.push_sql(&format!(
+            "UPDATE {} SET {} FROM (VALUES ",
+            table,
+            assignments.join(", ")
+        ));
+
+        let types: Vec<String> = std::iter::once(&update.key_type)
+            .chain(update.columns.iter().map(|(_, t)| t))
+            .map(|t| t.to_postgres_type())
+            .collect();
+        for (i, row) in update.rows.iter().enumerate() {
+            fragment.push_sql(if i > 0 { ", (" } else { "(" });
+            for (j, (value, cast)) in row.iter().zip(&types).enumerate() {
+                if j > 0 {
+                    fragment.push_sql(", ");
+                }
+                let idx = fragment.push_param(value.clone());
+                fragment.push_sql(&format!("{}::{}", self.placeholder(idx), cast));
+            }
+            fragment.push_sql(")");
+        }
+
+        let names: Vec<String> = std::iter::once(&update.key)
+            .chain(update.columns.iter().map(|(c, _)| c))
+            .map(|c| self.quote_name(c))
+            .collect();
+        fragment.push_sql(&format!(
+            ") AS {} ({}) WHERE {}.{} = {}.{}",
+            ALIAS,
+            names.join(", "),
+            table,
+            key,
+            ALIAS,
+            key
+        ));
+        for (column, value) in &update.conditions {
+            let idx = fragment.push_param(value.clone());
+            fragment.push_sql(&format!(
+                " AND {}.{} = {}",
+                table,
+                self.quote_name(column),
+                self.placeholder(idx)
+            ));
+        }
+        fragment
+    }
+
     fn generate(&self, query: &Query) -> SqlFragment {
+        let mut arrays = false;
+        query.walk_exprs(&mut |e| {
+            arrays |= matches!(e, Expr::In { values, .. } if bindable_array(values))
+        });
+        if !arrays {
+            return self.generate_query(query, self);
+        }
+
+        // Bind IN lists as one array so the statement text doesn't depend on
+        // the list length and large lists don't run into the parameter limit
+        let mut query = query.clone();
+        query.walk_exprs_mut(&mut |e| {
+            if let Expr::In {
+                column,
+                values,
+                negated,
+            } = e
+            {
+                if bindable_array(values) {
+                    *e = Expr::InArray {
+                        column: std::mem::take(column),
+                        values: std::mem::take(values),
+                        negated: *negated,
+                    };
+                }
+            }
+        });
+        self.generate_query(&query, self)
+    }
+
+    fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment) {
+        self.generate_expr_inner(expr, fragment, self);
+    }
+}
+
+impl PostgresDialect {
+    /// Generate without rewriting IN lists, shared with the other dialects
+    fn generate_query(&self, query: &Query, q: &dyn Dialect) -> SqlFragment {
         let mut fragment = SqlFragment::new();
 
+        if !query.ctes.is_empty() {
+            self.generate_with(query, &mut fragment, q);
+        }
+
         match query.query_type {
-            QueryType::Select => self.generate_select(query, &mut fragment),
-            QueryType::Insert => self.generate_insert(query, &mut fragment),
-            QueryType::Update => self.generate_update(query, &mut fragment),
-            QueryType::Delete => self.generate_delete(query, &mut fragment),
+            QueryType::Select => self.generate_select(query, &mut fragment, q),
+            QueryType::Insert => self.generate_insert(query, &mut fragment, q),
+            QueryType::Update => self.generate_update(query, &mut fragment, q),
+            QueryType::Delete => self.generate_delete(query, &mut fragment, q),
+            QueryType::Truncate => self.generate_truncate(query, &mut fragment, q),
+        }
+
+        if !query.comments.is_empty() {
+            fragment.sql = format!("{} {}", sql_comment(&query.comments), fragment.sql);
         }
 
         fragment
     }
 
-    fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment) {
+    fn generate_expr_inner(&self, expr: &Expr, fragment: &mut SqlFragment, q: &dyn Dialect) {
         match expr {
             Expr::Column(name) => {
-                fragment.push_sql(name);
+                fragment.push_sql(&q.quote_name(name));
             }
             Expr::Value(value) => {
                 let idx = fragment.push_param(value.clone());
                 fragment.push_sql(&self.placeholder(idx));
             }
             Expr::Compare { column, op, value } => {
-                fragment.push_sql(column);
+                fragment.push_sql(&q.quote_name(column));
                 fragment.push_sql(" ");
                 fragment.push_sql(op.as_sql());
                 if *op != CompareOp::IsNull && *op != CompareOp::IsNotNull {
@@ -138,23 +851,59 @@ impl Dialect for PostgresDialect {
                 }
             }
             Expr::ColumnCompare { left, op, right } => {
-                fragment.push_sql(left);
+                fragment.push_sql(&q.quote_name(left));
                 fragment.push_sql(" ");
                 fragment.push_sql(op.as_sql());
                 fragment.push_sql(" ");
-                fragment.push_sql(right);
+                fragment.push_sql(&q.quote_name(right));
             }
-            Expr::Between { column, low, high } => {
-                fragment.push_sql(column);
-                fragment.push_sql(" BETWEEN ");
-                let idx = fragment.push_param(low.clone());
-                fragment.push_sql(&self.placeholder(idx));
-                fragment.push_sql(" AND ");
-                let idx = fragment.push_param(high.clone());
-                fragment.push_sql(&self.placeholder(idx));
+            Expr::Between {
+                column,
+                low,
+                high,
+                negated,
+                symmetric,
+            } => {
+                let between = |fragment: &mut SqlFragment, low: &Value, high: &Value, symmetric| {
+                    fragment.push_sql(&q.quote_name(column));
+                    fragment.push_sql(if *negated {
+                        " NOT BETWEEN "
+                    } else {
+                        " BETWEEN "
+                    });
+                    if symmetric {
+                        fragment.push_sql("SYMMETRIC ");
+                    }
+                    let idx = fragment.push_param(low.clone());
+                    fragment.push_sql(&self.placeholder(idx));
+                    fragment.push_sql(" AND ");
+                    let idx = fragment.push_param(high.clone());
+                    fragment.push_sql(&self.placeholder(idx));
+                };
+                if !*symmetric || q.supports_between_symmetric() {
+                    between(fragment, low, high, *symmetric);
+                } else {
+                    // Within either ordering of the bounds, or outside both
+                    fragment.push_sql("(");
+                    between(fragment, low, high, false);
+                    fragment.push_sql(if *negated { " AND " } else { " OR " });
+                    between(fragment, high, low, false);
+                    fragment.push_sql(")");
+                }
+            }
+            Expr::RelativeTime {
+                column,
+                seconds,
+                older,
+            } => {
+                fragment.push_sql(&q.relative_time(&q.quote_name(column), *seconds, *older));
             }
-            Expr::In { column, values, negated } => {
-                fragment.push_sql(column);
+            Expr::In {
+                column,
+                values,
+                negated,
+            } => {
+                fragment.push_sql(&q.quote_name(column));
                 if *negated {
                     fragment.push_sql(" NOT IN (");
                 } else {
@@ -175,7 +924,7 @@ impl Dialect for PostgresDialect {
                     if i > 0 {
                         fragment.push_sql(" AND ");
                     }
-                    self.generate_expr(e, fragment);
+                    self.generate_expr_inner(e, fragment, q);
                 }
                 fragment.push_sql(")");
             }
@@ -185,92 +934,336 @@ impl Dialect for PostgresDialect {
                     if i > 0 {
                         fragment.push_sql(" OR ");
                     }
-                    self.generate_expr(e, fragment);
+                    self.generate_expr_inner(e, fragment, q);
                 }
                 fragment.push_sql(")");
             }
             Expr::Not(e) => {
                 fragment.push_sql("NOT (");
-                self.generate_expr(e, fragment);
+                self.generate_expr_inner(e, fragment, q);
                 fragment.push_sql(")");
             }
             Expr::Raw(sql) => {
                 fragment.push_sql(sql);
             }
             Expr::Function { name, args } => {
+                if let Some(call) = q.translate_function(name, args) {
+                    return self.generate_expr_inner(&call, fragment, q);
+                }
                 fragment.push_sql(name);
                 fragment.push_sql("(");
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
                         fragment.push_sql(", ");
                     }
-                    self.generate_expr(arg, fragment);
+                    self.generate_expr_inner(arg, fragment, q);
                 }
                 fragment.push_sql(")");
             }
-            Expr::Aggregate { function, column, distinct } => {
-                fragment.push_sql(function.as_sql());
+            Expr::Aggregate {
+                function,
+                column,
+                distinct,
+                filter,
+                order_by,
+                separator,
+            } => {
+                fragment.push_sql(q.aggregate_name(function));
                 fragment.push_sql("(");
                 if *distinct {
                     fragment.push_sql("DISTINCT ");
                 }
-                fragment.push_sql(column);
+                match filter {
+                    Some(condition) if !q.supports_aggregate_filter() => {
+                        // Rows outside the filter become NULL, which every
+                        // aggregate skips
+                        fragment.push_sql("CASE WHEN ");
+                        self.generate_expr_inner(condition, fragment, q);
+                        fragment.push_sql(" THEN ");
+                        if column == "*" {
+                            fragment.push_sql("1");
+                        } else {
+                            fragment.push_sql(&q.quote_name(column));
+                        }
+                        fragment.push_sql(" END");
+                    }
+                    _ => fragment.push_sql(&q.quote_name(column)),
+                }
+                let separator = (*function == AggregateFunc::StringAgg)
+                    .then(|| quote_literal(separator.as_deref().unwrap_or(",")));
+                if let (Some(separator), false) = (&separator, q.uses_separator_clause()) {
+                    fragment.push_sql(", ");
+                    fragment.push_sql(separator);
+                }
+                self.generate_order_by(order_by, fragment, q);
+                if let (Some(separator), true) = (&separator, q.uses_separator_clause()) {
+                    fragment.push_sql(" SEPARATOR ");
+                    fragment.push_sql(separator);
+                }
                 fragment.push_sql(")");
+                if let (Some(condition), true) = (filter, q.supports_aggregate_filter()) {
+                    fragment.push_sql(" FILTER (WHERE ");
+                    self.generate_expr_inner(condition, fragment, q);
+                    fragment.push_sql(")");
+                }
+            }
+            Expr::Cast { expr, sql_type } => {
+                fragment.push_sql("CAST(");
+                self.generate_expr_inner(expr, fragment, q);
+                fragment.push_sql(&format!(" AS {})", sql_type));
             }
             Expr::Arithmetic { left, op, right } => {
                 fragment.push_sql("(");
-                self.generate_expr(left, fragment);
+                self.generate_expr_inner(left, fragment, q);
                 fragment.push_sql(" ");
                 fragment.push_sql(op.as_sql());
                 fragment.push_sql(" ");
-                self.generate_expr(right, fragment);
+                self.generate_expr_inner(right, fragment, q);
                 fragment.push_sql(")");
             }
-            Expr::Case { conditions, else_result } => {
+            Expr::Case {
+                conditions,
+                else_result,
+            } => {
                 fragment.push_sql("CASE");
                 for (when, then) in conditions {
                     fragment.push_sql(" WHEN ");
-                    self.generate_expr(when, fragment);
+                    self.generate_expr_inner(when, fragment, q);
                     fragment.push_sql(" THEN ");
-                    self.generate_expr(then, fragment);
+                    self.generate_expr_inner(then, fragment, q);
                 }
                 if let Some(else_expr) = else_result {
                     fragment.push_sql(" ELSE ");
-                    self.generate_expr(else_expr, fragment);
+                    self.generate_expr_inner(else_expr, fragment, q);
                 }
                 fragment.push_sql(" END");
             }
             Expr::Subquery(query) => {
                 fragment.push_sql("(");
-                let sub = self.generate(query);
-                fragment.append(sub);
+                let sub = self.generate_query(query, q);
+                fragment.append_numbered(sub);
+                fragment.push_sql(")");
+            }
+            Expr::Exists { query, negated } => {
+                if *negated {
+                    fragment.push_sql("NOT ");
+                }
+                fragment.push_sql("EXISTS (");
+                let sub = self.generate_query(query, q);
+                fragment.append_numbered(sub);
+                fragment.push_sql(")");
+            }
+            Expr::InSubquery {
+                column,
+                query,
+                negated,
+            } => {
+                fragment.push_sql(&q.quote_name(column));
+                if *negated {
+                    fragment.push_sql(" NOT IN (");
+                } else {
+                    fragment.push_sql(" IN (");
+                }
+                let sub = self.generate_query(query, q);
+                fragment.append_numbered(sub);
+                fragment.push_sql(")");
+            }
+            Expr::SubqueryCompare {
+                column,
+                op,
+                quantifier,
+                query,
+            } => {
+                fragment.push_sql(&q.quote_name(column));
+                fragment.push_sql(" ");
+                fragment.push_sql(op.as_sql());
+                fragment.push_sql(" ");
+                if let Some(quantifier) = quantifier {
+                    fragment.push_sql(quantifier.as_sql());
+                    fragment.push_sql(" ");
+                }
+                fragment.push_sql("(");
+                let sub = self.generate_query(query, q);
+                fragment.append_numbered(sub);
+                fragment.push_sql(")");
+            }
+            Expr::JsonGet {
+                column,
+                path,
+                as_text,
+            } => {
+                fragment.push_sql(&Self::json_path(&q.quote_name(column), path, *as_text));
+            }
+            Expr::JsonCompare {
+                column,
+                path,
+                op,
+                value,
+            } => {
+                // `->>` yields text, so cast it to match scalar parameters
+                let cast = match value {
+                    Value::Bool(_) => Some("boolean"),
+                    Value::Int32(_) => Some("integer"),
+                    Value::Int64(_) => Some("bigint"),
+                    Value::Float64(_) => Some("double precision"),
+                    Value::Decimal(_) => Some("numeric"),
+                    _ => None,
+                };
+                match (value, cast) {
+                    (Value::Json(_), _) => {
+                        fragment.push_sql(&Self::json_path(&q.quote_name(column), path, false))
+                    }
+                    (_, Some(cast)) => fragment.push_sql(&format!(
+                        "({})::{}",
+                        Self::json_path(&q.quote_name(column), path, true),
+                        cast
+                    )),
+                    (_, None) => {
+                        fragment.push_sql(&Self::json_path(&q.quote_name(column), path, true))
+                    }
+                }
+                fragment.push_sql(" ");
+                fragment.push_sql(op.as_sql());
+                if *op != CompareOp::IsNull && *op != CompareOp::IsNotNull {
+                    fragment.push_sql(" ");
+                    let idx = fragment.push_param(value.clone());
+                    fragment.push_sql(&self.placeholder(idx));
+                }
+            }
+            Expr::JsonContains { column, value } => {
+                fragment.push_sql(&q.quote_name(column));
+                fragment.push_sql(" @> ");
+                let idx = fragment.push_param(value.clone());
+                fragment.push_sql(&self.placeholder(idx));
+            }
+            Expr::InArray {
+                column,
+                values,
+                negated,
+            } => {
+                fragment.push_sql(&q.quote_name(column));
+                fragment.push_sql(if *negated { " <> ALL(" } else { " = ANY(" });
+                let idx = fragment.push_param(Value::Array(values.clone()));
+                fragment.push_sql(&self.placeholder(idx));
                 fragment.push_sql(")");
             }
+            Expr::ArrayContains { column, values } | Expr::ArrayOverlaps { column, values } => {
+                fragment.push_sql(&q.quote_name(column));
+                fragment.push_sql(if matches!(expr, Expr::ArrayContains { .. }) {
+                    " @> "
+                } else {
+                    " && "
+                });
+                let idx = fragment.push_param(Value::Array(values.clone()));
+                fragment.push_sql(&self.placeholder(idx));
+            }
+            Expr::RangeOverlaps { column, value } | Expr::RangeContains { column, value } => {
+                fragment.push_sql(&q.quote_name(column));
+                fragment.push_sql(if matches!(expr, Expr::RangeOverlaps { .. }) {
+                    " && "
+                } else {
+                    " @> "
+                });
+                let idx = fragment.push_param(value.clone());
+                fragment.push_sql(&self.placeholder(idx));
+                // An untyped parameter after @> is taken to be a range, so a
+                // single element names its type
+                fragment.push_sql(match value {
+                    Value::Int32(_) => "::integer",
+                    Value::Int64(_) => "::bigint",
+                    Value::Decimal(_) => "::numeric",
+                    Value::DateTime(_) => "::timestamptz",
+                    Value::Date(_) => "::date",
+                    _ => "",
+                });
+            }
         }
     }
-}
 
-impl PostgresDialect {
-    fn generate_select(&self, query: &Query, fragment: &mut SqlFragment) {
-        fragment.push_sql("SELECT ");
+    /// `column->'key'` for one key, `column#>'{a,b}'` for a nested path
+    fn json_path(column: &str, path: &[String], as_text: bool) -> String {
+        let arrow = if as_text { ">>" } else { ">" };
+        match path {
+            [key] if key.parse::<u32>().is_ok() => format!("{}-{}{}", column, arrow, key),
+            [key] => format!("{}-{}{}", column, arrow, quote_literal(key)),
+            _ => {
+                let elements: Vec<String> = path
+                    .iter()
+                    .map(|key| {
+                        if !key.is_empty() && !key.contains(|c: char| "{},\" \\".contains(c)) {
+                            key.clone()
+                        } else {
+                            format!("\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\""))
+                        }
+                    })
+                    .collect();
+                let array = format!("{{{}}}", elements.join(","));
+                format!("{}#{}{}", column, arrow, quote_literal(&array))
+            }
+        }
+    }
 
-        if query.distinct {
-            fragment.push_sql("DISTINCT ");
+    fn generate_with(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        fragment.push_sql("WITH ");
+        if query.ctes.iter().any(|cte| cte.recursive_term.is_some()) {
+            fragment.push_sql("RECURSIVE ");
         }
 
-        // Columns
-        if query.columns.is_empty() {
-            fragment.push_sql("*");
-        } else {
-            fragment.push_sql(&query.columns.join(", "));
+        for (i, cte) in query.ctes.iter().enumerate() {
+            if i > 0 {
+                fragment.push_sql(", ");
+            }
+            fragment.push_sql(&q.quote_name(&cte.name));
+            if !cte.columns.is_empty() {
+                fragment.push_sql(" (");
+                fragment.push_sql(&q.quote_names(&cte.columns));
+                fragment.push_sql(")");
+            }
+            fragment.push_sql(" AS (");
+            fragment.append_numbered(self.generate_query(&cte.query, q));
+            if let Some(recursive) = &cte.recursive_term {
+                fragment.push_sql(" UNION ALL ");
+                fragment.append_numbered(self.generate_query(recursive, q));
+            }
+            fragment.push_sql(")");
+        }
+
+        fragment.push_sql(" ");
+    }
+
+    fn generate_select(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        fragment.push_sql("SELECT ");
+
+        if query.distinct {
+            fragment.push_sql("DISTINCT ");
+        }
+
+        // Columns
+        if query.columns.is_empty() && query.select_exprs.is_empty() {
+            fragment.push_sql("*");
+        } else {
+            fragment.push_sql(&q.quote_names(&query.columns));
+        }
+        for (i, (expr, alias)) in query.select_exprs.iter().enumerate() {
+            if i > 0 || !query.columns.is_empty() {
+                fragment.push_sql(", ");
+            }
+            self.generate_expr_inner(expr, fragment, q);
+            fragment.push_sql(" AS ");
+            fragment.push_sql(&q.quote_identifier(alias));
         }
 
         // FROM
         fragment.push_sql(" FROM ");
-        fragment.push_sql(&query.table);
-        if let Some(alias) = &query.alias {
-            fragment.push_sql(" AS ");
-            fragment.push_sql(alias);
+        match &query.from_function {
+            Some(function) => self.generate_table_function(function, fragment, q),
+            None => {
+                fragment.push_sql(&q.quote_name(&query.table));
+                if let Some(alias) = &query.alias {
+                    fragment.push_sql(" AS ");
+                    fragment.push_sql(&q.quote_name(alias));
+                }
+            }
         }
 
         // JOINs
@@ -278,133 +1271,385 @@ impl PostgresDialect {
             fragment.push_sql(" ");
             fragment.push_sql(join.join_type.as_sql());
             fragment.push_sql(" ");
-            fragment.push_sql(&join.table);
+            match &join.function {
+                Some(function) => self.generate_table_function(function, fragment, q),
+                None => fragment.push_sql(&q.quote_name(&join.table)),
+            }
             if let Some(alias) = &join.alias {
                 fragment.push_sql(" AS ");
-                fragment.push_sql(alias);
+                fragment.push_sql(&q.quote_name(alias));
+            }
+            if join.join_type != JoinType::Cross {
+                fragment.push_sql(" ON ");
+                self.generate_expr_inner(&join.on, fragment, q);
             }
-            fragment.push_sql(" ON ");
-            self.generate_expr(&join.on, fragment);
         }
 
         // WHERE
         if let Some(where_clause) = &query.where_clause {
             fragment.push_sql(" WHERE ");
-            self.generate_expr(where_clause, fragment);
+            self.generate_expr_inner(where_clause, fragment, q);
         }
 
         // GROUP BY
-        if !query.group_by.is_empty() {
+        if !query.group_by.is_empty() || query.grouping_sets.is_some() {
             fragment.push_sql(" GROUP BY ");
-            fragment.push_sql(&query.group_by.join(", "));
+            let mut items: Vec<String> = query.group_by.iter().map(|c| q.quote_name(c)).collect();
+            match &query.grouping_sets {
+                Some(GroupingSets::Rollup(columns)) if !q.supports_grouping_sets() => {
+                    // MySQL's spelling, which rolls up every grouped column
+                    items.extend(columns.iter().map(|c| q.quote_name(c)));
+                    fragment.push_sql(&items.join(", "));
+                    fragment.push_sql(" WITH ROLLUP");
+                }
+                Some(sets) => {
+                    items.push(grouping_sets_sql(sets, q));
+                    fragment.push_sql(&items.join(", "));
+                }
+                None => fragment.push_sql(&items.join(", ")),
+            }
         }
 
         // HAVING
         if let Some(having) = &query.having {
             fragment.push_sql(" HAVING ");
-            self.generate_expr(having, fragment);
+            self.generate_expr_inner(having, fragment, q);
         }
 
-        // ORDER BY
-        if !query.order_by.is_empty() {
-            fragment.push_sql(" ORDER BY ");
-            let order_parts: Vec<String> = query
-                .order_by
-                .iter()
-                .map(|o| format!("{} {}", o.column, o.order.as_sql()))
-                .collect();
-            fragment.push_sql(&order_parts.join(", "));
+        self.generate_order_limit(query, fragment, q);
+
+        // OFFSET
+        if let Some(offset) = query.offset {
+            fragment.push_sql(" OFFSET ");
+            fragment.push_sql(&offset.to_string());
         }
 
-        // LIMIT
+        // FOR UPDATE
+        if query.for_update {
+            fragment.push_sql(" FOR UPDATE");
+        }
+    }
+
+    /// `ORDER BY ... LIMIT n`, either part omitted when unset
+    fn generate_order_limit(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        self.generate_order_by(&query.order_by, fragment, q);
+
         if let Some(limit) = query.limit {
             fragment.push_sql(" LIMIT ");
             fragment.push_sql(&limit.to_string());
         }
+    }
+
+    /// ` ORDER BY ...`, or nothing for an empty list
+    fn generate_order_by(&self, order_by: &[OrderBy], fragment: &mut SqlFragment, q: &dyn Dialect) {
+        for (i, order_by) in order_by.iter().enumerate() {
+            fragment.push_sql(if i == 0 { " ORDER BY " } else { ", " });
+            let key = |fragment: &mut SqlFragment| match &order_by.expr {
+                Some(expr) => self.generate_expr_inner(expr, fragment, q),
+                None => fragment.push_sql(&q.quote_name(&order_by.column)),
+            };
+
+            // Without NULLS FIRST/LAST, sort on `key IS NULL` first: true
+            // sorts after false, so DESC puts the NULLs first
+            if let (Some(nulls), false) = (order_by.nulls, q.supports_nulls_order()) {
+                key(fragment);
+                fragment.push_sql(match nulls {
+                    NullsOrder::First => " IS NULL DESC, ",
+                    NullsOrder::Last => " IS NULL ASC, ",
+                });
+            }
+
+            key(fragment);
+            fragment.push_sql(" ");
+            fragment.push_sql(order_by.order.as_sql());
+            if let (Some(nulls), true) = (order_by.nulls, q.supports_nulls_order()) {
+                fragment.push_sql(match nulls {
+                    NullsOrder::First => " NULLS FIRST",
+                    NullsOrder::Last => " NULLS LAST",
+                });
+            }
+        }
+    }
 
-        // OFFSET
+    /// WHERE for UPDATE and DELETE, bounded by ORDER BY, LIMIT and OFFSET if set
+    ///
+    /// Dialects without `LIMIT` on these statements pick the rows in a
+    /// subquery over the same tables, by the query's key columns or, without
+    /// them, the row locator: `WHERE ctid IN (SELECT ctid FROM t WHERE ... LIMIT n)`.
+    /// The filter of an UPDATE with `update_from` tables also stays in the
+    /// outer WHERE, since it joins those tables to the updated rows.
+    fn generate_bounded_where(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        let bounded = query.limit.is_some() || query.offset.is_some() || !query.order_by.is_empty();
+        let locator = match q.row_locator() {
+            Some(locator) if bounded => locator,
+            _ => {
+                if let Some(where_clause) = &query.where_clause {
+                    fragment.push_sql(" WHERE ");
+                    self.generate_expr_inner(where_clause, fragment, q);
+                }
+                self.generate_order_limit(query, fragment, q);
+                return;
+            }
+        };
+
+        let keys = if query.key_columns.is_empty() {
+            vec![locator.to_string()]
+        } else {
+            query
+                .key_columns
+                .iter()
+                .map(|column| q.quote_name(column))
+                .collect()
+        };
+        // Qualify the keys once other tables could share their names
+        let columns = |table: &str| {
+            let columns: Vec<String> = keys
+                .iter()
+                .map(|key| {
+                    if query.update_from.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", table, key)
+                    }
+                })
+                .collect();
+            columns.join(", ")
+        };
+        let table = q.quote_name(&query.table);
+        let inner = query
+            .alias
+            .as_ref()
+            .map_or(table.clone(), |alias| q.quote_name(alias));
+
+        fragment.push_sql(" WHERE ");
+        if let (Some(where_clause), false) = (&query.where_clause, query.update_from.is_empty()) {
+            self.generate_expr_inner(where_clause, fragment, q);
+            fragment.push_sql(" AND ");
+        }
+        fragment.push_sql(&match keys.len() {
+            1 => columns(&table),
+            _ => format!("({})", columns(&table)),
+        });
+        fragment.push_sql(&format!(" IN (SELECT {} FROM {}", columns(&inner), table));
+        if let Some(alias) = &query.alias {
+            fragment.push_sql(" AS ");
+            fragment.push_sql(&q.quote_name(alias));
+        }
+        for from in &query.update_from {
+            fragment.push_sql(", ");
+            fragment.push_sql(&q.quote_name(from));
+        }
+        if let Some(where_clause) = &query.where_clause {
+            fragment.push_sql(" WHERE ");
+            self.generate_expr_inner(where_clause, fragment, q);
+        }
+        self.generate_order_limit(query, fragment, q);
         if let Some(offset) = query.offset {
             fragment.push_sql(" OFFSET ");
             fragment.push_sql(&offset.to_string());
         }
+        fragment.push_sql(")");
+    }
 
-        // FOR UPDATE
-        if query.for_update {
-            fragment.push_sql(" FOR UPDATE");
+    /// `name(args) [WITH ORDINALITY] [AS alias (columns)]`
+    fn generate_table_function(
+        &self,
+        function: &TableFunction,
+        fragment: &mut SqlFragment,
+        q: &dyn Dialect,
+    ) {
+        fragment.push_sql(&function.name);
+        fragment.push_sql("(");
+        for (i, arg) in function.args.iter().enumerate() {
+            if i > 0 {
+                fragment.push_sql(", ");
+            }
+            self.generate_expr_inner(arg, fragment, q);
+        }
+        fragment.push_sql(")");
+        if function.with_ordinality {
+            fragment.push_sql(" WITH ORDINALITY");
+        }
+        if let Some(alias) = &function.alias {
+            fragment.push_sql(" AS ");
+            fragment.push_sql(&q.quote_name(alias));
+            if !function.columns.is_empty() {
+                fragment.push_sql(&format!(" ({})", q.quote_names(&function.columns)));
+            }
         }
     }
 
-    fn generate_insert(&self, query: &Query, fragment: &mut SqlFragment) {
+    fn generate_insert(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        if let (true, Some(source)) = (query.temp_table, &query.source) {
+            // Column lists mean definitions on MySQL, so the SELECT names them
+            fragment.push_sql("CREATE TEMPORARY TABLE ");
+            fragment.push_sql(&q.quote_name(&query.table));
+            fragment.push_sql(" AS ");
+            let sub = self.generate_query(source, q);
+            fragment.append_numbered(sub);
+            return;
+        }
+
         fragment.push_sql("INSERT INTO ");
-        fragment.push_sql(&query.table);
+        fragment.push_sql(&q.quote_name(&query.table));
 
-        if let Some(values) = query.values.first() {
-            let columns: Vec<&String> = values.keys().collect();
+        if let Some(source) = &query.source {
+            if query.columns.iter().any(|c| c != "*") {
+                fragment.push_sql(" (");
+                fragment.push_sql(&q.quote_names(&query.columns));
+                fragment.push_sql(")");
+            }
+            fragment.push_sql(" ");
+            let sub = self.generate_query(source, q);
+            fragment.append_numbered(sub);
+        } else if query.insert_columns().is_empty() && !q.supports_default_values() {
+            // SQLite has neither DEFAULT nor an empty column list in VALUES
+            fragment.push_sql(" DEFAULT VALUES");
+        } else if !query.values.is_empty() {
+            let columns = query.insert_columns();
+            let names: Vec<String> = columns.iter().map(|c| q.quote_name(c)).collect();
             fragment.push_sql(" (");
-            fragment.push_sql(&columns.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", "));
-            fragment.push_sql(") VALUES (");
+            fragment.push_sql(&names.join(", "));
+            fragment.push_sql(") VALUES ");
 
-            for (i, col) in columns.iter().enumerate() {
-                if i > 0 {
+            for (row_idx, values) in query.values.iter().enumerate() {
+                if row_idx > 0 {
                     fragment.push_sql(", ");
                 }
-                let value = values.get(*col).unwrap();
-                let idx = fragment.push_param(value.clone());
-                fragment.push_sql(&self.placeholder(idx));
+                fragment.push_sql("(");
+                for (i, col) in columns.iter().enumerate() {
+                    if i > 0 {
+                        fragment.push_sql(", ");
+                    }
+                    // Rows missing a column fall back to its default
+                    match values.get(*col) {
+                        Some(value) => {
+                            let idx = fragment.push_param(value.clone());
+                            fragment.push_sql(&self.placeholder(idx));
+                        }
+                        None => fragment.push_sql("DEFAULT"),
+                    }
+                }
+                fragment.push_sql(")");
             }
-            fragment.push_sql(")");
+        }
+
+        // ON CONFLICT
+        if let Some(on_conflict) = &query.on_conflict {
+            self.generate_on_conflict(on_conflict, fragment, q);
         }
 
         // RETURNING
-        if !query.returning.is_empty() {
+        if !query.returning.is_empty() && q.supports_returning() {
             fragment.push_sql(" RETURNING ");
-            fragment.push_sql(&query.returning.join(", "));
+            fragment.push_sql(&q.quote_names(&query.returning));
+        }
+    }
+
+    fn generate_on_conflict(
+        &self,
+        on_conflict: &OnConflict,
+        fragment: &mut SqlFragment,
+        q: &dyn Dialect,
+    ) {
+        fragment.push_sql(" ON CONFLICT");
+        if !on_conflict.target.is_empty() {
+            fragment.push_sql(" (");
+            fragment.push_sql(&q.quote_names(&on_conflict.target));
+            fragment.push_sql(")");
+        }
+
+        match &on_conflict.action {
+            ConflictAction::DoNothing => fragment.push_sql(" DO NOTHING"),
+            ConflictAction::DoUpdate(updates) => {
+                fragment.push_sql(" DO UPDATE SET ");
+                for (i, update) in updates.iter().enumerate() {
+                    if i > 0 {
+                        fragment.push_sql(", ");
+                    }
+                    let value = match update {
+                        ConflictUpdate::Excluded(column) => {
+                            format!("EXCLUDED.{}", q.quote_name(column))
+                        }
+                        ConflictUpdate::Value(_, value) => {
+                            let idx = fragment.push_param(value.clone());
+                            self.placeholder(idx)
+                        }
+                    };
+                    fragment.push_sql(&format!("{} = {}", q.quote_name(update.column()), value));
+                }
+            }
         }
     }
 
-    fn generate_update(&self, query: &Query, fragment: &mut SqlFragment) {
+    fn generate_update(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
         fragment.push_sql("UPDATE ");
-        fragment.push_sql(&query.table);
+        fragment.push_sql(&q.quote_name(&query.table));
         fragment.push_sql(" SET ");
 
+        let mut first = true;
         if let Some(values) = query.values.first() {
-            let parts: Vec<String> = values
-                .iter()
-                .map(|(col, val)| {
-                    let idx = fragment.push_param(val.clone());
-                    format!("{} = {}", col, self.placeholder(idx))
-                })
-                .collect();
-            fragment.push_sql(&parts.join(", "));
+            for (col, val) in values {
+                if !first {
+                    fragment.push_sql(", ");
+                }
+                first = false;
+                let idx = fragment.push_param(val.clone());
+                fragment.push_sql(&format!(
+                    "{} = {}",
+                    q.quote_name(col),
+                    self.placeholder(idx)
+                ));
+            }
+        }
+        for (col, expr) in &query.set_exprs {
+            if !first {
+                fragment.push_sql(", ");
+            }
+            first = false;
+            fragment.push_sql(&q.quote_name(col));
+            fragment.push_sql(" = ");
+            self.generate_expr_inner(expr, fragment, q);
         }
 
-        // WHERE
-        if let Some(where_clause) = &query.where_clause {
-            fragment.push_sql(" WHERE ");
-            self.generate_expr(where_clause, fragment);
+        // FROM
+        if !query.update_from.is_empty() {
+            fragment.push_sql(" FROM ");
+            fragment.push_sql(&q.quote_names(&query.update_from));
         }
 
+        // WHERE, ORDER BY and LIMIT
+        self.generate_bounded_where(query, fragment, q);
+
         // RETURNING
-        if !query.returning.is_empty() {
+        if !query.returning.is_empty() && q.supports_returning() {
             fragment.push_sql(" RETURNING ");
-            fragment.push_sql(&query.returning.join(", "));
+            fragment.push_sql(&q.quote_names(&query.returning));
+        }
+    }
+
+    fn generate_truncate(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
+        fragment.push_sql("TRUNCATE TABLE ");
+        fragment.push_sql(&q.quote_name(&query.table));
+        if query.truncate.restart_identity {
+            fragment.push_sql(" RESTART IDENTITY");
+        }
+        if query.truncate.cascade {
+            fragment.push_sql(" CASCADE");
         }
     }
 
-    fn generate_delete(&self, query: &Query, fragment: &mut SqlFragment) {
+    fn generate_delete(&self, query: &Query, fragment: &mut SqlFragment, q: &dyn Dialect) {
         fragment.push_sql("DELETE FROM ");
-        fragment.push_sql(&query.table);
+        fragment.push_sql(&q.quote_name(&query.table));
 
-        // WHERE
-        if let Some(where_clause) = &query.where_clause {
-            fragment.push_sql(" WHERE ");
-            self.generate_expr(where_clause, fragment);
-        }
+        // WHERE, ORDER BY and LIMIT
+        self.generate_bounded_where(query, fragment, q);
 
         // RETURNING
-        if !query.returning.is_empty() {
+        if !query.returning.is_empty() && q.supports_returning() {
             fragment.push_sql(" RETURNING ");
-            fragment.push_sql(&query.returning.join(", "));
+            fragment.push_sql(&q.quote_names(&query.returning));
         }
     }
 }
@@ -422,8 +1667,25 @@ impl Dialect for MySqlDialect {
         "?".to_string()
     }
 
+    fn max_params(&self) -> usize {
+        // Prepared statements count placeholders in a u16
+        65535
+    }
+
     fn quote_identifier(&self, name: &str) -> String {
-        format!("`{}`", name.replace('`', "``"))
+        identifier::quote(self.name(), name)
+    }
+
+    fn literal(&self, value: &Value) -> String {
+        match value {
+            // DOUBLE has no NaN or infinity
+            Value::Float64(f) if !f.is_finite() => "NULL".to_string(),
+            Value::DateTime(dt) => format!("'{}'", dt.format("%Y-%m-%d %H:%M:%S%.6f")),
+            Value::Array(items) => json_array_literal(items).replace('\\', "\\\\"),
+            // Backslash escapes inside quotes unless NO_BACKSLASH_ESCAPES is
+            // set, and doubling it is harmless then as well
+            _ => value.to_sql_literal().replace('\\', "\\\\"),
+        }
     }
 
     fn supports_returning(&self) -> bool {
@@ -434,17 +1696,185 @@ impl Dialect for MySqlDialect {
         false
     }
 
+    fn supports_nulls_order(&self) -> bool {
+        false
+    }
+
+    /// The level is set for the next transaction only, so it goes first
+    fn begin_transaction(&self, isolation: Option<IsolationLevel>) -> Vec<String> {
+        let mut statements: Vec<String> = isolation
+            .iter()
+            .map(|level| format!("SET TRANSACTION ISOLATION LEVEL {}", level.as_sql()))
+            .collect();
+        statements.push("BEGIN".to_string());
+        statements
+    }
+
+    fn relative_time(&self, column: &str, seconds: u64, older: bool) -> String {
+        // Timestamps are stored as UTC, so NOW() would be off by the session
+        // time zone
+        let op = if older { "<" } else { ">=" };
+        format!(
+            "{} {} DATE_SUB(UTC_TIMESTAMP(), INTERVAL {} SECOND)",
+            column, op, seconds
+        )
+    }
+
+    fn translate_function(&self, name: &str, args: &[Expr]) -> Option<Expr> {
+        match name {
+            // LENGTH counts bytes
+            "LENGTH" => Some(Expr::function("CHAR_LENGTH", args.to_vec())),
+            // CONCAT is NULL if any argument is; CONCAT_WS skips them
+            "CONCAT" => {
+                let args = std::iter::once(Expr::raw("''")).chain(args.iter().cloned());
+                Some(Expr::function("CONCAT_WS", args.collect()))
+            }
+            "DATE_TRUNC" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                let arg = &mysql_local_time(arg, zone);
+                let start = match unit {
+                    DatePart::Week => Expr::function(
+                        "SUBDATE",
+                        vec![
+                            Expr::function("DATE", vec![arg.clone()]),
+                            Expr::function("WEEKDAY", vec![arg.clone()]),
+                        ],
+                    ),
+                    _ => {
+                        let format = match unit {
+                            DatePart::Year => "'%Y-01-01'",
+                            DatePart::Month => "'%Y-%m-01'",
+                            DatePart::Hour => "'%Y-%m-%d %H:00:00'",
+                            DatePart::Minute => "'%Y-%m-%d %H:%i:00'",
+                            _ => "'%Y-%m-%d'",
+                        };
+                        Expr::function("DATE_FORMAT", vec![arg.clone(), Expr::raw(format)])
+                    }
+                };
+                Some(Expr::function("TIMESTAMP", vec![start]))
+            }
+            "EXTRACT" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                let arg = mysql_local_time(arg, zone);
+                Some(match unit {
+                    // Mode 3: ISO weeks, starting on Monday
+                    DatePart::Week => Expr::function("WEEK", vec![arg, Expr::raw("3")]),
+                    DatePart::Year => Expr::function("YEAR", vec![arg]),
+                    DatePart::Month => Expr::function("MONTH", vec![arg]),
+                    DatePart::Day => Expr::function("DAYOFMONTH", vec![arg]),
+                    DatePart::Hour => Expr::function("HOUR", vec![arg]),
+                    DatePart::Minute => Expr::function("MINUTE", vec![arg]),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn supports_aggregate_filter(&self) -> bool {
+        false
+    }
+
+    fn aggregate_name(&self, function: &AggregateFunc) -> &'static str {
+        match function {
+            AggregateFunc::StringAgg => "GROUP_CONCAT",
+            AggregateFunc::ArrayAgg => "JSON_ARRAYAGG",
+            // Booleans are stored as 0/1
+            AggregateFunc::BoolAnd => "MIN",
+            AggregateFunc::BoolOr => "MAX",
+            _ => function.as_sql(),
+        }
+    }
+
+    fn uses_separator_clause(&self) -> bool {
+        true
+    }
+
+    fn supports_grouping_sets(&self) -> bool {
+        false
+    }
+
+    fn supports_ranges(&self) -> bool {
+        false
+    }
+
+    fn validate(&self, query: &Query) -> Result<()> {
+        validate_ranges(query, self.supports_ranges(), self.name())?;
+        validate_bounded_offset(query, self.row_locator().is_some(), self.name())?;
+        match &query.grouping_sets {
+            // WITH ROLLUP covers every grouped column, so it only matches a
+            // ROLLUP that is the whole GROUP BY
+            Some(GroupingSets::Rollup(_)) if query.group_by.is_empty() => Ok(()),
+            None => Ok(()),
+            Some(sets) => Err(ChakraError::Query(QueryError::Invalid {
+                message: format!(
+                    "GROUP BY {} is not supported by mysql, except a ROLLUP of all grouped columns",
+                    sets.keyword()
+                ),
+            })),
+        }
+    }
+
+    fn supports_between_symmetric(&self) -> bool {
+        false
+    }
+
+    /// MySQL can't name a whole row, so the columns must be known
+    fn checksum_sql(&self, sql: &str, columns: &[String]) -> Option<String> {
+        if columns.is_empty() {
+            return None;
+        }
+        let columns: Vec<_> = columns
+            .iter()
+            .map(|c| format!("t.{}", self.quote_identifier(c)))
+            .collect();
+        Some(format!(
+            "SELECT COUNT(*) AS {}, COALESCE(BIT_XOR(CAST(CONV(LEFT(MD5(JSON_ARRAY({})), 16), 16, 10) AS UNSIGNED)), 0) AS {} FROM ({}) AS t",
+            crate::checksum::ROW_COUNT_COLUMN,
+            columns.join(", "),
+            crate::checksum::CHECKSUM_COLUMN,
+            sql
+        ))
+    }
+
+    fn drop_temp_table(&self, table: &str) -> String {
+        // TEMPORARY keeps a permanent table of the same name safe
+        format!("DROP TEMPORARY TABLE IF EXISTS {}", self.quote_name(table))
+    }
+
     fn generate(&self, query: &Query) -> SqlFragment {
         // Similar to PostgreSQL but with MySQL-specific syntax
         // For now, use a simplified implementation
         let pg = PostgresDialect;
-        let mut fragment = pg.generate(query);
+        let rewritten;
+        let query = if has_json(query) {
+            rewritten = rewrite_json(query, JsonSyntax::MySql);
+            &rewritten
+        } else {
+            query
+        };
+        let mut fragment = if query.on_conflict.is_some() {
+            let mut query = query.clone();
+            query.on_conflict = None;
+            pg.generate_query(&query, self)
+        } else if query.query_type == QueryType::Truncate {
+            // TRUNCATE always resets AUTO_INCREMENT and has no CASCADE
+            let query = Query {
+                truncate: TruncateOptions::default(),
+                ..query.clone()
+            };
+            pg.generate_query(&query, self)
+        } else if !query.update_from.is_empty() {
+            pg.generate_query(&self.multi_table_update(query), self)
+        } else {
+            pg.generate_query(query, self)
+        };
 
-        // Replace $N with ?
+        // Replace $N with ?, leaving JSON paths like '$.key' alone
         let mut new_sql = String::new();
         let mut in_placeholder = false;
-        for c in fragment.sql.chars() {
-            if c == '$' {
+        let mut chars = fragment.sql.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '$' && chars.peek().is_some_and(|n| n.is_ascii_digit()) {
                 in_placeholder = true;
                 new_sql.push('?');
             } else if in_placeholder && c.is_ascii_digit() {
@@ -459,11 +1889,88 @@ impl Dialect for MySqlDialect {
         // Replace ILIKE with LIKE (case-insensitive by default in MySQL)
         fragment.sql = fragment.sql.replace(" ILIKE ", " LIKE ");
 
+        if let Some(on_conflict) = &query.on_conflict {
+            self.generate_on_duplicate_key(query, on_conflict, &mut fragment);
+        }
+
         fragment
     }
 
     fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment) {
-        PostgresDialect.generate_expr(expr, fragment);
+        PostgresDialect.generate_expr_inner(expr, fragment, self);
+    }
+}
+
+impl MySqlDialect {
+    /// MySQL's upsert: conflicts on any unique key, and "do nothing" is a
+    /// no-op assignment so other errors are not swallowed like INSERT IGNORE
+    /// Rewrite `UPDATE t ... FROM other` as `UPDATE t, other SET t.col = ...`
+    fn multi_table_update(&self, query: &Query) -> Query {
+        let qualify = |col: &String| {
+            if col.contains('.') {
+                col.clone()
+            } else {
+                format!("{}.{}", query.table, col)
+            }
+        };
+
+        let mut query = query.clone();
+        query.table = format!(
+            "{}, {}",
+            self.quote_name(&query.table),
+            self.quote_names(&query.update_from)
+        );
+        query.update_from.clear();
+        query.values = query
+            .values
+            .iter()
+            .map(|row| row.iter().map(|(c, v)| (qualify(c), v.clone())).collect())
+            .collect();
+        query.set_exprs = query
+            .set_exprs
+            .iter()
+            .map(|(c, e)| (qualify(c), e.clone()))
+            .collect();
+        query
+    }
+
+    fn generate_on_duplicate_key(
+        &self,
+        query: &Query,
+        on_conflict: &OnConflict,
+        fragment: &mut SqlFragment,
+    ) {
+        match &on_conflict.action {
+            ConflictAction::DoNothing => {
+                let column = on_conflict
+                    .target
+                    .first()
+                    .or_else(|| query.values.first().and_then(|v| v.keys().next()))
+                    .or_else(|| query.columns.first().filter(|c| *c != "*"));
+                if let Some(column) = column {
+                    let column = self.quote_name(column);
+                    fragment.push_sql(&format!(" ON DUPLICATE KEY UPDATE {} = {}", column, column));
+                }
+            }
+            ConflictAction::DoUpdate(updates) => {
+                fragment.push_sql(" ON DUPLICATE KEY UPDATE ");
+                for (i, update) in updates.iter().enumerate() {
+                    if i > 0 {
+                        fragment.push_sql(", ");
+                    }
+                    let value = match update {
+                        ConflictUpdate::Excluded(column) => {
+                            format!("VALUES({})", self.quote_name(column))
+                        }
+                        ConflictUpdate::Value(_, value) => {
+                            let idx = fragment.push_param(value.clone());
+                            self.placeholder(idx)
+                        }
+                    };
+                    fragment.push_sql(&format!("{} = {}", self.quote_name(update.column()), value));
+                }
+            }
+        }
     }
 }
 
@@ -480,8 +1987,34 @@ impl Dialect for SqliteDialect {
         format!("?{}", index)
     }
 
+    fn max_params(&self) -> usize {
+        // SQLITE_MAX_VARIABLE_NUMBER since 3.32
+        32766
+    }
+
     fn quote_identifier(&self, name: &str) -> String {
-        format!("\"{}\"", name.replace('"', "\"\""))
+        identifier::quote(self.name(), name)
+    }
+
+    fn literal(&self, value: &Value) -> String {
+        match value {
+            Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+            // SQLite stores NaN as NULL and reads 9e999 as infinity
+            Value::Float64(f) if f.is_nan() => "NULL".to_string(),
+            Value::Float64(f) if f.is_infinite() => {
+                format!("{}9e999", if *f < 0.0 { "-" } else { "" })
+            }
+            Value::Array(items) => json_array_literal(items),
+            _ => value.to_sql_literal(),
+        }
+    }
+
+    fn binds_at_names(&self) -> bool {
+        true
+    }
+
+    fn supports_default_values(&self) -> bool {
+        false
     }
 
     fn supports_returning(&self) -> bool {
@@ -492,63 +2025,1977 @@ impl Dialect for SqliteDialect {
         false // Use LIKE with COLLATE NOCASE
     }
 
-    fn generate(&self, query: &Query) -> SqlFragment {
-        PostgresDialect.generate(query)
+    fn supports_nulls_order(&self) -> bool {
+        true // SQLite 3.30+
     }
 
-    fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment) {
-        PostgresDialect.generate_expr(expr, fragment);
+    fn relative_time(&self, column: &str, seconds: u64, older: bool) -> String {
+        // Timestamps are stored as RFC 3339 text; datetime() normalizes them
+        // to UTC in the same format as datetime('now')
+        let op = if older { "<" } else { ">=" };
+        format!(
+            "datetime({}) {} datetime('now', '-{} seconds')",
+            column, op, seconds
+        )
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::query::Query;
+    fn translate_function(&self, name: &str, args: &[Expr]) -> Option<Expr> {
+        match name {
+            // The multi-argument forms of MAX and MIN are scalar
+            "GREATEST" => Some(Expr::function("MAX", args.to_vec())),
+            "LEAST" => Some(Expr::function("MIN", args.to_vec())),
+            "DATE_TRUNC" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                let arg = &sqlite_local_time(arg, zone)?;
+                let (function, modifiers): (&str, &[&str]) = match unit {
+                    DatePart::Year => ("datetime", &["'start of year'"]),
+                    DatePart::Month => ("datetime", &["'start of month'"]),
+                    DatePart::Week => ("datetime", &["'-6 days'", "'weekday 1'", "'start of day'"]),
+                    DatePart::Day => ("datetime", &["'start of day'"]),
+                    DatePart::Hour => ("strftime", &["'%Y-%m-%d %H:00:00'"]),
+                    DatePart::Minute => ("strftime", &["'%Y-%m-%d %H:%M:00'"]),
+                };
+                let modifiers = modifiers.iter().map(|m| Expr::raw(*m));
+                // strftime takes its format first
+                let args = if function == "strftime" {
+                    modifiers.chain(std::iter::once(arg.clone())).collect()
+                } else {
+                    std::iter::once(arg.clone()).chain(modifiers).collect()
+                };
+                Some(Expr::function(function, args))
+            }
+            "EXTRACT" => {
+                let (unit, arg, zone) = date_part_args(args)?;
+                let arg = sqlite_local_time(arg, zone)?;
+                let strftime = |format: &str, arg: Expr| {
+                    Expr::cast(
+                        Expr::function("strftime", vec![Expr::raw(format), arg]),
+                        "INTEGER",
+                    )
+                };
+                Some(match unit {
+                    // ISO week: the week of the year holding this week's Thursday
+                    DatePart::Week => {
+                        let thursday = Expr::function(
+                            "date",
+                            vec![arg, Expr::raw("'-3 days'"), Expr::raw("'weekday 4'")],
+                        );
+                        let day = Expr::Arithmetic {
+                            left: Box::new(strftime("'%j'", thursday)),
+                            op: ArithmeticOp::Sub,
+                            right: Box::new(Expr::raw("1")),
+                        };
+                        let week = Expr::Arithmetic {
+                            left: Box::new(day),
+                            op: ArithmeticOp::Div,
+                            right: Box::new(Expr::raw("7")),
+                        };
+                        Expr::Arithmetic {
+                            left: Box::new(week),
+                            op: ArithmeticOp::Add,
+                            right: Box::new(Expr::raw("1")),
+                        }
+                    }
+                    DatePart::Year => strftime("'%Y'", arg),
+                    DatePart::Month => strftime("'%m'", arg),
+                    DatePart::Day => strftime("'%d'", arg),
+                    DatePart::Hour => strftime("'%H'", arg),
+                    DatePart::Minute => strftime("'%M'", arg),
+                })
+            }
+            _ => None,
+        }
+    }
 
-    #[test]
-    fn test_select_query() {
-        let query = Query::select()
-            .from("users")
-            .columns(&["id", "name"])
-            .filter(Expr::eq("is_active", true))
-            .order_by_desc("created_at")
-            .limit(10)
-            .build();
+    fn supports_aggregate_filter(&self) -> bool {
+        true
+    }
 
-        let dialect = PostgresDialect;
-        let fragment = dialect.generate(&query);
+    fn aggregate_name(&self, function: &AggregateFunc) -> &'static str {
+        match function {
+            AggregateFunc::StringAgg => "group_concat",
+            AggregateFunc::ArrayAgg => "json_group_array",
+            // Booleans are stored as 0/1
+            AggregateFunc::BoolAnd => "MIN",
+            AggregateFunc::BoolOr => "MAX",
+            _ => function.as_sql(),
+        }
+    }
 
-        assert!(fragment.sql.contains("SELECT id, name FROM users"));
-        assert!(fragment.sql.contains("WHERE is_active = $1"));
-        assert!(fragment.sql.contains("ORDER BY created_at DESC"));
-        assert!(fragment.sql.contains("LIMIT 10"));
+    fn uses_separator_clause(&self) -> bool {
+        false
     }
 
-    #[test]
-    fn test_insert_query() {
-        let query = Query::insert()
-            .table("users")
-            .set("name", "Alice")
-            .set("email", "alice@example.com")
-            .returning(&["id"])
-            .build();
+    fn supports_grouping_sets(&self) -> bool {
+        false
+    }
 
-        let dialect = PostgresDialect;
-        let fragment = dialect.generate(&query);
+    fn supports_ranges(&self) -> bool {
+        false
+    }
 
-        assert!(fragment.sql.contains("INSERT INTO users"));
-        assert!(fragment.sql.contains("RETURNING id"));
+    fn supports_between_symmetric(&self) -> bool {
+        false
     }
 
-    #[test]
-    fn test_and_expression() {
-        let expr = Expr::eq("a", 1).and(Expr::eq("b", 2));
-        let dialect = PostgresDialect;
-        let mut fragment = SqlFragment::new();
-        dialect.generate_expr(&expr, &mut fragment);
+    fn row_locator(&self) -> Option<&'static str> {
+        Some("rowid")
+    }
 
-        assert!(fragment.sql.contains("AND"));
+    /// SQLite transactions are always serializable, which satisfies any
+    /// level asked for
+    fn begin_transaction(&self, _isolation: Option<IsolationLevel>) -> Vec<String> {
+        vec!["BEGIN".to_string()]
+    }
+
+    /// Only all foreign keys at once, through `PRAGMA defer_foreign_keys`
+    fn set_constraints(&self, names: &[&str], deferred: bool) -> Result<String> {
+        if !names.is_empty() {
+            return Err(ChakraError::config(
+                "SQLite can only defer all foreign keys; pass no constraint names",
+            ));
+        }
+        Ok(format!(
+            "PRAGMA defer_foreign_keys = {}",
+            if deferred { "ON" } else { "OFF" }
+        ))
+    }
+
+    fn generate(&self, query: &Query) -> SqlFragment {
+        if query.query_type == QueryType::Truncate {
+            // Without TRUNCATE, an unfiltered DELETE uses SQLite's truncate
+            // optimization; rowids restart unless the table is AUTOINCREMENT
+            let query = Query {
+                query_type: QueryType::Delete,
+                ..query.clone()
+            };
+            return PostgresDialect.generate_query(&query, self);
+        }
+
+        let json = has_json(query);
+        if !json && !Self::has_quantified(query) {
+            return PostgresDialect.generate_query(query, self);
+        }
+
+        let outer = query.alias.as_deref().unwrap_or(&query.table);
+        let mut query = if json {
+            rewrite_json(query, JsonSyntax::Sqlite)
+        } else {
+            query.clone()
+        };
+        query.where_clause = query
+            .where_clause
+            .map(|e| Self::rewrite_quantified(&e, outer));
+        query.having = query.having.map(|e| Self::rewrite_quantified(&e, outer));
+        PostgresDialect.generate_query(&query, self)
+    }
+
+    fn generate_expr(&self, expr: &Expr, fragment: &mut SqlFragment) {
+        PostgresDialect.generate_expr_inner(expr, fragment, self);
+    }
+}
+
+impl SqliteDialect {
+    fn has_quantified(query: &Query) -> bool {
+        fn walk(expr: &Expr) -> bool {
+            match expr {
+                Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().any(walk),
+                Expr::Not(e) => walk(e),
+                Expr::SubqueryCompare { quantifier, .. } => quantifier.is_some(),
+                _ => false,
+            }
+        }
+        query.where_clause.as_ref().is_some_and(walk) || query.having.as_ref().is_some_and(walk)
+    }
+
+    /// SQLite has no `ANY`/`ALL`, so rewrite them as IN or correlated EXISTS
+    ///
+    /// `= ANY` and `<> ALL` become `IN` and `NOT IN`. Other operators need a
+    /// plain `SELECT column FROM ... WHERE ...` subquery, whose filter gains
+    /// the comparison; anything else is left for SQLite to reject.
+    fn rewrite_quantified(expr: &Expr, outer: &str) -> Expr {
+        match expr {
+            Expr::And(exprs) => Expr::And(
+                exprs
+                    .iter()
+                    .map(|e| Self::rewrite_quantified(e, outer))
+                    .collect(),
+            ),
+            Expr::Or(exprs) => Expr::Or(
+                exprs
+                    .iter()
+                    .map(|e| Self::rewrite_quantified(e, outer))
+                    .collect(),
+            ),
+            Expr::Not(e) => Expr::Not(Box::new(Self::rewrite_quantified(e, outer))),
+            Expr::SubqueryCompare {
+                column,
+                op,
+                quantifier: Some(quantifier),
+                query,
+            } => match (quantifier, op) {
+                (Quantifier::Any, CompareOp::Eq) => Expr::in_subquery(column, (**query).clone()),
+                (Quantifier::All, CompareOp::Ne) => {
+                    Expr::not_in_subquery(column, (**query).clone())
+                }
+                _ => Self::correlated_exists(column, op, *quantifier, query, outer)
+                    .unwrap_or_else(|| expr.clone()),
+            },
+            _ => expr.clone(),
+        }
+    }
+
+    /// `x op ANY (SELECT c FROM t WHERE w)` as `EXISTS (SELECT 1 FROM t WHERE w AND x op c)`,
+    /// and `ALL` as `NOT EXISTS (... AND NOT (x op c))`
+    fn correlated_exists(
+        column: &str,
+        op: &CompareOp,
+        quantifier: Quantifier,
+        query: &Query,
+        outer: &str,
+    ) -> Option<Expr> {
+        let inner = match query.columns.as_slice() {
+            [inner] if inner != "*" && !inner.contains('(') && !inner.contains(' ') => inner,
+            _ => return None,
+        };
+        if !query.group_by.is_empty()
+            || query.having.is_some()
+            || query.limit.is_some()
+            || query.offset.is_some()
+            || !query.ctes.is_empty()
+        {
+            return None;
+        }
+
+        // Qualify the outer column so it cannot bind to the subquery's table
+        let column = if column.contains('.') {
+            column.to_string()
+        } else {
+            format!("{}.{}", outer, column)
+        };
+        let mut comparison = Expr::Raw(format!(
+            "{} {} {}",
+            SqliteDialect.quote_name(&column),
+            op.as_sql(),
+            SqliteDialect.quote_name(inner)
+        ));
+        if quantifier == Quantifier::All {
+            comparison = comparison.not();
+        }
+
+        let mut sub = query.clone();
+        sub.columns = vec!["1".to_string()];
+        sub.distinct = false;
+        sub.order_by.clear();
+        sub.where_clause = Some(match sub.where_clause.take() {
+            Some(filter) => filter.and(comparison),
+            None => comparison,
+        });
+
+        Some(match quantifier {
+            Quantifier::Any => Expr::exists(sub),
+            Quantifier::All => Expr::not_exists(sub),
+        })
+    }
+}
+
+/// The unit, timestamp and time zone of a call built by `Expr::date_trunc`,
+/// `Expr::extract` or their `_in` variants
+fn date_part_args(args: &[Expr]) -> Option<(DatePart, &Expr, Option<&Expr>)> {
+    let (unit, arg, zone) = match args {
+        [Expr::Raw(unit), arg] => (unit, arg, None),
+        [Expr::Raw(unit), arg, zone] => (unit, arg, Some(zone)),
+        _ => return None,
+    };
+    let unit = DatePart::ALL
+        .into_iter()
+        .find(|part| part.as_sql() == unit)?;
+    Some((unit, arg, zone))
+}
+
+/// Whether a zone literal names UTC, where no conversion is needed
+fn is_utc(zone: &Expr) -> bool {
+    matches!(zone, Expr::Raw(zone) if ["'UTC'", "'Z'", "'+00:00'"].contains(&zone.as_str()))
+}
+
+/// Local time in `zone` of a UTC timestamp on MySQL
+///
+/// Named zones need the server's time zone tables; without them the result
+/// is NULL.
+fn mysql_local_time(arg: &Expr, zone: Option<&Expr>) -> Expr {
+    match zone {
+        Some(zone) if !is_utc(zone) => Expr::function(
+            "CONVERT_TZ",
+            vec![arg.clone(), Expr::raw("'+00:00'"), zone.clone()],
+        ),
+        _ => arg.clone(),
+    }
+}
+
+/// Local time in `zone` of a UTC timestamp on SQLite, which has no time
+/// zone database: `None` unless the zone is UTC or an offset like `'+05:30'`
+fn sqlite_local_time(arg: &Expr, zone: Option<&Expr>) -> Option<Expr> {
+    match zone {
+        None => Some(arg.clone()),
+        Some(zone) if is_utc(zone) => Some(arg.clone()),
+        Some(Expr::Raw(offset)) => {
+            let hhmm = offset
+                .strip_prefix("'+")
+                .or_else(|| offset.strip_prefix("'-"))?;
+            let hhmm = hhmm.strip_suffix('\'')?;
+            let (hours, minutes) = hhmm.split_once(':')?;
+            let digits = |s: &str| s.len() == 2 && s.bytes().all(|b| b.is_ascii_digit());
+            (digits(hours) && digits(minutes))
+                .then(|| Expr::function("datetime", vec![arg.clone(), Expr::Raw(offset.clone())]))
+        }
+        Some(_) => None,
+    }
+}
+
+/// Render grouping sets the Postgres way, e.g. `ROLLUP ("a", "b")`
+fn grouping_sets_sql(sets: &GroupingSets, q: &dyn Dialect) -> String {
+    let columns = match sets {
+        GroupingSets::Rollup(columns) | GroupingSets::Cube(columns) => q.quote_names(columns),
+        GroupingSets::Sets(sets) => sets
+            .iter()
+            .map(|set| format!("({})", q.quote_names(set)))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    format!("{} ({})", sets.keyword(), columns)
+}
+
+/// Whether values can be bound as one typed Postgres array: a non-empty
+/// list of scalars of a single type, NULLs aside
+fn bindable_array(values: &[Value]) -> bool {
+    let mut kinds = values
+        .iter()
+        .filter(|v| !v.is_null())
+        .map(std::mem::discriminant);
+    let Some(first) = kinds.next() else {
+        return false;
+    };
+    let supported = values.iter().find(|v| !v.is_null()).is_some_and(|v| {
+        matches!(
+            v,
+            Value::Bool(_)
+                | Value::Int32(_)
+                | Value::Int64(_)
+                | Value::Float64(_)
+                | Value::String(_)
+                | Value::Uuid(_)
+                | Value::DateTime(_)
+                | Value::Date(_)
+                | Value::Time(_)
+        )
+    });
+    supported && kinds.all(|k| k == first)
+}
+
+/// Quote a string as a SQL literal
+fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// JSON function flavour for dialects without Postgres' jsonb operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonSyntax {
+    /// `JSON_EXTRACT`, `JSON_UNQUOTE` and `JSON_CONTAINS`
+    MySql,
+    /// `json_extract`, with containment expanded into per-key checks
+    Sqlite,
+}
+
+impl JsonSyntax {
+    /// Quote a column for the dialect, as rewritten SQL is emitted raw
+    fn quote(self, column: &str) -> String {
+        match self {
+            JsonSyntax::MySql => MySqlDialect.quote_name(column),
+            JsonSyntax::Sqlite => SqliteDialect.quote_name(column),
+        }
+    }
+}
+
+/// Reject range operators on a dialect without range types
+fn validate_ranges(query: &Query, supported: bool, dialect: &str) -> Result<()> {
+    let mut found = false;
+    query.walk_exprs(&mut |e| {
+        found |= matches!(e, Expr::RangeOverlaps { .. } | Expr::RangeContains { .. })
+    });
+    if found && !supported {
+        return Err(ChakraError::Query(QueryError::Invalid {
+            message: format!("range operators are not supported by {}", dialect),
+        }));
+    }
+    Ok(())
+}
+
+/// Reject a multi-row INSERT whose rows set different columns where the
+/// dialect can't fill the gaps with `DEFAULT`
+fn validate_default_values(query: &Query, supported: bool, dialect: &str) -> Result<()> {
+    if query.query_type == QueryType::Insert && !supported && query.has_missing_values() {
+        return Err(ChakraError::Query(QueryError::Invalid {
+            message: format!(
+                "rows of a multi-row INSERT must set the same columns on {}, which has no \
+                 DEFAULT in VALUES; use insert_all to split them",
+                dialect
+            ),
+        }));
+    }
+    Ok(())
+}
+
+/// Reject OFFSET on an UPDATE or DELETE where no row locator lets a
+/// subquery skip the rows
+fn validate_bounded_offset(query: &Query, supported: bool, dialect: &str) -> Result<()> {
+    let modifies = matches!(query.query_type, QueryType::Update | QueryType::Delete);
+    if modifies && !supported && query.offset.is_some() {
+        return Err(ChakraError::Query(QueryError::Invalid {
+            message: format!("UPDATE and DELETE take no OFFSET on {}", dialect),
+        }));
+    }
+    Ok(())
+}
+
+/// Reject `ON CONFLICT DO UPDATE` without conflict columns where the
+/// dialect can't infer them
+fn validate_conflict_target(query: &Query, required: bool, dialect: &str) -> Result<()> {
+    match &query.on_conflict {
+        Some(OnConflict {
+            target,
+            action: ConflictAction::DoUpdate(_),
+        }) if required && target.is_empty() => Err(ChakraError::Query(QueryError::Invalid {
+            message: format!(
+                "ON CONFLICT DO UPDATE needs conflict columns on {}: use OnConflict::columns",
+                dialect
+            ),
+        })),
+        _ => Ok(()),
+    }
+}
+
+fn is_json(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::JsonGet { .. }
+            | Expr::JsonCompare { .. }
+            | Expr::JsonContains { .. }
+            | Expr::ArrayContains { .. }
+            | Expr::ArrayOverlaps { .. }
+    )
+}
+
+/// Whether any expression in the query, subqueries included, uses JSON
+fn has_json(query: &Query) -> bool {
+    let mut found = false;
+    query.walk_exprs(&mut |e| found |= is_json(e));
+    found
+}
+
+/// Rewrite JSON expressions into the dialect's JSON functions
+///
+/// Array operators are included, as arrays are stored as JSON outside Postgres.
+fn rewrite_json(query: &Query, syntax: JsonSyntax) -> Query {
+    let mut query = query.clone();
+    query.walk_exprs_mut(&mut |e| {
+        if is_json(e) {
+            *e = rewrite_json_expr(e, syntax);
+        }
+    });
+    query
+}
+
+fn rewrite_json_expr(expr: &Expr, syntax: JsonSyntax) -> Expr {
+    match expr {
+        Expr::JsonGet {
+            column,
+            path,
+            as_text,
+        } => {
+            let extract = json_extract(column, path, syntax);
+            match syntax {
+                JsonSyntax::MySql if *as_text => Expr::Raw(format!("JSON_UNQUOTE({})", extract)),
+                _ => Expr::Raw(extract),
+            }
+        }
+        Expr::JsonCompare {
+            column,
+            path,
+            op,
+            value,
+        } => {
+            let extract = json_extract(column, path, syntax);
+            if syntax == JsonSyntax::Sqlite {
+                return Expr::Compare {
+                    column: extract,
+                    op: op.clone(),
+                    value: value.clone(),
+                };
+            }
+
+            // JSON_EXTRACT keeps strings quoted and turns JSON null into
+            // 'null' rather than NULL
+            let text = format!("JSON_UNQUOTE({})", extract);
+            let missing = format!("({} IS NULL OR JSON_TYPE({}) = 'NULL')", extract, extract);
+            match (op, value) {
+                (CompareOp::IsNull, _) => Expr::Raw(missing),
+                (CompareOp::IsNotNull, _) => Expr::Raw(missing).not(),
+                (_, Value::Bool(b)) => Expr::Compare {
+                    column: text,
+                    op: op.clone(),
+                    value: Value::String(b.to_string()),
+                },
+                (CompareOp::Like | CompareOp::ILike, _) | (_, Value::String(_)) => Expr::Compare {
+                    column: text,
+                    op: op.clone(),
+                    value: value.clone(),
+                },
+                _ => Expr::Compare {
+                    column: extract,
+                    op: op.clone(),
+                    value: value.clone(),
+                },
+            }
+        }
+        Expr::ArrayContains { column, values } => rewrite_json_expr(
+            &Expr::JsonContains {
+                column: column.clone(),
+                value: json_array(values),
+            },
+            syntax,
+        ),
+        Expr::ArrayOverlaps { column, values } => match syntax {
+            JsonSyntax::MySql => Expr::Function {
+                name: "JSON_OVERLAPS".to_string(),
+                args: vec![
+                    Expr::Column(column.clone()),
+                    Expr::Value(json_array(values)),
+                ],
+            },
+            JsonSyntax::Sqlite => Expr::exists(
+                Query::select()
+                    .from(format!("json_each({})", JsonSyntax::Sqlite.quote(column)))
+                    .column("1")
+                    .filter(Expr::is_in("value", values.clone()))
+                    .build(),
+            ),
+        },
+        Expr::JsonContains { column, value } => match syntax {
+            JsonSyntax::MySql => Expr::Function {
+                name: "JSON_CONTAINS".to_string(),
+                args: vec![Expr::Column(column.clone()), Expr::Value(value.clone())],
+            },
+            JsonSyntax::Sqlite => {
+                let json = match value {
+                    Value::Json(json) => Some(json.clone()),
+                    Value::String(s) => serde_json::from_str(s).ok(),
+                    _ => None,
+                };
+                match json {
+                    Some(json) => {
+                        let mut checks = Vec::new();
+                        sqlite_contains(column, &mut Vec::new(), &json, &mut checks);
+                        match checks.len() {
+                            0 => Expr::Raw("1 = 1".to_string()),
+                            1 => checks.remove(0),
+                            _ => Expr::And(checks),
+                        }
+                    }
+                    None => expr.clone(),
+                }
+            }
+        },
+        _ => expr.clone(),
+    }
+}
+
+/// Values as a JSON array parameter
+fn json_array(values: &[Value]) -> Value {
+    Value::Json(serde_json::to_value(values).unwrap_or_default())
+}
+
+/// `$.a[0]."odd key"`, the path syntax shared by MySQL and SQLite
+fn json_path_literal(path: &[String]) -> String {
+    let mut out = String::from("$");
+    for key in path {
+        if key.parse::<u32>().is_ok() {
+            out.push_str(&format!("[{}]", key));
+        } else if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            out.push('.');
+            out.push_str(key);
+        } else {
+            out.push_str(&format!(
+                ".\"{}\"",
+                key.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+        }
+    }
+    quote_literal(&out)
+}
+
+fn json_extract(column: &str, path: &[String], syntax: JsonSyntax) -> String {
+    let name = match syntax {
+        JsonSyntax::MySql => "JSON_EXTRACT",
+        JsonSyntax::Sqlite => "json_extract",
+    };
+    format!(
+        "{}({}, {})",
+        name,
+        syntax.quote(column),
+        json_path_literal(path)
+    )
+}
+
+/// Expand `column @> json` into checks SQLite can run: objects match key by
+/// key, and arrays need each element to appear somewhere in the array
+fn sqlite_contains(
+    column: &str,
+    path: &mut Vec<String>,
+    json: &serde_json::Value,
+    checks: &mut Vec<Expr>,
+) {
+    use serde_json::Value as Json;
+
+    let extract = || json_extract(column, path, JsonSyntax::Sqlite);
+    match json {
+        Json::Object(map) => {
+            for (key, value) in map {
+                path.push(key.clone());
+                sqlite_contains(column, path, value, checks);
+                path.pop();
+            }
+        }
+        Json::Array(items) => {
+            for item in items {
+                let filter = match item {
+                    Json::Null => Expr::is_null("value"),
+                    _ => Expr::eq("value", json_scalar(item)),
+                };
+                let each = Query::select()
+                    .from(format!(
+                        "json_each({}, {})",
+                        JsonSyntax::Sqlite.quote(column),
+                        json_path_literal(path)
+                    ))
+                    .column("1")
+                    .filter(filter)
+                    .build();
+                checks.push(Expr::exists(each));
+            }
+        }
+        Json::Null => checks.push(Expr::Raw(format!(
+            "json_type({}, {}) = 'null'",
+            JsonSyntax::Sqlite.quote(column),
+            json_path_literal(path)
+        ))),
+        _ => checks.push(Expr::Compare {
+            column: extract(),
+            op: CompareOp::Eq,
+            value: json_scalar(json),
+        }),
+    }
+}
+
+/// A JSON scalar as the value `json_extract` returns for it; nested
+/// containers come back as their minified JSON text
+fn json_scalar(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Bool(b) => Value::Bool(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int64(i),
+            None => Value::Float64(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        other => Value::String(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{Column, DatePart, F};
+    use crate::query::{Order, Query};
+    use crate::types::FieldType;
+
+    #[test]
+    fn test_select_query() {
+        let query = Query::select()
+            .from("users")
+            .columns(&["id", "name"])
+            .filter(Expr::eq("is_active", true))
+            .order_by_desc("created_at")
+            .limit(10)
+            .build();
+
+        let dialect = PostgresDialect;
+        let fragment = dialect.generate(&query);
+
+        assert!(fragment.sql.contains(r#"SELECT "id", "name" FROM "users""#));
+        assert!(fragment.sql.contains(r#"WHERE "is_active" = $1"#));
+        assert!(fragment.sql.contains(r#"ORDER BY "created_at" DESC"#));
+        assert!(fragment.sql.contains("LIMIT 10"));
+    }
+
+    #[test]
+    fn test_insert_query() {
+        let query = Query::insert()
+            .table("users")
+            .set("name", "Alice")
+            .set("email", "alice@example.com")
+            .returning(&["id"])
+            .build();
+
+        let dialect = PostgresDialect;
+        let fragment = dialect.generate(&query);
+
+        assert!(fragment.sql.contains(r#"INSERT INTO "users""#));
+        assert!(fragment.sql.contains(r#"RETURNING "id""#));
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        let query = Query::select()
+            .from("Order")
+            .columns(&["Order.userId", "COUNT(*) AS n", "t.*"])
+            .filter(Expr::eq("user", 1))
+            .group_by(&["Order.userId"])
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT \"Order\".\"userId\", COUNT(*) AS n, \"t\".* FROM \"Order\" \
+             WHERE \"user\" = $1 GROUP BY \"Order\".\"userId\""
+        );
+
+        assert_eq!(MySqlDialect.quote_name("select"), "`select`");
+        assert_eq!(SqliteDialect.quote_name("\"done\""), "\"done\"");
+        assert_eq!(PostgresDialect.quote_name("lower(email)"), "lower(email)");
+    }
+
+    #[test]
+    fn test_and_expression() {
+        let expr = Expr::eq("a", 1).and(Expr::eq("b", 2));
+        let dialect = PostgresDialect;
+        let mut fragment = SqlFragment::new();
+        dialect.generate_expr(&expr, &mut fragment);
+
+        assert!(fragment.sql.contains("AND"));
+        assert_eq!(fragment.params.len(), 2);
+    }
+
+    #[test]
+    fn test_sql_comment() {
+        let query = Query::select()
+            .from("orders")
+            .comment("service=checkout route=/pay")
+            .filter(Expr::eq("id", 1))
+            .build();
+
+        let fragment = MySqlDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "/*route='%2Fpay',service='checkout'*/ SELECT * FROM `orders` WHERE `id` = ?"
+        );
+    }
+
+    #[test]
+    fn test_multi_row_insert() {
+        let query = Query::insert()
+            .table("tags")
+            .set("slug", "rust")
+            .values(HashMap::from([("slug".to_string(), Value::from("sql"))]))
+            .values(HashMap::new())
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "INSERT INTO \"tags\" (\"slug\") VALUES ($1), ($2), (DEFAULT)"
+        );
+        let fragment = MySqlDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "INSERT INTO `tags` (`slug`) VALUES (?), (?), (DEFAULT)"
+        );
         assert_eq!(fragment.params.len(), 2);
+        assert!(PostgresDialect.validate(&query).is_ok());
+
+        // SQLite has no DEFAULT in VALUES, so rows are split by the columns
+        // they set
+        let err = SqliteDialect.validate(&query).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("must set the same columns on sqlite"));
+        let batches = SqliteDialect.generate_batches(&query);
+        assert_eq!(
+            batches.iter().map(|f| f.sql.as_str()).collect::<Vec<_>>(),
+            vec![
+                "INSERT INTO \"tags\" (\"slug\") VALUES ($1), ($2)",
+                "INSERT INTO \"tags\" DEFAULT VALUES",
+            ]
+        );
+        assert!(SqliteDialect
+            .insert_batches(&query)
+            .iter()
+            .all(|batch| SqliteDialect.validate(batch).is_ok()));
+    }
+
+    #[test]
+    fn test_insert_select() {
+        let source = Query::select()
+            .from("users")
+            .columns(&["id", "email"])
+            .filter(Expr::eq("is_active", true))
+            .build();
+        let query = Query::insert()
+            .table("archived_users")
+            .columns(&["user_id", "email"])
+            .from_select(source)
+            .on_conflict(OnConflict::columns(&["user_id"]).do_nothing())
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "INSERT INTO \"archived_users\" (\"user_id\", \"email\") SELECT \"id\", \"email\" \
+             FROM \"users\" WHERE \"is_active\" = $1 ON CONFLICT (\"user_id\") DO NOTHING"
+        );
+        let fragment = MySqlDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "INSERT INTO `archived_users` (`user_id`, `email`) SELECT `id`, `email` FROM \
+             `users` WHERE `is_active` = ? ON DUPLICATE KEY UPDATE `user_id` = `user_id`"
+        );
+        assert_eq!(fragment.params, vec![Value::Bool(true)]);
+    }
+
+    #[test]
+    fn test_insert_into_temp() {
+        let source = Query::select()
+            .from("orders")
+            .columns(&["user_id", "SUM(total) AS total"])
+            .filter(Expr::eq("status", "paid"))
+            .group_by(&["user_id"])
+            .build();
+        let query = Query::insert_into_temp("paid_totals", source).build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "CREATE TEMPORARY TABLE \"paid_totals\" AS SELECT \"user_id\", SUM(total) AS total \
+             FROM \"orders\" WHERE \"status\" = $1 GROUP BY \"user_id\""
+        );
+        assert_eq!(
+            MySqlDialect.drop_temp_table("paid_totals"),
+            "DROP TEMPORARY TABLE IF EXISTS `paid_totals`"
+        );
+    }
+
+    #[test]
+    fn test_update_from() {
+        let query = Query::update()
+            .table("products")
+            .set("synced", true)
+            .set_expr("price", F::col("price_list.price"))
+            .update_from("price_list")
+            .filter(Expr::raw("products.sku = price_list.sku"))
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "UPDATE \"products\" SET \"synced\" = $1, \"price\" = \"price_list\".\"price\" \
+             FROM \"price_list\" WHERE products.sku = price_list.sku"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "UPDATE `products`, `price_list` SET `products`.`synced` = ?, `products`.`price` \
+             = `price_list`.`price` WHERE products.sku = price_list.sku"
+        );
+    }
+
+    #[test]
+    fn test_bounded_delete() {
+        let query = Query::delete()
+            .table("jobs")
+            .filter(Expr::eq("done", true))
+            .order_by_asc("id")
+            .limit(500)
+            .build();
+
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "DELETE FROM `jobs` WHERE `done` = ? ORDER BY `id` ASC LIMIT 500"
+        );
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "DELETE FROM \"jobs\" WHERE ctid IN (SELECT ctid FROM \"jobs\" WHERE \"done\" = $1 \
+             ORDER BY \"id\" ASC LIMIT 500)"
+        );
+
+        let query = Query::update()
+            .table("jobs")
+            .set("claimed", true)
+            .filter(Expr::eq("claimed", false))
+            .limit(10)
+            .build();
+        let fragment = SqliteDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "UPDATE \"jobs\" SET \"claimed\" = $1 WHERE rowid IN (SELECT rowid FROM \"jobs\" \
+             WHERE \"claimed\" = $2 LIMIT 10)"
+        );
+        assert_eq!(fragment.params, vec![Value::Bool(true), Value::Bool(false)]);
+
+        // Key columns instead of ctid, with the alias and OFFSET in the subquery
+        let query = Query::delete()
+            .table("events")
+            .alias("e")
+            .filter(Expr::eq("e.kind", "ping"))
+            .order_by_asc("e.at")
+            .limit(100)
+            .offset(50)
+            .key_columns(["tenant_id", "id"])
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "DELETE FROM \"events\" WHERE (\"tenant_id\", \"id\") IN (SELECT \"tenant_id\", \
+             \"id\" FROM \"events\" AS \"e\" WHERE \"e\".\"kind\" = $1 ORDER BY \"e\".\"at\" ASC \
+             LIMIT 100 OFFSET 50)"
+        );
+        let err = MySqlDialect.validate(&query).unwrap_err();
+        assert!(err.to_string().contains("take no OFFSET"));
+        assert!(PostgresDialect.validate(&query).is_ok());
+
+        // The joined tables stay in the outer statement with their condition
+        let query = Query::update()
+            .table("stock")
+            .update_from("orders")
+            .set_expr("qty", F::col("orders.qty"))
+            .filter(Expr::raw("stock.sku = orders.sku"))
+            .limit(10)
+            .key_columns(["id"])
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "UPDATE \"stock\" SET \"qty\" = \"orders\".\"qty\" FROM \"orders\" WHERE \
+             stock.sku = orders.sku AND \"stock\".\"id\" IN (SELECT \"stock\".\"id\" FROM \
+             \"stock\", \"orders\" WHERE stock.sku = orders.sku LIMIT 10)"
+        );
+    }
+
+    #[test]
+    fn test_order_by_nulls() {
+        let lower = Expr::Function {
+            name: "LOWER".to_string(),
+            args: vec![Expr::column("name")],
+        };
+        let query = Query::select()
+            .from("users")
+            .order_by_desc("last_login")
+            .nulls_last()
+            .order_by_expr(lower, Order::Asc)
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT * FROM \"users\" ORDER BY \"last_login\" DESC NULLS LAST, \
+             LOWER(\"name\") ASC"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "SELECT * FROM `users` ORDER BY `last_login` IS NULL ASC, `last_login` DESC, \
+             LOWER(`name`) ASC"
+        );
+    }
+
+    #[test]
+    fn test_between_variants() {
+        let query = Query::select()
+            .from("events")
+            .filter(
+                F::col("day")
+                    .not_between(1, 5)
+                    .and(Expr::between("hour", 18, 9).symmetric()),
+            )
+            .build();
+
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"events\" WHERE (\"day\" NOT BETWEEN $1 AND $2 AND \
+             \"hour\" BETWEEN SYMMETRIC $3 AND $4)"
+        );
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT * FROM \"events\" WHERE (\"day\" NOT BETWEEN $1 AND $2 AND \
+             (\"hour\" BETWEEN $3 AND $4 OR \"hour\" BETWEEN $5 AND $6))"
+        );
+        let negated = Query::select()
+            .from("events")
+            .filter(Expr::not_between("hour", 18, 9).symmetric())
+            .build();
+        let fragment = MySqlDialect.generate(&negated);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM `events` WHERE (`hour` NOT BETWEEN ? AND ? AND `hour` NOT BETWEEN ? AND ?)"
+        );
+        assert_eq!(fragment.params.len(), 4);
+    }
+
+    #[test]
+    fn test_aggregate_filter() {
+        let failed = Expr::count_all().filter_where(Expr::eq("status", "failed"));
+        let mut fragment = SqlFragment::new();
+        PostgresDialect.generate_expr(&failed, &mut fragment);
+        assert_eq!(fragment.sql, "COUNT(*) FILTER (WHERE \"status\" = $1)");
+        assert_eq!(fragment.params.len(), 1);
+
+        let refunded = Expr::count_all().filter_where(Expr::is_not_null("refunded_at"));
+        let mut fragment = SqlFragment::new();
+        MySqlDialect.generate_expr(&refunded, &mut fragment);
+        assert_eq!(
+            fragment.sql,
+            "COUNT(CASE WHEN `refunded_at` IS NOT NULL THEN 1 END)"
+        );
+
+        let spent = Expr::aggregate(AggregateFunc::Sum, "amount")
+            .filter_where(Expr::is_null("refunded_at"))
+            .filter_where(Expr::raw("amount > 0"));
+        let mut fragment = SqlFragment::new();
+        MySqlDialect.generate_expr(&spent, &mut fragment);
+        assert_eq!(
+            fragment.sql,
+            "SUM(CASE WHEN (`refunded_at` IS NULL AND amount > 0) THEN `amount` END)"
+        );
+    }
+
+    #[test]
+    fn test_bool_aggregates() {
+        let render = |dialect: &dyn Dialect, expr: &Expr| {
+            let mut fragment = SqlFragment::new();
+            dialect.generate_expr(expr, &mut fragment);
+            fragment.sql
+        };
+
+        let shipped = Expr::bool_and("shipped");
+        assert_eq!(render(&PostgresDialect, &shipped), "BOOL_AND(\"shipped\")");
+        assert_eq!(render(&MySqlDialect, &shipped), "MIN(`shipped`)");
+        assert_eq!(
+            render(&SqliteDialect, &Expr::every("shipped")),
+            "MIN(\"shipped\")"
+        );
+
+        let late = Expr::bool_or("late").filter_where(Expr::is_null("cancelled_at"));
+        assert_eq!(
+            render(&PostgresDialect, &late),
+            "BOOL_OR(\"late\") FILTER (WHERE \"cancelled_at\" IS NULL)"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &late),
+            "MAX(CASE WHEN `cancelled_at` IS NULL THEN `late` END)"
+        );
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let hour = std::time::Duration::from_secs(3600);
+        let query = Query::select()
+            .from("events")
+            .filter(F::col("created_at").within_last(hour))
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT * FROM \"events\" WHERE \"created_at\" >= now() - interval '3600 seconds'"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "SELECT * FROM `events` WHERE `created_at` >= DATE_SUB(UTC_TIMESTAMP(), INTERVAL 3600 SECOND)"
+        );
+
+        let stale = Query::delete()
+            .from("sessions")
+            .filter(Expr::older_than("seen_at", hour * 24))
+            .build();
+        let fragment = SqliteDialect.generate(&stale);
+        assert_eq!(
+            fragment.sql,
+            "DELETE FROM \"sessions\" WHERE datetime(\"seen_at\") < datetime('now', '-86400 seconds')"
+        );
+        assert!(fragment.params.is_empty());
+    }
+
+    #[test]
+    fn test_string_aggregation() {
+        let tags = Expr::string_agg("tag", ", ").ordered_by("tag", Order::Asc);
+        let render = |dialect: &dyn Dialect, expr: &Expr| {
+            let mut fragment = SqlFragment::new();
+            dialect.generate_expr(expr, &mut fragment);
+            fragment.sql
+        };
+        assert_eq!(
+            render(&PostgresDialect, &tags),
+            "STRING_AGG(\"tag\", ', ' ORDER BY \"tag\" ASC)"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &tags),
+            "GROUP_CONCAT(`tag` ORDER BY `tag` ASC SEPARATOR ', ')"
+        );
+        assert_eq!(
+            render(&SqliteDialect, &tags),
+            "group_concat(\"tag\", ', ' ORDER BY \"tag\" ASC)"
+        );
+
+        let names = Expr::aggregate(AggregateFunc::StringAgg, "name")
+            .ordered_by("rank", Order::Desc)
+            .filter_where(Expr::is_not_null("rank"));
+        assert_eq!(
+            render(&PostgresDialect, &names),
+            "STRING_AGG(\"name\", ',' ORDER BY \"rank\" DESC) \
+             FILTER (WHERE \"rank\" IS NOT NULL)"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &names),
+            "GROUP_CONCAT(CASE WHEN `rank` IS NOT NULL THEN `name` END \
+             ORDER BY `rank` DESC SEPARATOR ',')"
+        );
+
+        let ids = Expr::array_agg("id").ordered_by("created_at", Order::Asc);
+        assert_eq!(
+            render(&PostgresDialect, &ids),
+            "ARRAY_AGG(\"id\" ORDER BY \"created_at\" ASC)"
+        );
+        assert_eq!(
+            render(&SqliteDialect, &ids),
+            "json_group_array(\"id\" ORDER BY \"created_at\" ASC)"
+        );
+    }
+
+    #[test]
+    fn test_scalar_functions() {
+        let render = |dialect: &dyn Dialect, expr: &Expr| {
+            let mut fragment = SqlFragment::new();
+            dialect.generate_expr(expr, &mut fragment);
+            fragment.sql
+        };
+
+        let name = Expr::concat(vec![
+            Expr::upper(Expr::column("first")),
+            Expr::raw("' '"),
+            Expr::coalesce(vec![Expr::column("last"), Expr::raw("''")]),
+        ]);
+        assert_eq!(
+            render(&PostgresDialect, &name),
+            "CONCAT(UPPER(\"first\"), ' ', COALESCE(\"last\", ''))"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &name),
+            "CONCAT_WS('', UPPER(`first`), ' ', COALESCE(`last`, ''))"
+        );
+
+        let longest = Expr::greatest(vec![
+            Expr::length(Expr::column("title")),
+            Expr::nullif(Expr::column("min_len"), Expr::raw("0")),
+        ]);
+        assert_eq!(
+            render(&MySqlDialect, &longest),
+            "GREATEST(CHAR_LENGTH(`title`), NULLIF(`min_len`, 0))"
+        );
+        assert_eq!(
+            render(&SqliteDialect, &longest),
+            "MAX(LENGTH(\"title\"), NULLIF(\"min_len\", 0))"
+        );
+
+        let day = Expr::date_trunc(DatePart::Day, Expr::column("created_at"));
+        assert_eq!(
+            render(&PostgresDialect, &day),
+            "DATE_TRUNC('day', \"created_at\")"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &day),
+            "TIMESTAMP(DATE_FORMAT(`created_at`, '%Y-%m-%d'))"
+        );
+        assert_eq!(
+            render(&SqliteDialect, &day),
+            "datetime(\"created_at\", 'start of day')"
+        );
+        let week = Expr::date_trunc(DatePart::Week, Expr::column("created_at"));
+        assert_eq!(
+            render(&MySqlDialect, &week),
+            "TIMESTAMP(SUBDATE(DATE(`created_at`), WEEKDAY(`created_at`)))"
+        );
+        let hour = Expr::date_trunc(DatePart::Hour, Expr::column("created_at"));
+        assert_eq!(
+            render(&SqliteDialect, &hour),
+            "strftime('%Y-%m-%d %H:00:00', \"created_at\")"
+        );
+    }
+
+    #[test]
+    fn test_date_parts_in_time_zone() {
+        let render = |dialect: &dyn Dialect, expr: &Expr| {
+            let mut fragment = SqlFragment::new();
+            dialect.generate_expr(expr, &mut fragment);
+            fragment.sql
+        };
+
+        let day = F::col("created_at").trunc(DatePart::Day, "Asia/Kolkata");
+        assert_eq!(
+            render(&PostgresDialect, &day),
+            "DATE_TRUNC('day', TIMEZONE('Asia/Kolkata', \"created_at\"))"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &day),
+            "TIMESTAMP(DATE_FORMAT(CONVERT_TZ(`created_at`, '+00:00', 'Asia/Kolkata'), '%Y-%m-%d'))"
+        );
+        // SQLite has no zone database: named zones stay untranslated
+        assert_eq!(
+            render(&SqliteDialect, &day),
+            "DATE_TRUNC('day', \"created_at\", 'Asia/Kolkata')"
+        );
+        let offset = F::col("created_at").trunc(DatePart::Day, "+05:30");
+        assert_eq!(
+            render(&SqliteDialect, &offset),
+            "datetime(datetime(\"created_at\", '+05:30'), 'start of day')"
+        );
+        let utc = F::col("created_at").trunc(DatePart::Hour, "UTC");
+        assert_eq!(
+            render(&MySqlDialect, &utc),
+            "TIMESTAMP(DATE_FORMAT(`created_at`, '%Y-%m-%d %H:00:00'))"
+        );
+
+        let month = F::col("created_at").extract(DatePart::Month);
+        assert_eq!(
+            render(&PostgresDialect, &month),
+            "CAST(DATE_PART('month', \"created_at\") AS INTEGER)"
+        );
+        assert_eq!(render(&MySqlDialect, &month), "MONTH(`created_at`)");
+        assert_eq!(
+            render(&SqliteDialect, &month),
+            "CAST(strftime('%m', \"created_at\") AS INTEGER)"
+        );
+        let week = Expr::extract_in(DatePart::Week, Expr::column("created_at"), "Europe/Berlin");
+        assert_eq!(
+            render(&PostgresDialect, &week),
+            "CAST(DATE_PART('week', TIMEZONE('Europe/Berlin', \"created_at\")) AS INTEGER)"
+        );
+        assert_eq!(
+            render(&MySqlDialect, &week),
+            "WEEK(CONVERT_TZ(`created_at`, '+00:00', 'Europe/Berlin'), 3)"
+        );
+        assert_eq!(
+            render(&SqliteDialect, &Expr::extract(DatePart::Week, Expr::column("created_at"))),
+            "(((CAST(strftime('%j', date(\"created_at\", '-3 days', 'weekday 4')) AS INTEGER) - 1) / 7) + 1)"
+        );
+    }
+
+    #[test]
+    fn test_column_functions() {
+        let price: Column<(), i64> = Column::new("price");
+        let list_price: Column<(), i64> = Column::new("list_price");
+        let expr = price
+            .coalesce(0)
+            .and(price.greatest(list_price))
+            .and(price.nullif(-1));
+
+        let mut fragment = SqlFragment::new();
+        SqliteDialect.generate_expr(&expr, &mut fragment);
+        assert_eq!(
+            fragment.sql,
+            "(COALESCE(\"price\", $1) AND MAX(\"price\", \"list_price\") AND NULLIF(\"price\", $2))"
+        );
+        assert_eq!(fragment.params, vec![Value::Int64(0), Value::Int64(-1)]);
+
+        let mut fragment = SqlFragment::new();
+        PostgresDialect.generate_expr(&F::col("nickname").coalesce("anon"), &mut fragment);
+        assert_eq!(fragment.sql, "COALESCE(\"nickname\", $1)");
+    }
+
+    #[test]
+    fn test_grouping_sets() {
+        let rollup = Query::select()
+            .from("sales")
+            .columns(&["region", "product"])
+            .group_by_rollup(&["region", "product"])
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&rollup).sql,
+            "SELECT \"region\", \"product\" FROM \"sales\" GROUP BY \
+             ROLLUP (\"region\", \"product\")"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&rollup).sql,
+            "SELECT `region`, `product` FROM `sales` GROUP BY `region`, `product` WITH ROLLUP"
+        );
+        assert!(MySqlDialect.validate(&rollup).is_ok());
+        assert!(SqliteDialect.validate(&rollup).is_err());
+
+        let sets = Query::select()
+            .from("sales")
+            .group_by(&["year"])
+            .grouping_sets(&[&["region", "product"], &["region"], &[]])
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&sets).sql,
+            "SELECT * FROM \"sales\" GROUP BY \"year\", \
+             GROUPING SETS ((\"region\", \"product\"), (\"region\"), ())"
+        );
+        let err = MySqlDialect.validate(&sets).unwrap_err();
+        assert!(err.to_string().contains("GROUPING SETS"));
+
+        let partial = Query::select()
+            .from("sales")
+            .group_by(&["year"])
+            .group_by_cube(&["region"])
+            .build();
+        assert!(PostgresDialect.validate(&partial).is_ok());
+        assert!(MySqlDialect.validate(&partial).is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let query = Query::truncate("sessions")
+            .restart_identity()
+            .cascade()
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "TRUNCATE TABLE \"sessions\" RESTART IDENTITY CASCADE"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "TRUNCATE TABLE `sessions`"
+        );
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "DELETE FROM \"sessions\""
+        );
+    }
+
+    #[test]
+    fn test_subquery_expressions() {
+        let orders = Query::select()
+            .from("orders")
+            .columns(&["1"])
+            .filter(Expr::raw("orders.user_id = users.id"))
+            .filter(Expr::eq("status", "open"))
+            .build();
+        let banned = Query::select().from("bans").columns(&["user_id"]).build();
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::eq("active", true))
+            .filter(Expr::exists(orders))
+            .filter(Expr::not_in_subquery("id", banned))
+            .build();
+
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"users\" WHERE (\"active\" = $1 AND EXISTS (SELECT 1 FROM \
+             \"orders\" WHERE (orders.user_id = users.id AND \"status\" = $2)) AND \"id\" NOT \
+             IN (SELECT \"user_id\" FROM \"bans\"))"
+        );
+        assert_eq!(fragment.params.len(), 2);
+    }
+
+    #[test]
+    fn test_quantified_comparison() {
+        let prices = Query::select()
+            .from("competitors")
+            .columns(&["price"])
+            .filter(Expr::eq("region", "eu"))
+            .build();
+        let query = Query::select()
+            .from("products")
+            .filter(Expr::all("price", CompareOp::Lt, prices.clone()))
+            .build();
+
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "SELECT * FROM `products` WHERE `price` < ALL (SELECT `price` FROM `competitors` \
+             WHERE `region` = ?)"
+        );
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT * FROM \"products\" WHERE NOT EXISTS (SELECT 1 FROM \"competitors\" WHERE \
+             (\"region\" = $1 AND NOT (\"products\".\"price\" < \"price\")))"
+        );
+
+        let query = Query::select()
+            .from("products")
+            .filter(Expr::any("sku", CompareOp::Eq, prices))
+            .build();
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT * FROM \"products\" WHERE \"sku\" IN (SELECT \"price\" FROM \
+             \"competitors\" WHERE \"region\" = $1)"
+        );
+    }
+
+    #[test]
+    fn test_json_expressions() {
+        let data = F::col("data");
+        let query = Query::select()
+            .from("events")
+            .filter(data.json_get("kind").eq("signup"))
+            .filter(data.json_get("user").json_get("age").gte(18i64))
+            .filter(data.json_contains(serde_json::json!({"tags": ["beta"]})))
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT * FROM \"events\" WHERE (\"data\"->>'kind' = $1 AND \
+             (\"data\"#>>'{user,age}')::bigint >= $2 AND \"data\" @> $3)"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "SELECT * FROM `events` WHERE (JSON_UNQUOTE(JSON_EXTRACT(`data`, '$.kind')) = ? \
+             AND JSON_EXTRACT(`data`, '$.user.age') >= ? AND JSON_CONTAINS(`data`, ?))"
+        );
+
+        let fragment = SqliteDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"events\" WHERE (json_extract(\"data\", '$.kind') = $1 AND \
+             json_extract(\"data\", '$.user.age') >= $2 AND EXISTS (SELECT 1 FROM \
+             json_each(\"data\", '$.tags') WHERE \"value\" = $3))"
+        );
+        assert_eq!(fragment.params[2], Value::String("beta".into()));
+    }
+
+    #[test]
+    fn test_array_parameters() {
+        let banned = Query::select()
+            .from("bans")
+            .columns(&["user_id"])
+            .filter(Expr::not_in("reason", vec!["spam", "abuse"]))
+            .build();
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::is_in("id", vec![1i64, 2, 3]))
+            .filter(Expr::not_in_subquery("id", banned))
+            .filter(F::col("tags").array_overlaps(vec!["admin", "staff"]))
+            .build();
+
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"users\" WHERE (\"id\" = ANY($1) AND \"id\" NOT IN (SELECT \
+             \"user_id\" FROM \"bans\" WHERE \"reason\" <> ALL($2)) AND \"tags\" && $3)"
+        );
+        assert_eq!(
+            fragment.params[0],
+            Value::Array(vec![Value::Int64(1), Value::Int64(2), Value::Int64(3)])
+        );
+
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "SELECT * FROM `users` WHERE (`id` IN (?, ?, ?) AND `id` NOT IN (SELECT `user_id` \
+             FROM `bans` WHERE `reason` NOT IN (?, ?)) AND JSON_OVERLAPS(`tags`, ?))"
+        );
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT * FROM \"users\" WHERE (\"id\" IN ($1, $2, $3) AND \"id\" NOT IN (SELECT \
+             \"user_id\" FROM \"bans\" WHERE \"reason\" NOT IN ($4, $5)) AND EXISTS (SELECT 1 \
+             FROM json_each(\"tags\") WHERE \"value\" IN ($6, $7)))"
+        );
+
+        // Mixed types have no array type, so they stay a list
+        let query = Query::select()
+            .from("t")
+            .filter(Expr::In {
+                column: "k".into(),
+                values: vec![Value::Int64(1), Value::String("a".into())],
+                negated: false,
+            })
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT * FROM \"t\" WHERE \"k\" IN ($1, $2)"
+        );
+    }
+
+    #[test]
+    fn test_range_operators() {
+        use crate::range::Range;
+
+        let start = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let end = chrono::DateTime::from_timestamp(1_700_003_600, 0).unwrap();
+        let query = Query::select()
+            .from("bookings")
+            .filter(F::col("during").range_overlaps(Range::from(start..end)))
+            .filter(Expr::range_contains("during", start))
+            .filter(Expr::range_contains("seats", Range::from(1..=4)))
+            .build();
+
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"bookings\" WHERE (\"during\" && $1 AND \"during\" @> $2::timestamptz \
+             AND \"seats\" @> $3)"
+        );
+        assert_eq!(fragment.params[0], Value::from(Range::from(start..end)));
+        assert_eq!(fragment.params[1], Value::DateTime(start));
+
+        assert!(PostgresDialect.validate(&query).is_ok());
+        let err = MySqlDialect.validate(&query).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("range operators are not supported by mysql"));
+        assert!(SqliteDialect.validate(&query).is_err());
+    }
+
+    #[test]
+    fn test_table_functions() {
+        let query = Query::select()
+            .columns(&["day"])
+            .from_function(
+                TableFunction::new(
+                    "generate_series",
+                    vec![
+                        Expr::value("2024-01-01"),
+                        Expr::value("2024-01-31"),
+                        Expr::raw("interval '1 day'"),
+                    ],
+                )
+                .alias("d")
+                .columns(&["day"]),
+            )
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT \"day\" FROM generate_series($1, $2, interval '1 day') AS \"d\" (\"day\")"
+        );
+
+        let query = Query::select()
+            .from("posts")
+            .columns(&["posts.id", "t.tag", "t.n"])
+            .cross_join_function(
+                TableFunction::new("unnest", vec![Expr::column("posts.tags")])
+                    .with_ordinality()
+                    .alias("t")
+                    .columns(&["tag", "n"]),
+            )
+            .filter(Expr::eq("posts.draft", false))
+            .build();
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "SELECT \"posts\".\"id\", \"t\".\"tag\", \"t\".\"n\" FROM \"posts\" CROSS JOIN \
+             unnest(\"posts\".\"tags\") WITH ORDINALITY AS \"t\" (\"tag\", \"n\") WHERE \
+             \"posts\".\"draft\" = $1"
+        );
+
+        let ids = Query::select()
+            .columns(&["id"])
+            .from_function(
+                TableFunction::unnest(vec![3i64, 1, 2])
+                    .alias("ids")
+                    .columns(&["id"]),
+            )
+            .build();
+        let fragment = PostgresDialect.generate(&ids);
+        assert_eq!(
+            fragment.sql,
+            "SELECT \"id\" FROM unnest($1) AS \"ids\" (\"id\")"
+        );
+        assert_eq!(fragment.params.len(), 1);
+    }
+
+    #[test]
+    fn test_upsert() {
+        let query = Query::insert()
+            .table("counters")
+            .set("name", "hits")
+            .on_conflict(
+                OnConflict::columns(&["name"])
+                    .update(&["name"])
+                    .set("total", 0i64),
+            )
+            .returning(&["id"])
+            .build();
+
+        assert_eq!(
+            PostgresDialect.generate(&query).sql,
+            "INSERT INTO \"counters\" (\"name\") VALUES ($1) ON CONFLICT (\"name\") DO UPDATE \
+             SET \"name\" = EXCLUDED.\"name\", \"total\" = $2 RETURNING \"id\""
+        );
+
+        let query = Query {
+            returning: Vec::new(),
+            ..query
+        };
+        let fragment = MySqlDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "INSERT INTO `counters` (`name`) VALUES (?) ON DUPLICATE KEY UPDATE `name` = \
+             VALUES(`name`), `total` = ?"
+        );
+        assert_eq!(
+            fragment.params,
+            vec![Value::from("hits"), Value::from(0i64)]
+        );
+
+        let query = Query::insert()
+            .table("tags")
+            .set("slug", "rust")
+            .on_conflict(OnConflict::any())
+            .build();
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "INSERT INTO \"tags\" (\"slug\") VALUES ($1) ON CONFLICT DO NOTHING"
+        );
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "INSERT INTO `tags` (`slug`) VALUES (?) ON DUPLICATE KEY UPDATE `slug` = `slug`"
+        );
+
+        // PostgreSQL can't infer the conflict target for DO UPDATE
+        let query = Query::insert()
+            .table("tags")
+            .set("slug", "rust")
+            .on_conflict(OnConflict::any().update(&["slug"]))
+            .build();
+        let err = PostgresDialect.validate(&query).unwrap_err();
+        assert!(err.to_string().contains("needs conflict columns"));
+        assert!(SqliteDialect.validate(&query).is_ok());
+        assert!(MySqlDialect.validate(&query).is_ok());
+        assert!(PostgresDialect
+            .validate(&Query {
+                on_conflict: Some(OnConflict::any()),
+                ..query
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_bulk_update() {
+        let update = BulkUpdate {
+            table: "users".to_string(),
+            key: "id".to_string(),
+            key_type: FieldType::BigInt,
+            columns: vec![("name".to_string(), FieldType::Text)],
+            rows: vec![
+                vec![Value::from(1i64), Value::from("ada")],
+                vec![Value::from(2i64), Value::from("alan")],
+            ],
+            conditions: Vec::new(),
+        };
+
+        let fragment = PostgresDialect.generate_bulk_update(&update);
+        assert_eq!(
+            fragment.sql,
+            "UPDATE \"users\" SET \"name\" = chakra_values.\"name\" FROM (VALUES \
+             ($1::BIGINT, $2::TEXT), ($3::BIGINT, $4::TEXT)) AS chakra_values (\"id\", \"name\") \
+             WHERE \"users\".\"id\" = chakra_values.\"id\""
+        );
+        assert_eq!(fragment.params.len(), 4);
+
+        let fragment = MySqlDialect.generate_bulk_update(&update);
+        assert_eq!(
+            fragment.sql,
+            "UPDATE `users` SET `name` = CASE `id` WHEN ? THEN ? WHEN ? THEN ? ELSE `name` END \
+             WHERE `id` IN (?, ?)"
+        );
+        assert_eq!(fragment.params.len(), 6);
+
+        let scoped = BulkUpdate {
+            conditions: vec![("tenant_id".to_string(), Value::from(7i64))],
+            ..update.clone()
+        };
+        let fragment = PostgresDialect.generate_bulk_update(&scoped);
+        assert!(fragment.sql.ends_with(" AND \"users\".\"tenant_id\" = $5"));
+        assert!(SqliteDialect
+            .generate_bulk_update(&scoped)
+            .sql
+            .ends_with("IN (?5, ?6) AND \"tenant_id\" = ?7"));
+
+        // Three values per row, so at most two rows in 7 parameters
+        assert_eq!(update.chunks(7).len(), 1);
+        assert_eq!(update.chunks(5).len(), 2);
+    }
+
+    #[test]
+    fn test_recursive_cte() {
+        let anchor = Query::select()
+            .from("categories")
+            .columns(&["id", "parent_id"])
+            .filter(Expr::eq("id", 1))
+            .build();
+        let recursive = Query::select()
+            .from("categories")
+            .alias("c")
+            .columns(&["c.id", "c.parent_id"])
+            .join("tree", Expr::raw("c.parent_id = tree.id"))
+            .build();
+        let query = Query::select()
+            .from("tree")
+            .with_recursive("tree", &["id", "parent_id"], anchor, recursive)
+            .filter(Expr::ne("id", 7))
+            .build();
+
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "WITH RECURSIVE \"tree\" (\"id\", \"parent_id\") AS (SELECT \"id\", \"parent_id\" \
+             FROM \"categories\" WHERE \"id\" = $1 UNION ALL SELECT \"c\".\"id\", \
+             \"c\".\"parent_id\" FROM \"categories\" AS \"c\" INNER JOIN \"tree\" ON \
+             c.parent_id = tree.id) SELECT * FROM \"tree\" WHERE \"id\" != $2"
+        );
+        assert_eq!(fragment.params, vec![Value::from(1), Value::from(7)]);
+
+        let big = Query::select()
+            .from("orders")
+            .filter(Expr::gt("total", 100))
+            .build();
+        let query = Query::select()
+            .from("big")
+            .with_cte("big", big)
+            .filter(Expr::eq("status", "paid"))
+            .build();
+        assert_eq!(
+            MySqlDialect.generate(&query).sql,
+            "WITH `big` AS (SELECT * FROM `orders` WHERE `total` > ?) SELECT * FROM `big` \
+             WHERE `status` = ?"
+        );
+    }
+
+    #[test]
+    fn test_named_to_positional() {
+        let fragment = SqlFragment::from_sql(
+            "SELECT * FROM events WHERE owner = :owner AND (actor = :owner OR kind = :kind) \
+             AND note <> ':skip' AND at::date = $1",
+        )
+        .bind("owner", 7i64)
+        .bind("kind", "login");
+        let fragment = SqlFragment {
+            params: vec![Value::from("2024-01-01")],
+            ..fragment
+        };
+
+        let pg = fragment.to_positional(&PostgresDialect).unwrap();
+        assert_eq!(
+            pg.sql,
+            "SELECT * FROM events WHERE owner = $1 AND (actor = $1 OR kind = $2) \
+             AND note <> ':skip' AND at::date = $3"
+        );
+        assert_eq!(pg.params.len(), 3);
+
+        let mysql = fragment.to_positional(&MySqlDialect).unwrap();
+        assert_eq!(mysql.params.len(), 4);
+        assert_eq!(mysql.params[1], Value::from(7i64));
+
+        let missing = SqlFragment::from_sql("SELECT :id").to_positional(&PostgresDialect);
+        assert!(missing.is_err());
+
+        // MySQL variables are not placeholders, `@name` binds are on SQLite
+        let vars = SqlFragment::from_sql("SELECT @program_name, @@session.time_zone, @@version");
+        assert_eq!(vars.to_positional(&MySqlDialect).unwrap().sql, vars.sql);
+        let at = SqlFragment::from_sql("SELECT @@version, @a").bind("a", 1i64);
+        assert_eq!(
+            at.to_positional(&SqliteDialect).unwrap().sql,
+            "SELECT @@version, ?1"
+        );
+    }
+
+    #[test]
+    fn test_check_params() {
+        let sql =
+            "SELECT * FROM users WHERE id = $1 AND name <> '$2' AND (owner = $1 OR team = $2)";
+        let fragment = SqlFragment::with_params(sql, vec![Value::from(1i64), Value::from(2i64)]);
+        assert!(fragment.check_params(&PostgresDialect).is_ok());
+
+        let missing = SqlFragment::with_params(sql, vec![Value::from(1i64)]);
+        assert!(missing
+            .check_params(&PostgresDialect)
+            .unwrap_err()
+            .to_string()
+            .contains("$2"));
+
+        let unused =
+            SqlFragment::with_params("SELECT ?", vec![Value::from(1i64), Value::from(2i64)]);
+        assert!(unused
+            .check_params(&MySqlDialect)
+            .unwrap_err()
+            .to_string()
+            .contains("$2 is never used"));
+
+        let named = SqlFragment::from_sql("SELECT :a").bind("a", 1i64);
+        assert!(named.check_params(&PostgresDialect).is_ok());
+        assert!(named
+            .bind("b", 2i64)
+            .check_params(&PostgresDialect)
+            .is_err());
+
+        // MySQL variables are not placeholders, `@name` binds are on SQLite
+        let vars = SqlFragment::with_params(
+            "SELECT @program_name, @@session.time_zone, @@version WHERE id = ?",
+            vec![Value::from(1i64)],
+        );
+        assert!(vars.check_params(&MySqlDialect).is_ok());
+        assert_eq!(vars.to_positional(&MySqlDialect).unwrap().sql, vars.sql);
+        assert!(vars.check_params(&SqliteDialect).is_err());
+        let at = SqlFragment::from_sql("SELECT @@version, @a").bind("a", 1i64);
+        assert!(at.check_params(&SqliteDialect).is_ok());
+        assert_eq!(
+            at.to_positional(&SqliteDialect).unwrap().sql,
+            "SELECT @@version, ?1"
+        );
+    }
+
+    #[test]
+    fn test_select_exprs() {
+        let query = Query::select()
+            .from("posts")
+            .column("author_id")
+            .column_expr(
+                Expr::count_all().filter_where(Expr::eq("draft", false)),
+                "published",
+            )
+            .group_by(&["author_id"])
+            .build();
+        let fragment = PostgresDialect.generate(&query);
+        assert_eq!(
+            fragment.sql,
+            "SELECT \"author_id\", COUNT(*) FILTER (WHERE \"draft\" = $1) AS \"published\" \
+             FROM \"posts\" GROUP BY \"author_id\""
+        );
+        assert_eq!(fragment.params, vec![Value::Bool(false)]);
+
+        let query = Query::select()
+            .from("posts")
+            .column_expr(Expr::count_all(), "n")
+            .build();
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT COUNT(*) AS \"n\" FROM \"posts\""
+        );
+    }
+
+    #[test]
+    fn test_set_constraints() {
+        assert_eq!(
+            PostgresDialect.set_constraints(&[], true).unwrap(),
+            "SET CONSTRAINTS ALL DEFERRED"
+        );
+        assert_eq!(
+            PostgresDialect
+                .set_constraints(&["orders_customer_fk", "app.items_key"], false)
+                .unwrap(),
+            "SET CONSTRAINTS \"orders_customer_fk\", \"app\".\"items_key\" IMMEDIATE"
+        );
+        assert_eq!(
+            SqliteDialect.set_constraints(&[], true).unwrap(),
+            "PRAGMA defer_foreign_keys = ON"
+        );
+        assert!(SqliteDialect
+            .set_constraints(&["orders_customer_fk"], true)
+            .is_err());
+        assert!(MySqlDialect.set_constraints(&[], true).is_err());
+    }
+
+    #[test]
+    fn test_begin_transaction() {
+        let level = Some(IsolationLevel::Serializable);
+        assert_eq!(PostgresDialect.begin_transaction(None), ["BEGIN"]);
+        assert_eq!(
+            PostgresDialect.begin_transaction(level),
+            ["START TRANSACTION ISOLATION LEVEL SERIALIZABLE"]
+        );
+        assert_eq!(
+            MySqlDialect.begin_transaction(Some(IsolationLevel::ReadCommitted)),
+            ["SET TRANSACTION ISOLATION LEVEL READ COMMITTED", "BEGIN"]
+        );
+        assert_eq!(SqliteDialect.begin_transaction(level), ["BEGIN"]);
+    }
+
+    #[test]
+    fn test_into_named() {
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::eq("id", 1).and(Expr::eq("name", "a")))
+            .build();
+
+        let fragment = PostgresDialect.generate(&query).into_named(NamedStyle::At);
+        assert_eq!(
+            fragment.sql,
+            "SELECT * FROM \"users\" WHERE (\"id\" = @p1 AND \"name\" = @p2)"
+        );
+        assert!(fragment.params.is_empty());
+        assert_eq!(fragment.named.get("p2"), Some(&Value::from("a")));
+    }
+
+    #[test]
+    fn test_inline_params() {
+        let query = Query::select()
+            .from("users")
+            .filter(Expr::eq("id", 1).and(Expr::eq("name", "it's")))
+            .build();
+        assert_eq!(
+            PostgresDialect
+                .generate(&query)
+                .inline_params(&PostgresDialect),
+            "SELECT * FROM \"users\" WHERE (\"id\" = 1 AND \"name\" = 'it''s')"
+        );
+
+        // Literals are left alone, as are placeholders without a value
+        let fragment = SqlFragment::with_params("SELECT '$1', $1, :kind, $2", vec![Value::Null])
+            .bind("kind", "admin");
+        assert_eq!(
+            fragment.inline_params(&PostgresDialect),
+            "SELECT '$1', NULL, 'admin', $2"
+        );
+    }
+
+    #[test]
+    fn test_dialect_literals() {
+        let bytes = Value::Bytes(vec![0xde, 0xad]);
+        assert_eq!(PostgresDialect.literal(&bytes), "'\\xdead'::bytea");
+        assert_eq!(MySqlDialect.literal(&bytes), "X'dead'");
+        assert_eq!(SqliteDialect.literal(&bytes), "X'dead'");
+
+        let nan = Value::Float64(f64::NAN);
+        assert_eq!(PostgresDialect.literal(&nan), "'NaN'::float8");
+        assert_eq!(
+            PostgresDialect.literal(&Value::Float64(f64::NEG_INFINITY)),
+            "'-Infinity'::float8"
+        );
+        assert_eq!(
+            SqliteDialect.literal(&Value::Float64(f64::INFINITY)),
+            "9e999"
+        );
+        assert_eq!(MySqlDialect.literal(&nan), "NULL");
+
+        let path = Value::from("C:\\it's\\");
+        assert_eq!(PostgresDialect.literal(&path), "'C:\\it''s\\'");
+        assert_eq!(MySqlDialect.literal(&path), "'C:\\\\it''s\\\\'");
+
+        let tags = Value::Array(vec![Value::from("a"), Value::Int64(1)]);
+        assert_eq!(PostgresDialect.literal(&tags), "ARRAY['a', 1]");
+        assert_eq!(PostgresDialect.literal(&Value::Array(vec![])), "'{}'");
+        assert_eq!(SqliteDialect.literal(&tags), "'[\"a\",1]'");
+        assert_eq!(MySqlDialect.literal(&tags), "'[\"a\",1]'");
+        assert_eq!(SqliteDialect.literal(&Value::Bool(true)), "1");
+    }
+
+    #[test]
+    fn test_checksum_sql() {
+        let sql = PostgresDialect
+            .checksum_sql("SELECT * FROM \"users\"", &[])
+            .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT COUNT(*) AS row_count, COALESCE(BIT_XOR(('x' || LEFT(MD5(t::text), 16))::bit(64)::bigint), 0) AS checksum FROM (SELECT * FROM \"users\") AS t"
+        );
+
+        // MySQL hashes named columns only
+        assert!(MySqlDialect
+            .checksum_sql("SELECT * FROM `users`", &[])
+            .is_none());
+        let sql = MySqlDialect
+            .checksum_sql(
+                "SELECT `id`, `name` FROM `users`",
+                &["id".to_string(), "name".to_string()],
+            )
+            .unwrap();
+        assert!(sql.contains("MD5(JSON_ARRAY(t.`id`, t.`name`))"));
+
+        assert!(SqliteDialect
+            .checksum_sql("SELECT * FROM \"users\"", &[])
+            .is_none());
     }
 }
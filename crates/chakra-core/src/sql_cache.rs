@@ -0,0 +1,938 @@
+//! Compiled SQL cache for Chakra ORM
+//!
+//! Queries built in hot paths usually differ only in their parameter values.
+//! `SqlCache` keys generated SQL by the structural shape of a `Query`, with
+//! values reduced to their types and compared whole rather than by a hash, and keeps the SQL text together with the
+//! parameter slots it binds. A repeated shape reuses the text and only
+//! collects the new values instead of rendering the statement again.
+//!
+//! A shape is compiled once by rendering it a second time with different
+//! values. Shapes whose SQL changes with the values, or whose parameters
+//! cannot be traced back to a value, are remembered as uncacheable and always
+//! rendered normally.
+
+use crate::expr::Expr;
+use crate::identifier::{identifier_policy, IdentifierPolicy};
+use crate::net::{Cidr, MacAddr};
+use crate::query::{
+    ConflictAction, ConflictUpdate, Cte, Join, OnConflict, OrderBy, Query, TableFunction,
+};
+use crate::sql::{Dialect, SqlFragment};
+use crate::types::Value;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use rust_decimal::Decimal;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv6Addr};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Default number of shapes kept by `SqlCache::default`
+pub const DEFAULT_CAPACITY: usize = 512;
+
+/// Where a generated parameter comes from
+#[derive(Debug, Clone)]
+enum Slot {
+    /// The query value at this position in walk order
+    Value(usize),
+    /// A value the SQL binds regardless of the query's values
+    Fixed(Value),
+    /// An array assembled from other slots
+    Array(Vec<Slot>),
+}
+
+impl Slot {
+    fn bind(&self, values: &[Value]) -> Value {
+        match self {
+            Slot::Value(i) => values[*i].clone(),
+            Slot::Fixed(value) => value.clone(),
+            Slot::Array(slots) => Value::Array(slots.iter().map(|s| s.bind(values)).collect()),
+        }
+    }
+}
+
+/// SQL text and parameter slots for one query shape
+#[derive(Debug)]
+struct Plan {
+    sql: String,
+    slots: Vec<Slot>,
+}
+
+impl Plan {
+    fn bind(&self, values: &[Value]) -> SqlFragment {
+        let mut fragment = SqlFragment::new();
+        fragment.sql.push_str(&self.sql);
+        fragment.params = self.slots.iter().map(|slot| slot.bind(values)).collect();
+        fragment
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// `None` for shapes that can't be cached
+    plan: Option<Arc<Plan>>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<Shape, Entry>,
+    tick: u64,
+}
+
+/// Hit and miss counts of a cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Shapes currently cached, including uncacheable ones
+    pub len: usize,
+}
+
+/// LRU cache of generated SQL keyed by query shape
+#[derive(Debug)]
+pub struct SqlCache {
+    capacity: usize,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SqlCache {
+    /// Create a cache holding up to `capacity` shapes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Maximum number of shapes kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Generate SQL for a query, reusing the text of an earlier query of
+    /// the same shape
+    pub fn generate(&self, dialect: &dyn Dialect, query: &Query) -> SqlFragment {
+        let key = shape_key(dialect.name(), identifier_policy(dialect.name()), query);
+        let cached = self.lookup(&key);
+        if let Some(Some(plan)) = &cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return plan.bind(&values(query));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let fragment = dialect.generate(query);
+        if cached.is_none() {
+            let plan = compile(dialect, query, &values(query), &fragment);
+            self.insert(key, plan.map(Arc::new));
+        }
+        fragment
+    }
+
+    /// Hit and miss counts since the cache was created or cleared
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self.state.lock().unwrap().entries.len(),
+        }
+    }
+
+    /// Drop every cached shape and reset the counts
+    pub fn clear(&self) {
+        self.state.lock().unwrap().entries.clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    fn lookup(&self, key: &Shape) -> Option<Option<Arc<Plan>>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        state.entries.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.plan.clone()
+        })
+    }
+
+    fn insert(&self, key: Shape, plan: Option<Arc<Plan>>) {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&key) {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.tick += 1;
+        let last_used = state.tick;
+        state.entries.insert(key, Entry { plan, last_used });
+    }
+}
+
+impl Default for SqlCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Work out which value each parameter binds, by rendering the query again
+/// with every variable value changed
+///
+/// Returns `None` when the SQL text depends on the values or a parameter
+/// can't be traced to a single value.
+fn compile(
+    dialect: &dyn Dialect,
+    query: &Query,
+    values: &[Value],
+    fragment: &SqlFragment,
+) -> Option<Plan> {
+    if fragment.has_named_params() {
+        return None;
+    }
+
+    let mut probe = query.clone();
+    let mut next = 0;
+    let mut probe_values = Vec::with_capacity(values.len());
+    walk_values_mut(&mut probe, &mut |value| {
+        perturb(value, &mut next);
+        probe_values.push(value.clone());
+    });
+
+    let probed = dialect.generate(&probe);
+    if probed.sql != fragment.sql || probed.params.len() != fragment.params.len() {
+        return None;
+    }
+
+    let slots = fragment
+        .params
+        .iter()
+        .zip(&probed.params)
+        .map(|(param, probed)| trace(param, probed, values, &probe_values))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Plan {
+        sql: fragment.sql.clone(),
+        slots,
+    })
+}
+
+/// Find the slot of a parameter from its value in both renderings
+///
+/// Arrays the dialect built from several values, like `= ANY($1)` lists,
+/// are traced element by element.
+fn trace(param: &Value, probed: &Value, values: &[Value], probe_values: &[Value]) -> Option<Slot> {
+    let mut matches = probe_values
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| *value == probed);
+    match (matches.next(), matches.next(), param, probed) {
+        (Some((i, _)), None, ..) if values[i] == *param => Some(Slot::Value(i)),
+        _ if param == probed => Some(Slot::Fixed(param.clone())),
+        (.., Value::Array(params), Value::Array(probed)) if params.len() == probed.len() => params
+            .iter()
+            .zip(probed)
+            .map(|(param, probed)| trace(param, probed, values, probe_values))
+            .collect::<Option<Vec<_>>>()
+            .map(Slot::Array),
+        _ => None,
+    }
+}
+
+/// The values of a query that are bound as parameters, in walk order
+fn values(query: &Query) -> Vec<Value> {
+    let mut values = Vec::new();
+    walk_values(query, &mut |value| values.push(value.clone()));
+    values
+}
+
+/// Call `f` on the values of a query that are bound as parameters
+///
+/// Insert rows are visited in column order so the walk doesn't depend on
+/// hash map iteration. Values this skips stay part of the shape.
+fn walk_values(query: &Query, f: &mut dyn FnMut(&Value)) {
+    for row in &query.values {
+        let mut columns: Vec<(&String, &Value)> = row.iter().collect();
+        columns.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        columns.into_iter().for_each(|(_, value)| f(value));
+    }
+    if let Some(on_conflict) = &query.on_conflict {
+        if let ConflictAction::DoUpdate(updates) = &on_conflict.action {
+            for update in updates {
+                if let ConflictUpdate::Value(_, value) = update {
+                    f(value);
+                }
+            }
+        }
+    }
+    query.walk_exprs(&mut |expr| match expr {
+        Expr::Value(value)
+        | Expr::Compare { value, .. }
+        | Expr::JsonCompare { value, .. }
+        | Expr::JsonContains { value, .. }
+        | Expr::RangeOverlaps { value, .. }
+        | Expr::RangeContains { value, .. } => f(value),
+        Expr::Between { low, high, .. } => {
+            f(low);
+            f(high);
+        }
+        Expr::In { values, .. }
+        | Expr::InArray { values, .. }
+        | Expr::ArrayContains { values, .. }
+        | Expr::ArrayOverlaps { values, .. } => values.iter().for_each(&mut *f),
+        _ => {}
+    });
+}
+
+/// Like `walk_values`, but mutable
+fn walk_values_mut(query: &mut Query, f: &mut dyn FnMut(&mut Value)) {
+    for row in &mut query.values {
+        let mut columns: Vec<&String> = row.keys().collect();
+        columns.sort_unstable();
+        let columns: Vec<String> = columns.into_iter().cloned().collect();
+        for column in columns {
+            if let Some(value) = row.get_mut(&column) {
+                f(value);
+            }
+        }
+    }
+    if let Some(on_conflict) = &mut query.on_conflict {
+        if let ConflictAction::DoUpdate(updates) = &mut on_conflict.action {
+            for update in updates {
+                if let ConflictUpdate::Value(_, value) = update {
+                    f(value);
+                }
+            }
+        }
+    }
+    query.walk_exprs_mut(&mut |expr| match expr {
+        Expr::Value(value)
+        | Expr::Compare { value, .. }
+        | Expr::JsonCompare { value, .. }
+        | Expr::JsonContains { value, .. }
+        | Expr::RangeOverlaps { value, .. }
+        | Expr::RangeContains { value, .. } => f(value),
+        Expr::Between { low, high, .. } => {
+            f(low);
+            f(high);
+        }
+        Expr::In { values, .. }
+        | Expr::InArray { values, .. }
+        | Expr::ArrayContains { values, .. }
+        | Expr::ArrayOverlaps { values, .. } => values.iter_mut().for_each(&mut *f),
+        _ => {}
+    });
+}
+
+/// Change a value to a distinct one of the same type, leaving the parts
+/// hashed into its shape alone
+fn perturb(value: &mut Value, next: &mut u32) {
+    if let Value::Array(items) = value {
+        items.iter_mut().for_each(|item| perturb(item, next));
+        return;
+    }
+    if let Value::Range(range) = value {
+        **range = (**range).clone().map(|mut bound| {
+            perturb(&mut bound, next);
+            bound
+        });
+        return;
+    }
+    loop {
+        *next += 1;
+        let n = *next;
+        let changed = match value {
+            Value::Null | Value::Bool(_) | Value::Json(_) | Value::Array(_) | Value::Range(_) => {
+                return
+            }
+            Value::Int32(_) => Value::Int32(i32::MIN + n as i32),
+            Value::Int64(_) => Value::Int64(i64::MIN + i64::from(n)),
+            Value::Float64(_) => Value::Float64(-f64::from(n) - 0.5),
+            Value::Decimal(_) => Value::Decimal(Decimal::new(-i64::from(n), 3)),
+            Value::String(_) => Value::String(format!("\u{1}{}", n)),
+            Value::Bytes(_) => Value::Bytes([&[0xFF][..], &n.to_be_bytes()].concat()),
+            Value::Uuid(_) => Value::Uuid(Uuid::from_u128(u128::MAX - u128::from(n))),
+            Value::DateTime(_) => {
+                Value::DateTime(DateTime::from_timestamp(-i64::from(n), 0).unwrap_or_default())
+            }
+            Value::Date(_) => {
+                Value::Date(NaiveDate::from_num_days_from_ce_opt(n as i32).unwrap_or_default())
+            }
+            Value::Time(_) => Value::Time(
+                NaiveTime::from_num_seconds_from_midnight_opt(n % 86_400, n / 86_400)
+                    .unwrap_or_default(),
+            ),
+            Value::IpAddr(_) => Value::IpAddr(Ipv6Addr::from(u128::MAX - u128::from(n)).into()),
+            Value::Cidr(_) => Value::Cidr(Cidr::from(IpAddr::from(Ipv6Addr::from(
+                u128::MAX - u128::from(n),
+            )))),
+            Value::MacAddr(_) => {
+                let [a, b, c, d] = n.to_be_bytes();
+                Value::MacAddr(MacAddr([0xFF, 0xFF, a, b, c, d]))
+            }
+        };
+        if changed != *value {
+            *value = changed;
+            return;
+        }
+    }
+}
+
+/// The bytes a query's shape hashes to
+///
+/// Kept whole as the cache key, so two shapes can't share SQL because their
+/// hashes collide.
+type Shape = Vec<u8>;
+
+/// Collects what is fed to it instead of hashing it
+#[derive(Default)]
+struct ShapeBytes(Shape);
+
+impl Hasher for ShapeBytes {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&self.0);
+        hasher.finish()
+    }
+}
+
+/// The shape of a query: its structure, with the values `walk_values`
+/// visits reduced to their type
+fn shape_key(dialect: &str, identifiers: IdentifierPolicy, query: &Query) -> Shape {
+    let mut hasher = ShapeHasher(ShapeBytes::default());
+    dialect.hash(&mut hasher.0);
+    // Another policy writes the same names differently
+    identifiers.hash(&mut hasher.0);
+    hasher.query(query, true);
+    let ShapeHasher(ShapeBytes(shape)) = hasher;
+    shape
+}
+
+/// Feeds a query to `ShapeBytes` field by field
+///
+/// Structs are destructured whole so a new field fails to compile here
+/// until it's hashed.
+struct ShapeHasher(ShapeBytes);
+
+impl ShapeHasher {
+    fn str(&mut self, s: &str) {
+        s.hash(&mut self.0);
+    }
+
+    fn strs(&mut self, strs: &[String]) {
+        strs.hash(&mut self.0);
+    }
+
+    /// Insert rows and conflict values are only bound as parameters in the
+    /// `outer` query; nested ones are hashed whole
+    fn query(&mut self, query: &Query, outer: bool) {
+        let Query {
+            query_type,
+            table,
+            alias,
+            columns,
+            select_exprs,
+            values,
+            where_clause,
+            joins,
+            order_by,
+            group_by,
+            grouping_sets,
+            having,
+            limit,
+            offset,
+            distinct,
+            returning,
+            on_conflict,
+            source,
+            set_exprs,
+            update_from,
+            key_columns,
+            ctes,
+            from_function,
+            for_update,
+            unlimited,
+            comments,
+            sensitive,
+            truncate,
+            temp_table,
+        } = query;
+        query_type.hash(&mut self.0);
+        self.str(table);
+        alias.hash(&mut self.0);
+        self.strs(columns);
+        select_exprs.len().hash(&mut self.0);
+        for (expr, alias) in select_exprs {
+            self.expr(expr);
+            self.str(alias);
+        }
+        values.len().hash(&mut self.0);
+        for row in values {
+            let mut columns: Vec<(&String, &Value)> = row.iter().collect();
+            columns.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            columns.len().hash(&mut self.0);
+            for (column, value) in columns {
+                self.str(column);
+                self.value(value, outer);
+            }
+        }
+        self.opt_expr(where_clause.as_ref());
+        joins.len().hash(&mut self.0);
+        for Join {
+            join_type,
+            table,
+            alias,
+            on,
+            function,
+        } in joins
+        {
+            join_type.hash(&mut self.0);
+            self.str(table);
+            alias.hash(&mut self.0);
+            self.expr(on);
+            self.function(function.as_ref());
+        }
+        self.order_by(order_by);
+        self.strs(group_by);
+        grouping_sets.hash(&mut self.0);
+        self.opt_expr(having.as_ref());
+        (limit, offset, distinct).hash(&mut self.0);
+        self.strs(returning);
+        on_conflict.is_some().hash(&mut self.0);
+        if let Some(OnConflict { target, action }) = on_conflict {
+            self.strs(target);
+            match action {
+                ConflictAction::DoNothing => 0u8.hash(&mut self.0),
+                ConflictAction::DoUpdate(updates) => {
+                    1u8.hash(&mut self.0);
+                    updates.len().hash(&mut self.0);
+                    for update in updates {
+                        match update {
+                            ConflictUpdate::Excluded(column) => {
+                                0u8.hash(&mut self.0);
+                                self.str(column);
+                            }
+                            ConflictUpdate::Value(column, value) => {
+                                1u8.hash(&mut self.0);
+                                self.str(column);
+                                self.value(value, outer);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.opt_query(source.as_deref());
+        set_exprs.len().hash(&mut self.0);
+        for (column, expr) in set_exprs {
+            self.str(column);
+            self.expr(expr);
+        }
+        self.strs(update_from);
+        self.strs(key_columns);
+        ctes.len().hash(&mut self.0);
+        for Cte {
+            name,
+            columns,
+            query,
+            recursive_term,
+        } in ctes
+        {
+            self.str(name);
+            self.strs(columns);
+            self.query(query, false);
+            self.opt_query(recursive_term.as_deref());
+        }
+        self.function(from_function.as_ref());
+        (for_update, unlimited, comments, sensitive).hash(&mut self.0);
+        (truncate, temp_table).hash(&mut self.0);
+    }
+
+    fn opt_query(&mut self, query: Option<&Query>) {
+        query.is_some().hash(&mut self.0);
+        if let Some(query) = query {
+            self.query(query, false);
+        }
+    }
+
+    fn function(&mut self, function: Option<&TableFunction>) {
+        function.is_some().hash(&mut self.0);
+        if let Some(TableFunction {
+            name,
+            args,
+            alias,
+            columns,
+            with_ordinality,
+        }) = function
+        {
+            self.str(name);
+            self.exprs(args);
+            alias.hash(&mut self.0);
+            self.strs(columns);
+            with_ordinality.hash(&mut self.0);
+        }
+    }
+
+    fn order_by(&mut self, order_by: &[OrderBy]) {
+        order_by.len().hash(&mut self.0);
+        for OrderBy {
+            column,
+            order,
+            nulls,
+            expr,
+        } in order_by
+        {
+            self.str(column);
+            (order, nulls).hash(&mut self.0);
+            self.opt_expr(expr.as_ref());
+        }
+    }
+
+    fn opt_expr(&mut self, expr: Option<&Expr>) {
+        expr.is_some().hash(&mut self.0);
+        if let Some(expr) = expr {
+            self.expr(expr);
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        exprs.len().hash(&mut self.0);
+        exprs.iter().for_each(|expr| self.expr(expr));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        std::mem::discriminant(expr).hash(&mut self.0);
+        match expr {
+            Expr::Column(column) | Expr::Raw(column) => self.str(column),
+            Expr::Value(value) => self.value(value, true),
+            Expr::Compare { column, op, value } => {
+                self.str(column);
+                op.hash(&mut self.0);
+                self.value(value, true);
+            }
+            Expr::ColumnCompare { left, op, right } => {
+                self.str(left);
+                op.hash(&mut self.0);
+                self.str(right);
+            }
+            Expr::Between {
+                column,
+                low,
+                high,
+                negated,
+                symmetric,
+            } => {
+                self.str(column);
+                self.value(low, true);
+                self.value(high, true);
+                (negated, symmetric).hash(&mut self.0);
+            }
+            Expr::RelativeTime {
+                column,
+                seconds,
+                older,
+            } => {
+                self.str(column);
+                (seconds, older).hash(&mut self.0);
+            }
+            Expr::In {
+                column,
+                values,
+                negated,
+            }
+            | Expr::InArray {
+                column,
+                values,
+                negated,
+            } => {
+                self.str(column);
+                self.values(values);
+                negated.hash(&mut self.0);
+            }
+            Expr::And(exprs) | Expr::Or(exprs) => self.exprs(exprs),
+            Expr::Not(expr) => self.expr(expr),
+            Expr::Function { name, args } => {
+                self.str(name);
+                self.exprs(args);
+            }
+            Expr::Aggregate {
+                function,
+                column,
+                distinct,
+                filter,
+                order_by,
+                separator,
+            } => {
+                function.hash(&mut self.0);
+                self.str(column);
+                distinct.hash(&mut self.0);
+                self.opt_expr(filter.as_deref());
+                self.order_by(order_by);
+                separator.hash(&mut self.0);
+            }
+            Expr::Arithmetic { left, op, right } => {
+                self.expr(left);
+                op.hash(&mut self.0);
+                self.expr(right);
+            }
+            Expr::Case {
+                conditions,
+                else_result,
+            } => {
+                conditions.len().hash(&mut self.0);
+                for (when, then) in conditions {
+                    self.expr(when);
+                    self.expr(then);
+                }
+                self.opt_expr(else_result.as_deref());
+            }
+            Expr::Subquery(query) => self.query(query, false),
+            Expr::Exists { query, negated } => {
+                self.query(query, false);
+                negated.hash(&mut self.0);
+            }
+            Expr::InSubquery {
+                column,
+                query,
+                negated,
+            } => {
+                self.str(column);
+                self.query(query, false);
+                negated.hash(&mut self.0);
+            }
+            Expr::SubqueryCompare {
+                column,
+                op,
+                quantifier,
+                query,
+            } => {
+                self.str(column);
+                (op, quantifier).hash(&mut self.0);
+                self.query(query, false);
+            }
+            Expr::JsonGet {
+                column,
+                path,
+                as_text,
+            } => {
+                self.str(column);
+                self.strs(path);
+                as_text.hash(&mut self.0);
+            }
+            Expr::JsonCompare {
+                column,
+                path,
+                op,
+                value,
+            } => {
+                self.str(column);
+                self.strs(path);
+                op.hash(&mut self.0);
+                self.value(value, true);
+            }
+            Expr::JsonContains { column, value }
+            | Expr::RangeOverlaps { column, value }
+            | Expr::RangeContains { column, value } => {
+                self.str(column);
+                self.value(value, true);
+            }
+            Expr::ArrayContains { column, values } | Expr::ArrayOverlaps { column, values } => {
+                self.str(column);
+                self.values(values);
+            }
+            Expr::Cast { expr, sql_type } => {
+                self.expr(expr);
+                self.str(sql_type);
+            }
+        }
+    }
+
+    fn values(&mut self, values: &[Value]) {
+        values.len().hash(&mut self.0);
+        values.iter().for_each(|value| self.value(value, true));
+    }
+
+    /// Hash the type of a `bound` value, or all of any other
+    ///
+    /// Nulls, booleans and JSON are always hashed whole since dialects
+    /// rewrite them, as are the bounds and length of ranges and arrays.
+    fn value(&mut self, value: &Value, bound: bool) {
+        std::mem::discriminant(value).hash(&mut self.0);
+        match value {
+            Value::Null => {}
+            Value::Bool(b) => b.hash(&mut self.0),
+            Value::Json(json) => self.str(&json.to_string()),
+            Value::Array(items) => {
+                items.len().hash(&mut self.0);
+                items.iter().for_each(|item| self.value(item, bound));
+            }
+            Value::Range(range) => {
+                range.is_empty().hash(&mut self.0);
+                for bound_value in [range.lower(), range.upper()] {
+                    match bound_value {
+                        Bound::Included(v) => {
+                            0u8.hash(&mut self.0);
+                            self.value(v, bound);
+                        }
+                        Bound::Excluded(v) => {
+                            1u8.hash(&mut self.0);
+                            self.value(v, bound);
+                        }
+                        Bound::Unbounded => 2u8.hash(&mut self.0),
+                    }
+                }
+            }
+            _ if bound => {}
+            Value::Int32(v) => v.hash(&mut self.0),
+            Value::Int64(v) => v.hash(&mut self.0),
+            Value::Float64(v) => v.to_bits().hash(&mut self.0),
+            Value::Decimal(v) => v.hash(&mut self.0),
+            Value::String(v) => self.str(v),
+            Value::Bytes(v) => v.hash(&mut self.0),
+            Value::Uuid(v) => v.hash(&mut self.0),
+            Value::DateTime(v) => v.hash(&mut self.0),
+            Value::Date(v) => v.hash(&mut self.0),
+            Value::Time(v) => v.hash(&mut self.0),
+            Value::IpAddr(v) => v.hash(&mut self.0),
+            Value::Cidr(v) => v.hash(&mut self.0),
+            Value::MacAddr(v) => v.hash(&mut self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::F;
+    use crate::sql::{MySqlDialect, PostgresDialect};
+
+    fn assert_same(cached: SqlFragment, generated: SqlFragment) {
+        assert_eq!(cached.sql, generated.sql);
+        assert_eq!(cached.params, generated.params);
+    }
+
+    fn find(id: i64, name: &str) -> Query {
+        Query::select()
+            .from("users")
+            .filter(Expr::eq("id", id))
+            .filter(F::new("name").is_in(vec![name, "admin"]))
+            .limit(10)
+            .build()
+    }
+
+    #[test]
+    fn test_reuses_sql_for_same_shape() {
+        let cache = SqlCache::new(8);
+        for (id, name) in [(1, "alice"), (2, "bob"), (3, "admin")] {
+            let query = find(id, name);
+            assert_same(
+                cache.generate(&PostgresDialect, &query),
+                PostgresDialect.generate(&query),
+            );
+        }
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                len: 1
+            }
+        );
+
+        // Rows are matched by column, whatever order they were built in
+        let insert = |a: (&str, Value), b: (&str, Value)| {
+            let row = HashMap::from([(a.0.to_string(), a.1), (b.0.to_string(), b.1)]);
+            Query::insert().table("events").values(row).build()
+        };
+        let first = insert(("id", 1.into()), ("kind", "click".into()));
+        let second = insert(("kind", "view".into()), ("id", 2.into()));
+        cache.generate(&MySqlDialect, &first);
+        assert_same(
+            cache.generate(&MySqlDialect, &second),
+            MySqlDialect.generate(&second),
+        );
+        assert_eq!(cache.stats().hits, 3);
+    }
+
+    #[test]
+    fn test_value_dependent_shapes() {
+        let cache = SqlCache::new(1);
+        // MySQL binds array containment as one JSON array built from the values
+        for values in [vec![1, 2], vec![3, 4]] {
+            let query = Query::select()
+                .from("posts")
+                .filter(F::new("tags").array_contains(values))
+                .build();
+            assert_same(
+                cache.generate(&MySqlDialect, &query),
+                MySqlDialect.generate(&query),
+            );
+        }
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 0,
+                misses: 2,
+                len: 1
+            }
+        );
+
+        // A new shape evicts the least recently used one
+        cache.generate(&PostgresDialect, &find(1, "alice"));
+        assert_eq!(cache.stats().len, 1);
+        cache.generate(&PostgresDialect, &find(2, "bob"));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_shape_key() {
+        let key = |query: &Query| shape_key("postgres", IdentifierPolicy::AlwaysQuote, query);
+        assert_eq!(key(&find(1, "alice")), key(&find(2, "bob")));
+        assert_ne!(
+            key(&find(1, "alice")),
+            shape_key("mysql", IdentifierPolicy::AlwaysQuote, &find(1, "alice"))
+        );
+        assert_ne!(
+            key(&find(1, "alice")),
+            shape_key("postgres", IdentifierPolicy::FoldLower, &find(1, "alice"))
+        );
+
+        // Bound values only keep their type; booleans are rendered by some
+        // dialects and stay whole
+        let active = |value: Value| {
+            Query::select()
+                .from("users")
+                .filter(Expr::eq("active", value))
+                .build()
+        };
+        assert_ne!(key(&active(1i64.into())), key(&active("1".into())));
+        assert_ne!(key(&active(true.into())), key(&active(false.into())));
+
+        // Rows of a nested INSERT aren't bound by the walk, so they're hashed whole
+        let archive = |id: i64| {
+            let mut cte = Query::insert().table("archive").set("id", id).build();
+            cte.returning = vec!["id".to_string()];
+            Query::select().with_cte("moved", cte).from("moved").build()
+        };
+        assert_ne!(key(&archive(1)), key(&archive(2)));
+
+        // Names are delimited, so moving text between them changes the shape
+        let pick =
+            |table: &str, column: &str| Query::select().from(table).columns(&[column]).build();
+        assert_ne!(key(&pick("ab", "c")), key(&pick("a", "bc")));
+    }
+}
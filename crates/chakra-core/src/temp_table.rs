@@ -0,0 +1,72 @@
+//! Session-scoped staging tables
+//!
+//! A `TempTable` holds intermediate results of a multi-step job in a
+//! temporary table, so later steps can join against them:
+//!
+//! ```rust,ignore
+//! let totals = TempTable::stage(&executor, "paid_totals", paid_orders).await?;
+//! let big = executor
+//!     .fetch_all(&totals.select().filter(Expr::gt("total", 1000)).build())
+//!     .await?;
+//! totals.drop().await?;
+//! ```
+//!
+//! Temporary tables belong to one connection, so every step must run on the
+//! same one, e.g. inside a `Transaction` or on a single pooled connection.
+
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::query::{Query, QueryBuilder};
+use tracing::debug;
+
+/// A temporary table created by `stage`
+pub struct TempTable<'a> {
+    executor: &'a dyn Executor,
+    name: String,
+    dropped: bool,
+}
+
+impl<'a> TempTable<'a> {
+    /// Create temporary table `name` holding the rows of `query`
+    pub async fn stage(
+        executor: &'a dyn Executor,
+        name: impl Into<String>,
+        query: Query,
+    ) -> Result<TempTable<'a>> {
+        let name = name.into();
+        let create = Query::insert_into_temp(name.clone(), query).build();
+        let fragment = executor.generate(&create);
+        executor.execute(&fragment.sql, &fragment.params).await?;
+        Ok(Self {
+            executor,
+            name,
+            dropped: false,
+        })
+    }
+
+    /// The table's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Start a SELECT on the table
+    pub fn select(&self) -> QueryBuilder {
+        Query::select().from(self.name.clone())
+    }
+
+    /// Drop the table now rather than when the session ends
+    pub async fn drop(mut self) -> Result<()> {
+        self.dropped = true;
+        let sql = self.executor.dialect().drop_temp_table(&self.name);
+        self.executor.execute(&sql, &[]).await?;
+        Ok(())
+    }
+}
+
+impl Drop for TempTable<'_> {
+    fn drop(&mut self) {
+        if !self.dropped {
+            debug!("Temporary table {} kept until the session ends", self.name);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Discriminator-column multi-tenancy
+//!
+//! Models marked `#[chakra(tenant_scoped)]` keep every tenant's rows in one
+//! table, told apart by a `tenant_id` column. Inside `TenantContext::scope`,
+//! querysets and `Crud` methods on those models add the tenant for you:
+//!
+//! ```rust,ignore
+//! TenantContext::scope(42, async {
+//!     // SELECT ... FROM projects WHERE tenant_id = 42
+//!     let projects = Project::objects().all(&executor).await?;
+//!     // INSERT INTO projects (..., tenant_id) VALUES (..., 42)
+//!     project.save(&executor).await?;
+//!     // Reports across tenants opt out explicitly
+//!     let total = Project::objects().all_tenants().count(&executor).await?;
+//! })
+//! .await
+//! ```
+//!
+//! The tenant is read when a statement runs, so querysets built outside the
+//! scope are filtered too. Outside any scope nothing is added. Hand-built
+//! `Query`s are never rewritten.
+
+use crate::expr::Expr;
+use crate::model::Model;
+use crate::types::Value;
+use std::future::Future;
+
+tokio::task_local! {
+    static TENANT: Value;
+}
+
+/// The tenant of the current task
+pub struct TenantContext;
+
+impl TenantContext {
+    /// Run `future` with `tenant` as the current tenant
+    pub async fn scope<F: Future>(tenant: impl Into<Value>, future: F) -> F::Output {
+        TENANT.scope(tenant.into(), future).await
+    }
+
+    /// Run `f` with `tenant` as the current tenant, for synchronous code
+    pub fn sync_scope<R>(tenant: impl Into<Value>, f: impl FnOnce() -> R) -> R {
+        TENANT.sync_scope(tenant.into(), f)
+    }
+
+    /// The current tenant, if inside a scope
+    pub fn current() -> Option<Value> {
+        TENANT.try_with(Value::clone).ok()
+    }
+}
+
+/// The tenant column of `M` and the current tenant, if both are set
+pub fn tenant_value<M: Model>() -> Option<(&'static str, Value)> {
+    let column = M::tenant_column()?;
+    Some((column, TenantContext::current()?))
+}
+
+/// `table.tenant_column = current tenant` for `M`, if both are set
+///
+/// The column is qualified so joined tables with their own tenant column
+/// don't make it ambiguous.
+pub fn tenant_filter<M: Model>() -> Option<Expr> {
+    tenant_value::<M>()
+        .map(|(column, tenant)| Expr::eq(format!("{}.{}", M::table_name(), column), tenant))
+}
@@ -0,0 +1,728 @@
+//! Transactions and savepoints
+//!
+//! `Transaction` wraps an `Executor` in BEGIN/COMMIT. A pooled executor
+//! would run each statement on whichever connection comes next, so the
+//! transaction holds one from `Executor::pin` until it ends. Calling
+//! `transaction()` on an open transaction starts a savepoint instead, so code
+//! that opens its own transaction can run inside a caller's without
+//! committing the caller's work early; `Executor::begin_transaction` picks
+//! whichever fits.
+//!
+//! `transaction()` runs a closure in a transaction, committing if it returns
+//! `Ok` and rolling back otherwise. It can pick the isolation level and run
+//! the closure again when the database aborts it for conflicting with a
+//! concurrent transaction:
+//!
+//! ```rust,ignore
+//! let options = TransactionOptions::new()
+//!     .isolation(IsolationLevel::Serializable)
+//!     .retry(TransactionRetry::new().max_attempts(5));
+//! let total = executor.transaction(&options, |tx| async move {
+//!     let mut account = Account::get(tx, id).await?;
+//!     account.balance -= amount;
+//!     account.save(tx).await?;
+//!     Ok(account.balance)
+//! }.boxed()).await?;
+//! ```
+//!
+//! The adapters' `transaction` methods hold one pooled connection for the
+//! whole closure. A retried closure runs from the start, so it shouldn't have
+//! effects outside the database it can't repeat.
+//!
+//! For debugging, `debug_statements()` runs every statement of a transaction
+//! in its own savepoint. A failing statement is rolled back alone and
+//! recorded with its SQL, parameters and the server's error, and the
+//! transaction stays usable even on PostgreSQL, which otherwise aborts it:
+//!
+//! ```rust,ignore
+//! let tx = Transaction::begin(&executor).await?.debug_statements();
+//! if let Err(e) = import(&tx).await {
+//!     for failure in tx.failed_statements() {
+//!         eprintln!("{}", failure);
+//!     }
+//!     return Err(e);
+//! }
+//! ```
+//!
+//! Each statement costs two extra round trips, so this is meant for
+//! development, not production.
+
+use crate::error::{ChakraError, Result};
+use crate::executor::{buffered_stream, ExecResult, Executor};
+use crate::query::Query;
+use crate::result::Row;
+use crate::sql::{Dialect, SqlFragment};
+use crate::sql_cache::SqlCache;
+use crate::types::Value;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use std::fmt;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Savepoint wrapping each statement under `debug_statements`
+const STATEMENT_SAVEPOINT: &str = "chakra_stmt";
+
+/// Statements that control the transaction itself and are never wrapped
+const CONTROL_VERBS: &[&str] = &[
+    "BEGIN",
+    "START",
+    "COMMIT",
+    "END",
+    "ROLLBACK",
+    "SAVEPOINT",
+    "RELEASE",
+];
+
+/// A savepoint statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavepointOp {
+    /// `SAVEPOINT name`
+    Begin,
+    /// `RELEASE SAVEPOINT name`
+    Release,
+    /// `ROLLBACK TO SAVEPOINT name`
+    RollbackTo,
+}
+
+impl SavepointOp {
+    /// SQL for this statement on savepoint `name`
+    ///
+    /// The syntax is the same on PostgreSQL, MySQL and SQLite. Names must be
+    /// plain identifiers, so they are never quoted.
+    pub fn sql(&self, name: &str) -> Result<String> {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(ChakraError::config(format!(
+                "Invalid savepoint name '{}'",
+                name
+            )));
+        }
+        Ok(match self {
+            SavepointOp::Begin => format!("SAVEPOINT {}", name),
+            SavepointOp::Release => format!("RELEASE SAVEPOINT {}", name),
+            SavepointOp::RollbackTo => format!("ROLLBACK TO SAVEPOINT {}", name),
+        })
+    }
+}
+
+/// Transaction isolation level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// The level as written after `ISOLATION LEVEL`
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// Retrying a transaction closure after a serialization failure or deadlock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionRetry {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled after each
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for TransactionRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl TransactionRetry {
+    /// Create retry settings with default backoff
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the total number of attempts
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the wait before the first retry
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the longest wait between attempts
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Wait before retry number `retry`, counting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// How `transaction()` runs its closure
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Isolation level, or the database's default
+    pub isolation: Option<IsolationLevel>,
+    /// Retries after conflicts; none by default
+    pub retry: Option<TransactionRetry>,
+}
+
+impl TransactionOptions {
+    /// Default isolation, no retries
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run at isolation level `level`
+    pub fn isolation(mut self, level: IsolationLevel) -> Self {
+        self.isolation = Some(level);
+        self
+    }
+
+    /// Run the closure again after a serialization failure or deadlock
+    pub fn retry(mut self, retry: TransactionRetry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// A statement that failed under `debug_statements`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedStatement {
+    /// Position of the statement in the transaction, starting at 1
+    pub index: usize,
+    pub sql: String,
+    pub params: Vec<Value>,
+    /// The error reported for the statement
+    pub error: String,
+}
+
+impl FailedStatement {
+    /// The statement pretty-printed with its parameters inlined
+    pub fn debug_sql(&self) -> String {
+        let mut fragment = SqlFragment::from_sql(self.sql.clone());
+        fragment.params = self.params.clone();
+        fragment.debug_sql()
+    }
+}
+
+impl fmt::Display for FailedStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Statement {} failed: {}", self.index, self.error)?;
+        write!(f, "{}", self.debug_sql())
+    }
+}
+
+/// Statement count and failures shared by a debugged transaction and the
+/// transactions nested in it
+#[derive(Debug, Default)]
+struct StatementLog {
+    statements: AtomicUsize,
+    failures: Mutex<Vec<FailedStatement>>,
+}
+
+/// The connection a transaction's statements run on
+#[derive(Clone)]
+enum Connection<'a> {
+    /// An executor that runs every statement on one connection
+    Bound(&'a dyn Executor),
+    /// A connection checked out of a pool for the transaction
+    Pinned(Arc<dyn Executor + 'a>),
+}
+
+impl<'a> Deref for Connection<'a> {
+    type Target = dyn Executor + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Connection::Bound(executor) => *executor,
+            Connection::Pinned(executor) => executor.as_ref(),
+        }
+    }
+}
+
+/// An open transaction, or a savepoint within one
+///
+/// Dropping it without `commit` or `rollback` leaves the statements it ran
+/// to be rolled back by the database when the outer transaction ends, or
+/// when the pool resets a connection the transaction held.
+pub struct Transaction<'a> {
+    executor: Connection<'a>,
+    /// 0 for the outermost transaction, otherwise the savepoint level
+    depth: usize,
+    finished: bool,
+    /// Set by `debug_statements`
+    log: Option<Arc<StatementLog>>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Begin a transaction on `executor`
+    pub async fn begin(executor: &'a dyn Executor) -> Result<Transaction<'a>> {
+        Self::begin_with(executor, None).await
+    }
+
+    /// Begin a transaction on `executor` at isolation level `isolation`, or
+    /// the database's default
+    ///
+    /// A pooled executor's connection is held by the transaction, and its
+    /// nested transactions, until they are all dropped.
+    pub async fn begin_with(
+        executor: &'a dyn Executor,
+        isolation: Option<IsolationLevel>,
+    ) -> Result<Transaction<'a>> {
+        let executor = match executor.pin().await? {
+            Some(conn) => Connection::Pinned(Arc::from(conn)),
+            None => Connection::Bound(executor),
+        };
+        for sql in executor.dialect().begin_transaction(isolation) {
+            executor.execute(&sql, &[]).await?;
+        }
+        Ok(Self {
+            executor,
+            depth: 0,
+            finished: false,
+            log: None,
+        })
+    }
+
+    /// Run each following statement in its own savepoint, rolling back and
+    /// recording just the statement on failure
+    ///
+    /// Nested transactions started afterwards share the mode and the record
+    /// of failures. Errors are returned unchanged.
+    pub fn debug_statements(mut self) -> Self {
+        self.log.get_or_insert_with(Default::default);
+        self
+    }
+
+    /// Statements that failed under `debug_statements`, oldest first
+    pub fn failed_statements(&self) -> Vec<FailedStatement> {
+        match &self.log {
+            Some(log) => log
+                .failures
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Start a nested transaction, backed by a savepoint
+    pub async fn transaction(&self) -> Result<Transaction<'a>> {
+        let depth = self.depth + 1;
+        self.savepoint(&savepoint_name(depth)).await?;
+        Ok(Self {
+            executor: self.executor.clone(),
+            depth,
+            finished: false,
+            log: self.log.clone(),
+        })
+    }
+
+    /// Savepoint level: 0 for the outermost transaction
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Create a savepoint named `name`
+    pub async fn savepoint(&self, name: &str) -> Result<()> {
+        self.run(SavepointOp::Begin, name).await
+    }
+
+    /// Release savepoint `name`, keeping its changes
+    pub async fn release_savepoint(&self, name: &str) -> Result<()> {
+        self.run(SavepointOp::Release, name).await
+    }
+
+    /// Undo everything since savepoint `name`, which stays open
+    pub async fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        self.run(SavepointOp::RollbackTo, name).await
+    }
+
+    /// Check the deferrable constraints `names`, or all of them if empty,
+    /// only at commit, e.g. to insert rows that reference each other
+    ///
+    /// SQLite defers all foreign keys and takes no names; MySQL can't defer
+    /// constraints.
+    pub async fn set_constraints_deferred(&self, names: &[&str]) -> Result<()> {
+        let sql = self.executor.dialect().set_constraints(names, true)?;
+        self.executor.execute(&sql, &[]).await?;
+        Ok(())
+    }
+
+    /// Check constraints `names`, or all of them if empty, after each
+    /// statement again, failing now if they are already violated
+    pub async fn set_constraints_immediate(&self, names: &[&str]) -> Result<()> {
+        let sql = self.executor.dialect().set_constraints(names, false)?;
+        self.executor.execute(&sql, &[]).await?;
+        Ok(())
+    }
+
+    /// Commit the transaction, or release a nested transaction's savepoint
+    pub async fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        if self.depth == 0 {
+            self.executor.execute("COMMIT", &[]).await?;
+            Ok(())
+        } else {
+            self.release_savepoint(&savepoint_name(self.depth)).await
+        }
+    }
+
+    /// Roll back the transaction, or a nested transaction to its savepoint
+    pub async fn rollback(mut self) -> Result<()> {
+        self.finished = true;
+        if self.depth == 0 {
+            self.executor.execute("ROLLBACK", &[]).await?;
+            Ok(())
+        } else {
+            let name = savepoint_name(self.depth);
+            self.rollback_to_savepoint(&name).await?;
+            self.release_savepoint(&name).await
+        }
+    }
+
+    async fn run(&self, op: SavepointOp, name: &str) -> Result<()> {
+        self.executor.execute(&op.sql(name)?, &[]).await?;
+        Ok(())
+    }
+
+    /// Run `statement` inside the statement savepoint if `debug_statements`
+    /// is on, recording it if it fails
+    async fn isolate<T, F>(&self, sql: &str, params: &[Value], statement: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let Some(log) = self.log.as_ref().filter(|_| !is_control(sql)) else {
+            return statement.await;
+        };
+        let index = log.statements.fetch_add(1, Ordering::Relaxed) + 1;
+        self.run(SavepointOp::Begin, STATEMENT_SAVEPOINT).await?;
+        match statement.await {
+            Ok(result) => {
+                self.run(SavepointOp::Release, STATEMENT_SAVEPOINT).await?;
+                Ok(result)
+            }
+            Err(e) => {
+                let failure = FailedStatement {
+                    index,
+                    sql: sql.to_string(),
+                    params: params.to_vec(),
+                    error: e.to_string(),
+                };
+                warn!("{}", failure);
+                log.failures
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(failure);
+                self.run(SavepointOp::RollbackTo, STATEMENT_SAVEPOINT)
+                    .await?;
+                self.run(SavepointOp::Release, STATEMENT_SAVEPOINT).await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            debug!(
+                "Transaction at depth {} dropped without commit, will be rolled back",
+                self.depth
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Executor for Transaction<'_> {
+    fn dialect(&self) -> &dyn Dialect {
+        self.executor.dialect()
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.isolate(sql, params, self.executor.query(sql, params))
+            .await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.isolate(sql, params, self.executor.execute(sql, params))
+            .await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        self.isolate(sql, params, self.executor.execute_with_id(sql, params))
+            .await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        if self.log.is_none() {
+            return self.executor.execute_many(sql, params).await;
+        }
+        // Each execution is isolated in its own statement savepoint
+        let mut affected = 0;
+        for params in params {
+            affected += self.execute(sql, params).await?;
+        }
+        Ok(affected)
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        // MySQL emulates RETURNING in the executor, with statements that
+        // can't be isolated one at a time
+        if self.log.is_none() {
+            return self.executor.execute_returning(query).await;
+        }
+        self.check_read_only(query)?;
+        let fragment = self.generate(query);
+        if query.returning.is_empty() {
+            self.execute(&fragment.sql, &fragment.params).await?;
+            return Ok(Vec::new());
+        }
+        self.query(&fragment.sql, &fragment.params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        // Statement savepoints need the whole result before releasing
+        if self.log.is_some() && !is_control(&sql) {
+            return buffered_stream(self, sql, params);
+        }
+        self.executor.stream(sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.executor.max_rows_guard()
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.executor.sql_cache()
+    }
+
+    fn read_only(&self) -> bool {
+        self.executor.read_only()
+    }
+
+    async fn begin_transaction(&self) -> Result<Transaction<'_>> {
+        self.transaction().await
+    }
+}
+
+/// Run `f` in a transaction on `executor`, committing if it returns `Ok`
+/// and rolling back otherwise
+///
+/// With `options.retry`, a transaction that fails with a serialization
+/// failure or deadlock, in the closure or at commit, is rolled back and run
+/// again after a backoff. The last error is returned once attempts run out.
+///
+/// Each attempt runs on one connection, pinned as `Transaction::begin`
+/// does; the adapters' `transaction` methods hold the same one for every
+/// attempt.
+pub async fn transaction<T, F>(
+    executor: &dyn Executor,
+    options: &TransactionOptions,
+    mut f: F,
+) -> Result<T>
+where
+    F: for<'c, 't> FnMut(&'t Transaction<'c>) -> BoxFuture<'t, Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let tx = Transaction::begin_with(executor, options.isolation).await?;
+        let result = match f(&tx).await {
+            Ok(value) => tx.commit().await.map(|()| value),
+            Err(e) => {
+                if let Err(rollback) = tx.rollback().await {
+                    warn!("Failed to roll back transaction: {}", rollback);
+                }
+                Err(e)
+            }
+        };
+        match (result, options.retry) {
+            (Err(e), Some(retry))
+                if e.is_transaction_conflict() && attempt < retry.max_attempts =>
+            {
+                let backoff = retry.backoff(attempt);
+                warn!(
+                    "Transaction attempt {} of {} failed: {}; retrying in {:?}",
+                    attempt, retry.max_attempts, e, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+fn savepoint_name(depth: usize) -> String {
+    format!("chakra_sp_{}", depth)
+}
+
+/// Whether `sql` begins, ends or manages a transaction or savepoint
+fn is_control(sql: &str) -> bool {
+    sql.split_whitespace()
+        .next()
+        .is_some_and(|verb| CONTROL_VERBS.iter().any(|v| v.eq_ignore_ascii_case(verb)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::PostgresDialect;
+
+    /// Runs each statement on the next of two connections, like a pool,
+    /// recording which one it ran on
+    #[derive(Default)]
+    struct Pool {
+        next: AtomicUsize,
+        log: Mutex<Vec<(usize, String)>>,
+    }
+
+    impl Pool {
+        fn run(&self, conn: usize, sql: &str) {
+            self.log.lock().unwrap().push((conn, sql.to_string()));
+        }
+    }
+
+    #[async_trait]
+    impl Executor for Pool {
+        fn dialect(&self) -> &dyn Dialect {
+            &PostgresDialect
+        }
+
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            self.run(self.next.fetch_add(1, Ordering::Relaxed) % 2, sql);
+            Ok(Vec::new())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64> {
+            self.run(self.next.fetch_add(1, Ordering::Relaxed) % 2, sql);
+            Ok(1)
+        }
+
+        async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+            let conn = self.next.fetch_add(1, Ordering::Relaxed) % 2;
+            Ok(Some(Box::new(Pinned { pool: self, conn })))
+        }
+    }
+
+    /// One of the pool's connections
+    struct Pinned<'a> {
+        pool: &'a Pool,
+        conn: usize,
+    }
+
+    #[async_trait]
+    impl Executor for Pinned<'_> {
+        fn dialect(&self) -> &dyn Dialect {
+            &PostgresDialect
+        }
+
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>> {
+            self.pool.run(self.conn, sql);
+            Ok(Vec::new())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64> {
+            self.pool.run(self.conn, sql);
+            Ok(1)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_pins_connection() {
+        let pool = Pool::default();
+        let tx = Transaction::begin(&pool).await.unwrap();
+        tx.execute("INSERT INTO t VALUES (1)", &[]).await.unwrap();
+        let nested = tx.begin_transaction().await.unwrap();
+        assert_eq!(nested.depth(), 1);
+        nested.query("SELECT * FROM t", &[]).await.unwrap();
+        nested.commit().await.unwrap();
+        tx.execute("INSERT INTO t VALUES (2)", &[]).await.unwrap();
+        tx.rollback().await.unwrap();
+
+        let log = pool.log.lock().unwrap().clone();
+        let conns: Vec<_> = log.iter().map(|(conn, _)| *conn).collect();
+        assert_eq!(conns, vec![conns[0]; 7]);
+        let sql: Vec<_> = log.iter().map(|(_, sql)| sql.as_str()).collect();
+        assert_eq!(
+            sql,
+            [
+                "BEGIN",
+                "INSERT INTO t VALUES (1)",
+                "SAVEPOINT chakra_sp_1",
+                "SELECT * FROM t",
+                "RELEASE SAVEPOINT chakra_sp_1",
+                "INSERT INTO t VALUES (2)",
+                "ROLLBACK",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_savepoint_sql() {
+        assert_eq!(
+            SavepointOp::Begin.sql("before_import").unwrap(),
+            "SAVEPOINT before_import"
+        );
+        assert_eq!(
+            SavepointOp::RollbackTo.sql("sp_1").unwrap(),
+            "ROLLBACK TO SAVEPOINT sp_1"
+        );
+        assert!(SavepointOp::Release.sql("x; DROP TABLE users").is_err());
+        assert!(SavepointOp::Begin.sql("1st").is_err());
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let retry = TransactionRetry::new()
+            .initial_backoff(Duration::from_millis(10))
+            .max_backoff(Duration::from_millis(30));
+        assert_eq!(retry.backoff(1), Duration::from_millis(10));
+        assert_eq!(retry.backoff(2), Duration::from_millis(20));
+        assert_eq!(retry.backoff(3), Duration::from_millis(30));
+        assert_eq!(TransactionRetry::new().max_attempts(0).max_attempts, 1);
+    }
+
+    #[test]
+    fn test_control_statements() {
+        assert!(is_control("ROLLBACK TO SAVEPOINT chakra_sp_1"));
+        assert!(is_control("  commit"));
+        assert!(!is_control("INSERT INTO savepoints VALUES (1)"));
+        assert!(!is_control(""));
+    }
+}
@@ -4,10 +4,14 @@
 //! - `Value` - Runtime representation of database values
 //! - `FieldType` - Schema-level field type definitions
 
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use crate::net::{Cidr, MacAddr};
+use crate::range::Range;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::ops::Bound;
 use uuid::Uuid;
 
 /// Runtime representation of a database value
@@ -38,6 +42,14 @@ pub enum Value {
     Date(NaiveDate),
     /// Time only
     Time(NaiveTime),
+    /// IP address
+    IpAddr(IpAddr),
+    /// IP network
+    Cidr(Cidr),
+    /// MAC address
+    MacAddr(MacAddr),
+    /// Range of values
+    Range(Box<Range<Value>>),
     /// JSON value
     Json(serde_json::Value),
     /// Array of values
@@ -117,10 +129,52 @@ impl Value {
             Value::DateTime(_) => "datetime",
             Value::Date(_) => "date",
             Value::Time(_) => "time",
+            Value::IpAddr(_) => "ip_addr",
+            Value::Cidr(_) => "cidr",
+            Value::MacAddr(_) => "mac_addr",
+            Value::Range(_) => "range",
             Value::Json(_) => "json",
             Value::Array(_) => "array",
         }
     }
+
+    /// Render this value as a dialect-neutral SQL literal
+    ///
+    /// Intended for logs and for comparing or keying values. SQL that will
+    /// run on a database should use `Dialect::literal`, which follows that
+    /// database's escaping and literal syntax.
+    pub fn to_sql_literal(&self) -> String {
+        fn quote(s: &str) -> String {
+            format!("'{}'", s.replace('\'', "''"))
+        }
+
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+            Value::Int32(i) => i.to_string(),
+            Value::Int64(i) => i.to_string(),
+            Value::Float64(f) => f.to_string(),
+            Value::Decimal(d) => d.to_string(),
+            Value::String(s) => quote(s),
+            Value::Bytes(b) => {
+                let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("X'{}'", hex)
+            }
+            Value::Uuid(u) => quote(&u.to_string()),
+            Value::DateTime(dt) => quote(&dt.to_rfc3339()),
+            Value::Date(d) => quote(&d.to_string()),
+            Value::Time(t) => quote(&t.to_string()),
+            Value::IpAddr(a) => quote(&a.to_string()),
+            Value::Cidr(c) => quote(&c.to_string()),
+            Value::MacAddr(m) => quote(&m.to_string()),
+            Value::Range(r) => quote(&r.to_string()),
+            Value::Json(j) => quote(&j.to_string()),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_sql_literal()).collect();
+                format!("ARRAY[{}]", items.join(", "))
+            }
+        }
+    }
 }
 
 // Implement From for common types
@@ -172,6 +226,49 @@ impl From<DateTime<Utc>> for Value {
     }
 }
 
+/// Timestamps without a time zone are stored as UTC
+impl From<NaiveDateTime> for Value {
+    fn from(v: NaiveDateTime) -> Self {
+        Value::DateTime(v.and_utc())
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(v: NaiveDate) -> Self {
+        Value::Date(v)
+    }
+}
+
+impl From<NaiveTime> for Value {
+    fn from(v: NaiveTime) -> Self {
+        Value::Time(v)
+    }
+}
+
+impl From<IpAddr> for Value {
+    fn from(v: IpAddr) -> Self {
+        Value::IpAddr(v)
+    }
+}
+
+impl From<Cidr> for Value {
+    fn from(v: Cidr) -> Self {
+        Value::Cidr(v)
+    }
+}
+
+impl From<MacAddr> for Value {
+    fn from(v: MacAddr) -> Self {
+        Value::MacAddr(v)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(v: Decimal) -> Self {
+        Value::Decimal(v)
+    }
+}
+
 impl From<serde_json::Value> for Value {
     fn from(v: serde_json::Value) -> Self {
         Value::Json(v)
@@ -193,6 +290,70 @@ impl<T: Into<Value>> From<Vec<T>> for Value {
     }
 }
 
+/// Trait for converting to Value, the counterpart of `FromValue`
+///
+/// Every `Clone` type with `Into<Value>` has it. Implement it, with
+/// `FromValue`, to store an application type such as `UserId(Uuid)` in a
+/// model field:
+///
+/// ```rust,ignore
+/// impl ToValue for UserId {
+///     fn to_value(&self) -> Value {
+///         Value::Uuid(self.0)
+///     }
+/// }
+///
+/// impl FromValue for UserId {
+///     fn from_value(value: &Value) -> Result<Self> {
+///         Uuid::from_value(value).map(UserId)
+///     }
+/// }
+/// ```
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl<T: Clone + Into<Value>> ToValue for T {
+    fn to_value(&self) -> Value {
+        self.clone().into()
+    }
+}
+
+/// A Rust enum stored as a database enum, usually from `#[derive(DbEnum)]`
+///
+/// Values are the variants' labels, `snake_case` unless renamed:
+///
+/// ```rust,ignore
+/// #[derive(DbEnum, Debug, Clone, PartialEq)]
+/// enum OrderStatus {
+///     Pending,
+///     Shipped,
+///     #[chakra(rename = "cancelled_by_user")]
+///     Cancelled,
+/// }
+///
+/// #[derive(Model)]
+/// struct Order {
+///     #[chakra(primary_key)]
+///     id: i64,
+///     #[chakra(db_enum = "order_status")]
+///     status: OrderStatus,
+/// }
+/// ```
+///
+/// PostgreSQL gets `CREATE TYPE order_status AS ENUM (...)`, MySQL an
+/// inline `ENUM(...)` and SQLite a `TEXT` column with a CHECK on the labels.
+pub trait DbEnum: Sized {
+    /// Every variant's label, in declaration order
+    const VARIANTS: &'static [&'static str];
+
+    /// This variant's label
+    fn label(&self) -> &'static str;
+
+    /// The variant with `label`
+    fn from_label(label: &str) -> Option<Self>;
+}
+
 /// Schema-level field type definition
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -233,10 +394,24 @@ pub enum FieldType {
     Json,
     /// JSONB (PostgreSQL)
     JsonB,
+    /// IP address, optionally with a network prefix
+    Inet,
+    /// IP network
+    Cidr,
+    /// MAC address
+    MacAddr,
     /// Array of another type
     Array { element_type: Box<FieldType> },
-    /// Enum with possible values
-    Enum { values: Vec<String> },
+    /// Range of integers, decimals, dates or timestamps
+    Range { element_type: Box<FieldType> },
+    /// Enum with possible values, a named database type on PostgreSQL
+    Enum {
+        values: Vec<String>,
+        /// Type name, e.g. `order_status`; without one the enum is stored
+        /// as a string
+        #[serde(default)]
+        name: Option<String>,
+    },
 }
 
 impl FieldType {
@@ -264,6 +439,67 @@ impl FieldType {
         }
     }
 
+    /// Create a range field
+    pub fn range(element_type: FieldType) -> Self {
+        FieldType::Range {
+            element_type: Box::new(element_type),
+        }
+    }
+
+    /// Create an enum field stored as the database type `name`
+    pub fn db_enum<E: DbEnum>(name: impl Into<String>) -> Self {
+        FieldType::Enum {
+            values: E::VARIANTS.iter().map(|v| v.to_string()).collect(),
+            name: Some(name.into()),
+        }
+    }
+
+    /// Whether `value` can be bound to a column of this type without a cast
+    ///
+    /// Types match by family, so an `Int32` fits a `BIGINT` column and any
+    /// number fits a decimal one. NULL fits every type, JSON columns take
+    /// any value and text columns any scalar.
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (_, Value::Null) => true,
+            (FieldType::Json | FieldType::JsonB, _) => true,
+            (FieldType::Boolean, Value::Bool(_)) => true,
+            (
+                FieldType::SmallInt | FieldType::Integer | FieldType::BigInt,
+                Value::Int32(_) | Value::Int64(_),
+            ) => true,
+            (
+                FieldType::Float | FieldType::Double | FieldType::Decimal { .. },
+                Value::Int32(_) | Value::Int64(_) | Value::Float64(_) | Value::Decimal(_),
+            ) => true,
+            (FieldType::Enum { .. }, Value::String(_)) => true,
+            // Custom value types without a known column type are stored as
+            // text, whatever value they convert to
+            (FieldType::String { .. } | FieldType::Char { .. } | FieldType::Text, _) => {
+                !matches!(value, Value::Bytes(_) | Value::Array(_) | Value::Range(_))
+            }
+            (FieldType::Binary { .. }, Value::Bytes(_)) => true,
+            (FieldType::Uuid, Value::Uuid(_)) => true,
+            (FieldType::Date, Value::Date(_)) => true,
+            (FieldType::Time, Value::Time(_)) => true,
+            (FieldType::Timestamp | FieldType::TimestampTz, Value::DateTime(_)) => true,
+            (FieldType::Inet | FieldType::Cidr, Value::IpAddr(_) | Value::Cidr(_)) => true,
+            (FieldType::MacAddr, Value::MacAddr(_)) => true,
+            (FieldType::Array { element_type }, Value::Array(items)) => {
+                items.iter().all(|item| element_type.accepts(item))
+            }
+            (FieldType::Range { element_type }, Value::Range(range)) => {
+                [range.lower(), range.upper()]
+                    .into_iter()
+                    .all(|bound| match bound {
+                        Bound::Included(v) | Bound::Excluded(v) => element_type.accepts(v),
+                        Bound::Unbounded => true,
+                    })
+            }
+            _ => false,
+        }
+    }
+
     /// Get the SQL type name for PostgreSQL
     pub fn to_postgres_type(&self) -> String {
         match self {
@@ -276,7 +512,9 @@ impl FieldType {
             FieldType::Decimal { precision, scale } => {
                 format!("NUMERIC({}, {})", precision, scale)
             }
-            FieldType::String { max_length: Some(n) } => format!("VARCHAR({})", n),
+            FieldType::String {
+                max_length: Some(n),
+            } => format!("VARCHAR({})", n),
             FieldType::String { max_length: None } => "VARCHAR".to_string(),
             FieldType::Char { length } => format!("CHAR({})", length),
             FieldType::Text => "TEXT".to_string(),
@@ -288,10 +526,24 @@ impl FieldType {
             FieldType::TimestampTz => "TIMESTAMPTZ".to_string(),
             FieldType::Json => "JSON".to_string(),
             FieldType::JsonB => "JSONB".to_string(),
+            FieldType::Inet => "INET".to_string(),
+            FieldType::Cidr => "CIDR".to_string(),
+            FieldType::MacAddr => "MACADDR".to_string(),
             FieldType::Array { element_type } => {
                 format!("{}[]", element_type.to_postgres_type())
             }
-            FieldType::Enum { .. } => "VARCHAR(255)".to_string(), // Simplified for now
+            FieldType::Range { element_type } => match **element_type {
+                FieldType::SmallInt | FieldType::Integer => "INT4RANGE".to_string(),
+                FieldType::BigInt => "INT8RANGE".to_string(),
+                FieldType::Timestamp => "TSRANGE".to_string(),
+                FieldType::TimestampTz => "TSTZRANGE".to_string(),
+                FieldType::Date => "DATERANGE".to_string(),
+                _ => "NUMRANGE".to_string(),
+            },
+            FieldType::Enum {
+                name: Some(name), ..
+            } => name.clone(),
+            FieldType::Enum { name: None, .. } => "VARCHAR(255)".to_string(),
         }
     }
 
@@ -307,20 +559,35 @@ impl FieldType {
             FieldType::Decimal { precision, scale } => {
                 format!("DECIMAL({}, {})", precision, scale)
             }
-            FieldType::String { max_length: Some(n) } => format!("VARCHAR({})", n),
+            FieldType::String {
+                max_length: Some(n),
+            } => format!("VARCHAR({})", n),
             FieldType::String { max_length: None } => "VARCHAR(255)".to_string(),
             FieldType::Char { length } => format!("CHAR({})", length),
             FieldType::Text => "TEXT".to_string(),
-            FieldType::Binary { max_length: Some(n) } => format!("VARBINARY({})", n),
+            FieldType::Binary {
+                max_length: Some(n),
+            } => format!("VARBINARY({})", n),
             FieldType::Binary { max_length: None } => "BLOB".to_string(),
             FieldType::Uuid => "CHAR(36)".to_string(),
             FieldType::Date => "DATE".to_string(),
             FieldType::Time => "TIME".to_string(),
             FieldType::Timestamp | FieldType::TimestampTz => "DATETIME".to_string(),
             FieldType::Json | FieldType::JsonB => "JSON".to_string(),
+            // Longest text forms, an IPv6 address with a prefix and a MAC address
+            FieldType::Inet | FieldType::Cidr => "VARCHAR(43)".to_string(),
+            FieldType::MacAddr => "CHAR(17)".to_string(),
             FieldType::Array { .. } => "JSON".to_string(), // MySQL doesn't have native arrays
-            FieldType::Enum { values } => {
-                format!("ENUM({})", values.iter().map(|v| format!("'{}'", v)).collect::<Vec<_>>().join(", "))
+            FieldType::Range { .. } => "VARCHAR(255)".to_string(),
+            FieldType::Enum { values, .. } => {
+                format!(
+                    "ENUM({})",
+                    values
+                        .iter()
+                        .map(|v| format!("'{}'", v))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
             }
         }
     }
@@ -331,14 +598,18 @@ impl FieldType {
             FieldType::Boolean => "INTEGER".to_string(),
             FieldType::SmallInt | FieldType::Integer | FieldType::BigInt => "INTEGER".to_string(),
             FieldType::Float | FieldType::Double | FieldType::Decimal { .. } => "REAL".to_string(),
-            FieldType::String { .. } | FieldType::Char { .. } | FieldType::Text => "TEXT".to_string(),
+            FieldType::String { .. } | FieldType::Char { .. } | FieldType::Text => {
+                "TEXT".to_string()
+            }
             FieldType::Binary { .. } => "BLOB".to_string(),
             FieldType::Uuid => "TEXT".to_string(),
             FieldType::Date | FieldType::Time | FieldType::Timestamp | FieldType::TimestampTz => {
                 "TEXT".to_string()
             }
             FieldType::Json | FieldType::JsonB => "TEXT".to_string(),
+            FieldType::Inet | FieldType::Cidr | FieldType::MacAddr => "TEXT".to_string(),
             FieldType::Array { .. } => "TEXT".to_string(), // Store as JSON
+            FieldType::Range { .. } => "TEXT".to_string(),
             FieldType::Enum { .. } => "TEXT".to_string(),
         }
     }
@@ -402,4 +673,32 @@ mod tests {
         let v: Value = Option::<i32>::None.into();
         assert!(v.is_null());
     }
+
+    #[test]
+    fn test_sql_literal() {
+        assert_eq!(Value::Null.to_sql_literal(), "NULL");
+        assert_eq!(Value::Int64(7).to_sql_literal(), "7");
+        assert_eq!(Value::from("O'Brien").to_sql_literal(), "'O''Brien'");
+        assert_eq!(Value::Bytes(vec![0xde, 0xad]).to_sql_literal(), "X'dead'");
+    }
+
+    #[test]
+    fn test_field_type_accepts() {
+        assert!(FieldType::BigInt.accepts(&Value::Int32(1)));
+        assert!(FieldType::decimal(10, 2).accepts(&Value::Float64(1.5)));
+        assert!(FieldType::Uuid.accepts(&Value::Null));
+        assert!(FieldType::Json.accepts(&Value::from("x")));
+        assert!(!FieldType::Integer.accepts(&Value::from("42")));
+        assert!(FieldType::Text.accepts(&Value::Int64(42)));
+        assert!(!FieldType::Text.accepts(&Value::Bytes(vec![1])));
+        assert!(!FieldType::TimestampTz.accepts(&Value::Date(NaiveDate::default())));
+
+        let ids = FieldType::array(FieldType::BigInt);
+        assert!(ids.accepts(&Value::Array(vec![Value::Int64(1), Value::Null])));
+        assert!(!ids.accepts(&Value::Array(vec![Value::Int64(1), "a".into()])));
+
+        let seats = FieldType::range(FieldType::Integer);
+        assert!(seats.accepts(&Value::from(crate::range::Range::from(1..5))));
+        assert!(!seats.accepts(&Value::from(crate::range::Range::from("a".."b"))));
+    }
 }
@@ -0,0 +1,194 @@
+//! Model validation
+//!
+//! Field checks declared on a model run before every insert and update, so
+//! bad values are reported per field instead of as a database error, or not
+//! at all:
+//!
+//! ```rust,ignore
+//! #[derive(Model)]
+//! struct User {
+//!     #[chakra(primary_key, auto_increment)]
+//!     id: i64,
+//!     #[chakra(validate(length(min = 1, max = 255)))]
+//!     name: String,
+//!     #[chakra(validate(email))]
+//!     email: Option<String>,
+//!     #[chakra(validate(range(min = 0, max = 150), custom = "not_reserved"))]
+//!     age: i32,
+//! }
+//!
+//! fn not_reserved(age: &i32) -> Result<(), String> { ... }
+//! ```
+//!
+//! `Model::validate` runs every check and returns all failures together as
+//! `ValidationError::Fields`; `save`, `insert`, `update`, `bulk_create` and
+//! `bulk_update` call it and write nothing if it fails. Checks on an
+//! `Option` field only run when it is `Some`.
+
+use crate::error::ValidationError;
+use std::fmt::Display;
+
+/// A value with a length, in characters for strings
+pub trait Length {
+    fn length(&self) -> usize;
+}
+
+impl Length for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl Length for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> Length for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Length for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Check that `value` has between `min` and `max` items or characters
+pub fn length<V: Length + ?Sized>(
+    field: &str,
+    value: &V,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Result<(), ValidationError> {
+    let actual_length = value.length();
+    if let Some(min_length) = min.filter(|min| actual_length < *min) {
+        return Err(ValidationError::TooShort {
+            field: field.to_string(),
+            min_length,
+            actual_length,
+        });
+    }
+    if let Some(max_length) = max.filter(|max| actual_length > *max) {
+        return Err(ValidationError::TooLong {
+            field: field.to_string(),
+            max_length,
+            actual_length,
+        });
+    }
+    Ok(())
+}
+
+/// Check that `value` looks like an email address: one `@` between a local
+/// part and a dotted domain, and no whitespace
+///
+/// Whether the address exists can only be checked by sending to it.
+pub fn email(field: &str, value: &str) -> Result<(), ValidationError> {
+    let valid = match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|label| !label.is_empty())
+                && !value.chars().any(char::is_whitespace)
+        }
+        None => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::InvalidFormat {
+            field: field.to_string(),
+            message: "not an email address".to_string(),
+        })
+    }
+}
+
+/// Check that `min <= value <= max`
+pub fn range<T: PartialOrd + Display>(
+    field: &str,
+    value: &T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), ValidationError> {
+    let message = match (min, max) {
+        (Some(min), _) if *value < min => format!("{} is less than {}", value, min),
+        (_, Some(max)) if *value > max => format!("{} is greater than {}", value, max),
+        _ => return Ok(()),
+    };
+    Err(ValidationError::OutOfRange {
+        field: field.to_string(),
+        message,
+    })
+}
+
+/// Run an application check returning its message on failure
+pub fn custom<T: ?Sized>(
+    field: &str,
+    value: &T,
+    check: impl FnOnce(&T) -> Result<(), String>,
+) -> Result<(), ValidationError> {
+    check(value).map_err(|message| ValidationError::FieldValidation {
+        field: field.to_string(),
+        message,
+    })
+}
+
+/// `Ok` if no check failed, otherwise every failure as `Fields`
+pub fn collect(errors: Vec<ValidationError>) -> Result<(), ValidationError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError::Fields { errors })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validators() {
+        assert!(length("name", "", Some(1), None).is_err());
+        assert!(length("name", "héllo", None, Some(5)).is_ok());
+        assert!(matches!(
+            length("tags", &vec![1, 2, 3], None, Some(2)),
+            Err(ValidationError::TooLong {
+                max_length: 2,
+                actual_length: 3,
+                ..
+            })
+        ));
+
+        for valid in ["a@b.co", "first.last+tag@mail.example.org"] {
+            assert!(email("email", valid).is_ok(), "{}", valid);
+        }
+        for invalid in [
+            "", "plain", "@b.co", "a@b", "a@b..co", "a@@b.co", "a b@c.de",
+        ] {
+            assert!(email("email", invalid).is_err(), "{}", invalid);
+        }
+
+        assert!(range("age", &5, Some(0), Some(150)).is_ok());
+        let err = range("age", &-1, Some(0), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Value out of range for field 'age': -1 is less than 0"
+        );
+        assert!(range("ratio", &1.5, None, Some(1.0)).is_err());
+
+        let err = custom("slug", "admin", |s: &str| match s {
+            "admin" => Err("is reserved".to_string()),
+            _ => Ok(()),
+        })
+        .unwrap_err();
+        assert_eq!(err.field(), Some("slug"));
+
+        assert!(collect(Vec::new()).is_ok());
+        let err = collect(vec![err]).unwrap_err();
+        assert_eq!(err.errors().len(), 1);
+    }
+}
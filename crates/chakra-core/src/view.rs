@@ -0,0 +1,115 @@
+//! Database views defined in Rust
+//!
+//! A `View` names a SELECT query. Migration generation turns it into
+//! `CREATE VIEW`, and a read-only model reads from it, so a view's
+//! definition is versioned next to the code that uses it:
+//!
+//! ```rust,ignore
+//! pub fn active_users() -> View {
+//!     View::define(
+//!         "active_users",
+//!         Query::select()
+//!             .from("users")
+//!             .columns(&["id", "name", "email"])
+//!             .filter(Expr::eq("is_active", true))
+//!             .build(),
+//!     )
+//! }
+//!
+//! #[derive(Model)]
+//! #[chakra(view = "active_users")]
+//! struct ActiveUser {
+//!     #[chakra(primary_key)]
+//!     id: i64,
+//!     name: String,
+//!     email: String,
+//! }
+//!
+//! let migration = MigrationGenerator::new()
+//!     .view(active_users())
+//!     .from_models(&[User::meta(), ActiveUser::meta()], &current);
+//! let users = ActiveUser::objects().all(&executor).await?;
+//! ```
+//!
+//! Views can't take parameters, so values in the query are written into the
+//! definition as literals. Models over a view are queried like any other,
+//! but inserting, updating or deleting them fails with `ModelError::ReadOnly`.
+
+use crate::query::Query;
+use crate::router::DEFAULT_DATABASE;
+use crate::sql::Dialect;
+use serde::{Deserialize, Serialize};
+
+/// A named SELECT query, created in the database as a view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    /// View name
+    pub name: String,
+    /// Named database the view lives in, if not the default one
+    #[serde(default)]
+    pub database: Option<String>,
+    /// The SELECT the view runs
+    pub query: Query,
+}
+
+impl View {
+    /// Define view `name` as the rows of `query`
+    pub fn define(name: impl Into<String>, query: Query) -> Self {
+        Self {
+            name: name.into(),
+            database: None,
+            query,
+        }
+    }
+
+    /// Set the database, leaving it unset for the default one
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        let database = database.into();
+        self.database = (database != DEFAULT_DATABASE).then_some(database);
+        self
+    }
+
+    /// The view's SELECT for `dialect`, with its values inlined
+    pub fn to_sql(&self, dialect: &dyn Dialect) -> String {
+        dialect.generate(&self.query).inline_params(dialect)
+    }
+
+    /// `CREATE VIEW` for `dialect`
+    pub fn create_sql(&self, dialect: &dyn Dialect) -> String {
+        format!(
+            "CREATE VIEW {} AS {}",
+            dialect.quote_name(&self.name),
+            self.to_sql(dialect)
+        )
+    }
+
+    /// `DROP VIEW` for `dialect`
+    pub fn drop_sql(&self, dialect: &dyn Dialect) -> String {
+        format!("DROP VIEW {}", dialect.quote_name(&self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::Expr;
+    use crate::sql::{MySqlDialect, PostgresDialect};
+
+    #[test]
+    fn test_view_sql() {
+        let view = View::define(
+            "active_users",
+            Query::select()
+                .from("users")
+                .columns(&["id", "name"])
+                .filter(Expr::eq("is_active", true))
+                .build(),
+        );
+        assert_eq!(
+            view.create_sql(&PostgresDialect),
+            "CREATE VIEW \"active_users\" AS SELECT \"id\", \"name\" FROM \"users\" WHERE \"is_active\" = TRUE"
+        );
+        assert_eq!(view.drop_sql(&MySqlDialect), "DROP VIEW `active_users`");
+        assert_eq!(view.database("default").database, None);
+    }
+}
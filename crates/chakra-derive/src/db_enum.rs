@@ -0,0 +1,101 @@
+//! DbEnum derive macro implementation
+
+use convert_case::{Case, Casing};
+use darling::{FromDeriveInput, FromVariant};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Ident};
+
+/// Container attributes for DbEnum
+#[derive(Debug, FromDeriveInput)]
+#[darling(attributes(chakra), supports(enum_unit))]
+struct DbEnumAttrs {
+    ident: Ident,
+    data: darling::ast::Data<VariantAttrs, ()>,
+}
+
+/// Variant-level attributes
+#[derive(Debug, FromVariant)]
+#[darling(attributes(chakra))]
+struct VariantAttrs {
+    ident: Ident,
+
+    /// Label override, `snake_case` of the variant otherwise
+    #[darling(default)]
+    rename: Option<String>,
+}
+
+impl VariantAttrs {
+    fn label(&self) -> String {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| self.ident.to_string().to_case(Case::Snake))
+    }
+}
+
+/// Expand the DbEnum derive macro
+pub fn expand_db_enum(input: DeriveInput) -> syn::Result<TokenStream> {
+    let attrs = DbEnumAttrs::from_derive_input(&input)?;
+
+    let enum_name = &attrs.ident;
+    let variants = match &attrs.data {
+        darling::ast::Data::Enum(variants) => variants,
+        _ => unreachable!("darling only accepts unit enums"),
+    };
+    if variants.is_empty() {
+        return Err(syn::Error::new(
+            enum_name.span(),
+            "a DbEnum needs at least one variant",
+        ));
+    }
+
+    let labels: Vec<_> = variants.iter().map(|v| v.label()).collect();
+    if let Some((i, label)) = labels
+        .iter()
+        .enumerate()
+        .find(|(i, label)| labels[..*i].contains(label))
+    {
+        return Err(syn::Error::new(
+            variants[i].ident.span(),
+            format!("label `{}` is used by another variant", label),
+        ));
+    }
+    let idents: Vec<_> = variants.iter().map(|v| &v.ident).collect();
+
+    let expanded = quote! {
+        impl chakra_core::types::DbEnum for #enum_name {
+            const VARIANTS: &'static [&'static str] = &[#(#labels),*];
+
+            fn label(&self) -> &'static str {
+                match self {
+                    #(Self::#idents => #labels,)*
+                }
+            }
+
+            fn from_label(label: &str) -> Option<Self> {
+                match label {
+                    #(#labels => Some(Self::#idents),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl From<#enum_name> for chakra_core::types::Value {
+            fn from(value: #enum_name) -> Self {
+                chakra_core::types::Value::String(
+                    chakra_core::types::DbEnum::label(&value).to_string(),
+                )
+            }
+        }
+
+        impl chakra_core::result::FromValue for #enum_name {
+            fn from_value(
+                value: &chakra_core::types::Value,
+            ) -> chakra_core::error::Result<Self> {
+                chakra_core::result::enum_from_value(value)
+            }
+        }
+    };
+
+    Ok(expanded)
+}
@@ -1,5 +1,6 @@
 //! Field parsing and metadata extraction
 
+use darling::util::Override;
 use darling::{FromField, FromMeta};
 use proc_macro2::TokenStream;
 use quote::quote;
@@ -12,6 +13,7 @@ pub struct FieldAttrs {
     /// Field identifier
     pub ident: Option<Ident>,
     /// Field visibility
+    #[allow(dead_code)]
     pub vis: Visibility,
     /// Field type
     pub ty: Type,
@@ -40,14 +42,52 @@ pub struct FieldAttrs {
     #[darling(default)]
     pub index: bool,
 
-    /// Default value expression
+    /// Default value expression, e.g. `default = "now()"`; a bare `default`
+    /// instead decodes a missing column as `Default::default()`
     #[darling(default)]
-    pub default: Option<String>,
+    pub default: Option<Override<String>>,
+
+    /// Client-side id strategy, e.g. `"snowflake"`, `"ulid"`, `"nanoid:12"`
+    #[darling(default)]
+    pub generated: Option<syn::LitStr>,
 
     /// Skip this field
     #[darling(default)]
     pub skip: bool,
 
+    /// Redact this field's values in debug SQL and records
+    #[darling(default)]
+    pub sensitive: bool,
+
+    /// Decode with `FromValue::from_value_lossy`, e.g. an `i64` from an
+    /// average or a `NaiveDate` from a timestamp
+    #[darling(default)]
+    pub lossy: bool,
+
+    /// Module converting the field with `to_value(&T) -> Value` and
+    /// `from_value(&Value) -> Result<T>` instead of `ToValue` and
+    /// `FromValue`; for an `Option<T>` they convert the `T`, with `None`
+    /// stored as NULL
+    #[darling(default)]
+    pub with: Option<syn::Path>,
+
+    /// Store this field encrypted, as if it were `Encrypted<T>`
+    #[darling(default)]
+    pub encrypted: bool,
+
+    /// An `Option<T>` of a nested `FromRow` type read from the same row,
+    /// `None` when all its columns are NULL
+    #[darling(default)]
+    pub flatten_option: bool,
+
+    /// Column prefix of a `flatten_option` field, e.g. `"author_"`
+    #[darling(default)]
+    pub prefix: Option<String>,
+
+    /// The model's `Snapshot` for change tracking, not a column
+    #[darling(default)]
+    pub snapshot: bool,
+
     /// Foreign key reference (table.column)
     #[darling(default)]
     pub references: Option<String>,
@@ -56,9 +96,76 @@ pub struct FieldAttrs {
     #[darling(default)]
     pub json: bool,
 
+    /// Database enum type of a `DbEnum` field, e.g. `"order_status"`
+    #[darling(default)]
+    pub db_enum: Option<String>,
+
     /// Rename strategy override
     #[darling(default)]
     pub rename: Option<String>,
+
+    /// `Related<T>` loaded through this model's foreign key column
+    #[darling(default)]
+    pub belongs_to: Option<String>,
+
+    /// `Related<Vec<T>>` loaded through the target's foreign key column
+    #[darling(default)]
+    pub has_many: Option<String>,
+
+    /// `Related<Vec<T>>` loaded through a join table
+    #[darling(default)]
+    pub many_to_many: Option<ManyToManyAttr>,
+
+    /// Checks run by `Model::validate` before each write
+    #[darling(default)]
+    pub validate: Option<ValidateAttr>,
+}
+
+/// `#[chakra(validate(length(min = 1, max = 255), email, range(min = 0), custom = "check"))]`
+#[derive(Debug, FromMeta)]
+pub struct ValidateAttr {
+    /// Bounds on the length in characters, or items for a `Vec`
+    #[darling(default)]
+    pub length: Option<LengthAttr>,
+    /// Must look like an email address
+    #[darling(default)]
+    pub email: bool,
+    /// Bounds on the value
+    #[darling(default)]
+    pub range: Option<RangeAttr>,
+    /// Functions `fn(&T) -> Result<(), String>`, repeatable; `&str` or
+    /// `&[T]` work for `String` and `Vec<T>`
+    #[darling(default, multiple)]
+    pub custom: Vec<syn::Path>,
+}
+
+/// `length(min = 1, max = 255)`
+#[derive(Debug, FromMeta)]
+pub struct LengthAttr {
+    #[darling(default)]
+    pub min: Option<usize>,
+    #[darling(default)]
+    pub max: Option<usize>,
+}
+
+/// `range(min = 0, max = 150)`, bounds of the field's own type
+#[derive(Debug, FromMeta)]
+pub struct RangeAttr {
+    #[darling(default)]
+    pub min: Option<syn::Expr>,
+    #[darling(default)]
+    pub max: Option<syn::Expr>,
+}
+
+/// `#[chakra(many_to_many(through = "post_tags", foreign_key = "post_id", related_key = "tag_id"))]`
+#[derive(Debug, FromMeta)]
+pub struct ManyToManyAttr {
+    /// Join table
+    pub through: String,
+    /// Join table column pointing at this model
+    pub foreign_key: String,
+    /// Join table column pointing at the target model
+    pub related_key: String,
 }
 
 impl FieldAttrs {
@@ -81,6 +188,77 @@ impl FieldAttrs {
         self.ident.as_ref().expect("field must have a name")
     }
 
+    /// Check if this field is a relationship rather than a column
+    pub fn is_relation(&self) -> bool {
+        self.belongs_to.is_some() || self.has_many.is_some() || self.many_to_many.is_some()
+    }
+
+    /// The target model of a relationship field, unwrapping `Related<T>`
+    /// and `Related<Vec<T>>`, and whether it holds many records
+    pub fn relation_target(&self) -> syn::Result<(&Type, bool)> {
+        let error = || {
+            syn::Error::new_spanned(
+                &self.ty,
+                "relationship fields must be Related<T> or Related<Vec<T>>",
+            )
+        };
+        let related = generic_argument(&self.ty, "Related").ok_or_else(error)?;
+        match generic_argument(related, "Vec") {
+            Some(target) => Ok((target, true)),
+            None => Ok((related, false)),
+        }
+    }
+
+    /// Generate RelationMeta construction for a relationship field
+    pub fn to_relation_meta(&self) -> syn::Result<TokenStream> {
+        let name = self.field_name().to_string();
+        let (target, many) = self.relation_target()?;
+        let opt = |value: Option<&String>| match value {
+            Some(value) => quote! { Some(#value.to_string()) },
+            None => quote! { None },
+        };
+        let (relation_type, foreign_key, through, related_key) = match (
+            &self.belongs_to,
+            &self.has_many,
+            &self.many_to_many,
+        ) {
+            (Some(fk), None, None) if !many => {
+                (quote! { ManyToOne }, opt(Some(fk)), opt(None), opt(None))
+            }
+            (None, Some(fk), None) if many => {
+                (quote! { OneToMany }, opt(Some(fk)), opt(None), opt(None))
+            }
+            (None, None, Some(m2m)) if many => (
+                quote! { ManyToMany },
+                opt(Some(&m2m.foreign_key)),
+                opt(Some(&m2m.through)),
+                opt(Some(&m2m.related_key)),
+            ),
+            _ => return Err(syn::Error::new_spanned(
+                &self.ty,
+                "use belongs_to with Related<T>, or has_many or many_to_many with Related<Vec<T>>",
+            )),
+        };
+        // The type's name, not its meta(): two models pointing at each other
+        // would otherwise initialize each other's metadata recursively
+        let target_model = match target {
+            Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        }
+        .unwrap_or_default();
+        Ok(quote! {
+            chakra_core::model::RelationMeta {
+                name: #name.to_string(),
+                relation_type: chakra_core::model::RelationType::#relation_type,
+                target_model: #target_model.to_string(),
+                foreign_key: #foreign_key,
+                through_table: #through,
+                related_key: #related_key,
+                back_populates: None,
+            }
+        })
+    }
+
     /// Check if this is an Option type
     pub fn is_option(&self) -> bool {
         is_option_type(&self.ty)
@@ -102,14 +280,269 @@ impl FieldAttrs {
         &self.ty
     }
 
+    /// Whether the derive wraps this field in `Encrypted`: it is marked
+    /// `encrypted` without already being `Encrypted<T>`
+    pub fn wraps_encrypted(&self) -> bool {
+        self.encrypted && !self.is_encrypted_type()
+    }
+
+    /// Whether the field is declared as `Encrypted<T>`
+    pub fn is_encrypted_type(&self) -> bool {
+        generic_argument(self.inner_type(), "Encrypted").is_some()
+    }
+
+    /// Reject `with` alongside attributes that decode the field themselves
+    pub fn check_with(&self) -> syn::Result<()> {
+        if self.with.is_some() && (self.encrypted || self.lossy || self.flatten_option) {
+            return Err(syn::Error::new_spanned(
+                &self.ty,
+                "with can't be combined with encrypted, lossy or flatten_option",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The field's value as a `Value`, encrypted if marked so
+    ///
+    /// Encryption fails without a keyring, so for encrypted fields the
+    /// expression uses `?` and must be in a function returning `Result`.
+    pub fn to_value_expr(&self) -> TokenStream {
+        let field_name = self.field_name();
+        let to_value = match &self.with {
+            Some(with) => quote! { #with::to_value },
+            None => quote! { chakra_core::types::ToValue::to_value },
+        };
+        let encrypt = |value: TokenStream| {
+            let encrypted = if self.wraps_encrypted() {
+                quote! { chakra_core::encryption::Encrypted::new(#value.clone()).encrypt()? }
+            } else {
+                quote! { #value.encrypt()? }
+            };
+            quote! { chakra_core::types::Value::Bytes(#encrypted) }
+        };
+        let encrypted = self.wraps_encrypted() || self.is_encrypted_type();
+        match (encrypted, self.is_option()) {
+            (true, true) => {
+                let value = encrypt(quote! { value });
+                quote! {
+                    match &self.#field_name {
+                        Some(value) => #value,
+                        None => chakra_core::types::Value::Null,
+                    }
+                }
+            }
+            (true, false) => encrypt(quote! { self.#field_name }),
+            (false, true) => quote! {
+                self.#field_name.as_ref().map_or(chakra_core::types::Value::Null, #to_value)
+            },
+            (false, false) => quote! { #to_value(&self.#field_name) },
+        }
+    }
+
+    /// The row a `flatten_option` field is read from
+    pub fn flattened_row_expr(&self) -> TokenStream {
+        match &self.prefix {
+            Some(prefix) => quote! { &row.with_prefix(#prefix) },
+            None => quote! { row },
+        }
+    }
+
+    /// Decode the field from `row`
+    pub fn decode_row_expr(&self) -> TokenStream {
+        let col_name = self.column_name();
+        let ty = &self.ty;
+        let inner = self.inner_type();
+        if self.flatten_option {
+            let row = self.flattened_row_expr();
+            return quote! {
+                {
+                    let row = #row;
+                    if <#inner as chakra_core::result::FromRow>::all_null(row) {
+                        None
+                    } else {
+                        Some(<#inner as chakra_core::result::FromRow>::from_row(row)?)
+                    }
+                }
+            };
+        }
+        let decode = match (self.wraps_encrypted(), self.is_option()) {
+            (true, true) => quote! {
+                row.try_get::<chakra_core::encryption::Encrypted<#inner>>(#col_name)?
+                    .map(chakra_core::encryption::Encrypted::into_inner)
+            },
+            (true, false) => quote! {
+                row.get_as::<chakra_core::encryption::Encrypted<#ty>>(#col_name)?.into_inner()
+            },
+            (false, true) if self.with.is_some() => {
+                let with = &self.with;
+                quote! {
+                    row.try_get::<chakra_core::types::Value>(#col_name)?
+                        .map(|value| #with::from_value(&value))
+                        .transpose()?
+                }
+            }
+            (false, false) if self.with.is_some() => {
+                let with = &self.with;
+                quote! { #with::from_value(&row.get_as::<chakra_core::types::Value>(#col_name)?)? }
+            }
+            (false, true) if self.lossy => quote! {
+                row.try_get::<chakra_core::result::Lossy<#inner>>(#col_name)?.map(|v| v.0)
+            },
+            (false, false) if self.lossy => quote! {
+                row.get_as::<chakra_core::result::Lossy<#ty>>(#col_name)?.0
+            },
+            (false, true) => quote! { row.try_get(#col_name)? },
+            (false, false) => quote! { row.get_as(#col_name)? },
+        };
+        match self.default {
+            Some(Override::Inherit) => quote! {
+                if row.has_column(#col_name) { #decode } else { Default::default() }
+            },
+            _ => decode,
+        }
+    }
+
+    /// Decode the field from `value`
+    pub fn decode_value_expr(&self) -> TokenStream {
+        let ty = &self.ty;
+        let inner = self.inner_type();
+        match (self.wraps_encrypted(), self.is_option()) {
+            (true, true) => quote! {
+                <Option<chakra_core::encryption::Encrypted<#inner>> as chakra_core::result::FromValue>::from_value(&value)?
+                    .map(chakra_core::encryption::Encrypted::into_inner)
+            },
+            (true, false) => quote! {
+                <chakra_core::encryption::Encrypted<#ty> as chakra_core::result::FromValue>::from_value(&value)?
+                    .into_inner()
+            },
+            (false, true) if self.with.is_some() => {
+                let with = &self.with;
+                quote! {
+                    match &value {
+                        chakra_core::types::Value::Null => None,
+                        value => Some(#with::from_value(value)?),
+                    }
+                }
+            }
+            (false, false) if self.with.is_some() => {
+                let with = &self.with;
+                quote! { #with::from_value(&value)? }
+            }
+            (false, _) if self.lossy => quote! {
+                <#ty as chakra_core::result::FromValue>::from_value_lossy(&value)?
+            },
+            (false, _) => quote! { chakra_core::result::FromValue::from_value(&value)? },
+        }
+    }
+
+    /// Statements pushing this field's failed checks onto `errors`, only
+    /// when it is `Some` for an `Option`
+    pub fn validate_expr(&self) -> Option<TokenStream> {
+        let validate = self.validate.as_ref()?;
+        let field_name = self.field_name();
+        let col_name = self.column_name();
+        let opt = |value: Option<TokenStream>| match value {
+            Some(value) => quote! { Some(#value) },
+            None => quote! { None },
+        };
+        let mut checks = Vec::new();
+        if let Some(length) = &validate.length {
+            let min = opt(length.min.map(|min| quote! { #min }));
+            let max = opt(length.max.map(|max| quote! { #max }));
+            checks.push(quote! { chakra_core::validate::length(#col_name, value, #min, #max) });
+        }
+        if validate.email {
+            checks.push(quote! { chakra_core::validate::email(#col_name, value) });
+        }
+        if let Some(range) = &validate.range {
+            let min = opt(range.min.as_ref().map(|min| quote! { #min }));
+            let max = opt(range.max.as_ref().map(|max| quote! { #max }));
+            checks.push(quote! { chakra_core::validate::range(#col_name, value, #min, #max) });
+        }
+        for custom in &validate.custom {
+            // Called through a closure so `fn(&str)` checks a `String`
+            checks.push(quote! {
+                chakra_core::validate::custom(#col_name, value, |value| #custom(value))
+            });
+        }
+        let checks = quote! { #(errors.extend(#checks.err());)* };
+        Some(if self.is_option() {
+            quote! {
+                if let Some(value) = &self.#field_name {
+                    #checks
+                }
+            }
+        } else {
+            quote! {
+                {
+                    let value = &self.#field_name;
+                    #checks
+                }
+            }
+        })
+    }
+
     /// Generate FieldType expression
     pub fn field_type_expr(&self) -> TokenStream {
+        if self.encrypted {
+            return quote! { chakra_core::types::FieldType::Binary { max_length: None } };
+        }
         let ty = self.inner_type();
+        if let Some(name) = &self.db_enum {
+            return quote! { chakra_core::types::FieldType::db_enum::<#ty>(#name) };
+        }
         type_to_field_type(ty, self.json)
     }
 
+    /// The `IdStrategy` named by `generated`, parsed like
+    /// `IdStrategy::from_str` so a typo fails the build
+    pub fn id_strategy(&self) -> syn::Result<Option<TokenStream>> {
+        let Some(lit) = &self.generated else {
+            return Ok(None);
+        };
+        let value = lit.value();
+        let invalid = || {
+            syn::Error::new(
+                lit.span(),
+                format!(
+                    "invalid id strategy `{}`, expected snowflake[:machine_id], ulid, \
+                     nanoid[:length], uuid or custom:name",
+                    value
+                ),
+            )
+        };
+        let (name, arg) = match value.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (value.as_str(), None),
+        };
+        let strategy = match (name, arg) {
+            ("snowflake", None) => quote! { chakra_core::id::IdStrategy::snowflake() },
+            ("snowflake", Some(id)) => match id.parse::<u16>() {
+                // Machine ids are 10 bits
+                Ok(id) if id < 1 << 10 => quote! {
+                    chakra_core::id::IdStrategy::Snowflake { machine_id: Some(#id) }
+                },
+                _ => return Err(invalid()),
+            },
+            ("ulid", None) => quote! { chakra_core::id::IdStrategy::Ulid },
+            ("nanoid", None) => quote! { chakra_core::id::IdStrategy::nanoid() },
+            ("nanoid", Some(length)) => match length.parse::<usize>() {
+                Ok(length) if length > 0 => quote! {
+                    chakra_core::id::IdStrategy::NanoId { length: #length }
+                },
+                _ => return Err(invalid()),
+            },
+            ("uuid", None) => quote! { chakra_core::id::IdStrategy::UuidV4 },
+            ("custom", Some(name)) if !name.is_empty() => quote! {
+                chakra_core::id::IdStrategy::Custom(#name.to_string())
+            },
+            _ => return Err(invalid()),
+        };
+        Ok(Some(strategy))
+    }
+
     /// Generate FieldMeta construction
-    pub fn to_field_meta(&self) -> TokenStream {
+    pub fn to_field_meta(&self) -> syn::Result<TokenStream> {
         let name = self.column_name();
         let field_type = self.field_type_expr();
         let primary_key = self.primary_key;
@@ -117,8 +550,13 @@ impl FieldAttrs {
         let nullable = self.nullable || self.is_option();
         let unique = self.unique;
         let index = self.index;
+        let sensitive = self.sensitive || self.encrypted;
 
-        let default_expr = if let Some(ref default) = self.default {
+        let default_expr = if let Some(strategy) = self.id_strategy()? {
+            quote! {
+                Some(chakra_core::model::FieldDefault::Generated(#strategy))
+            }
+        } else if let Some(Override::Explicit(ref default)) = self.default {
             quote! { Some(chakra_core::model::FieldDefault::Expression(#default.to_string())) }
         } else if self.auto_increment {
             quote! { Some(chakra_core::model::FieldDefault::AutoIncrement) }
@@ -146,7 +584,7 @@ impl FieldAttrs {
             quote! { None }
         };
 
-        quote! {
+        Ok(quote! {
             chakra_core::model::FieldMeta {
                 name: #name.to_string(),
                 column: None,
@@ -158,8 +596,9 @@ impl FieldAttrs {
                 index: #index,
                 default: #default_expr,
                 foreign_key: #fk_expr,
+                sensitive: #sensitive,
             }
-        }
+        })
     }
 }
 
@@ -182,9 +621,23 @@ fn type_to_field_type(ty: &Type, is_json: bool) -> TokenStream {
                 "String" => quote! { chakra_core::types::FieldType::Text },
                 "Uuid" => quote! { chakra_core::types::FieldType::Uuid },
                 "DateTime" => quote! { chakra_core::types::FieldType::TimestampTz },
+                "NaiveDateTime" => quote! { chakra_core::types::FieldType::Timestamp },
                 "NaiveDate" => quote! { chakra_core::types::FieldType::Date },
                 "NaiveTime" => quote! { chakra_core::types::FieldType::Time },
+                "IpAddr" => quote! { chakra_core::types::FieldType::Inet },
+                "Cidr" => quote! { chakra_core::types::FieldType::Cidr },
+                "MacAddr" => quote! { chakra_core::types::FieldType::MacAddr },
                 "Value" => quote! { chakra_core::types::FieldType::Json },
+                "Encrypted" => {
+                    quote! { chakra_core::types::FieldType::Binary { max_length: None } }
+                }
+                "Range" => match generic_argument(ty, "Range") {
+                    Some(inner) => {
+                        let element = type_to_field_type(inner, false);
+                        quote! { chakra_core::types::FieldType::range(#element) }
+                    }
+                    None => quote! { chakra_core::types::FieldType::Text },
+                },
                 "Vec" => {
                     // Check if it's Vec<u8> for bytes
                     if let syn::PathArguments::AngleBracketed(ref args) = segment.arguments {
@@ -208,6 +661,22 @@ fn type_to_field_type(ty: &Type, is_json: bool) -> TokenStream {
     quote! { chakra_core::types::FieldType::Text }
 }
 
+/// The first type argument of `ty` if its last path segment is `wrapper`
+fn generic_argument<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Check if a type is Option<T>
 fn is_option_type(ty: &Type) -> bool {
     if let Type::Path(ref path) = ty {
@@ -234,4 +703,31 @@ mod tests {
         assert_eq!(to_snake_case("userName"), "user_name");
         assert_eq!(to_snake_case("user_name"), "user_name");
     }
+
+    #[test]
+    fn test_id_strategy() {
+        use syn::parse::Parser;
+
+        let strategy = |generated: &str| {
+            let field = syn::Field::parse_named
+                .parse2(quote! { #[chakra(generated = #generated)] id: String })
+                .unwrap();
+            FieldAttrs::from_field(&field).unwrap().id_strategy()
+        };
+        let parsed = |generated| strategy(generated).unwrap().unwrap().to_string();
+        assert_eq!(
+            parsed("snowflake:3"),
+            quote! { chakra_core::id::IdStrategy::Snowflake { machine_id: Some(3u16) } }
+                .to_string()
+        );
+        assert_eq!(
+            parsed("nanoid:12"),
+            quote! { chakra_core::id::IdStrategy::NanoId { length: 12usize } }.to_string()
+        );
+        assert!(parsed("custom:order").contains("Custom"));
+        for invalid in ["uuidv4", "snowflake:1024", "nanoid:0", "custom:", "ulid:1"] {
+            let err = strategy(invalid).unwrap_err();
+            assert!(err.to_string().starts_with("invalid id strategy"));
+        }
+    }
 }
@@ -29,25 +29,58 @@ pub fn expand_from_row(input: DeriveInput) -> syn::Result<TokenStream> {
 
     let struct_name = &attrs.ident;
     let fields = attrs.fields();
+    if let Some(f) = fields.iter().find(|f| f.flatten_option && !f.is_option()) {
+        return Err(syn::Error::new_spanned(
+            &f.ty,
+            "flatten_option fields must be Option<T>",
+        ));
+    }
+    if let Some(f) = fields
+        .iter()
+        .find(|f| f.prefix.is_some() && !f.flatten_option)
+    {
+        return Err(syn::Error::new_spanned(
+            &f.ty,
+            "prefix only applies to flatten_option fields",
+        ));
+    }
+    for f in &fields {
+        f.check_with()?;
+    }
+    if let Some(f) = fields.iter().find(|f| f.validate.is_some()) {
+        return Err(syn::Error::new_spanned(
+            &f.ty,
+            "validate is only supported by #[derive(Model)]",
+        ));
+    }
 
     let from_row_fields: Vec<_> = fields
         .iter()
         .map(|f| {
             let field_name = f.field_name();
-            let col_name = f.column_name();
-
-            if f.is_option() {
-                quote! {
-                    #field_name: row.try_get(#col_name)?
-                }
-            } else {
-                quote! {
-                    #field_name: row.get_as(#col_name)?
-                }
+            let decode = f.decode_row_expr();
+            quote! {
+                #field_name: #decode
             }
         })
         .collect();
 
+    // NULL throughout when every column, nested ones included, is NULL
+    let columns: Vec<_> = fields
+        .iter()
+        .filter(|f| !f.flatten_option)
+        .map(|f| f.column_name())
+        .collect();
+    let nested_null: Vec<_> = fields
+        .iter()
+        .filter(|f| f.flatten_option)
+        .map(|f| {
+            let inner = f.inner_type();
+            let row = f.flattened_row_expr();
+            quote! { && <#inner as chakra_core::result::FromRow>::all_null(#row) }
+        })
+        .collect();
+
     let expanded = quote! {
         impl chakra_core::result::FromRow for #struct_name {
             fn from_row(row: &chakra_core::result::Row) -> chakra_core::error::Result<Self> {
@@ -55,6 +88,13 @@ pub fn expand_from_row(input: DeriveInput) -> syn::Result<TokenStream> {
                     #(#from_row_fields),*
                 })
             }
+
+            fn all_null(row: &chakra_core::result::Row) -> bool {
+                [#(#columns),*]
+                    .iter()
+                    .all(|column| row.get(column).map_or(true, chakra_core::types::Value::is_null))
+                    #(#nested_null)*
+            }
         }
     };
 
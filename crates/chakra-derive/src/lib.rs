@@ -3,15 +3,18 @@
 //! This crate provides derive macros for:
 //! - `#[derive(Model)]` - Derive the Model trait
 //! - `#[derive(FromRow)]` - Derive row deserialization
+//! - `#[derive(DbEnum)]` - Store a Rust enum as a database enum
 //! - `#[derive(IntoParams)]` - Derive parameter conversion
+//! - `sql!` - SQL with parameters checked against its placeholders
 
 use proc_macro::TokenStream;
-use quote::quote;
 use syn::{parse_macro_input, DeriveInput};
 
+mod db_enum;
 mod field;
-mod model;
 mod from_row;
+mod model;
+mod sql;
 
 /// Derive the Model trait for a struct
 ///
@@ -35,6 +38,9 @@ mod from_row;
 ///     #[chakra(default = "now()")]
 ///     created_at: chrono::DateTime<chrono::Utc>,
 /// }
+///
+/// // Typed columns: `UserColumns`, one per field
+/// let filter = User::col().name.eq("alice");
 /// ```
 #[proc_macro_derive(Model, attributes(chakra))]
 pub fn derive_model(input: TokenStream) -> TokenStream {
@@ -56,8 +62,15 @@ pub fn derive_model(input: TokenStream) -> TokenStream {
 /// #[derive(FromRow)]
 /// struct UserRow {
 ///     id: i64,
+///     #[chakra(rename = "user_name")]
 ///     name: String,
 ///     email: Option<String>,
+///     // 0 when the query has no `logins` column
+///     #[chakra(default)]
+///     logins: i64,
+///     // `org_id`, `org_name`, ...; `None` when all are NULL after a LEFT JOIN
+///     #[chakra(flatten_option, prefix = "org_")]
+///     org: Option<OrgRow>,
 /// }
 /// ```
 #[proc_macro_derive(FromRow, attributes(chakra))]
@@ -70,6 +83,35 @@ pub fn derive_from_row(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Derive the DbEnum trait for an enum of unit variants
+///
+/// # Example
+///
+/// ```ignore
+/// use chakra_derive::DbEnum;
+///
+/// #[derive(DbEnum, Debug, Clone, PartialEq)]
+/// enum OrderStatus {
+///     // "pending"
+///     Pending,
+///     #[chakra(rename = "on_hold")]
+///     Held,
+/// }
+///
+/// // On a model field, stored as the PostgreSQL type `order_status`
+/// #[chakra(db_enum = "order_status")]
+/// status: OrderStatus,
+/// ```
+#[proc_macro_derive(DbEnum, attributes(chakra))]
+pub fn derive_db_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match db_enum::expand_db_enum(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
 /// Attribute macro for defining a model inline
 ///
 /// # Example
@@ -94,3 +136,30 @@ pub fn model(attr: TokenStream, item: TokenStream) -> TokenStream {
         Err(err) => err.to_compile_error().into(),
     }
 }
+
+/// Build a `SqlFragment` from SQL and its parameters, checking at compile
+/// time that every placeholder has a parameter and every parameter is used
+///
+/// Positional parameters fill `$1`, `$2`, ... (or `?` in order); named ones,
+/// written after them, fill `:name` placeholders.
+///
+/// # Example
+///
+/// ```ignore
+/// use chakra_core::sql;
+///
+/// let fragment = sql!("SELECT * FROM users WHERE id = $1 AND org = :org", id, org = org_id);
+/// let user = User::raw_fragment(fragment).get(&executor).await?;
+///
+/// // error: placeholder $2 has no parameter: 1 given
+/// let fragment = sql!("SELECT * FROM users WHERE id = $1 AND name = $2", id);
+/// ```
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as sql::SqlInput);
+
+    match sql::expand_sql(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
@@ -2,10 +2,11 @@
 
 use crate::field::FieldAttrs;
 use convert_case::{Case, Casing};
+use darling::util::Override;
 use darling::{FromDeriveInput, FromMeta};
 use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{Data, DeriveInput, Fields, Ident};
+use syn::{DeriveInput, Ident, Visibility};
 
 /// Container-level attributes for Model
 #[derive(Debug, FromDeriveInput)]
@@ -13,6 +14,8 @@ use syn::{Data, DeriveInput, Fields, Ident};
 struct ModelAttrs {
     /// Struct identifier
     ident: Ident,
+    /// Struct visibility, shared by its generated columns struct
+    vis: Visibility,
     /// Struct data
     data: darling::ast::Data<(), FieldAttrs>,
 
@@ -20,34 +23,201 @@ struct ModelAttrs {
     #[darling(default)]
     table: Option<String>,
 
+    /// View the model reads from instead of a table, e.g.
+    /// `view = "active_users"`; such models are read-only
+    #[darling(default)]
+    view: Option<String>,
+
     /// Schema name
     #[darling(default)]
     schema: Option<String>,
 
+    /// Named database the model is routed to, e.g. `database = "analytics"`
+    #[darling(default)]
+    database: Option<String>,
+
+    /// Column picking the shard of each row, e.g. `shard_key = "user_id"`
+    #[darling(default)]
+    shard_key: Option<String>,
+
+    /// Filter applied to every query, e.g. `"status <> 'archived'"`
+    #[darling(default)]
+    default_scope: Option<String>,
+
+    /// Rows belong to the `TenantContext` tenant, through a `tenant_id`
+    /// column or the one given, e.g. `tenant_scoped = "org_id"`
+    #[darling(default)]
+    tenant_scoped: Option<Override<String>>,
+
+    /// Named scopes, generated as associated functions
+    #[darling(default, multiple, rename = "scope")]
+    scopes: Vec<ScopeAttr>,
+
+    /// Table indexes beyond single-field `index`
+    #[darling(default, multiple, rename = "index")]
+    indexes: Vec<IndexAttr>,
+
     /// Rename all fields strategy
     #[darling(default)]
+    #[allow(dead_code)]
     rename_all: Option<String>,
 }
 
+/// A named scope, `#[chakra(scope(name = "active", filter = "..."))]`
+#[derive(Debug, FromMeta)]
+struct ScopeAttr {
+    name: Ident,
+    filter: String,
+}
+
+/// An index, `#[chakra(index(expr = "lower(email)", unique))]` or
+/// `#[chakra(index(columns = "tenant_id, created_at", include = "status", fillfactor = 70))]`
+#[derive(Debug, FromMeta)]
+struct IndexAttr {
+    /// Defaults to `{table}_{keys}_idx`
+    #[darling(default)]
+    name: Option<String>,
+    /// Comma-separated key columns
+    #[darling(default)]
+    columns: Option<String>,
+    /// SQL expression key, after any columns
+    #[darling(default)]
+    expr: Option<String>,
+    #[darling(default)]
+    unique: bool,
+    /// Comma-separated non-key columns stored in the index
+    #[darling(default)]
+    include: Option<String>,
+    #[darling(default)]
+    fillfactor: Option<u8>,
+}
+
+impl IndexAttr {
+    /// Expand to an `IndexMeta`, checking columns against `fields`
+    fn to_index_meta(
+        &self,
+        table: &str,
+        fields: &[&FieldAttrs],
+        span: proc_macro2::Span,
+    ) -> syn::Result<TokenStream> {
+        let split = |list: &Option<String>| -> syn::Result<Vec<String>> {
+            let columns: Vec<String> = list
+                .iter()
+                .flat_map(|l| l.split(','))
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect();
+            match columns
+                .iter()
+                .find(|c| !fields.iter().any(|f| f.column_name() == **c))
+            {
+                Some(missing) => Err(syn::Error::new(
+                    span,
+                    format!("index refers to unknown column `{}`", missing),
+                )),
+                None => Ok(columns),
+            }
+        };
+        let columns = split(&self.columns)?;
+        let include = split(&self.include)?;
+        if columns.is_empty() && self.expr.is_none() {
+            return Err(syn::Error::new(span, "index needs `columns` or `expr`"));
+        }
+        if self.fillfactor.is_some_and(|f| !(10..=100).contains(&f)) {
+            return Err(syn::Error::new(
+                span,
+                "index fillfactor must be between 10 and 100",
+            ));
+        }
+        let name = self.name.clone().unwrap_or_else(|| {
+            let keys: Vec<&str> = columns
+                .iter()
+                .map(String::as_str)
+                .chain(self.expr.as_deref())
+                .collect();
+            let slug: String = keys
+                .join("_")
+                .to_lowercase()
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("_");
+            format!("{}_{}_idx", table, slug)
+        });
+        let expr = self.expr.iter();
+        let unique = self.unique.then(|| quote! { .unique() });
+        let include =
+            (!include.is_empty()).then(|| quote! { .include(vec![#(#include.to_string()),*]) });
+        let fillfactor = self.fillfactor.map(|f| {
+            let f = f.to_string();
+            quote! { .storage("fillfactor", #f) }
+        });
+        Ok(quote! {
+            chakra_core::model::IndexMeta::new(#name, vec![#(#columns.to_string()),*])
+                #(.expression(#expr))*
+                #unique
+                #include
+                #fillfactor
+        })
+    }
+}
+
 impl ModelAttrs {
     /// Get the table name
     fn table_name(&self) -> String {
         self.table
             .clone()
+            .or_else(|| self.view.clone())
             .unwrap_or_else(|| self.ident.to_string().to_case(Case::Snake) + "s")
     }
 
     /// Get all fields
     fn fields(&self) -> Vec<&FieldAttrs> {
         match &self.data {
-            darling::ast::Data::Struct(fields) => fields.iter().filter(|f| !f.skip).collect(),
+            darling::ast::Data::Struct(fields) => fields
+                .iter()
+                .filter(|f| !f.skip && !f.snapshot && !f.is_relation())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Get the `#[chakra(snapshot)]` field
+    fn snapshot_field(&self) -> syn::Result<Option<&FieldAttrs>> {
+        let snapshots: Vec<&FieldAttrs> = match &self.data {
+            darling::ast::Data::Struct(fields) => fields.iter().filter(|f| f.snapshot).collect(),
+            _ => vec![],
+        };
+        match snapshots.as_slice() {
+            [] => Ok(None),
+            [snapshot] if self.fields().len() > 64 => Err(syn::Error::new(
+                snapshot.field_name().span(),
+                "change tracking supports at most 64 fields",
+            )),
+            [snapshot] => Ok(Some(*snapshot)),
+            [_, second, ..] => Err(syn::Error::new(
+                second.field_name().span(),
+                "a model can have only one snapshot field",
+            )),
+        }
+    }
+
+    /// Get relationship fields
+    fn relations(&self) -> Vec<&FieldAttrs> {
+        match &self.data {
+            darling::ast::Data::Struct(fields) => {
+                fields.iter().filter(|f| f.is_relation()).collect()
+            }
             _ => vec![],
         }
     }
 
     /// Get primary key fields
     fn primary_key_fields(&self) -> Vec<&FieldAttrs> {
-        self.fields().into_iter().filter(|f| f.primary_key).collect()
+        self.fields()
+            .into_iter()
+            .filter(|f| f.primary_key)
+            .collect()
     }
 }
 
@@ -56,14 +226,64 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
     let attrs = ModelAttrs::from_derive_input(&input)?;
 
     let struct_name = &attrs.ident;
+    if attrs.table.is_some() && attrs.view.is_some() {
+        return Err(syn::Error::new(
+            struct_name.span(),
+            "a model reads from either a `table` or a `view`",
+        ));
+    }
     let table_name = attrs.table_name();
+    let view = attrs.view.is_some();
     let schema = match &attrs.schema {
         Some(s) => quote! { Some(#s.to_string()) },
         None => quote! { None },
     };
+    let database = match &attrs.database {
+        Some(d) => quote! { Some(#d.to_string()) },
+        None => quote! { None },
+    };
 
     let fields = attrs.fields();
+    if let Some(f) = fields
+        .iter()
+        .find(|f| f.flatten_option || f.prefix.is_some())
+    {
+        return Err(syn::Error::new_spanned(
+            &f.ty,
+            "flatten_option is only supported by #[derive(FromRow)]; use a relationship on models",
+        ));
+    }
+    for f in &fields {
+        f.check_with()?;
+    }
+    // Names differing only in case are one column once identifiers are folded
+    for (i, f) in fields.iter().enumerate() {
+        let column = f.column_name();
+        if let Some(other) = fields[..i]
+            .iter()
+            .map(|o| o.column_name())
+            .find(|o| o.eq_ignore_ascii_case(&column))
+        {
+            return Err(syn::Error::new(
+                f.ident.as_ref().map_or(struct_name.span(), |i| i.span()),
+                format!(
+                    "column `{}` clashes with `{}` when identifiers are folded to lowercase",
+                    column, other
+                ),
+            ));
+        }
+    }
     let pk_fields = attrs.primary_key_fields();
+    let shard_key = match &attrs.shard_key {
+        Some(column) if !fields.iter().any(|f| f.column_name() == *column) => {
+            return Err(syn::Error::new(
+                struct_name.span(),
+                format!("shard_key `{}` is not a column of this model", column),
+            ));
+        }
+        Some(column) => quote! { Some(#column.to_string()) },
+        None => quote! { None },
+    };
 
     // Determine primary key type
     let pk_type = if pk_fields.len() == 1 {
@@ -80,7 +300,10 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
     };
 
     // Generate field metadata
-    let field_metas: Vec<_> = fields.iter().map(|f| f.to_field_meta()).collect();
+    let field_metas = fields
+        .iter()
+        .map(|f| f.to_field_meta())
+        .collect::<syn::Result<Vec<_>>>()?;
 
     // Generate primary_key() method
     let pk_impl = if pk_fields.len() == 1 {
@@ -110,28 +333,156 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
         .iter()
         .map(|f| {
             let field_name = f.field_name();
-            let col_name = f.column_name();
-            if f.is_option() {
+            let decode = f.decode_row_expr();
+            quote! {
+                #field_name: #decode
+            }
+        })
+        .collect();
+
+    // Change tracking: the snapshot starts with the loaded values
+    let snapshot = attrs.snapshot_field()?;
+    let from_row_snapshot: Vec<_> = snapshot
+        .iter()
+        .map(|f| {
+            let field_name = f.field_name();
+            quote! {
+                #field_name: Default::default()
+            }
+        })
+        .collect();
+    let mark_clean = snapshot.map(|_| {
+        quote! {
+            chakra_core::model::Model::mark_clean(&mut model);
+        }
+    });
+    let snapshot_impl = snapshot.map(|f| {
+        let snapshot_name = f.field_name();
+        let checks = fields.iter().enumerate().map(|(i, f)| {
+            let field_name = f.field_name();
+            // Ciphertexts differ on every encryption, so compare plain values
+            let changed = if f.wraps_encrypted() {
+                quote! { chakra_core::encryption::changed(snapshot, #i, &self.#field_name)? }
+            } else if f.is_encrypted_type() {
+                // Without a keyring every field counts as changed, and the
+                // write that follows reports the error
+                let value = f.to_value_expr();
                 quote! {
-                    #field_name: row.try_get(#col_name)?
+                    snapshot.changed(
+                        #i,
+                        &(|| Ok::<_, chakra_core::error::ChakraError>(#value))().ok()?,
+                    )?
                 }
             } else {
-                quote! {
-                    #field_name: row.get_as(#col_name)?
+                let value = f.to_value_expr();
+                quote! { snapshot.changed(#i, &#value)? }
+            };
+            quote! {
+                if #changed {
+                    mask.insert(#i);
                 }
             }
+        });
+        quote! {
+            fn snapshot_mut(&mut self) -> Option<&mut chakra_core::model::Snapshot> {
+                Some(&mut self.#snapshot_name)
+            }
+
+            fn changed_fields(&self) -> Option<chakra_core::model::FieldMask> {
+                let snapshot = &self.#snapshot_name;
+                let mut mask = chakra_core::model::FieldMask::default();
+                #(#checks)*
+                Some(mask)
+            }
+        }
+    });
+
+    // Relationships start unloaded
+    let relations = attrs.relations();
+    let from_row_relations: Vec<_> = relations
+        .iter()
+        .map(|f| {
+            let field_name = f.field_name();
+            quote! {
+                #field_name: chakra_core::model::Related::new()
+            }
         })
         .collect();
+    let relation_metas = relations
+        .iter()
+        .map(|f| f.to_relation_meta())
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    // Generate related_meta() and set_related() methods
+    let mut related_meta_arms = Vec::new();
+    let mut set_related_arms = Vec::new();
+    for f in &relations {
+        let field_name = f.field_name();
+        let name = field_name.to_string();
+        let (target, many) = f.relation_target()?;
+        related_meta_arms.push(quote! {
+            #name => Some(<#target as chakra_core::model::Model>::meta())
+        });
+        let loaded = if many {
+            quote! {
+                chakra_core::model::Related::loaded(
+                    rows.iter()
+                        .map(<#target as chakra_core::model::Model>::from_row)
+                        .collect::<chakra_core::error::Result<Vec<_>>>()?
+                )
+            }
+        } else {
+            quote! {
+                chakra_core::model::Related::loaded_from(
+                    rows.first()
+                        .map(<#target as chakra_core::model::Model>::from_row)
+                        .transpose()?
+                )
+            }
+        };
+        set_related_arms.push(quote! {
+            #name => {
+                self.#field_name = #loaded;
+                Ok(())
+            }
+        });
+    }
+    let relation_impl = (!relations.is_empty()).then(|| {
+        quote! {
+            fn related_meta(name: &str) -> Option<&'static chakra_core::model::ModelMeta> {
+                match name {
+                    #(#related_meta_arms,)*
+                    _ => None,
+                }
+            }
+
+            fn set_related(
+                &mut self,
+                name: &str,
+                rows: &[chakra_core::result::Row],
+            ) -> chakra_core::error::Result<()> {
+                match name {
+                    #(#set_related_arms,)*
+                    _ => Err(chakra_core::error::ChakraError::Model(
+                        chakra_core::error::ModelError::InvalidRelationship {
+                            model: stringify!(#struct_name).to_string(),
+                            relationship: name.to_string(),
+                        },
+                    )),
+                }
+            }
+        }
+    });
 
     // Generate to_values() method
     let to_values_fields: Vec<_> = fields
         .iter()
         .filter(|f| !f.auto_increment) // Skip auto-increment on insert
         .map(|f| {
-            let field_name = f.field_name();
             let col_name = f.column_name();
+            let value = f.to_value_expr();
             quote! {
-                map.insert(#col_name.to_string(), (&self.#field_name).into());
+                map.insert(#col_name.to_string(), #value);
             }
         })
         .collect();
@@ -140,10 +491,10 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
     let get_field_arms: Vec<_> = fields
         .iter()
         .map(|f| {
-            let field_name = f.field_name();
             let col_name = f.column_name();
+            let value = f.to_value_expr();
             quote! {
-                #col_name => Some((&self.#field_name).into())
+                #col_name => Ok(Some(#value))
             }
         })
         .collect();
@@ -154,16 +505,98 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
         .map(|f| {
             let field_name = f.field_name();
             let col_name = f.column_name();
-            let ty = &f.ty;
+            let decode = f.decode_value_expr();
             quote! {
                 #col_name => {
-                    self.#field_name = chakra_core::result::FromValue::from_value(&value)?;
+                    self.#field_name = #decode;
                     Ok(())
                 }
             }
         })
         .collect();
 
+    // Generate validate() from the fields' checks
+    let validations: Vec<_> = fields.iter().filter_map(|f| f.validate_expr()).collect();
+    let validate_impl = (!validations.is_empty()).then(|| {
+        quote! {
+            fn validate(&self) -> std::result::Result<(), chakra_core::error::ValidationError> {
+                let mut errors = Vec::new();
+                #(#validations)*
+                chakra_core::validate::collect(errors)
+            }
+        }
+    });
+
+    // Generate default_scope() and named scope functions
+    let default_scope_impl = attrs.default_scope.as_ref().map(|filter| {
+        quote! {
+            fn default_scope() -> Option<chakra_core::expr::Expr> {
+                Some(chakra_core::expr::Expr::raw(#filter))
+            }
+        }
+    });
+    let tenant_impl = match &attrs.tenant_scoped {
+        Some(column) => {
+            let column = column.clone().unwrap_or_else(|| "tenant_id".to_string());
+            if !fields.iter().any(|f| f.column_name() == column) {
+                return Err(syn::Error::new(
+                    struct_name.span(),
+                    format!("tenant_scoped model has no `{}` column", column),
+                ));
+            }
+            Some(quote! {
+                fn tenant_column() -> Option<&'static str> {
+                    Some(#column)
+                }
+            })
+        }
+        None => None,
+    };
+    let index_metas = attrs
+        .indexes
+        .iter()
+        .map(|index| index.to_index_meta(&table_name, &fields, struct_name.span()))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let scope_fns: Vec<_> = attrs
+        .scopes
+        .iter()
+        .map(|scope| {
+            let name = &scope.name;
+            let filter = &scope.filter;
+            quote! {
+                pub fn #name() -> chakra_core::query::QueryBuilder {
+                    <Self as chakra_core::model::Model>::query()
+                        .filter(chakra_core::expr::Expr::raw(#filter))
+                }
+            }
+        })
+        .collect();
+
+    // Typed column references, one per field
+    let vis = &attrs.vis;
+    let columns_name = Ident::new(&format!("{}Columns", struct_name), struct_name.span());
+    let columns_doc = format!("Typed columns of [`{0}`], from `{0}::col()`", struct_name);
+    let column_fields: Vec<_> = fields
+        .iter()
+        .map(|f| {
+            let field_name = f.field_name();
+            let ty = f.inner_type();
+            quote! {
+                pub #field_name: chakra_core::expr::Column<#struct_name, #ty>
+            }
+        })
+        .collect();
+    let column_inits: Vec<_> = fields
+        .iter()
+        .map(|f| {
+            let field_name = f.field_name();
+            let col_name = f.column_name();
+            quote! {
+                #field_name: chakra_core::expr::Column::new(#col_name)
+            }
+        })
+        .collect();
+
     // Primary key column names
     let pk_columns: Vec<_> = pk_fields.iter().map(|f| f.column_name()).collect();
 
@@ -196,11 +629,14 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
                         name: stringify!(#struct_name).to_string(),
                         table: #table_name.to_string(),
                         schema: #schema,
+                        database: #database,
+                        shard_key: #shard_key,
                         primary_key: vec![#(#pk_columns.to_string()),*],
                         fields: Self::fields().to_vec(),
-                        indexes: Vec::new(),
+                        indexes: vec![#(#index_metas),*],
                         constraints: Vec::new(),
-                        relationships: Vec::new(),
+                        relationships: vec![#(#relation_metas),*],
+                        view: #view,
                     }
                 })
             }
@@ -214,21 +650,26 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
             #pk_impl
 
             fn from_row(row: &chakra_core::result::Row) -> chakra_core::error::Result<Self> {
-                Ok(Self {
-                    #(#from_row_fields),*
-                })
+                #[allow(unused_mut)]
+                let mut model = Self {
+                    #(#from_row_fields,)*
+                    #(#from_row_relations,)*
+                    #(#from_row_snapshot,)*
+                };
+                #mark_clean
+                Ok(model)
             }
 
-            fn to_values(&self) -> std::collections::HashMap<String, chakra_core::types::Value> {
+            fn to_values(&self) -> chakra_core::error::Result<std::collections::HashMap<String, chakra_core::types::Value>> {
                 let mut map = std::collections::HashMap::new();
                 #(#to_values_fields)*
-                map
+                Ok(map)
             }
 
-            fn get_field(&self, name: &str) -> Option<chakra_core::types::Value> {
+            fn get_field(&self, name: &str) -> chakra_core::error::Result<Option<chakra_core::types::Value>> {
                 match name {
                     #(#get_field_arms,)*
-                    _ => None,
+                    _ => Ok(None),
                 }
             }
 
@@ -240,6 +681,33 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
                     )),
                 }
             }
+
+            #default_scope_impl
+
+            #tenant_impl
+
+            #relation_impl
+
+            #snapshot_impl
+
+            #validate_impl
+        }
+
+        #[doc = #columns_doc]
+        #[derive(Debug, Clone, Copy)]
+        #vis struct #columns_name {
+            #(#column_fields),*
+        }
+
+        impl #struct_name {
+            /// Typed references to this model's columns
+            pub fn col() -> #columns_name {
+                #columns_name {
+                    #(#column_inits),*
+                }
+            }
+
+            #(#scope_fns)*
         }
 
         // Also implement FromRow
@@ -247,6 +715,12 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
             fn from_row(row: &chakra_core::result::Row) -> chakra_core::error::Result<Self> {
                 <Self as chakra_core::model::Model>::from_row(row)
             }
+
+            fn all_null(row: &chakra_core::result::Row) -> bool {
+                <Self as chakra_core::model::Model>::fields()
+                    .iter()
+                    .all(|f| row.get(f.column_name()).map_or(true, chakra_core::types::Value::is_null))
+            }
         }
     };
 
@@ -254,10 +728,7 @@ pub fn expand_model(input: DeriveInput) -> syn::Result<TokenStream> {
 }
 
 /// Expand the model attribute macro
-pub fn expand_model_attribute(
-    _attr: TokenStream,
-    input: DeriveInput,
-) -> syn::Result<TokenStream> {
+pub fn expand_model_attribute(_attr: TokenStream, input: DeriveInput) -> syn::Result<TokenStream> {
     // For now, just forward to the derive macro
     // In a full implementation, we could parse additional attributes here
     let derive_impl = expand_model(input.clone())?;
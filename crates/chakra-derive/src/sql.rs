@@ -0,0 +1,307 @@
+//! The `sql!` macro: SQL with its parameters, checked at compile time
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::BTreeSet;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, LitStr, Token};
+
+/// `"SQL", positional..., name = value...`
+pub struct SqlInput {
+    sql: LitStr,
+    positional: Vec<Expr>,
+    named: Vec<(String, Expr)>,
+}
+
+impl Parse for SqlInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let sql: LitStr = input.parse()?;
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            for arg in Punctuated::<Expr, Token![,]>::parse_terminated(input)? {
+                match arg {
+                    Expr::Assign(assign) => {
+                        let Expr::Path(ref path) = *assign.left else {
+                            return Err(syn::Error::new_spanned(
+                                assign.left,
+                                "expected a parameter name",
+                            ));
+                        };
+                        let Some(name) = path.path.get_ident() else {
+                            return Err(syn::Error::new_spanned(path, "expected a parameter name"));
+                        };
+                        named.push((name.to_string(), *assign.right));
+                    }
+                    arg if named.is_empty() => positional.push(arg),
+                    arg => {
+                        return Err(syn::Error::new_spanned(
+                            arg,
+                            "positional parameters must come before named ones",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            sql,
+            positional,
+            named,
+        })
+    }
+}
+
+/// Expand `sql!` into a `SqlFragment`
+pub fn expand_sql(input: SqlInput) -> syn::Result<TokenStream> {
+    let text = input.sql.value();
+    let placeholders = placeholders(&text);
+
+    let mut indexes = BTreeSet::new();
+    let mut names = BTreeSet::new();
+    for placeholder in &placeholders {
+        match placeholder {
+            Placeholder::Index(n) => {
+                indexes.insert(*n);
+            }
+            Placeholder::Name(name) => {
+                names.insert(name.as_str());
+            }
+            // A MySQL user variable unless a parameter is bound to it
+            Placeholder::AtName(name) => {
+                if input.named.iter().any(|(n, _)| n == name) {
+                    names.insert(name.as_str());
+                }
+            }
+        }
+    }
+
+    let given = input.positional.len();
+    if let Some(&n) = indexes.iter().find(|&&n| n == 0 || n > given) {
+        return Err(syn::Error::new_spanned(
+            &input.sql,
+            format!("placeholder ${} has no parameter: {} given", n, given),
+        ));
+    }
+    if let Some((i, arg)) = input
+        .positional
+        .iter()
+        .enumerate()
+        .find(|(i, _)| !indexes.contains(&(i + 1)))
+    {
+        return Err(syn::Error::new_spanned(
+            arg,
+            format!(
+                "parameter {} is not used: the SQL has no ${} placeholder",
+                i + 1,
+                i + 1
+            ),
+        ));
+    }
+    for name in &names {
+        if !input.named.iter().any(|(n, _)| n == name) {
+            return Err(syn::Error::new_spanned(
+                &input.sql,
+                format!("placeholder :{} has no parameter", name),
+            ));
+        }
+    }
+    for (name, value) in &input.named {
+        if !names.contains(name.as_str()) {
+            return Err(syn::Error::new_spanned(
+                value,
+                format!(
+                    "parameter {} is not used: the SQL has no :{} placeholder",
+                    name, name
+                ),
+            ));
+        }
+    }
+
+    let sql = &input.sql;
+    let positional = &input.positional;
+    let binds = input
+        .named
+        .iter()
+        .map(|(name, value)| quote! { .bind(#name, #value) });
+    Ok(quote! {
+        chakra_core::sql::SqlFragment::with_params(
+            #sql,
+            vec![#(chakra_core::types::Value::from(#positional)),*],
+        )
+        #(#binds)*
+    })
+}
+
+/// A placeholder found in the SQL
+#[derive(Debug, PartialEq)]
+enum Placeholder {
+    /// `$N`, `?N`, or `?` numbered in order
+    Index(usize),
+    /// `:name`
+    Name(String),
+    /// `@name`, a placeholder on SQLite but a user variable on MySQL
+    AtName(String),
+}
+
+/// Placeholders in `sql`, skipping literals, quoted identifiers and comments
+///
+/// Follows the runtime tokenizer in `chakra_core::format`.
+fn placeholders(sql: &str) -> Vec<Placeholder> {
+    let bytes = sql.as_bytes();
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80;
+    let digits = |start: usize| {
+        let end = bytes[start..]
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .map_or(bytes.len(), |p| start + p);
+        (end, sql[start..end].parse::<usize>().ok())
+    };
+
+    let mut found = Vec::new();
+    let mut next = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => i = skip_quoted(bytes, i, quote),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |p| i + p + 1);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = sql[i + 2..].find("*/").map_or(bytes.len(), |p| i + p + 4);
+            }
+            b'$' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let (end, n) = digits(i + 1);
+                found.extend(n.map(Placeholder::Index));
+                i = end;
+            }
+            b'$' => i = dollar_quote_end(sql, i).unwrap_or(i + 1),
+            b'?' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let (end, n) = digits(i + 1);
+                found.extend(n.map(Placeholder::Index));
+                i = end;
+            }
+            b'?' => {
+                next += 1;
+                found.push(Placeholder::Index(next));
+                i += 1;
+            }
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            // MySQL system variables
+            b'@' if bytes.get(i + 1) == Some(&b'@') => {
+                i = bytes[i + 2..]
+                    .iter()
+                    .position(|&b| !is_word(b) && b != b'.')
+                    .map_or(bytes.len(), |p| i + 2 + p);
+            }
+            b':' | b'@'
+                if bytes
+                    .get(i + 1)
+                    .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_') =>
+            {
+                let end = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| !is_word(b))
+                    .map_or(bytes.len(), |p| i + 1 + p);
+                let name = sql[i + 1..end].to_string();
+                found.push(if bytes[i] == b'@' {
+                    Placeholder::AtName(name)
+                } else {
+                    Placeholder::Name(name)
+                });
+                i = end;
+            }
+            b if is_word(b) => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|&b| !is_word(b))
+                    .map_or(bytes.len(), |p| i + p);
+            }
+            _ => i += 1,
+        }
+    }
+    found
+}
+
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn dollar_quote_end(sql: &str, start: usize) -> Option<usize> {
+    let tag_len = sql[start + 1..].find('$')? + 2;
+    let tag = &sql[start..start + tag_len];
+    if !tag[1..tag_len - 1]
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    {
+        return None;
+    }
+    let body = start + tag_len;
+    Some(
+        sql[body..]
+            .find(tag)
+            .map_or(sql.len(), |p| body + p + tag_len),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders() {
+        let found = placeholders(
+            "SELECT '$9', \"?\" FROM t -- :skip\n WHERE a = $2 AND b::int = :b AND c = $$ ? $$ /* @x */ AND d = $1",
+        );
+        assert_eq!(
+            found,
+            vec![
+                Placeholder::Index(2),
+                Placeholder::Name("b".to_string()),
+                Placeholder::Index(1)
+            ]
+        );
+
+        let found = placeholders("SELECT ? , ?, ?5");
+        assert_eq!(
+            found,
+            vec![
+                Placeholder::Index(1),
+                Placeholder::Index(2),
+                Placeholder::Index(5)
+            ]
+        );
+
+        let found = placeholders("SELECT @@session.time_zone, @name");
+        assert_eq!(found, vec![Placeholder::AtName("name".to_string())]);
+    }
+
+    #[test]
+    fn test_expand_sql_checks_params() {
+        let check = |tokens: TokenStream| expand_sql(syn::parse2(tokens).unwrap());
+
+        assert!(check(quote! { "SELECT $1, $2, $1", a, b }).is_ok());
+        assert!(check(quote! { "SELECT :id", id = 1 }).is_ok());
+
+        let err = check(quote! { "SELECT $1, $2", a }).unwrap_err();
+        assert_eq!(err.to_string(), "placeholder $2 has no parameter: 1 given");
+        let err = check(quote! { "SELECT $1", a, b }).unwrap_err();
+        assert!(err.to_string().contains("parameter 2 is not used"));
+        assert!(check(quote! { "SELECT :id" }).is_err());
+        assert!(check(quote! { "SELECT @program_name, @@version" }).is_ok());
+        assert!(check(quote! { "SELECT @id", id = 1 }).is_ok());
+        assert!(check(quote! { "SELECT 1", id = 1 }).is_err());
+        assert!(syn::parse2::<SqlInput>(quote! { "SELECT $1", id = 1, a }).is_err());
+    }
+}
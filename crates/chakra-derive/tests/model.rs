@@ -0,0 +1,327 @@
+use chakra_core::expr::{CompareOp, Expr};
+use chakra_core::model::Model as _;
+use chakra_core::types::Value;
+use chakra_core::Model;
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "users")]
+pub struct User {
+    #[chakra(primary_key, auto_increment)]
+    id: i64,
+    #[chakra(column = "user_name")]
+    name: String,
+    #[chakra(nullable)]
+    email: Option<String>,
+}
+
+#[test]
+fn test_typed_columns() {
+    let cols = User::col();
+    assert_eq!(cols.name.name(), "user_name");
+
+    let Expr::Compare { column, op, value } = cols.name.eq("alice") else {
+        panic!("expected a comparison");
+    };
+    assert_eq!((column.as_str(), op), ("user_name", CompareOp::Eq));
+    assert_eq!(value, Value::String("alice".into()));
+
+    // Nullable fields compare against their inner type
+    assert!(matches!(
+        cols.email.ends_with("@example.com"),
+        Expr::Compare {
+            op: CompareOp::Like,
+            ..
+        }
+    ));
+    assert!(matches!(
+        cols.email.is_null(),
+        Expr::Compare {
+            op: CompareOp::IsNull,
+            ..
+        }
+    ));
+
+    let query = User::query().filter(cols.id.is_in(vec![1, 2])).build();
+    assert!(matches!(query.where_clause, Some(Expr::In { .. })));
+}
+
+#[test]
+fn test_to_values() {
+    let user = User {
+        id: 1,
+        name: "alice".into(),
+        email: None,
+    };
+    let values = user.to_values().unwrap();
+    assert_eq!(values["user_name"], Value::String("alice".into()));
+    assert_eq!(values["email"], Value::Null);
+    assert!(!values.contains_key("id"));
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountId(i64);
+
+impl chakra_core::types::ToValue for AccountId {
+    fn to_value(&self) -> Value {
+        Value::Int64(self.0)
+    }
+}
+
+impl chakra_core::result::FromValue for AccountId {
+    fn from_value(value: &Value) -> chakra_core::error::Result<Self> {
+        i64::from_value(value).map(AccountId)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Email(String);
+
+/// Stores an `Email` lowercased
+mod email_codec {
+    use super::Email;
+    use chakra_core::result::FromValue;
+    use chakra_core::types::Value;
+
+    pub fn to_value(email: &Email) -> Value {
+        Value::String(email.0.to_lowercase())
+    }
+
+    pub fn from_value(value: &Value) -> chakra_core::error::Result<Email> {
+        String::from_value(value).map(Email)
+    }
+}
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "accounts")]
+pub struct Account {
+    #[chakra(primary_key)]
+    id: AccountId,
+    parent_id: Option<AccountId>,
+    #[chakra(with = "email_codec")]
+    email: Email,
+    #[chakra(with = "email_codec")]
+    backup_email: Option<Email>,
+}
+
+#[test]
+fn test_custom_value_types() {
+    use chakra_core::result::Row;
+
+    let account = Account {
+        id: AccountId(7),
+        parent_id: None,
+        email: Email("Ann@Example.com".into()),
+        backup_email: Some(Email("ANN@backup.io".into())),
+    };
+    let values = account.to_values().unwrap();
+    assert_eq!(values["id"], Value::Int64(7));
+    assert_eq!(values["parent_id"], Value::Null);
+    assert_eq!(values["email"], Value::String("ann@example.com".into()));
+    assert_eq!(
+        values["backup_email"],
+        Value::String("ann@backup.io".into())
+    );
+
+    let row = Row::new(
+        vec![
+            "id".into(),
+            "parent_id".into(),
+            "email".into(),
+            "backup_email".into(),
+        ],
+        vec![
+            Value::Int64(8),
+            Value::Int64(7),
+            Value::String("bo@example.com".into()),
+            Value::Null,
+        ],
+    );
+    let mut loaded = Account::from_row(&row).unwrap();
+    assert_eq!(loaded.id, AccountId(8));
+    assert_eq!(loaded.parent_id, Some(AccountId(7)));
+    assert_eq!(loaded.email, Email("bo@example.com".into()));
+    assert_eq!(loaded.backup_email, None);
+
+    loaded
+        .set_field("backup_email", Value::String("bo@backup.io".into()))
+        .unwrap();
+    assert_eq!(loaded.backup_email, Some(Email("bo@backup.io".into())));
+    assert_eq!(
+        loaded.get_field("parent_id").unwrap(),
+        Some(Value::Int64(7))
+    );
+}
+
+#[derive(chakra_core::DbEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OrderStatus {
+    #[default]
+    Pending,
+    InTransit,
+    #[chakra(rename = "cancelled_by_user")]
+    Cancelled,
+}
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "orders")]
+pub struct Order {
+    #[chakra(primary_key)]
+    id: i64,
+    #[chakra(db_enum = "order_status")]
+    status: OrderStatus,
+    #[chakra(db_enum = "order_status")]
+    previous_status: Option<OrderStatus>,
+}
+
+#[test]
+fn test_db_enum() {
+    use chakra_core::result::{FromValue, Row};
+    use chakra_core::types::{DbEnum, FieldType};
+
+    assert_eq!(
+        OrderStatus::VARIANTS,
+        ["pending", "in_transit", "cancelled_by_user"]
+    );
+    assert_eq!(
+        OrderStatus::from_label("in_transit"),
+        Some(OrderStatus::InTransit)
+    );
+    assert!(OrderStatus::from_value(&Value::String("lost".into())).is_err());
+
+    let field = Order::meta().get_field("status").unwrap();
+    assert_eq!(
+        field.field_type,
+        FieldType::Enum {
+            values: vec![
+                "pending".into(),
+                "in_transit".into(),
+                "cancelled_by_user".into()
+            ],
+            name: Some("order_status".into()),
+        }
+    );
+    assert!(Order::meta().get_field("previous_status").unwrap().nullable);
+
+    let order = Order {
+        id: 1,
+        status: OrderStatus::Cancelled,
+        previous_status: None,
+    };
+    let values = order.to_values().unwrap();
+    assert_eq!(values["status"], Value::String("cancelled_by_user".into()));
+    assert_eq!(values["previous_status"], Value::Null);
+
+    let row = Row::new(
+        vec!["id".into(), "status".into(), "previous_status".into()],
+        vec![
+            Value::Int64(2),
+            Value::String("in_transit".into()),
+            Value::String("pending".into()),
+        ],
+    );
+    let loaded = Order::from_row(&row).unwrap();
+    assert_eq!(loaded.status, OrderStatus::InTransit);
+    assert_eq!(loaded.previous_status, Some(OrderStatus::Pending));
+}
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "profiles")]
+pub struct Profile {
+    #[chakra(primary_key)]
+    id: i64,
+    #[chakra(column = "displayName")]
+    display_name: String,
+}
+
+#[test]
+fn test_folded_column_names() {
+    use chakra_core::identifier::{set_identifier_policy, IdentifierPolicy};
+    use chakra_core::query::Query;
+    use chakra_core::result::Row;
+    use chakra_core::sql::{Dialect, PostgresDialect};
+
+    set_identifier_policy(IdentifierPolicy::FoldLower);
+    let query = Query::select()
+        .from(Profile::table_name())
+        .columns(&["id", "displayName"])
+        .build();
+    let sql = PostgresDialect.generate(&query).sql;
+
+    // PostgreSQL reports the column as stored
+    let row = Row::new(
+        vec!["id".into(), "displayname".into()],
+        vec![Value::Int64(1), Value::String("Ann".into())],
+    );
+    let profile = Profile::from_row(&row);
+    set_identifier_policy(IdentifierPolicy::AlwaysQuote);
+
+    assert_eq!(sql, "SELECT \"id\", \"displayname\" FROM \"profiles\"");
+    assert_eq!(profile.unwrap().display_name, "Ann");
+}
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "patients")]
+pub struct Patient {
+    #[chakra(primary_key)]
+    id: i64,
+    diagnosis: chakra_core::encryption::Encrypted<String>,
+    #[chakra(encrypted)]
+    ssn: Option<String>,
+}
+
+#[test]
+fn test_encrypted_without_keyring() {
+    use chakra_core::error::ErrorCode;
+
+    // No test here sets a keyring, so writes fail instead of storing
+    // plain text
+    let patient = Patient {
+        id: 1,
+        diagnosis: "flu".to_string().into(),
+        ssn: Some("123-45-6789".into()),
+    };
+    let err = patient.to_values().unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Config);
+    assert!(err.to_string().contains("No keyring set"));
+    assert_eq!(
+        patient.get_field("ssn").unwrap_err().code(),
+        ErrorCode::Config
+    );
+    assert_eq!(patient.get_field("id").unwrap(), Some(Value::Int64(1)));
+}
+
+#[derive(Model, Debug, Clone, Default)]
+#[chakra(table = "shipments")]
+pub struct Shipment {
+    #[chakra(primary_key, generated = "snowflake:7")]
+    id: i64,
+    #[chakra(generated = "nanoid:12")]
+    public_id: String,
+}
+
+#[test]
+fn test_generated_ids() {
+    use chakra_core::id::IdStrategy;
+    use chakra_core::model::FieldDefault;
+
+    let strategies: Vec<_> = Shipment::fields()
+        .iter()
+        .map(|f| match &f.default {
+            Some(FieldDefault::Generated(strategy)) => strategy.clone(),
+            other => panic!("expected a generated id, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        strategies,
+        [
+            IdStrategy::Snowflake {
+                machine_id: Some(7)
+            },
+            IdStrategy::NanoId { length: 12 },
+        ]
+    );
+
+    let mut shipment = Shipment::default();
+    let values = shipment.prepare_insert().unwrap();
+    assert_ne!(values["id"], Value::Int64(0));
+    assert_eq!(shipment.public_id.len(), 12);
+}
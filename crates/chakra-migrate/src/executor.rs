@@ -1,10 +1,12 @@
 //! Migration executor for applying and rolling back migrations
 
 use crate::history::{MigrationHistory, MigrationRecord};
-use crate::migration::{Migration, MigrationDirection, MigrationResult, MigrationStatus};
+use crate::migration::{Migration, MigrationDirection, MigrationResult};
 use crate::planner::PlannedMigration;
 use async_trait::async_trait;
 use chakra_core::error::{ChakraError, Result};
+use chakra_core::format::SqlFormatter;
+use chakra_core::transaction::SavepointOp;
 use chakra_schema::ddl::{DdlGenerator, DdlStatement};
 use std::time::Instant;
 use tracing::{debug, error, info, warn};
@@ -26,6 +28,52 @@ pub trait SqlExecutor: Send + Sync {
 
     /// Rollback a transaction
     async fn rollback_transaction(&self) -> Result<()>;
+
+    /// Create a savepoint in the open transaction
+    async fn begin_savepoint(&self, name: &str) -> Result<()> {
+        self.execute(&SavepointOp::Begin.sql(name)?).await?;
+        Ok(())
+    }
+
+    /// Release a savepoint, keeping its changes
+    async fn release_savepoint(&self, name: &str) -> Result<()> {
+        self.execute(&SavepointOp::Release.sql(name)?).await?;
+        Ok(())
+    }
+
+    /// Undo everything since a savepoint, which stays open
+    async fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        self.execute(&SavepointOp::RollbackTo.sql(name)?).await?;
+        Ok(())
+    }
+
+    /// Run steps on one connection in a transaction that is always rolled back
+    ///
+    /// Stops at the first statement that errors or verification query that
+    /// returns rows. Only conclusive where DDL is transactional.
+    async fn speculate(&self, _steps: &[SpeculativeStep]) -> Result<SpeculativeRun> {
+        Err(ChakraError::config(
+            "Speculative runs are not supported by this executor",
+        ))
+    }
+}
+
+/// A statement in a speculative run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpeculativeStep {
+    /// A migration statement
+    Execute(String),
+    /// A verification query that must return no rows
+    Verify(String),
+}
+
+/// The outcome of `SqlExecutor::speculate`
+#[derive(Debug, Clone, Default)]
+pub struct SpeculativeRun {
+    /// Affected rows of each completed statement, or rows returned by a verification
+    pub counts: Vec<u64>,
+    /// Index of the step that failed, with the reason
+    pub failed: Option<(usize, String)>,
 }
 
 /// Migration executor
@@ -40,6 +88,8 @@ pub struct MigrationExecutor<'a> {
     use_transactions: bool,
     /// Whether to run in dry-run mode
     dry_run: bool,
+    /// Formatter for dry-run output
+    formatter: SqlFormatter,
 }
 
 impl<'a> MigrationExecutor<'a> {
@@ -55,6 +105,7 @@ impl<'a> MigrationExecutor<'a> {
             history,
             use_transactions: true,
             dry_run: false,
+            formatter: SqlFormatter::default(),
         }
     }
 
@@ -70,6 +121,39 @@ impl<'a> MigrationExecutor<'a> {
         self
     }
 
+    /// Set the formatter used for dry-run output
+    pub fn formatter(mut self, formatter: SqlFormatter) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    /// Render the SQL a plan would run as a formatted script
+    pub fn render_plan(&self, plan: &[PlannedMigration]) -> String {
+        let mut out = String::new();
+
+        for planned in plan {
+            let migration = &planned.migration;
+            let statements = match planned.direction {
+                MigrationDirection::Up => self.generate_up_statements(migration),
+                MigrationDirection::Down => self.generate_down_statements(migration),
+            };
+
+            out.push_str(&format!(
+                "-- {} {} ({})\n\n",
+                migration.id, planned.direction, migration.name
+            ));
+            for stmt in &statements {
+                if let Some(ref description) = stmt.description {
+                    out.push_str(&format!("-- {}\n", description));
+                }
+                out.push_str(&self.formatter.format(&stmt.sql));
+                out.push_str(";\n\n");
+            }
+        }
+
+        out
+    }
+
     /// Execute a plan
     pub async fn execute_plan(&self, plan: &[PlannedMigration]) -> Vec<MigrationResult> {
         let mut results = Vec::new();
@@ -89,7 +173,10 @@ impl<'a> MigrationExecutor<'a> {
             results.push(result);
 
             if !success {
-                warn!("Migration {} failed, stopping execution", planned.migration.id);
+                warn!(
+                    "Migration {} failed, stopping execution",
+                    planned.migration.id
+                );
                 break;
             }
         }
@@ -102,6 +189,80 @@ impl<'a> MigrationExecutor<'a> {
         results
     }
 
+    /// Apply a plan and run its verification queries, then roll it all back
+    ///
+    /// Nothing is recorded in the history. Results stop at the first
+    /// migration that fails.
+    pub async fn test_plan(&self, plan: &[PlannedMigration]) -> Result<Vec<MigrationResult>> {
+        let start = Instant::now();
+        let mut steps = Vec::new();
+        let mut owners = Vec::new();
+
+        for (i, planned) in plan.iter().enumerate() {
+            let migration = &planned.migration;
+            let statements = match planned.direction {
+                MigrationDirection::Up => self.generate_up_statements(migration),
+                MigrationDirection::Down => self.generate_down_statements(migration),
+            };
+            steps.extend(
+                statements
+                    .into_iter()
+                    .map(|s| SpeculativeStep::Execute(s.sql)),
+            );
+            if planned.direction == MigrationDirection::Up {
+                steps.extend(
+                    migration
+                        .verify
+                        .iter()
+                        .cloned()
+                        .map(SpeculativeStep::Verify),
+                );
+            }
+            owners.resize(steps.len(), i);
+        }
+
+        let run = self.executor.speculate(&steps).await?;
+        let duration_ms = start.elapsed().as_millis() as u64;
+        info!(
+            "Tested {} migrations in {}ms, rolled back",
+            plan.len(),
+            duration_ms
+        );
+
+        let failed_owner = run.failed.as_ref().map(|(index, _)| owners[*index]);
+        let mut results = Vec::new();
+        for (i, planned) in plan.iter().enumerate() {
+            let executed = steps
+                .iter()
+                .zip(&owners)
+                .take(run.counts.len())
+                .filter(|(step, owner)| **owner == i && matches!(step, SpeculativeStep::Execute(_)))
+                .count();
+            let error = match (&run.failed, failed_owner) {
+                (Some((index, reason)), Some(owner)) if owner == i => Some(match &steps[*index] {
+                    SpeculativeStep::Execute(sql) | SpeculativeStep::Verify(sql) => {
+                        format!("{}: {}", reason, sql)
+                    }
+                }),
+                _ => None,
+            };
+
+            results.push(MigrationResult {
+                migration_id: planned.migration.id.clone(),
+                direction: planned.direction,
+                success: error.is_none(),
+                error,
+                duration_ms,
+                statements_executed: executed,
+            });
+            if failed_owner == Some(i) {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+
     /// Execute a single migration
     async fn execute_one(&self, planned: &PlannedMigration) -> MigrationResult {
         let migration = &planned.migration;
@@ -110,9 +271,7 @@ impl<'a> MigrationExecutor<'a> {
 
         info!(
             "Running migration {} {} ({})",
-            migration.id,
-            direction,
-            migration.name
+            migration.id, direction, migration.name
         );
 
         // Generate SQL statements
@@ -124,7 +283,7 @@ impl<'a> MigrationExecutor<'a> {
         if self.dry_run {
             info!("DRY RUN: Would execute {} statements", statements.len());
             for (i, stmt) in statements.iter().enumerate() {
-                debug!("  {}: {}", i + 1, stmt.sql);
+                info!("  {}:\n{};", i + 1, self.formatter.format(&stmt.sql));
             }
             return MigrationResult {
                 migration_id: migration.id.clone(),
@@ -182,8 +341,8 @@ impl<'a> MigrationExecutor<'a> {
                 error!("Migration {} failed: {}", migration.id, e);
 
                 // Record failure
-                let record = MigrationRecord::new(&migration.id, &migration.name)
-                    .failed(e.to_string());
+                let record =
+                    MigrationRecord::new(&migration.id, &migration.name).failed(e.to_string());
 
                 if let Err(e) = self.history.record_applied(record).await {
                     error!("Failed to record migration failure: {}", e);
@@ -207,7 +366,7 @@ impl<'a> MigrationExecutor<'a> {
 
         // Add raw SQL if present
         if let Some(ref sql) = migration.raw_sql_up {
-            statements.push(DdlStatement::new(sql));
+            statements.extend(self.raw_statements(sql));
         }
 
         // Generate from operations
@@ -224,7 +383,7 @@ impl<'a> MigrationExecutor<'a> {
 
         // Add raw SQL if present
         if let Some(ref sql) = migration.raw_sql_down {
-            statements.push(DdlStatement::new(sql));
+            statements.extend(self.raw_statements(sql));
         }
 
         // Generate from operations in reverse order
@@ -253,6 +412,18 @@ impl<'a> MigrationExecutor<'a> {
             (DropTable { name, cascade }, MigrationDirection::Up) => {
                 vec![self.ddl_generator.drop_table(name, *cascade)]
             }
+            (
+                TruncateTable {
+                    name,
+                    cascade,
+                    restart_identity,
+                },
+                MigrationDirection::Up,
+            ) => {
+                vec![self
+                    .ddl_generator
+                    .truncate_table(name, *cascade, *restart_identity)]
+            }
             (RenameTable { from, to }, MigrationDirection::Up) => {
                 vec![self.ddl_generator.rename_table(from, to)]
             }
@@ -283,7 +454,7 @@ impl<'a> MigrationExecutor<'a> {
             (CreateIndex { table, index }, MigrationDirection::Up) => {
                 vec![self.ddl_generator.create_index(table, index)]
             }
-            (CreateIndex { table, index }, MigrationDirection::Down) => {
+            (CreateIndex { index, .. }, MigrationDirection::Down) => {
                 vec![self.ddl_generator.drop_index(&index.name)]
             }
             (DropIndex { name }, MigrationDirection::Up) => {
@@ -311,18 +482,46 @@ impl<'a> MigrationExecutor<'a> {
             (DropForeignKey { table, name }, MigrationDirection::Up) => {
                 vec![self.ddl_generator.drop_foreign_key(table, name)]
             }
-            (RawSql { up, down }, MigrationDirection::Up) => {
-                vec![DdlStatement::new(up)]
+            (CreateView(view), MigrationDirection::Up)
+            | (DropView(view), MigrationDirection::Down) => {
+                vec![self.ddl_generator.create_view(view)]
             }
-            (RawSql { up, down }, MigrationDirection::Down) => {
-                down.as_ref()
-                    .map(|sql| vec![DdlStatement::new(sql)])
-                    .unwrap_or_default()
+            (CreateView(view), MigrationDirection::Down)
+            | (DropView(view), MigrationDirection::Up) => {
+                vec![self.ddl_generator.drop_view(&view.name)]
             }
+            (CreateType(custom_type), MigrationDirection::Up) => {
+                vec![self.ddl_generator.create_type(custom_type)]
+            }
+            (CreateType(custom_type), MigrationDirection::Down) => {
+                vec![self.ddl_generator.drop_type(custom_type.name())]
+            }
+            (AddEnumValue(value), MigrationDirection::Up) => {
+                vec![self.ddl_generator.add_enum_value(
+                    &value.type_name,
+                    &value.value,
+                    value.after.as_deref(),
+                )]
+            }
+            (RawSql { up, .. }, MigrationDirection::Up) => self.raw_statements(up),
+            (RawSql { down, .. }, MigrationDirection::Down) => down
+                .as_ref()
+                .map(|sql| self.raw_statements(sql))
+                .unwrap_or_default(),
             _ => vec![],
         }
     }
 
+    /// Split a raw SQL script into individual statements
+    fn raw_statements(&self, sql: &str) -> Vec<DdlStatement> {
+        self.ddl_generator
+            .script_splitter()
+            .split(sql)
+            .into_iter()
+            .map(DdlStatement::new)
+            .collect()
+    }
+
     /// Execute statements with a transaction
     async fn execute_with_transaction(&self, statements: &[DdlStatement]) -> Result<usize> {
         self.executor.begin_transaction().await?;
@@ -404,6 +603,32 @@ mod tests {
             self.statements.lock().await.push("ROLLBACK".to_string());
             Ok(())
         }
+
+        async fn speculate(&self, steps: &[SpeculativeStep]) -> Result<SpeculativeRun> {
+            let mut statements = self.statements.lock().await;
+            let mut run = SpeculativeRun::default();
+            statements.push("BEGIN".to_string());
+            for (i, step) in steps.iter().enumerate() {
+                match step {
+                    SpeculativeStep::Execute(sql) => {
+                        statements.push(sql.clone());
+                        run.counts.push(0);
+                    }
+                    SpeculativeStep::Verify(sql) => {
+                        statements.push(sql.clone());
+                        let rows = u64::from(sql.contains("orphan"));
+                        run.counts.push(rows);
+                        if rows > 0 {
+                            run.failed =
+                                Some((i, format!("verification returned {} row(s)", rows)));
+                            break;
+                        }
+                    }
+                }
+            }
+            statements.push("ROLLBACK".to_string());
+            Ok(run)
+        }
     }
 
     #[tokio::test]
@@ -412,11 +637,11 @@ mod tests {
         let ddl_gen = PostgresDdlGenerator;
         let history = InMemoryHistory::new();
 
-        let migration = Migration::new("001", "create_users")
-            .operation(chakra_schema::diff::MigrationOperation::CreateTable(
-                Table::new("users")
-                    .column(Column::new("id", ColumnType::BigSerial).not_null()),
-            ));
+        let migration = Migration::new("001", "create_users").operation(
+            chakra_schema::diff::MigrationOperation::CreateTable(
+                Table::new("users").column(Column::new("id", ColumnType::BigSerial).not_null()),
+            ),
+        );
 
         let planned = PlannedMigration {
             migration,
@@ -432,4 +657,94 @@ mod tests {
         let stmts = executor.statements.lock().await;
         assert!(stmts.iter().any(|s| s.contains("CREATE TABLE")));
     }
+
+    #[tokio::test]
+    async fn test_raw_sql_is_split() {
+        let executor = MockExecutor::new();
+        let ddl_gen = PostgresDdlGenerator;
+        let history = InMemoryHistory::new();
+
+        let migration = Migration::new("002", "add_trigger").raw_sql(
+            "CREATE FUNCTION touch() RETURNS trigger AS $$ BEGIN RETURN NEW; END; $$ LANGUAGE plpgsql;\n\
+             CREATE TRIGGER users_touch BEFORE UPDATE ON users FOR EACH ROW EXECUTE FUNCTION touch();",
+            None,
+        );
+
+        let planned = PlannedMigration {
+            migration,
+            direction: MigrationDirection::Up,
+        };
+
+        let exec = MigrationExecutor::new(&executor, &ddl_gen, &history);
+        let results = exec.execute_plan(&[planned]).await;
+        assert!(results[0].success);
+
+        let stmts = executor.statements.lock().await;
+        assert!(stmts
+            .iter()
+            .any(|s| s.starts_with("CREATE FUNCTION") && s.ends_with("plpgsql")));
+        assert!(stmts.iter().any(|s| s.starts_with("CREATE TRIGGER")));
+    }
+
+    #[tokio::test]
+    async fn test_plan_is_rolled_back() {
+        let executor = MockExecutor::new();
+        let ddl_gen = PostgresDdlGenerator;
+        let history = InMemoryHistory::new();
+
+        let users = Migration::new("004", "create_users")
+            .operation(chakra_schema::diff::MigrationOperation::CreateTable(
+                Table::new("users").column(Column::new("id", ColumnType::BigSerial).not_null()),
+            ))
+            .verify("SELECT id FROM users WHERE id IS NULL");
+        let posts = Migration::new("005", "backfill_posts")
+            .raw_sql("UPDATE posts SET author_id = 1", None)
+            .verify(
+                "SELECT id FROM posts WHERE author_id NOT IN (SELECT id FROM users) -- orphans",
+            );
+        let plan: Vec<_> = [users, posts]
+            .into_iter()
+            .map(|migration| PlannedMigration {
+                migration,
+                direction: MigrationDirection::Up,
+            })
+            .collect();
+
+        let exec = MigrationExecutor::new(&executor, &ddl_gen, &history);
+        let results = exec.test_plan(&plan).await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert_eq!(results[0].statements_executed, 1);
+        assert!(!results[1].success);
+        assert!(results[1].error.as_deref().unwrap().contains("-- orphans"));
+
+        let stmts = executor.statements.lock().await;
+        assert_eq!(stmts.last().map(String::as_str), Some("ROLLBACK"));
+        assert!(!stmts.iter().any(|s| s == "COMMIT"));
+        assert!(history.get_applied().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_render_plan() {
+        let executor = MockExecutor::new();
+        let ddl_gen = PostgresDdlGenerator;
+        let history = InMemoryHistory::new();
+
+        let migration = Migration::new("003", "backfill").raw_sql(
+            "update users set active = true where created_at < now() and deleted_at is null",
+            None,
+        );
+        let planned = PlannedMigration {
+            migration,
+            direction: MigrationDirection::Up,
+        };
+
+        let exec = MigrationExecutor::new(&executor, &ddl_gen, &history);
+        assert_eq!(
+            exec.render_plan(&[planned]),
+            "-- 003 up (backfill)\n\n\
+             UPDATE users\nSET active = TRUE\nWHERE created_at < now()\n  AND deleted_at IS NULL;\n\n"
+        );
+    }
 }
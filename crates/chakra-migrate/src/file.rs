@@ -28,10 +28,7 @@ impl MigrationFile {
 
     /// Get the filename
     pub fn filename(&self) -> &str {
-        self.path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
+        self.path.file_name().and_then(|n| n.to_str()).unwrap_or("")
     }
 }
 
@@ -96,26 +93,35 @@ impl MigrationLoader {
         Ok(migrations)
     }
 
+    /// Load the migrations of one database, `None` for the default one
+    pub async fn load_database(&self, database: Option<&str>) -> Result<Vec<MigrationFile>> {
+        let mut migrations = self.load_all().await?;
+        migrations.retain(|mf| mf.migration.database.as_deref() == database);
+        Ok(migrations)
+    }
+
     /// Load a single migration file
     pub async fn load_file(&self, path: &Path) -> Result<MigrationFile> {
-        let content = fs::read_to_string(path).await.map_err(|e| {
-            ChakraError::internal(format!("Failed to read migration file: {}", e))
-        })?;
+        let content = fs::read_to_string(path)
+            .await
+            .map_err(|e| ChakraError::internal(format!("Failed to read migration file: {}", e)))?;
 
-        let migration: Migration = toml::from_str(&content).map_err(|e| {
-            ChakraError::internal(format!("Failed to parse migration file: {}", e))
-        })?;
+        let migration: Migration = toml::from_str(&content)
+            .map_err(|e| ChakraError::internal(format!("Failed to parse migration file: {}", e)))?;
 
         Ok(MigrationFile::new(path, migration))
     }
 
     /// Save a migration to disk
     pub async fn save(&self, migration: &Migration, app: Option<&str>) -> Result<PathBuf> {
-        // Determine directory
-        let dir = match app {
-            Some(app_name) => self.root.join(app_name),
-            None => self.root.clone(),
-        };
+        // Determine directory: one per named database, then per app
+        let mut dir = self.root.clone();
+        if let Some(ref database) = migration.database {
+            dir.push(database);
+        }
+        if let Some(app_name) = app {
+            dir.push(app_name);
+        }
 
         // Create directory if needed
         fs::create_dir_all(&dir).await.map_err(|e| {
@@ -127,14 +133,13 @@ impl MigrationLoader {
         let path = dir.join(&filename);
 
         // Serialize to TOML
-        let content = toml::to_string_pretty(migration).map_err(|e| {
-            ChakraError::internal(format!("Failed to serialize migration: {}", e))
-        })?;
+        let content = toml::to_string_pretty(migration)
+            .map_err(|e| ChakraError::internal(format!("Failed to serialize migration: {}", e)))?;
 
         // Write file
-        fs::write(&path, content).await.map_err(|e| {
-            ChakraError::internal(format!("Failed to write migration file: {}", e))
-        })?;
+        fs::write(&path, content)
+            .await
+            .map_err(|e| ChakraError::internal(format!("Failed to write migration file: {}", e)))?;
 
         info!("Saved migration to {:?}", path);
         Ok(path)
@@ -159,11 +164,7 @@ pub fn generate_migration_id() -> String {
 
 /// Generate a new migration ID with sequence number
 pub fn generate_migration_id_seq(seq: u32) -> String {
-    format!(
-        "{}_{}",
-        chrono::Utc::now().format("%Y%m%d"),
-        format!("{:04}", seq)
-    )
+    format!("{}_{:04}", chrono::Utc::now().format("%Y%m%d"), seq)
 }
 
 #[cfg(test)]
@@ -186,6 +187,35 @@ mod tests {
         assert_eq!(loaded.migration.name, "test_migration");
     }
 
+    #[tokio::test]
+    async fn test_database_migrations() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = MigrationLoader::new(temp_dir.path());
+
+        loader
+            .save(&Migration::new("20240101_000000", "users"), None)
+            .await
+            .unwrap();
+        let path = loader
+            .save(
+                &Migration::new("20240102_000000", "page_views").database("analytics"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(path.starts_with(temp_dir.path().join("analytics")));
+
+        let default = loader.load_database(None).await.unwrap();
+        assert_eq!(default.len(), 1);
+        assert_eq!(default[0].migration.name, "users");
+        let analytics = loader.load_database(Some("analytics")).await.unwrap();
+        assert_eq!(analytics.len(), 1);
+        assert_eq!(
+            analytics[0].migration.database.as_deref(),
+            Some("analytics")
+        );
+    }
+
     #[test]
     fn test_migration_id_format() {
         let id = generate_migration_id();
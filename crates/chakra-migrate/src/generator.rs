@@ -2,12 +2,12 @@
 
 use crate::file::generate_migration_id;
 use crate::migration::Migration;
+use chakra_core::identifier::IdentifierPolicy;
 use chakra_core::model::ModelMeta;
+use chakra_core::router::{Routes, DEFAULT_DATABASE};
+use chakra_core::view::View;
 use chakra_schema::diff::{SchemaDiff, SchemaDiffer};
-use chakra_schema::schema::{
-    Column, ColumnDefault, ColumnType, ForeignKey, Index, PrimaryKey, Schema, Table,
-};
-use std::collections::HashMap;
+use chakra_schema::schema::{self, Schema, Table};
 use tracing::{debug, info};
 
 /// Migration generator for auto-detecting schema changes
@@ -19,6 +19,16 @@ pub struct MigrationGenerator {
     pub app: Option<String>,
     /// Tables to exclude from comparison
     pub exclude_tables: Vec<String>,
+    /// Index foreign key columns the models leave unindexed
+    pub index_foreign_keys: bool,
+    /// Named database to generate for, `None` for the default one
+    pub database: Option<String>,
+    /// Database overrides for models, as given to the `Router`
+    pub routes: Routes,
+    /// Views to create alongside the models' tables
+    pub views: Vec<View>,
+    /// Identifier policy of the database, see `SchemaDiffer::identifiers`
+    pub identifiers: IdentifierPolicy,
 }
 
 impl MigrationGenerator {
@@ -28,6 +38,11 @@ impl MigrationGenerator {
             reversible: true,
             app: None,
             exclude_tables: vec!["chakra_migrations".to_string()],
+            index_foreign_keys: false,
+            database: None,
+            routes: Routes::new(),
+            views: Vec::new(),
+            identifiers: IdentifierPolicy::default(),
         }
     }
 
@@ -49,15 +64,63 @@ impl MigrationGenerator {
         self
     }
 
+    /// Create indexes for unindexed foreign key columns
+    pub fn index_foreign_keys(mut self, enabled: bool) -> Self {
+        self.index_foreign_keys = enabled;
+        self
+    }
+
+    /// Compare names under the database's identifier policy
+    pub fn identifiers(mut self, policy: IdentifierPolicy) -> Self {
+        self.identifiers = policy;
+        self
+    }
+
+    /// Generate for the models of a named database only
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        let database = database.into();
+        self.database = (database != DEFAULT_DATABASE).then_some(database);
+        self
+    }
+
+    /// Route models to databases as the application's `Router` does
+    pub fn routes(mut self, routes: Routes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Add a view, created in the database it names
+    pub fn view(mut self, view: View) -> Self {
+        self.views.push(view);
+        self
+    }
+
     /// Generate a migration from model metadata
+    ///
+    /// Only models routed to the generator's database are included, so each
+    /// database gets its own migration set. The same goes for views. Models
+    /// reading from a view get no table.
     pub fn from_models(&self, models: &[&ModelMeta], current_schema: &Schema) -> Option<Migration> {
-        let target_schema = self.models_to_schema(models);
+        let database = self.database.as_deref().unwrap_or(DEFAULT_DATABASE);
+        let models: Vec<&ModelMeta> = models
+            .iter()
+            .copied()
+            .filter(|m| self.routes.database_for(m) == database)
+            .collect();
+        let mut target_schema = self.models_to_schema(&models);
+        for view in &self.views {
+            if view.database.as_deref().unwrap_or(DEFAULT_DATABASE) == database {
+                target_schema.add_view(schema::View::from_view(view));
+            }
+        }
         self.from_schema_diff(current_schema, &target_schema)
     }
 
     /// Generate a migration from a schema diff
     pub fn from_schema_diff(&self, from: &Schema, to: &Schema) -> Option<Migration> {
-        let mut differ = SchemaDiffer::new();
+        let mut differ = SchemaDiffer::new()
+            .index_foreign_keys(self.index_foreign_keys)
+            .identifiers(self.identifiers);
 
         for table in &self.exclude_tables {
             differ = differ.exclude_table(table);
@@ -75,89 +138,124 @@ impl MigrationGenerator {
 
         let mut migration = Migration::new(&id, &name);
         migration.app = self.app.clone();
+        migration.database = self.database.clone();
         migration.reversible = self.reversible;
 
-        // Convert diff to operations
+        // Convert diff to operations, dropping views before what they read
+        for view in &diff.views_to_drop {
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::DropView(
+                    view.clone(),
+                ));
+        }
+
+        // Types come before the tables using them
+        for custom_type in &diff.types_to_create {
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::CreateType(
+                    custom_type.clone(),
+                ));
+        }
+        for value in &diff.enum_values_to_add {
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::AddEnumValue(
+                    value.clone(),
+                ));
+        }
+
         for table in &diff.tables_to_create {
-            migration.operations.push(
-                chakra_schema::diff::MigrationOperation::CreateTable(table.clone()),
-            );
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::CreateTable(
+                    table.clone(),
+                ));
         }
 
         for table_name in &diff.tables_to_drop {
-            migration.operations.push(
-                chakra_schema::diff::MigrationOperation::DropTable {
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::DropTable {
                     name: table_name.clone(),
                     cascade: true,
-                },
-            );
+                });
         }
 
         for table_diff in &diff.table_modifications {
             for column in &table_diff.columns_to_add {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::AddColumn {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::AddColumn {
                         table: table_diff.table_name.clone(),
                         column: column.clone(),
-                    },
-                );
+                    });
             }
 
             for column_name in &table_diff.columns_to_drop {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::DropColumn {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::DropColumn {
                         table: table_diff.table_name.clone(),
                         column: column_name.clone(),
-                    },
-                );
+                    });
             }
 
             for (old, new) in &table_diff.columns_to_modify {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::AlterColumn {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::AlterColumn {
                         table: table_diff.table_name.clone(),
                         from: old.clone(),
                         to: new.clone(),
-                    },
-                );
+                    });
             }
 
             for index in &table_diff.indexes_to_create {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::CreateIndex {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::CreateIndex {
                         table: table_diff.table_name.clone(),
                         index: index.clone(),
-                    },
-                );
+                    });
             }
 
             for index_name in &table_diff.indexes_to_drop {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::DropIndex {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::DropIndex {
                         name: index_name.clone(),
-                    },
-                );
+                    });
             }
 
             for constraint in &table_diff.constraints_to_add {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::AddConstraint {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::AddConstraint {
                         table: table_diff.table_name.clone(),
                         constraint: constraint.clone(),
-                    },
-                );
+                    });
             }
 
             for fk in &table_diff.foreign_keys_to_add {
-                migration.operations.push(
-                    chakra_schema::diff::MigrationOperation::AddForeignKey {
+                migration
+                    .operations
+                    .push(chakra_schema::diff::MigrationOperation::AddForeignKey {
                         table: table_diff.table_name.clone(),
                         foreign_key: fk.clone(),
-                    },
-                );
+                    });
             }
         }
 
+        for view in &diff.views_to_create {
+            migration
+                .operations
+                .push(chakra_schema::diff::MigrationOperation::CreateView(
+                    view.clone(),
+                ));
+        }
+
         let migration = migration.with_checksum();
         info!(
             "Generated migration {} with {} operations",
@@ -172,7 +270,7 @@ impl MigrationGenerator {
     fn models_to_schema(&self, models: &[&ModelMeta]) -> Schema {
         let mut schema = Schema::new();
 
-        for model in models {
+        for model in models.iter().filter(|m| !m.view) {
             let table = self.model_to_table(model);
             schema.add_table(table);
         }
@@ -182,74 +280,7 @@ impl MigrationGenerator {
 
     /// Convert a single model to a table
     fn model_to_table(&self, model: &ModelMeta) -> Table {
-        let mut table = Table::new(&model.table);
-
-        if let Some(ref schema_name) = model.schema {
-            table.schema = Some(schema_name.clone());
-        }
-
-        // Add columns
-        for field in &model.fields {
-            let column_type = ColumnType::from_field_type(&field.field_type);
-
-            let mut column = Column::new(field.column_name(), column_type);
-            column.nullable = field.nullable;
-            column.auto_increment = field.auto_increment;
-
-            if let Some(ref default) = field.default {
-                column.default = Some(self.convert_default(default));
-            }
-
-            table.add_column(column);
-        }
-
-        // Set primary key
-        if !model.primary_key.is_empty() {
-            table.primary_key = Some(PrimaryKey::new(model.primary_key.clone()));
-        }
-
-        // Add indexes
-        for index_meta in &model.indexes {
-            let index = Index::new(&index_meta.name, index_meta.columns.clone());
-            table.add_index(if index_meta.unique {
-                index.unique()
-            } else {
-                index
-            });
-        }
-
-        // Add foreign keys from field definitions
-        for field in &model.fields {
-            if let Some(ref fk) = field.foreign_key {
-                let foreign_key = ForeignKey::new(
-                    vec![field.column_name().to_string()],
-                    &fk.table,
-                    vec![fk.column.clone()],
-                )
-                .on_delete(fk.on_delete.clone())
-                .on_update(fk.on_update.clone());
-
-                table.add_foreign_key(foreign_key);
-            }
-        }
-
-        table
-    }
-
-    /// Convert a field default to a column default
-    fn convert_default(&self, default: &chakra_core::model::FieldDefault) -> ColumnDefault {
-        match default {
-            chakra_core::model::FieldDefault::Value(v) => {
-                ColumnDefault::Expression(format!("{:?}", v))
-            }
-            chakra_core::model::FieldDefault::Expression(expr) => {
-                ColumnDefault::Expression(expr.clone())
-            }
-            chakra_core::model::FieldDefault::AutoIncrement => {
-                ColumnDefault::Expression("DEFAULT".to_string())
-            }
-            chakra_core::model::FieldDefault::Uuid => ColumnDefault::GenerateUuid,
-        }
+        Table::from_model(model)
     }
 
     /// Generate a descriptive name for the migration
@@ -257,7 +288,11 @@ impl MigrationGenerator {
         let mut parts = Vec::new();
 
         if !diff.tables_to_create.is_empty() {
-            let tables: Vec<_> = diff.tables_to_create.iter().map(|t| t.name.as_str()).collect();
+            let tables: Vec<_> = diff
+                .tables_to_create
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect();
             parts.push(format!("create_{}", tables.join("_")));
         }
 
@@ -267,12 +302,12 @@ impl MigrationGenerator {
 
         for mod_diff in &diff.table_modifications {
             if !mod_diff.columns_to_add.is_empty() {
-                let cols: Vec<_> = mod_diff.columns_to_add.iter().map(|c| c.name.as_str()).collect();
-                parts.push(format!(
-                    "add_{}_to_{}",
-                    cols.join("_"),
-                    mod_diff.table_name
-                ));
+                let cols: Vec<_> = mod_diff
+                    .columns_to_add
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect();
+                parts.push(format!("add_{}_to_{}", cols.join("_"), mod_diff.table_name));
             }
 
             if !mod_diff.columns_to_drop.is_empty() {
@@ -284,6 +319,51 @@ impl MigrationGenerator {
             }
         }
 
+        let created: Vec<_> = diff
+            .views_to_create
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        let dropped: Vec<_> = diff.views_to_drop.iter().map(|v| v.name.as_str()).collect();
+        let updated: Vec<_> = created
+            .iter()
+            .filter(|name| dropped.contains(name))
+            .copied()
+            .collect();
+        if !updated.is_empty() {
+            parts.push(format!("update_view_{}", updated.join("_")));
+        }
+        let created: Vec<_> = created
+            .into_iter()
+            .filter(|name| !updated.contains(name))
+            .collect();
+        if !created.is_empty() {
+            parts.push(format!("create_view_{}", created.join("_")));
+        }
+        let dropped: Vec<_> = dropped
+            .into_iter()
+            .filter(|name| !updated.contains(name))
+            .collect();
+        if !dropped.is_empty() {
+            parts.push(format!("drop_view_{}", dropped.join("_")));
+        }
+
+        // Types are named only when nothing else changed, as tables using
+        // them usually come with them
+        if parts.is_empty() && !diff.types_to_create.is_empty() {
+            let types: Vec<_> = diff.types_to_create.iter().map(|t| t.name()).collect();
+            parts.push(format!("create_type_{}", types.join("_")));
+        }
+        if parts.is_empty() && !diff.enum_values_to_add.is_empty() {
+            let mut types: Vec<_> = diff
+                .enum_values_to_add
+                .iter()
+                .map(|v| v.type_name.as_str())
+                .collect();
+            types.dedup();
+            parts.push(format!("add_values_to_{}", types.join("_")));
+        }
+
         if parts.is_empty() {
             "schema_changes".to_string()
         } else if parts.len() == 1 {
@@ -307,10 +387,7 @@ mod tests {
                     .auto_increment()
                     .build(),
             )
-            .field(
-                chakra_core::model::FieldMeta::builder("name", FieldType::string(100))
-                    .build(),
-            )
+            .field(chakra_core::model::FieldMeta::builder("name", FieldType::string(100)).build())
             .build()
     }
 
@@ -338,4 +415,62 @@ mod tests {
         assert!(!m.operations.is_empty());
         assert_eq!(m.app, Some("core".to_string()));
     }
+
+    #[test]
+    fn test_generate_per_database() {
+        let user = create_test_model();
+        let page_view = ModelMeta::builder("PageView", "page_views")
+            .database("analytics")
+            .build();
+        let event = ModelMeta::builder("Event", "events").build();
+        let routes = Routes::new().route("Event", "analytics");
+        let current = Schema::new();
+
+        let generator = MigrationGenerator::new().routes(routes.clone());
+        let main = generator
+            .from_models(&[&user, &page_view, &event], &current)
+            .unwrap();
+        assert_eq!(main.name, "create_users");
+        assert_eq!(main.database, None);
+
+        let generator = MigrationGenerator::new()
+            .routes(routes)
+            .database("analytics");
+        let analytics = generator
+            .from_models(&[&user, &page_view, &event], &current)
+            .unwrap();
+        assert_eq!(analytics.operations.len(), 2);
+        assert_eq!(analytics.database.as_deref(), Some("analytics"));
+    }
+
+    #[test]
+    fn test_generate_views() {
+        use chakra_core::query::Query;
+        use chakra_schema::diff::MigrationOperation;
+
+        let user = create_test_model();
+        let named = ModelMeta::builder("NamedUser", "named_users")
+            .view()
+            .build();
+        let view = View::define(
+            "named_users",
+            Query::select()
+                .from("users")
+                .columns(&["id", "name"])
+                .build(),
+        );
+
+        let mut current = Schema::new();
+        current.add_table(Table::from_model(&user));
+        let generator = MigrationGenerator::new().view(view.clone());
+        let migration = generator.from_models(&[&user, &named], &current).unwrap();
+        assert_eq!(migration.name, "create_view_named_users");
+        assert!(
+            matches!(&migration.operations[..], [MigrationOperation::CreateView(v)] if v.name == "named_users")
+        );
+
+        // Views of another database are left out
+        let generator = MigrationGenerator::new().view(view.database("analytics"));
+        assert!(generator.from_models(&[&user, &named], &current).is_none());
+    }
 }
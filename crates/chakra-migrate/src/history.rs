@@ -1,6 +1,6 @@
 //! Migration history tracking
 
-use crate::migration::{MigrationDirection, MigrationStatus};
+use crate::migration::MigrationStatus;
 use async_trait::async_trait;
 use chakra_core::error::Result;
 use chrono::{DateTime, Utc};
@@ -143,7 +143,7 @@ impl MigrationHistory for InMemoryHistory {
             .filter(|r| r.status == MigrationStatus::Applied)
             .cloned()
             .collect();
-        applied.sort_by(|a, b| a.applied_at.cmp(&b.applied_at));
+        applied.sort_by_key(|a| a.applied_at);
         Ok(applied)
     }
 
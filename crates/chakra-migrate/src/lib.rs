@@ -5,6 +5,7 @@
 //! - Schema change detection
 //! - Migration execution
 //! - Rollback support
+//! - Migrating every shard of a sharded database
 //! - Django-style auto migrations
 
 pub mod executor;
@@ -13,10 +14,12 @@ pub mod generator;
 pub mod history;
 pub mod migration;
 pub mod planner;
+pub mod shard;
 
-pub use executor::MigrationExecutor;
+pub use executor::{MigrationExecutor, SpeculativeRun, SpeculativeStep};
 pub use file::{MigrationFile, MigrationLoader};
 pub use generator::MigrationGenerator;
 pub use history::{MigrationHistory, MigrationRecord};
 pub use migration::{Migration, MigrationDirection, MigrationStatus};
 pub use planner::MigrationPlanner;
+pub use shard::{ShardMigrator, ShardResults};
@@ -1,5 +1,6 @@
 //! Migration types and definitions
 
+use chakra_core::router::DEFAULT_DATABASE;
 use chakra_schema::diff::MigrationOperation;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -16,6 +17,9 @@ pub struct Migration {
     pub description: Option<String>,
     /// App/module this migration belongs to
     pub app: Option<String>,
+    /// Named database this migration runs on, if not the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
     /// Dependencies (other migration IDs)
     pub dependencies: Vec<String>,
     /// Operations in this migration
@@ -26,6 +30,9 @@ pub struct Migration {
     pub raw_sql_up: Option<String>,
     /// Custom SQL for reverse migration (if any)
     pub raw_sql_down: Option<String>,
+    /// Queries checked by `migrate test`; any returned row is a failure
+    #[serde(default)]
+    pub verify: Vec<String>,
     /// Checksum of the migration content
     pub checksum: String,
     /// When this migration was created
@@ -42,11 +49,13 @@ impl Migration {
             name: name.into(),
             description: None,
             app: None,
+            database: None,
             dependencies: Vec::new(),
             operations: Vec::new(),
             reversible: true,
             raw_sql_up: None,
             raw_sql_down: None,
+            verify: Vec::new(),
             checksum: String::new(),
             created_at: Utc::now(),
             metadata: HashMap::new(),
@@ -65,6 +74,13 @@ impl Migration {
         self
     }
 
+    /// Set the database, leaving it unset for the default one
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        let database = database.into();
+        self.database = (database != DEFAULT_DATABASE).then_some(database);
+        self
+    }
+
     /// Add a dependency
     pub fn depends_on(mut self, migration_id: impl Into<String>) -> Self {
         self.dependencies.push(migration_id.into());
@@ -93,6 +109,13 @@ impl Migration {
         self
     }
 
+    /// Add a verification query, e.g. one selecting rows that violate an
+    /// invariant the migration should establish
+    pub fn verify(mut self, sql: impl Into<String>) -> Self {
+        self.verify.push(sql.into());
+        self
+    }
+
     /// Calculate and set checksum
     pub fn with_checksum(mut self) -> Self {
         self.checksum = self.calculate_checksum();
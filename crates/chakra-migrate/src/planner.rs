@@ -5,7 +5,7 @@ use crate::history::MigrationHistory;
 use crate::migration::{Migration, MigrationDirection};
 use chakra_core::error::{ChakraError, Result};
 use std::collections::{HashMap, HashSet, VecDeque};
-use tracing::{debug, info, warn};
+use tracing::{info, warn};
 
 /// A planned migration operation
 #[derive(Debug, Clone)]
@@ -186,15 +186,12 @@ impl MigrationPlanner {
         // Build graph
         for m in migrations {
             in_degree.entry(m.id.as_str()).or_insert(0);
-            graph.entry(m.id.as_str()).or_insert_with(Vec::new);
+            graph.entry(m.id.as_str()).or_default();
 
             for dep in &m.dependencies {
                 if ids.contains(dep.as_str()) {
                     *in_degree.entry(m.id.as_str()).or_insert(0) += 1;
-                    graph
-                        .entry(dep.as_str())
-                        .or_insert_with(Vec::new)
-                        .push(m.id.as_str());
+                    graph.entry(dep.as_str()).or_default().push(m.id.as_str());
                 }
             }
         }
@@ -239,10 +236,7 @@ impl MigrationPlanner {
         for (id, deps) in &self.dependencies {
             for dep in deps {
                 if !self.migrations.contains_key(dep) {
-                    warn!(
-                        "Migration {} depends on missing migration {}",
-                        id, dep
-                    );
+                    warn!("Migration {} depends on missing migration {}", id, dep);
                 }
             }
         }
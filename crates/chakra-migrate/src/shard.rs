@@ -0,0 +1,259 @@
+//! Applying migrations to every shard of a sharded database
+//!
+//! Shards share one migration set but each keeps its own history, so a
+//! shard that missed a run is brought up to date with the rest. Shards are
+//! migrated one at a time in the order they were added, and a failure stops
+//! the run before the next shard, so at most one shard is left part-way.
+
+use crate::executor::{MigrationExecutor, SqlExecutor};
+use crate::history::MigrationHistory;
+use crate::migration::MigrationResult;
+use crate::planner::{MigrationPlanner, PlannedMigration};
+use chakra_core::error::Result;
+use chakra_schema::ddl::DdlGenerator;
+use tracing::{info, warn};
+
+/// A shard's connection and migration history
+struct Shard<'a> {
+    name: String,
+    executor: &'a dyn SqlExecutor,
+    history: &'a dyn MigrationHistory,
+}
+
+/// The migrations run on one shard
+#[derive(Debug, Clone)]
+pub struct ShardResults {
+    /// Shard name
+    pub shard: String,
+    /// Result of each migration run, stopping at the first failure
+    pub results: Vec<MigrationResult>,
+}
+
+impl ShardResults {
+    /// Whether every migration on the shard succeeded
+    pub fn success(&self) -> bool {
+        self.results.iter().all(|r| r.success)
+    }
+}
+
+/// Runs migrations on each shard of a sharded database
+pub struct ShardMigrator<'a> {
+    ddl_generator: &'a dyn DdlGenerator,
+    shards: Vec<Shard<'a>>,
+    use_transactions: bool,
+    dry_run: bool,
+}
+
+impl<'a> ShardMigrator<'a> {
+    /// Create a migrator with no shards
+    pub fn new(ddl_generator: &'a dyn DdlGenerator) -> Self {
+        Self {
+            ddl_generator,
+            shards: Vec::new(),
+            use_transactions: true,
+            dry_run: false,
+        }
+    }
+
+    /// Add a shard
+    pub fn shard(
+        mut self,
+        name: impl Into<String>,
+        executor: &'a dyn SqlExecutor,
+        history: &'a dyn MigrationHistory,
+    ) -> Self {
+        self.shards.push(Shard {
+            name: name.into(),
+            executor,
+            history,
+        });
+        self
+    }
+
+    /// Set whether to use transactions
+    pub fn use_transactions(mut self, use_tx: bool) -> Self {
+        self.use_transactions = use_tx;
+        self
+    }
+
+    /// Set dry-run mode
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Apply pending migrations, up to `target` if given, on every shard
+    pub async fn up(
+        &self,
+        planner: &MigrationPlanner,
+        target: Option<&str>,
+    ) -> Result<Vec<ShardResults>> {
+        let mut all = Vec::new();
+        for shard in &self.shards {
+            let plan = planner.plan_up(shard.history, target).await?;
+            let results = self.run(shard, &plan).await;
+            let failed = !results.success();
+            all.push(results);
+            if failed {
+                break;
+            }
+        }
+        Ok(all)
+    }
+
+    /// Roll back the last `count` migrations on every shard
+    pub async fn down(
+        &self,
+        planner: &MigrationPlanner,
+        count: usize,
+    ) -> Result<Vec<ShardResults>> {
+        let mut all = Vec::new();
+        for shard in &self.shards {
+            let plan = planner.plan_down(shard.history, count).await?;
+            let results = self.run(shard, &plan).await;
+            let failed = !results.success();
+            all.push(results);
+            if failed {
+                break;
+            }
+        }
+        Ok(all)
+    }
+
+    async fn run(&self, shard: &Shard<'_>, plan: &[PlannedMigration]) -> ShardResults {
+        info!(
+            "Migrating shard {}: {} migration(s)",
+            shard.name,
+            plan.len()
+        );
+        let results = MigrationExecutor::new(shard.executor, self.ddl_generator, shard.history)
+            .use_transactions(self.use_transactions)
+            .dry_run(self.dry_run)
+            .execute_plan(plan)
+            .await;
+        if results.len() < plan.len() || results.iter().any(|r| !r.success) {
+            warn!(
+                "Migrations failed on shard {}, skipping later shards",
+                shard.name
+            );
+        }
+        ShardResults {
+            shard: shard.name.clone(),
+            results,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{SpeculativeRun, SpeculativeStep};
+    use crate::file::MigrationFile;
+    use crate::history::{InMemoryHistory, MigrationRecord};
+    use crate::migration::Migration;
+    use async_trait::async_trait;
+    use chakra_core::error::ChakraError;
+    use chakra_schema::ddl::PostgresDdlGenerator;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    /// Records statements, failing any that mention `fail`
+    #[derive(Default)]
+    struct Recorder {
+        statements: Mutex<Vec<String>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl SqlExecutor for Recorder {
+        async fn execute(&self, sql: &str) -> Result<u64> {
+            if self.fail && sql.contains("fail") {
+                return Err(ChakraError::internal("statement failed"));
+            }
+            self.statements.lock().unwrap().push(sql.to_string());
+            Ok(0)
+        }
+
+        async fn execute_in_transaction(&self, statements: &[&str]) -> Result<Vec<u64>> {
+            let mut counts = Vec::new();
+            for sql in statements {
+                counts.push(self.execute(sql).await?);
+            }
+            Ok(counts)
+        }
+
+        async fn begin_transaction(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn commit_transaction(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn rollback_transaction(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn speculate(&self, _steps: &[SpeculativeStep]) -> Result<SpeculativeRun> {
+            Ok(SpeculativeRun::default())
+        }
+    }
+
+    fn planner() -> MigrationPlanner {
+        let file = |migration: Migration| MigrationFile {
+            path: PathBuf::from(format!("{}.json", migration.id)),
+            migration,
+        };
+        MigrationPlanner::new(vec![
+            file(Migration::new("001", "orders").raw_sql("CREATE TABLE orders (id BIGINT)", None)),
+            file(
+                Migration::new("002", "orders_index")
+                    .depends_on("001")
+                    .raw_sql("CREATE INDEX fail_idx ON orders (id)", None),
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_up_applies_each_shard() {
+        let (s0, s1) = (Recorder::default(), Recorder::default());
+        let (h0, h1) = (InMemoryHistory::new(), InMemoryHistory::new());
+        // s1 already has the first migration
+        h1.record_applied(MigrationRecord::new("001", "001").applied(0, 1))
+            .await
+            .unwrap();
+
+        let migrator = ShardMigrator::new(&PostgresDdlGenerator)
+            .shard("s0", &s0, &h0)
+            .shard("s1", &s1, &h1);
+        let results = migrator.up(&planner(), None).await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(ShardResults::success));
+        assert_eq!(results[0].results.len(), 2);
+        assert_eq!(results[1].results.len(), 1);
+        assert_eq!(s1.statements.lock().unwrap().len(), 1);
+        assert!(h1.is_applied("002").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failure_stops_later_shards() {
+        let s0 = Recorder {
+            fail: true,
+            ..Default::default()
+        };
+        let s1 = Recorder::default();
+        let (h0, h1) = (InMemoryHistory::new(), InMemoryHistory::new());
+
+        let migrator = ShardMigrator::new(&PostgresDdlGenerator)
+            .shard("s0", &s0, &h0)
+            .shard("s1", &s1, &h1);
+        let results = migrator.up(&planner(), None).await.unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].success());
+        assert!(s1.statements.lock().unwrap().is_empty());
+        assert!(h0.is_applied("001").await.unwrap());
+        assert!(!h0.is_applied("002").await.unwrap());
+    }
+}
@@ -1,6 +1,7 @@
 //! MySQL configuration
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 /// MySQL connection configuration
@@ -16,8 +17,29 @@ pub struct MySqlConfig {
     pub user: String,
     /// Password
     pub password: Option<String>,
+    /// Path of the server's Unix socket, used instead of TCP
+    #[serde(default)]
+    pub socket: Option<String>,
+    /// Extra driver options added to the connection URL, e.g.
+    /// `stmt_cache_size` or `compression`
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
     /// Connection timeout
     pub connect_timeout: Duration,
+    /// Program name, exposed as the `@program_name` user variable
+    ///
+    /// mysql_async can't send connection attributes, so the name does not
+    /// appear in `SHOW PROCESSLIST` or
+    /// `performance_schema.session_connect_attrs`. Read it from
+    /// `performance_schema.user_variables_by_thread` instead, joined to
+    /// `performance_schema.threads` for the processlist id.
+    pub program_name: Option<String>,
+    /// Clean sessions with `COM_RESET_CONNECTION` when they return to the pool
+    pub reset_connection: bool,
+    /// Make sessions read-only and reject writing queries before they are
+    /// sent, e.g. for analytics credentials
+    #[serde(default)]
+    pub read_only: bool,
     /// Pool configuration
     pub pool_min: usize,
     pub pool_max: usize,
@@ -32,15 +54,26 @@ impl MySqlConfig {
             database: database.into(),
             user: "root".to_string(),
             password: None,
+            socket: None,
+            options: BTreeMap::new(),
             connect_timeout: Duration::from_secs(30),
+            program_name: Some("chakra-orm".to_string()),
+            reset_connection: true,
+            read_only: false,
             pool_min: 1,
             pool_max: 10,
         }
     }
 
     /// Parse from a connection URL
+    ///
+    /// A `socket` parameter connects through a Unix socket, e.g.
+    /// `mysql://app@localhost/db?socket=/var/run/mysqld/mysqld.sock`.
+    /// Parameters other than `socket` and `program_name` become driver
+    /// options.
     pub fn from_url(url: &str) -> Result<Self, ConfigError> {
-        let url = url.strip_prefix("mysql://")
+        let url = url
+            .strip_prefix("mysql://")
             .ok_or_else(|| ConfigError::InvalidUrl("URL must start with mysql://".into()))?;
 
         let (auth, rest) = if url.contains('@') {
@@ -75,20 +108,41 @@ impl MySqlConfig {
             ("root".to_string(), None)
         };
 
-        let database = database
-            .map(|d| d.split('?').next().unwrap_or(d).to_string())
-            .unwrap_or_else(|| "mysql".to_string());
+        let (database, query) = match database {
+            Some(d) => match d.split_once('?') {
+                Some((d, q)) => (d.to_string(), Some(q)),
+                None => (d.to_string(), None),
+            },
+            None => ("mysql".to_string(), None),
+        };
 
-        Ok(Self {
-            host,
-            port,
+        let mut config = Self::new(
+            if host.is_empty() {
+                "localhost".to_string()
+            } else {
+                host
+            },
             database,
-            user,
-            password,
-            connect_timeout: Duration::from_secs(30),
-            pool_min: 1,
-            pool_max: 10,
-        })
+        );
+        config.port = port;
+        config.user = user;
+        config.password = password;
+        for pair in query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .filter(|p| !p.is_empty())
+        {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match key {
+                "socket" => config.socket = Some(value),
+                "program_name" => config.program_name = Some(value),
+                _ => {
+                    config.options.insert(percent_decode(key), value);
+                }
+            }
+        }
+        Ok(config)
     }
 
     /// Set port
@@ -97,6 +151,18 @@ impl MySqlConfig {
         self
     }
 
+    /// Connect through the Unix socket at `path` instead of TCP
+    pub fn socket(mut self, path: impl Into<String>) -> Self {
+        self.socket = Some(path.into());
+        self
+    }
+
+    /// Add a driver option to the connection URL
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.insert(key.into(), value.into());
+        self
+    }
+
     /// Set user
     pub fn user(mut self, user: impl Into<String>) -> Self {
         self.user = user.into();
@@ -109,6 +175,28 @@ impl MySqlConfig {
         self
     }
 
+    /// Set the program name used to identify connections, see
+    /// `MySqlConfig::program_name` for where it shows up
+    pub fn program_name(mut self, name: impl Into<String>) -> Self {
+        self.program_name = Some(name.into());
+        self
+    }
+
+    /// Set whether sessions are reset when they return to the pool
+    ///
+    /// Disabling this keeps temp tables, user variables and prepared
+    /// statements across checkouts.
+    pub fn reset_connection(mut self, reset: bool) -> Self {
+        self.reset_connection = reset;
+        self
+    }
+
+    /// Set read-only mode
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
     /// Set pool size
     pub fn pool_size(mut self, min: usize, max: usize) -> Self {
         self.pool_min = min;
@@ -119,18 +207,82 @@ impl MySqlConfig {
     /// Build connection URL for mysql_async
     pub fn connection_url(&self) -> String {
         let auth = if let Some(ref password) = self.password {
-            format!("{}:{}@", self.user, password)
+            format!(
+                "{}:{}@",
+                encode_segment(&self.user),
+                encode_segment(password)
+            )
         } else {
-            format!("{}@", self.user)
+            format!("{}@", encode_segment(&self.user))
         };
 
-        format!(
+        let mut url = format!(
             "mysql://{}{}:{}/{}",
-            auth, self.host, self.port, self.database
-        )
+            auth,
+            self.host,
+            self.port,
+            encode_segment(&self.database)
+        );
+
+        let params: Vec<String> = self
+            .socket
+            .iter()
+            .map(|socket| format!("socket={}", percent_encode(socket)))
+            .chain(
+                self.options
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v))),
+            )
+            .collect();
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
+        }
+        url
     }
 }
 
+/// Encode a URL query component
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Percent-encode a user, password or database name, where `/` would end
+/// the part
+fn encode_segment(s: &str) -> String {
+    percent_encode(s).replace('/', "%2F")
+}
+
+/// Decode `%XX` escapes in a URL component
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
 impl Default for MySqlConfig {
     fn default() -> Self {
         Self::new("localhost", "mysql")
@@ -157,4 +309,58 @@ mod tests {
         assert_eq!(config.user, "user");
         assert_eq!(config.password, Some("pass".to_string()));
     }
+
+    #[test]
+    fn test_connection_url_encodes_credentials() {
+        let config = MySqlConfig::new("localhost", "my/db")
+            .user("o'neil")
+            .password("p@ss:w/rd?#");
+        let opts = mysql_async::Opts::from_url(&config.connection_url()).unwrap();
+        assert_eq!(opts.user(), Some("o'neil"));
+        assert_eq!(opts.pass(), Some("p@ss:w/rd?#"));
+        assert_eq!(opts.db_name(), Some("my/db"));
+        assert_eq!(opts.ip_or_hostname(), "localhost");
+    }
+
+    #[test]
+    fn test_unix_socket_and_options() {
+        let config = MySqlConfig::from_url(
+            "mysql://app@localhost/mydb?socket=/var/run/mysqld/mysqld.sock&stmt_cache_size=64",
+        )
+        .unwrap();
+        assert_eq!(
+            config.socket.as_deref(),
+            Some("/var/run/mysqld/mysqld.sock")
+        );
+        assert_eq!(config.options["stmt_cache_size"], "64");
+        assert_eq!(config.program_name.as_deref(), Some("chakra-orm"));
+
+        let url = config.connection_url();
+        assert_eq!(
+            url,
+            "mysql://app@localhost:3306/mydb?socket=/var/run/mysqld/mysqld.sock&stmt_cache_size=64"
+        );
+        let opts = mysql_async::Opts::from_url(&url).unwrap();
+        assert_eq!(opts.socket(), Some("/var/run/mysqld/mysqld.sock"));
+        assert_eq!(opts.stmt_cache_size(), 64);
+
+        let config = MySqlConfig::new("localhost", "mydb")
+            .socket("/tmp/my sql.sock")
+            .option("compression", "fast");
+        let opts = mysql_async::Opts::from_url(&config.connection_url()).unwrap();
+        assert_eq!(opts.socket(), Some("/tmp/my sql.sock"));
+        assert!(opts.compression().is_some());
+    }
+
+    #[test]
+    fn test_program_name() {
+        let config =
+            MySqlConfig::from_url("mysql://localhost/mydb?program_name=billing-api").unwrap();
+        assert_eq!(config.database, "mydb");
+        assert_eq!(config.program_name.as_deref(), Some("billing-api"));
+        assert_eq!(
+            MySqlConfig::default().program_name.as_deref(),
+            Some("chakra-orm")
+        );
+    }
 }
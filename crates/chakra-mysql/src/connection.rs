@@ -2,52 +2,89 @@
 
 use crate::config::MySqlConfig;
 use chakra_core::error::{ChakraError, ConnectionError, Result};
+use chakra_core::sql::{Dialect, MySqlDialect};
+use chakra_core::types::Value;
+use chakra_pool::credentials::{CredentialProvider, Credentials};
 use mysql_async::{prelude::*, Pool, PoolConstraints, PoolOpts};
-use std::sync::Arc;
-use tracing::{debug, info};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
 
 /// A MySQL connection pool
 pub struct MySqlPool {
-    pool: Pool,
+    pool: RwLock<Pool>,
     config: MySqlConfig,
+    credentials: Option<Arc<dyn CredentialProvider>>,
 }
 
 impl MySqlPool {
     /// Create a new connection pool
     pub async fn new(config: MySqlConfig) -> Result<Self> {
-        let pool_opts = PoolOpts::default()
-            .with_constraints(
-                PoolConstraints::new(config.pool_min, config.pool_max).unwrap()
-            );
-
-        let pool = Pool::new(
-            mysql_async::OptsBuilder::from_opts(
-                mysql_async::Opts::from_url(&config.connection_url())
-                    .map_err(|e| ChakraError::Connection(ConnectionError::Configuration {
-                        message: e.to_string(),
-                    }))?
-            ).pool_opts(pool_opts)
-        );
-
+        let pool = open(&config, None)?;
         info!("MySQL connection pool created");
+        Ok(Self {
+            pool: RwLock::new(pool),
+            config,
+            credentials: None,
+        })
+    }
 
-        Ok(Self { pool, config })
+    /// Create a pool whose connections log in with the user and password
+    /// from `provider`, so they need not be in the config
+    ///
+    /// mysql_async opens connections from fixed options, so the credentials
+    /// are fetched now and again, into a fresh pool, when the server rejects
+    /// them. Connections already open keep their sessions.
+    pub async fn with_credentials(
+        config: MySqlConfig,
+        provider: Arc<dyn CredentialProvider>,
+    ) -> Result<Self> {
+        let pool = open(&config, Some(provider.credentials().await?))?;
+        info!("MySQL connection pool created");
+        Ok(Self {
+            pool: RwLock::new(pool),
+            config,
+            credentials: Some(provider),
+        })
     }
 
     /// Get a connection from the pool
     pub async fn get(&self) -> Result<MySqlConnection> {
-        let conn = self.pool.get_conn().await.map_err(|e| {
-            ChakraError::Connection(ConnectionError::ConnectionFailed {
-                message: e.to_string(),
-            })
-        })?;
+        let pool = self.pool.read().unwrap().clone();
+        let conn = match (pool.get_conn().await, &self.credentials) {
+            (Err(e), Some(provider)) if is_access_denied(&e) => {
+                warn!(
+                    "Authentication failed, retrying with fresh credentials: {}",
+                    e
+                );
+                provider.invalidate();
+                let pool = open(&self.config, Some(provider.credentials().await?))?;
+                *self.pool.write().unwrap() = pool.clone();
+                pool.get_conn().await
+            }
+            (result, _) => result,
+        };
+        let conn = conn.map_err(connect_error)?;
 
         Ok(MySqlConnection { conn })
     }
 
+    /// Get a connection reporting a different program name
+    ///
+    /// The override lasts until the connection is returned, when the
+    /// session reset restores the configured name.
+    pub async fn get_with_program_name(&self, name: &str) -> Result<MySqlConnection> {
+        let mut conn = self.get().await?;
+        conn.conn
+            .exec_drop("SET @program_name = ?", (name,))
+            .await
+            .map_err(|e| ChakraError::internal(e.to_string()))?;
+        Ok(conn)
+    }
+
     /// Disconnect the pool
     pub async fn disconnect(self) -> Result<()> {
-        self.pool.disconnect().await.map_err(|e| {
+        let pool = self.pool.into_inner().unwrap();
+        pool.disconnect().await.map_err(|e| {
             ChakraError::Connection(ConnectionError::ConnectionFailed {
                 message: e.to_string(),
             })
@@ -61,6 +98,43 @@ impl MySqlPool {
     }
 }
 
+/// A mysql_async pool for `config`, logging in with `credentials` if given
+fn open(config: &MySqlConfig, credentials: Option<Credentials>) -> Result<Pool> {
+    let pool_opts = PoolOpts::default()
+        .with_constraints(PoolConstraints::new(config.pool_min, config.pool_max).unwrap())
+        .with_reset_connection(config.reset_connection);
+
+    // mysql_async does not send connection attributes, so the program
+    // name is exposed as a session variable, re-applied after each reset
+    // along with read-only mode. Setup statements can't take parameters,
+    // so the name is inlined as an escaped literal
+    let mut setup: Vec<String> = config
+        .program_name
+        .iter()
+        .map(|name| set_program_name_sql(name))
+        .collect();
+    if config.read_only {
+        setup.push(READ_ONLY_SQL.to_string());
+    }
+
+    let mut opts = mysql_async::OptsBuilder::from_opts(
+        mysql_async::Opts::from_url(&config.connection_url()).map_err(|e| {
+            ChakraError::Connection(ConnectionError::Configuration {
+                message: e.to_string(),
+            })
+        })?,
+    )
+    .pool_opts(pool_opts)
+    .setup(setup);
+    if let Some(credentials) = credentials {
+        if let Some(username) = credentials.username {
+            opts = opts.user(Some(username));
+        }
+        opts = opts.pass(Some(credentials.password));
+    }
+    Ok(Pool::new(opts))
+}
+
 /// A MySQL connection
 pub struct MySqlConnection {
     conn: mysql_async::Conn,
@@ -96,6 +170,35 @@ impl MySqlConnection {
     }
 }
 
+/// `ER_ACCESS_DENIED_ERROR`
+const ACCESS_DENIED: u16 = 1045;
+
+/// Whether the server rejected the user or password
+fn is_access_denied(e: &mysql_async::Error) -> bool {
+    matches!(e, mysql_async::Error::Server(server) if server.code == ACCESS_DENIED)
+}
+
+/// Convert a failed checkout into a `ChakraError`, keeping rejected
+/// credentials apart
+fn connect_error(e: mysql_async::Error) -> ChakraError {
+    let message = e.to_string();
+    if is_access_denied(&e) {
+        ChakraError::Connection(ConnectionError::AuthenticationFailed { message })
+    } else {
+        ChakraError::Connection(ConnectionError::ConnectionFailed { message })
+    }
+}
+
+/// Makes every later transaction in the session read-only
+const READ_ONLY_SQL: &str = "SET SESSION TRANSACTION READ ONLY";
+
+fn set_program_name_sql(name: &str) -> String {
+    format!(
+        "SET @program_name = {}",
+        MySqlDialect.literal(&Value::String(name.to_string()))
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -106,4 +209,29 @@ mod tests {
         assert_eq!(config.host, "localhost");
         assert_eq!(config.database, "test_db");
     }
+
+    #[test]
+    fn test_set_program_name_sql() {
+        assert_eq!(
+            set_program_name_sql("worker-3's"),
+            "SET @program_name = 'worker-3''s'"
+        );
+        assert_eq!(
+            set_program_name_sql("a\\'; DROP TABLE t; -- \\"),
+            "SET @program_name = 'a\\\\''; DROP TABLE t; -- \\\\'"
+        );
+    }
+
+    #[test]
+    fn test_connect_error() {
+        let server = |code| {
+            mysql_async::Error::Server(mysql_async::ServerError {
+                code,
+                message: "denied".to_string(),
+                state: "28000".to_string(),
+            })
+        };
+        assert!(connect_error(server(ACCESS_DENIED)).is_authentication_failure());
+        assert!(!connect_error(server(1040)).is_authentication_failure());
+    }
 }
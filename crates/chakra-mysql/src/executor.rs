@@ -1,19 +1,36 @@
 //! MySQL query executor
 
-use crate::connection::MySqlPool;
+use crate::connection::{MySqlConnection, MySqlPool};
 use crate::types::to_mysql_value;
+use async_trait::async_trait;
 use chakra_core::error::{ChakraError, QueryError, Result};
+use chakra_core::executor::{buffered_stream, ExecResult, Executor};
+use chakra_core::expr::Expr;
+use chakra_core::format::insert_values_row;
+use chakra_core::query::{Query, QueryType};
 use chakra_core::result::Row;
-use chakra_core::sql::{MySqlDialect, SqlFragment};
+use chakra_core::script::ScriptSplitter;
+use chakra_core::sql::{Dialect, MySqlDialect, SqlFragment};
+use chakra_core::sql_cache::SqlCache;
+use chakra_core::transaction::{Transaction as ChakraTransaction, TransactionOptions};
 use chakra_core::types::Value;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
 use mysql_async::prelude::*;
+use mysql_async::{Conn, Transaction, TxOpts};
 use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
 use tracing::{debug, error};
 
+/// Rows read ahead of a stream's consumer
+const STREAM_BUFFER: usize = 256;
+
 /// MySQL query executor
 pub struct MySqlExecutor {
     pool: Arc<MySqlPool>,
     dialect: MySqlDialect,
+    max_rows_guard: Option<usize>,
+    sql_cache: Option<SqlCache>,
 }
 
 impl MySqlExecutor {
@@ -22,9 +39,23 @@ impl MySqlExecutor {
         Self {
             pool,
             dialect: MySqlDialect,
+            max_rows_guard: None,
+            sql_cache: None,
         }
     }
 
+    /// Limit unbounded SELECTs run through `fetch_all` to `max_rows`
+    pub fn with_max_rows_guard(mut self, max_rows: usize) -> Self {
+        self.max_rows_guard = Some(max_rows);
+        self
+    }
+
+    /// Reuse generated SQL for up to `capacity` query shapes
+    pub fn with_sql_cache(mut self, capacity: usize) -> Self {
+        self.sql_cache = Some(SqlCache::new(capacity));
+        self
+    }
+
     /// Get the dialect
     pub fn dialect(&self) -> &MySqlDialect {
         &self.dialect
@@ -33,55 +64,628 @@ impl MySqlExecutor {
     /// Execute a query and return rows
     pub async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
         let mut conn = self.pool.get().await?;
+        query_on(conn.inner(), sql, params).await
+    }
 
-        debug!("Executing query: {} with {} params", sql, params.len());
+    /// Stream the rows of a query, reading them off the connection as they
+    /// are consumed
+    ///
+    /// The connection is held until the stream ends or is dropped.
+    pub fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'static, Result<Row>> {
+        let pool = Arc::clone(&self.pool);
+        let start = async move {
+            let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+            let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+            tokio::spawn(async move {
+                let rows = async {
+                    let mut conn = pool.get().await?;
+                    debug!(
+                        "Streaming query: {} with {} params",
+                        sql,
+                        mysql_params.len()
+                    );
+                    let mut result = conn
+                        .inner()
+                        .exec_iter(sql.as_str(), mysql_params)
+                        .await
+                        .map_err(query_error)?;
+                    while let Some(row) = result.next().await.map_err(query_error)? {
+                        // The receiver is gone once the stream is dropped
+                        if tx.send(Ok(mysql_row_to_chakra(row))).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok::<_, ChakraError>(())
+                };
+                if let Err(e) = rows.await {
+                    error!("Query failed: {}", e);
+                    let _ = tx.send(Err(e)).await;
+                }
+            });
+            stream::unfold(
+                rx,
+                |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+            )
+        };
+        stream::once(start).flatten().boxed()
+    }
 
-        let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+    /// Execute a query with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
+    pub async fn query_fragment(&self, fragment: &SqlFragment) -> Result<Vec<Row>> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.query(&fragment.sql, &fragment.params).await;
+        }
+        self.query(&fragment.sql, &fragment.params).await
+    }
 
-        let result: Vec<mysql_async::Row> = conn
+    /// Execute a statement and return affected row count
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        let mut conn = self.pool.get().await?;
+        execute_on(conn.inner(), sql, params).await
+    }
+
+    /// Execute a statement and return affected row count and the
+    /// `LAST_INSERT_ID()` it set, which is the first row's for a multi-row
+    /// INSERT
+    pub async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        let mut conn = self.pool.get().await?;
+        execute_with_id_on(conn.inner(), sql, params).await
+    }
+
+    /// Execute a statement once for each parameter set, in one transaction,
+    /// and return the total affected row count
+    ///
+    /// A single-row INSERT is sent as multi-row INSERTs of as many rows as
+    /// the parameter limit allows. Other statements are prepared once and
+    /// each execution sends only its parameters.
+    pub async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        if params.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn
             .inner()
-            .exec(sql, mysql_params)
+            .start_transaction(TxOpts::default())
             .await
-            .map_err(|e| {
-                error!("Query failed: {}", e);
+            .map_err(query_error)?;
+        let affected = execute_many_on(&mut tx, sql, params).await?;
+        tx.commit().await.map_err(query_error)?;
+        Ok(affected)
+    }
+
+    /// Execute a statement with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
+    pub async fn execute_fragment(&self, fragment: &SqlFragment) -> Result<u64> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.execute(&fragment.sql, &fragment.params).await;
+        }
+        self.execute(&fragment.sql, &fragment.params).await
+    }
+
+    /// Execute a multi-statement SQL script, returning the number of statements run
+    ///
+    /// Understands `DELIMITER` so stored procedure and trigger definitions can
+    /// be loaded from the same file as plain statements.
+    pub async fn execute_script(&self, script: &str) -> Result<usize> {
+        let mut conn = self.pool.get().await?;
+        let statements = ScriptSplitter::mysql().split(script);
+
+        for sql in &statements {
+            debug!("Executing script statement: {}", sql);
+            conn.inner().query_drop(sql.as_str()).await.map_err(|e| {
+                error!("Statement failed: {}", e);
                 ChakraError::Query(QueryError::ExecutionFailed {
                     message: e.to_string(),
                 })
             })?;
+        }
 
-        Ok(result.into_iter().map(mysql_row_to_chakra).collect())
+        Ok(statements.len())
     }
 
-    /// Execute a query with a SqlFragment
-    pub async fn query_fragment(&self, fragment: &SqlFragment) -> Result<Vec<Row>> {
-        self.query(&fragment.sql, &fragment.params).await
-    }
+    /// Run an INSERT, UPDATE or DELETE and return its `returning` columns
+    ///
+    /// MySQL has no RETURNING, so inside one transaction the affected rows
+    /// are located by primary key and selected: after an INSERT from the
+    /// explicit key values or `LAST_INSERT_ID()`, after an UPDATE from keys
+    /// locked beforehand, and before a DELETE.
+    pub async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        self.dialect.validate(query)?;
+        self.check_read_only(query)?;
+        if query.returning.is_empty() {
+            self.execute_fragment(&Executor::generate(self, query))
+                .await?;
+            return Ok(Vec::new());
+        }
 
-    /// Execute a statement and return affected row count
-    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
         let mut conn = self.pool.get().await?;
+        let mut tx = conn
+            .inner()
+            .start_transaction(TxOpts::default())
+            .await
+            .map_err(query_error)?;
+        let rows = self.returning_on(&mut tx, query).await?;
+        tx.commit().await.map_err(query_error)?;
+        Ok(rows)
+    }
 
-        debug!("Executing statement: {} with {} params", sql, params.len());
+    /// Run a statement and select its `returning` columns on `tx`, which
+    /// must already be in a transaction
+    async fn returning_on<S: Session>(&self, tx: &mut S, query: &Query) -> Result<Vec<Row>> {
+        let statement = Executor::generate(self, query);
+        if query.returning.is_empty() {
+            tx_execute(tx, &statement).await?;
+            return Ok(Vec::new());
+        }
+        if !query.update_from.is_empty() {
+            return Err(invalid(
+                "RETURNING can't be emulated for multi-table UPDATE",
+            ));
+        }
+        let keys = primary_key(tx, &query.table).await?;
 
-        let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+        // The rows a statement will touch, locked until commit
+        let mut locate = Query::select().from(&query.table).for_update().build();
+        locate.where_clause = query.where_clause.clone();
+        locate.order_by = query.order_by.clone();
+        locate.limit = query.limit;
+
+        match query.query_type {
+            QueryType::Insert => {
+                tx_execute(tx, &statement).await?;
+                if let Some(inserted) = explicit_keys(query, &keys) {
+                    return self.select_by_keys(tx, query, &keys, &inserted).await;
+                }
+                let (last_insert_id, affected) = (tx.last_insert_id(), tx.affected_rows());
+                let increment = auto_increment_increment(tx).await?;
+                let inserted = generated_keys(query, &keys, last_insert_id, affected, increment)?;
+                let rows = self.select_by_keys(tx, query, &keys, &inserted).await?;
+                if rows.len() != inserted.len() {
+                    return Err(invalid(format!(
+                        "RETURNING on MySQL found {} of the {} rows inserted into `{}`",
+                        rows.len(),
+                        inserted.len(),
+                        query.table
+                    )));
+                }
+                Ok(rows)
+            }
+            QueryType::Update => {
+                if let Some((key, _)) = query.set_exprs.iter().find(|(c, _)| keys.contains(c)) {
+                    return Err(invalid(format!(
+                        "RETURNING on MySQL can't follow `{}` set by an expression; set it to a value",
+                        key
+                    )));
+                }
+                locate.columns = keys.clone();
+                let located: Vec<Vec<Value>> = tx_query(tx, &self.dialect.generate(&locate))
+                    .await?
+                    .iter()
+                    .map(|row| updated_keys(query, &keys, row_keys(row, &keys)))
+                    .collect();
+                tx_execute(tx, &statement).await?;
+                self.select_by_keys(tx, query, &keys, &located).await
+            }
+            QueryType::Delete => {
+                locate.columns = query.returning.clone();
+                let deleted = tx_query(tx, &self.dialect.generate(&locate)).await?;
+                tx_execute(tx, &statement).await?;
+                Ok(deleted)
+            }
+            _ => Err(invalid("RETURNING needs an INSERT, UPDATE or DELETE")),
+        }
+    }
+
+    /// Run `f` in a transaction on one connection, committing if it returns
+    /// `Ok` and rolling back otherwise
+    ///
+    /// Deadlocks are retried as `options` say; see
+    /// `chakra_core::transaction::transaction`. Streams inside the closure
+    /// are read whole before they yield.
+    pub async fn transaction<T, F>(&self, options: &TransactionOptions, f: F) -> Result<T>
+    where
+        F: for<'c, 't> FnMut(&'t ChakraTransaction<'c>) -> BoxFuture<'t, Result<T>>,
+    {
+        let conn = PinnedConnection {
+            executor: self,
+            conn: Mutex::new(self.pool.get().await?),
+        };
+        chakra_core::transaction::transaction(&conn, options, f).await
+    }
+
+    /// Select the `returning` columns of the rows with the given keys
+    async fn select_by_keys<S: Session>(
+        &self,
+        tx: &mut S,
+        query: &Query,
+        keys: &[String],
+        rows: &[Vec<Value>],
+    ) -> Result<Vec<Row>> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut select = Query::select().from(&query.table).build();
+        select.columns = query.returning.clone();
+        select.where_clause = Some(key_filter(keys, rows));
+        tx_query(tx, &self.dialect.generate(&select)).await
+    }
+}
+
+#[async_trait]
+impl Executor for MySqlExecutor {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        MySqlExecutor::query(self, sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        MySqlExecutor::execute(self, sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        MySqlExecutor::execute_with_id(self, sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        MySqlExecutor::execute_many(self, sql, params).await
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        MySqlExecutor::execute_returning(self, query).await
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.pool.config().read_only
+    }
+
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        Ok(Some(Box::new(PinnedConnection {
+            executor: self,
+            conn: Mutex::new(self.pool.get().await?),
+        })))
+    }
+}
+
+/// The connection a `transaction` closure, or a transaction begun on the
+/// executor, runs on
+struct PinnedConnection<'a> {
+    executor: &'a MySqlExecutor,
+    conn: Mutex<MySqlConnection>,
+}
+
+#[async_trait]
+impl Executor for PinnedConnection<'_> {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.executor.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        query_on(self.conn.lock().await.inner(), sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        execute_on(self.conn.lock().await.inner(), sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        execute_with_id_on(self.conn.lock().await.inner(), sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        execute_many_on(self.conn.lock().await.inner(), sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        buffered_stream(self, sql, params)
+    }
+
+    async fn execute_returning(&self, query: &Query) -> Result<Vec<Row>> {
+        self.dialect().validate(query)?;
+        self.check_read_only(query)?;
+        let mut conn = self.conn.lock().await;
+        self.executor.returning_on(conn.inner(), query).await
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.executor.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.executor.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.executor.pool.config().read_only
+    }
+}
+
+/// A connection, or a transaction on one, that reports what its last
+/// statement did
+trait Session: Queryable {
+    fn last_insert_id(&self) -> Option<u64>;
+    fn affected_rows(&self) -> u64;
+}
+
+impl Session for Conn {
+    fn last_insert_id(&self) -> Option<u64> {
+        Conn::last_insert_id(self)
+    }
+
+    fn affected_rows(&self) -> u64 {
+        Conn::affected_rows(self)
+    }
+}
+
+impl Session for Transaction<'_> {
+    fn last_insert_id(&self) -> Option<u64> {
+        Conn::last_insert_id(self)
+    }
+
+    fn affected_rows(&self) -> u64 {
+        Conn::affected_rows(self)
+    }
+}
+
+/// Execute a query on `conn` and return rows
+async fn query_on(conn: &mut Conn, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+    debug!("Executing query: {} with {} params", sql, params.len());
+
+    let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+
+    let result: Vec<mysql_async::Row> = conn.exec(sql, mysql_params).await.map_err(|e| {
+        error!("Query failed: {}", e);
+        query_error(e)
+    })?;
+
+    Ok(result.into_iter().map(mysql_row_to_chakra).collect())
+}
+
+/// Execute a statement on `conn` and return affected row count
+async fn execute_on(conn: &mut Conn, sql: &str, params: &[Value]) -> Result<u64> {
+    debug!("Executing statement: {} with {} params", sql, params.len());
+
+    let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
 
-        conn.inner()
-            .exec_drop(sql, mysql_params)
+    conn.exec_drop(sql, mysql_params).await.map_err(|e| {
+        error!("Statement failed: {}", e);
+        query_error(e)
+    })?;
+
+    Ok(conn.affected_rows())
+}
+
+/// Execute a statement on `conn` and return affected row count and the
+/// AUTO_INCREMENT key it generated, if any
+async fn execute_with_id_on(conn: &mut Conn, sql: &str, params: &[Value]) -> Result<ExecResult> {
+    let rows_affected = execute_on(conn, sql, params).await?;
+    Ok(ExecResult {
+        rows_affected,
+        last_insert_id: conn
+            .last_insert_id()
+            .filter(|&id| id != 0)
+            .map(|id| Value::Int64(id as i64)),
+    })
+}
+
+/// Execute a statement once for each parameter set on `session` and return
+/// the total affected row count
+async fn execute_many_on<S: Session>(
+    session: &mut S,
+    sql: &str,
+    params: &[Vec<Value>],
+) -> Result<u64> {
+    if let Some(batches) = insert_batches(sql, params, MySqlDialect.max_params()) {
+        debug!(
+            "Inserting {} rows in {} statements: {}",
+            params.len(),
+            batches.len(),
+            sql
+        );
+        let mut affected = 0;
+        for (sql, params) in batches {
+            let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+            session
+                .exec_drop(sql.as_str(), mysql_params)
+                .await
+                .map_err(|e| {
+                    error!("Statement failed: {}", e);
+                    query_error(e)
+                })?;
+            affected += session.affected_rows();
+        }
+        return Ok(affected);
+    }
+
+    debug!("Executing statement {} times: {}", params.len(), sql);
+
+    let statement = session.prep(sql).await.map_err(query_error)?;
+    let mut affected = 0;
+    for params in params {
+        let mysql_params: Vec<mysql_async::Value> = params.iter().map(to_mysql_value).collect();
+        session
+            .exec_drop(&statement, mysql_params)
             .await
             .map_err(|e| {
                 error!("Statement failed: {}", e);
-                ChakraError::Query(QueryError::ExecutionFailed {
-                    message: e.to_string(),
-                })
+                query_error(e)
             })?;
+        affected += session.affected_rows();
+    }
+
+    Ok(affected)
+}
 
-        Ok(conn.inner().affected_rows())
+/// A single-row INSERT repeated as multi-row INSERTs over the parameter
+/// sets, each binding at most `max_params` values
+///
+/// `None` if `sql` isn't such an INSERT or the sets differ in length.
+fn insert_batches(
+    sql: &str,
+    params: &[Vec<Value>],
+    max_params: usize,
+) -> Option<Vec<(String, Vec<Value>)>> {
+    let width = params.first()?.len();
+    if width == 0 || params.iter().any(|p| p.len() != width) {
+        return None;
     }
+    let row = insert_values_row(sql)?;
+    let rows_per_statement = (max_params / width).max(1);
+    let batches = params
+        .chunks(rows_per_statement)
+        .map(|chunk| {
+            let mut batch = sql[..row.end].to_string();
+            for _ in 1..chunk.len() {
+                batch.push_str(", ");
+                batch.push_str(&sql[row.clone()]);
+            }
+            batch.push_str(&sql[row.end..]);
+            (batch, chunk.concat())
+        })
+        .collect();
+    Some(batches)
+}
 
-    /// Execute a statement with a SqlFragment
-    pub async fn execute_fragment(&self, fragment: &SqlFragment) -> Result<u64> {
-        self.execute(&fragment.sql, &fragment.params).await
+/// Run a statement inside a transaction
+async fn tx_execute<S: Session>(tx: &mut S, fragment: &SqlFragment) -> Result<()> {
+    debug!("Executing statement: {}", fragment.sql);
+    let params: Vec<mysql_async::Value> = fragment.params.iter().map(to_mysql_value).collect();
+    tx.exec_drop(fragment.sql.as_str(), params)
+        .await
+        .map_err(query_error)
+}
+
+/// Run a query inside a transaction
+async fn tx_query<S: Session>(tx: &mut S, fragment: &SqlFragment) -> Result<Vec<Row>> {
+    debug!("Executing query: {}", fragment.sql);
+    let params: Vec<mysql_async::Value> = fragment.params.iter().map(to_mysql_value).collect();
+    let rows: Vec<mysql_async::Row> = tx
+        .exec(fragment.sql.as_str(), params)
+        .await
+        .map_err(query_error)?;
+    Ok(rows.into_iter().map(mysql_row_to_chakra).collect())
+}
+
+/// Primary key columns of a table, in key order
+async fn primary_key<S: Session>(tx: &mut S, table: &str) -> Result<Vec<String>> {
+    let keys: Vec<String> = tx
+        .exec(
+            "SELECT COLUMN_NAME FROM information_schema.KEY_COLUMN_USAGE
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND CONSTRAINT_NAME = 'PRIMARY'
+             ORDER BY ORDINAL_POSITION",
+            (table,),
+        )
+        .await
+        .map_err(query_error)?;
+    if keys.is_empty() {
+        return Err(invalid(format!(
+            "RETURNING on MySQL needs a primary key on `{}`",
+            table
+        )));
+    }
+    Ok(keys)
+}
+
+/// `auto_increment_increment` of the session: the step between ids one
+/// INSERT generates
+async fn auto_increment_increment<S: Session>(tx: &mut S) -> Result<u64> {
+    let increment: Option<u64> = tx
+        .query_first("SELECT @@auto_increment_increment")
+        .await
+        .map_err(query_error)?;
+    Ok(increment.unwrap_or(1))
+}
+
+/// Key values of the rows an INSERT created, when every row sets every key
+/// column
+fn explicit_keys(query: &Query, keys: &[String]) -> Option<Vec<Vec<Value>>> {
+    if query.values.is_empty() {
+        return None;
+    }
+    query
+        .values
+        .iter()
+        .map(|row| keys.iter().map(|k| row.get(k).cloned()).collect())
+        .collect()
+}
+
+/// Key values of the rows an INSERT created from a single auto-increment
+/// column
+///
+/// A multi-row VALUES insert gets ids `increment` apart starting at
+/// `LAST_INSERT_ID()` in every `innodb_autoinc_lock_mode`.
+fn generated_keys(
+    query: &Query,
+    keys: &[String],
+    last_insert_id: Option<u64>,
+    affected: u64,
+    increment: u64,
+) -> Result<Vec<Vec<Value>>> {
+    match (keys, last_insert_id) {
+        ([_], Some(first)) if query.on_conflict.is_none() => Ok((0..affected)
+            .map(|i| vec![Value::Int64((first + i * increment) as i64)])
+            .collect()),
+        _ => Err(invalid(format!(
+            "RETURNING on MySQL needs the key of each row inserted into `{}`",
+            query.table
+        ))),
+    }
+}
+
+/// Key values of a located row after an UPDATE that may set them
+fn updated_keys(query: &Query, keys: &[String], located: Vec<Value>) -> Vec<Value> {
+    let Some(assignments) = query.values.first() else {
+        return located;
+    };
+    keys.iter()
+        .zip(located)
+        .map(|(k, v)| assignments.get(k).cloned().unwrap_or(v))
+        .collect()
+}
+
+/// Key values of a located row
+fn row_keys(row: &Row, keys: &[String]) -> Vec<Value> {
+    keys.iter()
+        .map(|k| row.get(k).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+/// Match rows by key: `id IN (...)`, or one AND per row for composite keys
+fn key_filter(keys: &[String], rows: &[Vec<Value>]) -> Expr {
+    if let [key] = keys {
+        return Expr::is_in(key, rows.iter().map(|r| r[0].clone()).collect());
     }
+    rows.iter()
+        .map(|row| {
+            keys.iter()
+                .zip(row)
+                .map(|(k, v)| Expr::eq(k, v.clone()))
+                .reduce(Expr::and)
+                .expect("primary key has columns")
+        })
+        .reduce(Expr::or)
+        .expect("at least one row")
+}
+
+fn invalid(message: impl Into<String>) -> ChakraError {
+    ChakraError::Query(QueryError::Invalid {
+        message: message.into(),
+    })
 }
 
 /// Convert a MySQL row to a Chakra row
@@ -102,7 +706,160 @@ fn mysql_row_to_chakra(row: mysql_async::Row) -> Row {
     Row::new(columns, values)
 }
 
+/// `ER_DUP_ENTRY`
+const DUPLICATE_ENTRY: u16 = 1062;
+
+/// `ER_LOCK_DEADLOCK`
+const LOCK_DEADLOCK: u16 = 1213;
+
+/// Convert a failed query into a `ChakraError`, keeping unique violations
+/// structured so models can map them to their constraints, and deadlocks
+/// so they can be retried
+fn query_error(e: mysql_async::Error) -> ChakraError {
+    match &e {
+        mysql_async::Error::Server(server) if server.code == DUPLICATE_ENTRY => {
+            ChakraError::unique_violation(duplicate_key(&server.message), Vec::new())
+        }
+        mysql_async::Error::Server(server) if server.code == LOCK_DEADLOCK => {
+            ChakraError::Query(QueryError::Deadlock {
+                message: server.message.clone(),
+            })
+        }
+        _ => ChakraError::Query(QueryError::ExecutionFailed {
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Key name from `Duplicate entry '1-home' for key 'pages.uq_tenant_slug'`
+///
+/// MySQL 8 prefixes the key with its table; older servers don't.
+fn duplicate_key(message: &str) -> Option<String> {
+    let key = message.rsplit_once("for key '")?.1.strip_suffix('\'')?;
+    Some(key.rsplit_once('.').map_or(key, |(_, key)| key).to_string())
+}
+
 #[cfg(test)]
 mod tests {
     // Integration tests would require a running MySQL instance
+    use super::*;
+
+    #[test]
+    fn test_duplicate_key() {
+        assert_eq!(
+            duplicate_key("Duplicate entry '1-home' for key 'pages.uq_tenant_slug'").as_deref(),
+            Some("uq_tenant_slug")
+        );
+        assert_eq!(
+            duplicate_key("Duplicate entry 'a@b.c' for key 'email'").as_deref(),
+            Some("email")
+        );
+    }
+
+    #[test]
+    fn test_inserted_keys() {
+        let keys = vec!["id".to_string()];
+        let query = Query::insert()
+            .table("users")
+            .set("name", "a")
+            .returning(&["id", "name"])
+            .build();
+        assert_eq!(explicit_keys(&query, &keys), None);
+        assert_eq!(
+            generated_keys(&query, &keys, Some(7), 1, 1).unwrap(),
+            vec![vec![Value::Int64(7)]]
+        );
+        assert!(generated_keys(&query, &keys, None, 1, 1).is_err());
+
+        let explicit = Query::insert().table("users").set("id", 42i64).build();
+        assert_eq!(
+            explicit_keys(&explicit, &keys),
+            Some(vec![vec![Value::Int64(42)]])
+        );
+    }
+
+    #[test]
+    fn test_generated_keys_step_by_increment() {
+        // Galera and multi-primary setups run with auto_increment_increment > 1
+        let keys = vec!["id".to_string()];
+        let query = Query::insert()
+            .table("users")
+            .set("name", "a")
+            .returning(&["id"])
+            .build();
+        assert_eq!(
+            generated_keys(&query, &keys, Some(11), 3, 10).unwrap(),
+            vec![
+                vec![Value::Int64(11)],
+                vec![Value::Int64(21)],
+                vec![Value::Int64(31)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_updated_keys() {
+        let keys = vec!["tenant_id".to_string(), "slug".to_string()];
+        let located = vec![Value::Int64(1), Value::String("home".into())];
+
+        let renamed = Query::update()
+            .table("pages")
+            .set("slug", "start")
+            .returning(&["title"])
+            .build();
+        assert_eq!(
+            updated_keys(&renamed, &keys, located.clone()),
+            vec![Value::Int64(1), Value::String("start".into())]
+        );
+
+        let retitled = Query::update().table("pages").set("title", "Home").build();
+        assert_eq!(updated_keys(&retitled, &keys, located.clone()), located);
+    }
+
+    #[test]
+    fn test_insert_batches() {
+        let sql = "INSERT INTO `tags` (`id`, `name`) VALUES (?, ?)";
+        let params: Vec<Vec<Value>> = (0..1000)
+            .map(|i| vec![Value::Int64(i), Value::String(format!("tag {}", i))])
+            .collect();
+
+        // One round trip instead of a thousand
+        let batches = insert_batches(sql, &params, MySqlDialect.max_params()).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].1.len(), 2000);
+        assert_eq!(batches[0].1[2], Value::Int64(1));
+
+        // 300 rows fit in 600 parameters
+        let batches = insert_batches(sql, &params, 600).unwrap();
+        assert_eq!(batches.len(), 4);
+        assert_eq!(batches[3].1.len(), 200);
+        assert_eq!(
+            batches[3].0,
+            format!(
+                "INSERT INTO `tags` (`id`, `name`) VALUES (?, ?){}",
+                ", (?, ?)".repeat(99)
+            )
+        );
+
+        assert!(
+            insert_batches("UPDATE `tags` SET `name` = ? WHERE `id` = ?", &params, 600).is_none()
+        );
+        assert!(insert_batches(sql, &[vec![Value::Int64(1)], Vec::new()], 600).is_none());
+    }
+
+    #[test]
+    fn test_key_filter() {
+        let keys = vec!["tenant_id".to_string(), "slug".to_string()];
+        let rows = vec![
+            vec![Value::Int64(1), Value::String("home".into())],
+            vec![Value::Int64(2), Value::String("about".into())],
+        ];
+        let mut select = Query::select().from("pages").build();
+        select.columns = vec!["title".to_string()];
+        select.where_clause = Some(key_filter(&keys, &rows));
+        assert_eq!(
+            MySqlDialect.generate(&select).sql,
+            "SELECT `title` FROM `pages` WHERE ((`tenant_id` = ? AND `slug` = ?) OR (`tenant_id` = ? AND `slug` = ?))"
+        );
+    }
 }
@@ -1,6 +1,7 @@
 //! Type conversions between Chakra and MySQL
 
 use chakra_core::types::Value;
+use chrono::{NaiveDate, NaiveTime};
 use mysql_async::Value as MySqlValue;
 
 /// Convert a Chakra Value to a MySQL Value
@@ -18,6 +19,10 @@ pub fn to_mysql_value(value: &Value) -> MySqlValue {
         Value::DateTime(dt) => MySqlValue::from(dt.format("%Y-%m-%d %H:%M:%S%.6f").to_string()),
         Value::Date(d) => MySqlValue::from(d.format("%Y-%m-%d").to_string()),
         Value::Time(t) => MySqlValue::from(t.format("%H:%M:%S%.6f").to_string()),
+        Value::IpAddr(a) => MySqlValue::from(a.to_string()),
+        Value::Cidr(c) => MySqlValue::from(c.to_string()),
+        Value::MacAddr(m) => MySqlValue::from(m.to_string()),
+        Value::Range(r) => MySqlValue::from(r.to_string()),
         Value::Json(j) => MySqlValue::from(j.to_string()),
         Value::Array(arr) => {
             let json = serde_json::Value::Array(
@@ -50,7 +55,33 @@ pub fn from_mysql_value(value: MySqlValue) -> Value {
                 Err(_) => Value::Bytes(b),
             }
         }
-        _ => Value::Null,
+        // DATE, DATETIME and TIMESTAMP; a DATE has no time of day
+        MySqlValue::Date(year, month, day, hour, minute, second, micros) => {
+            let Some(date) = NaiveDate::from_ymd_opt(year.into(), month.into(), day.into()) else {
+                // Zero dates such as 0000-00-00
+                return Value::Null;
+            };
+            match (hour, minute, second, micros) {
+                (0, 0, 0, 0) => Value::Date(date),
+                _ => {
+                    NaiveTime::from_hms_micro_opt(hour.into(), minute.into(), second.into(), micros)
+                        .map_or(Value::Null, |time| date.and_time(time).into())
+                }
+            }
+        }
+        // TIME is a duration of up to ±838 hours; only times of day fit NaiveTime
+        MySqlValue::Time(false, 0, hour, minute, second, micros) => {
+            NaiveTime::from_hms_micro_opt(hour.into(), minute.into(), second.into(), micros)
+                .map_or(Value::Null, Value::Time)
+        }
+        MySqlValue::Time(negative, days, hour, minute, second, micros) => Value::String(format!(
+            "{}{}:{:02}:{:02}.{:06}",
+            if negative { "-" } else { "" },
+            days * 24 + hour as u32,
+            minute,
+            second,
+            micros
+        )),
     }
 }
 
@@ -65,4 +96,30 @@ mod tests {
         // Just verify it doesn't panic
         assert!(!matches!(mysql_val, MySqlValue::NULL));
     }
+
+    #[test]
+    fn test_from_mysql_temporal() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(
+            from_mysql_value(MySqlValue::Date(2024, 5, 1, 0, 0, 0, 0)),
+            Value::Date(date)
+        );
+        let time = NaiveTime::from_hms_micro_opt(12, 30, 0, 5).unwrap();
+        assert_eq!(
+            from_mysql_value(MySqlValue::Date(2024, 5, 1, 12, 30, 0, 5)),
+            Value::DateTime(date.and_time(time).and_utc())
+        );
+        assert_eq!(
+            from_mysql_value(MySqlValue::Date(0, 0, 0, 0, 0, 0, 0)),
+            Value::Null
+        );
+        assert_eq!(
+            from_mysql_value(MySqlValue::Time(false, 0, 12, 30, 0, 5)),
+            Value::Time(time)
+        );
+        assert_eq!(
+            from_mysql_value(MySqlValue::Time(true, 1, 2, 0, 0, 0)),
+            Value::String("-26:00:00.000000".into())
+        );
+    }
 }
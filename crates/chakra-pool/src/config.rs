@@ -27,6 +27,166 @@ pub struct PoolConfig {
     pub connection_string: String,
     /// Application name for connection identification
     pub application_name: Option<String>,
+    /// Number of hot statements prepared on new connections (0 disables)
+    pub warm_statements: usize,
+    /// Fail fast instead of queueing when the pool is overloaded
+    pub load_shedding: Option<LoadShedding>,
+    /// Adjust `min_connections` toward observed demand
+    pub adaptive: Option<AdaptiveSizing>,
+    /// Retry failed connects with backoff
+    pub connect_retry: Option<ConnectRetry>,
+}
+
+/// Retries of failed connects, with exponential backoff
+///
+/// Meant to ride out a managed database failover, when connects are
+/// refused or time out until the new primary is reachable. Authentication
+/// failures are not retried.
+#[derive(Debug, Clone)]
+pub struct ConnectRetry {
+    /// Connect attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled after each
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ConnectRetry {
+    /// Create retry settings with default backoff
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the total number of attempts
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the wait before the first retry
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the longest wait between attempts
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Wait before retry number `retry`, counting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Settings for the controller that moves `min_connections` with demand
+///
+/// Evaluated on every health check tick. The minimum grows while acquires
+/// wait longer than `scale_up_wait` and shrinks while more than
+/// `scale_down_idle` of the connections sit idle, but only after the same
+/// signal was seen for `hysteresis` ticks in a row.
+#[derive(Debug, Clone)]
+pub struct AdaptiveSizing {
+    /// Average acquire wait over a tick that counts as pressure
+    pub scale_up_wait: Duration,
+    /// Idle fraction of open connections that counts as slack
+    pub scale_down_idle: f64,
+    /// Consecutive ticks a signal must hold before acting
+    pub hysteresis: u32,
+    /// Connections added or removed per adjustment
+    pub step: u32,
+}
+
+impl Default for AdaptiveSizing {
+    fn default() -> Self {
+        Self {
+            scale_up_wait: Duration::from_millis(10),
+            scale_down_idle: 0.5,
+            hysteresis: 3,
+            step: 1,
+        }
+    }
+}
+
+impl AdaptiveSizing {
+    /// Create adaptive sizing with default thresholds
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the wait that counts as pressure
+    pub fn scale_up_wait(mut self, wait: Duration) -> Self {
+        self.scale_up_wait = wait;
+        self
+    }
+
+    /// Set the idle fraction that counts as slack
+    pub fn scale_down_idle(mut self, fraction: f64) -> Self {
+        self.scale_down_idle = fraction;
+        self
+    }
+
+    /// Set how many consecutive ticks a signal must hold
+    pub fn hysteresis(mut self, ticks: u32) -> Self {
+        self.hysteresis = ticks.max(1);
+        self
+    }
+
+    /// Set the adjustment step
+    pub fn step(mut self, step: u32) -> Self {
+        self.step = step.max(1);
+        self
+    }
+}
+
+/// Thresholds at which `acquire()` fails with `PoolOverloaded`
+#[derive(Debug, Clone, Default)]
+pub struct LoadShedding {
+    /// Maximum number of callers waiting for a connection
+    pub max_waiters: Option<usize>,
+    /// Maximum recent average wait while callers are queued
+    pub target_wait: Option<Duration>,
+}
+
+impl LoadShedding {
+    /// Create load shedding settings with no thresholds
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shed load once this many callers are waiting
+    pub fn max_waiters(mut self, max: usize) -> Self {
+        self.max_waiters = Some(max);
+        self
+    }
+
+    /// Shed load while callers queue and the average wait exceeds `target`
+    pub fn target_wait(mut self, target: Duration) -> Self {
+        self.target_wait = Some(target);
+        self
+    }
+
+    /// Check whether a new caller should be turned away
+    pub fn is_overloaded(&self, waiters: usize, avg_wait: Duration) -> bool {
+        self.max_waiters.is_some_and(|max| waiters >= max)
+            || (waiters > 0 && self.target_wait.is_some_and(|target| avg_wait > target))
+    }
 }
 
 impl PoolConfig {
@@ -43,9 +203,27 @@ impl PoolConfig {
             test_on_checkin: false,
             connection_string: connection_string.into(),
             application_name: None,
+            warm_statements: 32,
+            load_shedding: None,
+            adaptive: None,
+            connect_retry: None,
         }
     }
 
+    /// A pool of exactly one connection that is never closed while the pool
+    /// is open
+    ///
+    /// For databases that only live as long as their connection, such as
+    /// SQLite `:memory:`: every caller sees the same database, queueing for
+    /// it in turn.
+    pub fn single_connection(connection_string: impl Into<String>) -> Self {
+        Self::new(connection_string)
+            .min_connections(1)
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    }
+
     /// Set minimum connections
     pub fn min_connections(mut self, min: u32) -> Self {
         self.min_connections = min;
@@ -100,6 +278,32 @@ impl PoolConfig {
         self
     }
 
+    /// Set how many hot statements new connections prepare up front
+    pub fn warm_statements(mut self, count: usize) -> Self {
+        self.warm_statements = count;
+        self
+    }
+
+    /// Enable load shedding
+    pub fn load_shedding(mut self, shedding: LoadShedding) -> Self {
+        self.load_shedding = Some(shedding);
+        self
+    }
+
+    /// Enable adaptive sizing of `min_connections`
+    ///
+    /// The configured `min_connections` becomes the floor.
+    pub fn adaptive(mut self, adaptive: AdaptiveSizing) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Retry failed connects with backoff
+    pub fn connect_retry(mut self, retry: ConnectRetry) -> Self {
+        self.connect_retry = Some(retry);
+        self
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> Result<(), ConfigError> {
         if self.min_connections > self.max_connections {
@@ -137,10 +341,17 @@ impl Default for PoolConfig {
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
     #[error("Invalid range for {field}: min ({min}) > max ({max})")]
-    InvalidRange { field: &'static str, min: u32, max: u32 },
+    InvalidRange {
+        field: &'static str,
+        min: u32,
+        max: u32,
+    },
 
     #[error("Invalid value for {field}: {message}")]
-    InvalidValue { field: &'static str, message: String },
+    InvalidValue {
+        field: &'static str,
+        message: String,
+    },
 
     #[error("Missing required field: {field}")]
     MissingField { field: &'static str },
@@ -208,9 +419,31 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_single_connection() {
+        let config = PoolConfig::single_connection("sqlite::memory:");
+        assert_eq!((config.min_connections, config.max_connections), (1, 1));
+        assert!(config.idle_timeout.is_none() && config.max_lifetime.is_none());
+        assert!(config.validate().is_ok());
+    }
+
     #[test]
     fn test_empty_connection_string() {
         let config = PoolConfig::default();
         assert!(config.validate().is_err());
     }
+
+    #[test]
+    fn test_load_shedding_thresholds() {
+        let shedding = LoadShedding::new().target_wait(Duration::from_millis(50));
+        let slow = Duration::from_millis(80);
+
+        // A slow average alone doesn't shed while nobody is queued
+        assert!(!shedding.is_overloaded(0, slow));
+        assert!(shedding.is_overloaded(1, slow));
+        assert!(!shedding.is_overloaded(1, Duration::from_millis(10)));
+        assert!(LoadShedding::new()
+            .max_waiters(8)
+            .is_overloaded(8, Duration::ZERO));
+    }
 }
@@ -0,0 +1,489 @@
+//! Database credentials fetched at connect time
+//!
+//! A `CredentialProvider` hands connection managers a username and password
+//! each time they open a connection, so passwords never have to sit in
+//! `chakra.toml` or a static environment variable. When the database rejects
+//! the credentials (e.g. after the secret was rotated), `connect_with` calls
+//! `invalidate` and retries once with freshly fetched ones.
+//!
+//! The AWS Secrets Manager and Vault providers are behind the `aws` and
+//! `vault` features and shell out to the `aws` and `vault` CLIs, picking up
+//! their usual credential chains.
+
+use async_trait::async_trait;
+use chakra_core::error::{ChakraError, Result};
+use parking_lot::Mutex;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A username and password for one connection
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// Username, or `None` to keep the configured one
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Credentials with only a password
+    pub fn password(password: impl Into<String>) -> Self {
+        Self {
+            username: None,
+            password: password.into(),
+        }
+    }
+
+    /// Set the username
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// Parse a secret: either a bare password or a JSON object with
+    /// `password` and optionally `username`, as stored by RDS and Vault
+    pub fn parse(secret: &str) -> Result<Self> {
+        let secret = secret.trim();
+        if !secret.starts_with('{') {
+            return Ok(Self::password(secret));
+        }
+        let value: serde_json::Value = serde_json::from_str(secret)
+            .map_err(|e| ChakraError::config(format!("Invalid credentials JSON: {}", e)))?;
+        Self::from_json(&value)
+    }
+
+    fn from_json(value: &serde_json::Value) -> Result<Self> {
+        let password = value
+            .get("password")
+            .and_then(|p| p.as_str())
+            .ok_or_else(|| ChakraError::config("Credentials JSON has no \"password\""))?;
+        let username = value
+            .get("username")
+            .and_then(|u| u.as_str())
+            .map(str::to_string);
+        Ok(Self {
+            username,
+            password: password.to_string(),
+        })
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Source of database credentials
+#[async_trait]
+pub trait CredentialProvider: Send + Sync + Debug {
+    /// Fetch the credentials for a new connection
+    async fn credentials(&self) -> Result<Credentials>;
+
+    /// Drop anything cached, called after the database rejected the
+    /// credentials
+    fn invalidate(&self) {}
+}
+
+/// Credentials read from environment variables on every connect
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    password_var: String,
+    username_var: Option<String>,
+}
+
+impl EnvCredentials {
+    /// Read the password from `password_var`
+    pub fn new(password_var: impl Into<String>) -> Self {
+        Self {
+            password_var: password_var.into(),
+            username_var: None,
+        }
+    }
+
+    /// Also read the username from `username_var`
+    pub fn username_var(mut self, username_var: impl Into<String>) -> Self {
+        self.username_var = Some(username_var.into());
+        self
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for EnvCredentials {
+    async fn credentials(&self) -> Result<Credentials> {
+        let read = |var: &str| {
+            std::env::var(var).map_err(|_| {
+                ChakraError::config(format!("Environment variable {} is not set", var))
+            })
+        };
+        let mut credentials = Credentials::password(read(&self.password_var)?);
+        if let Some(ref var) = self.username_var {
+            credentials.username = Some(read(var)?);
+        }
+        Ok(credentials)
+    }
+}
+
+/// Credentials read from files on every connect, e.g. mounted Kubernetes
+/// or Docker secrets that are rewritten on rotation
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    password_file: PathBuf,
+    username_file: Option<PathBuf>,
+}
+
+impl FileCredentials {
+    /// Read the password (or a JSON secret, see `Credentials::parse`) from
+    /// `password_file`
+    pub fn new(password_file: impl Into<PathBuf>) -> Self {
+        Self {
+            password_file: password_file.into(),
+            username_file: None,
+        }
+    }
+
+    /// Also read the username from `username_file`
+    pub fn username_file(mut self, username_file: impl Into<PathBuf>) -> Self {
+        self.username_file = Some(username_file.into());
+        self
+    }
+}
+
+async fn read_secret_file(path: &PathBuf) -> Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| ChakraError::config(format!("Failed to read {}: {}", path.display(), e)))
+}
+
+#[async_trait]
+impl CredentialProvider for FileCredentials {
+    async fn credentials(&self) -> Result<Credentials> {
+        let mut credentials = Credentials::parse(&read_secret_file(&self.password_file).await?)?;
+        if let Some(ref path) = self.username_file {
+            credentials.username = Some(read_secret_file(path).await?.trim().to_string());
+        }
+        Ok(credentials)
+    }
+}
+
+/// Credentials printed by a command, e.g. a secrets manager CLI
+///
+/// The command's output is parsed with `Credentials::parse`.
+#[derive(Debug, Clone)]
+pub struct CommandCredentials {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandCredentials {
+    /// Run `program` with `args`
+    pub fn new(
+        program: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Run the command and return its trimmed output
+    async fn output(&self) -> Result<String> {
+        let output = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| ChakraError::config(format!("Failed to run {}: {}", self.program, e)))?;
+        if !output.status.success() {
+            return Err(ChakraError::config(format!(
+                "{} failed: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map(|s| s.trim().to_string())
+            .map_err(|_| ChakraError::config(format!("{} printed invalid UTF-8", self.program)))
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for CommandCredentials {
+    async fn credentials(&self) -> Result<Credentials> {
+        Credentials::parse(&self.output().await?)
+    }
+}
+
+/// A secret in AWS Secrets Manager, fetched with the `aws` CLI
+///
+/// The secret is the JSON stored for RDS, with `username` and `password`.
+#[cfg(feature = "aws")]
+#[derive(Debug, Clone)]
+pub struct AwsSecretsManager {
+    command: CommandCredentials,
+}
+
+#[cfg(feature = "aws")]
+impl AwsSecretsManager {
+    /// Fetch the secret `secret_id` (a name or ARN)
+    pub fn new(secret_id: impl Into<String>) -> Self {
+        Self::with_args(secret_id, Vec::<String>::new())
+    }
+
+    /// Fetch the secret from another region
+    pub fn region(secret_id: impl Into<String>, region: impl Into<String>) -> Self {
+        Self::with_args(secret_id, ["--region".to_string(), region.into()])
+    }
+
+    fn with_args(secret_id: impl Into<String>, extra: impl IntoIterator<Item = String>) -> Self {
+        let mut args: Vec<String> = ["secretsmanager", "get-secret-value", "--secret-id"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        args.push(secret_id.into());
+        args.extend(["--query", "SecretString", "--output", "text"].map(str::to_string));
+        args.extend(extra);
+        Self {
+            command: CommandCredentials::new("aws", args),
+        }
+    }
+}
+
+#[cfg(feature = "aws")]
+#[async_trait]
+impl CredentialProvider for AwsSecretsManager {
+    async fn credentials(&self) -> Result<Credentials> {
+        self.command.credentials().await
+    }
+}
+
+/// A secret in HashiCorp Vault, fetched with the `vault` CLI
+///
+/// Works for KV v1 and v2 secrets with a `password` key, and for the
+/// database secrets engine's dynamic credentials.
+#[cfg(feature = "vault")]
+#[derive(Debug, Clone)]
+pub struct VaultCredentials {
+    command: CommandCredentials,
+}
+
+#[cfg(feature = "vault")]
+impl VaultCredentials {
+    /// Read `path`, e.g. `secret/data/app/db` or `database/creds/app`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            command: CommandCredentials::new(
+                "vault",
+                ["read".to_string(), "-format=json".to_string(), path.into()],
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "vault")]
+#[async_trait]
+impl CredentialProvider for VaultCredentials {
+    async fn credentials(&self) -> Result<Credentials> {
+        let output = self.command.output().await?;
+        let value: serde_json::Value = serde_json::from_str(&output)
+            .map_err(|e| ChakraError::config(format!("Invalid vault output: {}", e)))?;
+        // KV v2 nests the secret one level deeper
+        let data = &value["data"];
+        let data = if data["data"].is_object() {
+            &data["data"]
+        } else {
+            data
+        };
+        Credentials::from_json(data)
+    }
+}
+
+/// Reuse another provider's credentials for `ttl`, for providers that are
+/// slow or rate limited
+#[derive(Debug)]
+pub struct CachedCredentials<P> {
+    inner: P,
+    ttl: Duration,
+    cached: Mutex<Option<(Credentials, Instant)>>,
+}
+
+impl<P: CredentialProvider> CachedCredentials<P> {
+    /// Cache `inner`'s credentials for `ttl`
+    pub fn new(inner: P, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: CredentialProvider> CredentialProvider for CachedCredentials<P> {
+    async fn credentials(&self) -> Result<Credentials> {
+        if let Some((ref credentials, fetched)) = *self.cached.lock() {
+            if fetched.elapsed() < self.ttl {
+                return Ok(credentials.clone());
+            }
+        }
+        let credentials = self.inner.credentials().await?;
+        *self.cached.lock() = Some((credentials.clone(), Instant::now()));
+        Ok(credentials)
+    }
+
+    fn invalidate(&self) {
+        *self.cached.lock() = None;
+        self.inner.invalidate();
+    }
+}
+
+/// Open a connection with `provider`'s credentials, retrying once with fresh
+/// ones if the database rejects them
+pub async fn connect_with<T, F, Fut>(provider: &dyn CredentialProvider, mut connect: F) -> Result<T>
+where
+    F: FnMut(Credentials) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    match connect(provider.credentials().await?).await {
+        Err(e) if e.is_authentication_failure() => {
+            warn!(
+                "Authentication failed, retrying with fresh credentials: {}",
+                e
+            );
+            provider.invalidate();
+            connect(provider.credentials().await?).await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chakra_core::error::ConnectionError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Hands out `secret-N`, counting fetches
+    #[derive(Debug, Default)]
+    struct Rotating {
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CredentialProvider for Rotating {
+        async fn credentials(&self) -> Result<Credentials> {
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(Credentials::password(format!("secret-{}", n)))
+        }
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        assert_eq!(
+            Credentials::parse("hunter2\n").unwrap(),
+            Credentials::password("hunter2")
+        );
+        let credentials =
+            Credentials::parse(r#"{"username": "app", "password": "pw", "host": "db"}"#).unwrap();
+        assert_eq!(credentials, Credentials::password("pw").username("app"));
+        assert!(Credentials::parse(r#"{"username": "app"}"#).is_err());
+        assert!(!format!("{:?}", credentials).contains("pw"));
+    }
+
+    #[tokio::test]
+    async fn test_env_and_file_credentials() {
+        std::env::set_var("CHAKRA_TEST_DB_PASSWORD", "from-env");
+        std::env::set_var("CHAKRA_TEST_DB_USER", "app");
+        let env =
+            EnvCredentials::new("CHAKRA_TEST_DB_PASSWORD").username_var("CHAKRA_TEST_DB_USER");
+        assert_eq!(
+            env.credentials().await.unwrap(),
+            Credentials::password("from-env").username("app")
+        );
+        assert!(EnvCredentials::new("CHAKRA_TEST_DB_MISSING")
+            .credentials()
+            .await
+            .is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("chakra-test-password-{}", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+        let file = FileCredentials::new(&path);
+        assert_eq!(
+            file.credentials().await.unwrap(),
+            Credentials::password("first")
+        );
+        // Rotated secrets are picked up on the next connect
+        std::fs::write(&path, "second").unwrap();
+        assert_eq!(
+            file.credentials().await.unwrap(),
+            Credentials::password("second")
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_command_credentials() {
+        let command = CommandCredentials::new("echo", [r#"{"username": "app", "password": "pw"}"#]);
+        assert_eq!(
+            command.credentials().await.unwrap(),
+            Credentials::password("pw").username("app")
+        );
+        assert!(CommandCredentials::new("false", Vec::<String>::new())
+            .credentials()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_credentials() {
+        let cached = CachedCredentials::new(Rotating::default(), Duration::from_secs(60));
+        assert_eq!(cached.credentials().await.unwrap().password, "secret-0");
+        assert_eq!(cached.credentials().await.unwrap().password, "secret-0");
+        cached.invalidate();
+        assert_eq!(cached.credentials().await.unwrap().password, "secret-1");
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_retries_on_auth_failure() {
+        let provider = Arc::new(CachedCredentials::new(
+            Rotating::default(),
+            Duration::from_secs(60),
+        ));
+        let attempts = AtomicUsize::new(0);
+        let connect = |credentials: Credentials| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if credentials.password == "secret-0" {
+                    Err(ChakraError::Connection(
+                        ConnectionError::AuthenticationFailed {
+                            message: "password authentication failed".to_string(),
+                        },
+                    ))
+                } else {
+                    Ok(credentials.password)
+                }
+            }
+        };
+        assert_eq!(
+            connect_with(provider.as_ref(), connect).await.unwrap(),
+            "secret-1"
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // Other errors are not retried
+        let result: Result<()> = connect_with(provider.as_ref(), |_| async {
+            Err(ChakraError::connection("connection refused"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(provider.inner.fetches.load(Ordering::SeqCst), 2);
+    }
+}
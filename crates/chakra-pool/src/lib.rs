@@ -5,13 +5,29 @@
 //! - Connection lifecycle management
 //! - Health checking and validation
 //! - Pool metrics and monitoring
+//! - Shared prepared statement registry
+//! - Read-your-writes tracking for replica routing
+//! - Credential providers for rotating database passwords
 
 pub mod config;
+pub mod credentials;
 pub mod manager;
 pub mod metrics;
 pub mod pool;
+pub mod routing;
+pub mod statements;
 
-pub use config::PoolConfig;
+pub use config::{AdaptiveSizing, ConnectRetry, LoadShedding, PoolConfig};
+#[cfg(feature = "aws")]
+pub use credentials::AwsSecretsManager;
+#[cfg(feature = "vault")]
+pub use credentials::VaultCredentials;
+pub use credentials::{
+    CachedCredentials, CommandCredentials, CredentialProvider, Credentials, EnvCredentials,
+    FileCredentials,
+};
 pub use manager::ConnectionManager;
 pub use metrics::PoolMetrics;
 pub use pool::{Pool, PooledConnection};
+pub use routing::{Lsn, ReadYourWrites, Route};
+pub use statements::StatementRegistry;
@@ -35,11 +35,24 @@ pub trait ConnectionManager: Send + Sync + Debug {
         Ok(())
     }
 
+    /// Prepare a statement ahead of its first use
+    ///
+    /// Called with the pool's hot statements when a connection is created.
+    async fn prepare(&self, _conn: &mut Self::Connection, _sql: &str) -> Result<()> {
+        Ok(())
+    }
+
     /// Reset connection state (e.g., rollback any open transaction)
     async fn reset(&self, conn: &mut Self::Connection) -> Result<()>;
 
     /// Close a connection
     async fn close(&self, conn: Self::Connection) -> Result<()>;
+
+    /// Forget anything cached for connecting, such as resolved addresses
+    ///
+    /// Called after a failed connect and by `Pool::invalidate_all`, so the
+    /// next connect starts fresh, e.g. after a DNS failover.
+    fn invalidate(&self) {}
 }
 
 /// Connection wrapper with metadata
@@ -55,6 +68,9 @@ pub struct ManagedConnection<C> {
     pub use_count: u64,
     /// Unique connection ID
     pub id: u64,
+    /// Pool generation the connection was opened in; connections from
+    /// before `Pool::invalidate_all` are closed instead of reused
+    pub generation: u64,
 }
 
 impl<C> ManagedConnection<C> {
@@ -67,6 +83,7 @@ impl<C> ManagedConnection<C> {
             last_used_at: now,
             use_count: 0,
             id,
+            generation: 0,
         }
     }
 
@@ -18,6 +18,8 @@ pub struct PoolMetrics {
     pub acquires_success: AtomicU64,
     /// Failed connection acquires (timeout)
     pub acquires_timeout: AtomicU64,
+    /// Acquires rejected by load shedding
+    pub acquires_shed: AtomicU64,
     /// Total connection releases
     pub releases_total: AtomicU64,
     /// Total validations performed
@@ -32,6 +34,16 @@ pub struct PoolMetrics {
     pub total_acquire_wait_us: AtomicU64,
     /// Maximum acquire wait time in microseconds
     pub max_acquire_wait_us: AtomicU64,
+    /// Current adaptive minimum pool size
+    pub min_connections: AtomicU64,
+    /// Times adaptive sizing raised the minimum
+    pub min_scale_ups: AtomicU64,
+    /// Times adaptive sizing lowered the minimum
+    pub min_scale_downs: AtomicU64,
+    /// Failed connects that were retried
+    pub connect_retries: AtomicU64,
+    /// Calls to `Pool::invalidate_all`
+    pub invalidations: AtomicU64,
 }
 
 impl PoolMetrics {
@@ -74,6 +86,12 @@ impl PoolMetrics {
         self.acquires_timeout.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record an acquire rejected by load shedding
+    pub fn record_acquire_shed(&self) {
+        self.acquires_total.fetch_add(1, Ordering::Relaxed);
+        self.acquires_shed.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Record a connection release
     pub fn record_release(&self) {
         self.releases_total.fetch_add(1, Ordering::Relaxed);
@@ -89,6 +107,26 @@ impl PoolMetrics {
         }
     }
 
+    /// Record a retried connect
+    pub fn record_connect_retry(&self) {
+        self.connect_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record an invalidation of every connection
+    pub fn record_invalidation(&self) {
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record an adaptive change of the minimum pool size
+    pub fn record_min_connections_change(&self, from: u32, to: u32) {
+        if to > from {
+            self.min_scale_ups.fetch_add(1, Ordering::Relaxed);
+        } else if to < from {
+            self.min_scale_downs.fetch_add(1, Ordering::Relaxed);
+        }
+        self.min_connections.store(u64::from(to), Ordering::Relaxed);
+    }
+
     /// Set idle connection count
     pub fn set_idle_connections(&self, count: u64) {
         self.idle_connections.store(count, Ordering::Relaxed);
@@ -107,6 +145,7 @@ impl PoolMetrics {
             acquires_total: self.acquires_total.load(Ordering::Relaxed),
             acquires_success: self.acquires_success.load(Ordering::Relaxed),
             acquires_timeout: self.acquires_timeout.load(Ordering::Relaxed),
+            acquires_shed: self.acquires_shed.load(Ordering::Relaxed),
             releases_total: self.releases_total.load(Ordering::Relaxed),
             validations_total: self.validations_total.load(Ordering::Relaxed),
             validations_failed: self.validations_failed.load(Ordering::Relaxed),
@@ -116,6 +155,11 @@ impl PoolMetrics {
             max_acquire_wait: Duration::from_micros(
                 self.max_acquire_wait_us.load(Ordering::Relaxed),
             ),
+            min_connections: self.min_connections.load(Ordering::Relaxed),
+            min_scale_ups: self.min_scale_ups.load(Ordering::Relaxed),
+            min_scale_downs: self.min_scale_downs.load(Ordering::Relaxed),
+            connect_retries: self.connect_retries.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
         }
     }
 
@@ -123,11 +167,10 @@ impl PoolMetrics {
     pub fn average_acquire_wait(&self) -> Duration {
         let total = self.total_acquire_wait_us.load(Ordering::Relaxed);
         let count = self.acquires_success.load(Ordering::Relaxed);
-        if count > 0 {
-            Duration::from_micros(total / count)
-        } else {
-            Duration::ZERO
-        }
+        total
+            .checked_div(count)
+            .map(Duration::from_micros)
+            .unwrap_or(Duration::ZERO)
     }
 
     /// Reset all metrics
@@ -137,11 +180,16 @@ impl PoolMetrics {
         self.acquires_total.store(0, Ordering::Relaxed);
         self.acquires_success.store(0, Ordering::Relaxed);
         self.acquires_timeout.store(0, Ordering::Relaxed);
+        self.acquires_shed.store(0, Ordering::Relaxed);
         self.releases_total.store(0, Ordering::Relaxed);
         self.validations_total.store(0, Ordering::Relaxed);
         self.validations_failed.store(0, Ordering::Relaxed);
         self.total_acquire_wait_us.store(0, Ordering::Relaxed);
         self.max_acquire_wait_us.store(0, Ordering::Relaxed);
+        self.min_scale_ups.store(0, Ordering::Relaxed);
+        self.min_scale_downs.store(0, Ordering::Relaxed);
+        self.connect_retries.store(0, Ordering::Relaxed);
+        self.invalidations.store(0, Ordering::Relaxed);
     }
 }
 
@@ -153,6 +201,7 @@ pub struct MetricsSnapshot {
     pub acquires_total: u64,
     pub acquires_success: u64,
     pub acquires_timeout: u64,
+    pub acquires_shed: u64,
     pub releases_total: u64,
     pub validations_total: u64,
     pub validations_failed: u64,
@@ -160,6 +209,11 @@ pub struct MetricsSnapshot {
     pub in_use_connections: u64,
     pub avg_acquire_wait: Duration,
     pub max_acquire_wait: Duration,
+    pub min_connections: u64,
+    pub min_scale_ups: u64,
+    pub min_scale_downs: u64,
+    pub connect_retries: u64,
+    pub invalidations: u64,
 }
 
 impl MetricsSnapshot {
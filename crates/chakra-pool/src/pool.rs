@@ -3,16 +3,17 @@
 //! This module provides the core connection pool.
 
 use crate::config::PoolConfig;
-use crate::manager::{ConnectionManager, ConnectionState, ManagedConnection};
+use crate::manager::{ConnectionManager, ManagedConnection};
 use crate::metrics::PoolMetrics;
+use crate::statements::StatementRegistry;
 use chakra_core::error::{ChakraError, Result};
 use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::sync::Semaphore;
 use tracing::{debug, error, info, trace, warn};
 
 /// A connection pool
@@ -27,8 +28,20 @@ pub struct Pool<M: ConnectionManager> {
     semaphore: Arc<Semaphore>,
     /// Pool metrics
     metrics: Arc<PoolMetrics>,
+    /// Statements seen on any connection, used to warm new ones
+    statements: StatementRegistry,
     /// Next connection ID
     next_id: AtomicU64,
+    /// Callers currently waiting for a permit
+    waiters: AtomicUsize,
+    /// Moving average of recent acquire waits in microseconds
+    recent_wait_us: AtomicU64,
+    /// Current minimum pool size, moved by adaptive sizing
+    min_connections: AtomicU32,
+    /// Adaptive sizing state carried between maintenance ticks
+    adaptive: Mutex<AdaptiveState>,
+    /// Bumped by `invalidate_all`; older connections are not reused
+    generation: AtomicU64,
     /// Whether the pool is closed
     closed: std::sync::atomic::AtomicBool,
 }
@@ -47,11 +60,20 @@ impl<M: ConnectionManager + 'static> Pool<M> {
             semaphore: Arc::new(Semaphore::new(config.max_connections as usize)),
             connections: Mutex::new(VecDeque::new()),
             metrics: Arc::new(PoolMetrics::new()),
+            statements: StatementRegistry::default(),
             next_id: AtomicU64::new(1),
+            waiters: AtomicUsize::new(0),
+            recent_wait_us: AtomicU64::new(0),
+            min_connections: AtomicU32::new(config.min_connections),
+            adaptive: Mutex::new(AdaptiveState::default()),
+            generation: AtomicU64::new(0),
             closed: std::sync::atomic::AtomicBool::new(false),
             config,
         });
 
+        pool.metrics
+            .record_min_connections_change(0, pool.config.min_connections);
+
         // Initialize minimum connections
         pool.initialize_connections().await?;
 
@@ -72,9 +94,8 @@ impl<M: ConnectionManager + 'static> Pool<M> {
             match self.create_connection().await {
                 Ok(conn) => {
                     self.connections.lock().push_back(conn);
-                    self.metrics.set_idle_connections(
-                        self.connections.lock().len() as u64,
-                    );
+                    self.metrics
+                        .set_idle_connections(self.connections.lock().len() as u64);
                 }
                 Err(e) => {
                     warn!("Failed to create initial connection: {}", e);
@@ -87,10 +108,59 @@ impl<M: ConnectionManager + 'static> Pool<M> {
     /// Create a new connection
     async fn create_connection(&self) -> Result<ManagedConnection<M::Connection>> {
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
-        let conn = self.manager.connect().await?;
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut conn = self.connect().await?;
         self.metrics.record_connection_created();
         debug!(connection_id = id, "Created new connection");
-        Ok(ManagedConnection::new(conn, id))
+
+        for sql in self.statements.hot(self.config.warm_statements) {
+            if let Err(e) = self.manager.prepare(&mut conn, &sql).await {
+                debug!(
+                    connection_id = id,
+                    "Dropping statement that failed to prepare: {}", e
+                );
+                self.statements.forget(&sql);
+            }
+        }
+
+        let mut conn = ManagedConnection::new(conn, id);
+        conn.generation = generation;
+        Ok(conn)
+    }
+
+    /// Connect through the manager, retrying with backoff if configured
+    async fn connect(&self) -> Result<M::Connection> {
+        let mut retry = 0;
+        loop {
+            let error = match self.manager.connect().await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => e,
+            };
+            // Drop cached addresses so the next attempt re-resolves
+            self.manager.invalidate();
+            retry += 1;
+            let Some(policy) = &self.config.connect_retry else {
+                return Err(error);
+            };
+            if retry >= policy.max_attempts || error.is_authentication_failure() || self.is_closed()
+            {
+                return Err(error);
+            }
+            let backoff = policy.backoff(retry);
+            warn!(
+                retry,
+                backoff_ms = backoff.as_millis() as u64,
+                "Connect failed, retrying: {}",
+                error
+            );
+            self.metrics.record_connect_retry();
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// Whether `conn` was opened before the last `invalidate_all`
+    fn is_stale(&self, conn: &ManagedConnection<M::Connection>) -> bool {
+        conn.generation < self.generation.load(Ordering::Acquire)
     }
 
     /// Start the background maintenance task
@@ -126,10 +196,7 @@ impl<M: ConnectionManager + 'static> Pool<M> {
             while let Some(conn) = connections.pop_front() {
                 // Check if connection has expired
                 if self.is_connection_expired(&conn) {
-                    debug!(
-                        connection_id = conn.id,
-                        "Connection expired, will close"
-                    );
+                    debug!(connection_id = conn.id, "Connection expired, will close");
                     expired.push(conn);
                 } else {
                     to_check.push(conn);
@@ -149,7 +216,7 @@ impl<M: ConnectionManager + 'static> Pool<M> {
         }
 
         // Validate connections
-        for mut conn in connections_to_check {
+        for conn in connections_to_check {
             let is_valid = self.manager.is_valid(&conn.connection).await;
             self.metrics.record_validation(is_valid);
 
@@ -171,24 +238,89 @@ impl<M: ConnectionManager + 'static> Pool<M> {
         self.metrics
             .set_idle_connections(self.connections.lock().len() as u64);
 
-        // Ensure minimum connections
+        // Move the minimum with demand, then ensure it
+        self.adjust_min_connections();
         self.ensure_minimum_connections().await;
     }
 
+    /// Adaptive sizing: nudge the minimum toward observed demand
+    fn adjust_min_connections(&self) {
+        let Some(adaptive) = &self.config.adaptive else {
+            return;
+        };
+
+        let snapshot = self.metrics.snapshot();
+        let status = self.status();
+        let mut state = self.adaptive.lock();
+
+        // Average wait of the acquires since the previous tick
+        let total_wait_us = self.metrics.total_acquire_wait_us.load(Ordering::Relaxed);
+        let acquires = snapshot
+            .acquires_success
+            .saturating_sub(state.last_acquires);
+        let window_wait = total_wait_us
+            .saturating_sub(state.last_wait_us)
+            .checked_div(acquires)
+            .map(Duration::from_micros)
+            .unwrap_or(Duration::ZERO);
+        state.last_acquires = snapshot.acquires_success;
+        state.last_wait_us = total_wait_us;
+
+        let open = status.idle_connections + status.in_use_connections;
+        let idle_ratio = match open {
+            0 => 0.0,
+            n => f64::from(status.idle_connections) / f64::from(n),
+        };
+        let pressure =
+            window_wait > adaptive.scale_up_wait || self.waiters.load(Ordering::Relaxed) > 0;
+        let slack = !pressure && idle_ratio > adaptive.scale_down_idle;
+
+        state.up_streak = if pressure { state.up_streak + 1 } else { 0 };
+        state.down_streak = if slack { state.down_streak + 1 } else { 0 };
+
+        let current = self.min_connections.load(Ordering::Relaxed);
+        let target = if state.up_streak >= adaptive.hysteresis {
+            (current + adaptive.step).min(self.config.max_connections)
+        } else if state.down_streak >= adaptive.hysteresis {
+            current
+                .saturating_sub(adaptive.step)
+                .max(self.config.min_connections)
+        } else {
+            current
+        };
+
+        if target != current {
+            state.up_streak = 0;
+            state.down_streak = 0;
+            self.min_connections.store(target, Ordering::Relaxed);
+            self.metrics.record_min_connections_change(current, target);
+            info!(
+                from = current,
+                to = target,
+                "Adaptive sizing changed min_connections"
+            );
+        }
+    }
+
     /// Ensure we have at least min_connections
+    ///
+    /// Never opens more than `max_connections` in total: while every
+    /// permit is out, extra idle connections could not be handed to anyone.
     async fn ensure_minimum_connections(&self) {
-        let current = self.connections.lock().len() as u32;
-        if current < self.config.min_connections {
-            let needed = self.config.min_connections - current;
-            for _ in 0..needed {
-                match self.create_connection().await {
-                    Ok(conn) => {
-                        self.connections.lock().push_back(conn);
-                    }
-                    Err(e) => {
-                        warn!("Failed to create connection for minimum pool: {}", e);
-                        break;
-                    }
+        let min_connections = self.min_connections.load(Ordering::Relaxed);
+        let status = self.status();
+        let open = status.idle_connections + status.in_use_connections;
+        let needed = min_connections
+            .saturating_sub(status.idle_connections)
+            .min(self.config.max_connections.saturating_sub(open));
+        for _ in 0..needed {
+            match self.create_connection().await {
+                Ok(conn) => {
+                    self.connections.lock().push_back(conn);
+                }
+                Err(e) => {
+                    warn!("Failed to create connection for minimum pool: {}", e);
+                    break;
                 }
             }
         }
@@ -196,6 +328,10 @@ impl<M: ConnectionManager + 'static> Pool<M> {
 
     /// Check if a connection has expired
     fn is_connection_expired(&self, conn: &ManagedConnection<M::Connection>) -> bool {
+        if self.is_stale(conn) {
+            return true;
+        }
+
         // Check max lifetime
         if let Some(max_lifetime) = self.config.max_lifetime {
             if conn.age() > max_lifetime {
@@ -222,9 +358,21 @@ impl<M: ConnectionManager + 'static> Pool<M> {
             ));
         }
 
+        if let Some(shedding) = &self.config.load_shedding {
+            let waiters = self.waiters.load(Ordering::Relaxed);
+            let avg_wait = Duration::from_micros(self.recent_wait_us.load(Ordering::Relaxed));
+            if shedding.is_overloaded(waiters, avg_wait) {
+                self.metrics.record_acquire_shed();
+                return Err(ChakraError::Connection(
+                    chakra_core::error::ConnectionError::PoolOverloaded { waiters, avg_wait },
+                ));
+            }
+        }
+
         let start = Instant::now();
 
         // Acquire semaphore permit with timeout
+        let waiting = WaiterGuard::new(&self.waiters);
         let permit = tokio::time::timeout(
             self.config.acquire_timeout,
             self.semaphore.clone().acquire_owned(),
@@ -236,15 +384,26 @@ impl<M: ConnectionManager + 'static> Pool<M> {
                 timeout: self.config.acquire_timeout,
             })
         })?
-        .map_err(|_| {
-            ChakraError::Connection(chakra_core::error::ConnectionError::PoolClosed)
-        })?;
+        .map_err(|_| ChakraError::Connection(chakra_core::error::ConnectionError::PoolClosed))?;
+        drop(waiting);
+        self.record_wait(start.elapsed());
 
         // Try to get an existing connection
         let conn = loop {
             let conn = self.connections.lock().pop_front();
 
             match conn {
+                Some(conn) if self.is_stale(&conn) => {
+                    debug!(
+                        connection_id = conn.id,
+                        "Closing connection opened before invalidation"
+                    );
+                    if let Err(e) = self.manager.close(conn.connection).await {
+                        error!("Failed to close stale connection: {}", e);
+                    }
+                    self.metrics.record_connection_closed();
+                    continue;
+                }
                 Some(mut conn) => {
                     // Validate if configured
                     if self.config.test_on_checkout {
@@ -291,10 +450,20 @@ impl<M: ConnectionManager + 'static> Pool<M> {
         Ok(PooledConnection {
             pool: Arc::clone(self),
             connection: Some(conn),
-            permit: Some(permit),
+            _permit: Some(permit),
         })
     }
 
+    /// Fold a permit wait into the moving average used for load shedding
+    fn record_wait(&self, wait: Duration) {
+        let sample = wait.as_micros() as u64;
+        let _ = self
+            .recent_wait_us
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                Some(avg - avg / 8 + sample / 8)
+            });
+    }
+
     /// Release a connection back to the pool
     async fn release(&self, mut conn: ManagedConnection<M::Connection>) {
         // Check if pool is closed
@@ -306,6 +475,19 @@ impl<M: ConnectionManager + 'static> Pool<M> {
             return;
         }
 
+        // Opened before an invalidation, e.g. to a failed-over primary
+        if self.is_stale(&conn) {
+            debug!(
+                connection_id = conn.id,
+                "Closing connection opened before invalidation"
+            );
+            if let Err(e) = self.manager.close(conn.connection).await {
+                error!("Failed to close stale connection: {}", e);
+            }
+            self.metrics.record_connection_closed();
+            return;
+        }
+
         // Validate if configured
         if self.config.test_on_checkin {
             if !self.manager.is_valid(&conn.connection).await {
@@ -351,6 +533,14 @@ impl<M: ConnectionManager + 'static> Pool<M> {
         &self.metrics
     }
 
+    /// Get the shared statement registry
+    ///
+    /// Adapters record the statements they run so new connections can
+    /// prepare the hot ones up front.
+    pub fn statements(&self) -> &StatementRegistry {
+        &self.statements
+    }
+
     /// Get current pool status
     pub fn status(&self) -> PoolStatus {
         let idle = self.connections.lock().len() as u32;
@@ -365,6 +555,33 @@ impl<M: ConnectionManager + 'static> Pool<M> {
         }
     }
 
+    /// Close every connection and reconnect from scratch, e.g. during an
+    /// orchestrated failover
+    ///
+    /// Idle connections are closed now and connections in use are closed
+    /// when they are returned. The manager's cached connect state, such as
+    /// resolved addresses, is dropped first, so the new connections follow
+    /// the failover.
+    pub async fn invalidate_all(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.manager.invalidate();
+        self.metrics.record_invalidation();
+        info!("Invalidating all pool connections");
+
+        let connections: Vec<_> = self.connections.lock().drain(..).collect();
+        for conn in connections {
+            if let Err(e) = self.manager.close(conn.connection).await {
+                error!("Failed to close connection: {}", e);
+            }
+            self.metrics.record_connection_closed();
+        }
+        self.metrics.set_idle_connections(0);
+
+        if !self.is_closed() {
+            self.ensure_minimum_connections().await;
+        }
+    }
+
     /// Check if the pool is closed
     pub fn is_closed(&self) -> bool {
         self.closed.load(Ordering::Relaxed)
@@ -399,6 +616,31 @@ impl<M: ConnectionManager + 'static> Pool<M> {
     }
 }
 
+/// Adaptive sizing signals from previous maintenance ticks
+#[derive(Debug, Default)]
+struct AdaptiveState {
+    last_acquires: u64,
+    last_wait_us: u64,
+    up_streak: u32,
+    down_streak: u32,
+}
+
+/// Counts a caller as waiting until dropped, even if the wait is cancelled
+struct WaiterGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaiterGuard<'a> {
+    fn new(waiters: &'a AtomicUsize) -> Self {
+        waiters.fetch_add(1, Ordering::Relaxed);
+        Self(waiters)
+    }
+}
+
+impl Drop for WaiterGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Pool status
 #[derive(Debug, Clone)]
 pub struct PoolStatus {
@@ -412,7 +654,7 @@ pub struct PoolStatus {
 pub struct PooledConnection<M: ConnectionManager + 'static> {
     pool: Arc<Pool<M>>,
     connection: Option<ManagedConnection<M::Connection>>,
-    permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    _permit: Option<tokio::sync::OwnedSemaphorePermit>,
 }
 
 impl<M: ConnectionManager + 'static> PooledConnection<M> {
@@ -438,6 +680,16 @@ impl<M: ConnectionManager + 'static> PooledConnection<M> {
     pub fn detach(mut self) -> Option<M::Connection> {
         self.connection.take().map(|c| c.connection)
     }
+
+    /// Return the connection to the pool and wait until it is back
+    ///
+    /// Unlike dropping, the connection is reset and idle again by the time
+    /// this returns, and its permit is only freed afterwards.
+    pub async fn release(mut self) {
+        if let Some(conn) = self.connection.take() {
+            self.pool.release(conn).await;
+        }
+    }
 }
 
 impl<M: ConnectionManager + 'static> Deref for PooledConnection<M> {
@@ -465,11 +717,29 @@ impl<M: ConnectionManager + 'static> DerefMut for PooledConnection<M> {
 impl<M: ConnectionManager + 'static> Drop for PooledConnection<M> {
     fn drop(&mut self) {
         if let Some(conn) = self.connection.take() {
-            let pool = Arc::clone(&self.pool);
-            // Spawn a task to release the connection
-            tokio::spawn(async move {
-                pool.release(conn).await;
-            });
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    let pool = Arc::clone(&self.pool);
+                    // Hold the permit until the connection is back, so the
+                    // next caller reuses it instead of opening another
+                    let permit = self._permit.take();
+                    // Spawn a task to release the connection
+                    handle.spawn(async move {
+                        pool.release(conn).await;
+                        drop(permit);
+                    });
+                }
+                Err(_) => {
+                    // No runtime to reset the connection on (e.g. during
+                    // shutdown), so close it here instead of returning it
+                    debug!(
+                        connection_id = conn.id,
+                        "Dropped outside a runtime, closing connection"
+                    );
+                    drop(conn);
+                    self.pool.metrics.record_connection_closed();
+                }
+            }
         }
         // Permit is automatically released when dropped
     }
@@ -538,4 +808,285 @@ mod tests {
         // Connection should be released after drop
         tokio::time::sleep(Duration::from_millis(10)).await;
     }
+
+    #[tokio::test]
+    async fn test_explicit_release() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(1);
+
+        let pool = Pool::new(MockManager, config).await.unwrap();
+        let conn = pool.acquire().await.unwrap();
+        conn.release().await;
+
+        let status = pool.status();
+        assert_eq!(status.idle_connections, 1);
+        assert_eq!(status.in_use_connections, 0);
+        assert_eq!(pool.metrics().snapshot().releases_total, 1);
+    }
+
+    #[derive(Debug, Default)]
+    struct PreparingManager {
+        prepared: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ConnectionManager for PreparingManager {
+        type Connection = u64;
+
+        async fn connect(&self) -> Result<Self::Connection> {
+            Ok(rand::random())
+        }
+
+        async fn is_valid(&self, _conn: &Self::Connection) -> bool {
+            true
+        }
+
+        fn has_expired(&self, _conn: &Self::Connection) -> bool {
+            false
+        }
+
+        async fn prepare(&self, _conn: &mut Self::Connection, sql: &str) -> Result<()> {
+            self.prepared.lock().push(sql.to_string());
+            Ok(())
+        }
+
+        async fn reset(&self, _conn: &mut Self::Connection) -> Result<()> {
+            Ok(())
+        }
+
+        async fn close(&self, _conn: Self::Connection) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_connections_prepare_hot_statements() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(2)
+            .warm_statements(1);
+
+        let pool = Pool::new(PreparingManager::default(), config)
+            .await
+            .unwrap();
+        let first = pool.acquire().await.unwrap();
+        pool.statements().record("SELECT 2");
+        pool.statements().record("SELECT 1");
+        pool.statements().record("SELECT 1");
+
+        let _second = pool.acquire().await.unwrap();
+        assert_eq!(*pool.manager.prepared.lock(), vec!["SELECT 1"]);
+        first.release().await;
+    }
+
+    /// Refuses the first `failures` connects, as during a failover
+    #[derive(Debug, Default)]
+    struct FailoverManager {
+        failures: AtomicU32,
+        invalidations: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl ConnectionManager for FailoverManager {
+        type Connection = u64;
+
+        async fn connect(&self) -> Result<Self::Connection> {
+            let remaining = self.failures.load(Ordering::SeqCst);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::SeqCst);
+                return Err(ChakraError::connection("connection refused"));
+            }
+            Ok(rand::random())
+        }
+
+        async fn is_valid(&self, _conn: &Self::Connection) -> bool {
+            true
+        }
+
+        fn has_expired(&self, _conn: &Self::Connection) -> bool {
+            false
+        }
+
+        async fn reset(&self, _conn: &mut Self::Connection) -> Result<()> {
+            Ok(())
+        }
+
+        async fn close(&self, _conn: Self::Connection) -> Result<()> {
+            Ok(())
+        }
+
+        fn invalidate(&self) {
+            self.invalidations.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_retry() {
+        let retry = crate::ConnectRetry::new()
+            .max_attempts(3)
+            .initial_backoff(Duration::from_millis(100));
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(3), Duration::from_millis(400));
+        assert_eq!(
+            retry
+                .clone()
+                .max_backoff(Duration::from_millis(150))
+                .backoff(3),
+            Duration::from_millis(150)
+        );
+
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(2)
+            .connect_retry(retry);
+        let pool = Pool::new(FailoverManager::default(), config).await.unwrap();
+
+        pool.manager.failures.store(2, Ordering::SeqCst);
+        let conn = pool.acquire().await.unwrap();
+        assert_eq!(pool.metrics().snapshot().connect_retries, 2);
+        assert_eq!(pool.manager.invalidations.load(Ordering::SeqCst), 2);
+
+        pool.manager.failures.store(3, Ordering::SeqCst);
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.metrics().snapshot().connect_retries, 4);
+        conn.release().await;
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_all() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(2)
+            .max_connections(3);
+        let pool = Pool::new(FailoverManager::default(), config).await.unwrap();
+        let held = pool.acquire().await.unwrap();
+        let (held_id, idle) = (held.id(), pool.status().idle_connections);
+        assert_eq!(idle, 1);
+
+        pool.invalidate_all().await;
+        assert_eq!(pool.manager.invalidations.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.metrics().snapshot().invalidations, 1);
+        // The idle connection was replaced to keep the minimum
+        assert_eq!(pool.status().idle_connections, 2);
+        assert_eq!(pool.metrics().snapshot().connections_closed, 1);
+
+        // The connection in use is closed when it comes back
+        held.release().await;
+        assert_eq!(pool.status().idle_connections, 2);
+        assert_eq!(pool.metrics().snapshot().connections_closed, 2);
+        let conn = pool.acquire().await.unwrap();
+        assert_ne!(conn.id(), held_id);
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(1)
+            .load_shedding(crate::LoadShedding::new().max_waiters(1));
+
+        let pool = Pool::new(MockManager, config).await.unwrap();
+        let held = pool.acquire().await.unwrap();
+
+        let waiter = tokio::spawn({
+            let pool = Arc::clone(&pool);
+            async move { pool.acquire().await.map(|c| c.id()) }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let err = pool.acquire().await.err().unwrap();
+        assert!(matches!(
+            err,
+            ChakraError::Connection(chakra_core::error::ConnectionError::PoolOverloaded {
+                waiters: 1,
+                ..
+            })
+        ));
+        assert_eq!(pool.metrics().snapshot().acquires_shed, 1);
+
+        held.release().await;
+        assert!(waiter.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_sizing() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(4)
+            .adaptive(crate::AdaptiveSizing::new().hysteresis(2));
+
+        let pool = Pool::new(MockManager, config).await.unwrap();
+
+        // One slow tick is not enough to act on
+        pool.metrics
+            .record_acquire_success(Duration::from_millis(50));
+        pool.run_maintenance().await;
+        assert_eq!(pool.metrics().snapshot().min_connections, 0);
+
+        pool.metrics
+            .record_acquire_success(Duration::from_millis(50));
+        pool.run_maintenance().await;
+        let snapshot = pool.metrics().snapshot();
+        assert_eq!(snapshot.min_connections, 1);
+        assert_eq!(snapshot.min_scale_ups, 1);
+        assert_eq!(pool.status().idle_connections, 1);
+
+        // Idle ticks bring it back down to the configured floor
+        pool.run_maintenance().await;
+        pool.run_maintenance().await;
+        let snapshot = pool.metrics().snapshot();
+        assert_eq!(snapshot.min_connections, 0);
+        assert_eq!(snapshot.min_scale_downs, 1);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_sizing_stays_within_max() {
+        let config = PoolConfig::new("test://localhost")
+            .min_connections(0)
+            .max_connections(2)
+            .adaptive(crate::AdaptiveSizing::new().hysteresis(1).step(2));
+
+        let pool = Pool::new(MockManager, config).await.unwrap();
+        let first = pool.acquire().await.unwrap();
+        let second = pool.acquire().await.unwrap();
+
+        // Every connection is out and an acquirer is queued
+        let waiter = tokio::spawn({
+            let pool = Arc::clone(&pool);
+            async move { pool.acquire().await.map(|conn| conn.id()) }
+        });
+        while pool.waiters.load(Ordering::Relaxed) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        pool.run_maintenance().await;
+        pool.run_maintenance().await;
+        let snapshot = pool.metrics().snapshot();
+        assert_eq!(snapshot.min_connections, 2);
+        assert_eq!(snapshot.connections_created, 2);
+        assert_eq!(pool.status().idle_connections, 0);
+
+        first.release().await;
+        assert!(waiter.await.unwrap().is_ok());
+        second.release().await;
+        assert_eq!(pool.metrics().snapshot().connections_created, 2);
+    }
+
+    #[test]
+    fn test_drop_outside_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (pool, conn) = runtime.block_on(async {
+            let config = PoolConfig::new("test://localhost")
+                .min_connections(0)
+                .max_connections(1);
+            let pool = Pool::new(MockManager, config).await.unwrap();
+            let conn = pool.acquire().await.unwrap();
+            (pool, conn)
+        });
+        drop(runtime);
+
+        drop(conn);
+        assert_eq!(pool.metrics().snapshot().connections_closed, 1);
+        assert_eq!(pool.status().in_use_connections, 0);
+    }
 }
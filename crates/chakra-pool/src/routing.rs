@@ -0,0 +1,172 @@
+//! Read-your-writes stickiness for replica routing
+//!
+//! After a logical session writes, its reads should go to the primary until
+//! the replicas have replayed that write. `ReadYourWrites` remembers each
+//! session's last write and decides where its next read may go: back to a
+//! replica once the sticky window has passed, or earlier if the replica's
+//! replay position has reached the write's WAL position.
+
+use dashmap::DashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Default time reads stay on the primary after a write
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+
+/// A write-ahead log position, as reported by `pg_current_wal_lsn()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lsn(pub u64);
+
+impl FromStr for Lsn {
+    type Err = String;
+
+    /// Parse the `XXXXXXXX/XXXXXXXX` text form
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (high, low) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid LSN: {}", s))?;
+        let high = u32::from_str_radix(high, 16).map_err(|_| format!("Invalid LSN: {}", s))?;
+        let low = u32::from_str_radix(low, 16).map_err(|_| format!("Invalid LSN: {}", s))?;
+        Ok(Lsn((u64::from(high) << 32) | u64::from(low)))
+    }
+}
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xFFFF_FFFF)
+    }
+}
+
+/// Where a read should be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Primary,
+    Replica,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LastWrite {
+    at: Instant,
+    lsn: Option<Lsn>,
+}
+
+/// Tracks recent writes per session to keep its reads on the primary
+#[derive(Debug)]
+pub struct ReadYourWrites {
+    window: Duration,
+    writes: DashMap<String, LastWrite>,
+}
+
+impl ReadYourWrites {
+    /// Keep reads on the primary for `window` after each write
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            writes: DashMap::new(),
+        }
+    }
+
+    /// The sticky window
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Record a write by a session, with the primary's WAL position if known
+    pub fn record_write(&self, session: &str, lsn: Option<Lsn>) {
+        let write = LastWrite {
+            at: Instant::now(),
+            lsn,
+        };
+        self.writes
+            .entry(session.to_string())
+            .and_modify(|w| {
+                w.at = write.at;
+                w.lsn = w.lsn.max(write.lsn);
+            })
+            .or_insert(write);
+    }
+
+    /// Whether the session's pending write carries an LSN worth checking
+    /// against a replica before deciding where to read
+    pub fn needs_lsn_check(&self, session: &str) -> bool {
+        self.writes
+            .get(session)
+            .is_some_and(|w| w.lsn.is_some() && w.at.elapsed() < self.window)
+    }
+
+    /// Decide where a session's next read goes
+    ///
+    /// `replica_lsn` is the replica's `pg_last_wal_replay_lsn()`, if checked.
+    pub fn route_read(&self, session: &str, replica_lsn: Option<Lsn>) -> Route {
+        let Some(write) = self.writes.get(session).map(|w| *w) else {
+            return Route::Replica;
+        };
+
+        let caught_up = matches!((write.lsn, replica_lsn), (Some(w), Some(r)) if r >= w);
+        if caught_up || write.at.elapsed() >= self.window {
+            self.writes.remove(session);
+            return Route::Replica;
+        }
+        Route::Primary
+    }
+
+    /// Stop tracking a session, e.g. when it ends
+    pub fn forget(&self, session: &str) {
+        self.writes.remove(session);
+    }
+
+    /// Drop sessions whose window has passed
+    pub fn purge_expired(&self) {
+        self.writes.retain(|_, w| w.at.elapsed() < self.window);
+    }
+
+    /// Number of sessions currently pinned to the primary
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Check if no session is pinned to the primary
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+impl Default for ReadYourWrites {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lsn_round_trip() {
+        let lsn: Lsn = "16/B374D848".parse().unwrap();
+        assert_eq!(lsn.0, 0x16_B374_D848);
+        assert_eq!(lsn.to_string(), "16/B374D848");
+        assert!("garbage".parse::<Lsn>().is_err());
+    }
+
+    #[test]
+    fn test_route_read() {
+        let tracker = ReadYourWrites::new(Duration::from_secs(60));
+        assert_eq!(tracker.route_read("alice", None), Route::Replica);
+
+        tracker.record_write("alice", Some(Lsn(100)));
+        assert!(tracker.needs_lsn_check("alice"));
+        assert_eq!(tracker.route_read("alice", None), Route::Primary);
+        assert_eq!(tracker.route_read("alice", Some(Lsn(99))), Route::Primary);
+        assert_eq!(tracker.route_read("bob", None), Route::Replica);
+
+        // The replica caught up, so the session is released
+        assert_eq!(tracker.route_read("alice", Some(Lsn(100))), Route::Replica);
+        assert!(tracker.is_empty());
+
+        let expired = ReadYourWrites::new(Duration::ZERO);
+        expired.record_write("alice", None);
+        assert_eq!(expired.route_read("alice", None), Route::Replica);
+    }
+}
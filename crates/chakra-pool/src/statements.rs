@@ -0,0 +1,145 @@
+//! Shared prepared statement registry for Chakra ORM
+//!
+//! Connections report the statements they run here, keyed by a fingerprint
+//! of the SQL text, so a freshly created connection can prepare the hot ones
+//! up front instead of paying for it on its first requests.
+
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Default number of distinct statements tracked
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Statements observed across the connections of a pool
+#[derive(Debug)]
+pub struct StatementRegistry {
+    statements: DashMap<u64, Entry>,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    sql: String,
+    hits: AtomicU64,
+}
+
+impl StatementRegistry {
+    /// Create a registry tracking at most `capacity` statements
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            statements: DashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Fingerprint SQL text, ignoring differences in whitespace
+    pub fn fingerprint(sql: &str) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for (i, word) in sql.split_whitespace().enumerate() {
+            if i > 0 {
+                hash = (hash ^ u64::from(b' ')).wrapping_mul(0x100000001b3);
+            }
+            for byte in word.bytes() {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    /// Record a use of a statement, returning its fingerprint
+    ///
+    /// When the registry is full, the least used statement makes room.
+    pub fn record(&self, sql: &str) -> u64 {
+        let fingerprint = Self::fingerprint(sql);
+
+        if let Some(entry) = self.statements.get(&fingerprint) {
+            entry.hits.fetch_add(1, Ordering::Relaxed);
+            return fingerprint;
+        }
+
+        if self.statements.len() >= self.capacity {
+            let coldest = self
+                .statements
+                .iter()
+                .min_by_key(|e| e.hits.load(Ordering::Relaxed))
+                .map(|e| *e.key());
+            match coldest {
+                Some(key) => {
+                    self.statements.remove(&key);
+                }
+                None => return fingerprint,
+            }
+        }
+
+        self.statements
+            .entry(fingerprint)
+            .or_insert_with(|| Entry {
+                sql: sql.to_string(),
+                hits: AtomicU64::new(0),
+            })
+            .hits
+            .fetch_add(1, Ordering::Relaxed);
+        fingerprint
+    }
+
+    /// Stop tracking a statement, e.g. after it failed to prepare
+    pub fn forget(&self, sql: &str) {
+        self.statements.remove(&Self::fingerprint(sql));
+    }
+
+    /// The `limit` most used statements, most used first
+    pub fn hot(&self, limit: usize) -> Vec<String> {
+        let mut entries: Vec<(u64, String)> = self
+            .statements
+            .iter()
+            .map(|e| (e.hits.load(Ordering::Relaxed), e.sql.clone()))
+            .collect();
+        entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        entries
+            .into_iter()
+            .take(limit)
+            .map(|(_, sql)| sql)
+            .collect()
+    }
+
+    /// Number of tracked statements
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    /// Check if no statements are tracked
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+}
+
+impl Default for StatementRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_statements() {
+        let registry = StatementRegistry::new(2);
+        registry.record("SELECT * FROM users WHERE id = $1");
+        registry.record("SELECT *  FROM users\n WHERE id = $1");
+        registry.record("SELECT * FROM orders");
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.hot(1), vec!["SELECT * FROM users WHERE id = $1"]);
+
+        // The least used statement is evicted to make room
+        registry.record("SELECT * FROM items");
+        assert_eq!(
+            registry.hot(10),
+            vec!["SELECT * FROM users WHERE id = $1", "SELECT * FROM items"]
+        );
+
+        registry.forget("SELECT * FROM items");
+        assert_eq!(registry.len(), 1);
+    }
+}
@@ -3,11 +3,17 @@
 use crate::config::PostgresConfig;
 use async_trait::async_trait;
 use chakra_core::error::{ChakraError, ConnectionError, Result};
+use chakra_core::types::Value;
+use chakra_pool::credentials::{connect_with, CredentialProvider, Credentials};
 use chakra_pool::manager::ConnectionManager;
+use chakra_pool::Lsn;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
-use tokio_postgres::{Client, NoTls};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{Client, NoTls, Statement};
 use tracing::{debug, error, info};
 
 /// A PostgreSQL connection
@@ -18,6 +24,10 @@ pub struct PostgresConnection {
     pub created_at: Instant,
     /// Connection ID
     pub id: u64,
+    /// Statements prepared on this connection, by SQL text
+    statements: Mutex<HashMap<String, Statement>>,
+    /// Whether DML statements are kept prepared between uses
+    cache_statements: bool,
 }
 
 impl PostgresConnection {
@@ -27,9 +37,89 @@ impl PostgresConnection {
             client,
             created_at: Instant::now(),
             id,
+            statements: Mutex::new(HashMap::new()),
+            cache_statements: false,
         }
     }
 
+    /// Keep prepared DML statements for reuse, see
+    /// `PostgresConfig::statement_cache`
+    pub fn with_statement_cache(mut self, enabled: bool) -> Self {
+        self.cache_statements = enabled;
+        self
+    }
+
+    /// Whether `prepare_cached` keeps the statement for `sql`
+    ///
+    /// Only SELECT, INSERT, UPDATE, DELETE and WITH statements are cached,
+    /// and only with the statement cache on.
+    pub fn caches(&self, sql: &str) -> bool {
+        self.cache_statements && is_dml(sql)
+    }
+
+    /// Prepare a statement, reusing an earlier preparation of the same SQL
+    /// where `caches` allows
+    pub async fn prepare_cached(
+        &self,
+        sql: &str,
+    ) -> std::result::Result<Statement, tokio_postgres::Error> {
+        if !self.caches(sql) {
+            return self.client.prepare(sql).await;
+        }
+        if let Some(stmt) = self.statements.lock().unwrap().get(sql) {
+            return Ok(stmt.clone());
+        }
+
+        let stmt = self.client.prepare(sql).await?;
+        let mut statements = self.statements.lock().unwrap();
+        if statements.len() < MAX_CACHED_STATEMENTS {
+            statements.insert(sql.to_string(), stmt.clone());
+        }
+        Ok(stmt)
+    }
+
+    /// Current WAL write position on a primary
+    pub async fn current_wal_lsn(&self) -> Result<Lsn> {
+        self.query_lsn("SELECT pg_current_wal_lsn()::text")
+            .await?
+            .ok_or_else(|| {
+                ChakraError::Connection(ConnectionError::ConnectionFailed {
+                    message: "pg_current_wal_lsn() returned NULL".to_string(),
+                })
+            })
+    }
+
+    /// Last WAL position replayed by a replica, or `None` on a primary
+    pub async fn replay_lsn(&self) -> Result<Option<Lsn>> {
+        self.query_lsn("SELECT pg_last_wal_replay_lsn()::text")
+            .await
+    }
+
+    async fn query_lsn(&self, sql: &str) -> Result<Option<Lsn>> {
+        let to_error = |message: String| {
+            ChakraError::Connection(ConnectionError::ConnectionFailed { message })
+        };
+        let row = self
+            .client
+            .query_one(sql, &[])
+            .await
+            .map_err(|e| to_error(format!("Failed to read WAL position: {}", e)))?;
+        row.get::<_, Option<String>>(0)
+            .map(|lsn| lsn.parse().map_err(to_error))
+            .transpose()
+    }
+
+    /// Forget cached statements, e.g. after `DEALLOCATE ALL`
+    pub fn clear_statement_cache(&self) {
+        self.statements.lock().unwrap().clear();
+    }
+
+    /// Forget the cached statement for `sql`, e.g. after a schema change
+    /// made its plan stale
+    pub fn forget_statement(&self, sql: &str) {
+        self.statements.lock().unwrap().remove(sql);
+    }
+
     /// Check if the connection is valid
     pub async fn is_valid(&self) -> bool {
         self.client.simple_query("SELECT 1").await.is_ok()
@@ -41,10 +131,16 @@ impl PostgresConnection {
     }
 }
 
+/// How long resolved host addresses are reused while connects succeed
+const DNS_TTL: Duration = Duration::from_secs(30);
+
 /// PostgreSQL connection manager
 #[derive(Debug)]
 pub struct PostgresConnectionManager {
     config: PostgresConfig,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Addresses the host last resolved to, dropped when a connect fails
+    resolved: Mutex<Option<(Vec<IpAddr>, Instant)>>,
     next_id: AtomicU64,
 }
 
@@ -53,27 +149,89 @@ impl PostgresConnectionManager {
     pub fn new(config: PostgresConfig) -> Self {
         Self {
             config,
+            credentials: None,
+            resolved: Mutex::new(None),
             next_id: AtomicU64::new(1),
         }
     }
-}
 
-#[async_trait]
-impl ConnectionManager for PostgresConnectionManager {
-    type Connection = PostgresConnection;
+    /// Fetch the user and password from `provider` for each new connection
+    /// instead of using the configured ones
+    pub fn with_credentials(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.credentials = Some(provider);
+        self
+    }
 
-    async fn connect(&self) -> Result<Self::Connection> {
-        let conn_str = self.config.connection_string();
+    /// The host's addresses, resolved again once they are older than
+    /// `DNS_TTL` or after a failed connect
+    ///
+    /// `None` for Unix sockets, IP literals and configs that already give
+    /// a `hostaddr` or several hosts, which the driver handles itself.
+    async fn resolve(&self) -> Result<Option<Vec<IpAddr>>> {
+        let config = &self.config;
+        if config.is_unix_socket()
+            || config.host.contains(',')
+            || config.host.parse::<IpAddr>().is_ok()
+            || config.options.contains_key("hostaddr")
+        {
+            return Ok(None);
+        }
+        if let Some((ref addrs, resolved_at)) = *self.resolved.lock().unwrap() {
+            if resolved_at.elapsed() < DNS_TTL {
+                return Ok(Some(addrs.clone()));
+            }
+        }
+
+        let mut addrs: Vec<IpAddr> = tokio::net::lookup_host((config.host.as_str(), config.port))
+            .await
+            .map_err(|e| {
+                ChakraError::Connection(ConnectionError::ConnectionFailed {
+                    message: format!("Failed to resolve {}: {}", config.host, e),
+                })
+            })?
+            .map(|addr| addr.ip())
+            .collect();
+        addrs.dedup();
+        debug!(host = %config.host, ?addrs, "Resolved PostgreSQL host");
+        *self.resolved.lock().unwrap() = Some((addrs.clone(), Instant::now()));
+        Ok(Some(addrs))
+    }
+
+    /// Open a connection with the configured credentials overridden
+    async fn connect_as(&self, credentials: Option<Credentials>) -> Result<PostgresConnection> {
+        let mut config = self.config.clone();
+        if let Some(credentials) = credentials {
+            if let Some(username) = credentials.username {
+                config.user = username;
+            }
+            config.password = Some(credentials.password);
+        }
+        // Pin the resolved addresses; the host name is kept for TLS and
+        // authentication
+        if let Some(addrs) = self.resolve().await? {
+            config.host = vec![config.host.as_str(); addrs.len()].join(",");
+            let addrs: Vec<String> = addrs.iter().map(IpAddr::to_string).collect();
+            config
+                .options
+                .insert("hostaddr".to_string(), addrs.join(","));
+        }
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
 
         debug!(connection_id = id, "Creating PostgreSQL connection");
 
-        let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        let (client, connection) = tokio_postgres::connect(&config.connection_string(), NoTls)
             .await
             .map_err(|e| {
-                ChakraError::Connection(ConnectionError::ConnectionFailed {
-                    message: e.to_string(),
-                })
+                let message = e.to_string();
+                match e.code() {
+                    Some(code)
+                        if *code == SqlState::INVALID_PASSWORD
+                            || *code == SqlState::INVALID_AUTHORIZATION_SPECIFICATION =>
+                    {
+                        ChakraError::Connection(ConnectionError::AuthenticationFailed { message })
+                    }
+                    _ => ChakraError::Connection(ConnectionError::ConnectionFailed { message }),
+                }
             })?;
 
         // Spawn the connection handler
@@ -83,20 +241,28 @@ impl ConnectionManager for PostgresConnectionManager {
             }
         });
 
-        // Set schema if specified
-        if let Some(ref schema) = self.config.schema {
-            client
-                .simple_query(&format!("SET search_path TO {}", schema))
-                .await
-                .map_err(|e| {
-                    ChakraError::Connection(ConnectionError::ConnectionFailed {
-                        message: format!("Failed to set schema: {}", e),
-                    })
-                })?;
-        }
+        // Set schema and read-only mode if specified
+        configure_session(&client, &self.config).await?;
 
         info!(connection_id = id, "PostgreSQL connection established");
-        Ok(PostgresConnection::new(client, id))
+        Ok(PostgresConnection::new(client, id).with_statement_cache(self.config.statement_cache))
+    }
+}
+
+#[async_trait]
+impl ConnectionManager for PostgresConnectionManager {
+    type Connection = PostgresConnection;
+
+    async fn connect(&self) -> Result<Self::Connection> {
+        match self.credentials {
+            Some(ref provider) => {
+                connect_with(provider.as_ref(), |credentials| {
+                    self.connect_as(Some(credentials))
+                })
+                .await
+            }
+            None => self.connect_as(None).await,
+        }
     }
 
     async fn is_valid(&self, conn: &Self::Connection) -> bool {
@@ -111,30 +277,37 @@ impl ConnectionManager for PostgresConnectionManager {
         }
     }
 
+    async fn prepare(&self, conn: &mut Self::Connection, sql: &str) -> Result<()> {
+        if !conn.caches(sql) {
+            return Ok(());
+        }
+        conn.prepare_cached(sql).await.map(|_| ()).map_err(|e| {
+            ChakraError::Connection(ConnectionError::ConnectionFailed {
+                message: format!("Failed to prepare statement: {}", e),
+            })
+        })
+    }
+
     async fn reset(&self, conn: &mut Self::Connection) -> Result<()> {
         // Reset session state
-        conn.client
-            .simple_query("DISCARD ALL")
-            .await
-            .map_err(|e| {
+        let policy = self.config.reset_policy;
+        let statements = policy
+            .sql()
+            .into_iter()
+            .chain(self.config.on_reset.iter().map(String::as_str));
+        if policy.deallocates() {
+            conn.clear_statement_cache();
+        }
+        for sql in statements {
+            conn.client.simple_query(sql).await.map_err(|e| {
                 ChakraError::Connection(ConnectionError::ConnectionFailed {
                     message: format!("Failed to reset connection: {}", e),
                 })
             })?;
-
-        // Re-set schema if needed
-        if let Some(ref schema) = self.config.schema {
-            conn.client
-                .simple_query(&format!("SET search_path TO {}", schema))
-                .await
-                .map_err(|e| {
-                    ChakraError::Connection(ConnectionError::ConnectionFailed {
-                        message: format!("Failed to set schema: {}", e),
-                    })
-                })?;
         }
 
-        Ok(())
+        // Re-set schema and read-only mode if needed
+        configure_session(&conn.client, &self.config).await
     }
 
     async fn close(&self, conn: Self::Connection) -> Result<()> {
@@ -143,6 +316,11 @@ impl ConnectionManager for PostgresConnectionManager {
         drop(conn);
         Ok(())
     }
+
+    fn invalidate(&self) {
+        // The host may point elsewhere now, e.g. after a failover
+        *self.resolved.lock().unwrap() = None;
+    }
 }
 
 /// PostgreSQL connection pool
@@ -154,14 +332,38 @@ pub struct PostgresPool {
 impl PostgresPool {
     /// Create a new connection pool
     pub async fn new(config: PostgresConfig) -> Result<Self> {
-        let manager = PostgresConnectionManager::new(config.clone());
+        Self::from_manager(PostgresConnectionManager::new(config.clone()), config).await
+    }
 
-        let pool_config = chakra_pool::PoolConfig::new(&config.connection_string())
+    /// Create a pool whose connections fetch their user and password from
+    /// `provider`, so they need not be in the config
+    pub async fn with_credentials(
+        config: PostgresConfig,
+        provider: Arc<dyn CredentialProvider>,
+    ) -> Result<Self> {
+        Self::from_manager(
+            PostgresConnectionManager::new(config.clone()).with_credentials(provider),
+            config,
+        )
+        .await
+    }
+
+    async fn from_manager(
+        manager: PostgresConnectionManager,
+        config: PostgresConfig,
+    ) -> Result<Self> {
+        let mut pool_config = chakra_pool::PoolConfig::new(config.connection_string())
             .min_connections(config.pool.min_size as u32)
             .max_connections(config.pool.max_size as u32)
             .acquire_timeout(config.pool.connection_timeout)
             .idle_timeout(config.pool.idle_timeout)
             .max_lifetime(config.pool.max_lifetime);
+        if let Some(ref name) = config.application_name {
+            pool_config = pool_config.application_name(name.clone());
+        }
+        if let Some(ref retry) = config.pool.connect_retry {
+            pool_config = pool_config.connect_retry(retry.clone());
+        }
 
         let pool = chakra_pool::Pool::new(manager, pool_config).await?;
 
@@ -173,6 +375,39 @@ impl PostgresPool {
         self.pool.acquire().await
     }
 
+    /// Get a connection reporting a different `application_name`
+    ///
+    /// The override lasts until the connection is returned, when the
+    /// session reset restores the configured name.
+    pub async fn get_with_application_name(
+        &self,
+        name: &str,
+    ) -> Result<chakra_pool::PooledConnection<PostgresConnectionManager>> {
+        let conn = self.pool.acquire().await?;
+        conn.client
+            .simple_query(&set_application_name_sql(name))
+            .await
+            .map_err(|e| {
+                ChakraError::Connection(ConnectionError::ConnectionFailed {
+                    message: format!("Failed to set application_name: {}", e),
+                })
+            })?;
+        Ok(conn)
+    }
+
+    /// Close every connection and reconnect with freshly resolved
+    /// addresses, e.g. when told a failover happened
+    ///
+    /// Connections in use are closed when they are returned.
+    pub async fn invalidate_all(&self) {
+        self.pool.invalidate_all().await
+    }
+
+    /// Get the statements shared between connections of this pool
+    pub fn statements(&self) -> &chakra_pool::StatementRegistry {
+        self.pool.statements()
+    }
+
     /// Get pool status
     pub fn status(&self) -> chakra_pool::pool::PoolStatus {
         self.pool.status()
@@ -194,6 +429,51 @@ impl PostgresPool {
     }
 }
 
+/// Upper bound on statements cached per connection
+const MAX_CACHED_STATEMENTS: usize = 256;
+
+/// Whether `sql` is a DML statement, after any leading comments
+fn is_dml(sql: &str) -> bool {
+    let mut rest = sql.trim_start();
+    loop {
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, r)| r).trim_start();
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, r)| r).trim_start();
+        } else {
+            break;
+        }
+    }
+    let keyword: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    matches!(
+        keyword.as_str(),
+        "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "WITH"
+    )
+}
+
+/// Apply the session settings of `config`
+async fn configure_session(client: &Client, config: &PostgresConfig) -> Result<()> {
+    for sql in config.session_sql() {
+        client.simple_query(&sql).await.map_err(|e| {
+            ChakraError::Connection(ConnectionError::ConnectionFailed {
+                message: format!("Failed to configure session: {}", e),
+            })
+        })?;
+    }
+    Ok(())
+}
+
+fn set_application_name_sql(name: &str) -> String {
+    format!(
+        "SET application_name = {}",
+        Value::String(name.to_string()).to_sql_literal()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,4 +484,24 @@ mod tests {
         let manager = PostgresConnectionManager::new(config);
         assert_eq!(manager.next_id.load(Ordering::Relaxed), 1);
     }
+
+    #[test]
+    fn test_is_dml() {
+        assert!(is_dml("SELECT * FROM users"));
+        assert!(is_dml(
+            "  /* route='/pay' */\n-- note\nwith t AS (SELECT 1) SELECT * FROM t"
+        ));
+        assert!(is_dml("DELETE FROM sessions WHERE expires_at < now()"));
+        assert!(!is_dml("ALTER TABLE users ADD COLUMN age INT"));
+        assert!(!is_dml("CREATE INDEX ON users (email)"));
+        assert!(!is_dml("SET search_path TO app"));
+    }
+
+    #[test]
+    fn test_set_application_name_sql() {
+        assert_eq!(
+            set_application_name_sql("worker-3's"),
+            "SET application_name = 'worker-3''s'"
+        );
+    }
 }
@@ -1,21 +1,43 @@
 //! PostgreSQL query executor
 
-use crate::connection::PostgresPool;
+use crate::connection::{PostgresConnection, PostgresConnectionManager, PostgresPool};
 use crate::types::{row_from_postgres, to_postgres_param};
 use async_trait::async_trait;
 use chakra_core::error::{ChakraError, QueryError, Result};
+use chakra_core::executor::{buffered_stream, Executor};
 use chakra_core::result::Row;
+use chakra_core::script::ScriptSplitter;
 use chakra_core::sql::{Dialect, PostgresDialect, SqlFragment};
+use chakra_core::sql_cache::SqlCache;
+use chakra_core::transaction::{SavepointOp, Transaction, TransactionOptions};
 use chakra_core::types::Value;
-use chakra_migrate::executor::SqlExecutor;
+use chakra_migrate::executor::{SpeculativeRun, SpeculativeStep, SqlExecutor};
+use chakra_pool::PooledConnection;
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::types::ToSql;
+use tokio_postgres::Statement;
 use tracing::{debug, error};
 
+/// Rows fetched per round trip when streaming
+const STREAM_BATCH_SIZE: usize = 1000;
+
+/// Executions `execute_many` sends ahead of their results
+const PIPELINE_DEPTH: usize = 256;
+
+/// Numbers the server-side cursors opened for streams
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(1);
+
 /// PostgreSQL query executor
 pub struct PostgresExecutor {
     pool: Arc<PostgresPool>,
     dialect: PostgresDialect,
+    max_rows_guard: Option<usize>,
+    sql_cache: Option<SqlCache>,
+    stream_batch_size: usize,
 }
 
 impl PostgresExecutor {
@@ -24,42 +46,135 @@ impl PostgresExecutor {
         Self {
             pool,
             dialect: PostgresDialect,
+            max_rows_guard: None,
+            sql_cache: None,
+            stream_batch_size: STREAM_BATCH_SIZE,
         }
     }
 
+    /// Limit unbounded SELECTs run through `fetch_all` to `max_rows`
+    pub fn with_max_rows_guard(mut self, max_rows: usize) -> Self {
+        self.max_rows_guard = Some(max_rows);
+        self
+    }
+
+    /// Reuse generated SQL for up to `capacity` query shapes
+    pub fn with_sql_cache(mut self, capacity: usize) -> Self {
+        self.sql_cache = Some(SqlCache::new(capacity));
+        self
+    }
+
+    /// Fetch `batch_size` rows per round trip when streaming
+    pub fn with_stream_batch_size(mut self, batch_size: usize) -> Self {
+        self.stream_batch_size = batch_size.max(1);
+        self
+    }
+
     /// Get the dialect
     pub fn dialect(&self) -> &PostgresDialect {
         &self.dialect
     }
 
+    /// Get the connection pool
+    pub fn pool(&self) -> &Arc<PostgresPool> {
+        &self.pool
+    }
+
     /// Execute a query and return rows
     pub async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
         let conn = self.pool.get().await?;
+        self.query_on(&conn, false, sql, params).await
+    }
 
+    /// Execute a query on `conn`
+    ///
+    /// A statement whose cached plan went stale is prepared again and
+    /// retried, unless `in_transaction`: the failure has already aborted
+    /// the transaction, so the error is returned and the statement is only
+    /// prepared again on its next use.
+    async fn query_on(
+        &self,
+        conn: &PostgresConnection,
+        in_transaction: bool,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<Vec<Row>> {
         debug!("Executing query: {} with {} params", sql, params.len());
 
         let pg_params: Vec<Box<dyn ToSql + Sync + Send>> =
             params.iter().map(to_postgres_param).collect();
 
-        let param_refs: Vec<&(dyn ToSql + Sync)> =
-            pg_params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)).collect();
-
-        let rows = conn
-            .client
-            .query(sql, &param_refs)
-            .await
-            .map_err(|e| {
-                error!("Query failed: {}", e);
-                ChakraError::Query(QueryError::ExecutionFailed {
-                    message: e.to_string(),
-                })
-            })?;
+        let param_refs: Vec<&(dyn ToSql + Sync)> = pg_params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let stmt = self.prepare(conn, sql).await?;
+        let rows = match conn.client.query(&stmt, &param_refs).await {
+            Err(e) if is_stale_plan(&e) => {
+                conn.forget_statement(sql);
+                if in_transaction {
+                    Err(e)
+                } else {
+                    let stmt = self.prepare(conn, sql).await?;
+                    conn.client.query(&stmt, &param_refs).await
+                }
+            }
+            result => result,
+        }
+        .map_err(|e| {
+            error!("Query failed: {}", e);
+            query_error(e)
+        })?;
 
         Ok(rows.iter().map(row_from_postgres).collect())
     }
 
+    /// Prepare `sql` on `conn`, recording it for pool warming if the
+    /// connection caches it
+    async fn prepare(&self, conn: &PostgresConnection, sql: &str) -> Result<Statement> {
+        let stmt = conn.prepare_cached(sql).await.map_err(|e| {
+            error!("Prepare failed: {}", e);
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: e.to_string(),
+            })
+        })?;
+        if conn.caches(sql) {
+            self.pool.statements().record(sql);
+        }
+        Ok(stmt)
+    }
+
+    /// Stream the rows of a query through a server-side cursor
+    ///
+    /// The cursor lives in its own transaction on a connection held until
+    /// the stream ends or is dropped, and rows are fetched
+    /// `stream_batch_size` at a time.
+    pub fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        let batch_size = self.stream_batch_size;
+        let open = async move {
+            let cursor = Cursor::open(self.pool.get().await?, &sql, &params).await?;
+            Ok::<_, ChakraError>(
+                stream::try_unfold(cursor, move |mut cursor| async move {
+                    let rows = cursor.fetch(batch_size).await?;
+                    Ok::<_, ChakraError>(
+                        rows.map(|rows| (stream::iter(rows.into_iter().map(Ok)), cursor)),
+                    )
+                })
+                .try_flatten(),
+            )
+        };
+        stream::once(open).try_flatten().boxed()
+    }
+
     /// Execute a query with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
     pub async fn query_fragment(&self, fragment: &SqlFragment) -> Result<Vec<Row>> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.query(&fragment.sql, &fragment.params).await;
+        }
         self.query(&fragment.sql, &fragment.params).await
     }
 
@@ -72,31 +187,122 @@ impl PostgresExecutor {
     /// Execute a statement and return affected row count
     pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
         let conn = self.pool.get().await?;
+        self.execute_on(&conn, false, sql, params).await
+    }
 
+    /// Execute a statement on `conn`, retrying a stale plan as `query_on`
+    /// does
+    async fn execute_on(
+        &self,
+        conn: &PostgresConnection,
+        in_transaction: bool,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<u64> {
         debug!("Executing statement: {} with {} params", sql, params.len());
 
         let pg_params: Vec<Box<dyn ToSql + Sync + Send>> =
             params.iter().map(to_postgres_param).collect();
 
-        let param_refs: Vec<&(dyn ToSql + Sync)> =
-            pg_params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)).collect();
+        let param_refs: Vec<&(dyn ToSql + Sync)> = pg_params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let stmt = self.prepare(conn, sql).await?;
+        let result = match conn.client.execute(&stmt, &param_refs).await {
+            Err(e) if is_stale_plan(&e) => {
+                conn.forget_statement(sql);
+                if in_transaction {
+                    Err(e)
+                } else {
+                    let stmt = self.prepare(conn, sql).await?;
+                    conn.client.execute(&stmt, &param_refs).await
+                }
+            }
+            result => result,
+        }
+        .map_err(|e| {
+            error!("Statement failed: {}", e);
+            query_error(e)
+        })?;
 
-        let result = conn
-            .client
-            .execute(sql, &param_refs)
+        Ok(result)
+    }
+
+    /// Execute a statement once for each parameter set, in one transaction,
+    /// and return the total affected row count
+    ///
+    /// The statement is prepared once and its executions are pipelined:
+    /// up to `PIPELINE_DEPTH` are sent before waiting for their results.
+    pub async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        if params.is_empty() {
+            return Ok(0);
+        }
+        let conn = self.pool.get().await?;
+        conn.client
+            .batch_execute("BEGIN")
             .await
-            .map_err(|e| {
+            .map_err(query_error)?;
+        match self.execute_many_on(&conn, sql, params).await {
+            Ok(affected) => {
+                conn.client
+                    .batch_execute("COMMIT")
+                    .await
+                    .map_err(query_error)?;
+                Ok(affected)
+            }
+            Err(e) => {
+                conn.client.batch_execute("ROLLBACK").await.ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Pipeline a statement's executions on `conn`
+    async fn execute_many_on(
+        &self,
+        conn: &PostgresConnection,
+        sql: &str,
+        params: &[Vec<Value>],
+    ) -> Result<u64> {
+        debug!("Executing statement {} times: {}", params.len(), sql);
+
+        let stmt = self.prepare(conn, sql).await?;
+
+        let mut affected = 0;
+        for chunk in params.chunks(PIPELINE_DEPTH) {
+            let pg_params: Vec<Vec<Box<dyn ToSql + Sync + Send>>> = chunk
+                .iter()
+                .map(|params| params.iter().map(to_postgres_param).collect())
+                .collect();
+            let executions = pg_params.iter().map(|params| {
+                let param_refs = params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync));
+                conn.client.execute_raw(&stmt, param_refs)
+            });
+            // The transaction is aborted, so a stale plan is only evicted
+            // for the next call
+            let counts = future::try_join_all(executions).await.map_err(|e| {
+                if is_stale_plan(&e) {
+                    conn.forget_statement(sql);
+                }
                 error!("Statement failed: {}", e);
-                ChakraError::Query(QueryError::ExecutionFailed {
-                    message: e.to_string(),
-                })
+                query_error(e)
             })?;
+            affected += counts.iter().sum::<u64>();
+        }
 
-        Ok(result)
+        Ok(affected)
     }
 
     /// Execute a statement with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
     pub async fn execute_fragment(&self, fragment: &SqlFragment) -> Result<u64> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.execute(&fragment.sql, &fragment.params).await;
+        }
         self.execute(&fragment.sql, &fragment.params).await
     }
 
@@ -115,18 +321,52 @@ impl PostgresExecutor {
         Ok(())
     }
 
-    /// Begin a transaction
-    pub async fn begin(&self) -> Result<PostgresTransaction> {
+    /// Execute a multi-statement SQL script, returning the number of statements run
+    ///
+    /// Statements run in order on a single connection; execution stops at the
+    /// first failure.
+    pub async fn execute_script(&self, script: &str) -> Result<usize> {
         let conn = self.pool.get().await?;
+        let statements = ScriptSplitter::postgres().split(script);
 
-        conn.client
-            .batch_execute("BEGIN")
-            .await
-            .map_err(|e| {
+        for sql in &statements {
+            debug!("Executing script statement: {}", sql);
+            conn.client.batch_execute(sql).await.map_err(|e| {
                 ChakraError::Query(QueryError::ExecutionFailed {
-                    message: format!("Failed to begin transaction: {}", e),
+                    message: e.to_string(),
                 })
             })?;
+        }
+
+        Ok(statements.len())
+    }
+
+    /// Run `f` in a transaction on one connection, committing if it returns
+    /// `Ok` and rolling back otherwise
+    ///
+    /// Serialization failures and deadlocks are retried as `options` say;
+    /// see `chakra_core::transaction::transaction`. Streams inside the
+    /// closure are read whole rather than through a cursor.
+    pub async fn transaction<T, F>(&self, options: &TransactionOptions, f: F) -> Result<T>
+    where
+        F: for<'c, 't> FnMut(&'t Transaction<'c>) -> BoxFuture<'t, Result<T>>,
+    {
+        let conn = PinnedConnection {
+            executor: self,
+            conn: self.pool.get().await?,
+        };
+        chakra_core::transaction::transaction(&conn, options, f).await
+    }
+
+    /// Begin a transaction
+    pub async fn begin(&self) -> Result<PostgresTransaction<'_>> {
+        let conn = self.pool.get().await?;
+
+        conn.client.batch_execute("BEGIN").await.map_err(|e| {
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: format!("Failed to begin transaction: {}", e),
+            })
+        })?;
 
         Ok(PostgresTransaction {
             executor: self,
@@ -135,6 +375,179 @@ impl PostgresExecutor {
     }
 }
 
+#[async_trait]
+impl Executor for PostgresExecutor {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        PostgresExecutor::query(self, sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        PostgresExecutor::execute(self, sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        PostgresExecutor::execute_many(self, sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        PostgresExecutor::stream(self, sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.pool.config().read_only
+    }
+
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        Ok(Some(Box::new(PinnedConnection {
+            executor: self,
+            conn: self.pool.get().await?,
+        })))
+    }
+}
+
+/// The connection a `transaction` closure, or a transaction begun on the
+/// executor, runs on
+struct PinnedConnection<'a> {
+    executor: &'a PostgresExecutor,
+    conn: PooledConnection<PostgresConnectionManager>,
+}
+
+#[async_trait]
+impl Executor for PinnedConnection<'_> {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.executor.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.executor.query_on(&self.conn, true, sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.executor
+            .execute_on(&self.conn, true, sql, params)
+            .await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        self.executor.execute_many_on(&self.conn, sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        buffered_stream(self, sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.executor.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.executor.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.executor.pool.config().read_only
+    }
+}
+
+/// A server-side cursor, open in a transaction on the connection it holds
+struct Cursor {
+    /// `None` once the cursor is closed and the connection released
+    conn: Option<PooledConnection<PostgresConnectionManager>>,
+    name: String,
+}
+
+impl Cursor {
+    async fn open(
+        conn: PooledConnection<PostgresConnectionManager>,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<Self> {
+        let cursor = Self {
+            conn: Some(conn),
+            name: format!(
+                "chakra_cursor_{}",
+                NEXT_CURSOR.fetch_add(1, Ordering::Relaxed)
+            ),
+        };
+        let client = cursor.client();
+        debug!("Streaming query: {} with {} params", sql, params.len());
+        client.batch_execute("BEGIN").await.map_err(query_error)?;
+
+        let pg_params: Vec<Box<dyn ToSql + Sync + Send>> =
+            params.iter().map(to_postgres_param).collect();
+        let param_refs: Vec<&(dyn ToSql + Sync)> = pg_params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+        let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", cursor.name, sql);
+        client
+            .execute(declare.as_str(), &param_refs)
+            .await
+            .map_err(|e| {
+                error!("Query failed: {}", e);
+                query_error(e)
+            })?;
+        Ok(cursor)
+    }
+
+    fn client(&self) -> &tokio_postgres::Client {
+        &self.conn.as_ref().expect("cursor already closed").client
+    }
+
+    /// The next batch, or `None` once the cursor is exhausted and closed
+    async fn fetch(&mut self, batch_size: usize) -> Result<Option<Vec<Row>>> {
+        if self.conn.is_none() {
+            return Ok(None);
+        }
+        let fetch = format!("FETCH {} FROM {}", batch_size, self.name);
+        let rows = self
+            .client()
+            .query(fetch.as_str(), &[])
+            .await
+            .map_err(query_error)?;
+        if rows.len() < batch_size {
+            let close = format!("CLOSE {}; COMMIT", self.name);
+            self.client()
+                .batch_execute(&close)
+                .await
+                .map_err(query_error)?;
+            self.conn = None;
+            if rows.is_empty() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(rows.iter().map(row_from_postgres).collect()))
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        // Dropped part way through or after an error: end the transaction
+        // before the connection goes back to the pool
+        if let Some(conn) = self.conn.take() {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move {
+                    if let Err(e) = conn.client.batch_execute("ROLLBACK").await {
+                        debug!("Failed to roll back stream: {}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
 /// A PostgreSQL transaction
 pub struct PostgresTransaction<'a> {
     executor: &'a PostgresExecutor,
@@ -152,18 +565,36 @@ impl<'a> PostgresTransaction<'a> {
         self.executor.execute(sql, params).await
     }
 
+    /// Create a savepoint within the transaction
+    pub async fn savepoint(&self, name: &str) -> Result<()> {
+        self.executor
+            .execute_batch(&[&SavepointOp::Begin.sql(name)?])
+            .await
+    }
+
+    /// Release a savepoint, keeping its changes
+    pub async fn release_savepoint(&self, name: &str) -> Result<()> {
+        self.executor
+            .execute_batch(&[&SavepointOp::Release.sql(name)?])
+            .await
+    }
+
+    /// Undo everything since a savepoint, which stays open
+    pub async fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        self.executor
+            .execute_batch(&[&SavepointOp::RollbackTo.sql(name)?])
+            .await
+    }
+
     /// Commit the transaction
     pub async fn commit(mut self) -> Result<()> {
         let conn = self.executor.pool.get().await?;
 
-        conn.client
-            .batch_execute("COMMIT")
-            .await
-            .map_err(|e| {
-                ChakraError::Query(QueryError::ExecutionFailed {
-                    message: format!("Failed to commit transaction: {}", e),
-                })
-            })?;
+        conn.client.batch_execute("COMMIT").await.map_err(|e| {
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: format!("Failed to commit transaction: {}", e),
+            })
+        })?;
 
         self.committed = true;
         Ok(())
@@ -173,14 +604,11 @@ impl<'a> PostgresTransaction<'a> {
     pub async fn rollback(mut self) -> Result<()> {
         let conn = self.executor.pool.get().await?;
 
-        conn.client
-            .batch_execute("ROLLBACK")
-            .await
-            .map_err(|e| {
-                ChakraError::Query(QueryError::ExecutionFailed {
-                    message: format!("Failed to rollback transaction: {}", e),
-                })
-            })?;
+        conn.client.batch_execute("ROLLBACK").await.map_err(|e| {
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: format!("Failed to rollback transaction: {}", e),
+            })
+        })?;
 
         self.committed = true; // Prevent rollback in drop
         Ok(())
@@ -196,6 +624,17 @@ impl<'a> Drop for PostgresTransaction<'a> {
     }
 }
 
+/// Whether a cached statement failed because a schema change altered its
+/// result type, so preparing it again fixes it
+fn is_stale_plan(e: &tokio_postgres::Error) -> bool {
+    e.as_db_error().is_some_and(|db| {
+        *db.code() == SqlState::FEATURE_NOT_SUPPORTED
+            && db
+                .message()
+                .contains("cached plan must not change result type")
+    })
+}
+
 #[async_trait]
 impl SqlExecutor for PostgresExecutor {
     async fn execute(&self, sql: &str) -> Result<u64> {
@@ -234,6 +673,53 @@ impl SqlExecutor for PostgresExecutor {
         Ok(results)
     }
 
+    async fn speculate(&self, steps: &[SpeculativeStep]) -> Result<SpeculativeRun> {
+        let conn = self.pool.get().await?;
+
+        conn.client.batch_execute("BEGIN").await.map_err(|e| {
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: e.to_string(),
+            })
+        })?;
+
+        let mut run = SpeculativeRun::default();
+        for (i, step) in steps.iter().enumerate() {
+            let result = match step {
+                SpeculativeStep::Execute(sql) => conn.client.execute(sql.as_str(), &[]).await,
+                SpeculativeStep::Verify(sql) => conn
+                    .client
+                    .query(sql.as_str(), &[])
+                    .await
+                    .map(|rows| rows.len() as u64),
+            };
+            match result {
+                Ok(count) => {
+                    run.counts.push(count);
+                    if matches!(step, SpeculativeStep::Verify(_)) && count > 0 {
+                        run.failed = Some((i, format!("verification returned {} row(s)", count)));
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let reason = match e.as_db_error() {
+                        Some(db) => db.message().to_string(),
+                        None => e.to_string(),
+                    };
+                    run.failed = Some((i, reason));
+                    break;
+                }
+            }
+        }
+
+        conn.client.batch_execute("ROLLBACK").await.map_err(|e| {
+            ChakraError::Query(QueryError::ExecutionFailed {
+                message: e.to_string(),
+            })
+        })?;
+
+        Ok(run)
+    }
+
     async fn begin_transaction(&self) -> Result<()> {
         let conn = self.pool.get().await?;
         conn.client.batch_execute("BEGIN").await.map_err(|e| {
@@ -265,9 +751,56 @@ impl SqlExecutor for PostgresExecutor {
     }
 }
 
+/// Convert a failed query into a `ChakraError`, keeping unique violations
+/// structured so models can map them to their constraints, and transaction
+/// conflicts so they can be retried
+fn query_error(e: tokio_postgres::Error) -> ChakraError {
+    match e.as_db_error() {
+        Some(db) if *db.code() == SqlState::UNIQUE_VIOLATION => ChakraError::unique_violation(
+            db.constraint().map(str::to_string),
+            db.detail().map(unique_columns).unwrap_or_default(),
+        ),
+        Some(db) if *db.code() == SqlState::T_R_SERIALIZATION_FAILURE => {
+            ChakraError::Query(QueryError::SerializationFailure {
+                message: db.message().to_string(),
+            })
+        }
+        Some(db) if *db.code() == SqlState::T_R_DEADLOCK_DETECTED => {
+            ChakraError::Query(QueryError::Deadlock {
+                message: db.message().to_string(),
+            })
+        }
+        _ => ChakraError::Query(QueryError::ExecutionFailed {
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Columns from a detail like `Key (tenant_id, slug)=(1, home) already exists.`
+fn unique_columns(detail: &str) -> Vec<String> {
+    detail
+        .strip_prefix("Key (")
+        .and_then(|rest| rest.split_once(")="))
+        .map(|(columns, _)| {
+            columns
+                .split(',')
+                .map(|c| c.trim().trim_matches('"').to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
+    // Integration tests would require a running PostgreSQL instance
     use super::*;
 
-    // Integration tests would require a running PostgreSQL instance
+    #[test]
+    fn test_unique_columns() {
+        assert_eq!(
+            unique_columns("Key (tenant_id, slug)=(1, home) already exists."),
+            ["tenant_id", "slug"]
+        );
+        assert!(unique_columns("something else").is_empty());
+    }
 }
@@ -4,9 +4,12 @@ use crate::connection::PostgresPool;
 use async_trait::async_trait;
 use chakra_core::error::Result;
 use chakra_schema::introspect::{
-    RawColumnInfo, RawConstraintInfo, RawIndexInfo, RawTableInfo, SchemaIntrospector,
+    default_acl_object_type, parse_acl, parse_storage, RawColumnInfo, RawConstraintInfo,
+    SchemaIntrospector,
+};
+use chakra_schema::schema::{
+    CustomType, DefaultPrivilege, IndexColumn, Schema, Table, View, ViewDefinition,
 };
-use chakra_schema::schema::{Schema, Table};
 use std::sync::Arc;
 use tracing::debug;
 
@@ -32,13 +35,43 @@ impl PostgresIntrospector {
                 obj_description((quote_ident(table_schema) || '.' || quote_ident(table_name))::regclass, 'pg_class') as comment
             FROM information_schema.tables
             WHERE table_schema = '{}'
-            AND table_type IN ('BASE TABLE', 'VIEW')
+            AND table_type = 'BASE TABLE'
             ORDER BY table_name
             "#,
             schema
         )
     }
 
+    /// Get views query
+    fn views_query(&self, schema: &str) -> String {
+        format!(
+            r#"
+            SELECT table_name, view_definition
+            FROM information_schema.views
+            WHERE table_schema = '{}'
+            ORDER BY table_name
+            "#,
+            schema
+        )
+    }
+
+    /// Get enum types query, with labels in their sort order
+    fn enums_query(&self, schema: &str) -> String {
+        format!(
+            r#"
+            SELECT
+                t.typname as type_name,
+                array_agg(e.enumlabel::text ORDER BY e.enumsortorder) as labels
+            FROM pg_type t
+            JOIN pg_enum e ON e.enumtypid = t.oid
+            JOIN pg_namespace n ON n.oid = t.typnamespace
+            WHERE n.nspname = '{}'
+            GROUP BY t.typname
+            "#,
+            schema
+        )
+    }
+
     /// Get columns query
     fn columns_query(&self, schema: &str, table: &str) -> String {
         format!(
@@ -49,7 +82,7 @@ impl PostgresIntrospector {
                 c.ordinal_position,
                 c.column_default,
                 c.is_nullable = 'YES' as is_nullable,
-                c.data_type,
+                CASE WHEN c.data_type = 'USER-DEFINED' THEN c.udt_name ELSE c.data_type END as data_type,
                 c.character_maximum_length,
                 c.numeric_precision,
                 c.numeric_scale,
@@ -66,6 +99,9 @@ impl PostgresIntrospector {
     }
 
     /// Get indexes query
+    ///
+    /// Key columns come before `INCLUDE` columns in `indkey`; a 0 entry is
+    /// an expression, whose SQL `pg_get_indexdef` returns.
     fn indexes_query(&self, schema: &str, table: &str) -> String {
         format!(
             r#"
@@ -76,21 +112,63 @@ impl PostgresIntrospector {
                 ix.indisprimary as is_primary,
                 am.amname as index_type,
                 pg_get_expr(ix.indpred, ix.indrelid) as where_clause,
-                array_agg(a.attname ORDER BY array_position(ix.indkey, a.attnum)) as column_names
+                ARRAY(
+                    SELECT pg_get_indexdef(ix.indexrelid, k, true)
+                    FROM generate_series(1, ix.indnkeyatts) AS k ORDER BY k
+                ) as column_names,
+                ARRAY(
+                    SELECT ix.indkey[k - 1] = 0
+                    FROM generate_series(1, ix.indnkeyatts) AS k ORDER BY k
+                ) as is_expression,
+                ARRAY(
+                    SELECT pg_get_indexdef(ix.indexrelid, k, true)
+                    FROM generate_series(ix.indnkeyatts + 1, ix.indnatts) AS k ORDER BY k
+                ) as include_columns,
+                COALESCE(i.reloptions, '{{}}') as storage
             FROM pg_index ix
             JOIN pg_class t ON t.oid = ix.indrelid
             JOIN pg_class i ON i.oid = ix.indexrelid
             JOIN pg_namespace n ON n.oid = t.relnamespace
             JOIN pg_am am ON am.oid = i.relam
-            LEFT JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = ANY(ix.indkey)
             WHERE n.nspname = '{}'
             AND t.relname = '{}'
-            GROUP BY t.relname, i.relname, ix.indisunique, ix.indisprimary, am.amname, ix.indpred, ix.indrelid
             "#,
             schema, table
         )
     }
 
+    /// Get table owner and ACL query
+    fn permissions_query(&self, schema: &str, table: &str) -> String {
+        format!(
+            r#"
+            SELECT
+                pg_get_userbyid(c.relowner) as owner,
+                c.relacl::text[] as acl
+            FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = '{}'
+            AND c.relname = '{}'
+            "#,
+            schema, table
+        )
+    }
+
+    /// Get default privileges query, for the schema and database-wide
+    fn default_privileges_query(&self, schema: &str) -> String {
+        format!(
+            r#"
+            SELECT
+                pg_get_userbyid(d.defaclrole) as role,
+                d.defaclobjtype::text as object_type,
+                d.defaclacl::text[] as acl
+            FROM pg_default_acl d
+            LEFT JOIN pg_namespace n ON n.oid = d.defaclnamespace
+            WHERE n.nspname = '{}' OR d.defaclnamespace = 0
+            "#,
+            schema
+        )
+    }
+
     /// Get constraints query
     fn constraints_query(&self, schema: &str, table: &str) -> String {
         format!(
@@ -104,7 +182,9 @@ impl PostgresIntrospector {
                 ccu.table_name as references_table,
                 array_agg(ccu.column_name) as references_columns,
                 rc.delete_rule as on_delete,
-                rc.update_rule as on_update
+                rc.update_rule as on_update,
+                tc.is_deferrable = 'YES' as is_deferrable,
+                tc.initially_deferred = 'YES' as initially_deferred
             FROM information_schema.table_constraints tc
             LEFT JOIN information_schema.key_column_usage kcu
                 ON tc.constraint_name = kcu.constraint_name
@@ -122,7 +202,8 @@ impl PostgresIntrospector {
             WHERE tc.table_schema = '{}'
             AND tc.table_name = '{}'
             GROUP BY tc.table_name, tc.constraint_name, tc.constraint_type, cc.check_clause,
-                     ccu.table_name, rc.delete_rule, rc.update_rule
+                     ccu.table_name, rc.delete_rule, rc.update_rule, tc.is_deferrable,
+                     tc.initially_deferred
             "#,
             schema, table
         )
@@ -144,10 +225,60 @@ impl SchemaIntrospector for PostgresIntrospector {
             schema.add_table(table);
         }
 
+        let conn = self.pool.get().await?;
+        let enum_rows = conn
+            .client
+            .query(&self.enums_query(schema_name), &[])
+            .await
+            .map_err(|e| chakra_core::error::ChakraError::internal(e.to_string()))?;
+
+        for row in &enum_rows {
+            schema.add_type(CustomType::Enum {
+                name: row.get("type_name"),
+                values: row.get("labels"),
+            });
+        }
+
+        let view_rows = conn
+            .client
+            .query(&self.views_query(schema_name), &[])
+            .await
+            .map_err(|e| chakra_core::error::ChakraError::internal(e.to_string()))?;
+
+        for row in &view_rows {
+            let name: String = row.get("table_name");
+            // NULL when the current user doesn't own the view
+            let definition: Option<String> = row.get("view_definition");
+            schema.add_view(View::new(
+                name,
+                ViewDefinition::Sql(definition.unwrap_or_default()),
+            ));
+        }
+
+        let privilege_rows = conn
+            .client
+            .query(&self.default_privileges_query(schema_name), &[])
+            .await
+            .map_err(|e| chakra_core::error::ChakraError::internal(e.to_string()))?;
+
+        for row in &privilege_rows {
+            let role: String = row.get("role");
+            let object_type: String = row.get("object_type");
+            let acl: Vec<String> = row.get("acl");
+            for grant in parse_acl(&acl) {
+                schema.default_privileges.push(DefaultPrivilege {
+                    role: role.clone(),
+                    object_type: default_acl_object_type(&object_type).to_string(),
+                    grant,
+                });
+            }
+        }
+
         debug!(
-            "Introspected schema {} with {} tables",
+            "Introspected schema {} with {} tables and {} views",
             schema_name,
-            schema.tables.len()
+            schema.tables.len(),
+            schema.views.len()
         );
 
         Ok(schema)
@@ -200,10 +331,34 @@ impl SchemaIntrospector for PostgresIntrospector {
                 "PRIMARY KEY" => {
                     table.primary_key = Some(chakra_schema::schema::PrimaryKey::new(columns));
                 }
-                "UNIQUE" | "CHECK" | "FOREIGN KEY" => {
-                    // Handle other constraints
+                "UNIQUE" | "FOREIGN KEY" => {
+                    // The joins repeat columns of multi-column keys
+                    let raw = RawConstraintInfo {
+                        table_name: row.get("table_name"),
+                        constraint_name: row.get("constraint_name"),
+                        constraint_type,
+                        columns: distinct(columns),
+                        check_expression: None,
+                        references_table: row.get("references_table"),
+                        references_columns: row
+                            .get::<_, Option<Vec<Option<String>>>>("references_columns")
+                            .map(|c| distinct(c.into_iter().flatten().collect())),
+                        on_delete: row.get("on_delete"),
+                        on_update: row.get("on_update"),
+                        is_deferrable: row.get("is_deferrable"),
+                        initially_deferred: row.get("initially_deferred"),
+                    };
+                    if let Some(constraint) = raw.to_constraint() {
+                        table.add_constraint(constraint);
+                    }
+                    if let Some(fk) = raw.to_foreign_key() {
+                        table.add_foreign_key(fk);
+                    }
+                }
+                _ => {
+                    // CHECK rows include NOT NULL columns, which are read
+                    // from the column definitions instead
                 }
-                _ => {}
             }
         }
 
@@ -217,16 +372,45 @@ impl SchemaIntrospector for PostgresIntrospector {
         for row in &index_rows {
             let is_primary: bool = row.get("is_primary");
             if !is_primary {
-                let index = chakra_schema::schema::Index::new(
+                let columns: Vec<String> = row.get("column_names");
+                let is_expression: Vec<bool> = row.get("is_expression");
+                let mut index = chakra_schema::schema::Index::new(
                     row.get::<_, String>("index_name"),
-                    row.get::<_, Vec<String>>("column_names"),
+                    Vec::<String>::new(),
                 );
+                index.columns = columns
+                    .into_iter()
+                    .zip(is_expression)
+                    .map(|(column, expression)| match expression {
+                        true => IndexColumn::expression(column),
+                        false => IndexColumn::new(column),
+                    })
+                    .collect();
+                index.include = row.get("include_columns");
+                index.storage = parse_storage(&row.get::<_, Vec<String>>("storage"));
 
                 let is_unique: bool = row.get("is_unique");
                 table.add_index(if is_unique { index.unique() } else { index });
             }
         }
 
+        // Get owner and grants; a NULL ACL means only the owner has access
+        let permission_rows = conn
+            .client
+            .query(&self.permissions_query(schema_name, table_name), &[])
+            .await
+            .map_err(|e| chakra_core::error::ChakraError::internal(e.to_string()))?;
+
+        if let Some(row) = permission_rows.first() {
+            let owner: String = row.get("owner");
+            let acl: Option<Vec<String>> = row.get("acl");
+            table.grants = parse_acl(&acl.unwrap_or_default())
+                .into_iter()
+                .filter(|g| g.grantee != owner)
+                .collect();
+            table.owner = Some(owner);
+        }
+
         Ok(table)
     }
 
@@ -278,6 +462,17 @@ impl SchemaIntrospector for PostgresIntrospector {
     }
 }
 
+/// `values` without repeats, keeping the first occurrence of each
+fn distinct(values: Vec<String>) -> Vec<String> {
+    let mut seen = Vec::with_capacity(values.len());
+    for value in values {
+        if !seen.contains(&value) {
+            seen.push(value);
+        }
+    }
+    seen
+}
+
 #[cfg(test)]
 mod tests {
     // Integration tests would require a running PostgreSQL instance
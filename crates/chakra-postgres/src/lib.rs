@@ -12,13 +12,12 @@ pub mod executor;
 pub mod introspect;
 pub mod types;
 
-pub use config::PostgresConfig;
+pub use config::{PostgresConfig, ResetPolicy};
 pub use connection::{PostgresConnection, PostgresPool};
 pub use executor::PostgresExecutor;
 pub use introspect::PostgresIntrospector;
 
 use chakra_core::error::Result;
-use tokio_postgres::Client;
 
 /// Create a PostgreSQL connection pool
 pub async fn connect(config: PostgresConfig) -> Result<PostgresPool> {
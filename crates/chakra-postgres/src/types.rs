@@ -1,7 +1,252 @@
 //! Type conversions between Chakra and PostgreSQL
 
+use bytes::{BufMut, BytesMut};
+use chakra_core::net::{Cidr, MacAddr};
+use chakra_core::range::Range;
 use chakra_core::types::Value;
-use tokio_postgres::types::{FromSql, ToSql, Type};
+use std::error::Error;
+use std::net::IpAddr;
+use std::ops::Bound;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, Kind, ToSql, Type};
+
+/// A string parameter, also accepted by enum columns
+#[derive(Debug)]
+struct Text(String);
+
+impl ToSql for Text {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        // An enum's binary format is its label, as for text
+        self.0.as_str().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Enum(_)) || <String as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+/// An enum column's label
+struct Label(String);
+
+impl<'a> FromSql<'a> for Label {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(Label(std::str::from_utf8(raw)?.to_string()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Enum(_))
+    }
+}
+
+/// Address family bytes of the `inet` and `cidr` binary format
+const PGSQL_AF_INET: u8 = 2;
+const PGSQL_AF_INET6: u8 = 3;
+
+/// An `inet` or `cidr` parameter
+#[derive(Debug)]
+struct Inet(Cidr);
+
+impl ToSql for Inet {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let (family, octets) = match self.0.addr() {
+            IpAddr::V4(addr) => (PGSQL_AF_INET, addr.octets().to_vec()),
+            IpAddr::V6(addr) => (PGSQL_AF_INET6, addr.octets().to_vec()),
+        };
+        out.put_u8(family);
+        out.put_u8(self.0.prefix());
+        out.put_u8(u8::from(*ty == Type::CIDR));
+        out.put_u8(octets.len() as u8);
+        out.put_slice(&octets);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Inet {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let [family, prefix, _is_cidr, len, octets @ ..] = raw else {
+            return Err("invalid inet value".into());
+        };
+        let addr = match (*family, *len as usize) {
+            (PGSQL_AF_INET, 4) if octets.len() == 4 => IpAddr::from(<[u8; 4]>::try_from(octets)?),
+            (PGSQL_AF_INET6, 16) if octets.len() == 16 => {
+                IpAddr::from(<[u8; 16]>::try_from(octets)?)
+            }
+            _ => return Err("invalid inet value".into()),
+        };
+        Ok(Inet(Cidr::new(addr, *prefix)?))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+}
+
+/// A `macaddr` parameter or column
+#[derive(Debug)]
+struct Mac(MacAddr);
+
+impl ToSql for Mac {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(&self.0.octets());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::MACADDR
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Mac {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(Mac(MacAddr(raw.try_into()?)))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::MACADDR
+    }
+}
+
+/// Flag bits of the range binary format
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LB_INC: u8 = 0x02;
+const RANGE_UB_INC: u8 = 0x04;
+const RANGE_LB_INF: u8 = 0x08;
+const RANGE_UB_INF: u8 = 0x10;
+
+/// A parameter or column of any range type
+#[derive(Debug)]
+struct PgRange(Range<Value>);
+
+impl ToSql for PgRange {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let Kind::Range(element) = ty.kind() else {
+            return Err(format!("{} is not a range type", ty).into());
+        };
+        let range = &self.0;
+        if range.is_empty() {
+            out.put_u8(RANGE_EMPTY);
+            return Ok(IsNull::No);
+        }
+        let flags = match range.lower() {
+            Bound::Included(_) => RANGE_LB_INC,
+            Bound::Excluded(_) => 0,
+            Bound::Unbounded => RANGE_LB_INF,
+        } | match range.upper() {
+            Bound::Included(_) => RANGE_UB_INC,
+            Bound::Excluded(_) => 0,
+            Bound::Unbounded => RANGE_UB_INF,
+        };
+        out.put_u8(flags);
+        for bound in [range.lower(), range.upper()] {
+            if let Bound::Included(value) | Bound::Excluded(value) = bound {
+                // Each bound is its element's binary form behind its length
+                let start = out.len();
+                out.put_i32(0);
+                range_element(value, element)?.to_sql_checked(element, out)?;
+                let len = i32::try_from(out.len() - start - 4)?;
+                out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+            }
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Range(_))
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PgRange {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let Kind::Range(element) = ty.kind() else {
+            return Err(format!("{} is not a range type", ty).into());
+        };
+        let Some((&flags, mut rest)) = raw.split_first() else {
+            return Err("invalid range value".into());
+        };
+        if flags & RANGE_EMPTY != 0 {
+            return Ok(PgRange(Range::empty()));
+        }
+        let mut bound =
+            |infinite: u8, inclusive: u8| -> Result<Bound<Value>, Box<dyn Error + Sync + Send>> {
+                if flags & infinite != 0 {
+                    return Ok(Bound::Unbounded);
+                }
+                let (len, tail) = rest.split_first_chunk::<4>().ok_or("invalid range value")?;
+                let len = usize::try_from(i32::from_be_bytes(*len))?;
+                let (bytes, tail) = tail.split_at_checked(len).ok_or("invalid range value")?;
+                rest = tail;
+                let value = element_from_sql(element, bytes)?;
+                Ok(if flags & inclusive != 0 {
+                    Bound::Included(value)
+                } else {
+                    Bound::Excluded(value)
+                })
+            };
+        let lower = bound(RANGE_LB_INF, RANGE_LB_INC)?;
+        let upper = bound(RANGE_UB_INF, RANGE_UB_INC)?;
+        Ok(PgRange(Range::new(lower, upper)))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Range(_))
+    }
+}
+
+/// A range bound as a parameter of the range's element type, widening or
+/// narrowing integers and dropping the zone of timestamps as needed
+fn range_element(
+    value: &Value,
+    ty: &Type,
+) -> Result<Box<dyn ToSql + Sync + Send>, Box<dyn Error + Sync + Send>> {
+    Ok(match (value, ty) {
+        (Value::Int64(i), &Type::INT4) => Box::new(i32::try_from(*i)?),
+        (Value::Int32(i), &Type::INT8) => Box::new(i64::from(*i)),
+        (Value::Int32(i), &Type::NUMERIC) => Box::new(rust_decimal::Decimal::from(*i)),
+        (Value::Int64(i), &Type::NUMERIC) => Box::new(rust_decimal::Decimal::from(*i)),
+        (Value::DateTime(dt), &Type::TIMESTAMP) => Box::new(dt.naive_utc()),
+        _ => to_postgres_param(value),
+    })
+}
+
+/// Decode a range bound of the built-in range types' element types
+fn element_from_sql(ty: &Type, raw: &[u8]) -> Result<Value, Box<dyn Error + Sync + Send>> {
+    Ok(match *ty {
+        Type::INT4 => Value::Int32(i32::from_sql(ty, raw)?),
+        Type::INT8 => Value::Int64(i64::from_sql(ty, raw)?),
+        Type::NUMERIC => Value::Decimal(rust_decimal::Decimal::from_sql(ty, raw)?),
+        Type::TIMESTAMPTZ => Value::DateTime(chrono::DateTime::<chrono::Utc>::from_sql(ty, raw)?),
+        Type::TIMESTAMP => Value::DateTime(chrono::NaiveDateTime::from_sql(ty, raw)?.and_utc()),
+        Type::DATE => Value::Date(chrono::NaiveDate::from_sql(ty, raw)?),
+        _ => return Err(format!("unsupported range element type {}", ty).into()),
+    })
+}
 
 /// Convert a Chakra Value to a PostgreSQL parameter
 pub fn to_postgres_param(value: &Value) -> Box<dyn ToSql + Sync + Send> {
@@ -11,16 +256,23 @@ pub fn to_postgres_param(value: &Value) -> Box<dyn ToSql + Sync + Send> {
         Value::Int32(i) => Box::new(*i),
         Value::Int64(i) => Box::new(*i),
         Value::Float64(f) => Box::new(*f),
-        Value::Decimal(d) => Box::new(d.to_string()),
-        Value::String(s) => Box::new(s.clone()),
+        Value::Decimal(d) => Box::new(*d),
+        Value::String(s) => Box::new(Text(s.clone())),
         Value::Bytes(b) => Box::new(b.clone()),
         Value::Uuid(u) => Box::new(*u),
         Value::DateTime(dt) => Box::new(*dt),
         Value::Date(d) => Box::new(*d),
         Value::Time(t) => Box::new(*t),
+        Value::IpAddr(a) => Box::new(Inet(Cidr::from(*a))),
+        Value::Cidr(c) => Box::new(Inet(*c)),
+        Value::MacAddr(m) => Box::new(Mac(*m)),
+        Value::Range(r) => Box::new(PgRange((**r).clone())),
         Value::Json(j) => Box::new(j.clone()),
         Value::Array(arr) => {
-            // Convert array to JSON for simplicity
+            if let Some(array) = typed_array(arr) {
+                return array;
+            }
+            // Mixed element types have no Postgres array type, so send JSON
             let json = serde_json::Value::Array(
                 arr.iter()
                     .map(|v| match v {
@@ -36,42 +288,159 @@ pub fn to_postgres_param(value: &Value) -> Box<dyn ToSql + Sync + Send> {
     }
 }
 
+/// Bind an array of same-typed scalars as a Postgres array, e.g. for `= ANY($1)`
+fn typed_array(arr: &[Value]) -> Option<Box<dyn ToSql + Sync + Send>> {
+    fn collect<T>(arr: &[Value], f: impl Fn(&Value) -> Option<T>) -> Option<Vec<Option<T>>> {
+        arr.iter()
+            .map(|v| {
+                if v.is_null() {
+                    Some(None)
+                } else {
+                    f(v).map(Some)
+                }
+            })
+            .collect()
+    }
+
+    let array: Box<dyn ToSql + Sync + Send> = match arr.iter().find(|v| !v.is_null())? {
+        Value::Bool(_) => Box::new(collect(arr, |v| v.as_bool())?),
+        Value::Int32(_) => Box::new(collect(arr, |v| match v {
+            Value::Int32(i) => Some(*i),
+            _ => None,
+        })?),
+        Value::Int64(_) => Box::new(collect(arr, |v| match v {
+            Value::Int64(i) => Some(*i),
+            _ => None,
+        })?),
+        Value::Float64(_) => Box::new(collect(arr, |v| match v {
+            Value::Float64(f) => Some(*f),
+            _ => None,
+        })?),
+        Value::Decimal(_) => Box::new(collect(arr, |v| match v {
+            Value::Decimal(d) => Some(*d),
+            _ => None,
+        })?),
+        Value::String(_) => Box::new(collect(arr, |v| v.as_str().map(str::to_string))?),
+        Value::Uuid(_) => Box::new(collect(arr, |v| match v {
+            Value::Uuid(u) => Some(*u),
+            _ => None,
+        })?),
+        Value::DateTime(_) => Box::new(collect(arr, |v| match v {
+            Value::DateTime(dt) => Some(*dt),
+            _ => None,
+        })?),
+        Value::Date(_) => Box::new(collect(arr, |v| match v {
+            Value::Date(d) => Some(*d),
+            _ => None,
+        })?),
+        Value::Time(_) => Box::new(collect(arr, |v| match v {
+            Value::Time(t) => Some(*t),
+            _ => None,
+        })?),
+        Value::IpAddr(_) => Box::new(collect(arr, |v| match v {
+            Value::IpAddr(a) => Some(Inet(Cidr::from(*a))),
+            _ => None,
+        })?),
+        _ => return None,
+    };
+    Some(array)
+}
+
 /// Convert a PostgreSQL row value to a Chakra Value
-pub fn from_postgres_value(
-    row: &tokio_postgres::Row,
-    idx: usize,
-    col_type: &Type,
-) -> Value {
+pub fn from_postgres_value(row: &tokio_postgres::Row, idx: usize, col_type: &Type) -> Value {
     match *col_type {
-        Type::BOOL => row.get::<_, Option<bool>>(idx).map(Value::Bool).unwrap_or(Value::Null),
-        Type::INT2 => row.get::<_, Option<i16>>(idx).map(|i| Value::Int32(i as i32)).unwrap_or(Value::Null),
-        Type::INT4 => row.get::<_, Option<i32>>(idx).map(Value::Int32).unwrap_or(Value::Null),
-        Type::INT8 => row.get::<_, Option<i64>>(idx).map(Value::Int64).unwrap_or(Value::Null),
-        Type::FLOAT4 => row.get::<_, Option<f32>>(idx).map(|f| Value::Float64(f as f64)).unwrap_or(Value::Null),
-        Type::FLOAT8 => row.get::<_, Option<f64>>(idx).map(Value::Float64).unwrap_or(Value::Null),
-        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => {
-            row.get::<_, Option<String>>(idx).map(Value::String).unwrap_or(Value::Null)
-        }
-        Type::BYTEA => row.get::<_, Option<Vec<u8>>>(idx).map(Value::Bytes).unwrap_or(Value::Null),
-        Type::UUID => row.get::<_, Option<uuid::Uuid>>(idx).map(Value::Uuid).unwrap_or(Value::Null),
-        Type::TIMESTAMPTZ => {
-            row.get::<_, Option<chrono::DateTime<chrono::Utc>>>(idx)
-                .map(Value::DateTime)
-                .unwrap_or(Value::Null)
-        }
-        Type::TIMESTAMP => {
-            row.get::<_, Option<chrono::NaiveDateTime>>(idx)
-                .map(|dt| Value::DateTime(chrono::DateTime::from_naive_utc_and_offset(dt, chrono::Utc)))
-                .unwrap_or(Value::Null)
-        }
-        Type::DATE => row.get::<_, Option<chrono::NaiveDate>>(idx).map(Value::Date).unwrap_or(Value::Null),
-        Type::TIME => row.get::<_, Option<chrono::NaiveTime>>(idx).map(Value::Time).unwrap_or(Value::Null),
-        Type::JSON | Type::JSONB => {
-            row.get::<_, Option<serde_json::Value>>(idx).map(Value::Json).unwrap_or(Value::Null)
-        }
+        Type::BOOL => row
+            .get::<_, Option<bool>>(idx)
+            .map(Value::Bool)
+            .unwrap_or(Value::Null),
+        Type::INT2 => row
+            .get::<_, Option<i16>>(idx)
+            .map(|i| Value::Int32(i as i32))
+            .unwrap_or(Value::Null),
+        Type::INT4 => row
+            .get::<_, Option<i32>>(idx)
+            .map(Value::Int32)
+            .unwrap_or(Value::Null),
+        Type::INT8 => row
+            .get::<_, Option<i64>>(idx)
+            .map(Value::Int64)
+            .unwrap_or(Value::Null),
+        Type::FLOAT4 => row
+            .get::<_, Option<f32>>(idx)
+            .map(|f| Value::Float64(f as f64))
+            .unwrap_or(Value::Null),
+        Type::FLOAT8 => row
+            .get::<_, Option<f64>>(idx)
+            .map(Value::Float64)
+            .unwrap_or(Value::Null),
+        Type::NUMERIC => row
+            .get::<_, Option<rust_decimal::Decimal>>(idx)
+            .map(Value::Decimal)
+            .unwrap_or(Value::Null),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => row
+            .get::<_, Option<String>>(idx)
+            .map(Value::String)
+            .unwrap_or(Value::Null),
+        Type::BYTEA => row
+            .get::<_, Option<Vec<u8>>>(idx)
+            .map(Value::Bytes)
+            .unwrap_or(Value::Null),
+        Type::UUID => row
+            .get::<_, Option<uuid::Uuid>>(idx)
+            .map(Value::Uuid)
+            .unwrap_or(Value::Null),
+        Type::TIMESTAMPTZ => row
+            .get::<_, Option<chrono::DateTime<chrono::Utc>>>(idx)
+            .map(Value::DateTime)
+            .unwrap_or(Value::Null),
+        Type::TIMESTAMP => row
+            .get::<_, Option<chrono::NaiveDateTime>>(idx)
+            .map(|dt| Value::DateTime(chrono::DateTime::from_naive_utc_and_offset(dt, chrono::Utc)))
+            .unwrap_or(Value::Null),
+        Type::DATE => row
+            .get::<_, Option<chrono::NaiveDate>>(idx)
+            .map(Value::Date)
+            .unwrap_or(Value::Null),
+        Type::TIME => row
+            .get::<_, Option<chrono::NaiveTime>>(idx)
+            .map(Value::Time)
+            .unwrap_or(Value::Null),
+        // An inet holding a single host is an address, otherwise a network
+        Type::INET => row
+            .get::<_, Option<Inet>>(idx)
+            .map(|Inet(net)| {
+                if net.is_host() {
+                    Value::IpAddr(net.addr())
+                } else {
+                    Value::Cidr(net)
+                }
+            })
+            .unwrap_or(Value::Null),
+        Type::CIDR => row
+            .get::<_, Option<Inet>>(idx)
+            .map(|Inet(net)| Value::Cidr(net))
+            .unwrap_or(Value::Null),
+        Type::MACADDR => row
+            .get::<_, Option<Mac>>(idx)
+            .map(|Mac(m)| Value::MacAddr(m))
+            .unwrap_or(Value::Null),
+        Type::JSON | Type::JSONB => row
+            .get::<_, Option<serde_json::Value>>(idx)
+            .map(Value::Json)
+            .unwrap_or(Value::Null),
+        _ if matches!(col_type.kind(), Kind::Range(_)) => row
+            .get::<_, Option<PgRange>>(idx)
+            .map(|PgRange(range)| Value::Range(Box::new(range)))
+            .unwrap_or(Value::Null),
+        _ if matches!(col_type.kind(), Kind::Enum(_)) => row
+            .get::<_, Option<Label>>(idx)
+            .map(|l| Value::String(l.0))
+            .unwrap_or(Value::Null),
         _ => {
             // Try to get as string
-            row.get::<_, Option<String>>(idx).map(Value::String).unwrap_or(Value::Null)
+            row.get::<_, Option<String>>(idx)
+                .map(Value::String)
+                .unwrap_or(Value::Null)
         }
     }
 }
@@ -104,4 +473,116 @@ mod tests {
         let _param = to_postgres_param(&val);
         // Just verify it doesn't panic
     }
+
+    #[test]
+    fn test_typed_array() {
+        let ids = typed_array(&[Value::Int64(1), Value::Null, Value::Int64(3)]).unwrap();
+        assert_eq!(format!("{:?}", ids), "[Some(1), None, Some(3)]");
+
+        assert!(typed_array(&[Value::Int64(1), Value::String("a".into())]).is_none());
+        assert!(typed_array(&[Value::Null]).is_none());
+    }
+
+    #[test]
+    fn test_enum_labels() {
+        let status = Type::new(
+            "order_status".into(),
+            90001,
+            Kind::Enum(vec!["pending".into(), "shipped".into()]),
+            "public".into(),
+        );
+        assert!(<Text as ToSql>::accepts(&status));
+        assert!(<Text as ToSql>::accepts(&Type::VARCHAR));
+        assert!(!<Text as ToSql>::accepts(&Type::INT8));
+
+        let mut out = BytesMut::new();
+        Text("shipped".into())
+            .to_sql_checked(&status, &mut out)
+            .unwrap();
+        assert_eq!(&out[..], b"shipped");
+        assert_eq!(Label::from_sql(&status, b"pending").unwrap().0, "pending");
+        assert!(!<Label as FromSql>::accepts(&Type::TEXT));
+    }
+
+    #[test]
+    fn test_network_codecs() {
+        let net: Cidr = "192.168.0.0/16".parse().unwrap();
+        let mut out = BytesMut::new();
+        Inet(net).to_sql_checked(&Type::CIDR, &mut out).unwrap();
+        assert_eq!(&out[..], [PGSQL_AF_INET, 16, 1, 4, 192, 168, 0, 0]);
+        assert_eq!(Inet::from_sql(&Type::CIDR, &out).unwrap().0, net);
+
+        let host: Cidr = "2001:db8::1".parse().unwrap();
+        let mut out = BytesMut::new();
+        Inet(host).to_sql_checked(&Type::INET, &mut out).unwrap();
+        assert_eq!(&out[..4], [PGSQL_AF_INET6, 128, 0, 16]);
+        assert_eq!(Inet::from_sql(&Type::INET, &out).unwrap().0, host);
+        assert!(Inet::from_sql(&Type::INET, &[PGSQL_AF_INET, 32, 0, 4, 10]).is_err());
+
+        let mac: MacAddr = "08:00:2b:01:02:03".parse().unwrap();
+        let mut out = BytesMut::new();
+        Mac(mac).to_sql_checked(&Type::MACADDR, &mut out).unwrap();
+        assert_eq!(Mac::from_sql(&Type::MACADDR, &out).unwrap().0, mac);
+        assert!(!<Mac as ToSql>::accepts(&Type::TEXT));
+    }
+
+    #[test]
+    fn test_range_codec() {
+        // [1,10) of int4range, bound from i64 values
+        let range = Range::from(1i64..10).map(Value::Int64);
+        let mut out = BytesMut::new();
+        PgRange(range)
+            .to_sql_checked(&Type::INT4_RANGE, &mut out)
+            .unwrap();
+        assert_eq!(
+            &out[..],
+            [
+                RANGE_LB_INC,
+                0,
+                0,
+                0,
+                4,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                4,
+                0,
+                0,
+                0,
+                10
+            ]
+        );
+        let decoded = PgRange::from_sql(&Type::INT4_RANGE, &out).unwrap().0;
+        assert_eq!(decoded, Range::from(1..10).map(Value::Int32));
+
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let range = Range::new(Bound::Excluded(Value::Date(day)), Bound::Unbounded);
+        let mut out = BytesMut::new();
+        PgRange(range.clone())
+            .to_sql_checked(&Type::DATE_RANGE, &mut out)
+            .unwrap();
+        assert_eq!(out[0], RANGE_UB_INF);
+        assert_eq!(PgRange::from_sql(&Type::DATE_RANGE, &out).unwrap().0, range);
+
+        let mut out = BytesMut::new();
+        PgRange(Range::empty())
+            .to_sql_checked(&Type::TSTZ_RANGE, &mut out)
+            .unwrap();
+        assert_eq!(&out[..], [RANGE_EMPTY]);
+        assert!(PgRange::from_sql(&Type::TSTZ_RANGE, &out)
+            .unwrap()
+            .0
+            .is_empty());
+
+        assert!(PgRange::from_sql(
+            &Type::INT4_RANGE,
+            &[RANGE_LB_INC | RANGE_UB_INF, 0, 0, 0, 4, 0]
+        )
+        .is_err());
+        assert!(!<PgRange as ToSql>::accepts(&Type::INT4));
+    }
 }
@@ -0,0 +1,651 @@
+//! Code generation from schemas
+//!
+//! This module renders Rust models and TypeScript types from a `Schema`,
+//! and records what was generated in a manifest so that tables whose
+//! definition has not changed are not rewritten on the next run.
+//!
+//! User code placed between `// <chakra:keep NAME>` and `// </chakra:keep>`
+//! markers survives regeneration; other edits are carried over with a
+//! three-way merge against the previously generated output.
+
+use crate::schema::{ColumnType, ConstraintType, Schema, Table};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+/// Version of the generator output, part of every fingerprint
+pub const GENERATOR_VERSION: u32 = 2;
+
+const KEEP_START: &str = "// <chakra:keep";
+const KEEP_END: &str = "// </chakra:keep>";
+
+/// Options for generated Rust models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelOptions {
+    /// Derive `Debug`
+    pub derive_debug: bool,
+    /// Derive `Clone`
+    pub derive_clone: bool,
+    /// Derive `Serialize` and `Deserialize`
+    pub derive_serialize: bool,
+}
+
+impl Default for ModelOptions {
+    fn default() -> Self {
+        Self {
+            derive_debug: true,
+            derive_clone: true,
+            derive_serialize: true,
+        }
+    }
+}
+
+/// Records the fingerprint of every generated file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationManifest {
+    /// Generator version that wrote the manifest
+    pub version: u32,
+    /// Fingerprints keyed by generated file path
+    pub files: BTreeMap<String, String>,
+}
+
+impl GenerationManifest {
+    /// Load a manifest, returning an empty one if the file does not exist
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the manifest
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let manifest = Self {
+            version: GENERATOR_VERSION,
+            files: self.files.clone(),
+        };
+        let content = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, content + "\n")
+    }
+
+    /// Check whether a file was generated from the same input
+    pub fn is_current(&self, file: &str, fingerprint: &str) -> bool {
+        self.files.get(file).map(String::as_str) == Some(fingerprint)
+    }
+
+    /// Record the fingerprint of a generated file
+    pub fn record(&mut self, file: impl Into<String>, fingerprint: impl Into<String>) {
+        self.files.insert(file.into(), fingerprint.into());
+    }
+}
+
+impl Schema {
+    /// Serialize the schema as a snapshot with a stable key order
+    pub fn to_snapshot(&self) -> String {
+        let value = serde_json::to_value(self).expect("schema serializes to JSON");
+        serde_json::to_string_pretty(&value).expect("JSON value serializes") + "\n"
+    }
+
+    /// Load a schema from a snapshot
+    pub fn from_snapshot(snapshot: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(snapshot)
+    }
+}
+
+/// Compute a stable fingerprint of a value
+///
+/// The value is hashed through its canonical JSON form (sorted keys) with
+/// FNV-1a, so fingerprints are stable across runs and toolchains.
+pub fn fingerprint<T: Serialize + ?Sized>(value: &T) -> String {
+    let json = serde_json::to_value(value)
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in GENERATOR_VERSION
+        .to_le_bytes()
+        .iter()
+        .chain(json.as_bytes())
+    {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Render a Rust model for a table
+pub fn rust_model(table: &Table, options: &ModelOptions) -> String {
+    let mut out = String::new();
+    let pk_columns: &[String] = table
+        .primary_key
+        .as_ref()
+        .map(|pk| pk.columns.as_slice())
+        .unwrap_or(&[]);
+
+    let _ = writeln!(out, "//! Model for the `{}` table", table.name);
+    out.push_str("//!\n//! Generated by `chakra generate models`.\n\n");
+    out.push_str("use chakra_core::prelude::*;\n");
+    if options.derive_serialize {
+        out.push_str("use serde::{Deserialize, Serialize};\n");
+    }
+    out.push('\n');
+
+    if let Some(ref comment) = table.comment {
+        let _ = writeln!(out, "/// {}", comment);
+    }
+
+    let mut derives = Vec::new();
+    if options.derive_debug {
+        derives.push("Debug");
+    }
+    if options.derive_clone {
+        derives.push("Clone");
+    }
+    if options.derive_serialize {
+        derives.extend(["Serialize", "Deserialize"]);
+    }
+    derives.push("Model");
+    keep_region(&mut out, "attrs");
+    let _ = writeln!(out, "#[derive({})]", derives.join(", "));
+
+    match table.schema {
+        Some(ref schema) => {
+            let _ = writeln!(
+                out,
+                "#[chakra(table = \"{}\", schema = \"{}\")]",
+                table.name, schema
+            );
+        }
+        None => {
+            let _ = writeln!(out, "#[chakra(table = \"{}\")]", table.name);
+        }
+    }
+    let _ = writeln!(out, "pub struct {} {{", to_pascal_case(&table.name));
+
+    for column in &table.columns {
+        let primary_key = pk_columns.contains(&column.name);
+        let field = field_name(&column.name);
+
+        let mut attrs = Vec::new();
+        if primary_key {
+            attrs.push("primary_key".to_string());
+        }
+        if column.auto_increment
+            || matches!(
+                column.column_type,
+                ColumnType::Serial | ColumnType::BigSerial
+            )
+        {
+            attrs.push("auto_increment".to_string());
+        }
+        if field != column.name {
+            attrs.push(format!("column = \"{}\"", column.name));
+        }
+        if is_unique(table, &column.name) {
+            attrs.push("unique".to_string());
+        }
+        if let Some(fk) = table
+            .foreign_keys
+            .iter()
+            .find(|fk| fk.columns.len() == 1 && fk.columns[0] == column.name)
+        {
+            attrs.push(format!(
+                "references = \"{}.{}\"",
+                fk.references_table,
+                fk.references_columns
+                    .first()
+                    .map(String::as_str)
+                    .unwrap_or("id")
+            ));
+        }
+
+        if let Some(ref comment) = column.comment {
+            let _ = writeln!(out, "    /// {}", comment);
+        }
+        if !attrs.is_empty() {
+            let _ = writeln!(out, "    #[chakra({})]", attrs.join(", "));
+        }
+
+        let ty = rust_type(&column.column_type);
+        if column.nullable && !primary_key {
+            let _ = writeln!(out, "    pub {}: Option<{}>,", field, ty);
+        } else {
+            let _ = writeln!(out, "    pub {}: {},", field, ty);
+        }
+    }
+
+    out.push_str("}\n\n");
+    keep_region(&mut out, "impl");
+    out
+}
+
+/// Render the `mod.rs` declaring one module per table
+pub fn rust_models_mod(tables: &[&Table]) -> String {
+    let mut out =
+        String::from("//! Database models\n//!\n//! Generated by `chakra generate models`.\n\n");
+    for table in tables {
+        let _ = writeln!(out, "pub mod {};", module_name(&table.name));
+    }
+    out.push('\n');
+    for table in tables {
+        let _ = writeln!(
+            out,
+            "pub use {}::{};",
+            module_name(&table.name),
+            to_pascal_case(&table.name)
+        );
+    }
+    out.push('\n');
+    keep_region(&mut out, "custom");
+    out
+}
+
+/// Render TypeScript interfaces for tables
+pub fn typescript_types(tables: &[&Table]) -> String {
+    let mut out = String::from("// Generated by `chakra generate types`.\n");
+
+    for table in tables {
+        out.push('\n');
+        if let Some(ref comment) = table.comment {
+            let _ = writeln!(out, "/** {} */", comment);
+        }
+        let _ = writeln!(out, "export interface {} {{", to_pascal_case(&table.name));
+        for column in &table.columns {
+            let ty = typescript_type(&column.column_type);
+            if column.nullable {
+                let _ = writeln!(out, "  {}: {} | null;", column.name, ty);
+            } else {
+                let _ = writeln!(out, "  {}: {};", column.name, ty);
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    out.push('\n');
+    keep_region(&mut out, "custom");
+    out
+}
+
+/// File name stem used for a table's model module
+pub fn module_name(table: &str) -> String {
+    field_name(&table.to_lowercase())
+}
+
+/// Result of a three-way merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// Merged content, with conflict markers where both sides changed
+    pub content: String,
+    /// Number of conflicting hunks
+    pub conflicts: usize,
+}
+
+/// Extract the bodies of `// <chakra:keep NAME>` regions by name
+pub fn keep_regions(content: &str) -> BTreeMap<String, String> {
+    let mut regions = BTreeMap::new();
+    let mut current: Option<(String, String)> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some((name, body)) = current.take() {
+            if trimmed == KEEP_END {
+                regions.insert(name, body);
+            } else {
+                current = Some((name, body + line));
+            }
+        } else if let Some(name) = keep_region_name(trimmed) {
+            current = Some((name.to_string(), String::new()));
+        }
+    }
+
+    regions
+}
+
+/// Copy keep region bodies from `existing` into freshly generated content
+///
+/// Regions are matched by name; regions the template does not declare are
+/// left to the three-way merge.
+pub fn preserve_regions(generated: &str, existing: &str) -> String {
+    let regions = keep_regions(existing);
+    let mut out = String::with_capacity(generated.len());
+    let mut skipping = false;
+
+    for line in generated.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if skipping {
+            if trimmed != KEEP_END {
+                continue;
+            }
+            skipping = false;
+        } else if let Some(body) = keep_region_name(trimmed).and_then(|n| regions.get(n)) {
+            out.push_str(line);
+            out.push_str(body);
+            skipping = true;
+            continue;
+        }
+        out.push_str(line);
+    }
+
+    out
+}
+
+/// Merge edits in `ours` and `theirs` relative to their common `base`
+///
+/// Hunks changed on only one side take that side; hunks changed on both
+/// sides are written with `<<<<<<<`/`>>>>>>>` markers.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let ours_matches = match_lines(&base, &ours);
+    let theirs_matches = match_lines(&base, &theirs);
+
+    // Base lines kept unchanged on both sides anchor the merge
+    let anchors = (0..base.len())
+        .filter_map(|i| Some((i, ours_matches[i]?, theirs_matches[i]?)))
+        .chain(std::iter::once((base.len(), ours.len(), theirs.len())));
+
+    let mut content = String::new();
+    let mut conflicts = 0;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+
+    for (bi, oi, ti) in anchors {
+        let (base_hunk, ours_hunk, theirs_hunk) = (&base[b..bi], &ours[o..oi], &theirs[t..ti]);
+
+        if ours_hunk == base_hunk {
+            content.extend(theirs_hunk.iter().copied());
+        } else if theirs_hunk == base_hunk || ours_hunk == theirs_hunk {
+            content.extend(ours_hunk.iter().copied());
+        } else {
+            conflicts += 1;
+            content.push_str("<<<<<<< local\n");
+            push_hunk(&mut content, ours_hunk);
+            content.push_str("=======\n");
+            push_hunk(&mut content, theirs_hunk);
+            content.push_str(">>>>>>> generated\n");
+        }
+
+        if bi < base.len() {
+            content.push_str(base[bi]);
+        }
+        (b, o, t) = (bi + 1, oi + 1, ti + 1);
+    }
+
+    MergeResult { content, conflicts }
+}
+
+fn keep_region(out: &mut String, name: &str) {
+    let _ = writeln!(out, "{} {}>", KEEP_START, name);
+    let _ = writeln!(out, "{}", KEEP_END);
+}
+
+fn keep_region_name(line: &str) -> Option<&str> {
+    line.strip_prefix(KEEP_START)?
+        .strip_suffix('>')
+        .map(str::trim)
+}
+
+fn push_hunk(out: &mut String, hunk: &[&str]) {
+    for line in hunk {
+        out.push_str(line);
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Match lines of `b` to lines of `a` along a longest common subsequence
+fn match_lines(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    for (i, m) in matches.iter_mut().enumerate().take(prefix) {
+        *m = Some(i);
+    }
+    for k in 0..suffix {
+        matches[a.len() - 1 - k] = Some(b.len() - 1 - k);
+    }
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    let (n, m) = (a_mid.len(), b_mid.len());
+    let width = m + 1;
+    let mut lcs = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * width + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a_mid[i] == b_mid[j] {
+            matches[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches
+}
+
+fn is_unique(table: &Table, column: &str) -> bool {
+    let single = |columns: &[String]| columns.len() == 1 && columns[0] == column;
+
+    table.constraints.iter().any(|c| match c.constraint_type {
+        ConstraintType::Unique { ref columns } => single(columns),
+        _ => false,
+    }) || table.indexes.iter().any(|i| {
+        i.unique && i.where_clause.is_none() && i.columns.len() == 1 && i.columns[0].name == column
+    })
+}
+
+fn rust_type(column_type: &ColumnType) -> String {
+    match column_type {
+        ColumnType::SmallInt => "i16".to_string(),
+        ColumnType::Integer | ColumnType::Serial => "i32".to_string(),
+        ColumnType::BigInt | ColumnType::BigSerial => "i64".to_string(),
+        ColumnType::Decimal { .. } => "rust_decimal::Decimal".to_string(),
+        ColumnType::Real => "f32".to_string(),
+        ColumnType::DoublePrecision => "f64".to_string(),
+        ColumnType::Char(_) | ColumnType::Varchar(_) | ColumnType::Text => "String".to_string(),
+        ColumnType::Boolean => "bool".to_string(),
+        ColumnType::Date => "chrono::NaiveDate".to_string(),
+        ColumnType::Time { .. } => "chrono::NaiveTime".to_string(),
+        ColumnType::Timestamp {
+            with_timezone: true,
+        } => "chrono::DateTime<chrono::Utc>".to_string(),
+        ColumnType::Timestamp {
+            with_timezone: false,
+        } => "chrono::NaiveDateTime".to_string(),
+        ColumnType::Interval | ColumnType::Custom(_) | ColumnType::Enum { .. } => {
+            "String".to_string()
+        }
+        ColumnType::Uuid => "uuid::Uuid".to_string(),
+        ColumnType::Json | ColumnType::Jsonb => "serde_json::Value".to_string(),
+        ColumnType::Bytea => "Vec<u8>".to_string(),
+        ColumnType::Inet => "std::net::IpAddr".to_string(),
+        ColumnType::Cidr => "chakra_core::net::Cidr".to_string(),
+        ColumnType::MacAddr => "chakra_core::net::MacAddr".to_string(),
+        ColumnType::Array(inner) => format!("Vec<{}>", rust_type(inner)),
+        ColumnType::Range(inner) => format!("chakra_core::range::Range<{}>", rust_type(inner)),
+    }
+}
+
+fn typescript_type(column_type: &ColumnType) -> String {
+    match column_type {
+        ColumnType::SmallInt
+        | ColumnType::Integer
+        | ColumnType::Serial
+        | ColumnType::Real
+        | ColumnType::DoublePrecision => "number".to_string(),
+        // 64-bit integers and decimals lose precision as JS numbers
+        ColumnType::BigInt | ColumnType::BigSerial | ColumnType::Decimal { .. } => {
+            "string".to_string()
+        }
+        ColumnType::Boolean => "boolean".to_string(),
+        ColumnType::Json | ColumnType::Jsonb => "unknown".to_string(),
+        ColumnType::Array(inner) => match typescript_type(inner) {
+            union if union.contains(" | ") => format!("({})[]", union),
+            element => format!("{}[]", element),
+        },
+        ColumnType::Enum { values, .. } => {
+            let labels: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+            labels.join(" | ")
+        }
+        _ => "string".to_string(),
+    }
+}
+
+fn to_pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn field_name(column: &str) -> String {
+    let mut name: String = column
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+    name
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Column, ForeignKey, PrimaryKey};
+
+    fn orders() -> Table {
+        let mut table = Table::new("order_items")
+            .column(Column::new("id", ColumnType::BigSerial).not_null())
+            .column(Column::new("order_id", ColumnType::BigInt).not_null())
+            .column(Column::new("type", ColumnType::Varchar(Some(20))))
+            .primary_key(PrimaryKey::single("id"));
+        table.add_foreign_key(ForeignKey::new(
+            vec!["order_id".into()],
+            "orders",
+            vec!["id".into()],
+        ));
+        table
+    }
+
+    #[test]
+    fn test_rust_model() {
+        let code = rust_model(&orders(), &ModelOptions::default());
+
+        assert!(code.contains("#[derive(Debug, Clone, Serialize, Deserialize, Model)]"));
+        assert!(code.contains("#[chakra(table = \"order_items\")]\npub struct OrderItems {"));
+        assert!(code.contains("    #[chakra(primary_key, auto_increment)]\n    pub id: i64,"));
+        assert!(code.contains("    #[chakra(references = \"orders.id\")]\n    pub order_id: i64,"));
+        assert!(code.contains("    #[chakra(column = \"type\")]\n    pub type_: Option<String>,"));
+    }
+
+    #[test]
+    fn test_typescript_types() {
+        let table = orders();
+        let code = typescript_types(&[&table]);
+        assert!(code.contains("export interface OrderItems {\n  id: string;\n"));
+        assert!(code.contains("  type: string | null;\n"));
+    }
+
+    #[test]
+    fn test_manifest_fingerprints() {
+        let table = orders();
+        let mut manifest = GenerationManifest::default();
+        manifest.record("order_items.rs", fingerprint(&table));
+
+        let manifest: GenerationManifest =
+            serde_json::from_str(&serde_json::to_string(&manifest).unwrap()).unwrap();
+        assert!(manifest.is_current("order_items.rs", &fingerprint(&table)));
+
+        let changed = table.column(Column::new("note", ColumnType::Text));
+        assert!(!manifest.is_current("order_items.rs", &fingerprint(&changed)));
+
+        let mut schema = Schema::with_name("public");
+        schema.add_table(changed);
+        let snapshot = schema.to_snapshot();
+        assert_eq!(
+            Schema::from_snapshot(&snapshot).unwrap().to_snapshot(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn test_preserve_keep_regions() {
+        let options = ModelOptions::default();
+        let old = rust_model(&orders(), &options).replace(
+            "// <chakra:keep impl>\n",
+            "// <chakra:keep impl>\nimpl OrderItems {\n    fn total(&self) {}\n}\n",
+        );
+
+        let table = orders().column(Column::new("note", ColumnType::Text));
+        let regenerated = preserve_regions(&rust_model(&table, &options), &old);
+        assert!(regenerated.contains("    pub note: Option<String>,"));
+        assert!(regenerated.contains(
+            "// <chakra:keep impl>\nimpl OrderItems {\n    fn total(&self) {}\n}\n// </chakra:keep>\n"
+        ));
+        assert_eq!(keep_regions(&regenerated).len(), 2);
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nB\nc\nd\n";
+        let theirs = "a\nb\nc\nd\ne\n";
+        let merged = merge3(base, ours, theirs);
+        assert_eq!(merged.content, "a\nB\nc\nd\ne\n");
+        assert_eq!(merged.conflicts, 0);
+
+        let merged = merge3(base, ours, "a\nX\nc\nd\n");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.content,
+            "a\n<<<<<<< local\nB\n=======\nX\n>>>>>>> generated\nc\nd\n"
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Row-level data comparison between databases
+//!
+//! This module compares table contents by primary key and produces the
+//! INSERT/UPDATE/DELETE statements needed to reconcile a target with a source.
+//! Large tables are compared in primary-key ordered chunks. Where both
+//! databases can hash rows themselves, each chunk is checksummed on the
+//! server and only the rows of chunks whose checksums differ are fetched.
+
+use chakra_core::result::Row;
+use chakra_core::sql::{Dialect, SqlFragment};
+use chakra_core::types::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// An update needed to bring a target row in line with the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowUpdate {
+    /// Primary key values of the row
+    pub key: Vec<Value>,
+    /// Changed columns and their source values
+    pub changes: Vec<(String, Value)>,
+}
+
+/// Differences between the contents of one table in two databases
+#[derive(Debug, Clone)]
+pub struct TableDataDiff {
+    /// Table name
+    pub table: String,
+    /// Primary key columns used to match rows
+    pub primary_key: Vec<String>,
+    /// Rows present in the source but missing from the target
+    pub inserts: Vec<Row>,
+    /// Rows present in both but with differing values
+    pub updates: Vec<RowUpdate>,
+    /// Keys of rows present in the target but missing from the source
+    pub deletes: Vec<Vec<Value>>,
+}
+
+impl TableDataDiff {
+    /// Create an empty diff for a table
+    pub fn new(table: impl Into<String>, primary_key: Vec<String>) -> Self {
+        Self {
+            table: table.into(),
+            primary_key,
+            inserts: Vec::new(),
+            updates: Vec::new(),
+            deletes: Vec::new(),
+        }
+    }
+
+    /// Check if the tables are identical
+    pub fn is_empty(&self) -> bool {
+        self.inserts.is_empty() && self.updates.is_empty() && self.deletes.is_empty()
+    }
+
+    /// Total number of row changes
+    pub fn change_count(&self) -> usize {
+        self.inserts.len() + self.updates.len() + self.deletes.len()
+    }
+
+    /// Compare source and target rows by primary key
+    pub fn compare_rows(&mut self, source: &[Row], target: &[Row]) {
+        let target_by_key: BTreeMap<String, &Row> = target
+            .iter()
+            .map(|row| (key_string(&row_key(row, &self.primary_key)), row))
+            .collect();
+        let mut seen = HashSet::with_capacity(source.len());
+
+        for row in source {
+            let key = row_key(row, &self.primary_key);
+            let key_str = key_string(&key);
+
+            match target_by_key.get(&key_str) {
+                None => self.inserts.push(row.clone()),
+                Some(existing) => {
+                    let changes: Vec<(String, Value)> = row
+                        .columns()
+                        .iter()
+                        .filter(|col| !self.primary_key.contains(col))
+                        .filter_map(|col| {
+                            let value = row.get(col).unwrap_or(&Value::Null);
+                            let other = existing.get(col).unwrap_or(&Value::Null);
+                            (!same_value(value, other)).then(|| (col.clone(), value.clone()))
+                        })
+                        .collect();
+
+                    if !changes.is_empty() {
+                        self.updates.push(RowUpdate { key, changes });
+                    }
+                }
+            }
+            seen.insert(key_str);
+        }
+
+        for (key_str, row) in &target_by_key {
+            if !seen.contains(key_str) {
+                self.deletes.push(row_key(row, &self.primary_key));
+            }
+        }
+    }
+
+    /// Generate the reconciliation statements for a dialect
+    pub fn to_sql(&self, dialect: &dyn Dialect) -> Vec<String> {
+        let table = dialect.quote_identifier(&self.table);
+        let mut statements = Vec::with_capacity(self.change_count());
+
+        for key in &self.deletes {
+            statements.push(format!(
+                "DELETE FROM {} WHERE {};",
+                table,
+                self.key_condition(dialect, key)
+            ));
+        }
+
+        for update in &self.updates {
+            let assignments: Vec<String> = update
+                .changes
+                .iter()
+                .map(|(col, value)| {
+                    format!(
+                        "{} = {}",
+                        dialect.quote_identifier(col),
+                        dialect.literal(value)
+                    )
+                })
+                .collect();
+            statements.push(format!(
+                "UPDATE {} SET {} WHERE {};",
+                table,
+                assignments.join(", "),
+                self.key_condition(dialect, &update.key)
+            ));
+        }
+
+        for row in &self.inserts {
+            let columns: Vec<String> = row
+                .columns()
+                .iter()
+                .map(|c| dialect.quote_identifier(c))
+                .collect();
+            let values: Vec<String> = row
+                .columns()
+                .iter()
+                .map(|c| dialect.literal(row.get(c).unwrap_or(&Value::Null)))
+                .collect();
+            statements.push(format!(
+                "INSERT INTO {} ({}) VALUES ({});",
+                table,
+                columns.join(", "),
+                values.join(", ")
+            ));
+        }
+
+        statements
+    }
+
+    fn key_condition(&self, dialect: &dyn Dialect, key: &[Value]) -> String {
+        self.primary_key
+            .iter()
+            .zip(key)
+            .map(|(col, value)| {
+                format!(
+                    "{} = {}",
+                    dialect.quote_identifier(col),
+                    dialect.literal(value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+}
+
+/// Extract the primary key values of a row
+pub fn row_key(row: &Row, primary_key: &[String]) -> Vec<Value> {
+    primary_key
+        .iter()
+        .map(|col| row.get(col).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+fn key_string(key: &[Value]) -> String {
+    key.iter()
+        .map(|v| normalize(v).to_sql_literal())
+        .collect::<Vec<_>>()
+        .join("\u{1f}")
+}
+
+/// Whether two values read from different databases hold the same data
+///
+/// Drivers decode the same column differently, e.g. an `INTEGER` as
+/// `Int32` on one side and `Int64` on the other, or a boolean as `0`/`1`
+/// on SQLite and MySQL, so values are normalized before comparing.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (normalize(a), normalize(b)) {
+        (Value::Float64(x), Value::Float64(y)) => x == y || (x.is_nan() && y.is_nan()),
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(&y).all(|(a, b)| same_value(a, b))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => Value::Int64(i64::from(*b)),
+        Value::Int32(i) => Value::Int64(i64::from(*i)),
+        _ => value.clone(),
+    }
+}
+
+/// Build a primary-key ordered SELECT for one chunk of a table
+///
+/// Selects rows with a key strictly greater than `after` and at most `upto`,
+/// either bound being optional.
+pub fn chunk_query(
+    dialect: &dyn Dialect,
+    table: &str,
+    primary_key: &[String],
+    after: Option<&[Value]>,
+    upto: Option<&[Value]>,
+    limit: Option<usize>,
+) -> SqlFragment {
+    let mut fragment = range_query(dialect, "*", table, primary_key, after, upto);
+    if let Some(limit) = limit {
+        fragment.push_sql(&format!(" LIMIT {}", limit));
+    }
+    fragment
+}
+
+/// Build a SELECT of the key of the last row in the chunk of `size` rows
+/// after `after`
+///
+/// Only the keys are read, so chunk bounds are found without fetching rows.
+/// It returns no row if fewer than `size` rows remain.
+pub fn boundary_query(
+    dialect: &dyn Dialect,
+    table: &str,
+    primary_key: &[String],
+    after: Option<&[Value]>,
+    size: usize,
+) -> SqlFragment {
+    let columns: Vec<String> = primary_key
+        .iter()
+        .map(|c| dialect.quote_identifier(c))
+        .collect();
+    let mut fragment = range_query(
+        dialect,
+        &columns.join(", "),
+        table,
+        primary_key,
+        after,
+        None,
+    );
+    fragment.push_sql(&format!(" LIMIT 1 OFFSET {}", size.saturating_sub(1)));
+    fragment
+}
+
+/// Build a query computing the checksum of one chunk on the server, or
+/// `None` if the dialect can't hash rows
+///
+/// `columns` are selected explicitly so both sides hash them in the same
+/// order; see `chakra_core::checksum` for reading the result.
+pub fn chunk_checksum_query(
+    dialect: &dyn Dialect,
+    table: &str,
+    primary_key: &[String],
+    columns: &[String],
+    after: Option<&[Value]>,
+    upto: Option<&[Value]>,
+) -> Option<SqlFragment> {
+    let select: Vec<String> = columns
+        .iter()
+        .map(|c| dialect.quote_identifier(c))
+        .collect();
+    let chunk = range_query(dialect, &select.join(", "), table, primary_key, after, upto);
+    let sql = dialect.checksum_sql(&chunk.sql, columns)?;
+    Some(SqlFragment::with_params(sql, chunk.params))
+}
+
+fn range_query(
+    dialect: &dyn Dialect,
+    select: &str,
+    table: &str,
+    primary_key: &[String],
+    after: Option<&[Value]>,
+    upto: Option<&[Value]>,
+) -> SqlFragment {
+    let key_columns: Vec<String> = primary_key
+        .iter()
+        .map(|c| dialect.quote_identifier(c))
+        .collect();
+    let key_expr = if key_columns.len() == 1 {
+        key_columns[0].clone()
+    } else {
+        format!("({})", key_columns.join(", "))
+    };
+
+    let mut fragment = SqlFragment::from_sql(format!(
+        "SELECT {} FROM {}",
+        select,
+        dialect.quote_identifier(table)
+    ));
+
+    let mut conditions = Vec::new();
+    for (op, bound) in [(">", after), ("<=", upto)] {
+        if let Some(values) = bound {
+            let placeholders: Vec<String> = values
+                .iter()
+                .map(|v| dialect.placeholder(fragment.push_param(v.clone())))
+                .collect();
+            let rhs = if placeholders.len() == 1 {
+                placeholders[0].clone()
+            } else {
+                format!("({})", placeholders.join(", "))
+            };
+            conditions.push(format!("{} {} {}", key_expr, op, rhs));
+        }
+    }
+
+    if !conditions.is_empty() {
+        fragment.push_sql(" WHERE ");
+        fragment.push_sql(&conditions.join(" AND "));
+    }
+
+    fragment.push_sql(" ORDER BY ");
+    fragment.push_sql(&key_columns.join(", "));
+    fragment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chakra_core::sql::{MySqlDialect, PostgresDialect, SqliteDialect};
+
+    fn row(id: i64, name: &str) -> Row {
+        Row::new(
+            vec!["id".to_string(), "name".to_string()],
+            vec![Value::Int64(id), Value::String(name.to_string())],
+        )
+    }
+
+    #[test]
+    fn test_compare_rows() {
+        let source = vec![row(1, "a"), row(2, "b"), row(3, "c")];
+        let target = vec![
+            row(2, "b"),
+            Row::new(
+                vec!["id".to_string(), "name".to_string()],
+                vec![Value::Int32(3), Value::String("x".to_string())],
+            ),
+            row(4, "d"),
+        ];
+
+        let mut diff = TableDataDiff::new("settings", vec!["id".to_string()]);
+        diff.compare_rows(&source, &target);
+
+        assert_eq!(diff.inserts.len(), 1);
+        assert_eq!(diff.updates.len(), 1);
+        assert_eq!(diff.deletes, vec![vec![Value::Int64(4)]]);
+
+        let sql = diff.to_sql(&PostgresDialect);
+        assert_eq!(
+            sql,
+            vec![
+                r#"DELETE FROM "settings" WHERE "id" = 4;"#,
+                r#"UPDATE "settings" SET "name" = 'c' WHERE "id" = 3;"#,
+                r#"INSERT INTO "settings" ("id", "name") VALUES (1, 'a');"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_rows_normalizes_values() {
+        let columns = vec!["id".to_string(), "active".to_string(), "score".to_string()];
+        let source = vec![Row::new(
+            columns.clone(),
+            vec![Value::Int64(1), Value::Bool(true), Value::Float64(f64::NAN)],
+        )];
+        let target = vec![Row::new(
+            columns.clone(),
+            vec![Value::Int32(1), Value::Int64(1), Value::Float64(f64::NAN)],
+        )];
+
+        let mut diff = TableDataDiff::new("players", vec!["id".to_string()]);
+        diff.compare_rows(&source, &target);
+        assert!(diff.is_empty());
+
+        let target = vec![Row::new(
+            columns,
+            vec![Value::Int32(1), Value::Int64(0), Value::Float64(0.5)],
+        )];
+        let mut diff = TableDataDiff::new("players", vec!["id".to_string()]);
+        diff.compare_rows(&source, &target);
+        assert_eq!(diff.updates.len(), 1);
+        assert_eq!(diff.updates[0].changes.len(), 2);
+    }
+
+    #[test]
+    fn test_chunk_query() {
+        let pk = vec!["tenant".to_string(), "key".to_string()];
+        let after = [Value::Int64(1), Value::from("a")];
+        let fragment = chunk_query(
+            &PostgresDialect,
+            "config",
+            &pk,
+            Some(&after),
+            None,
+            Some(500),
+        );
+
+        assert_eq!(
+            fragment.sql,
+            r#"SELECT * FROM "config" WHERE ("tenant", "key") > ($1, $2) ORDER BY "tenant", "key" LIMIT 500"#
+        );
+        assert_eq!(fragment.params.len(), 2);
+    }
+
+    #[test]
+    fn test_chunk_bounds_and_checksum() {
+        let pk = vec!["id".to_string()];
+        let after = [Value::Int64(1000)];
+
+        let fragment = boundary_query(&PostgresDialect, "orders", &pk, Some(&after), 1000);
+        assert_eq!(
+            fragment.sql,
+            r#"SELECT "id" FROM "orders" WHERE "id" > $1 ORDER BY "id" LIMIT 1 OFFSET 999"#
+        );
+
+        let columns = vec!["id".to_string(), "total".to_string()];
+        let upto = [Value::Int64(2000)];
+        let fragment = chunk_checksum_query(
+            &PostgresDialect,
+            "orders",
+            &pk,
+            &columns,
+            Some(&after),
+            Some(&upto),
+        )
+        .unwrap();
+        assert!(fragment.sql.starts_with("SELECT COUNT(*) AS row_count"));
+        assert!(fragment.sql.ends_with(
+            r#"FROM (SELECT "id", "total" FROM "orders" WHERE "id" > $1 AND "id" <= $2 ORDER BY "id") AS t"#
+        ));
+        assert_eq!(
+            fragment.params,
+            vec![Value::Int64(1000), Value::Int64(2000)]
+        );
+
+        assert!(
+            chunk_checksum_query(&SqliteDialect, "orders", &pk, &columns, None, None).is_none()
+        );
+    }
+
+    #[test]
+    fn test_to_sql_uses_dialect_literals() {
+        let mut diff = TableDataDiff::new("files", vec!["id".to_string()]);
+        diff.inserts.push(Row::new(
+            vec!["id".to_string(), "path".to_string(), "data".to_string()],
+            vec![
+                Value::Int64(1),
+                Value::from("C:\\tmp"),
+                Value::Bytes(vec![0xff]),
+            ],
+        ));
+
+        assert_eq!(
+            diff.to_sql(&PostgresDialect),
+            vec![
+                r#"INSERT INTO "files" ("id", "path", "data") VALUES (1, 'C:\tmp', '\xff'::bytea);"#
+            ]
+        );
+        assert_eq!(
+            diff.to_sql(&MySqlDialect),
+            vec![r#"INSERT INTO `files` (`id`, `path`, `data`) VALUES (1, 'C:\\tmp', X'ff');"#]
+        );
+    }
+}
@@ -3,11 +3,16 @@
 //! This module provides DDL statement generation for schema changes.
 
 use crate::schema::{
-    Column, ColumnDefault, ColumnType, Constraint, ConstraintType, ForeignKey, Index, PrimaryKey,
-    Schema, Table,
+    quote_literal, Column, ColumnType, Constraint, ConstraintType, CustomType, ForeignKey, Grant,
+    Index, Table, View,
 };
+use chakra_core::format::SqlFormatter;
+use chakra_core::identifier;
 use chakra_core::model::ForeignKeyAction;
+use chakra_core::script::ScriptSplitter;
+use chakra_core::sql::{MySqlDialect, PostgresDialect, SqliteDialect};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A DDL statement
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +50,68 @@ impl DdlStatement {
         self.description = Some(desc.into());
         self
     }
+
+    /// How risky running this statement is, judged from its SQL
+    pub fn safety(&self) -> Safety {
+        let sql = self.sql.trim_start().to_uppercase();
+        let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if sql.starts_with("DROP TABLE")
+            || sql.starts_with("TRUNCATE")
+            || sql.starts_with("DELETE")
+            || sql.contains(" DROP COLUMN ")
+        {
+            Safety::DataLoss
+        } else if sql.starts_with("DROP")
+            || sql.starts_with("REVOKE")
+            || sql.starts_with("RENAME")
+            || (sql.starts_with("ALTER TABLE")
+                && [
+                    " TYPE ",
+                    " SET NOT NULL",
+                    " MODIFY COLUMN ",
+                    " RENAME ",
+                    " DROP ",
+                ]
+                .iter()
+                .any(|change| sql.contains(change)))
+        {
+            Safety::Risky
+        } else {
+            Safety::Safe
+        }
+    }
+}
+
+/// How risky a DDL statement is to run against live data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Safety {
+    /// Adds to the schema without touching existing data
+    Safe,
+    /// Can fail on existing data, rewrite or lock the table, or break
+    /// queries, e.g. type changes, renames and dropped indexes
+    Risky,
+    /// Discards data
+    DataLoss,
+}
+
+impl fmt::Display for Safety {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Safety::Safe => "safe",
+            Safety::Risky => "risky",
+            Safety::DataLoss => "data loss",
+        })
+    }
+}
+
+impl fmt::Display for DdlStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref description) = self.description {
+            writeln!(f, "-- {}", description)?;
+        }
+        write!(f, "{};", SqlFormatter::default().format(&self.sql))
+    }
 }
 
 /// DDL generator for different database dialects
@@ -55,6 +122,41 @@ pub trait DdlGenerator: Send + Sync {
     /// Generate DROP TABLE statement
     fn drop_table(&self, table_name: &str, cascade: bool) -> DdlStatement;
 
+    /// Generate CREATE TEMPORARY TABLE, visible only to this session and
+    /// dropped when it ends
+    ///
+    /// Foreign keys are left out, since PostgreSQL lets temporary tables
+    /// reference only other temporary tables.
+    fn create_temp_table(&self, table: &Table) -> DdlStatement {
+        let mut table = table.clone();
+        table.schema = None;
+        table.foreign_keys.clear();
+        let create = self.create_table(&table);
+        let sql = match create.sql.strip_prefix("CREATE TABLE ") {
+            Some(rest) => format!("CREATE TEMPORARY TABLE {}", rest),
+            None => create.sql,
+        };
+        DdlStatement::new(sql)
+            .reversible(self.drop_temp_table(&table.name).sql)
+            .description(format!("Create temporary table {}", table.name))
+    }
+
+    /// Generate DROP of a temporary table, doing nothing if it is gone
+    fn drop_temp_table(&self, table_name: &str) -> DdlStatement {
+        DdlStatement::new(format!(
+            "DROP TABLE IF EXISTS {}",
+            quote_identifier(table_name)
+        ))
+    }
+
+    /// Generate TRUNCATE TABLE statement
+    fn truncate_table(
+        &self,
+        table_name: &str,
+        cascade: bool,
+        restart_identity: bool,
+    ) -> DdlStatement;
+
     /// Generate ALTER TABLE ADD COLUMN statement
     fn add_column(&self, table_name: &str, column: &Column) -> DdlStatement;
 
@@ -87,6 +189,41 @@ pub trait DdlGenerator: Send + Sync {
 
     /// Generate RENAME COLUMN statement
     fn rename_column(&self, table_name: &str, old_name: &str, new_name: &str) -> DdlStatement;
+
+    /// Generate GRANT statement
+    fn grant(&self, table_name: &str, grant: &Grant) -> DdlStatement;
+
+    /// Generate REVOKE statement
+    fn revoke(&self, table_name: &str, grant: &Grant) -> DdlStatement;
+
+    /// Generate ALTER TABLE OWNER TO statement
+    fn alter_owner(&self, table_name: &str, owner: &str) -> DdlStatement;
+
+    /// Generate CREATE VIEW statement
+    fn create_view(&self, view: &View) -> DdlStatement;
+
+    /// Generate DROP VIEW statement
+    fn drop_view(&self, view_name: &str) -> DdlStatement;
+
+    /// Generate CREATE TYPE statement
+    fn create_type(&self, custom_type: &CustomType) -> DdlStatement;
+
+    /// Generate DROP TYPE statement
+    fn drop_type(&self, type_name: &str) -> DdlStatement;
+
+    /// Generate ALTER TYPE ADD VALUE statement, placing `value` after the
+    /// label `after`, or last
+    fn add_enum_value(&self, type_name: &str, value: &str, after: Option<&str>) -> DdlStatement;
+
+    /// Splitter for raw SQL scripts in this dialect
+    fn script_splitter(&self) -> ScriptSplitter {
+        ScriptSplitter::default()
+    }
+
+    /// Whether `create_table` renders the table's foreign keys
+    fn inline_foreign_keys(&self) -> bool {
+        true
+    }
 }
 
 /// PostgreSQL DDL generator
@@ -123,12 +260,18 @@ impl DdlGenerator for PostgresDdlGenerator {
 
         // Constraints
         for constraint in &table.constraints {
-            parts.push(format!("    {}", self.constraint_definition(constraint)));
+            parts.push(format!(
+                "    {}",
+                constraint_definition(constraint, true, quote_identifier)
+            ));
         }
 
         // Foreign keys
         for fk in &table.foreign_keys {
-            parts.push(format!("    {}", self.foreign_key_definition(fk)));
+            parts.push(format!(
+                "    {}",
+                foreign_key_definition(fk, quote_identifier)
+            ));
         }
 
         sql.push_str(&parts.join(",\n"));
@@ -151,6 +294,23 @@ impl DdlGenerator for PostgresDdlGenerator {
         DdlStatement::new(sql).description(format!("Drop table {}", table_name))
     }
 
+    fn truncate_table(
+        &self,
+        table_name: &str,
+        cascade: bool,
+        restart_identity: bool,
+    ) -> DdlStatement {
+        let mut sql = format!("TRUNCATE TABLE {}", quote_identifier(table_name));
+        if restart_identity {
+            sql.push_str(" RESTART IDENTITY");
+        }
+        if cascade {
+            sql.push_str(" CASCADE");
+        }
+
+        DdlStatement::new(sql).description(format!("Truncate table {}", table_name))
+    }
+
     fn add_column(&self, table_name: &str, column: &Column) -> DdlStatement {
         let sql = format!(
             "ALTER TABLE {} ADD COLUMN {}",
@@ -176,7 +336,8 @@ impl DdlGenerator for PostgresDdlGenerator {
             quote_identifier(column_name)
         );
 
-        DdlStatement::new(sql).description(format!("Drop column {} from {}", column_name, table_name))
+        DdlStatement::new(sql)
+            .description(format!("Drop column {} from {}", column_name, table_name))
     }
 
     fn alter_column(&self, table_name: &str, old: &Column, new: &Column) -> Vec<DdlStatement> {
@@ -261,10 +422,7 @@ impl DdlGenerator for PostgresDdlGenerator {
                             column,
                             default.to_sql()
                         ))
-                        .description(format!(
-                            "Set default for {} in {}",
-                            new.name, table_name
-                        )),
+                        .description(format!("Set default for {} in {}", new.name, table_name)),
                     );
                 }
                 None => {
@@ -273,10 +431,7 @@ impl DdlGenerator for PostgresDdlGenerator {
                             "ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT",
                             table, column
                         ))
-                        .description(format!(
-                            "Drop default for {} in {}",
-                            new.name, table_name
-                        )),
+                        .description(format!("Drop default for {} in {}", new.name, table_name)),
                     );
                 }
             }
@@ -308,7 +463,11 @@ impl DdlGenerator for PostgresDdlGenerator {
             .columns
             .iter()
             .map(|c| {
-                let mut col = quote_identifier(&c.name);
+                let mut col = if c.expression {
+                    format!("({})", c.name)
+                } else {
+                    quote_identifier(&c.name)
+                };
                 if let Some(order) = &c.order {
                     col.push_str(match order {
                         crate::schema::IndexOrder::Asc => " ASC",
@@ -327,6 +486,20 @@ impl DdlGenerator for PostgresDdlGenerator {
         sql.push_str(&cols.join(", "));
         sql.push(')');
 
+        if !index.include.is_empty() {
+            let include: Vec<String> = index.include.iter().map(|c| quote_identifier(c)).collect();
+            sql.push_str(&format!(" INCLUDE ({})", include.join(", ")));
+        }
+
+        if !index.storage.is_empty() {
+            let params: Vec<String> = index
+                .storage
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect();
+            sql.push_str(&format!(" WITH ({})", params.join(", ")));
+        }
+
         if let Some(where_clause) = &index.where_clause {
             sql.push_str(" WHERE ");
             sql.push_str(where_clause);
@@ -348,7 +521,7 @@ impl DdlGenerator for PostgresDdlGenerator {
         let sql = format!(
             "ALTER TABLE {} ADD {}",
             quote_identifier(table_name),
-            self.constraint_definition(constraint)
+            constraint_definition(constraint, true, quote_identifier)
         );
 
         let reverse_sql = format!(
@@ -381,7 +554,7 @@ impl DdlGenerator for PostgresDdlGenerator {
         let sql = format!(
             "ALTER TABLE {} ADD {}",
             quote_identifier(table_name),
-            self.foreign_key_definition(fk)
+            foreign_key_definition(fk, quote_identifier)
         );
 
         let fk_name = fk
@@ -409,10 +582,7 @@ impl DdlGenerator for PostgresDdlGenerator {
             quote_identifier(table_name),
             quote_identifier(fk_name)
         ))
-        .description(format!(
-            "Drop foreign key {} from {}",
-            fk_name, table_name
-        ))
+        .description(format!("Drop foreign key {} from {}", fk_name, table_name))
     }
 
     fn rename_table(&self, old_name: &str, new_name: &str) -> DdlStatement {
@@ -447,6 +617,107 @@ impl DdlGenerator for PostgresDdlGenerator {
             old_name, new_name, table_name
         ))
     }
+
+    fn grant(&self, table_name: &str, grant: &Grant) -> DdlStatement {
+        let privileges = grant.privileges.join(", ");
+        let on = format!("ON {} ", quote_identifier(table_name));
+        let grantee = quote_grantee(&grant.grantee);
+        DdlStatement::new(format!("GRANT {} {}TO {}", privileges, on, grantee))
+            .reversible(format!("REVOKE {} {}FROM {}", privileges, on, grantee))
+            .description(format!(
+                "Grant {} on {} to {}",
+                privileges, table_name, grant.grantee
+            ))
+    }
+
+    fn revoke(&self, table_name: &str, grant: &Grant) -> DdlStatement {
+        let privileges = grant.privileges.join(", ");
+        let on = format!("ON {} ", quote_identifier(table_name));
+        let grantee = quote_grantee(&grant.grantee);
+        DdlStatement::new(format!("REVOKE {} {}FROM {}", privileges, on, grantee))
+            .reversible(format!("GRANT {} {}TO {}", privileges, on, grantee))
+            .description(format!(
+                "Revoke {} on {} from {}",
+                privileges, table_name, grant.grantee
+            ))
+    }
+
+    fn alter_owner(&self, table_name: &str, owner: &str) -> DdlStatement {
+        DdlStatement::new(format!(
+            "ALTER TABLE {} OWNER TO {}",
+            quote_identifier(table_name),
+            quote_identifier(owner)
+        ))
+        .description(format!("Set owner of {} to {}", table_name, owner))
+    }
+
+    fn create_view(&self, view: &View) -> DdlStatement {
+        DdlStatement::new(format!(
+            "CREATE VIEW {} AS {}",
+            quote_identifier(&view.name),
+            view.definition.to_sql(&PostgresDialect)
+        ))
+        .reversible(format!("DROP VIEW {}", quote_identifier(&view.name)))
+        .description(format!("Create view {}", view.name))
+    }
+
+    fn drop_view(&self, view_name: &str) -> DdlStatement {
+        DdlStatement::new(format!("DROP VIEW {}", quote_identifier(view_name)))
+            .description(format!("Drop view {}", view_name))
+    }
+
+    fn create_type(&self, custom_type: &CustomType) -> DdlStatement {
+        let definition = match custom_type {
+            CustomType::Enum { values, .. } => {
+                let values: Vec<String> = values.iter().map(|v| quote_literal(v)).collect();
+                format!("ENUM ({})", values.join(", "))
+            }
+            CustomType::Composite { fields, .. } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, column_type)| {
+                        format!(
+                            "{} {}",
+                            quote_identifier(name),
+                            column_type.to_postgres_sql()
+                        )
+                    })
+                    .collect();
+                format!("({})", fields.join(", "))
+            }
+        };
+        let name = custom_type.name();
+        DdlStatement::new(format!(
+            "CREATE TYPE {} AS {}",
+            quote_identifier(name),
+            definition
+        ))
+        .reversible(format!("DROP TYPE {}", quote_identifier(name)))
+        .description(format!("Create type {}", name))
+    }
+
+    fn drop_type(&self, type_name: &str) -> DdlStatement {
+        DdlStatement::new(format!("DROP TYPE {}", quote_identifier(type_name)))
+            .description(format!("Drop type {}", type_name))
+    }
+
+    /// Labels can't be removed again, so this is irreversible
+    fn add_enum_value(&self, type_name: &str, value: &str, after: Option<&str>) -> DdlStatement {
+        let position = after
+            .map(|after| format!(" AFTER {}", quote_literal(after)))
+            .unwrap_or_default();
+        DdlStatement::new(format!(
+            "ALTER TYPE {} ADD VALUE {}{}",
+            quote_identifier(type_name),
+            quote_literal(value),
+            position
+        ))
+        .description(format!("Add {} to type {}", value, type_name))
+    }
+
+    fn script_splitter(&self) -> ScriptSplitter {
+        ScriptSplitter::postgres()
+    }
 }
 
 impl PostgresDdlGenerator {
@@ -460,82 +731,119 @@ impl PostgresDdlGenerator {
             def.push_str(" NOT NULL");
         }
 
-        if let Some(default) = &column.default {
+        let integer = matches!(
+            column.column_type,
+            ColumnType::SmallInt | ColumnType::Integer | ColumnType::BigInt
+        );
+        if column.auto_increment && integer {
+            def.push_str(" GENERATED BY DEFAULT AS IDENTITY");
+        } else if let Some(default) = &column.default {
             def.push_str(" DEFAULT ");
             def.push_str(&default.to_sql());
         }
 
         def
     }
+}
 
-    fn constraint_definition(&self, constraint: &Constraint) -> String {
-        match &constraint.constraint_type {
-            ConstraintType::Unique { columns } => {
-                let cols: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
-                format!(
-                    "CONSTRAINT {} UNIQUE ({})",
-                    quote_identifier(&constraint.name),
-                    cols.join(", ")
-                )
-            }
-            ConstraintType::Check { expression } => {
-                format!(
-                    "CONSTRAINT {} CHECK ({})",
-                    quote_identifier(&constraint.name),
-                    expression
-                )
-            }
-            ConstraintType::Exclusion { expression } => {
-                format!(
-                    "CONSTRAINT {} EXCLUDE ({})",
-                    quote_identifier(&constraint.name),
-                    expression
-                )
-            }
+/// Inline constraint definition with double-quoted identifiers
+///
+/// `deferrable` says whether the database can defer UNIQUE and EXCLUDE
+/// constraints; CHECK constraints are never deferrable.
+fn constraint_definition(
+    constraint: &Constraint,
+    deferrable: bool,
+    quote: fn(&str) -> String,
+) -> String {
+    let timing = match deferrable {
+        true => deferrable_clause(constraint.deferrable, constraint.initially_deferred),
+        false => "",
+    };
+    match &constraint.constraint_type {
+        ConstraintType::Unique { columns } => {
+            let cols: Vec<String> = columns.iter().map(|c| quote(c)).collect();
+            format!(
+                "CONSTRAINT {} UNIQUE ({}){}",
+                quote(&constraint.name),
+                cols.join(", "),
+                timing
+            )
+        }
+        ConstraintType::Check { expression } => {
+            format!(
+                "CONSTRAINT {} CHECK ({})",
+                quote(&constraint.name),
+                expression
+            )
+        }
+        ConstraintType::Exclusion { expression } => {
+            format!(
+                "CONSTRAINT {} EXCLUDE ({}){}",
+                quote(&constraint.name),
+                expression,
+                timing
+            )
         }
     }
+}
 
-    fn foreign_key_definition(&self, fk: &ForeignKey) -> String {
-        let local_cols: Vec<String> = fk.columns.iter().map(|c| quote_identifier(c)).collect();
-        let ref_cols: Vec<String> = fk
-            .references_columns
-            .iter()
-            .map(|c| quote_identifier(c))
-            .collect();
-
-        let mut def = String::new();
+/// ` DEFERRABLE` or ` DEFERRABLE INITIALLY DEFERRED`, if set
+fn deferrable_clause(deferrable: bool, initially_deferred: bool) -> &'static str {
+    match (deferrable, initially_deferred) {
+        (_, true) => " DEFERRABLE INITIALLY DEFERRED",
+        (true, false) => " DEFERRABLE",
+        (false, false) => "",
+    }
+}
 
-        if let Some(name) = &fk.name {
-            def.push_str("CONSTRAINT ");
-            def.push_str(&quote_identifier(name));
-            def.push(' ');
-        }
+/// Inline foreign key definition, with identifiers written by `quote`
+fn foreign_key_definition(fk: &ForeignKey, quote: fn(&str) -> String) -> String {
+    let local_cols: Vec<String> = fk.columns.iter().map(|c| quote(c)).collect();
+    let ref_cols: Vec<String> = fk.references_columns.iter().map(|c| quote(c)).collect();
 
-        def.push_str("FOREIGN KEY (");
-        def.push_str(&local_cols.join(", "));
-        def.push_str(") REFERENCES ");
-        def.push_str(&quote_identifier(&fk.references_table));
-        def.push_str(" (");
-        def.push_str(&ref_cols.join(", "));
-        def.push(')');
+    let mut def = String::new();
 
-        if fk.on_delete != ForeignKeyAction::NoAction {
-            def.push_str(" ON DELETE ");
-            def.push_str(fk.on_delete.as_sql());
-        }
+    if let Some(name) = &fk.name {
+        def.push_str("CONSTRAINT ");
+        def.push_str(&quote(name));
+        def.push(' ');
+    }
 
-        if fk.on_update != ForeignKeyAction::NoAction {
-            def.push_str(" ON UPDATE ");
-            def.push_str(fk.on_update.as_sql());
-        }
+    def.push_str("FOREIGN KEY (");
+    def.push_str(&local_cols.join(", "));
+    def.push_str(") REFERENCES ");
+    def.push_str(&quote(&fk.references_table));
+    def.push_str(" (");
+    def.push_str(&ref_cols.join(", "));
+    def.push(')');
+
+    if fk.on_delete != ForeignKeyAction::NoAction {
+        def.push_str(" ON DELETE ");
+        def.push_str(fk.on_delete.as_sql());
+    }
 
-        def
+    if fk.on_update != ForeignKeyAction::NoAction {
+        def.push_str(" ON UPDATE ");
+        def.push_str(fk.on_update.as_sql());
     }
+
+    def.push_str(deferrable_clause(fk.deferrable, fk.initially_deferred));
+
+    def
 }
 
 /// Quote an identifier
-fn quote_identifier(name: &str) -> String {
-    format!("\"{}\"", name.replace('"', "\"\""))
+pub(crate) fn quote_identifier(name: &str) -> String {
+    identifier::quote("postgresql", name)
+}
+
+/// Quote a grantee, leaving the `PUBLIC` pseudo-role bare
+fn quote_grantee(name: &str) -> String {
+    if name.eq_ignore_ascii_case("public") {
+        "PUBLIC".to_string()
+    } else {
+        quote_identifier(name)
+    }
 }
 
 /// MySQL DDL generator
@@ -555,15 +863,42 @@ impl DdlGenerator for MySqlDdlGenerator {
         }
 
         if let Some(pk) = &table.primary_key {
-            let cols: Vec<String> = pk.columns.iter().map(|c| quote_mysql_identifier(c)).collect();
+            let cols: Vec<String> = pk
+                .columns
+                .iter()
+                .map(|c| quote_mysql_identifier(c))
+                .collect();
             parts.push(format!("    PRIMARY KEY ({})", cols.join(", ")));
         }
 
+        for constraint in &table.constraints {
+            let name = quote_mysql_identifier(&constraint.name);
+            match &constraint.constraint_type {
+                ConstraintType::Unique { columns } => {
+                    let cols: Vec<String> =
+                        columns.iter().map(|c| quote_mysql_identifier(c)).collect();
+                    parts.push(format!(
+                        "    CONSTRAINT {} UNIQUE ({})",
+                        name,
+                        cols.join(", ")
+                    ));
+                }
+                ConstraintType::Check { expression } => {
+                    parts.push(format!("    CONSTRAINT {} CHECK ({})", name, expression));
+                }
+                // MySQL doesn't support exclusion constraints
+                ConstraintType::Exclusion { .. } => {}
+            }
+        }
+
         sql.push_str(&parts.join(",\n"));
         sql.push_str("\n) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4");
 
         DdlStatement::new(sql)
-            .reversible(format!("DROP TABLE {}", quote_mysql_identifier(&table.name)))
+            .reversible(format!(
+                "DROP TABLE {}",
+                quote_mysql_identifier(&table.name)
+            ))
             .description(format!("Create table {}", table.name))
     }
 
@@ -576,6 +911,27 @@ impl DdlGenerator for MySqlDdlGenerator {
         DdlStatement::new(sql)
     }
 
+    fn drop_temp_table(&self, table_name: &str) -> DdlStatement {
+        // TEMPORARY keeps a permanent table of the same name safe
+        DdlStatement::new(format!(
+            "DROP TEMPORARY TABLE IF EXISTS {}",
+            quote_mysql_identifier(table_name)
+        ))
+    }
+
+    fn truncate_table(
+        &self,
+        table_name: &str,
+        _cascade: bool,
+        _restart_identity: bool,
+    ) -> DdlStatement {
+        // Always resets AUTO_INCREMENT; there is no CASCADE
+        DdlStatement::new(format!(
+            "TRUNCATE TABLE {}",
+            quote_mysql_identifier(table_name)
+        ))
+    }
+
     fn add_column(&self, table_name: &str, column: &Column) -> DdlStatement {
         DdlStatement::new(format!(
             "ALTER TABLE {} ADD COLUMN {}",
@@ -619,7 +975,13 @@ impl DdlGenerator for MySqlDdlGenerator {
         let cols: Vec<String> = index
             .columns
             .iter()
-            .map(|c| quote_mysql_identifier(&c.name))
+            .map(|c| {
+                if c.expression {
+                    format!("({})", c.name)
+                } else {
+                    quote_mysql_identifier(&c.name)
+                }
+            })
             .collect();
         sql.push_str(&cols.join(", "));
         sql.push(')');
@@ -734,6 +1096,59 @@ impl DdlGenerator for MySqlDdlGenerator {
             quote_mysql_identifier(old_name)
         ))
     }
+
+    fn grant(&self, table_name: &str, grant: &Grant) -> DdlStatement {
+        let privileges = grant.privileges.join(", ");
+        let on = format!("ON {} ", quote_mysql_identifier(table_name));
+        let grantee = quote_mysql_identifier(&grant.grantee);
+        DdlStatement::new(format!("GRANT {} {}TO {}", privileges, on, grantee))
+            .reversible(format!("REVOKE {} {}FROM {}", privileges, on, grantee))
+    }
+
+    fn revoke(&self, table_name: &str, grant: &Grant) -> DdlStatement {
+        let privileges = grant.privileges.join(", ");
+        let on = format!("ON {} ", quote_mysql_identifier(table_name));
+        let grantee = quote_mysql_identifier(&grant.grantee);
+        DdlStatement::new(format!("REVOKE {} {}FROM {}", privileges, on, grantee))
+            .reversible(format!("GRANT {} {}TO {}", privileges, on, grantee))
+    }
+
+    fn alter_owner(&self, _table_name: &str, _owner: &str) -> DdlStatement {
+        DdlStatement::new("-- MySQL tables have no owner")
+    }
+
+    fn create_view(&self, view: &View) -> DdlStatement {
+        DdlStatement::new(format!(
+            "CREATE VIEW {} AS {}",
+            quote_mysql_identifier(&view.name),
+            view.definition.to_sql(&MySqlDialect)
+        ))
+        .reversible(format!("DROP VIEW {}", quote_mysql_identifier(&view.name)))
+    }
+
+    fn drop_view(&self, view_name: &str) -> DdlStatement {
+        DdlStatement::new(format!("DROP VIEW {}", quote_mysql_identifier(view_name)))
+    }
+
+    fn create_type(&self, _custom_type: &CustomType) -> DdlStatement {
+        DdlStatement::new("-- MySQL declares enums on their columns")
+    }
+
+    fn drop_type(&self, _type_name: &str) -> DdlStatement {
+        DdlStatement::new("-- MySQL declares enums on their columns")
+    }
+
+    fn add_enum_value(&self, _type_name: &str, _value: &str, _after: Option<&str>) -> DdlStatement {
+        DdlStatement::new("-- MySQL declares enums on their columns")
+    }
+
+    fn script_splitter(&self) -> ScriptSplitter {
+        ScriptSplitter::mysql()
+    }
+
+    fn inline_foreign_keys(&self) -> bool {
+        false
+    }
 }
 
 impl MySqlDdlGenerator {
@@ -761,7 +1176,7 @@ impl MySqlDdlGenerator {
 
 /// Quote MySQL identifier with backticks
 fn quote_mysql_identifier(name: &str) -> String {
-    format!("`{}`", name.replace('`', "``"))
+    identifier::quote("mysql", name)
 }
 
 /// SQLite DDL generator
@@ -772,7 +1187,7 @@ impl DdlGenerator for SqliteDdlGenerator {
     fn create_table(&self, table: &Table) -> DdlStatement {
         let mut sql = String::new();
         sql.push_str("CREATE TABLE ");
-        sql.push_str(&quote_identifier(&table.name));
+        sql.push_str(&quote_sqlite_identifier(&table.name));
         sql.push_str(" (\n");
 
         let mut parts = Vec::new();
@@ -783,27 +1198,65 @@ impl DdlGenerator for SqliteDdlGenerator {
         // Add composite primary key if not already defined on column
         if let Some(pk) = &table.primary_key {
             if pk.columns.len() > 1 {
-                let cols: Vec<String> = pk.columns.iter().map(|c| quote_identifier(c)).collect();
+                let cols: Vec<String> = pk
+                    .columns
+                    .iter()
+                    .map(|c| quote_sqlite_identifier(c))
+                    .collect();
                 parts.push(format!("    PRIMARY KEY ({})", cols.join(", ")));
             }
         }
 
+        // Constraints and foreign keys can't be added after creation
+        for constraint in &table.constraints {
+            if !matches!(constraint.constraint_type, ConstraintType::Exclusion { .. }) {
+                parts.push(format!(
+                    "    {}",
+                    constraint_definition(constraint, false, quote_sqlite_identifier)
+                ));
+            }
+        }
+        for fk in &table.foreign_keys {
+            parts.push(format!(
+                "    {}",
+                foreign_key_definition(fk, quote_sqlite_identifier)
+            ));
+        }
+
         sql.push_str(&parts.join(",\n"));
         sql.push_str("\n)");
 
-        DdlStatement::new(sql)
-            .reversible(format!("DROP TABLE {}", quote_identifier(&table.name)))
+        DdlStatement::new(sql).reversible(format!(
+            "DROP TABLE {}",
+            quote_sqlite_identifier(&table.name)
+        ))
     }
 
     fn drop_table(&self, table_name: &str, _cascade: bool) -> DdlStatement {
-        DdlStatement::new(format!("DROP TABLE {}", quote_identifier(table_name)))
+        DdlStatement::new(format!(
+            "DROP TABLE {}",
+            quote_sqlite_identifier(table_name)
+        ))
+    }
+
+    fn truncate_table(
+        &self,
+        table_name: &str,
+        _cascade: bool,
+        _restart_identity: bool,
+    ) -> DdlStatement {
+        // No TRUNCATE; an unfiltered DELETE is optimized into one
+        DdlStatement::new(format!(
+            "DELETE FROM {}",
+            quote_sqlite_identifier(table_name)
+        ))
     }
 
     fn add_column(&self, table_name: &str, column: &Column) -> DdlStatement {
         DdlStatement::new(format!(
             "ALTER TABLE {} ADD COLUMN {} {}{}",
-            quote_identifier(table_name),
-            quote_identifier(&column.name),
+            quote_sqlite_identifier(table_name),
+            quote_sqlite_identifier(&column.name),
             column.column_type.to_sqlite_sql(),
             if column.nullable { "" } else { " NOT NULL" }
         ))
@@ -812,8 +1265,8 @@ impl DdlGenerator for SqliteDdlGenerator {
     fn drop_column(&self, table_name: &str, column_name: &str) -> DdlStatement {
         DdlStatement::new(format!(
             "ALTER TABLE {} DROP COLUMN {}",
-            quote_identifier(table_name),
-            quote_identifier(column_name)
+            quote_sqlite_identifier(table_name),
+            quote_sqlite_identifier(column_name)
         ))
     }
 
@@ -832,23 +1285,35 @@ impl DdlGenerator for SqliteDdlGenerator {
             "CREATE INDEX ".to_string()
         };
 
-        sql.push_str(&quote_identifier(&index.name));
+        sql.push_str(&quote_sqlite_identifier(&index.name));
         sql.push_str(" ON ");
-        sql.push_str(&quote_identifier(table_name));
+        sql.push_str(&quote_sqlite_identifier(table_name));
         sql.push_str(" (");
         let cols: Vec<String> = index
             .columns
             .iter()
-            .map(|c| quote_identifier(&c.name))
+            .map(|c| {
+                if c.expression {
+                    format!("({})", c.name)
+                } else {
+                    quote_sqlite_identifier(&c.name)
+                }
+            })
             .collect();
         sql.push_str(&cols.join(", "));
         sql.push(')');
 
-        DdlStatement::new(sql).reversible(format!("DROP INDEX {}", quote_identifier(&index.name)))
+        DdlStatement::new(sql).reversible(format!(
+            "DROP INDEX {}",
+            quote_sqlite_identifier(&index.name)
+        ))
     }
 
     fn drop_index(&self, index_name: &str) -> DdlStatement {
-        DdlStatement::new(format!("DROP INDEX {}", quote_identifier(index_name)))
+        DdlStatement::new(format!(
+            "DROP INDEX {}",
+            quote_sqlite_identifier(index_name)
+        ))
     }
 
     fn add_constraint(&self, _table_name: &str, _constraint: &Constraint) -> DdlStatement {
@@ -860,9 +1325,7 @@ impl DdlGenerator for SqliteDdlGenerator {
     }
 
     fn add_foreign_key(&self, _table_name: &str, _fk: &ForeignKey) -> DdlStatement {
-        DdlStatement::new(
-            "-- SQLite doesn't support adding foreign keys after table creation",
-        )
+        DdlStatement::new("-- SQLite doesn't support adding foreign keys after table creation")
     }
 
     fn drop_foreign_key(&self, _table_name: &str, _fk_name: &str) -> DdlStatement {
@@ -872,42 +1335,84 @@ impl DdlGenerator for SqliteDdlGenerator {
     fn rename_table(&self, old_name: &str, new_name: &str) -> DdlStatement {
         DdlStatement::new(format!(
             "ALTER TABLE {} RENAME TO {}",
-            quote_identifier(old_name),
-            quote_identifier(new_name)
+            quote_sqlite_identifier(old_name),
+            quote_sqlite_identifier(new_name)
         ))
         .reversible(format!(
             "ALTER TABLE {} RENAME TO {}",
-            quote_identifier(new_name),
-            quote_identifier(old_name)
+            quote_sqlite_identifier(new_name),
+            quote_sqlite_identifier(old_name)
         ))
     }
 
     fn rename_column(&self, table_name: &str, old_name: &str, new_name: &str) -> DdlStatement {
         DdlStatement::new(format!(
             "ALTER TABLE {} RENAME COLUMN {} TO {}",
-            quote_identifier(table_name),
-            quote_identifier(old_name),
-            quote_identifier(new_name)
+            quote_sqlite_identifier(table_name),
+            quote_sqlite_identifier(old_name),
+            quote_sqlite_identifier(new_name)
         ))
         .reversible(format!(
             "ALTER TABLE {} RENAME COLUMN {} TO {}",
-            quote_identifier(table_name),
-            quote_identifier(new_name),
-            quote_identifier(old_name)
+            quote_sqlite_identifier(table_name),
+            quote_sqlite_identifier(new_name),
+            quote_sqlite_identifier(old_name)
+        ))
+    }
+
+    fn grant(&self, _table_name: &str, _grant: &Grant) -> DdlStatement {
+        DdlStatement::new("-- SQLite doesn't support GRANT")
+    }
+
+    fn revoke(&self, _table_name: &str, _grant: &Grant) -> DdlStatement {
+        DdlStatement::new("-- SQLite doesn't support REVOKE")
+    }
+
+    fn alter_owner(&self, _table_name: &str, _owner: &str) -> DdlStatement {
+        DdlStatement::new("-- SQLite tables have no owner")
+    }
+
+    fn create_view(&self, view: &View) -> DdlStatement {
+        DdlStatement::new(format!(
+            "CREATE VIEW {} AS {}",
+            quote_sqlite_identifier(&view.name),
+            view.definition.to_sql(&SqliteDialect)
         ))
+        .reversible(format!("DROP VIEW {}", quote_sqlite_identifier(&view.name)))
+    }
+
+    fn drop_view(&self, view_name: &str) -> DdlStatement {
+        DdlStatement::new(format!("DROP VIEW {}", quote_sqlite_identifier(view_name)))
+    }
+
+    fn create_type(&self, _custom_type: &CustomType) -> DdlStatement {
+        DdlStatement::new("-- SQLite has no custom types")
+    }
+
+    fn drop_type(&self, _type_name: &str) -> DdlStatement {
+        DdlStatement::new("-- SQLite has no custom types")
+    }
+
+    fn add_enum_value(&self, _type_name: &str, _value: &str, _after: Option<&str>) -> DdlStatement {
+        DdlStatement::new("-- SQLite has no custom types")
+    }
+
+    fn script_splitter(&self) -> ScriptSplitter {
+        ScriptSplitter::sqlite()
     }
 }
 
 impl SqliteDdlGenerator {
     fn column_definition(&self, column: &Column, table: &Table) -> String {
-        let mut def = quote_identifier(&column.name);
+        let mut def = quote_sqlite_identifier(&column.name);
         def.push(' ');
         def.push_str(&column.column_type.to_sqlite_sql());
 
         // Check if this is a single-column primary key
-        let is_pk = table.primary_key.as_ref().map_or(false, |pk| {
-            pk.columns.len() == 1 && pk.columns[0] == column.name
-        });
+        let is_pk = table
+            .primary_key
+            .as_ref()
+            .is_some_and(|pk| pk.columns.len() == 1 && pk.columns[0] == column.name);
 
         if is_pk {
             def.push_str(" PRIMARY KEY");
@@ -923,13 +1428,29 @@ impl SqliteDdlGenerator {
             def.push_str(&default.to_sql());
         }
 
+        // Enums are TEXT limited to their labels
+        if let ColumnType::Enum { values, .. } = &column.column_type {
+            let values: Vec<String> = values.iter().map(|v| quote_literal(v)).collect();
+            def.push_str(&format!(
+                " CHECK ({} IN ({}))",
+                quote_sqlite_identifier(&column.name),
+                values.join(", ")
+            ));
+        }
+
         def
     }
 }
 
+/// Quote a SQLite identifier
+fn quote_sqlite_identifier(name: &str) -> String {
+    identifier::quote("sqlite", name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::schema::{ColumnType, PrimaryKey};
 
     #[test]
     fn test_postgres_create_table() {
@@ -947,6 +1468,56 @@ mod tests {
         assert!(stmt.reversible);
     }
 
+    #[test]
+    fn test_postgres_create_index_options() {
+        let index = Index::new("users_email_idx", Vec::<String>::new())
+            .expression("lower(email)")
+            .unique()
+            .include(vec!["name", "created_at"])
+            .storage("fillfactor", "70")
+            .where_clause("deleted_at IS NULL");
+        let stmt = PostgresDdlGenerator.create_index("users", &index);
+        assert_eq!(
+            stmt.sql,
+            "CREATE UNIQUE INDEX \"users_email_idx\" ON \"users\" ((lower(email))) \
+             INCLUDE (\"name\", \"created_at\") WITH (fillfactor = 70) WHERE deleted_at IS NULL"
+        );
+        let stmt = SqliteDdlGenerator.create_index("users", &index);
+        assert_eq!(
+            stmt.sql,
+            "CREATE UNIQUE INDEX \"users_email_idx\" ON \"users\" ((lower(email)))"
+        );
+    }
+
+    #[test]
+    fn test_deferrable_constraints() {
+        let mut table = Table::new("employees")
+            .column(Column::new("id", ColumnType::BigInt).not_null())
+            .column(Column::new("manager_id", ColumnType::BigInt));
+        table.add_constraint(
+            Constraint::new(
+                "employees_id_key",
+                ConstraintType::Unique {
+                    columns: vec!["id".into()],
+                },
+            )
+            .deferrable(),
+        );
+        table.add_foreign_key(
+            ForeignKey::new(vec!["manager_id".into()], "employees", vec!["id".into()])
+                .name("employees_manager_fk")
+                .initially_deferred(),
+        );
+
+        let sql = PostgresDdlGenerator.create_table(&table).sql;
+        assert!(sql.contains("CONSTRAINT \"employees_id_key\" UNIQUE (\"id\") DEFERRABLE,"));
+        assert!(sql.contains("REFERENCES \"employees\" (\"id\") DEFERRABLE INITIALLY DEFERRED"));
+        // SQLite only defers foreign keys
+        let sql = SqliteDdlGenerator.create_table(&table).sql;
+        assert!(sql.contains("UNIQUE (\"id\"),"));
+        assert!(sql.contains("DEFERRABLE INITIALLY DEFERRED"));
+    }
+
     #[test]
     fn test_postgres_add_column() {
         let column = Column::new("email", ColumnType::Varchar(Some(255))).not_null();
@@ -959,4 +1530,184 @@ mod tests {
         assert!(stmt.sql.contains("VARCHAR(255)"));
         assert!(stmt.reversible);
     }
+
+    #[test]
+    fn test_truncate_table() {
+        assert_eq!(
+            PostgresDdlGenerator.truncate_table("users", true, true).sql,
+            "TRUNCATE TABLE \"users\" RESTART IDENTITY CASCADE"
+        );
+        assert_eq!(
+            MySqlDdlGenerator.truncate_table("users", true, true).sql,
+            "TRUNCATE TABLE `users`"
+        );
+        assert_eq!(
+            SqliteDdlGenerator.truncate_table("users", true, true).sql,
+            "DELETE FROM \"users\""
+        );
+    }
+
+    #[test]
+    fn test_create_temp_table() {
+        let table = Table::new("staged_totals")
+            .column(Column::new("user_id", ColumnType::BigInt).not_null())
+            .column(Column::new("total", ColumnType::Integer));
+
+        let stmt = PostgresDdlGenerator.create_temp_table(&table);
+        assert!(stmt
+            .sql
+            .starts_with("CREATE TEMPORARY TABLE \"staged_totals\" ("));
+        assert_eq!(
+            stmt.reverse_sql.as_deref(),
+            Some("DROP TABLE IF EXISTS \"staged_totals\"")
+        );
+
+        let stmt = MySqlDdlGenerator.create_temp_table(&table);
+        assert!(stmt
+            .sql
+            .starts_with("CREATE TEMPORARY TABLE `staged_totals`"));
+        assert_eq!(
+            stmt.reverse_sql.as_deref(),
+            Some("DROP TEMPORARY TABLE IF EXISTS `staged_totals`")
+        );
+        assert_eq!(stmt.safety(), Safety::Safe);
+    }
+
+    #[test]
+    fn test_statement_safety() {
+        let users = Table::new("users");
+        let email = Column::new("email", ColumnType::Text);
+        let gen = PostgresDdlGenerator;
+        assert_eq!(gen.create_table(&users).safety(), Safety::Safe);
+        assert_eq!(gen.add_column("users", &email).safety(), Safety::Safe);
+        assert_eq!(gen.drop_column("users", "email").safety(), Safety::DataLoss);
+        assert_eq!(gen.drop_table("users", true).safety(), Safety::DataLoss);
+        assert_eq!(gen.drop_index("users_email_idx").safety(), Safety::Risky);
+        assert_eq!(
+            gen.rename_column("users", "email", "mail").safety(),
+            Safety::Risky
+        );
+        let not_null = email.clone().not_null();
+        for stmt in gen.alter_column("users", &email, &not_null) {
+            assert_eq!(stmt.safety(), Safety::Risky);
+        }
+    }
+
+    #[test]
+    fn test_grants() {
+        let grant = Grant::new("app_ro", ["select", "insert"]);
+        let stmt = PostgresDdlGenerator.grant("users", &grant);
+        assert_eq!(stmt.sql, "GRANT SELECT, INSERT ON \"users\" TO \"app_ro\"");
+        assert_eq!(
+            stmt.reverse_sql.as_deref(),
+            Some("REVOKE SELECT, INSERT ON \"users\" FROM \"app_ro\"")
+        );
+        assert_eq!(
+            PostgresDdlGenerator
+                .revoke("users", &Grant::new("public", ["SELECT"]))
+                .sql,
+            "REVOKE SELECT ON \"users\" FROM PUBLIC"
+        );
+        assert_eq!(
+            PostgresDdlGenerator.alter_owner("users", "app_owner").sql,
+            "ALTER TABLE \"users\" OWNER TO \"app_owner\""
+        );
+        assert_eq!(
+            MySqlDdlGenerator.grant("users", &grant).sql,
+            "GRANT SELECT, INSERT ON `users` TO `app_ro`"
+        );
+        assert!(SqliteDdlGenerator
+            .grant("users", &grant)
+            .sql
+            .starts_with("--"));
+    }
+
+    #[test]
+    fn test_views() {
+        use crate::schema::ViewDefinition;
+        use chakra_core::expr::Expr;
+        use chakra_core::query::Query;
+
+        let query = Query::select()
+            .from("orders")
+            .columns(&["id", "total"])
+            .filter(Expr::eq("status", "paid"))
+            .build();
+        let view = View::new("paid_orders", ViewDefinition::Query(Box::new(query)));
+        let stmt = PostgresDdlGenerator.create_view(&view);
+        assert_eq!(
+            stmt.sql,
+            "CREATE VIEW \"paid_orders\" AS SELECT \"id\", \"total\" FROM \"orders\" WHERE \"status\" = 'paid'"
+        );
+        assert_eq!(
+            stmt.reverse_sql.as_deref(),
+            Some("DROP VIEW \"paid_orders\"")
+        );
+        assert_eq!(
+            MySqlDdlGenerator.create_view(&view).sql,
+            "CREATE VIEW `paid_orders` AS SELECT `id`, `total` FROM `orders` WHERE `status` = 'paid'"
+        );
+
+        // Introspected definitions are used as reported
+        let view = View::new(
+            "recent",
+            ViewDefinition::Sql(" SELECT orders.id\n   FROM orders;".to_string()),
+        );
+        assert_eq!(
+            SqliteDdlGenerator.create_view(&view).sql,
+            "CREATE VIEW \"recent\" AS SELECT orders.id\n   FROM orders"
+        );
+        assert_eq!(
+            PostgresDdlGenerator.drop_view("recent").safety(),
+            Safety::Risky
+        );
+    }
+
+    #[test]
+    fn test_enum_types() {
+        let status = CustomType::Enum {
+            name: "order_status".into(),
+            values: vec!["pending".into(), "it's shipped".into()],
+        };
+        let stmt = PostgresDdlGenerator.create_type(&status);
+        assert_eq!(
+            stmt.sql,
+            "CREATE TYPE \"order_status\" AS ENUM ('pending', 'it''s shipped')"
+        );
+        assert_eq!(
+            stmt.reverse_sql.as_deref(),
+            Some("DROP TYPE \"order_status\"")
+        );
+        assert_eq!(
+            PostgresDdlGenerator
+                .add_enum_value("order_status", "lost", Some("pending"))
+                .sql,
+            "ALTER TYPE \"order_status\" ADD VALUE 'lost' AFTER 'pending'"
+        );
+        assert!(SqliteDdlGenerator
+            .create_type(&status)
+            .sql
+            .starts_with("--"));
+
+        let column_type = ColumnType::Enum {
+            name: "order_status".into(),
+            values: vec!["pending".into(), "shipped".into()],
+        };
+        let table = Table::new("orders")
+            .column(Column::new("id", ColumnType::BigInt).not_null())
+            .column(Column::new("status", column_type).not_null())
+            .primary_key(PrimaryKey::single("id"));
+        assert!(PostgresDdlGenerator
+            .create_table(&table)
+            .sql
+            .contains("\"status\" \"order_status\" NOT NULL"));
+        assert!(MySqlDdlGenerator
+            .create_table(&table)
+            .sql
+            .contains("`status` ENUM('pending', 'shipped') NOT NULL"));
+        assert!(SqliteDdlGenerator
+            .create_table(&table)
+            .sql
+            .contains("\"status\" TEXT NOT NULL CHECK (\"status\" IN ('pending', 'shipped'))"));
+    }
 }
@@ -3,7 +3,12 @@
 //! This module provides schema comparison and diff generation.
 
 use crate::ddl::{DdlGenerator, DdlStatement};
-use crate::schema::{Column, ColumnType, Constraint, ForeignKey, Index, Schema, Table};
+use crate::schema::{
+    Column, ColumnType, Constraint, CustomType, ForeignKey, Grant, Index, Schema, Table, View,
+    ViewDefinition,
+};
+use chakra_core::identifier::IdentifierPolicy;
+use chakra_core::sql::PostgresDialect;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
@@ -16,6 +21,29 @@ pub struct SchemaDiff {
     pub tables_to_drop: Vec<String>,
     /// Table modifications
     pub table_modifications: Vec<TableDiff>,
+    /// Views to create, including changed ones
+    #[serde(default)]
+    pub views_to_create: Vec<View>,
+    /// Views to drop, including changed ones, as currently defined
+    #[serde(default)]
+    pub views_to_drop: Vec<View>,
+    /// Custom types to create, before the tables using them
+    #[serde(default)]
+    pub types_to_create: Vec<CustomType>,
+    /// Labels to add to existing enum types
+    #[serde(default)]
+    pub enum_values_to_add: Vec<EnumValue>,
+}
+
+/// A label added to an existing enum type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumValue {
+    /// Enum type name
+    pub type_name: String,
+    /// New label
+    pub value: String,
+    /// Label it follows, or `None` to go last
+    pub after: Option<String>,
 }
 
 impl SchemaDiff {
@@ -24,12 +52,33 @@ impl SchemaDiff {
         self.tables_to_create.is_empty()
             && self.tables_to_drop.is_empty()
             && self.table_modifications.is_empty()
+            && self.views_to_create.is_empty()
+            && self.views_to_drop.is_empty()
+            && self.types_to_create.is_empty()
+            && self.enum_values_to_add.is_empty()
     }
 
     /// Generate DDL statements for the diff
     pub fn to_ddl(&self, generator: &dyn DdlGenerator) -> Vec<DdlStatement> {
         let mut statements = Vec::new();
 
+        // Drop views before the tables and columns they read
+        for view in &self.views_to_drop {
+            statements.push(generator.drop_view(&view.name));
+        }
+
+        // Create and extend types before the columns using them
+        for custom_type in &self.types_to_create {
+            statements.push(generator.create_type(custom_type));
+        }
+        for value in &self.enum_values_to_add {
+            statements.push(generator.add_enum_value(
+                &value.type_name,
+                &value.value,
+                value.after.as_deref(),
+            ));
+        }
+
         // Drop foreign keys first (to avoid FK constraint violations)
         for table_diff in &self.table_modifications {
             for fk_name in &table_diff.foreign_keys_to_drop {
@@ -49,6 +98,12 @@ impl SchemaDiff {
             for index in &table.indexes {
                 statements.push(generator.create_index(&table.name, index));
             }
+            if let Some(owner) = &table.owner {
+                statements.push(generator.alter_owner(&table.name, owner));
+            }
+            for grant in &table.grants {
+                statements.push(generator.grant(&table.name, grant));
+            }
         }
 
         // Modify existing tables
@@ -92,6 +147,17 @@ impl SchemaDiff {
             for constraint in &table_diff.constraints_to_add {
                 statements.push(generator.add_constraint(&table_diff.table_name, constraint));
             }
+
+            // Ownership and privileges
+            if let Some(owner) = &table_diff.owner_to_set {
+                statements.push(generator.alter_owner(&table_diff.table_name, owner));
+            }
+            for grant in &table_diff.grants_to_revoke {
+                statements.push(generator.revoke(&table_diff.table_name, grant));
+            }
+            for grant in &table_diff.grants_to_add {
+                statements.push(generator.grant(&table_diff.table_name, grant));
+            }
         }
 
         // Add foreign keys last (after all tables/columns exist)
@@ -108,6 +174,11 @@ impl SchemaDiff {
             }
         }
 
+        // Create views once everything they read exists
+        for view in &self.views_to_create {
+            statements.push(generator.create_view(view));
+        }
+
         statements
     }
 }
@@ -137,6 +208,15 @@ pub struct TableDiff {
     pub foreign_keys_to_add: Vec<ForeignKey>,
     /// Foreign keys to drop
     pub foreign_keys_to_drop: Vec<String>,
+    /// New owner
+    #[serde(default)]
+    pub owner_to_set: Option<String>,
+    /// Privileges to grant
+    #[serde(default)]
+    pub grants_to_add: Vec<Grant>,
+    /// Privileges to revoke
+    #[serde(default)]
+    pub grants_to_revoke: Vec<Grant>,
 }
 
 impl TableDiff {
@@ -154,6 +234,9 @@ impl TableDiff {
             constraints_to_drop: Vec::new(),
             foreign_keys_to_add: Vec::new(),
             foreign_keys_to_drop: Vec::new(),
+            owner_to_set: None,
+            grants_to_add: Vec::new(),
+            grants_to_revoke: Vec::new(),
         }
     }
 
@@ -169,6 +252,9 @@ impl TableDiff {
             && self.constraints_to_drop.is_empty()
             && self.foreign_keys_to_add.is_empty()
             && self.foreign_keys_to_drop.is_empty()
+            && self.owner_to_set.is_none()
+            && self.grants_to_add.is_empty()
+            && self.grants_to_revoke.is_empty()
     }
 }
 
@@ -181,6 +267,10 @@ pub struct SchemaDiffer {
     pub ignore_index_names: bool,
     /// Tables to exclude from comparison
     pub exclude_tables: HashSet<String>,
+    /// Index foreign key columns the target leaves unindexed
+    pub index_foreign_keys: bool,
+    /// Identifier policy of the database the schemas come from
+    pub identifiers: IdentifierPolicy,
 }
 
 impl SchemaDiffer {
@@ -207,12 +297,52 @@ impl SchemaDiffer {
         self
     }
 
+    /// Create an index for every foreign key the target schema doesn't
+    /// index, keeping any covering index the database already has
+    pub fn index_foreign_keys(mut self, enabled: bool) -> Self {
+        self.index_foreign_keys = enabled;
+        self
+    }
+
+    /// Compare names under the database's identifier policy, usually
+    /// `identifier_policy(dialect.name())`
+    pub fn identifiers(mut self, policy: IdentifierPolicy) -> Self {
+        self.identifiers = policy;
+        self
+    }
+
     /// Compare two schemas and return the diff
+    ///
+    /// Under the `FoldLower` identifier policy, names are compared as the
+    /// database stores them, so `userId` in a model matches `userid`.
     pub fn diff(&self, from: &Schema, to: &Schema) -> SchemaDiff {
+        let (folded_from, folded_to);
+        let policy = self.identifiers;
+        let (from, to) = if policy == IdentifierPolicy::FoldLower {
+            folded_from = folded(from, policy);
+            folded_to = folded(to, policy);
+            (&folded_from, &folded_to)
+        } else {
+            (from, to)
+        };
+        let indexed;
+        let to = if self.index_foreign_keys {
+            let mut schema = to.clone();
+            schema.index_foreign_keys(Some(from));
+            indexed = schema;
+            &indexed
+        } else {
+            to
+        };
+
         let mut diff = SchemaDiff {
             tables_to_create: Vec::new(),
             tables_to_drop: Vec::new(),
             table_modifications: Vec::new(),
+            views_to_create: Vec::new(),
+            views_to_drop: Vec::new(),
+            types_to_create: Vec::new(),
+            enum_values_to_add: Vec::new(),
         };
 
         let from_tables: HashSet<&str> = from
@@ -251,6 +381,54 @@ impl SchemaDiffer {
             }
         }
 
+        // Types are only ever added to: PostgreSQL can't remove enum labels,
+        // and unused types are left in place
+        let mut type_names: Vec<&String> = to.types.keys().collect();
+        type_names.sort_unstable();
+        for name in type_names {
+            match (from.types.get(name), &to.types[name]) {
+                (None, to_type) => diff.types_to_create.push(to_type.clone()),
+                (
+                    Some(CustomType::Enum {
+                        values: existing, ..
+                    }),
+                    CustomType::Enum { values, .. },
+                ) => {
+                    for (i, value) in values.iter().enumerate() {
+                        if !existing.contains(value) {
+                            diff.enum_values_to_add.push(EnumValue {
+                                type_name: name.clone(),
+                                value: value.clone(),
+                                after: i.checked_sub(1).map(|prev| values[prev].clone()),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Views can't be altered; a changed definition is recreated
+        let mut view_names: Vec<&String> = from.views.keys().chain(to.views.keys()).collect();
+        view_names.sort_unstable();
+        view_names.dedup();
+        for name in view_names
+            .into_iter()
+            .filter(|name| !self.exclude_tables.contains(*name))
+        {
+            match (from.views.get(name), to.views.get(name)) {
+                (Some(from_view), Some(to_view)) => {
+                    if self.views_differ(&from_view.definition, &to_view.definition) {
+                        diff.views_to_drop.push(from_view.clone());
+                        diff.views_to_create.push(to_view.clone());
+                    }
+                }
+                (Some(from_view), None) => diff.views_to_drop.push(from_view.clone()),
+                (None, Some(to_view)) => diff.views_to_create.push(to_view.clone()),
+                (None, None) => {}
+            }
+        }
+
         diff
     }
 
@@ -304,6 +482,15 @@ impl SchemaDiffer {
             diff.indexes_to_drop.push((*idx_name).to_string());
         }
 
+        // Indexes can't be altered; a changed definition is recreated
+        for idx_name in from_idx_names.intersection(&to_idx_names) {
+            let to_index = to_indexes[*idx_name];
+            if self.indexes_differ(from_indexes[*idx_name], to_index) {
+                diff.indexes_to_drop.push((*idx_name).to_string());
+                diff.indexes_to_create.push(to_index.clone());
+            }
+        }
+
         // Compare constraints
         let from_constraints: HashMap<&str, &Constraint> = from
             .constraints
@@ -328,6 +515,18 @@ impl SchemaDiffer {
             diff.constraints_to_drop.push((*const_name).to_string());
         }
 
+        // Deferrability can only be changed by recreating the constraint
+        for const_name in from_const_names.intersection(&to_const_names) {
+            let (from_const, to_const) =
+                (from_constraints[*const_name], to_constraints[*const_name]);
+            if (from_const.deferrable, from_const.initially_deferred)
+                != (to_const.deferrable, to_const.initially_deferred)
+            {
+                diff.constraints_to_drop.push((*const_name).to_string());
+                diff.constraints_to_add.push(to_const.clone());
+            }
+        }
+
         // Compare foreign keys
         let from_fks: HashMap<String, &ForeignKey> = from
             .foreign_keys
@@ -340,13 +539,21 @@ impl SchemaDiffer {
                 (name, fk)
             })
             .collect();
+        // An unnamed key matches an existing one on the same columns and
+        // table, whatever name the database gave it
         let to_fks: HashMap<String, &ForeignKey> = to
             .foreign_keys
             .iter()
             .map(|fk| {
+                let existing = || {
+                    from_fks.iter().find(|(_, f)| {
+                        f.columns == fk.columns && f.references_table == fk.references_table
+                    })
+                };
                 let name = fk
                     .name
                     .clone()
+                    .or_else(|| existing().map(|(name, _)| name.clone()))
                     .unwrap_or_else(|| format!("fk_{}_{}", to.name, fk.columns.join("_")));
                 (name, fk)
             })
@@ -356,21 +563,84 @@ impl SchemaDiffer {
         let to_fk_names: HashSet<&str> = to_fks.keys().map(|s| s.as_str()).collect();
 
         for fk_name in to_fk_names.difference(&from_fk_names) {
-            diff.foreign_keys_to_add
-                .push(to_fks[*fk_name].clone());
+            diff.foreign_keys_to_add.push(to_fks[*fk_name].clone());
         }
 
         for fk_name in from_fk_names.difference(&to_fk_names) {
             diff.foreign_keys_to_drop.push((*fk_name).to_string());
         }
 
+        for fk_name in from_fk_names.intersection(&to_fk_names) {
+            let (from_fk, to_fk) = (from_fks[*fk_name], to_fks[*fk_name]);
+            if (from_fk.deferrable, from_fk.initially_deferred)
+                != (to_fk.deferrable, to_fk.initially_deferred)
+            {
+                diff.foreign_keys_to_drop.push((*fk_name).to_string());
+                diff.foreign_keys_to_add.push(to_fk.clone().name(*fk_name));
+            }
+        }
+
+        // Compare ownership, only when the target declares an owner
+        if to.owner.is_some() && to.owner != from.owner {
+            diff.owner_to_set = to.owner.clone();
+        }
+
+        // Compare grants for the roles the target mentions; others are left alone
+        for grant in &to.grants {
+            let current: Vec<&String> = from
+                .grants
+                .iter()
+                .filter(|g| g.grantee == grant.grantee)
+                .flat_map(|g| &g.privileges)
+                .collect();
+
+            if grant.privileges.iter().any(|p| p == "ALL") {
+                let held = Grant::new(&grant.grantee, current);
+                if !held.is_all() {
+                    diff.grants_to_add.push(grant.clone());
+                }
+                continue;
+            }
+
+            let missing: Vec<&String> = grant
+                .privileges
+                .iter()
+                .filter(|p| !current.contains(p))
+                .collect();
+            let extra: Vec<&String> = current
+                .iter()
+                .copied()
+                .filter(|p| !grant.privileges.contains(p))
+                .collect();
+            if !missing.is_empty() {
+                diff.grants_to_add.push(Grant::new(&grant.grantee, missing));
+            }
+            if !extra.is_empty() {
+                diff.grants_to_revoke
+                    .push(Grant::new(&grant.grantee, extra));
+            }
+        }
+
         diff
     }
 
     /// Check if two columns differ
     fn columns_differ(&self, from: &Column, to: &Column) -> bool {
-        // Compare type
-        if from.column_type != to.column_type {
+        // Compare type; enum labels are compared with the type itself, which
+        // introspection reports by name
+        let same_type = match (&from.column_type, &to.column_type) {
+            (
+                ColumnType::Enum { name: a, .. } | ColumnType::Custom(a),
+                ColumnType::Enum { name: b, .. },
+            )
+            | (ColumnType::Enum { name: a, .. }, ColumnType::Custom(b)) => a == b,
+            // Range elements carry no precision, so ranges match by type name
+            (a @ ColumnType::Range(_), b @ ColumnType::Range(_)) => {
+                a.to_postgres_sql() == b.to_postgres_sql()
+            }
+            (a, b) => a == b,
+        };
+        if !same_type {
             return true;
         }
 
@@ -392,6 +662,126 @@ impl SchemaDiffer {
 
         false
     }
+
+    /// Compare the parts of an index definition that introspection reports:
+    /// uniqueness, keys, included columns and storage parameters
+    ///
+    /// Expressions are compared after removing the casts and parentheses
+    /// PostgreSQL adds, so `lower(email)` matches `lower((email)::text)`.
+    fn indexes_differ(&self, from: &Index, to: &Index) -> bool {
+        let keys = |index: &Index| -> Vec<(bool, String)> {
+            index
+                .columns
+                .iter()
+                .map(|c| match c.expression {
+                    true => (true, normalize_expression(&c.name)),
+                    false => (false, c.name.clone()),
+                })
+                .collect()
+        };
+        from.unique != to.unique
+            || keys(from) != keys(to)
+            || from.include != to.include
+            || from.storage != to.storage
+    }
+}
+
+impl SchemaDiffer {
+    /// Compare view definitions
+    ///
+    /// Two queries built in Rust are compared as built. Otherwise the SQL is
+    /// compared after removing what databases change when storing a view:
+    /// case, whitespace, identifier quotes, `table.` qualifiers, casts,
+    /// parentheses and a trailing `;`. Queries are generated as PostgreSQL
+    /// for this, as the only database with view introspection.
+    fn views_differ(&self, from: &ViewDefinition, to: &ViewDefinition) -> bool {
+        if let (ViewDefinition::Query(a), ViewDefinition::Query(b)) = (from, to) {
+            return serde_json::to_value(a).ok() != serde_json::to_value(b).ok();
+        }
+        let normalize = |definition: &ViewDefinition| {
+            let sql = unqualify(&definition.to_sql(&PostgresDialect).replace(['"', '`'], ""));
+            normalize_expression(&sql).replace(['(', ')'], "")
+        };
+        normalize(from) != normalize(to)
+    }
+}
+
+/// A copy of `schema` with its identifiers folded by `policy`
+fn folded(schema: &Schema, policy: IdentifierPolicy) -> Schema {
+    let mut schema = schema.clone();
+    schema.fold_identifiers(policy);
+    schema
+}
+
+/// Drop `table.` qualifiers from column references outside string literals
+fn unqualify(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut word_start = None;
+    let mut in_literal = false;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let is_ident = c.is_alphanumeric() || c == '_';
+        if c == '\'' {
+            in_literal = !in_literal;
+        } else if !in_literal && c == '.' {
+            let qualifier = word_start
+                .filter(|&start| out[start..].starts_with(|c: char| c.is_alphabetic() || c == '_'));
+            if let (Some(start), Some(next)) = (qualifier, chars.peek()) {
+                if next.is_alphabetic() || *next == '_' {
+                    out.truncate(start);
+                    word_start = None;
+                    continue;
+                }
+            }
+        }
+        match (is_ident && !in_literal, word_start) {
+            (true, None) => word_start = Some(out.len()),
+            (false, _) => word_start = None,
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Lowercase `expression`, drop whitespace and `::type` casts, and unwrap
+/// parentheses around a lone identifier
+fn normalize_expression(expression: &str) -> String {
+    let mut out = String::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            while chars
+                .peek()
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == ' ')
+            {
+                chars.next();
+            }
+        } else if !c.is_whitespace() {
+            out.extend(c.to_lowercase());
+        }
+    }
+    // `(email)` not preceded by a function name is just `email`
+    loop {
+        let bytes = out.as_bytes();
+        let wrapped = (0..bytes.len()).find_map(|open| {
+            let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+            if bytes[open] != b'(' || (open > 0 && is_ident(bytes[open - 1])) {
+                return None;
+            }
+            let len = bytes[open + 1..]
+                .iter()
+                .take_while(|b| is_ident(**b))
+                .count();
+            (len > 0 && bytes.get(open + 1 + len) == Some(&b')')).then_some((open, len))
+        });
+        let Some((open, len)) = wrapped else {
+            return out;
+        };
+        out.replace_range(open + 1 + len..open + 2 + len, "");
+        out.replace_range(open..open + 1, "");
+    }
 }
 
 /// Builder for creating migrations from model changes
@@ -404,19 +794,70 @@ pub struct MigrationBuilder {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MigrationOperation {
     CreateTable(Table),
-    DropTable { name: String, cascade: bool },
-    RenameTable { from: String, to: String },
-    AddColumn { table: String, column: Column },
-    DropColumn { table: String, column: String },
-    AlterColumn { table: String, from: Column, to: Column },
-    RenameColumn { table: String, from: String, to: String },
-    CreateIndex { table: String, index: Index },
-    DropIndex { name: String },
-    AddConstraint { table: String, constraint: Constraint },
-    DropConstraint { table: String, name: String },
-    AddForeignKey { table: String, foreign_key: ForeignKey },
-    DropForeignKey { table: String, name: String },
-    RawSql { up: String, down: Option<String> },
+    DropTable {
+        name: String,
+        cascade: bool,
+    },
+    TruncateTable {
+        name: String,
+        cascade: bool,
+        restart_identity: bool,
+    },
+    RenameTable {
+        from: String,
+        to: String,
+    },
+    AddColumn {
+        table: String,
+        column: Column,
+    },
+    DropColumn {
+        table: String,
+        column: String,
+    },
+    AlterColumn {
+        table: String,
+        from: Column,
+        to: Column,
+    },
+    RenameColumn {
+        table: String,
+        from: String,
+        to: String,
+    },
+    CreateIndex {
+        table: String,
+        index: Index,
+    },
+    DropIndex {
+        name: String,
+    },
+    AddConstraint {
+        table: String,
+        constraint: Constraint,
+    },
+    DropConstraint {
+        table: String,
+        name: String,
+    },
+    AddForeignKey {
+        table: String,
+        foreign_key: ForeignKey,
+    },
+    DropForeignKey {
+        table: String,
+        name: String,
+    },
+    CreateView(View),
+    /// The view as it was, so the drop can be reversed
+    DropView(View),
+    CreateType(CustomType),
+    /// Irreversible, as enum labels can't be removed
+    AddEnumValue(EnumValue),
+    RawSql {
+        up: String,
+        down: Option<String>,
+    },
 }
 
 impl MigrationBuilder {
@@ -442,6 +883,21 @@ impl MigrationBuilder {
         self
     }
 
+    /// Add a truncate table operation
+    pub fn truncate_table(
+        mut self,
+        name: impl Into<String>,
+        cascade: bool,
+        restart_identity: bool,
+    ) -> Self {
+        self.operations.push(MigrationOperation::TruncateTable {
+            name: name.into(),
+            cascade,
+            restart_identity,
+        });
+        self
+    }
+
     /// Add an add column operation
     pub fn add_column(mut self, table: impl Into<String>, column: Column) -> Self {
         self.operations.push(MigrationOperation::AddColumn {
@@ -526,12 +982,29 @@ mod tests {
         assert_eq!(diff.tables_to_drop[0], "old_table");
     }
 
+    #[test]
+    fn test_schema_diff_folded_identifiers() {
+        let mut from = Schema::new();
+        from.add_table(
+            Table::new("users").column(Column::new("userid", ColumnType::BigInt).not_null()),
+        );
+        let mut to = Schema::new();
+        to.add_table(
+            Table::new("users").column(Column::new("userId", ColumnType::BigInt).not_null()),
+        );
+
+        assert!(!SchemaDiffer::new().diff(&from, &to).is_empty());
+        assert!(SchemaDiffer::new()
+            .identifiers(IdentifierPolicy::FoldLower)
+            .diff(&from, &to)
+            .is_empty());
+    }
+
     #[test]
     fn test_schema_diff_modify_table() {
         let mut from = Schema::new();
         from.add_table(
-            Table::new("users")
-                .column(Column::new("id", ColumnType::BigSerial).not_null()),
+            Table::new("users").column(Column::new("id", ColumnType::BigSerial).not_null()),
         );
 
         let mut to = Schema::new();
@@ -548,4 +1021,271 @@ mod tests {
         assert_eq!(diff.table_modifications[0].columns_to_add.len(), 1);
         assert_eq!(diff.table_modifications[0].columns_to_add[0].name, "email");
     }
+
+    #[test]
+    fn test_schema_diff_grants() {
+        let mut from = Schema::new();
+        from.add_table(
+            Table::new("users")
+                .owner("postgres")
+                .grant(Grant::new("app_ro", ["SELECT", "DELETE"]))
+                .grant(Grant::new("reporting", ["SELECT"])),
+        );
+
+        let mut to = Schema::new();
+        to.add_table(
+            Table::new("users")
+                .owner("app_owner")
+                .grant(Grant::new("app_ro", ["SELECT", "INSERT"])),
+        );
+
+        let diff = SchemaDiffer::new().diff(&from, &to);
+        let table_diff = &diff.table_modifications[0];
+        assert_eq!(table_diff.owner_to_set.as_deref(), Some("app_owner"));
+        assert_eq!(
+            table_diff.grants_to_add,
+            vec![Grant::new("app_ro", ["INSERT"])]
+        );
+        // Roles the target doesn't mention keep their privileges
+        assert_eq!(
+            table_diff.grants_to_revoke,
+            vec![Grant::new("app_ro", ["DELETE"])]
+        );
+    }
+
+    #[test]
+    fn test_schema_diff_index_options() {
+        let declared = Index::new("users_email_idx", Vec::<String>::new())
+            .expression("lower(email)")
+            .unique()
+            .include(vec!["name"])
+            .storage("fillfactor", "70");
+        let mut to_table = Table::new("users");
+        to_table.add_index(declared.clone());
+        let mut to = Schema::new();
+        to.add_table(to_table);
+
+        // As PostgreSQL reports it back
+        let introspected = Index::new("users_email_idx", Vec::<String>::new())
+            .expression("lower((email)::text)")
+            .unique()
+            .include(vec!["name"])
+            .storage("fillfactor", "70");
+        let mut from_table = Table::new("users");
+        from_table.add_index(introspected);
+        let mut from = Schema::new();
+        from.add_table(from_table.clone());
+        assert!(SchemaDiffer::new()
+            .diff(&from, &to)
+            .table_modifications
+            .is_empty());
+
+        from_table.indexes[0]
+            .storage
+            .insert("fillfactor".into(), "90".into());
+        let mut from = Schema::new();
+        from.add_table(from_table);
+        let diff = SchemaDiffer::new().diff(&from, &to);
+        let table_diff = &diff.table_modifications[0];
+        assert_eq!(
+            table_diff.indexes_to_drop,
+            vec!["users_email_idx".to_string()]
+        );
+        assert_eq!(table_diff.indexes_to_create[0].storage["fillfactor"], "70");
+    }
+
+    #[test]
+    fn test_schema_diff_deferrable() {
+        let fk = ForeignKey::new(vec!["manager_id".into()], "employees", vec!["id".into()]);
+        let mut from_table = Table::new("employees");
+        from_table.add_foreign_key(fk.clone().name("employees_manager_id_fkey"));
+        let mut from = Schema::new();
+        from.add_table(from_table);
+
+        // The unnamed key matches the one the database named
+        let mut to_table = Table::new("employees");
+        to_table.add_foreign_key(fk.clone());
+        let mut to = Schema::new();
+        to.add_table(to_table);
+        assert!(SchemaDiffer::new()
+            .diff(&from, &to)
+            .table_modifications
+            .is_empty());
+
+        let mut to_table = Table::new("employees");
+        to_table.add_foreign_key(fk.initially_deferred());
+        let mut to = Schema::new();
+        to.add_table(to_table);
+        let diff = SchemaDiffer::new().diff(&from, &to);
+        let table_diff = &diff.table_modifications[0];
+        assert_eq!(
+            table_diff.foreign_keys_to_drop,
+            vec!["employees_manager_id_fkey".to_string()]
+        );
+        let added = &table_diff.foreign_keys_to_add[0];
+        assert_eq!(added.name.as_deref(), Some("employees_manager_id_fkey"));
+        assert!(added.deferrable && added.initially_deferred);
+    }
+
+    #[test]
+    fn test_schema_diff_index_foreign_keys() {
+        let mut to_table = Table::new("posts");
+        to_table.add_foreign_key(ForeignKey::new(
+            vec!["author_id".into()],
+            "users",
+            vec!["id".into()],
+        ));
+        to_table.add_foreign_key(ForeignKey::new(
+            vec!["topic_id".into()],
+            "topics",
+            vec!["id".into()],
+        ));
+        let mut to = Schema::new();
+        to.add_table(to_table.clone());
+
+        let mut from_table = to_table;
+        from_table.add_index(Index::new(
+            "idx_posts_author",
+            vec!["author_id", "created_at"],
+        ));
+        let mut from = Schema::new();
+        from.add_table(from_table);
+
+        let diff = SchemaDiffer::new()
+            .index_foreign_keys(true)
+            .diff(&from, &to);
+        let table_diff = &diff.table_modifications[0];
+        assert!(table_diff.indexes_to_drop.is_empty());
+        let created: Vec<_> = table_diff
+            .indexes_to_create
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(created, ["posts_topic_id_idx"]);
+
+        // Without the option the undeclared index is dropped
+        let diff = SchemaDiffer::new().diff(&from, &to);
+        assert_eq!(
+            diff.table_modifications[0].indexes_to_drop,
+            ["idx_posts_author"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_expression() {
+        assert_eq!(normalize_expression("lower((email)::text)"), "lower(email)");
+        assert_eq!(normalize_expression("LOWER(email)"), "lower(email)");
+        assert_eq!(
+            normalize_expression("(first_name || ' '::text) || last_name"),
+            "(first_name||'')||last_name"
+        );
+        assert_ne!(
+            normalize_expression("lower(a) || b"),
+            normalize_expression("lower(a || b)")
+        );
+    }
+
+    #[test]
+    fn test_schema_diff_views() {
+        use chakra_core::expr::Expr;
+        use chakra_core::query::Query;
+
+        let active = |flag: bool| {
+            View::from_view(&chakra_core::view::View::define(
+                "active_users",
+                Query::select()
+                    .from("users")
+                    .columns(&["id", "name"])
+                    .filter(Expr::eq("is_active", flag))
+                    .build(),
+            ))
+        };
+        let mut to = Schema::new();
+        to.add_view(active(true));
+
+        let diff = SchemaDiffer::new().diff(&Schema::new(), &to);
+        assert_eq!(diff.views_to_create.len(), 1);
+        assert!(diff.views_to_drop.is_empty());
+
+        // How PostgreSQL returns the same definition
+        let mut from = Schema::new();
+        from.add_view(View::new(
+            "active_users",
+            ViewDefinition::Sql(
+                " SELECT users.id,\n    users.name\n   FROM users\n  WHERE (users.is_active = true);".into(),
+            ),
+        ));
+        assert!(SchemaDiffer::new().diff(&from, &to).is_empty());
+
+        // A changed definition is dropped and recreated
+        let mut changed = Schema::new();
+        changed.add_view(active(false));
+        let diff = SchemaDiffer::new().diff(&from, &changed);
+        assert_eq!(diff.views_to_drop.len(), 1);
+        assert_eq!(diff.views_to_create.len(), 1);
+
+        let diff = SchemaDiffer::new().diff(&to, &Schema::new());
+        assert_eq!(diff.views_to_drop[0].name, "active_users");
+        assert!(SchemaDiffer::new()
+            .exclude_table("active_users")
+            .diff(&to, &Schema::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unqualify() {
+        assert_eq!(unqualify("users.id, u.name AS \"x\""), "id, name AS \"x\"");
+        assert_eq!(unqualify("'a.b' || 1.5"), "'a.b' || 1.5");
+    }
+
+    #[test]
+    fn test_schema_diff_enums() {
+        let orders = |values: &[&str]| {
+            Table::new("orders").column(Column::new(
+                "status",
+                ColumnType::Enum {
+                    name: "order_status".into(),
+                    values: values.iter().map(|v| v.to_string()).collect(),
+                },
+            ))
+        };
+        let mut to = Schema::new();
+        to.add_table(orders(&["pending", "shipped"]));
+
+        let diff = SchemaDiffer::new().diff(&Schema::new(), &to);
+        assert!(
+            matches!(&diff.types_to_create[..], [CustomType::Enum { name, .. }] if name == "order_status")
+        );
+        let ddl = diff.to_ddl(&crate::ddl::PostgresDdlGenerator);
+        assert!(ddl[0].sql.starts_with("CREATE TYPE"));
+        assert!(ddl[1].sql.starts_with("CREATE TABLE"));
+
+        // As introspected: the column reports its type by name
+        let mut from = Schema::new();
+        from.add_table(Table::new("orders").column(Column::new(
+            "status",
+            ColumnType::Custom("order_status".into()),
+        )));
+        from.add_type(CustomType::Enum {
+            name: "order_status".into(),
+            values: vec!["shipped".into()],
+        });
+        let diff = SchemaDiffer::new().diff(&from, &to);
+        assert!(diff.table_modifications.is_empty());
+        assert!(diff.types_to_create.is_empty());
+        assert_eq!(
+            diff.enum_values_to_add,
+            [EnumValue {
+                type_name: "order_status".into(),
+                value: "pending".into(),
+                after: None,
+            }]
+        );
+
+        let mut extended = Schema::new();
+        extended.add_table(orders(&["pending", "shipped", "lost"]));
+        let diff = SchemaDiffer::new().diff(&to, &extended);
+        assert_eq!(diff.enum_values_to_add[0].after.as_deref(), Some("shipped"));
+        assert!(diff.table_modifications.is_empty());
+    }
 }
@@ -3,12 +3,13 @@
 //! This module provides traits and implementations for introspecting database schemas.
 
 use crate::schema::{
-    Column, ColumnDefault, ColumnType, Constraint, ConstraintType, ForeignKey, Index,
+    Column, ColumnDefault, ColumnType, Constraint, ConstraintType, ForeignKey, Grant, Index,
     IndexColumn, IndexOrder, NullsOrder, PrimaryKey, Schema, Table,
 };
 use async_trait::async_trait;
 use chakra_core::error::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Trait for schema introspection
 #[async_trait]
@@ -96,15 +97,24 @@ pub struct RawIndexInfo {
     pub index_type: Option<String>,
     pub columns: Vec<RawIndexColumnInfo>,
     pub where_clause: Option<String>,
+    /// Non-key `INCLUDE` columns
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Storage parameters as `name=value`, as in `pg_class.reloptions`
+    #[serde(default)]
+    pub storage: Vec<String>,
 }
 
 /// Raw index column information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawIndexColumnInfo {
+    /// Column name, or the expression's SQL for expression keys
     pub column_name: String,
     pub ordinal_position: i32,
     pub sort_order: Option<String>,
     pub nulls_order: Option<String>,
+    #[serde(default)]
+    pub is_expression: bool,
 }
 
 impl RawIndexInfo {
@@ -117,6 +127,7 @@ impl RawIndexInfo {
                 .iter()
                 .map(|c| IndexColumn {
                     name: c.column_name.clone(),
+                    expression: c.is_expression,
                     order: c.sort_order.as_ref().and_then(|o| match o.as_str() {
                         "ASC" => Some(IndexOrder::Asc),
                         "DESC" => Some(IndexOrder::Desc),
@@ -132,6 +143,8 @@ impl RawIndexInfo {
             unique: self.is_unique,
             method: self.index_type.clone(),
             where_clause: self.where_clause.clone(),
+            include: self.include.clone(),
+            storage: parse_storage(&self.storage),
         }
     }
 }
@@ -148,6 +161,10 @@ pub struct RawConstraintInfo {
     pub references_columns: Option<Vec<String>>,
     pub on_delete: Option<String>,
     pub on_update: Option<String>,
+    #[serde(default)]
+    pub is_deferrable: bool,
+    #[serde(default)]
+    pub initially_deferred: bool,
 }
 
 impl RawConstraintInfo {
@@ -165,21 +182,26 @@ impl RawConstraintInfo {
 
     /// Convert to Constraint if applicable
     pub fn to_constraint(&self) -> Option<Constraint> {
-        match self.constraint_type.as_str() {
-            "UNIQUE" => Some(Constraint {
-                name: self.constraint_name.clone(),
-                constraint_type: ConstraintType::Unique {
+        let constraint = match self.constraint_type.as_str() {
+            "UNIQUE" => Constraint::new(
+                &self.constraint_name,
+                ConstraintType::Unique {
                     columns: self.columns.clone(),
                 },
-            }),
-            "CHECK" => self.check_expression.as_ref().map(|expr| Constraint {
-                name: self.constraint_name.clone(),
-                constraint_type: ConstraintType::Check {
-                    expression: expr.clone(),
+            ),
+            "CHECK" => Constraint::new(
+                &self.constraint_name,
+                ConstraintType::Check {
+                    expression: self.check_expression.clone()?,
                 },
-            }),
-            _ => None,
-        }
+            ),
+            _ => return None,
+        };
+        Some(Constraint {
+            deferrable: self.is_deferrable,
+            initially_deferred: self.initially_deferred,
+            ..constraint
+        })
     }
 
     /// Convert to ForeignKey if applicable
@@ -192,6 +214,8 @@ impl RawConstraintInfo {
                 references_columns: self.references_columns.clone().unwrap_or_default(),
                 on_delete: parse_fk_action(self.on_delete.as_deref()),
                 on_update: parse_fk_action(self.on_update.as_deref()),
+                deferrable: self.is_deferrable,
+                initially_deferred: self.initially_deferred,
             })
         } else {
             None
@@ -199,6 +223,79 @@ impl RawConstraintInfo {
     }
 }
 
+/// Parse a PostgreSQL ACL, e.g. `{app_ro=r/postgres,=r/postgres}`, into
+/// grants per role
+///
+/// An empty grantee is `PUBLIC`; grant options (`*`) are not kept.
+pub fn parse_acl<S: AsRef<str>>(items: &[S]) -> Vec<Grant> {
+    let mut grants: Vec<Grant> = Vec::new();
+    for item in items {
+        let Some((grantee, privileges)) = parse_acl_item(item.as_ref()) else {
+            continue;
+        };
+        match grants.iter_mut().find(|g| g.grantee == grantee) {
+            Some(grant) => grant
+                .privileges
+                .extend(privileges.into_iter().map(String::from)),
+            None => grants.push(Grant::new(grantee, privileges)),
+        }
+    }
+    grants
+}
+
+/// Parse one `grantee=privileges/grantor` ACL entry
+fn parse_acl_item(item: &str) -> Option<(String, Vec<&'static str>)> {
+    let (grantee, rest) = item.rsplit_once('=')?;
+    let privileges = rest.split('/').next().unwrap_or("");
+    let grantee = match grantee.trim_matches('"') {
+        "" => "PUBLIC".to_string(),
+        name => name.replace("\"\"", "\""),
+    };
+    let privileges = privileges
+        .chars()
+        .filter_map(|c| {
+            Some(match c {
+                'r' => "SELECT",
+                'a' => "INSERT",
+                'w' => "UPDATE",
+                'd' => "DELETE",
+                'D' => "TRUNCATE",
+                'x' => "REFERENCES",
+                't' => "TRIGGER",
+                'm' => "MAINTAIN",
+                'U' => "USAGE",
+                'X' => "EXECUTE",
+                'C' => "CREATE",
+                'c' => "CONNECT",
+                'T' => "TEMPORARY",
+                _ => return None,
+            })
+        })
+        .collect();
+    Some((grantee, privileges))
+}
+
+/// Parse storage parameters like `{fillfactor=70}` entries into a map
+pub fn parse_storage<S: AsRef<str>>(options: &[S]) -> BTreeMap<String, String> {
+    options
+        .iter()
+        .filter_map(|option| option.as_ref().split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The object kind of a `pg_default_acl.defaclobjtype` code
+pub fn default_acl_object_type(code: &str) -> &'static str {
+    match code {
+        "r" => "TABLES",
+        "S" => "SEQUENCES",
+        "f" => "FUNCTIONS",
+        "T" => "TYPES",
+        "n" => "SCHEMAS",
+        _ => "UNKNOWN",
+    }
+}
+
 /// Parse column type from database type string
 fn parse_column_type(
     data_type: &str,
@@ -247,6 +344,22 @@ fn parse_column_type(
         "JSON" => ColumnType::Json,
         "JSONB" => ColumnType::Jsonb,
         "BYTEA" | "BLOB" => ColumnType::Bytea,
+        "INET" => ColumnType::Inet,
+        "CIDR" => ColumnType::Cidr,
+        "MACADDR" => ColumnType::MacAddr,
+        "INT4RANGE" => ColumnType::Range(Box::new(ColumnType::Integer)),
+        "INT8RANGE" => ColumnType::Range(Box::new(ColumnType::BigInt)),
+        "NUMRANGE" => ColumnType::Range(Box::new(ColumnType::Decimal {
+            precision: 18,
+            scale: 2,
+        })),
+        "TSRANGE" => ColumnType::Range(Box::new(ColumnType::Timestamp {
+            with_timezone: false,
+        })),
+        "TSTZRANGE" => ColumnType::Range(Box::new(ColumnType::Timestamp {
+            with_timezone: true,
+        })),
+        "DATERANGE" => ColumnType::Range(Box::new(ColumnType::Date)),
         _ if dt.ends_with("[]") => {
             let inner = &dt[..dt.len() - 2];
             ColumnType::Array(Box::new(parse_column_type(
@@ -312,6 +425,25 @@ mod tests {
                 scale: 2
             }
         );
+        assert_eq!(
+            parse_column_type("daterange", None, None, None),
+            ColumnType::Range(Box::new(ColumnType::Date))
+        );
+    }
+
+    #[test]
+    fn test_parse_acl() {
+        let grants = parse_acl(&[
+            "postgres=arwdDxt/postgres",
+            "app_ro=r/postgres",
+            "=r*/postgres",
+            "\"Audit Team\"=ra/postgres",
+        ]);
+        assert_eq!(grants.len(), 4);
+        assert!(grants[0].is_all());
+        assert_eq!(grants[1], Grant::new("app_ro", ["SELECT"]));
+        assert_eq!(grants[2], Grant::new("PUBLIC", ["SELECT"]));
+        assert_eq!(grants[3], Grant::new("Audit Team", ["SELECT", "INSERT"]));
     }
 
     #[test]
@@ -4,16 +4,20 @@
 //! - Schema introspection from databases
 //! - DDL generation for schema changes
 //! - Schema comparison and diff generation
+//! - Row-level data comparison between databases
+//! - Model and type generation from schemas
 //! - Database-agnostic schema representation
 
+pub mod codegen;
+pub mod data_diff;
 pub mod ddl;
 pub mod diff;
 pub mod introspect;
 pub mod schema;
 
-pub use ddl::{DdlGenerator, DdlStatement};
+pub use codegen::GenerationManifest;
+pub use data_diff::TableDataDiff;
+pub use ddl::{DdlGenerator, DdlStatement, Safety};
 pub use diff::{SchemaDiff, SchemaDiffer};
 pub use introspect::SchemaIntrospector;
-pub use schema::{
-    Column, Constraint, ConstraintType, ForeignKey, Index, Schema, Table,
-};
+pub use schema::{Column, Constraint, ConstraintType, ForeignKey, Grant, Index, Schema, Table};
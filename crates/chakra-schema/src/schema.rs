@@ -2,10 +2,14 @@
 //!
 //! This module provides database-agnostic schema representation.
 
-use chakra_core::model::ForeignKeyAction;
+use crate::ddl::{DdlGenerator, DdlStatement};
+use chakra_core::identifier::IdentifierPolicy;
+use chakra_core::model::{ConstraintMeta, FieldDefault, ForeignKeyAction, ModelMeta};
+use chakra_core::query::Query;
+use chakra_core::sql::Dialect;
 use chakra_core::types::FieldType;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// A complete database schema
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -18,6 +22,12 @@ pub struct Schema {
     pub types: HashMap<String, CustomType>,
     /// Extensions (PostgreSQL-specific)
     pub extensions: Vec<String>,
+    /// Privileges applied to objects created later (PostgreSQL-specific)
+    #[serde(default)]
+    pub default_privileges: Vec<DefaultPrivilege>,
+    /// Views in the schema
+    #[serde(default)]
+    pub views: HashMap<String, View>,
 }
 
 impl Schema {
@@ -34,11 +44,25 @@ impl Schema {
         }
     }
 
-    /// Add a table
+    /// Add a table, and the enum types its columns use
     pub fn add_table(&mut self, table: Table) {
+        for column in &table.columns {
+            if let ColumnType::Enum { name, values } = &column.column_type {
+                self.add_type(CustomType::Enum {
+                    name: name.clone(),
+                    values: values.clone(),
+                });
+            }
+        }
         self.tables.insert(table.name.clone(), table);
     }
 
+    /// Add a custom type
+    pub fn add_type(&mut self, custom_type: CustomType) {
+        self.types
+            .insert(custom_type.name().to_string(), custom_type);
+    }
+
     /// Get a table by name
     pub fn get_table(&self, name: &str) -> Option<&Table> {
         self.tables.get(name)
@@ -63,6 +87,200 @@ impl Schema {
     pub fn table_names(&self) -> Vec<&str> {
         self.tables.keys().map(|s| s.as_str()).collect()
     }
+
+    /// Add a view
+    pub fn add_view(&mut self, view: View) {
+        self.views.insert(view.name.clone(), view);
+    }
+
+    /// Get a view by name
+    pub fn get_view(&self, name: &str) -> Option<&View> {
+        self.views.get(name)
+    }
+
+    /// Build the schema described by a set of models
+    ///
+    /// Models over a view add no table; their views are added separately.
+    pub fn from_models(models: &[ModelMeta]) -> Self {
+        let mut schema = Self::new();
+        for model in models.iter().filter(|m| !m.view) {
+            schema.add_table(Table::from_model(model));
+        }
+        schema
+    }
+
+    /// Foreign keys no index covers, by table name
+    ///
+    /// Deleting or updating a referenced row, and joining on the key, scans
+    /// the whole referencing table when nothing indexes its columns.
+    pub fn unindexed_foreign_keys(&self) -> Vec<(&Table, &ForeignKey)> {
+        let mut tables: Vec<&Table> = self.tables.values().collect();
+        tables.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        tables
+            .into_iter()
+            .flat_map(|t| {
+                t.unindexed_foreign_keys()
+                    .into_iter()
+                    .map(move |fk| (t, fk))
+            })
+            .collect()
+    }
+
+    /// Add an index for every foreign key none covers, keeping the covering
+    /// indexes `existing`, the current schema, already has
+    pub fn index_foreign_keys(&mut self, existing: Option<&Schema>) {
+        for table in self.tables.values_mut() {
+            let current = existing.and_then(|s| s.get_table(&table.name));
+            table.index_foreign_keys(current);
+        }
+    }
+
+    /// Rename tables, columns and the names referring to them the way
+    /// `policy` stores them, so models compare with introspected schemas
+    pub fn fold_identifiers(&mut self, policy: IdentifierPolicy) {
+        if policy != IdentifierPolicy::FoldLower {
+            return;
+        }
+        let fold = |name: &mut String| *name = policy.fold(name).into_owned();
+        let fold_all = |names: &mut Vec<String>| names.iter_mut().for_each(fold);
+
+        let tables = std::mem::take(&mut self.tables);
+        for (_, mut table) in tables {
+            fold(&mut table.name);
+            table.columns.iter_mut().for_each(|c| fold(&mut c.name));
+            if let Some(pk) = &mut table.primary_key {
+                fold_all(&mut pk.columns);
+            }
+            for index in &mut table.indexes {
+                fold(&mut index.name);
+                index
+                    .columns
+                    .iter_mut()
+                    .filter(|c| !c.expression)
+                    .for_each(|c| fold(&mut c.name));
+                fold_all(&mut index.include);
+            }
+            for constraint in &mut table.constraints {
+                fold(&mut constraint.name);
+                if let ConstraintType::Unique { columns } = &mut constraint.constraint_type {
+                    fold_all(columns);
+                }
+            }
+            for fk in &mut table.foreign_keys {
+                fold_all(&mut fk.columns);
+                fold(&mut fk.references_table);
+                fold_all(&mut fk.references_columns);
+            }
+            self.tables.insert(table.name.clone(), table);
+        }
+        let views = std::mem::take(&mut self.views);
+        for (_, mut view) in views {
+            fold(&mut view.name);
+            self.views.insert(view.name.clone(), view);
+        }
+    }
+
+    /// Tables ordered so each comes after the tables it references
+    ///
+    /// Ties are broken by name. Tables in a reference cycle come last.
+    pub fn creation_order(&self) -> Vec<&Table> {
+        let mut names: Vec<&str> = self.table_names();
+        names.sort_unstable();
+
+        let mut placed: HashSet<&str> = HashSet::new();
+        let mut order = Vec::new();
+        loop {
+            let ready: Vec<&str> = names
+                .iter()
+                .copied()
+                .filter(|name| {
+                    !placed.contains(name)
+                        && self.tables[*name].foreign_keys.iter().all(|fk| {
+                            let target = fk.references_table.as_str();
+                            target == *name || placed.contains(target) || !self.has_table(target)
+                        })
+                })
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for name in ready {
+                placed.insert(name);
+                order.push(&self.tables[name]);
+            }
+        }
+
+        order.extend(
+            names
+                .iter()
+                .filter(|name| !placed.contains(*name))
+                .map(|name| &self.tables[*name]),
+        );
+        order
+    }
+
+    /// Statements creating every type, table, index, foreign key and view
+    /// from scratch
+    ///
+    /// Types come first, by name. Foreign keys that point at a table not yet
+    /// created, i.e. within a reference cycle, are added once all tables
+    /// exist. Ownership and grants come next, and views, by name, last.
+    pub fn create_statements(&self, generator: &dyn DdlGenerator) -> Vec<DdlStatement> {
+        let mut types: Vec<&CustomType> = self.types.values().collect();
+        types.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+        let mut statements: Vec<DdlStatement> = types
+            .into_iter()
+            .map(|t| generator.create_type(t))
+            .collect();
+        let mut deferred = Vec::new();
+        let mut created: HashSet<&str> = HashSet::new();
+
+        for source in self.creation_order() {
+            let mut table = source.clone();
+            let (inline, later): (Vec<_>, Vec<_>) = std::mem::take(&mut table.foreign_keys)
+                .into_iter()
+                .partition(|fk| {
+                    let target = fk.references_table.as_str();
+                    generator.inline_foreign_keys()
+                        && (target == table.name
+                            || created.contains(target)
+                            || !self.has_table(target))
+                });
+            table.foreign_keys = inline;
+
+            statements.push(generator.create_table(&table));
+            for index in &table.indexes {
+                statements.push(generator.create_index(&table.name, index));
+            }
+            deferred.extend(later.into_iter().map(|fk| (table.name.clone(), fk)));
+            created.insert(source.name.as_str());
+        }
+
+        for (table, fk) in &deferred {
+            statements.push(generator.add_foreign_key(table, fk));
+        }
+        for table in self.creation_order() {
+            if let Some(owner) = &table.owner {
+                statements.push(generator.alter_owner(&table.name, owner));
+            }
+            for grant in &table.grants {
+                statements.push(generator.grant(&table.name, grant));
+            }
+        }
+        let mut views: Vec<&View> = self.views.values().collect();
+        views.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        statements.extend(views.into_iter().map(|view| generator.create_view(view)));
+        statements
+    }
+
+    /// The `create_statements` as one script
+    pub fn to_create_sql(&self, generator: &dyn DdlGenerator) -> String {
+        self.create_statements(generator)
+            .iter()
+            .map(|stmt| format!("{};\n", stmt.sql))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 /// A database table
@@ -84,6 +302,12 @@ pub struct Table {
     pub foreign_keys: Vec<ForeignKey>,
     /// Table comment
     pub comment: Option<String>,
+    /// Owning role
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Privileges granted to other roles
+    #[serde(default)]
+    pub grants: Vec<Grant>,
 }
 
 impl Table {
@@ -98,6 +322,8 @@ impl Table {
             constraints: Vec::new(),
             foreign_keys: Vec::new(),
             comment: None,
+            owner: None,
+            grants: Vec::new(),
         }
     }
 
@@ -124,6 +350,18 @@ impl Table {
         self
     }
 
+    /// Set the owning role
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Grant privileges to a role
+    pub fn grant(mut self, grant: Grant) -> Self {
+        self.grants.push(grant);
+        self
+    }
+
     /// Add an index
     pub fn add_index(&mut self, index: Index) {
         self.indexes.push(index);
@@ -149,6 +387,162 @@ impl Table {
         self.columns.iter_mut().find(|c| c.name == name)
     }
 
+    /// Whether lookups by `columns` can use an index: the primary key, a
+    /// unique constraint or a plain index has them as its leading columns
+    pub fn has_index_on(&self, columns: &[String]) -> bool {
+        let leads = |keys: &[String]| {
+            keys.len() >= columns.len() && columns.iter().all(|c| keys[..columns.len()].contains(c))
+        };
+        self.primary_key
+            .as_ref()
+            .is_some_and(|pk| leads(&pk.columns))
+            || self.constraints.iter().any(|c| match &c.constraint_type {
+                ConstraintType::Unique { columns } => leads(columns),
+                _ => false,
+            })
+            || self.covering_index(columns).is_some()
+    }
+
+    /// A full, column-only index leading with `columns`
+    fn covering_index(&self, columns: &[String]) -> Option<&Index> {
+        self.indexes.iter().find(|index| {
+            index.where_clause.is_none()
+                && index.columns.len() >= columns.len()
+                && index.columns[..columns.len()]
+                    .iter()
+                    .all(|key| !key.expression && columns.contains(&key.name))
+        })
+    }
+
+    /// Foreign keys whose columns no index covers
+    pub fn unindexed_foreign_keys(&self) -> Vec<&ForeignKey> {
+        self.foreign_keys
+            .iter()
+            .filter(|fk| !self.has_index_on(&fk.columns))
+            .collect()
+    }
+
+    /// Add an index named `{table}_{columns}_idx` for every foreign key none
+    /// covers, or the covering index `existing`, the table as it is now, has
+    pub fn index_foreign_keys(&mut self, existing: Option<&Table>) {
+        let missing: Vec<Vec<String>> = self
+            .unindexed_foreign_keys()
+            .iter()
+            .map(|fk| fk.columns.clone())
+            .collect();
+        for columns in missing {
+            if self.has_index_on(&columns) {
+                // Two keys on the same columns
+                continue;
+            }
+            let index = match existing.and_then(|t| t.covering_index(&columns)) {
+                Some(index) => index.clone(),
+                None => Index::new(format!("{}_{}_idx", self.name, columns.join("_")), columns),
+            };
+            self.add_index(index);
+        }
+    }
+
+    /// Build the table for a model, with its indexes, constraints and
+    /// foreign keys
+    pub fn from_model(model: &ModelMeta) -> Self {
+        let mut table = Table::new(&model.table);
+        table.schema = model.schema.clone();
+
+        for field in &model.fields {
+            let mut column = Column::new(
+                field.column_name(),
+                ColumnType::from_field_type(&field.field_type),
+            );
+            column.nullable = field.nullable;
+            column.auto_increment = field.auto_increment;
+            column.default = field
+                .default
+                .as_ref()
+                .and_then(ColumnDefault::from_field_default);
+            table.add_column(column);
+
+            if field.unique && !field.primary_key {
+                table.add_constraint(Constraint::new(
+                    format!("{}_{}_key", model.table, field.column_name()),
+                    ConstraintType::Unique {
+                        columns: vec![field.column_name().to_string()],
+                    },
+                ));
+            }
+            if let Some(ref fk) = field.foreign_key {
+                table.add_foreign_key(
+                    ForeignKey::new(
+                        vec![field.column_name().to_string()],
+                        &fk.table,
+                        vec![fk.column.clone()],
+                    )
+                    .on_delete(fk.on_delete.clone())
+                    .on_update(fk.on_update.clone()),
+                );
+            }
+        }
+
+        if !model.primary_key.is_empty() {
+            table.primary_key = Some(PrimaryKey::new(model.primary_key.clone()));
+        }
+
+        for index_meta in &model.indexes {
+            let mut index = Index::new(&index_meta.name, index_meta.columns.clone());
+            index.unique = index_meta.unique;
+            index.where_clause = index_meta.where_clause.clone();
+            index
+                .columns
+                .extend(index_meta.expressions.iter().map(IndexColumn::expression));
+            index.include = index_meta.include.clone();
+            index.storage = index_meta.storage.clone();
+            if index_meta.descending {
+                for column in &mut index.columns {
+                    column.order = Some(IndexOrder::Desc);
+                }
+            }
+            table.add_index(index);
+        }
+
+        for constraint in &model.constraints {
+            match constraint {
+                ConstraintMeta::Unique { name, columns } => table.add_constraint(Constraint::new(
+                    name,
+                    ConstraintType::Unique {
+                        columns: columns.clone(),
+                    },
+                )),
+                ConstraintMeta::Check { name, expression } => {
+                    table.add_constraint(Constraint::new(
+                        name,
+                        ConstraintType::Check {
+                            expression: expression.clone(),
+                        },
+                    ))
+                }
+                ConstraintMeta::ForeignKey {
+                    name,
+                    columns,
+                    references_table,
+                    references_columns,
+                    on_delete,
+                    on_update,
+                } => table.add_foreign_key(
+                    ForeignKey::new(
+                        columns.clone(),
+                        references_table,
+                        references_columns.clone(),
+                    )
+                    .name(name)
+                    .on_delete(on_delete.clone())
+                    .on_update(on_update.clone()),
+                ),
+            }
+        }
+
+        table
+    }
+
     /// Get qualified name (schema.table)
     pub fn qualified_name(&self) -> String {
         match &self.schema {
@@ -264,10 +658,20 @@ pub enum ColumnType {
     Jsonb,
     /// Binary data
     Bytea,
+    /// IP address, optionally with a network prefix
+    Inet,
+    /// IP network
+    Cidr,
+    /// MAC address
+    MacAddr,
     /// Array of another type
     Array(Box<ColumnType>),
-    /// Custom/enum type
+    /// Range of integers, decimals, dates or timestamps
+    Range(Box<ColumnType>),
+    /// Custom type, such as an introspected enum
     Custom(String),
+    /// Enum type, stored as a CHECK-constrained TEXT on SQLite
+    Enum { name: String, values: Vec<String> },
     /// Serial (auto-increment integer)
     Serial,
     /// Big serial (auto-increment big integer)
@@ -288,23 +692,40 @@ impl ColumnType {
             FieldType::Float => ColumnType::Real,
             FieldType::Double => ColumnType::DoublePrecision,
             FieldType::Char { length } => ColumnType::Char(*length as u32),
-            FieldType::String { max_length } => {
-                ColumnType::Varchar(max_length.map(|l| l as u32))
-            }
+            FieldType::String { max_length } => ColumnType::Varchar(max_length.map(|l| l as u32)),
             FieldType::Text => ColumnType::Text,
             FieldType::Boolean => ColumnType::Boolean,
             FieldType::Date => ColumnType::Date,
-            FieldType::Time => ColumnType::Time { with_timezone: false },
-            FieldType::Timestamp => ColumnType::Timestamp { with_timezone: false },
-            FieldType::TimestampTz => ColumnType::Timestamp { with_timezone: true },
+            FieldType::Time => ColumnType::Time {
+                with_timezone: false,
+            },
+            FieldType::Timestamp => ColumnType::Timestamp {
+                with_timezone: false,
+            },
+            FieldType::TimestampTz => ColumnType::Timestamp {
+                with_timezone: true,
+            },
             FieldType::Uuid => ColumnType::Uuid,
             FieldType::Json => ColumnType::Json,
             FieldType::JsonB => ColumnType::Jsonb,
             FieldType::Binary { .. } => ColumnType::Bytea,
+            FieldType::Inet => ColumnType::Inet,
+            FieldType::Cidr => ColumnType::Cidr,
+            FieldType::MacAddr => ColumnType::MacAddr,
             FieldType::Array { element_type } => {
                 ColumnType::Array(Box::new(ColumnType::from_field_type(element_type)))
             }
-            FieldType::Enum { .. } => ColumnType::Text, // Simplified for now
+            FieldType::Range { element_type } => {
+                ColumnType::Range(Box::new(ColumnType::from_field_type(element_type)))
+            }
+            FieldType::Enum {
+                name: Some(name),
+                values,
+            } => ColumnType::Enum {
+                name: name.clone(),
+                values: values.clone(),
+            },
+            FieldType::Enum { name: None, .. } => ColumnType::Text,
         }
     }
 
@@ -344,8 +765,25 @@ impl ColumnType {
             ColumnType::Json => "JSON".to_string(),
             ColumnType::Jsonb => "JSONB".to_string(),
             ColumnType::Bytea => "BYTEA".to_string(),
+            ColumnType::Inet => "INET".to_string(),
+            ColumnType::Cidr => "CIDR".to_string(),
+            ColumnType::MacAddr => "MACADDR".to_string(),
             ColumnType::Array(inner) => format!("{}[]", inner.to_postgres_sql()),
+            ColumnType::Range(inner) => match **inner {
+                ColumnType::SmallInt | ColumnType::Integer | ColumnType::Serial => "INT4RANGE",
+                ColumnType::BigInt | ColumnType::BigSerial => "INT8RANGE",
+                ColumnType::Timestamp {
+                    with_timezone: false,
+                } => "TSRANGE",
+                ColumnType::Timestamp {
+                    with_timezone: true,
+                } => "TSTZRANGE",
+                ColumnType::Date => "DATERANGE",
+                _ => "NUMRANGE",
+            }
+            .to_string(),
             ColumnType::Custom(name) => name.clone(),
+            ColumnType::Enum { name, .. } => crate::ddl::quote_identifier(name),
             ColumnType::Serial => "SERIAL".to_string(),
             ColumnType::BigSerial => "BIGSERIAL".to_string(),
         }
@@ -375,8 +813,16 @@ impl ColumnType {
             ColumnType::Json => "JSON".to_string(),
             ColumnType::Jsonb => "JSON".to_string(),
             ColumnType::Bytea => "BLOB".to_string(),
+            // Longest text forms, an IPv6 address with a prefix and a MAC address
+            ColumnType::Inet | ColumnType::Cidr => "VARCHAR(43)".to_string(),
+            ColumnType::MacAddr => "CHAR(17)".to_string(),
             ColumnType::Array(_) => "JSON".to_string(), // MySQL uses JSON for arrays
+            ColumnType::Range(_) => "VARCHAR(255)".to_string(),
             ColumnType::Custom(name) => name.clone(),
+            ColumnType::Enum { values, .. } => {
+                let values: Vec<String> = values.iter().map(|v| quote_literal(v)).collect();
+                format!("ENUM({})", values.join(", "))
+            }
             ColumnType::Serial => "INT AUTO_INCREMENT".to_string(),
             ColumnType::BigSerial => "BIGINT AUTO_INCREMENT".to_string(),
         }
@@ -400,13 +846,21 @@ impl ColumnType {
             ColumnType::Uuid => "TEXT".to_string(),
             ColumnType::Json | ColumnType::Jsonb => "TEXT".to_string(),
             ColumnType::Bytea => "BLOB".to_string(),
+            ColumnType::Inet | ColumnType::Cidr | ColumnType::MacAddr => "TEXT".to_string(),
             ColumnType::Array(_) => "TEXT".to_string(), // SQLite uses JSON text for arrays
+            ColumnType::Range(_) => "TEXT".to_string(),
             ColumnType::Custom(name) => name.clone(),
+            ColumnType::Enum { .. } => "TEXT".to_string(),
             ColumnType::Serial | ColumnType::BigSerial => "INTEGER".to_string(),
         }
     }
 }
 
+/// A single-quoted SQL string literal
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
 /// Default value for a column
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ColumnDefault {
@@ -429,6 +883,23 @@ pub enum ColumnDefault {
 }
 
 impl ColumnDefault {
+    /// Convert a model field default
+    ///
+    /// Auto-increment is part of the column definition, and ids generated
+    /// client-side only get a default when the database can produce the
+    /// same kind of id.
+    pub fn from_field_default(default: &FieldDefault) -> Option<Self> {
+        match default {
+            FieldDefault::Value(value) => Some(ColumnDefault::Expression(value.to_sql_literal())),
+            FieldDefault::Expression(expr) => Some(ColumnDefault::Expression(expr.clone())),
+            FieldDefault::AutoIncrement => None,
+            FieldDefault::Uuid => Some(ColumnDefault::GenerateUuid),
+            FieldDefault::Generated(strategy) => strategy
+                .has_database_default()
+                .then_some(ColumnDefault::GenerateUuid),
+        }
+    }
+
     /// Get SQL representation
     pub fn to_sql(&self) -> String {
         match self {
@@ -496,6 +967,12 @@ pub struct Index {
     pub method: Option<String>,
     /// Partial index condition
     pub where_clause: Option<String>,
+    /// Non-key columns stored in the index (PostgreSQL `INCLUDE`)
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Storage parameters, e.g. `fillfactor` (PostgreSQL `WITH`)
+    #[serde(default)]
+    pub storage: BTreeMap<String, String>,
 }
 
 impl Index {
@@ -503,20 +980,33 @@ impl Index {
     pub fn new(name: impl Into<String>, columns: Vec<impl Into<String>>) -> Self {
         Self {
             name: name.into(),
-            columns: columns
-                .into_iter()
-                .map(|c| IndexColumn {
-                    name: c.into(),
-                    order: None,
-                    nulls: None,
-                })
-                .collect(),
+            columns: columns.into_iter().map(IndexColumn::new).collect(),
             unique: false,
             method: None,
             where_clause: None,
+            include: Vec::new(),
+            storage: BTreeMap::new(),
         }
     }
 
+    /// Add an expression key, e.g. `lower(email)`
+    pub fn expression(mut self, expression: impl Into<String>) -> Self {
+        self.columns.push(IndexColumn::expression(expression));
+        self
+    }
+
+    /// Store `columns` in the index without making them part of the key
+    pub fn include(mut self, columns: Vec<impl Into<String>>) -> Self {
+        self.include.extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set a storage parameter, e.g. `storage("fillfactor", "70")`
+    pub fn storage(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage.insert(name.into(), value.into());
+        self
+    }
+
     /// Set unique
     pub fn unique(mut self) -> Self {
         self.unique = true;
@@ -539,12 +1029,35 @@ impl Index {
 /// Column in an index
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexColumn {
-    /// Column name
+    /// Column name, or the SQL expression if `expression` is set
     pub name: String,
     /// Sort order
     pub order: Option<IndexOrder>,
     /// Nulls ordering
     pub nulls: Option<NullsOrder>,
+    /// Whether `name` is an expression like `lower(email)`
+    #[serde(default)]
+    pub expression: bool,
+}
+
+impl IndexColumn {
+    /// A plain column
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            order: None,
+            nulls: None,
+            expression: false,
+        }
+    }
+
+    /// An expression, written as SQL
+    pub fn expression(expression: impl Into<String>) -> Self {
+        Self {
+            expression: true,
+            ..Self::new(expression)
+        }
+    }
 }
 
 /// Index sort order
@@ -568,6 +1081,37 @@ pub struct Constraint {
     pub name: String,
     /// Constraint type
     pub constraint_type: ConstraintType,
+    /// Checking can be deferred to commit (PostgreSQL, not for CHECK)
+    #[serde(default)]
+    pub deferrable: bool,
+    /// Deferred unless `SET CONSTRAINTS ... IMMEDIATE`
+    #[serde(default)]
+    pub initially_deferred: bool,
+}
+
+impl Constraint {
+    /// Create a constraint checked immediately
+    pub fn new(name: impl Into<String>, constraint_type: ConstraintType) -> Self {
+        Self {
+            name: name.into(),
+            constraint_type,
+            deferrable: false,
+            initially_deferred: false,
+        }
+    }
+
+    /// Allow deferring the check with `SET CONSTRAINTS ... DEFERRED`
+    pub fn deferrable(mut self) -> Self {
+        self.deferrable = true;
+        self
+    }
+
+    /// Check at commit unless set immediate
+    pub fn initially_deferred(mut self) -> Self {
+        self.deferrable = true;
+        self.initially_deferred = true;
+        self
+    }
 }
 
 /// Constraint types
@@ -596,6 +1140,12 @@ pub struct ForeignKey {
     pub on_delete: ForeignKeyAction,
     /// On update action
     pub on_update: ForeignKeyAction,
+    /// Checking can be deferred to commit
+    #[serde(default)]
+    pub deferrable: bool,
+    /// Deferred unless `SET CONSTRAINTS ... IMMEDIATE`
+    #[serde(default)]
+    pub initially_deferred: bool,
 }
 
 impl ForeignKey {
@@ -612,6 +1162,8 @@ impl ForeignKey {
             references_columns,
             on_delete: ForeignKeyAction::NoAction,
             on_update: ForeignKeyAction::NoAction,
+            deferrable: false,
+            initially_deferred: false,
         }
     }
 
@@ -632,16 +1184,130 @@ impl ForeignKey {
         self.on_update = action;
         self
     }
+
+    /// Allow deferring the check with `SET CONSTRAINTS ... DEFERRED`, e.g.
+    /// to insert rows that reference each other
+    pub fn deferrable(mut self) -> Self {
+        self.deferrable = true;
+        self
+    }
+
+    /// Check at commit unless set immediate
+    pub fn initially_deferred(mut self) -> Self {
+        self.deferrable = true;
+        self.initially_deferred = true;
+        self
+    }
 }
 
-/// Custom type (enum, composite, etc.)
+/// Table privileges that can be granted, besides `ALL`
+pub const TABLE_PRIVILEGES: &[&str] = &[
+    "SELECT",
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+    "TRUNCATE",
+    "REFERENCES",
+    "TRIGGER",
+];
+
+/// Privileges granted to a role
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    /// Receiving role, or `PUBLIC`
+    pub grantee: String,
+    /// Privileges such as `SELECT`, upper case
+    pub privileges: Vec<String>,
+}
+
+impl Grant {
+    /// Grant privileges to a role
+    pub fn new<I, S>(grantee: impl Into<String>, privileges: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            grantee: grantee.into(),
+            privileges: privileges
+                .into_iter()
+                .map(|p| p.as_ref().trim().to_uppercase())
+                .collect(),
+        }
+    }
+
+    /// Check if this grants every table privilege
+    pub fn is_all(&self) -> bool {
+        self.privileges.iter().any(|p| p == "ALL")
+            || TABLE_PRIVILEGES
+                .iter()
+                .all(|p| self.privileges.iter().any(|g| g == p))
+    }
+}
+
+/// Privileges a role's future objects grant automatically
+/// (`ALTER DEFAULT PRIVILEGES`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultPrivilege {
+    /// Role whose new objects get the privileges
+    pub role: String,
+    /// Kind of object, e.g. `TABLES` or `SEQUENCES`
+    pub object_type: String,
+    /// The privileges and who receives them
+    pub grant: Grant,
+}
+
+/// A database view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    /// View name
+    pub name: String,
+    /// The SELECT the view runs
+    pub definition: ViewDefinition,
+}
+
+impl View {
+    /// Create a view
+    pub fn new(name: impl Into<String>, definition: ViewDefinition) -> Self {
+        Self {
+            name: name.into(),
+            definition,
+        }
+    }
+
+    /// The schema view for a view defined in Rust
+    pub fn from_view(view: &chakra_core::view::View) -> Self {
+        Self::new(
+            &view.name,
+            ViewDefinition::Query(Box::new(view.query.clone())),
+        )
+    }
+}
+
+/// The SELECT behind a view
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ViewDefinition {
+    /// A query built in Rust, generated for each database
+    Query(Box<Query>),
+    /// SQL as the database reports it
+    Sql(String),
+}
+
+impl ViewDefinition {
+    /// The SELECT for `dialect`, with any values inlined
+    pub fn to_sql(&self, dialect: &dyn Dialect) -> String {
+        match self {
+            ViewDefinition::Query(query) => dialect.generate(query).inline_params(dialect),
+            ViewDefinition::Sql(sql) => sql.trim().trim_end_matches(';').trim_end().to_string(),
+        }
+    }
+}
+
+/// Custom type (enum, composite, etc.)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CustomType {
     /// Enum type
-    Enum {
-        name: String,
-        values: Vec<String>,
-    },
+    Enum { name: String, values: Vec<String> },
     /// Composite type
     Composite {
         name: String,
@@ -649,6 +1315,15 @@ pub enum CustomType {
     },
 }
 
+impl CustomType {
+    /// Type name
+    pub fn name(&self) -> &str {
+        match self {
+            CustomType::Enum { name, .. } | CustomType::Composite { name, .. } => name,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -669,6 +1344,138 @@ mod tests {
         assert_eq!(schema.get_table("users").unwrap().columns.len(), 3);
     }
 
+    #[test]
+    fn test_create_sql_from_models() {
+        use crate::ddl::{MySqlDdlGenerator, PostgresDdlGenerator};
+        use chakra_core::model::{FieldMeta, ForeignKeyMeta};
+
+        let users = ModelMeta::builder("User", "users")
+            .field(
+                FieldMeta::builder("id", FieldType::BigInt)
+                    .primary_key()
+                    .auto_increment()
+                    .build(),
+            )
+            .field(
+                FieldMeta::builder("email", FieldType::string(255))
+                    .unique()
+                    .build(),
+            )
+            .build();
+        let posts = ModelMeta::builder("Post", "posts")
+            .field(
+                FieldMeta::builder("id", FieldType::BigInt)
+                    .primary_key()
+                    .auto_increment()
+                    .build(),
+            )
+            .field(
+                FieldMeta::builder("author_id", FieldType::BigInt)
+                    .foreign_key(ForeignKeyMeta {
+                        table: "users".into(),
+                        column: "id".into(),
+                        on_delete: ForeignKeyAction::Cascade,
+                        on_update: ForeignKeyAction::NoAction,
+                    })
+                    .build(),
+            )
+            .build();
+
+        let schema = Schema::from_models(&[posts, users]);
+        let order: Vec<_> = schema
+            .creation_order()
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(order, ["users", "posts"]);
+
+        let sql = schema.to_create_sql(&PostgresDdlGenerator);
+        assert!(sql.starts_with("CREATE TABLE \"users\""));
+        assert!(sql.contains("\"id\" BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY"));
+        assert!(sql.contains("CONSTRAINT \"users_email_key\" UNIQUE (\"email\")"));
+        assert!(sql.contains("REFERENCES \"users\" (\"id\") ON DELETE CASCADE"));
+
+        // MySQL adds foreign keys once every table exists
+        let statements = schema.create_statements(&MySqlDdlGenerator);
+        assert_eq!(statements.len(), 3);
+        assert!(statements[2]
+            .sql
+            .starts_with("ALTER TABLE `posts` ADD CONSTRAINT"));
+    }
+
+    #[test]
+    fn test_index_foreign_keys() {
+        let mut table = Table::new("order_items")
+            .primary_key(PrimaryKey::new(vec!["order_id".into(), "line".into()]));
+        table.add_foreign_key(ForeignKey::new(
+            vec!["order_id".into()],
+            "orders",
+            vec!["id".into()],
+        ));
+        table.add_foreign_key(ForeignKey::new(
+            vec!["product_id".into()],
+            "products",
+            vec!["id".into()],
+        ));
+        table.add_foreign_key(ForeignKey::new(
+            vec!["line".into()],
+            "lines",
+            vec!["id".into()],
+        ));
+        table.add_index(Index::new("order_items_line_idx", vec!["line"]).where_clause("line > 0"));
+
+        // The primary key leads with order_id; a partial index doesn't count
+        let unindexed: Vec<_> = table
+            .unindexed_foreign_keys()
+            .iter()
+            .map(|fk| fk.columns[0].as_str())
+            .collect();
+        assert_eq!(unindexed, ["product_id", "line"]);
+
+        let mut existing = table.clone();
+        existing.add_index(Index::new("idx_items_line", vec!["line", "order_id"]));
+        table.index_foreign_keys(Some(&existing));
+        assert!(table.unindexed_foreign_keys().is_empty());
+        let names: Vec<_> = table.indexes.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "order_items_line_idx",
+                "order_items_product_id_idx",
+                "idx_items_line"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_identifiers() {
+        let mut table = Table::new("UserAccounts")
+            .column(Column::new("accountId", ColumnType::BigInt))
+            .primary_key(PrimaryKey::single("accountId"));
+        table.add_index(Index::new("UserAccounts_accountId_idx", vec!["accountId"]));
+        table.add_foreign_key(ForeignKey::new(
+            vec!["accountId".into()],
+            "Accounts",
+            vec!["Id".into()],
+        ));
+        let mut schema = Schema::new();
+        schema.add_table(table);
+
+        let mut unchanged = schema.clone();
+        unchanged.fold_identifiers(IdentifierPolicy::Preserve);
+        assert!(unchanged.has_table("UserAccounts"));
+
+        schema.fold_identifiers(IdentifierPolicy::FoldLower);
+        let table = schema.get_table("useraccounts").unwrap();
+        assert_eq!(table.name, "useraccounts");
+        assert_eq!(table.columns[0].name, "accountid");
+        assert_eq!(table.primary_key.as_ref().unwrap().columns, ["accountid"]);
+        assert_eq!(table.indexes[0].name, "useraccounts_accountid_idx");
+        assert_eq!(table.indexes[0].columns[0].name, "accountid");
+        assert_eq!(table.foreign_keys[0].references_table, "accounts");
+        assert_eq!(table.foreign_keys[0].references_columns, ["id"]);
+    }
+
     #[test]
     fn test_column_type_sql() {
         assert_eq!(ColumnType::BigInt.to_postgres_sql(), "BIGINT");
@@ -680,8 +1487,19 @@ mod tests {
             "VARCHAR(100)"
         );
         assert_eq!(
-            ColumnType::Timestamp { with_timezone: true }.to_postgres_sql(),
+            ColumnType::Timestamp {
+                with_timezone: true
+            }
+            .to_postgres_sql(),
             "TIMESTAMP WITH TIME ZONE"
         );
+
+        let during = ColumnType::from_field_type(&FieldType::range(FieldType::TimestampTz));
+        assert_eq!(during.to_postgres_sql(), "TSTZRANGE");
+        assert_eq!(during.to_mysql_sql(), "VARCHAR(255)");
+        assert_eq!(
+            ColumnType::Range(Box::new(ColumnType::Integer)).to_postgres_sql(),
+            "INT4RANGE"
+        );
     }
 }
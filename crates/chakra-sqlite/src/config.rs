@@ -10,7 +10,8 @@ pub struct SqliteConfig {
     pub path: PathBuf,
     /// Create database if it doesn't exist
     pub create_if_missing: bool,
-    /// Open in read-only mode
+    /// Open in read-only mode, refusing writes with `PRAGMA query_only`
+    /// and rejecting writing queries before they are sent
     pub read_only: bool,
     /// Enable WAL mode
     pub wal_mode: bool,
@@ -45,6 +46,46 @@ impl SqliteConfig {
         }
     }
 
+    /// Create a config for a named in-memory database, shared by every
+    /// connection opened with the same name in this process
+    ///
+    /// The database is dropped when its last connection closes.
+    pub fn shared_memory(name: &str) -> Self {
+        Self {
+            path: PathBuf::from(format!("file:{}?mode=memory&cache=shared", name)),
+            ..Self::memory()
+        }
+    }
+
+    /// Parse from a connection URL (`sqlite://path`, `sqlite:path`,
+    /// `sqlite::memory:` or a URI such as
+    /// `sqlite:file:test?mode=memory&cache=shared`)
+    pub fn from_url(url: &str) -> Result<Self, ConfigError> {
+        let path = url
+            .strip_prefix("sqlite://")
+            .or_else(|| url.strip_prefix("sqlite:"))
+            .ok_or_else(|| ConfigError::InvalidUrl("URL must start with sqlite:".into()))?;
+        // SQLite reads the parameters of `file:` URIs itself
+        if path.starts_with("file:") {
+            let config = Self::new(path);
+            return Ok(if config.is_memory() {
+                Self {
+                    path: config.path,
+                    ..Self::memory()
+                }
+            } else {
+                config
+            });
+        }
+        let path = path.split('?').next().unwrap_or(path);
+
+        match path {
+            "" => Err(ConfigError::InvalidUrl("missing database path".into())),
+            ":memory:" => Ok(Self::memory()),
+            path => Ok(Self::new(path)),
+        }
+    }
+
     /// Set read-only mode
     pub fn read_only(mut self, read_only: bool) -> Self {
         self.read_only = read_only;
@@ -69,9 +110,27 @@ impl SqliteConfig {
         self
     }
 
-    /// Check if this is an in-memory database
+    /// Check if this is an in-memory database, private or shared
     pub fn is_memory(&self) -> bool {
         self.path.to_string_lossy() == ":memory:"
+            || self.uri_param("mode").as_deref() == Some("memory")
+    }
+
+    /// Check if this is an in-memory database shared between connections,
+    /// see `shared_memory`
+    pub fn is_shared_memory(&self) -> bool {
+        self.is_memory() && self.uri_param("cache").as_deref() == Some("shared")
+    }
+
+    /// A query parameter of a `file:` URI path
+    fn uri_param(&self, key: &str) -> Option<String> {
+        let path = self.path.to_string_lossy();
+        let (_, query) = path.strip_prefix("file:")?.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.to_string())
     }
 }
 
@@ -81,6 +140,13 @@ impl Default for SqliteConfig {
     }
 }
 
+/// Configuration errors
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,4 +164,24 @@ mod tests {
         assert!(!config.is_memory());
         assert!(config.wal_mode);
     }
+
+    #[test]
+    fn test_config_from_url() {
+        assert!(SqliteConfig::from_url("sqlite::memory:")
+            .unwrap()
+            .is_memory());
+
+        let config = SqliteConfig::from_url("sqlite://data/app.db").unwrap();
+        assert_eq!(config.path, PathBuf::from("data/app.db"));
+
+        assert!(SqliteConfig::from_url("postgres://localhost/db").is_err());
+
+        let config = SqliteConfig::from_url("sqlite:file:test?mode=memory&cache=shared").unwrap();
+        assert_eq!(config.path, SqliteConfig::shared_memory("test").path);
+        assert!(config.is_shared_memory() && !config.wal_mode);
+        assert!(!SqliteConfig::memory().is_shared_memory());
+        assert!(!SqliteConfig::from_url("sqlite:file:app.db?cache=shared")
+            .unwrap()
+            .is_memory());
+    }
 }
@@ -1,7 +1,11 @@
 //! SQLite connection management
 
 use crate::config::SqliteConfig;
-use chakra_core::error::{ChakraError, ConnectionError, Result};
+use async_trait::async_trait;
+use chakra_core::error::{ChakraError, ConnectionError, QueryError, Result};
+use chakra_pool::manager::ConnectionManager;
+use chakra_pool::PoolConfig;
+use std::path::Path;
 use std::sync::Arc;
 use tokio_rusqlite::Connection;
 use tracing::{debug, info};
@@ -16,10 +20,9 @@ impl SqliteConnection {
     /// Open a connection with the given config
     pub async fn open(config: SqliteConfig) -> Result<Self> {
         let path = config.path.clone();
-        let create = config.create_if_missing;
-        let read_only = config.read_only;
 
-        let conn = if config.is_memory() {
+        // Shared in-memory databases are `file:` URIs, opened like files
+        let conn = if path == Path::new(":memory:") {
             Connection::open_in_memory().await
         } else {
             Connection::open(&path).await
@@ -31,9 +34,10 @@ impl SqliteConnection {
         })?;
 
         // Configure the connection
-        let wal_mode = config.wal_mode;
+        let wal_mode = config.wal_mode && !config.is_memory();
         let foreign_keys = config.foreign_keys;
         let busy_timeout = config.busy_timeout_ms;
+        let read_only = config.read_only;
 
         conn.call(move |conn| {
             // Set busy timeout
@@ -45,10 +49,15 @@ impl SqliteConnection {
             }
 
             // Enable WAL mode (only for file databases)
-            if wal_mode && path.to_string_lossy() != ":memory:" {
+            if wal_mode {
                 conn.execute_batch("PRAGMA journal_mode = WAL;")?;
             }
 
+            // Refuse writes, also for in-memory databases
+            if read_only {
+                conn.execute_batch("PRAGMA query_only = ON;")?;
+            }
+
             Ok(())
         })
         .await
@@ -64,6 +73,9 @@ impl SqliteConnection {
     }
 
     /// Open an in-memory connection
+    ///
+    /// The database is private to this connection; see
+    /// `SqliteConfig::shared_memory` to reach one from several.
     pub async fn open_memory() -> Result<Self> {
         Self::open(SqliteConfig::memory()).await
     }
@@ -89,7 +101,12 @@ impl SqliteConnection {
         self.conn
             .call(move |conn| f(conn).map_err(tokio_rusqlite::Error::from))
             .await
-            .map_err(|e| ChakraError::internal(format!("SQLite call failed: {}", e)))
+            .map_err(call_error)
+    }
+
+    /// Check if the connection still answers queries
+    pub async fn is_valid(&self) -> bool {
+        self.call(|c| c.execute_batch("SELECT 1")).await.is_ok()
     }
 
     /// Close the connection
@@ -103,6 +120,167 @@ impl SqliteConnection {
     }
 }
 
+/// SQLite connection manager
+#[derive(Debug)]
+pub struct SqliteConnectionManager {
+    config: SqliteConfig,
+}
+
+impl SqliteConnectionManager {
+    /// Create a new connection manager
+    pub fn new(config: SqliteConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl ConnectionManager for SqliteConnectionManager {
+    type Connection = SqliteConnection;
+
+    async fn connect(&self) -> Result<Self::Connection> {
+        SqliteConnection::open(self.config.clone()).await
+    }
+
+    async fn is_valid(&self, conn: &Self::Connection) -> bool {
+        conn.is_valid().await
+    }
+
+    fn has_expired(&self, _conn: &Self::Connection) -> bool {
+        false
+    }
+
+    async fn reset(&self, conn: &mut Self::Connection) -> Result<()> {
+        // Don't hand the next caller an open transaction
+        conn.call(|c| match c.is_autocommit() {
+            true => Ok(()),
+            false => c.execute_batch("ROLLBACK"),
+        })
+        .await
+    }
+
+    async fn close(&self, conn: Self::Connection) -> Result<()> {
+        debug!("Closing SQLite connection: {:?}", conn.config().path);
+        conn.close().await
+    }
+}
+
+/// SQLite connection pool
+///
+/// Each connection to `:memory:` opens its own empty database, so a pool of
+/// them is limited to one connection. A `SqliteConfig::shared_memory`
+/// database is reachable from every connection, and the pool keeps it alive
+/// while connections come and go.
+pub struct SqlitePool {
+    pool: Arc<chakra_pool::Pool<SqliteConnectionManager>>,
+    /// Open for the pool's lifetime so a shared in-memory database is never
+    /// dropped
+    _anchor: Option<SqliteConnection>,
+    config: SqliteConfig,
+}
+
+impl SqlitePool {
+    /// Create a pool with the default pool settings, or a single connection
+    /// for a private `:memory:` database
+    pub async fn new(config: SqliteConfig) -> Result<Self> {
+        let url = config.path.to_string_lossy().into_owned();
+        let pool_config = if is_private_memory(&config) {
+            PoolConfig::single_connection(url)
+        } else {
+            PoolConfig::new(url)
+        };
+        Self::with_pool_config(config, pool_config).await
+    }
+
+    /// Create a pool with the given pool settings
+    ///
+    /// Fails for a private `:memory:` database with more than one
+    /// connection, which would hand out different databases.
+    pub async fn with_pool_config(config: SqliteConfig, pool_config: PoolConfig) -> Result<Self> {
+        if is_private_memory(&config) && pool_config.max_connections > 1 {
+            return Err(ChakraError::config(
+                "every connection to `:memory:` opens its own empty database; use \
+                 SqliteConfig::shared_memory or PoolConfig::single_connection",
+            ));
+        }
+        let anchor = match config.is_shared_memory() {
+            true => Some(SqliteConnection::open(config.clone()).await?),
+            false => None,
+        };
+        let pool =
+            chakra_pool::Pool::new(SqliteConnectionManager::new(config.clone()), pool_config)
+                .await?;
+        Ok(Self {
+            pool,
+            _anchor: anchor,
+            config,
+        })
+    }
+
+    /// Get a connection from the pool
+    pub async fn get(&self) -> Result<chakra_pool::PooledConnection<SqliteConnectionManager>> {
+        self.pool.acquire().await
+    }
+
+    /// Get pool status
+    pub fn status(&self) -> chakra_pool::pool::PoolStatus {
+        self.pool.status()
+    }
+
+    /// Close the pool
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &SqliteConfig {
+        &self.config
+    }
+}
+
+fn is_private_memory(config: &SqliteConfig) -> bool {
+    config.is_memory() && !config.is_shared_memory()
+}
+
+/// Convert a failed call into a `ChakraError`, keeping unique violations
+/// structured so models can map them to their constraints, and a busy
+/// database or a table locked by another connection to a shared cache as a
+/// serialization failure so the transaction can be retried
+fn call_error(e: tokio_rusqlite::Error) -> ChakraError {
+    use rusqlite::ffi;
+
+    if let tokio_rusqlite::Error::Rusqlite(rusqlite::Error::SqliteFailure(err, Some(message))) = &e
+    {
+        if matches!(
+            err.extended_code,
+            ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+        ) {
+            return ChakraError::unique_violation(None, unique_columns(message));
+        }
+        if matches!(
+            err.code,
+            rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+        ) {
+            return ChakraError::Query(QueryError::SerializationFailure {
+                message: message.clone(),
+            });
+        }
+    }
+    ChakraError::internal(format!("SQLite call failed: {}", e))
+}
+
+/// Columns from `UNIQUE constraint failed: pages.tenant_id, pages.slug`
+fn unique_columns(message: &str) -> Vec<String> {
+    message
+        .split_once(": ")
+        .map(|(_, columns)| {
+            columns
+                .split(", ")
+                .map(|c| c.rsplit_once('.').map_or(c, |(_, c)| c).to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,12 +304,102 @@ mod tests {
         .unwrap();
 
         let name: String = conn
+            .call(|c| c.query_row("SELECT name FROM test WHERE id = 1", [], |row| row.get(0)))
+            .await
+            .unwrap();
+
+        assert_eq!(name, "Alice");
+    }
+
+    #[tokio::test]
+    async fn test_unique_violation() {
+        let conn = SqliteConnection::open_memory().await.unwrap();
+
+        let err = conn
             .call(|c| {
-                c.query_row("SELECT name FROM test WHERE id = 1", [], |row| row.get(0))
+                c.execute_batch(
+                    "CREATE TABLE pages (tenant_id INTEGER, slug TEXT, UNIQUE (tenant_id, slug));
+                     INSERT INTO pages VALUES (1, 'home');
+                     INSERT INTO pages VALUES (1, 'home');",
+                )
             })
             .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.unique_violation_fields().unwrap(),
+            ["tenant_id", "slug"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_memory() {
+        let config = SqliteConfig::shared_memory("test_shared_memory");
+        let first = SqliteConnection::open(config.clone()).await.unwrap();
+        first
+            .call(|c| c.execute_batch("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1);"))
+            .await
+            .unwrap();
+        let second = SqliteConnection::open(config).await.unwrap();
+        let count: i64 = second
+            .call(|c| c.query_row("SELECT count(*) FROM t", [], |row| row.get(0)))
+            .await
             .unwrap();
+        assert_eq!(count, 1);
 
-        assert_eq!(name, "Alice");
+        // Other names, and private databases, are separate
+        let other = SqliteConnection::open(SqliteConfig::shared_memory("test_shared_other"))
+            .await
+            .unwrap();
+        assert!(other
+            .call(|c| c.execute_batch("SELECT * FROM t"))
+            .await
+            .is_err());
+        let private = SqliteConnection::open_memory().await.unwrap();
+        assert!(private
+            .call(|c| c.execute_batch("SELECT * FROM t"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_pools() {
+        // A private database is one connection, so every checkout sees it
+        let pool = SqlitePool::new(SqliteConfig::memory()).await.unwrap();
+        assert_eq!(pool.status().max_connections, 1);
+        pool.get()
+            .await
+            .unwrap()
+            .call(|c| c.execute_batch("CREATE TABLE t (id INTEGER)"))
+            .await
+            .unwrap();
+        pool.get()
+            .await
+            .unwrap()
+            .call(|c| c.execute_batch("SELECT * FROM t"))
+            .await
+            .unwrap();
+        let pool_config = PoolConfig::new(":memory:").max_connections(4);
+        assert!(
+            SqlitePool::with_pool_config(SqliteConfig::memory(), pool_config)
+                .await
+                .is_err()
+        );
+
+        // A shared database is the same from concurrent connections
+        let pool = SqlitePool::new(SqliteConfig::shared_memory("test_memory_pools"))
+            .await
+            .unwrap();
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        first
+            .call(|c| c.execute_batch("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1);"))
+            .await
+            .unwrap();
+        drop(first);
+        second
+            .call(|c| c.execute_batch("SELECT * FROM t"))
+            .await
+            .unwrap();
     }
 }
@@ -1,30 +1,154 @@
 //! SQLite query executor
 
-use crate::connection::SqliteConnection;
+use crate::connection::{SqliteConnection, SqliteConnectionManager, SqlitePool};
 use crate::types::{row_to_chakra, to_sqlite_value};
-use chakra_core::error::{ChakraError, QueryError, Result};
+use async_trait::async_trait;
+use chakra_core::error::{ChakraError, Result};
+use chakra_core::executor::{buffered_stream, ExecResult, Executor};
 use chakra_core::result::Row;
-use chakra_core::sql::{SqlFragment, SqliteDialect};
+use chakra_core::script::ScriptSplitter;
+use chakra_core::sql::{Dialect, SqlFragment, SqliteDialect};
+use chakra_core::sql_cache::SqlCache;
+use chakra_core::transaction::{SavepointOp, Transaction, TransactionOptions};
 use chakra_core::types::Value;
+use chakra_pool::PooledConnection;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use rusqlite::params_from_iter;
+use rusqlite::types::Value as SqliteValue;
+use std::ops::Deref;
 use std::sync::Arc;
-use tracing::{debug, error};
+use tokio::sync::mpsc;
+
+/// Rows a stream's reader sends at a time
+const STREAM_CHUNK: usize = 256;
 
 /// SQLite query executor
 pub struct SqliteExecutor {
-    conn: Arc<SqliteConnection>,
+    source: Source,
     dialect: SqliteDialect,
+    max_rows_guard: Option<usize>,
+    sql_cache: Option<SqlCache>,
+}
+
+/// Where an executor gets its connection from
+#[derive(Clone)]
+enum Source {
+    /// One connection shared by every statement
+    Connection(Arc<SqliteConnection>),
+    /// A connection taken from the pool for each statement
+    Pool(Arc<SqlitePool>),
+}
+
+impl Source {
+    async fn acquire(&self) -> Result<Handle<'_>> {
+        match self {
+            Source::Connection(conn) => Ok(Handle::Shared(conn)),
+            Source::Pool(pool) => Ok(Handle::Pooled(pool.get().await?)),
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        match self {
+            Source::Connection(conn) => conn.config().read_only,
+            Source::Pool(pool) => pool.config().read_only,
+        }
+    }
+
+    /// A connection of its own for a stream to read on, or `None` if only
+    /// the shared connection can see the rows: a private in-memory
+    /// database, or one inside a transaction
+    async fn reader(&self) -> Result<Option<Reader>> {
+        match self {
+            Source::Pool(pool) => Ok(Some(Reader::Pooled(pool.get().await?))),
+            Source::Connection(conn) => {
+                let config = conn.config();
+                if config.is_memory() && !config.is_shared_memory() {
+                    return Ok(None);
+                }
+                if !conn.call(|conn| Ok(conn.is_autocommit())).await? {
+                    return Ok(None);
+                }
+                Ok(Some(Reader::Opened(
+                    SqliteConnection::open(config.clone()).await?,
+                )))
+            }
+        }
+    }
+}
+
+/// A connection held for one statement
+enum Handle<'a> {
+    Shared(&'a SqliteConnection),
+    Pooled(PooledConnection<SqliteConnectionManager>),
+}
+
+impl Deref for Handle<'_> {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        match self {
+            Handle::Shared(conn) => conn,
+            Handle::Pooled(conn) => conn,
+        }
+    }
+}
+
+/// A connection held by a stream until it ends
+enum Reader {
+    Pooled(PooledConnection<SqliteConnectionManager>),
+    /// Opened for the stream and closed with it
+    Opened(SqliteConnection),
+}
+
+impl Deref for Reader {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        match self {
+            Reader::Pooled(conn) => conn,
+            Reader::Opened(conn) => conn,
+        }
+    }
 }
 
 impl SqliteExecutor {
     /// Create a new executor
     pub fn new(conn: Arc<SqliteConnection>) -> Self {
+        Self::with_source(Source::Connection(conn))
+    }
+
+    /// Create an executor that takes a connection from `pool` for each
+    /// statement
+    ///
+    /// Statements can run on different connections, so open transactions
+    /// with `transaction`, which keeps one connection until it ends, rather
+    /// than `begin`.
+    pub fn from_pool(pool: Arc<SqlitePool>) -> Self {
+        Self::with_source(Source::Pool(pool))
+    }
+
+    fn with_source(source: Source) -> Self {
         Self {
-            conn,
+            source,
             dialect: SqliteDialect,
+            max_rows_guard: None,
+            sql_cache: None,
         }
     }
 
+    /// Limit unbounded SELECTs run through `fetch_all` to `max_rows`
+    pub fn with_max_rows_guard(mut self, max_rows: usize) -> Self {
+        self.max_rows_guard = Some(max_rows);
+        self
+    }
+
+    /// Reuse generated SQL for up to `capacity` query shapes
+    pub fn with_sql_cache(mut self, capacity: usize) -> Self {
+        self.sql_cache = Some(SqlCache::new(capacity));
+        self
+    }
+
     /// Get the dialect
     pub fn dialect(&self) -> &SqliteDialect {
         &self.dialect
@@ -32,32 +156,86 @@ impl SqliteExecutor {
 
     /// Execute a query and return rows
     pub async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
-        let sql = sql.to_string();
-        let params: Vec<_> = params.iter().map(to_sqlite_value).collect();
-
-        self.conn
-            .call(move |conn| {
-                let mut stmt = conn.prepare(&sql)?;
-
-                let column_names: Vec<String> = stmt
-                    .column_names()
-                    .iter()
-                    .map(|s| s.to_string())
-                    .collect();
-
-                let rows: Vec<Row> = stmt
-                    .query_map(params_from_iter(params.iter()), |row| {
-                        row_to_chakra(row, &column_names)
-                    })?
-                    .collect::<std::result::Result<Vec<_>, _>>()?;
+        query_on(&*self.source.acquire().await?, sql, params).await
+    }
 
-                Ok(rows)
-            })
-            .await
+    /// Stream the rows of a query, stepping one statement as they are
+    /// consumed
+    ///
+    /// The statement runs on a connection of its own, taken from the pool
+    /// or opened with the executor's config, so other statements run while
+    /// the stream is read, and it sees one snapshot of the database. Rows
+    /// are read ahead in chunks of 256, one chunk at a time. A private
+    /// in-memory database, or one in a transaction, can't be read from
+    /// another connection, so there the rows are read at once.
+    pub fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'static, Result<Row>> {
+        let source = self.source.clone();
+        let params: Vec<SqliteValue> = params.iter().map(to_sqlite_value).collect();
+        let start = async move {
+            let reader = match source.reader().await? {
+                Some(reader) => reader,
+                None => {
+                    let rows = source
+                        .acquire()
+                        .await?
+                        .call(move |conn| query_rows(conn, &sql, &params))
+                        .await?;
+                    return Ok(stream::iter(rows.into_iter().map(Ok)).boxed());
+                }
+            };
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(async move {
+                let chunks = tx.clone();
+                let result = reader
+                    .call(move |conn| {
+                        let mut stmt = conn.prepare(&sql)?;
+                        let column_names: Vec<String> =
+                            stmt.column_names().iter().map(|s| s.to_string()).collect();
+                        let mut cursor = stmt.query(params_from_iter(params.iter()))?;
+                        let mut chunk = Vec::with_capacity(STREAM_CHUNK);
+                        while let Some(row) = cursor.next()? {
+                            chunk.push(row_to_chakra(row, &column_names)?);
+                            // The receiver is gone once the stream is dropped
+                            if chunk.len() == STREAM_CHUNK
+                                && chunks
+                                    .blocking_send(Ok(std::mem::replace(
+                                        &mut chunk,
+                                        Vec::with_capacity(STREAM_CHUNK),
+                                    )))
+                                    .is_err()
+                            {
+                                return Ok(());
+                            }
+                        }
+                        let _ = chunks.blocking_send(Ok(chunk));
+                        Ok(())
+                    })
+                    .await;
+                if let Err(e) = result {
+                    let _ = tx.send(Err(e)).await;
+                }
+            });
+            let chunks = stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|chunk| (chunk, rx))
+            });
+            Ok::<_, ChakraError>(
+                chunks
+                    .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+                    .try_flatten()
+                    .boxed(),
+            )
+        };
+        stream::once(start).try_flatten().boxed()
     }
 
     /// Execute a query with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
     pub async fn query_fragment(&self, fragment: &SqlFragment) -> Result<Vec<Row>> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.query(&fragment.sql, &fragment.params).await;
+        }
         self.query(&fragment.sql, &fragment.params).await
     }
 
@@ -69,19 +247,38 @@ impl SqliteExecutor {
 
     /// Execute a statement and return affected row count
     pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
-        let sql = sql.to_string();
-        let params: Vec<_> = params.iter().map(to_sqlite_value).collect();
+        execute_on(&*self.source.acquire().await?, sql, params).await
+    }
 
-        self.conn
-            .call(move |conn| {
-                let count = conn.execute(&sql, params_from_iter(params.iter()))?;
-                Ok(count as u64)
-            })
-            .await
+    /// Execute a statement and return affected row count and the rowid of
+    /// the last row it inserted
+    ///
+    /// The rowid is read in the same call, so no other statement on the
+    /// connection can move it first.
+    pub async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        execute_with_id_on(&*self.source.acquire().await?, sql, params).await
+    }
+
+    /// Execute a statement once for each parameter set and return the total
+    /// affected row count
+    ///
+    /// The statement is prepared once and run for every set inside a
+    /// savepoint, which begins a transaction when none is open.
+    pub async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        if params.is_empty() {
+            return Ok(0);
+        }
+        execute_many_on(&*self.source.acquire().await?, sql, params).await
     }
 
     /// Execute a statement with a SqlFragment
+    ///
+    /// Named placeholders are rewritten into positional ones first.
     pub async fn execute_fragment(&self, fragment: &SqlFragment) -> Result<u64> {
+        if fragment.has_named_params() {
+            let fragment = fragment.to_positional(&self.dialect)?;
+            return self.execute(&fragment.sql, &fragment.params).await;
+        }
         self.execute(&fragment.sql, &fragment.params).await
     }
 
@@ -89,7 +286,9 @@ impl SqliteExecutor {
     pub async fn execute_batch(&self, sql: &str) -> Result<()> {
         let sql = sql.to_string();
 
-        self.conn
+        self.source
+            .acquire()
+            .await?
             .call(move |conn| {
                 conn.execute_batch(&sql)?;
                 Ok(())
@@ -97,6 +296,25 @@ impl SqliteExecutor {
             .await
     }
 
+    /// Execute a multi-statement SQL script, returning the number of statements run
+    pub async fn execute_script(&self, script: &str) -> Result<usize> {
+        let statements = ScriptSplitter::sqlite().split(script);
+        let count = statements.len();
+
+        self.source
+            .acquire()
+            .await?
+            .call(move |conn| {
+                for sql in &statements {
+                    conn.execute_batch(sql)?;
+                }
+                Ok(())
+            })
+            .await?;
+
+        Ok(count)
+    }
+
     /// Begin a transaction
     pub async fn begin(&self) -> Result<()> {
         self.execute_batch("BEGIN").await
@@ -112,17 +330,232 @@ impl SqliteExecutor {
         self.execute_batch("ROLLBACK").await
     }
 
+    /// Create a savepoint in the open transaction
+    pub async fn savepoint(&self, name: &str) -> Result<()> {
+        self.execute_batch(&SavepointOp::Begin.sql(name)?).await
+    }
+
+    /// Release a savepoint, keeping its changes
+    pub async fn release_savepoint(&self, name: &str) -> Result<()> {
+        self.execute_batch(&SavepointOp::Release.sql(name)?).await
+    }
+
+    /// Undo everything since a savepoint, which stays open
+    pub async fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        self.execute_batch(&SavepointOp::RollbackTo.sql(name)?)
+            .await
+    }
+
+    /// Run `f` in a transaction, committing if it returns `Ok` and rolling
+    /// back otherwise
+    ///
+    /// Retries follow `options`, see `chakra_core::transaction::transaction`;
+    /// a `SQLITE_BUSY` from another connection to the file counts as a
+    /// serialization failure. On a shared connection, statements from
+    /// outside the closure can still reach it while it runs; on a pool the
+    /// closure keeps one connection to itself, and its streams are read
+    /// whole before they yield.
+    pub async fn transaction<T, F>(&self, options: &TransactionOptions, f: F) -> Result<T>
+    where
+        F: for<'c, 't> FnMut(&'t Transaction<'c>) -> BoxFuture<'t, Result<T>>,
+    {
+        match &self.source {
+            Source::Connection(_) => chakra_core::transaction::transaction(self, options, f).await,
+            Source::Pool(pool) => {
+                let conn = PinnedConnection {
+                    executor: self,
+                    conn: pool.get().await?,
+                };
+                chakra_core::transaction::transaction(&conn, options, f).await
+            }
+        }
+    }
+
     /// Get the last inserted row ID
     pub async fn last_insert_rowid(&self) -> Result<i64> {
-        self.conn
+        self.source
+            .acquire()
+            .await?
             .call(|conn| Ok(conn.last_insert_rowid()))
             .await
     }
 }
 
+#[async_trait]
+impl Executor for SqliteExecutor {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        SqliteExecutor::query(self, sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        SqliteExecutor::execute(self, sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        SqliteExecutor::execute_with_id(self, sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        SqliteExecutor::execute_many(self, sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        SqliteExecutor::stream(self, sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.source.read_only()
+    }
+
+    /// A shared connection already runs every statement
+    async fn pin(&self) -> Result<Option<Box<dyn Executor + '_>>> {
+        match &self.source {
+            Source::Connection(_) => Ok(None),
+            Source::Pool(pool) => Ok(Some(Box::new(PinnedConnection {
+                executor: self,
+                conn: pool.get().await?,
+            }))),
+        }
+    }
+}
+
+/// The pooled connection a `transaction` closure, or a transaction begun
+/// on the executor, runs on
+struct PinnedConnection<'a> {
+    executor: &'a SqliteExecutor,
+    conn: PooledConnection<SqliteConnectionManager>,
+}
+
+#[async_trait]
+impl Executor for PinnedConnection<'_> {
+    fn dialect(&self) -> &dyn Dialect {
+        &self.executor.dialect
+    }
+
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        query_on(&self.conn, sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        execute_on(&self.conn, sql, params).await
+    }
+
+    async fn execute_with_id(&self, sql: &str, params: &[Value]) -> Result<ExecResult> {
+        execute_with_id_on(&self.conn, sql, params).await
+    }
+
+    async fn execute_many(&self, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+        if params.is_empty() {
+            return Ok(0);
+        }
+        execute_many_on(&self.conn, sql, params).await
+    }
+
+    fn stream(&self, sql: String, params: Vec<Value>) -> BoxStream<'_, Result<Row>> {
+        buffered_stream(self, sql, params)
+    }
+
+    fn max_rows_guard(&self) -> Option<usize> {
+        self.executor.max_rows_guard
+    }
+
+    fn sql_cache(&self) -> Option<&SqlCache> {
+        self.executor.sql_cache.as_ref()
+    }
+
+    fn read_only(&self) -> bool {
+        self.conn.config().read_only
+    }
+}
+
+async fn query_on(conn: &SqliteConnection, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+    let sql = sql.to_string();
+    let params: Vec<_> = params.iter().map(to_sqlite_value).collect();
+
+    conn.call(move |conn| query_rows(conn, &sql, &params)).await
+}
+
+async fn execute_on(conn: &SqliteConnection, sql: &str, params: &[Value]) -> Result<u64> {
+    let sql = sql.to_string();
+    let params: Vec<_> = params.iter().map(to_sqlite_value).collect();
+
+    conn.call(move |conn| {
+        let count = conn.execute(&sql, params_from_iter(params.iter()))?;
+        Ok(count as u64)
+    })
+    .await
+}
+
+async fn execute_with_id_on(
+    conn: &SqliteConnection,
+    sql: &str,
+    params: &[Value],
+) -> Result<ExecResult> {
+    let sql = sql.to_string();
+    let params: Vec<_> = params.iter().map(to_sqlite_value).collect();
+
+    conn.call(move |conn| {
+        let count = conn.execute(&sql, params_from_iter(params.iter()))?;
+        Ok(ExecResult {
+            rows_affected: count as u64,
+            last_insert_id: (count > 0).then(|| Value::Int64(conn.last_insert_rowid())),
+        })
+    })
+    .await
+}
+
+async fn execute_many_on(conn: &SqliteConnection, sql: &str, params: &[Vec<Value>]) -> Result<u64> {
+    let sql = sql.to_string();
+    let params: Vec<Vec<_>> = params
+        .iter()
+        .map(|params| params.iter().map(to_sqlite_value).collect())
+        .collect();
+
+    conn.call(move |conn| {
+        let savepoint = conn.savepoint()?;
+        let mut affected = 0;
+        {
+            let mut stmt = savepoint.prepare_cached(&sql)?;
+            for params in &params {
+                affected += stmt.execute(params_from_iter(params.iter()))? as u64;
+            }
+        }
+        savepoint.commit()?;
+        Ok(affected)
+    })
+    .await
+}
+
+/// Run a query on `conn` and collect its rows
+fn query_rows(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &[SqliteValue],
+) -> rusqlite::Result<Vec<Row>> {
+    let mut stmt = conn.prepare_cached(sql)?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        row_to_chakra(row, &column_names)
+    })?;
+    rows.collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chakra_core::query::Query;
 
     #[tokio::test]
     async fn test_query_execute() {
@@ -147,6 +580,2294 @@ mod tests {
         // Query
         let rows = executor.query("SELECT * FROM users", &[]).await.unwrap();
         assert_eq!(rows.len(), 1);
-        assert_eq!(rows[0].get("name"), Some(&Value::String("Alice".to_string())));
+        assert_eq!(
+            rows[0].get("name"),
+            Some(&Value::String("Alice".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_script() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+
+        let count = executor
+            .execute_script(
+                "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT, edits INTEGER DEFAULT 0);
+                 CREATE TRIGGER notes_edit AFTER UPDATE OF body ON notes BEGIN
+                     UPDATE notes SET edits = edits + 1 WHERE id = new.id;
+                 END;
+                 -- seed
+                 INSERT INTO notes (body) VALUES ('a; b');",
+            )
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+
+        executor
+            .execute("UPDATE notes SET body = 'c'", &[])
+            .await
+            .unwrap();
+        let rows = executor
+            .query("SELECT edits FROM notes", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get("edits"), Some(&Value::Int64(1)));
+    }
+
+    #[tokio::test]
+    async fn test_execute_many() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE items (sku TEXT PRIMARY KEY, qty INTEGER)")
+            .await
+            .unwrap();
+
+        let insert = "INSERT INTO items (sku, qty) VALUES (?, ?)";
+        let rows = |skus: &[&str]| -> Vec<Vec<Value>> {
+            skus.iter()
+                .map(|sku| vec![Value::from(*sku), Value::Int32(1)])
+                .collect()
+        };
+        assert_eq!(
+            executor
+                .execute_many(insert, &rows(&["a", "b", "c"]))
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(executor.execute_many(insert, &[]).await.unwrap(), 0);
+
+        // A failing set leaves none of the others applied
+        let err = executor
+            .execute_many(insert, &rows(&["d", "a"]))
+            .await
+            .unwrap_err();
+        assert!(err.is_unique_violation());
+        let count = executor
+            .query("SELECT count(*) AS n FROM items", &[])
+            .await
+            .unwrap();
+        assert_eq!(count[0].get("n"), Some(&Value::Int64(3)));
+
+        // Inside an open transaction it nests, and rolls back with it
+        executor.begin().await.unwrap();
+        let update = "UPDATE items SET qty = qty + ? WHERE sku = ?";
+        let params = vec![
+            vec![Value::Int32(1), Value::from("a")],
+            vec![Value::Int32(1), Value::from("x")],
+        ];
+        assert_eq!(executor.execute_many(update, &params).await.unwrap(), 1);
+        executor.rollback().await.unwrap();
+        let qty = executor
+            .query("SELECT qty FROM items WHERE sku = 'a'", &[])
+            .await
+            .unwrap();
+        assert_eq!(qty[0].get("qty"), Some(&Value::Int64(1)));
+    }
+
+    #[tokio::test]
+    async fn test_max_rows_guard() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn).with_max_rows_guard(2);
+
+        executor
+            .execute_batch(
+                "CREATE TABLE events (id INTEGER PRIMARY KEY);
+                 INSERT INTO events (id) VALUES (1), (2), (3);",
+            )
+            .await
+            .unwrap();
+
+        let guarded = Query::select().from("events").build();
+        assert_eq!(executor.fetch_all(&guarded).await.unwrap().len(), 2);
+
+        let unlimited = Query::select().from("events").unlimited().build();
+        assert_eq!(executor.fetch_all(&unlimited).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let config = crate::config::SqliteConfig::memory().read_only(true);
+        let conn = Arc::new(SqliteConnection::open(config).await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        assert!(Executor::read_only(&executor));
+
+        let rows = executor.query("SELECT 1 AS one", &[]).await.unwrap();
+        assert_eq!(rows.len(), 1);
+
+        let insert = Query::insert()
+            .table("events")
+            .set("name", "signup")
+            .build();
+        let err = executor.insert_all(&insert).await.unwrap_err();
+        assert!(err.is_policy_violation());
+        assert_eq!(
+            err.to_string(),
+            "Statement denied by rule 'read_only': INSERT is not allowed on a read-only connection"
+        );
+        let locking = Query::select().from("events").for_update().build();
+        assert!(executor
+            .fetch_all(&locking)
+            .await
+            .unwrap_err()
+            .is_policy_violation());
+
+        // Raw SQL is refused by the session itself
+        let err = executor
+            .execute("CREATE TABLE events (name TEXT)", &[])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("readonly database"));
+    }
+
+    #[tokio::test]
+    async fn test_named_params() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+
+        executor
+            .execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, alias TEXT)")
+            .await
+            .unwrap();
+
+        let insert = SqlFragment::from_sql("INSERT INTO users (name, alias) VALUES (:name, :name)")
+            .bind("name", "Alice");
+        executor.execute_fragment(&insert).await.unwrap();
+
+        let select = SqlFragment::from_sql("SELECT alias FROM users WHERE name = @name")
+            .bind("name", "Alice");
+        let rows = executor.query_fragment(&select).await.unwrap();
+        assert_eq!(
+            rows[0].get("alias"),
+            Some(&Value::String("Alice".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_transaction() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE items (name TEXT)")
+            .await
+            .unwrap();
+
+        let tx = Transaction::begin(&executor).await.unwrap();
+        tx.execute("INSERT INTO items (name) VALUES ('kept')", &[])
+            .await
+            .unwrap();
+
+        let nested = tx.transaction().await.unwrap();
+        assert_eq!(nested.depth(), 1);
+        nested
+            .execute("INSERT INTO items (name) VALUES ('undone')", &[])
+            .await
+            .unwrap();
+        nested.rollback().await.unwrap();
+
+        let nested = tx.transaction().await.unwrap();
+        nested
+            .execute("INSERT INTO items (name) VALUES ('released')", &[])
+            .await
+            .unwrap();
+        nested.commit().await.unwrap();
+        tx.commit().await.unwrap();
+
+        let rows = executor.query("SELECT name FROM items", &[]).await.unwrap();
+        let names: Vec<_> = rows.iter().filter_map(|r| r.get("name")).collect();
+        assert_eq!(
+            names,
+            vec![
+                &Value::String("kept".to_string()),
+                &Value::String("released".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_closure() {
+        use chakra_core::error::{ChakraError, QueryError};
+        use chakra_core::transaction::TransactionRetry;
+        use futures::FutureExt;
+        use std::time::Duration;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE items (name TEXT)")
+            .await
+            .unwrap();
+        let count = || async {
+            executor
+                .query("SELECT name FROM items", &[])
+                .await
+                .unwrap()
+                .len()
+        };
+        fn conflict() -> ChakraError {
+            ChakraError::Query(QueryError::SerializationFailure {
+                message: "could not serialize access".into(),
+            })
+        }
+
+        // Committed on Ok
+        let options = TransactionOptions::new();
+        let name = executor
+            .transaction(&options, |tx| {
+                async move {
+                    tx.execute("INSERT INTO items (name) VALUES ('a')", &[])
+                        .await?;
+                    Ok("a")
+                }
+                .boxed()
+            })
+            .await
+            .unwrap();
+        assert_eq!(name, "a");
+        assert_eq!(count().await, 1);
+
+        // Rolled back on Err, without retries by default
+        let mut attempts = 0;
+        let err = executor
+            .transaction(&options, |tx| {
+                attempts += 1;
+                async move {
+                    tx.execute("INSERT INTO items (name) VALUES ('b')", &[])
+                        .await?;
+                    Err::<(), _>(conflict())
+                }
+                .boxed()
+            })
+            .await
+            .unwrap_err();
+        assert!(err.is_transaction_conflict());
+        assert_eq!(attempts, 1);
+        assert_eq!(count().await, 1);
+
+        // Run again after a conflict, keeping only the last attempt's work
+        let options =
+            options.retry(TransactionRetry::new().initial_backoff(Duration::from_millis(1)));
+        let mut attempts = 0;
+        executor
+            .transaction(&options, |tx| {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    tx.execute("INSERT INTO items (name) VALUES ('c')", &[])
+                        .await?;
+                    if attempt < 2 {
+                        return Err(conflict());
+                    }
+                    Ok(())
+                }
+                .boxed()
+            })
+            .await
+            .unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(count().await, 2);
+
+        // Other errors aren't retried, and retries stop at max_attempts
+        let mut attempts = 0;
+        let err = executor
+            .transaction(&options, |_| {
+                attempts += 1;
+                async { Err::<(), _>(ChakraError::Query(QueryError::NotFound)) }.boxed()
+            })
+            .await
+            .unwrap_err();
+        assert!(err.is_not_found());
+        assert_eq!(attempts, 1);
+        let mut attempts = 0;
+        executor
+            .transaction(&options, |_| {
+                attempts += 1;
+                async { Err::<(), _>(conflict()) }.boxed()
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_pool_executor() {
+        use crate::config::SqliteConfig;
+        use chakra_core::error::QueryError;
+        use chakra_pool::PoolConfig;
+        use futures::FutureExt;
+
+        let config = SqliteConfig::shared_memory("test_pool_executor");
+        let pool_config = PoolConfig::new("test_pool_executor").max_connections(2);
+        let pool = SqlitePool::with_pool_config(config, pool_config)
+            .await
+            .unwrap();
+        let executor = SqliteExecutor::from_pool(Arc::new(pool));
+        executor
+            .execute_batch("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)")
+            .await
+            .unwrap();
+
+        // Every statement sees the writes of the ones before it, whichever
+        // connection they ran on
+        let result = executor
+            .execute_with_id("INSERT INTO items (name) VALUES (?)", &["a".into()])
+            .await
+            .unwrap();
+        assert_eq!(result.last_insert_id, Some(Value::Int64(1)));
+        let rows = executor
+            .execute_many(
+                "INSERT INTO items (name) VALUES (?)",
+                &[vec!["b".into()], vec!["c".into()]],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows, 2);
+        let names: Vec<String> = executor
+            .stream("SELECT name FROM items ORDER BY id".into(), vec![])
+            .map_ok(|row| row.get_as::<String>("name").unwrap())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        // A transaction keeps one connection: it reads its own writes, and a
+        // rollback undoes them
+        let options = TransactionOptions::new();
+        let err = executor
+            .transaction(&options, |tx| {
+                async move {
+                    tx.execute("INSERT INTO items (name) VALUES ('d')", &[])
+                        .await?;
+                    let rows = tx.query("SELECT name FROM items", &[]).await?;
+                    assert_eq!(rows.len(), 4);
+                    Err::<(), _>(ChakraError::Query(QueryError::ExecutionFailed {
+                        message: "abort".into(),
+                    }))
+                }
+                .boxed()
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("abort"));
+        executor
+            .transaction(&options, |tx| {
+                async move {
+                    tx.execute("DELETE FROM items WHERE name = 'a'", &[])
+                        .await?;
+                    Ok(())
+                }
+                .boxed()
+            })
+            .await
+            .unwrap();
+
+        // So does a transaction begun on the executor
+        let tx = Transaction::begin(&executor).await.unwrap();
+        tx.execute("INSERT INTO items (name) VALUES ('e')", &[])
+            .await
+            .unwrap();
+        let nested = tx.transaction().await.unwrap();
+        nested
+            .execute("DELETE FROM items WHERE name = 'b'", &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            nested
+                .query("SELECT name FROM items", &[])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        nested.commit().await.unwrap();
+        tx.rollback().await.unwrap();
+
+        let rows = executor
+            .query("SELECT name FROM items ORDER BY id", &[])
+            .await
+            .unwrap();
+        let names: Vec<String> = rows.iter().map(|row| row.get_as("name").unwrap()).collect();
+        assert_eq!(names, ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_debug_statements() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE items (name TEXT NOT NULL UNIQUE)")
+            .await
+            .unwrap();
+
+        let tx = Transaction::begin(&executor)
+            .await
+            .unwrap()
+            .debug_statements();
+        let insert = "INSERT INTO items (name) VALUES (?1)";
+        tx.execute(insert, &["a".into()]).await.unwrap();
+        tx.execute(insert, &["b".into()]).await.unwrap();
+        let err = tx.execute(insert, &["a".into()]).await.unwrap_err();
+        assert!(err.is_unique_violation());
+
+        let nested = tx.transaction().await.unwrap();
+        assert!(nested.execute(insert, &[Value::Null]).await.is_err());
+        nested.commit().await.unwrap();
+
+        let failures = tx.failed_statements();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].index, 3);
+        assert_eq!(failures[0].params, vec![Value::String("a".to_string())]);
+        assert_eq!(failures[1].index, 4);
+        assert!(failures[1].to_string().starts_with("Statement 4 failed: "));
+        // Only the failing statements were rolled back
+        tx.execute(insert, &["c".into()]).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let rows = executor.query("SELECT name FROM items", &[]).await.unwrap();
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_set_constraints_deferred() {
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE teams (id INTEGER PRIMARY KEY, captain_id INTEGER NOT NULL REFERENCES players (id));
+                 CREATE TABLE players (id INTEGER PRIMARY KEY, team_id INTEGER NOT NULL REFERENCES teams (id));",
+            )
+            .await
+            .unwrap();
+        let insert_team = "INSERT INTO teams (id, captain_id) VALUES (1, 10)";
+        let insert_player = "INSERT INTO players (id, team_id) VALUES (10, 1)";
+
+        let tx = Transaction::begin(&executor).await.unwrap();
+        let err = tx.execute(insert_team, &[]).await.unwrap_err();
+        assert!(err.to_string().contains("FOREIGN KEY constraint failed"));
+        tx.rollback().await.unwrap();
+
+        let tx = Transaction::begin(&executor).await.unwrap();
+        tx.set_constraints_deferred(&[]).await.unwrap();
+        tx.execute(insert_team, &[]).await.unwrap();
+        tx.execute(insert_player, &[]).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let rows = executor
+            .query(
+                "SELECT teams.id, players.id AS captain FROM teams \
+                 JOIN players ON players.id = teams.captain_id",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_as::<i64>("id").unwrap(), 1);
+        assert_eq!(rows[0].get_as::<i64>("captain").unwrap(), 10);
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "tasks")]
+    struct Task {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        title: String,
+        done: bool,
+    }
+
+    #[tokio::test]
+    async fn test_queryset() {
+        use chakra_core::expr::Q;
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE tasks (id INTEGER PRIMARY KEY, title TEXT, done BOOLEAN);
+                 INSERT INTO tasks (title, done) VALUES ('write', 1), ('test', 0), ('ship', 0);",
+            )
+            .await
+            .unwrap();
+
+        let open = Task::objects().filter(Q::new("done", false));
+        assert_eq!(open.count(&executor).await.unwrap(), 2);
+        assert_eq!(open.clone().limit(1).count(&executor).await.unwrap(), 1);
+        assert!(open.exists(&executor).await.unwrap());
+
+        let titles: Vec<_> = open
+            .clone()
+            .order_by("-title")
+            .all(&executor)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.title)
+            .collect();
+        assert_eq!(titles, vec!["test", "ship"]);
+
+        let first = Task::objects().first(&executor).await.unwrap().unwrap();
+        assert_eq!(first.title, "write");
+
+        let shipped = Task::objects().filter(Q::new("title", "ship"));
+        assert_eq!(shipped.get(&executor).await.unwrap().id, 3);
+        assert!(open
+            .get(&executor)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("Multiple"));
+        let missing = Task::objects()
+            .exclude(Q::new("title", "ship"))
+            .filter(Q::new("title", "ship"));
+        assert!(missing.get(&executor).await.unwrap_err().is_not_found());
+        assert!(!missing.exists(&executor).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_in_bulk() {
+        use chakra_core::crud::Crud;
+        use chakra_core::expr::Q;
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE tasks (id INTEGER PRIMARY KEY, title TEXT, done BOOLEAN)")
+            .await
+            .unwrap();
+        // Several batches of ids
+        let rows: Vec<Vec<Value>> = (1..=2_500i64)
+            .map(|id| {
+                vec![
+                    Value::Int64(id),
+                    Value::String(format!("task {}", id)),
+                    Value::Bool(id % 2 == 0),
+                ]
+            })
+            .collect();
+        executor
+            .execute_many(
+                "INSERT INTO tasks (id, title, done) VALUES (?, ?, ?)",
+                &rows,
+            )
+            .await
+            .unwrap();
+
+        let mut ids: Vec<i64> = (1..=2_500).collect();
+        ids.extend([7, 7, 99_999]);
+        let tasks = Task::in_bulk(&executor, &ids).await.unwrap();
+        assert_eq!(tasks.len(), 2_500);
+        assert_eq!(tasks[&2_499].title, "task 2499");
+        assert!(!tasks.contains_key(&99_999));
+
+        // The queryset's filters still apply
+        let open = Task::objects()
+            .filter(Q::new("done", false))
+            .in_bulk(&executor, &ids)
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1_250);
+        assert!(open.keys().all(|id| id % 2 == 1));
+
+        assert!(Task::in_bulk(&executor, &[]).await.unwrap().is_empty());
+        assert!(Task::objects()
+            .limit(5)
+            .in_bulk(&executor, &[1])
+            .await
+            .is_err());
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "notes")]
+    struct Note {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        title: String,
+        body: String,
+        #[chakra(snapshot)]
+        loaded: chakra_core::model::Snapshot,
+    }
+
+    #[tokio::test]
+    async fn test_changed_fields() {
+        use chakra_core::crud::Crud;
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE notes (id INTEGER PRIMARY KEY, title TEXT, body TEXT)")
+            .await
+            .unwrap();
+
+        let mut note = Note {
+            title: "draft".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(note.changed_fields(), None);
+        note.save(&executor).await.unwrap();
+        assert!(note.changed_fields().unwrap().is_empty());
+
+        let mut loaded = Note::get(&executor, note.id).await.unwrap();
+        loaded.title = "final".to_string();
+        assert_eq!(loaded.changed_columns().unwrap(), ["title"]);
+        assert_eq!(loaded.changed_fields().unwrap().bits(), 0b010);
+
+        // Only the title is written, keeping a concurrent change to the body
+        executor
+            .execute("UPDATE notes SET body = 'edited'", &[])
+            .await
+            .unwrap();
+        loaded.save(&executor).await.unwrap();
+        assert_eq!(loaded.body, "edited");
+        assert!(loaded.changed_fields().unwrap().is_empty());
+
+        // Nothing changed, so nothing is sent, not even the fallback INSERT
+        executor.execute("DELETE FROM notes", &[]).await.unwrap();
+        loaded.save(&executor).await.unwrap();
+        assert_eq!(Note::objects().count(&executor).await.unwrap(), 0);
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "patients")]
+    struct Patient {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        diagnosis: chakra_core::encryption::Encrypted<String>,
+        #[chakra(encrypted)]
+        ssn: Option<String>,
+        #[chakra(snapshot)]
+        loaded: chakra_core::model::Snapshot,
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_fields() {
+        use chakra_core::crud::Crud;
+        use chakra_core::encryption::{set_keyring, Aes256GcmCipher, Encrypted, Keyring};
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE patients (id INTEGER PRIMARY KEY, diagnosis BLOB, ssn BLOB)",
+            )
+            .await
+            .unwrap();
+        set_keyring(Keyring::new(Aes256GcmCipher::new("k1", &[1; 32])));
+
+        let mut patient = Patient {
+            diagnosis: Encrypted::new("flu".to_string()),
+            ssn: Some("123-45-6789".to_string()),
+            ..Default::default()
+        };
+        patient.save(&executor).await.unwrap();
+        let mut other = Patient::default();
+        other.save(&executor).await.unwrap();
+
+        // Only ciphertext reaches the database, and NULL stays NULL
+        let rows = executor
+            .query("SELECT diagnosis, ssn FROM patients ORDER BY id", &[])
+            .await
+            .unwrap();
+        let stored = rows[0].get("ssn").and_then(Value::as_bytes).unwrap();
+        assert!(!stored.windows(11).any(|w| w == b"123-45-6789"));
+        assert_eq!(rows[1].get("ssn"), Some(&Value::Null));
+
+        let mut loaded = Patient::get(&executor, patient.id).await.unwrap();
+        assert_eq!(
+            (loaded.diagnosis.as_str(), loaded.ssn.as_deref()),
+            ("flu", Some("123-45-6789"))
+        );
+        assert!(loaded.changed_fields().unwrap().is_empty());
+        loaded.ssn = None;
+        assert_eq!(loaded.changed_columns().unwrap(), ["ssn"]);
+        loaded.save(&executor).await.unwrap();
+        assert_eq!(Patient::get(&executor, patient.id).await.unwrap().ssn, None);
+
+        // After a rotation old rows still decrypt, and move to the new key when saved
+        set_keyring(
+            Keyring::new(Aes256GcmCipher::new("k2", &[2; 32]))
+                .previous(Aes256GcmCipher::new("k1", &[1; 32])),
+        );
+        let mut loaded = Patient::get(&executor, patient.id).await.unwrap();
+        assert_eq!(loaded.diagnosis.key_id(), Some("k1"));
+        assert!(loaded.diagnosis.needs_rotation());
+        loaded.diagnosis = loaded.diagnosis.rotate();
+        loaded.save(&executor).await.unwrap();
+        let loaded = Patient::get(&executor, patient.id).await.unwrap();
+        assert_eq!(loaded.diagnosis.key_id(), Some("k2"));
+        assert_eq!(*loaded.diagnosis, "flu");
+
+        set_keyring(Keyring::new(Aes256GcmCipher::new("k1", &[9; 32])));
+        assert!(Patient::get(&executor, patient.id).await.is_err());
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "signups")]
+    struct Signup {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        #[chakra(validate(length(min = 1, max = 20), custom = "not_reserved"))]
+        username: String,
+        #[chakra(validate(email))]
+        email: Option<String>,
+        #[chakra(validate(range(min = 13, max = 150)))]
+        age: i32,
+    }
+
+    fn not_reserved(username: &str) -> std::result::Result<(), String> {
+        match username {
+            "admin" | "root" => Err("is reserved".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validation() {
+        use chakra_core::crud::Crud;
+        use chakra_core::error::{ChakraError, ValidationError};
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch("CREATE TABLE signups (id INTEGER PRIMARY KEY, username TEXT, email TEXT, age INTEGER)")
+            .await
+            .unwrap();
+
+        let mut signup = Signup {
+            username: "admin".to_string(),
+            email: Some("not-an-address".to_string()),
+            age: 9,
+            ..Default::default()
+        };
+        let err = signup.save(&executor).await.unwrap_err();
+        assert!(err.is_validation_error());
+        let ChakraError::Validation(err) = err else {
+            unreachable!()
+        };
+        let fields: Vec<_> = err.errors().iter().filter_map(|e| e.field()).collect();
+        assert_eq!(fields, ["username", "email", "age"]);
+        assert_eq!(Signup::objects().count(&executor).await.unwrap(), 0);
+
+        // Unset optional fields are not checked
+        signup.username = "ada".to_string();
+        signup.email = None;
+        signup.age = 36;
+        assert!(signup.validate().is_ok());
+        signup.save(&executor).await.unwrap();
+
+        signup.username = String::new();
+        assert!(matches!(
+            signup.update(&executor).await,
+            Err(ChakraError::Validation(ValidationError::Fields { .. }))
+        ));
+        assert!(Signup::bulk_create(&executor, vec![signup.clone()])
+            .await
+            .is_err());
+        assert!(Signup::bulk_update(&executor, &[signup], &["username"])
+            .await
+            .is_err());
+        assert_eq!(Signup::get(&executor, 1).await.unwrap().username, "ada");
+    }
+
+    #[tokio::test]
+    async fn test_model_crud() {
+        use chakra_core::crud::Crud;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE tasks (id INTEGER PRIMARY KEY, title TEXT, done BOOLEAN DEFAULT 0);
+                 CREATE TRIGGER tasks_trim AFTER UPDATE ON tasks BEGIN
+                     UPDATE tasks SET title = trim(title) WHERE id = new.id;
+                 END;",
+            )
+            .await
+            .unwrap();
+
+        let mut task = Task {
+            title: "write".to_string(),
+            ..Default::default()
+        };
+        task.save(&executor).await.unwrap();
+        assert_eq!(task.id, 1);
+
+        task.title = " ship ".to_string();
+        task.done = true;
+        task.save(&executor).await.unwrap();
+        let loaded = Task::get(&executor, 1).await.unwrap();
+        assert_eq!(loaded.title, "ship");
+        assert!(loaded.done);
+
+        task.refresh(&executor).await.unwrap();
+        assert_eq!(task.title, "ship");
+
+        // A set key with no row yet is inserted
+        let mut imported = Task {
+            id: 10,
+            title: "import".to_string(),
+            done: false,
+        };
+        imported.save(&executor).await.unwrap();
+        assert_eq!(Task::get(&executor, 10).await.unwrap().title, "import");
+        assert!(imported.clone().update(&executor).await.is_ok());
+
+        assert_eq!(task.delete(&executor).await.unwrap(), 1);
+        assert!(Task::get(&executor, 1).await.unwrap_err().is_not_found());
+        assert!(task.update(&executor).await.unwrap_err().is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_create_update() {
+        use chakra_core::crud::{BulkCreate, Crud};
+        use chakra_core::model::Model;
+        use chakra_core::query::OnConflict;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE tasks (id INTEGER PRIMARY KEY, title TEXT UNIQUE, done BOOLEAN)",
+            )
+            .await
+            .unwrap();
+
+        let tasks: Vec<Task> = ["write", "test", "ship"]
+            .iter()
+            .map(|title| Task {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(Task::bulk_create(&executor, tasks).await.unwrap(), 3);
+
+        let again = vec![
+            Task {
+                title: "ship".to_string(),
+                ..Default::default()
+            },
+            Task {
+                title: "celebrate".to_string(),
+                ..Default::default()
+            },
+        ];
+        let options = BulkCreate::new()
+            .batch_size(1)
+            .on_conflict(OnConflict::any().do_nothing());
+        assert_eq!(
+            Task::bulk_create_with(&executor, again, options)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // The batches share a transaction: the duplicate undoes "plan"
+        let clash = vec![
+            Task {
+                title: "plan".to_string(),
+                ..Default::default()
+            },
+            Task {
+                title: "write".to_string(),
+                ..Default::default()
+            },
+        ];
+        let options = BulkCreate::new().batch_size(1);
+        assert!(Task::bulk_create_with(&executor, clash, options)
+            .await
+            .is_err());
+
+        let mut tasks = Task::objects().order_by("id").all(&executor).await.unwrap();
+        assert_eq!(tasks.len(), 4);
+        for task in &mut tasks[..2] {
+            task.done = true;
+            task.title = task.title.to_uppercase();
+        }
+        assert_eq!(
+            Task::bulk_update(&executor, &tasks[..2], &["done"])
+                .await
+                .unwrap(),
+            2
+        );
+
+        let loaded = Task::objects().order_by("id").all(&executor).await.unwrap();
+        let state: Vec<_> = loaded.iter().map(|t| (t.title.as_str(), t.done)).collect();
+        assert_eq!(
+            state,
+            vec![
+                ("write", true),
+                ("test", true),
+                ("ship", false),
+                ("celebrate", false)
+            ]
+        );
+        assert!(Task::bulk_update(&executor, &tasks, &["missing"])
+            .await
+            .is_err());
+    }
+
+    #[derive(chakra_core::DbEnum, Debug, Clone, PartialEq, Default)]
+    enum Priority {
+        #[default]
+        Low,
+        High,
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "tickets")]
+    struct Ticket {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        #[chakra(db_enum = "priority")]
+        priority: Priority,
+    }
+
+    #[tokio::test]
+    async fn test_db_enum_check() {
+        use chakra_core::crud::Crud;
+        use chakra_core::model::Model;
+        use chakra_schema::ddl::SqliteDdlGenerator;
+        use chakra_schema::schema::Schema;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        let schema = Schema::from_models(&[Ticket::meta().clone()]);
+        executor
+            .execute_batch(&schema.to_create_sql(&SqliteDdlGenerator))
+            .await
+            .unwrap();
+
+        let mut ticket = Ticket {
+            id: 0,
+            priority: Priority::High,
+        };
+        ticket.save(&executor).await.unwrap();
+        assert_eq!(
+            Ticket::get(&executor, ticket.id).await.unwrap().priority,
+            Priority::High
+        );
+
+        // Labels outside the enum fail the CHECK
+        let err = executor
+            .execute_batch("INSERT INTO tickets (priority) VALUES ('urgent')")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("CHECK constraint failed"));
+        assert_eq!(Ticket::objects().count(&executor).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_query() {
+        let primary = SqliteExecutor::new(Arc::new(SqliteConnection::open_memory().await.unwrap()));
+        let replica = SqliteExecutor::new(Arc::new(SqliteConnection::open_memory().await.unwrap()));
+        for (executor, rows) in [
+            (&primary, "(1, 'a'), (2, 'b')"),
+            (&replica, "(2, 'b'), (1, 'a')"),
+        ] {
+            executor
+                .execute_batch(&format!(
+                    "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT); INSERT INTO items VALUES {};",
+                    rows
+                ))
+                .await
+                .unwrap();
+        }
+
+        let query = Query::select().from("items").build();
+        let checksum = primary.checksum_query(&query).await.unwrap();
+        assert_eq!(checksum.rows, 2);
+        assert_eq!(checksum, replica.checksum_query(&query).await.unwrap());
+
+        replica
+            .execute_batch("UPDATE items SET name = 'c' WHERE id = 2")
+            .await
+            .unwrap();
+        assert_ne!(checksum, replica.checksum_query(&query).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_exec_result() {
+        let executor =
+            SqliteExecutor::new(Arc::new(SqliteConnection::open_memory().await.unwrap()));
+        executor
+            .execute_batch("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)")
+            .await
+            .unwrap();
+
+        // last_insert_rowid() without RETURNING, the first returning column
+        // with it
+        let insert = Query::insert().table("items").set("name", "a").build();
+        let result = executor.exec(&insert).await.unwrap();
+        assert_eq!(result.rows_affected, 1);
+        assert_eq!(result.last_insert_id, Some(Value::Int64(1)));
+        let insert = Query::insert()
+            .table("items")
+            .set("name", "b")
+            .returning(&["id"])
+            .build();
+        assert_eq!(
+            executor
+                .exec(&insert)
+                .await
+                .unwrap()
+                .last_insert_id_as::<i64>()
+                .unwrap(),
+            2
+        );
+
+        let update = Query::update().table("items").set("name", "c").build();
+        let result = executor.exec(&update).await.unwrap();
+        assert_eq!(
+            result,
+            ExecResult {
+                rows_affected: 2,
+                last_insert_id: None
+            }
+        );
+        assert!(result.last_insert_id_as::<i64>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multi_row_insert_defaults() {
+        let executor =
+            SqliteExecutor::new(Arc::new(SqliteConnection::open_memory().await.unwrap()));
+        executor
+            .execute_batch(
+                "CREATE TABLE tags (id INTEGER PRIMARY KEY, slug TEXT DEFAULT 'untitled', \
+                 color TEXT DEFAULT 'grey')",
+            )
+            .await
+            .unwrap();
+
+        let row = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::from(*v)))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+        let insert = Query::insert()
+            .table("tags")
+            .values(row(&[("slug", "rust")]))
+            .values(row(&[("slug", "sql"), ("color", "red")]))
+            .values(row(&[]))
+            .values(row(&[("slug", "orm")]))
+            .build();
+
+        // One statement can't leave columns out on SQLite
+        let err = executor.execute_query(&insert).await.unwrap_err();
+        assert!(err.to_string().contains("use insert_all"));
+
+        assert_eq!(executor.insert_all(&insert).await.unwrap(), 4);
+        let rows = executor
+            .query("SELECT slug, color FROM tags ORDER BY id", &[])
+            .await
+            .unwrap();
+        let tags: Vec<(String, String)> = rows
+            .iter()
+            .map(|r| (r.get_as("slug").unwrap(), r.get_as("color").unwrap()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("rust".to_string(), "grey".to_string()),
+                ("sql".to_string(), "red".to_string()),
+                ("untitled".to_string(), "grey".to_string()),
+                ("orm".to_string(), "grey".to_string()),
+            ]
+        );
+
+        // The statements run in one transaction: a failing one undoes the rest
+        let insert = Query::insert()
+            .table("tags")
+            .values(row(&[("slug", "go")]))
+            .values(row(&[("id", "1"), ("slug", "dup")]))
+            .build();
+        assert!(executor.insert_all(&insert).await.is_err());
+        let rows = executor.query("SELECT * FROM tags", &[]).await.unwrap();
+        assert_eq!(rows.len(), 4);
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(view = "open_tasks")]
+    struct OpenTask {
+        #[chakra(primary_key)]
+        id: i64,
+        title: String,
+    }
+
+    #[tokio::test]
+    async fn test_view_model() {
+        use chakra_core::crud::Crud;
+        use chakra_core::error::{ChakraError, ModelError};
+        use chakra_core::expr::Expr;
+        use chakra_core::model::Model;
+        use chakra_core::view::View;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE tasks (id INTEGER PRIMARY KEY, title TEXT, done BOOLEAN);
+                 INSERT INTO tasks (title, done) VALUES ('write', 1), ('test', 0), ('ship', 0);",
+            )
+            .await
+            .unwrap();
+        let view = View::define(
+            "open_tasks",
+            Query::select()
+                .from("tasks")
+                .columns(&["id", "title"])
+                .filter(Expr::eq("done", false))
+                .build(),
+        );
+        executor
+            .execute_batch(&view.create_sql(executor.dialect()))
+            .await
+            .unwrap();
+
+        let open = OpenTask::objects()
+            .order_by("id")
+            .all(&executor)
+            .await
+            .unwrap();
+        let titles: Vec<_> = open.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["test", "ship"]);
+        assert_eq!(OpenTask::get(&executor, 3).await.unwrap().title, "ship");
+
+        let mut task = open[0].clone();
+        assert!(matches!(
+            task.save(&executor).await,
+            Err(ChakraError::Model(ModelError::ReadOnly { .. }))
+        ));
+        assert!(task.delete(&executor).await.is_err());
+        assert_eq!(OpenTask::objects().count(&executor).await.unwrap(), 2);
+
+        executor
+            .execute_batch(&view.drop_sql(executor.dialect()))
+            .await
+            .unwrap();
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "projects", tenant_scoped)]
+    #[chakra(index(columns = "tenant_id", expr = "lower(name)", unique))]
+    #[chakra(index(
+        name = "projects_recent",
+        columns = "tenant_id, id",
+        include = "name",
+        fillfactor = 90
+    ))]
+    struct Project {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        tenant_id: i64,
+        name: String,
+    }
+
+    #[test]
+    fn test_model_indexes() {
+        use chakra_core::model::Model;
+
+        let indexes = &Project::meta().indexes;
+        assert_eq!(indexes[0].name, "projects_tenant_id_lower_name_idx");
+        assert_eq!(indexes[0].columns, ["tenant_id"]);
+        assert_eq!(indexes[0].expressions, ["lower(name)"]);
+        assert!(indexes[0].unique);
+        assert_eq!(indexes[1].name, "projects_recent");
+        assert_eq!(indexes[1].include, ["name"]);
+        assert_eq!(indexes[1].storage["fillfactor"], "90");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_scoping() {
+        use chakra_core::crud::Crud;
+        use chakra_core::model::Model;
+        use chakra_core::tenant::TenantContext;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE projects (id INTEGER PRIMARY KEY, tenant_id INTEGER, name TEXT);
+                 INSERT INTO projects (tenant_id, name) VALUES (1, 'alpha'), (2, 'beta');",
+            )
+            .await
+            .unwrap();
+
+        let other = TenantContext::scope(2, async {
+            let mut project = Project {
+                name: "gamma".to_string(),
+                ..Default::default()
+            };
+            project.save(&executor).await.unwrap();
+            assert_eq!(project.tenant_id, 2);
+
+            let names: Vec<_> = Project::objects()
+                .order_by("id")
+                .all(&executor)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name)
+                .collect();
+            assert_eq!(names, vec!["beta", "gamma"]);
+            assert_eq!(
+                Project::objects()
+                    .all_tenants()
+                    .count(&executor)
+                    .await
+                    .unwrap(),
+                3
+            );
+
+            // Tenant 1's row is invisible and untouchable from tenant 2
+            assert!(Project::get(&executor, 1).await.unwrap_err().is_not_found());
+            let mut stolen = Project {
+                id: 1,
+                tenant_id: 1,
+                name: "stolen".to_string(),
+            };
+            assert_eq!(stolen.delete(&executor).await.unwrap(), 0);
+            assert!(stolen.update(&executor).await.unwrap_err().is_not_found());
+            assert_eq!(
+                Project::bulk_update(&executor, &[stolen], &["name"])
+                    .await
+                    .unwrap(),
+                0
+            );
+            project
+        })
+        .await;
+        assert_eq!(other.tenant_id, 2);
+
+        // Outside a scope nothing is filtered
+        assert_eq!(Project::objects().count(&executor).await.unwrap(), 3);
+        assert_eq!(Project::get(&executor, 1).await.unwrap().name, "alpha");
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "members")]
+    struct Member {
+        #[chakra(primary_key, auto_increment)]
+        id: i64,
+        org_id: i64,
+        name: String,
+        active: bool,
+    }
+
+    #[tokio::test]
+    async fn test_registered_scopes() {
+        use chakra_core::crud::Crud;
+        use chakra_core::expr::Expr;
+        use chakra_core::model::{Model, ModelMeta};
+        use chakra_core::scope::{register_model_scope, register_scope, unregister_scope};
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE members (id INTEGER PRIMARY KEY, org_id INTEGER, name TEXT, active BOOLEAN);
+                 INSERT INTO members (org_id, name, active) VALUES (1, 'ann', 1), (1, 'bob', 0), (2, 'cy', 1);",
+            )
+            .await
+            .unwrap();
+
+        register_scope("org", |meta: &ModelMeta| {
+            meta.get_field("org_id")?;
+            Some(Expr::eq(format!("{}.org_id", meta.table), 1))
+        });
+        register_model_scope::<Member>("active", || Some(Expr::eq("members.active", true)));
+
+        let names = |members: Vec<Member>| members.into_iter().map(|m| m.name).collect::<Vec<_>>();
+        let scoped = Member::objects().order_by("id");
+        assert_eq!(names(scoped.all(&executor).await.unwrap()), ["ann"]);
+        assert_eq!(
+            names(
+                scoped
+                    .clone()
+                    .without_scope("active")
+                    .all(&executor)
+                    .await
+                    .unwrap()
+            ),
+            ["ann", "bob"]
+        );
+        assert_eq!(scoped.clone().unscoped().count(&executor).await.unwrap(), 3);
+        assert!(Member::get(&executor, 2).await.unwrap_err().is_not_found());
+        let explain = scoped.explain(executor.dialect()).unwrap();
+        assert!(explain.contains("-- scope org: \"members\".\"org_id\" = 1"));
+
+        // Saving and refreshing see every row
+        let mut bob = Member {
+            id: 2,
+            org_id: 1,
+            name: "bob".to_string(),
+            active: true,
+        };
+        bob.save(&executor).await.unwrap();
+        bob.refresh(&executor).await.unwrap();
+        assert_eq!(Member::objects().count(&executor).await.unwrap(), 2);
+
+        assert!(unregister_scope("org"));
+        assert!(unregister_scope("active"));
+        assert_eq!(Member::objects().count(&executor).await.unwrap(), 3);
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "authors")]
+    struct Author {
+        #[chakra(primary_key)]
+        id: i64,
+        name: String,
+        #[chakra(has_many = "author_id")]
+        posts: chakra_core::model::Related<Vec<Post>>,
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "posts")]
+    struct Post {
+        #[chakra(primary_key)]
+        id: i64,
+        title: String,
+        author_id: Option<i64>,
+        #[chakra(belongs_to = "author_id")]
+        author: chakra_core::model::Related<Author>,
+        #[chakra(many_to_many(
+            through = "post_tags",
+            foreign_key = "post_id",
+            related_key = "tag_id"
+        ))]
+        tags: chakra_core::model::Related<Vec<Tag>>,
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "tags")]
+    struct Tag {
+        #[chakra(primary_key)]
+        id: i64,
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_eager_loading() {
+        use chakra_core::model::Model;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE post_tags (post_id INTEGER, tag_id INTEGER);
+                 INSERT INTO authors VALUES (1, 'ada'), (2, 'alan');
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'notes', 1), (3, 'orphan', NULL);
+                 INSERT INTO tags VALUES (1, 'math'), (2, 'history');
+                 INSERT INTO post_tags VALUES (1, 1), (1, 2), (2, 1);",
+            )
+            .await
+            .unwrap();
+
+        let posts = Post::objects()
+            .select_related("author")
+            .prefetch_related("tags")
+            .order_by("posts.id")
+            .all(&executor)
+            .await
+            .unwrap();
+        assert_eq!(posts[0].author.get().unwrap().name, "ada");
+        assert_eq!(posts[0].title, "engines");
+        assert!(posts[2].author.get_optional().unwrap().is_none());
+        let tags: Vec<_> = posts[0]
+            .tags
+            .get()
+            .unwrap()
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(tags, vec!["math", "history"]);
+        assert!(posts[2].tags.get().unwrap().is_empty());
+
+        let authors = Author::objects()
+            .prefetch_related("posts")
+            .order_by("id")
+            .all(&executor)
+            .await
+            .unwrap();
+        assert_eq!(authors[0].posts.get().unwrap().len(), 2);
+        assert!(authors[1].posts.get().unwrap().is_empty());
+
+        let author = Post::objects()
+            .prefetch_related("author")
+            .first(&executor)
+            .await
+            .unwrap();
+        assert_eq!(author.unwrap().author.get().unwrap().id, 1);
+
+        assert!(Author::objects().select_related("posts").query().is_err());
+        assert!(Post::objects()
+            .prefetch_related("editor")
+            .all(&executor)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_select_related_custom_columns() {
+        use chakra_core::model::Model;
+
+        #[derive(chakra_core::Model, Debug, Clone, Default)]
+        #[chakra(table = "customer")]
+        struct Customer {
+            #[chakra(primary_key, column = "customer_id")]
+            id: i64,
+            #[chakra(column = "where")]
+            name: String,
+        }
+
+        #[derive(chakra_core::Model, Debug, Clone, Default)]
+        #[chakra(table = "order")]
+        struct Order {
+            #[chakra(primary_key, column = "order_id")]
+            id: i64,
+            #[chakra(column = "group")]
+            group_name: String,
+            customer_id: Option<i64>,
+            #[chakra(belongs_to = "customer_id")]
+            customer: chakra_core::model::Related<Customer>,
+        }
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                r#"CREATE TABLE "customer" ("customer_id" INTEGER PRIMARY KEY, "where" TEXT);
+                 CREATE TABLE "order" ("order_id" INTEGER PRIMARY KEY, "group" TEXT, "customer_id" INTEGER);
+                 INSERT INTO "customer" VALUES (1, 'ada');
+                 INSERT INTO "order" VALUES (1, 'retail', 1), (2, 'trade', NULL);"#,
+            )
+            .await
+            .unwrap();
+
+        let query = Order::objects().select_related("customer").query().unwrap();
+        assert_eq!(
+            SqliteDialect.generate(&query).sql,
+            "SELECT \"order\".*, \"customer\".\"customer_id\" AS \"customer__customer_id\", \
+             \"customer\".\"where\" AS \"customer__where\" FROM \"order\" \
+             LEFT JOIN \"customer\" AS \"customer\" ON \"customer\".\"customer_id\" = \"order\".\"customer_id\""
+        );
+        let orders = Order::objects()
+            .select_related("customer")
+            .order_by("order_id")
+            .all(&executor)
+            .await
+            .unwrap();
+        assert_eq!(orders[0].group_name, "retail");
+        assert_eq!(orders[0].customer.get().unwrap().name, "ada");
+        assert!(orders[1].customer.get_optional().unwrap().is_none());
+
+        let pairs: Vec<(i64, String)> = Order::objects()
+            .order_by("order_id")
+            .values_list(&executor, &["order_id", "group"])
+            .await
+            .unwrap();
+        assert_eq!(pairs, [(1, "retail".to_string()), (2, "trade".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_and_annotate() {
+        use chakra_core::expr::Expr;
+        use chakra_core::model::Model;
+        use chakra_core::queryset::{Avg, Count, Max};
+        use chakra_core::result::Row;
+
+        #[derive(chakra_core::FromRow)]
+        struct Totals {
+            count: i64,
+            last_author: Option<i64>,
+        }
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE post_tags (post_id INTEGER, tag_id INTEGER);
+                 INSERT INTO authors VALUES (1, 'ada'), (2, 'alan');
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'notes', 1), (3, 'orphan', NULL);
+                 INSERT INTO tags VALUES (1, 'math'), (2, 'history');
+                 INSERT INTO post_tags VALUES (1, 1), (1, 2), (2, 1);",
+            )
+            .await
+            .unwrap();
+
+        let totals: Totals = Post::objects()
+            .aggregate(
+                &executor,
+                [Count("*"), Max("author_id").alias("last_author")],
+            )
+            .await
+            .unwrap();
+        assert_eq!((totals.count, totals.last_author), (3, Some(1)));
+        let row: Row = Post::objects()
+            .filter(Expr::is_not_null("author_id"))
+            .aggregate(&executor, [Avg("id").alias("mean")])
+            .await
+            .unwrap();
+        assert_eq!(row.get_as::<f64>("mean").unwrap(), 1.5);
+        let row: Row = Post::objects()
+            .aggregate(
+                &executor,
+                [
+                    Count("tags"),
+                    Count("tags").distinct().alias("distinct_tags"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get_as::<i64>("tags__count").unwrap(), 3);
+        assert_eq!(row.get_as::<i64>("distinct_tags").unwrap(), 2);
+        // Limits apply before aggregating
+        let row: Row = Post::objects()
+            .order_by("id")
+            .limit(1)
+            .aggregate(&executor, [Max("id")])
+            .await
+            .unwrap();
+        assert_eq!(row.get_as::<i64>("id__max").unwrap(), 1);
+
+        let authors = Author::objects()
+            .annotate(Count("posts"))
+            .annotate(
+                Count("posts")
+                    .filter(Expr::eq("posts.title", "notes"))
+                    .alias("notes"),
+            )
+            .order_by("-posts__count")
+            .all_annotated(&executor)
+            .await
+            .unwrap();
+        assert_eq!(authors[0].name, "ada");
+        assert_eq!(authors[0].get::<i64>("posts__count").unwrap(), 2);
+        assert_eq!(authors[0].get::<i64>("notes").unwrap(), 1);
+        assert_eq!(authors[1].get::<i64>("posts__count").unwrap(), 0);
+
+        let posts = Post::objects()
+            .select_related("author")
+            .annotate(Count("tags"))
+            .order_by("posts.id")
+            .all_annotated(&executor)
+            .await
+            .unwrap();
+        let counts: Vec<i64> = posts
+            .iter()
+            .map(|p| p.get("tags__count").unwrap())
+            .collect();
+        assert_eq!(counts, [2, 1, 0]);
+        assert_eq!(posts[1].author.get().unwrap().name, "ada");
+    }
+
+    #[tokio::test]
+    async fn test_values_and_values_list() {
+        use chakra_core::expr::Q;
+        use chakra_core::model::Model;
+        use chakra_core::types::Value;
+
+        #[derive(chakra_core::FromRow)]
+        struct Title {
+            title: String,
+        }
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'notes', 1), (3, 'orphan', NULL);",
+            )
+            .await
+            .unwrap();
+
+        let rows = Post::objects()
+            .filter(Q::new("id", 1))
+            .values(&executor, &["title"])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), 1);
+        assert_eq!(rows[0]["title"], Value::String("engines".to_string()));
+        let rows = Post::objects()
+            .filter(Q::new("id", 3))
+            .values(&executor, &[])
+            .await
+            .unwrap();
+        assert_eq!(rows[0].len(), 3);
+        assert_eq!(rows[0]["author_id"], Value::Null);
+
+        let pairs: Vec<(i64, Option<i64>)> = Post::objects()
+            .order_by("-id")
+            .values_list(&executor, &["id", "author_id"])
+            .await
+            .unwrap();
+        assert_eq!(pairs, [(3, None), (2, Some(1)), (1, Some(1))]);
+        let titles: Vec<Title> = Post::objects()
+            .order_by("id")
+            .limit(1)
+            .values_list(&executor, &["title"])
+            .await
+            .unwrap();
+        assert_eq!(titles[0].title, "engines");
+        let authors: Vec<(Option<i64>,)> = Post::objects()
+            .distinct()
+            .values_list(&executor, &["author_id"])
+            .await
+            .unwrap();
+        assert_eq!(authors.len(), 2);
+
+        assert!(Post::objects()
+            .values(&executor, &["author"])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_raw() {
+        use chakra_core::crud::Crud;
+        use chakra_core::raw::RawQuery;
+        use futures::TryStreamExt;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'notes', 1), (3, 'orphan', NULL);",
+            )
+            .await
+            .unwrap();
+
+        // `$N` placeholders are rewritten for SQLite
+        let sql = "SELECT * FROM posts WHERE author_id = $1 AND id > $2 ORDER BY id";
+        let posts = Post::raw(sql, vec![1i64.into(), 1i64.into()])
+            .all(&executor)
+            .await
+            .unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "notes");
+
+        let fragment =
+            chakra_core::sql!("SELECT * FROM posts WHERE title = :title", title = "orphan");
+        let post = Post::raw_fragment(fragment).get(&executor).await.unwrap();
+        assert_eq!(post.id, 3);
+        let err = Post::raw("SELECT * FROM posts", vec![])
+            .get(&executor)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Multiple"));
+
+        let counts: Vec<_> = RawQuery::<(Option<i64>, i64)>::new(chakra_core::sql!(
+            "SELECT author_id, COUNT(*) FROM posts WHERE id >= $1 GROUP BY author_id ORDER BY author_id",
+            1i64
+        ))
+        .stream(&executor)
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(counts, [(None, 1), (Some(1), 2)]);
+
+        // Miscounted parameters fail before anything is sent
+        let err = Post::raw(
+            "SELECT * FROM posts WHERE id = $1",
+            vec![1i64.into(), 2i64.into()],
+        )
+        .first(&executor)
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("$2 is never used"));
+        assert!(Post::raw("SELECT * FROM posts WHERE id = ?", vec![])
+            .all(&executor)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_from_row_attributes() {
+        use chakra_core::raw::RawQuery;
+
+        #[derive(chakra_core::FromRow, Debug, PartialEq)]
+        struct Byline {
+            id: i64,
+            #[chakra(rename = "full_name")]
+            name: String,
+        }
+
+        #[derive(chakra_core::FromRow)]
+        struct PostRow {
+            title: String,
+            #[chakra(default)]
+            views: i64,
+            #[chakra(flatten_option, prefix = "author_")]
+            byline: Option<Byline>,
+            // Models flatten too, reading their own column names
+            #[chakra(flatten_option)]
+            author: Option<Author>,
+        }
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 INSERT INTO authors VALUES (1, 'ada');
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'orphan', NULL);",
+            )
+            .await
+            .unwrap();
+
+        let rows = RawQuery::<PostRow>::new(chakra_core::sql!(
+            "SELECT p.title, a.id AS author_id, a.name AS author_full_name, a.id, a.name
+             FROM posts p LEFT JOIN authors a ON a.id = p.author_id ORDER BY p.id"
+        ))
+        .all(&executor)
+        .await
+        .unwrap();
+        assert_eq!(rows[0].views, 0);
+        assert_eq!(
+            rows[0].byline,
+            Some(Byline {
+                id: 1,
+                name: "ada".to_string()
+            })
+        );
+        assert_eq!(rows[0].author.as_ref().unwrap().name, "ada");
+        assert_eq!(rows[1].title, "orphan");
+        assert!(rows[1].byline.is_none() && rows[1].author.is_none());
+
+        // A present column still has to decode
+        let err =
+            RawQuery::<PostRow>::new(chakra_core::sql!("SELECT 'x' AS title, 'many' AS views"))
+                .all(&executor)
+                .await;
+        assert!(err.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_from_row_decimal_and_dates() {
+        use chakra_core::error::ChakraError;
+        use chakra_core::raw::RawQuery;
+        use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+        use rust_decimal::Decimal;
+
+        #[derive(chakra_core::FromRow)]
+        struct OrderRow {
+            total: Decimal,
+            placed_on: NaiveDate,
+            placed_at: NaiveDateTime,
+            cutoff: Option<NaiveTime>,
+            // AVG is a REAL, truncated to whole units
+            #[chakra(lossy)]
+            average: i64,
+        }
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE orders (total DECIMAL(10, 2), placed_on DATE, placed_at TIMESTAMP, cutoff TIME);
+                 INSERT INTO orders VALUES (19.99, '2024-05-01', '2024-05-01 12:30:00', '17:00:00'),
+                                           (5, '2024-05-02', '2024-05-02T08:00:00.250', NULL);",
+            )
+            .await
+            .unwrap();
+
+        let rows = RawQuery::<OrderRow>::new(chakra_core::sql!(
+            "SELECT total, placed_on, placed_at, cutoff, (SELECT AVG(total) FROM orders) AS average
+             FROM orders ORDER BY placed_on"
+        ))
+        .all(&executor)
+        .await
+        .unwrap();
+        assert_eq!(rows[0].total, Decimal::new(1999, 2));
+        assert_eq!(rows[1].total, Decimal::from(5));
+        assert_eq!(
+            rows[0].placed_on,
+            NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()
+        );
+        assert_eq!(rows[1].placed_at.to_string(), "2024-05-02 08:00:00.250");
+        assert_eq!(rows[0].cutoff, NaiveTime::from_hms_opt(17, 0, 0));
+        assert_eq!((rows[0].average, rows[1].cutoff), (12, None));
+
+        // Without lossy, a fractional average is an error rather than truncated
+        #[derive(chakra_core::FromRow)]
+        struct Strict {
+            #[allow(dead_code)]
+            average: i64,
+        }
+        let err = RawQuery::<Strict>::new(chakra_core::sql!(
+            "SELECT AVG(total) AS average FROM orders"
+        ))
+        .all(&executor)
+        .await;
+        assert!(matches!(err, Err(ChakraError::TypeConversion { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        use chakra_core::crud::Crud;
+        use chakra_core::model::Model;
+        use chakra_core::router::Router;
+
+        let main = Arc::new(SqliteExecutor::new(Arc::new(
+            SqliteConnection::open_memory().await.unwrap(),
+        )));
+        let blog = Arc::new(SqliteExecutor::new(Arc::new(
+            SqliteConnection::open_memory().await.unwrap(),
+        )));
+        main.execute_batch("CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);")
+            .await
+            .unwrap();
+        blog.execute_batch(
+            "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+             INSERT INTO posts VALUES (1, 'engines', 1);",
+        )
+        .await
+        .unwrap();
+        let router = Router::new(main.clone())
+            .database("blog", blog.clone())
+            .route_model("Post", "blog");
+
+        let mut author = Author {
+            name: "ada".to_string(),
+            ..Default::default()
+        };
+        author.insert(&router).await.unwrap();
+        let mut post = Post {
+            title: "notes".to_string(),
+            author_id: Some(author.id),
+            ..Default::default()
+        };
+        post.insert(&router).await.unwrap();
+
+        assert_eq!(Post::objects().count(&router).await.unwrap(), 2);
+        assert_eq!(Author::objects().count(&router).await.unwrap(), 1);
+        assert_eq!(
+            blog.query("SELECT * FROM posts", &[]).await.unwrap().len(),
+            2
+        );
+        assert!(main.query("SELECT * FROM posts", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        use chakra_core::model::Model;
+        use chakra_core::session::Session;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "PRAGMA foreign_keys = ON;
+                 CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER REFERENCES authors (id));
+                 INSERT INTO authors VALUES (1, 'ada');
+                 INSERT INTO posts VALUES (1, 'engines', 1), (2, 'notes', 1);",
+            )
+            .await
+            .unwrap();
+
+        let mut session = Session::new(&executor);
+        let mut ada = session.get::<Author>(1).await.unwrap().unwrap();
+        executor
+            .execute("UPDATE authors SET name = 'changed'", &[])
+            .await
+            .unwrap();
+        // Loaded instances are reused, not fetched again
+        assert_eq!(session.get::<Author>(1).await.unwrap().unwrap().name, "ada");
+        let posts = session
+            .fetch(&Post::objects().order_by("id"))
+            .await
+            .unwrap();
+        assert_eq!(posts.len(), 2);
+        assert!(session.get::<Author>(9).await.unwrap().is_none());
+
+        // The post is queued before its author but saved after it
+        session.add(Post {
+            id: 3,
+            title: "sequel".to_string(),
+            author_id: Some(2),
+            ..Default::default()
+        });
+        session.add(Author {
+            id: 2,
+            name: "grace".to_string(),
+            ..Default::default()
+        });
+        ada.name = "ada lovelace".to_string();
+        session.add(ada);
+        session.delete(posts[0].clone());
+        session.delete(posts[1].clone());
+        assert_eq!(session.pending(), 5);
+        session.flush().await.unwrap();
+        assert_eq!(session.pending(), 0);
+
+        let rows = executor
+            .query("SELECT title, author_id FROM posts", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_as::<String>("title").unwrap(), "sequel");
+        assert_eq!(rows[0].get_as::<i64>("author_id").unwrap(), 2);
+        let names = executor
+            .query("SELECT name FROM authors ORDER BY id", &[])
+            .await
+            .unwrap();
+        assert_eq!(names[0].get_as::<String>("name").unwrap(), "ada lovelace");
+        assert!(session.cached::<Post>(1).is_none());
+        assert_eq!(session.instances::<Post>().len(), 1);
+        assert_eq!(session.cached::<Author>(2).unwrap().name, "grace");
+
+        // A failed flush rolls back and keeps the queue
+        session.add(Post {
+            title: "orphan".to_string(),
+            author_id: Some(99),
+            ..Default::default()
+        });
+        session.add(Author {
+            id: 3,
+            name: "mary".to_string(),
+            ..Default::default()
+        });
+        assert!(session.flush().await.is_err());
+        assert_eq!(session.pending(), 2);
+        assert_eq!(
+            executor
+                .query("SELECT * FROM authors", &[])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_pool() {
+        use crate::config::SqliteConfig;
+        use chakra_core::session::Session;
+        use chakra_pool::PoolConfig;
+
+        let config = SqliteConfig::shared_memory("test_session_pool");
+        let pool_config = PoolConfig::new("test_session_pool").max_connections(2);
+        let pool = SqlitePool::with_pool_config(config, pool_config)
+            .await
+            .unwrap();
+        let executor = SqliteExecutor::from_pool(Arc::new(pool));
+        executor
+            .execute_batch(
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER REFERENCES authors (id));",
+            )
+            .await
+            .unwrap();
+
+        // The author is saved before the post fails, on the same connection,
+        // so the rollback discards it
+        let mut session = Session::new(&executor);
+        session.add(Author {
+            id: 1,
+            name: "ada".to_string(),
+            ..Default::default()
+        });
+        session.add(Post {
+            title: "orphan".to_string(),
+            author_id: Some(99),
+            ..Default::default()
+        });
+        assert!(session.flush().await.is_err());
+        assert_eq!(session.pending(), 2);
+        let authors = executor.query("SELECT * FROM authors", &[]).await.unwrap();
+        assert!(authors.is_empty());
+        let posts = executor.query("SELECT * FROM posts", &[]).await.unwrap();
+        assert!(posts.is_empty());
+    }
+
+    #[derive(chakra_core::Model, Debug, Clone, Default)]
+    #[chakra(table = "orders", shard_key = "customer_id")]
+    struct Order {
+        #[chakra(primary_key)]
+        id: i64,
+        customer_id: i64,
+        total: i64,
+    }
+
+    #[tokio::test]
+    async fn test_shard_router() {
+        use chakra_core::crud::Crud;
+        use chakra_core::expr::Expr;
+        use chakra_core::model::Model;
+        use chakra_core::shard::ShardRouter;
+
+        let mut shards = Vec::new();
+        for _ in 0..3 {
+            let shard = Arc::new(SqliteExecutor::new(Arc::new(
+                SqliteConnection::open_memory().await.unwrap(),
+            )));
+            shard
+                .execute_batch("CREATE TABLE orders (id INTEGER PRIMARY KEY, customer_id INTEGER, total INTEGER)")
+                .await
+                .unwrap();
+            shards.push(shard);
+        }
+        let router = ShardRouter::new("s0", shards[0].clone())
+            .shard("s1", shards[1].clone())
+            .shard("s2", shards[2].clone())
+            .model::<Order>();
+
+        for id in 1..=30 {
+            let mut order = Order {
+                id,
+                customer_id: id % 10,
+                total: id * 10,
+            };
+            order.insert(&router).await.unwrap();
+        }
+        for shard in &shards {
+            let rows = shard.query("SELECT * FROM orders", &[]).await.unwrap();
+            assert!(!rows.is_empty() && rows.len() < 30);
+        }
+
+        // A customer's orders are all on one shard
+        let mine = Order::objects()
+            .filter(Expr::eq("customer_id", 3))
+            .all(&router)
+            .await
+            .unwrap();
+        assert_eq!(mine.len(), 3);
+        let (_, owner) = router.shard_for(&3i64.into());
+        assert_eq!(
+            owner
+                .query("SELECT * FROM orders WHERE customer_id = 3", &[])
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // Ordering, paging and counts span every shard
+        let top = Order::objects()
+            .order_by("-total")
+            .limit(3)
+            .offset(1)
+            .all(&router)
+            .await
+            .unwrap();
+        assert_eq!(top.iter().map(|o| o.id).collect::<Vec<_>>(), [29, 28, 27]);
+        assert_eq!(Order::objects().count(&router).await.unwrap(), 30);
+
+        let mut order = Order::get(&router, 7).await.unwrap();
+        order.total = 1;
+        order.save(&router).await.unwrap();
+        assert_eq!(Order::get(&router, 7).await.unwrap().total, 1);
+        order.delete(&router).await.unwrap();
+        assert_eq!(Order::objects().count(&router).await.unwrap(), 29);
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        use chakra_core::expr::Expr;
+        use chakra_core::model::Model;
+        use futures::TryStreamExt;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn).with_max_rows_guard(10);
+        executor
+            .execute_batch(
+                "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+                 INSERT INTO authors VALUES (1, 'ada');
+                 WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+                 INSERT INTO posts SELECT i, 'post ' || i, CASE WHEN i % 2 = 0 THEN 1 END FROM n;",
+            )
+            .await
+            .unwrap();
+
+        // The row guard doesn't apply to streams
+        let posts = Post::objects().order_by("id");
+        let mut stream = posts.stream(&executor).unwrap();
+        let mut count = 0;
+        while let Some(post) = stream.try_next().await.unwrap() {
+            count += 1;
+            assert_eq!(post.id, count);
+        }
+        assert_eq!(count, 1000);
+
+        let posts = Post::objects()
+            .select_related("author")
+            .filter(Expr::eq("posts.id", 2));
+        let loaded = posts.stream(&executor).unwrap().collect().await.unwrap();
+        assert_eq!(loaded[0].author.get().unwrap().name, "ada");
+        assert!(Post::objects()
+            .prefetch_related("tags")
+            .stream(&executor)
+            .is_err());
+
+        // Dropping a stream part way frees the connection
+        let posts = Post::objects().order_by("id");
+        let mut stream = posts.stream(&executor).unwrap();
+        assert_eq!(stream.try_next().await.unwrap().unwrap().id, 1);
+        drop(stream);
+        assert_eq!(
+            executor
+                .query("SELECT COUNT(*) AS n FROM posts", &[])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Statements run while reading a stream don't wait for it to end
+        let read = async {
+            let sql = "SELECT id FROM posts ORDER BY id;".to_string();
+            let mut stream = Executor::stream(&executor, sql, Vec::new());
+            let mut ids = Vec::new();
+            while let Some(row) = stream.try_next().await.unwrap() {
+                ids.push(row.get_as::<i64>("id").unwrap());
+                if ids.len() % 300 == 0 {
+                    executor
+                        .execute("UPDATE authors SET name = name", &[])
+                        .await
+                        .unwrap();
+                }
+            }
+            ids
+        };
+        let ids = tokio::time::timeout(std::time::Duration::from_secs(10), read)
+            .await
+            .expect("stream deadlocked");
+        assert_eq!(ids, (1..=1000).collect::<Vec<i64>>());
+
+        let mut stream =
+            Executor::stream(&executor, "SELECT * FROM missing".to_string(), Vec::new());
+        let err = stream.try_next().await.unwrap_err();
+        assert!(err.to_string().contains("no such table: missing"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_reader() {
+        use futures::TryStreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = crate::config::SqliteConfig::new(dir.path().join("stream.db"));
+        let conn = Arc::new(SqliteConnection::open(config).await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT);
+                 WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+                 INSERT INTO posts SELECT i, 'post ' || i FROM n;",
+            )
+            .await
+            .unwrap();
+
+        // The stream reads one snapshot on its own connection, so writes to
+        // the same table neither wait for it nor shift its rows
+        let sql = "SELECT id FROM posts".to_string();
+        let mut stream = Executor::stream(&executor, sql, Vec::new());
+        let mut ids = Vec::new();
+        while let Some(row) = stream.try_next().await.unwrap() {
+            ids.push(row.get_as::<i64>("id").unwrap());
+            if ids.len() == 300 {
+                executor
+                    .execute("DELETE FROM posts WHERE id <= 500", &[])
+                    .await
+                    .unwrap();
+                executor
+                    .execute("INSERT INTO posts (title) VALUES ('late')", &[])
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(ids, (1..=1000).collect::<Vec<i64>>());
+
+        // Inside a transaction the stream sees its uncommitted writes
+        executor.execute("BEGIN", &[]).await.unwrap();
+        executor.execute("DELETE FROM posts", &[]).await.unwrap();
+        let sql = "SELECT id FROM posts".to_string();
+        let rows: Vec<Row> = Executor::stream(&executor, sql, Vec::new())
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+        executor.execute("ROLLBACK", &[]).await.unwrap();
+    }
+
+    #[test]
+    fn test_queryset_explain() {
+        use chakra_core::expr::Q;
+        use chakra_core::model::Model;
+
+        let posts = Post::objects()
+            .inspect()
+            .filter(Q::new("title", "engines"))
+            .select_related("author")
+            .prefetch_related("tags")
+            .limit(5);
+        let explained = posts.explain(&SqliteDialect).unwrap();
+        let lines: Vec<_> = explained.lines().collect();
+        assert_eq!(lines[0], "-- Post.objects() on posts");
+        assert_eq!(lines[1], "-- 1. filter: \"title\" = 'engines'");
+        assert_eq!(
+            lines[2],
+            "-- 2. select_related(\"author\"): LEFT JOIN authors AS author ON \"author\".\"id\" = \"posts\".\"author_id\""
+        );
+        assert!(lines[3].contains("WHERE post_tags.post_id IN (...), keys from posts.id"));
+        assert_eq!(lines[4], "-- 4. limit(5)");
+        assert!(lines[5].starts_with("SELECT \"posts\".*"));
+        assert_eq!(posts.inspector().unwrap().steps().len(), 4);
+
+        let untraced = Post::objects().explain(&SqliteDialect).unwrap();
+        assert!(untraced.contains("call inspect()"));
+    }
+
+    #[tokio::test]
+    async fn test_temp_table() {
+        use chakra_core::expr::Expr;
+        use chakra_core::temp_table::TempTable;
+
+        let conn = Arc::new(SqliteConnection::open_memory().await.unwrap());
+        let executor = SqliteExecutor::new(conn);
+        executor
+            .execute_batch(
+                "CREATE TABLE orders (user_id INTEGER, total INTEGER);
+                 INSERT INTO orders VALUES (1, 500), (1, 700), (2, 300);",
+            )
+            .await
+            .unwrap();
+
+        let totals = Query::select()
+            .from("orders")
+            .columns(&["user_id", "SUM(total) AS total"])
+            .group_by(&["user_id"])
+            .build();
+        let staged = TempTable::stage(&executor, "user_totals", totals)
+            .await
+            .unwrap();
+
+        let big = staged.select().filter(Expr::gt("total", 1000)).build();
+        let rows = executor.fetch_all(&big).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("user_id"), Some(&Value::Int64(1)));
+
+        staged.drop().await.unwrap();
+        let gone = Query::select().from("user_totals").build();
+        assert!(executor.fetch_all(&gone).await.is_err());
     }
 }
@@ -12,7 +12,7 @@ pub mod executor;
 pub mod types;
 
 pub use config::SqliteConfig;
-pub use connection::SqliteConnection;
+pub use connection::{SqliteConnection, SqlitePool};
 pub use executor::SqliteExecutor;
 
 use chakra_core::error::Result;
@@ -22,7 +22,12 @@ pub async fn connect(config: SqliteConfig) -> Result<SqliteConnection> {
     SqliteConnection::open(config).await
 }
 
-/// Create an in-memory SQLite connection
+/// Create an in-memory SQLite connection, with a database of its own
 pub async fn connect_memory() -> Result<SqliteConnection> {
     SqliteConnection::open_memory().await
 }
+
+/// Create a SQLite connection pool
+pub async fn connect_pool(config: SqliteConfig) -> Result<SqlitePool> {
+    SqlitePool::new(config).await
+}
@@ -1,7 +1,7 @@
 //! Type conversions between Chakra and SQLite
 
 use chakra_core::types::Value;
-use rusqlite::types::{ToSql, Value as SqliteValue, ValueRef};
+use rusqlite::types::{Value as SqliteValue, ValueRef};
 
 /// Convert a Chakra Value to a SQLite Value
 pub fn to_sqlite_value(value: &Value) -> SqliteValue {
@@ -18,6 +18,10 @@ pub fn to_sqlite_value(value: &Value) -> SqliteValue {
         Value::DateTime(dt) => SqliteValue::Text(dt.to_rfc3339()),
         Value::Date(d) => SqliteValue::Text(d.to_string()),
         Value::Time(t) => SqliteValue::Text(t.to_string()),
+        Value::IpAddr(a) => SqliteValue::Text(a.to_string()),
+        Value::Cidr(c) => SqliteValue::Text(c.to_string()),
+        Value::MacAddr(m) => SqliteValue::Text(m.to_string()),
+        Value::Range(r) => SqliteValue::Text(r.to_string()),
         Value::Json(j) => SqliteValue::Text(j.to_string()),
         Value::Array(arr) => {
             let json = serde_json::Value::Array(
@@ -75,7 +79,10 @@ pub fn row_to_chakra(
         })
         .collect();
 
-    Ok(chakra_core::result::Row::new(column_names.to_vec(), values?))
+    Ok(chakra_core::result::Row::new(
+        column_names.to_vec(),
+        values?,
+    ))
 }
 
 #[cfg(test)]